@@ -0,0 +1,265 @@
+//! Benchmarks of the read paths, over an image made when the benchmarks
+//! start: mkfs'd, then populated from a tree written to a temporary host
+//! directory, so there's no binary fixture to keep in the repository.
+//!
+//! cargo bench [--features parallel] [-- filter]
+
+use criterion::{
+    black_box, criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput,
+};
+use ext2::{
+    mkfs, BlockDevice, Ext2, Ext2Options, FileDevice, MkfsOptions, WalkControl, WalkOptions,
+};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::{self, Cursor};
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+const IMAGE_SIZE: usize = 128 << 20;
+// enough that the directory's blocks go well past its 12 direct ones
+const BIG_DIR_ENTRIES: usize = 10_000;
+const BIG_FILE_SIZE: usize = 50 << 20;
+const DEPTH: usize = 32;
+const ROOT: usize = 2;
+
+// the tree the image is populated from
+fn write_host_tree(root: &Path) {
+    let small = root.join("small");
+    fs::create_dir_all(&small).unwrap();
+    for i in 0..16 {
+        fs::write(small.join(format!("file{}", i)), format!("file {}\n", i)).unwrap();
+    }
+    let big = root.join("big");
+    fs::create_dir_all(&big).unwrap();
+    for i in 0..BIG_DIR_ENTRIES {
+        fs::write(big.join(format!("e{:04}", i)), b"").unwrap();
+    }
+    // not all zeros, or the import would leave holes
+    let contents: Vec<u8> = (0..BIG_FILE_SIZE)
+        .map(|i| (i * 7 + i / 4096) as u8)
+        .collect();
+    fs::write(root.join("big.bin"), contents).unwrap();
+    let mut deep = root.to_path_buf();
+    for i in 0..DEPTH {
+        deep.push(format!("d{}", i));
+    }
+    fs::create_dir_all(&deep).unwrap();
+    fs::write(deep.join("leaf"), b"at the bottom\n").unwrap();
+}
+
+// the populated image, in an aligned buffer that lives as long as the
+// benchmarks, so opening it doesn't copy it
+fn make_image() -> &'static [u8] {
+    let host = std::env::temp_dir().join(format!("ext2-bench-{}", std::process::id()));
+    write_host_tree(&host);
+
+    let mut image = vec![0; IMAGE_SIZE];
+    mkfs(&mut image, &MkfsOptions::new().block_size(4096)).unwrap();
+    let mut ext2 = Ext2::new(image).unwrap();
+    ext2.populate_from_host(&host, "/").unwrap();
+    let mut device = Cursor::new(ext2.device_bytes().unwrap().into_owned());
+    ext2.sync(&mut device).unwrap();
+    fs::remove_dir_all(&host).unwrap();
+    aligned(device.get_ref())
+}
+
+fn aligned(bytes: &[u8]) -> &'static [u8] {
+    let words: &'static mut [u64] = vec![0u64; bytes.len() / 8].leak();
+    let disk =
+        unsafe { std::slice::from_raw_parts_mut(words.as_mut_ptr() as *mut u8, bytes.len()) };
+    disk.copy_from_slice(bytes);
+    disk
+}
+
+fn open(disk: &'static [u8]) -> Ext2 {
+    Ext2Options::new().read_only(true).open(disk).unwrap()
+}
+
+// a `FileDevice` that counts the reads made of it
+struct CountedFile {
+    file: FileDevice,
+    reads: Arc<AtomicUsize>,
+}
+
+impl BlockDevice for CountedFile {
+    fn size(&self) -> u64 {
+        self.file.size()
+    }
+
+    fn read_blocks(&self, first: u64, block_size: usize, buf: &mut [u8]) -> io::Result<()> {
+        self.reads.fetch_add(1, Ordering::Relaxed);
+        self.file.read_blocks(first, block_size, buf)
+    }
+}
+
+// the image in the file at `path`, read from it as needed, with what's been
+// read yet counted in `reads`
+fn open_file(path: &Path, readahead: usize, reads: &Arc<AtomicUsize>) -> Ext2 {
+    let device = CountedFile {
+        file: FileDevice::open(path).unwrap(),
+        reads: reads.clone(),
+    };
+    Ext2Options::new()
+        .read_only(true)
+        .readahead_blocks(readahead)
+        .open_device(device)
+        .unwrap()
+}
+
+// the total size of what's in `dir`, from each entry's inode
+fn ls_l(ext2: &Ext2, dir: usize) -> u64 {
+    let entries = ext2.read_dir_inode(dir).unwrap();
+    entries
+        .iter()
+        .map(|entry| ext2.get_inode(entry.inode).unwrap().size())
+        .sum()
+}
+
+fn deep_path() -> String {
+    let mut path = String::new();
+    for i in 0..DEPTH {
+        path.push_str(&format!("/d{}", i));
+    }
+    path + "/leaf"
+}
+
+// sha256 every regular file below the root, one after another
+fn hash_tree(ext2: &Ext2) -> Vec<u8> {
+    let mut combined = Sha256::new();
+    ext2.walk(ROOT, &WalkOptions::new(), &mut |entry| {
+        if entry.record.type_perm.bits() & 0xF000 == 0x8000 {
+            for chunk in ext2.file_chunks(entry.inode).unwrap() {
+                combined.update(chunk.unwrap());
+            }
+        }
+        WalkControl::Continue
+    })
+    .unwrap();
+    combined.finalize().to_vec()
+}
+
+// the same, with the files hashed on rayon's thread pool
+#[cfg(feature = "parallel")]
+fn hash_tree_parallel(ext2: &Ext2) -> u64 {
+    use std::sync::atomic::AtomicU64;
+    let combined = AtomicU64::new(0);
+    ext2.walk_parallel(ROOT, &|_, inode, record| {
+        if record.type_perm.bits() & 0xF000 != 0x8000 {
+            return Ok(());
+        }
+        let mut hasher = Sha256::new();
+        for chunk in ext2.file_chunks(inode)? {
+            hasher.update(chunk?);
+        }
+        let digest = hasher.finalize();
+        combined.fetch_xor(
+            u64::from_le_bytes(digest[..8].try_into().unwrap()),
+            Ordering::Relaxed,
+        );
+        Ok(())
+    })
+    .unwrap();
+    combined.into_inner()
+}
+
+fn benches(c: &mut Criterion) {
+    let disk = make_image();
+    let ext2 = open(disk);
+
+    c.bench_function("open", |b| b.iter(|| open(black_box(disk))));
+
+    let mut group = c.benchmark_group("read_dir_inode");
+    for (dir, entries) in [("/small", 16), ("/big", BIG_DIR_ENTRIES)] {
+        let inode = ext2.resolve_path(ROOT, dir).unwrap();
+        group.throughput(Throughput::Elements(entries as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(entries), &inode, |b, &inode| {
+            b.iter(|| ext2.read_dir_inode(inode).unwrap().len())
+        });
+    }
+    group.finish();
+
+    // what `ls -l` does: every entry and its inode; a fresh open each time on
+    // a file, so the inode table blocks are read from it
+    let big_dir = ext2.resolve_path(ROOT, "/big").unwrap();
+    let image_file = std::env::temp_dir().join(format!("ext2-bench-{}.img", std::process::id()));
+    fs::write(&image_file, disk).unwrap();
+    let mut group = c.benchmark_group("ls -l");
+    group.throughput(Throughput::Elements(BIG_DIR_ENTRIES as u64));
+    group.bench_function("in memory", |b| b.iter(|| ls_l(&ext2, big_dir)));
+    let reads = Arc::new(AtomicUsize::new(0));
+    group.bench_function("FileDevice", |b| {
+        b.iter_batched(
+            || open_file(&image_file, 0, &reads),
+            |ext2| ls_l(&ext2, big_dir),
+            BatchSize::PerIteration,
+        )
+    });
+    // and again on the same open, once everything's been read
+    let from_file = open_file(&image_file, 0, &reads);
+    group.bench_function("FileDevice again", |b| b.iter(|| ls_l(&from_file, big_dir)));
+    group.finish();
+
+    let big_file = ext2.resolve_path(ROOT, "/big.bin").unwrap();
+    let mut group = c.benchmark_group("read 50MB file");
+    group.throughput(Throughput::Bytes(BIG_FILE_SIZE as u64));
+    group.sample_size(10);
+    group.bench_function("read_file_inode", |b| {
+        b.iter(|| ext2.read_file_inode(big_file).unwrap().len())
+    });
+    group.bench_function("file_chunks", |b| {
+        b.iter(|| {
+            let mut len = 0;
+            for chunk in ext2.file_chunks(big_file).unwrap() {
+                len += black_box(chunk.unwrap()).len();
+            }
+            len
+        })
+    });
+    group.finish();
+
+    // each iteration opens the file afresh, since blocks once read are kept
+    let mut group = c.benchmark_group("cat 50MB file from a FileDevice");
+    group.throughput(Throughput::Bytes(BIG_FILE_SIZE as u64));
+    group.sample_size(10);
+    for readahead in [0, 32] {
+        let reads = Arc::new(AtomicUsize::new(0));
+        let ext2 = open_file(&image_file, readahead, &reads);
+        let opened = reads.load(Ordering::Relaxed);
+        ext2.copy_file_to(big_file, &mut io::sink()).unwrap();
+        println!(
+            "readahead {}: {} device reads to cat the file",
+            readahead,
+            reads.load(Ordering::Relaxed) - opened
+        );
+        group.bench_with_input(
+            BenchmarkId::new("readahead", readahead),
+            &readahead,
+            |b, &readahead| {
+                b.iter_batched(
+                    || open_file(&image_file, readahead, &reads),
+                    |ext2| ext2.copy_file_to(big_file, &mut io::sink()).unwrap(),
+                    BatchSize::PerIteration,
+                )
+            },
+        );
+    }
+    group.finish();
+    fs::remove_file(&image_file).unwrap();
+
+    let path = deep_path();
+    c.bench_function("resolve_path depth 33", |b| {
+        b.iter(|| ext2.resolve_path(ROOT, black_box(&path)).unwrap())
+    });
+
+    let mut group = c.benchmark_group("hash tree");
+    group.sample_size(10);
+    group.bench_function("walk", |b| b.iter(|| hash_tree(&ext2)));
+    #[cfg(feature = "parallel")]
+    group.bench_function("walk_parallel", |b| b.iter(|| hash_tree_parallel(&ext2)));
+    group.finish();
+}
+
+criterion_group!(ext2_benches, benches);
+criterion_main!(ext2_benches);
@@ -0,0 +1,86 @@
+//! Benchmark for `Ext2::walk_parallel`: sha256 every regular file under the
+//! root of an image, with rayon thread pools of 1, 2, 4, ... threads up to
+//! the number of cores, and print how long each took.
+//!
+//! cargo run --release --features parallel --example hash_tree -- image.ext2
+
+use ext2::{Ext2, Ext2Options};
+use sha2::{Digest, Sha256};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Instant;
+use std::{env, fs, process, thread};
+
+fn load_image(path: &str) -> Vec<u8> {
+    fs::read(path).unwrap_or_else(|err| {
+        eprintln!("{}: {}", path, err);
+        process::exit(1);
+    })
+}
+
+// hash every regular file, returning (files, bytes) and an order-independent
+// digest of the whole tree to check every run saw the same thing
+fn hash_tree(ext2: &Ext2) -> ext2::Result<(u64, u64, u64)> {
+    let files = AtomicU64::new(0);
+    let bytes = AtomicU64::new(0);
+    let combined = AtomicU64::new(0);
+    ext2.walk_parallel(2, &|path, inode, record| {
+        if record.type_perm.bits() & 0xF000 != 0x8000 {
+            return Ok(());
+        }
+        let mut hasher = Sha256::new();
+        hasher.update(path.as_bytes());
+        for chunk in ext2.file_chunks(inode)? {
+            hasher.update(chunk?);
+        }
+        let digest = hasher.finalize();
+        files.fetch_add(1, Ordering::Relaxed);
+        bytes.fetch_add(record.size(), Ordering::Relaxed);
+        combined.fetch_xor(
+            u64::from_le_bytes(digest[..8].try_into().unwrap()),
+            Ordering::Relaxed,
+        );
+        Ok(())
+    })?;
+    Ok((
+        files.into_inner(),
+        bytes.into_inner(),
+        combined.into_inner(),
+    ))
+}
+
+fn main() {
+    let Some(path) = env::args().nth(1) else {
+        eprintln!("usage: hash_tree image");
+        process::exit(1);
+    };
+    let disk = load_image(&path);
+    let ext2 = Ext2Options::new()
+        .read_only(true)
+        .open(disk)
+        .unwrap_or_else(|err| {
+            eprintln!("{}: {}", path, err);
+            process::exit(1);
+        });
+    let cores = thread::available_parallelism().map_or(1, |n| n.get());
+    println!("{} core(s)", cores);
+    let mut threads = 1;
+    let mut baseline = None;
+    while threads <= cores.max(1) {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()
+            .unwrap();
+        let start = Instant::now();
+        let (files, bytes, digest) = pool.install(|| hash_tree(&ext2)).unwrap_or_else(|err| {
+            eprintln!("{}", err);
+            process::exit(1);
+        });
+        let elapsed = start.elapsed().as_secs_f64();
+        let speedup = baseline.get_or_insert(elapsed).to_owned() / elapsed;
+        println!(
+            "{:>3} thread(s): {} files, {} bytes in {:.3}s ({:.2}x), tree digest {:016x}",
+            threads, files, bytes, elapsed, speedup, digest
+        );
+        threads *= 2;
+    }
+}
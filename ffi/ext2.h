@@ -0,0 +1,63 @@
+#ifndef EXT2_H
+#define EXT2_H
+
+/* Generated by cbindgen from src/ffi.rs, don't edit by hand. */
+
+#include <stddef.h>
+#include <stdint.h>
+
+// An open image, `ext2_t` in C.
+typedef struct ext2_t ext2_t;
+
+// Called by `ext2_readdir` for each entry with the `ctx` it was given, the
+// entry's inode, its name (`name_len` bytes, NUL-terminated) and its file
+// type as a `DT_*` value. Returning anything but 0 stops the listing, and
+// `ext2_readdir` returns that.
+typedef int (*ext2_dirent_cb)(void *ctx, uint32_t inode, const char *name, size_t name_len, uint8_t file_type);
+
+
+
+// Open the image in the `len` bytes at `buf`, and store a handle to it in
+// `*out` for the other functions. Returns -EINVAL if it isn't an ext2
+// filesystem. The handle must be released with `ext2_close`.
+//
+// # Safety
+//
+// `buf` must point to `len` readable bytes and `out` to a writable pointer.
+int ext2_open(const uint8_t *buf, size_t len, struct ext2_t **out);
+
+// Read up to `len` bytes of inode `inode`'s contents from byte `off` into
+// `buf`, and store how many were read in `*read`: fewer than `len` only at
+// the end of the file, and 0 past it.
+//
+// # Safety
+//
+// `fs` must come from `ext2_open`, `buf` must point to `len` writable bytes
+// and `read` to a writable `size_t`.
+int ext2_read_file(struct ext2_t *fs, uint32_t inode, uint8_t *buf, size_t off, size_t len, size_t *read);
+
+// Call `cb` for each entry of directory `inode`, "." and ".." included, in
+// the order they're stored.
+//
+// # Safety
+//
+// `fs` must come from `ext2_open`; `ctx` is only passed on to `cb`.
+int ext2_readdir(struct ext2_t *fs, uint32_t inode, ext2_dirent_cb cb, void *ctx);
+
+// Look up `path`, taken from the root whether it starts with '/' or not,
+// and store the inode it names in `*inode`.
+//
+// # Safety
+//
+// `fs` must come from `ext2_open`, `path` must be a NUL-terminated string
+// and `inode` must point to a writable `uint32_t`.
+int ext2_lookup_path(struct ext2_t *fs, const char *path, uint32_t *inode);
+
+// Release a handle from `ext2_open`. Does nothing with NULL.
+//
+// # Safety
+//
+// `fs` must come from `ext2_open` and not be used again.
+void ext2_close(struct ext2_t *fs);
+
+#endif  /* EXT2_H */
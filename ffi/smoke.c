@@ -0,0 +1,85 @@
+/*
+ * Smoke test of the C interface against the sample image:
+ *
+ *   cargo build --features ffi
+ *   cc -Wall -o target/smoke ffi/smoke.c -Iffi -Ltarget/debug -lext2
+ *   LD_LIBRARY_PATH=target/debug target/smoke myfs.ext2
+ *
+ * Exits non-zero and says what went wrong if anything does.
+ */
+#include <errno.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+#include "ext2.h"
+
+#define CHECK(cond)                                                           \
+    do {                                                                      \
+        if (!(cond)) {                                                        \
+            fprintf(stderr, "%s:%d: failed: %s\n", __FILE__, __LINE__, #cond); \
+            exit(1);                                                          \
+        }                                                                     \
+    } while (0)
+
+static int count_entry(void *ctx, uint32_t inode, const char *name,
+                       size_t name_len, uint8_t file_type) {
+    CHECK(strlen(name) == name_len);
+    printf("  %-20s inode %u type %u\n", name, inode, file_type);
+    ++*(int *)ctx;
+    return 0;
+}
+
+static int stop_at_first(void *ctx, uint32_t inode, const char *name,
+                         size_t name_len, uint8_t file_type) {
+    (void)ctx, (void)inode, (void)name, (void)name_len, (void)file_type;
+    return 42;
+}
+
+int main(int argc, char **argv) {
+    CHECK(argc == 2);
+    FILE *f = fopen(argv[1], "rb");
+    CHECK(f != NULL);
+    fseek(f, 0, SEEK_END);
+    size_t len = ftell(f);
+    rewind(f);
+    uint8_t *image = malloc(len);
+    CHECK(fread(image, 1, len, f) == len);
+    fclose(f);
+
+    ext2_t *fs = NULL;
+    CHECK(ext2_open(image, 1000, &fs) == -EINVAL);
+    CHECK(ext2_open(image, len, &fs) == 0);
+    /* the library keeps its own copy */
+    free(image);
+
+    uint32_t root = 0;
+    CHECK(ext2_lookup_path(fs, "/", &root) == 0 && root == 2);
+    int entries = 0;
+    printf("/:\n");
+    CHECK(ext2_readdir(fs, root, count_entry, &entries) == 0);
+    CHECK(entries >= 2);
+    CHECK(ext2_readdir(fs, root, stop_at_first, NULL) == 42);
+
+    uint32_t inode = 0;
+    CHECK(ext2_lookup_path(fs, "/no/such/file", &inode) == -ENOENT);
+    CHECK(ext2_lookup_path(fs, "/hello.txt/x", &inode) == -ENOTDIR);
+    CHECK(ext2_lookup_path(fs, "/hello.txt", &inode) == 0);
+
+    char buf[4096];
+    size_t got = 0;
+    CHECK(ext2_read_file(fs, inode, (uint8_t *)buf, 0, sizeof buf, &got) == 0);
+    CHECK(got > 0 && got < sizeof buf);
+    printf("/hello.txt: %.*s", (int)got, buf);
+    size_t tail = 0;
+    CHECK(ext2_read_file(fs, inode, (uint8_t *)buf, 1, sizeof buf, &tail) == 0);
+    CHECK(tail == got - 1);
+    CHECK(ext2_read_file(fs, inode, (uint8_t *)buf, got, sizeof buf, &tail) == 0);
+    CHECK(tail == 0);
+    CHECK(ext2_read_file(fs, 1u << 30, (uint8_t *)buf, 0, sizeof buf, &got) == -ENOENT);
+
+    ext2_close(fs);
+    ext2_close(NULL);
+    printf("ok\n");
+    return 0;
+}
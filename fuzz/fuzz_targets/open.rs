@@ -0,0 +1,18 @@
+#![no_main]
+
+//! Open arbitrary bytes as an image, and list the root directory if that
+//! works. `Ext2::new` has to turn away anything it can't make sense of with
+//! an error, never a panic; the seeds in `corpus/open` are small valid images
+//! to start the mutations from, bigger than libFuzzer's default input limit:
+//!
+//!     cargo fuzz run open -- -max_len=131072
+
+use ext2::Ext2;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    // `open_bytes` copies it into an aligned buffer, since `Ext2` casts into it
+    if let Ok(ext2) = Ext2::options().open_bytes(data) {
+        let _ = ext2.read_dir_inode(2);
+    }
+});
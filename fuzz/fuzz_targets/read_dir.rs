@@ -0,0 +1,71 @@
+#![no_main]
+
+//! Corrupt a known-good image and read everything in its root directory.
+//!
+//! Mutations land anywhere, the superblock included, so most inputs are
+//! turned away by `Ext2::new` and the rest exercise the group descriptors,
+//! inode tables, bitmaps, directory blocks and block pointers that
+//! `read_dir_inode` and `read_file_inode` walk. Any panic here is a missing
+//! bounds check.
+
+use ext2::{mkfs, Ext2, FixedClock, MkfsOptions};
+use libfuzzer_sys::fuzz_target;
+use std::io::Cursor;
+use std::sync::OnceLock;
+
+/// Files claiming to be bigger than this aren't read: a corrupt size makes a
+/// file of holes that takes forever to read, but nothing new gets exercised.
+const MAX_FILE_SIZE: u64 = 1 << 24;
+const SEED_SIZE: usize = 1 << 20;
+const ROOT: usize = 2;
+
+/// The image every input patches: made with the crate's mkfs and write path,
+/// the same every run, with a file in the root, one in a directory, and one
+/// big enough to need its indirect block.
+fn seed() -> &'static [u8] {
+    static SEED: OnceLock<Vec<u8>> = OnceLock::new();
+    SEED.get_or_init(|| {
+        let mut image = vec![0; SEED_SIZE];
+        let options = MkfsOptions::new().block_size(1024).clock(FixedClock(0));
+        mkfs(&mut image, &options).unwrap();
+        let mut ext2 = Ext2::options().clock(FixedClock(0)).open(image).unwrap();
+        let hello = ext2.create_file(ROOT, "hello.txt", 0o644).unwrap();
+        ext2.write_file(hello, 0, b"Hello, ext2 world!\n").unwrap();
+        let dir = ext2.create_dir(ROOT, "test_directory", 0o755).unwrap();
+        let inside = ext2.create_file(dir, "file_in_folder.txt", 0o644).unwrap();
+        ext2.write_file(inside, 0, b"Hello! I'm a file inside a folder.\n")
+            .unwrap();
+        let big = ext2.create_file(ROOT, "big.bin", 0o644).unwrap();
+        let contents: Vec<u8> = (0..20 << 10).map(|i| (i % 251) as u8).collect();
+        ext2.write_file(big, 0, &contents).unwrap();
+        let mut device = Cursor::new(ext2.device_bytes().unwrap().into_owned());
+        ext2.sync(&mut device).unwrap();
+        device.into_inner()
+    })
+}
+
+fuzz_target!(|patches: &[u8]| {
+    let mut image = seed().to_vec();
+
+    // each 5-byte record is a little-endian offset followed by a new byte value
+    for patch in patches.chunks_exact(5) {
+        let offset = u32::from_le_bytes([patch[0], patch[1], patch[2], patch[3]]) as usize;
+        let len = image.len();
+        image[offset % len] = patch[4];
+    }
+
+    let Ok(ext2) = Ext2::new(image) else {
+        return;
+    };
+    let Ok(entries) = ext2.read_dir_inode(ROOT) else {
+        return;
+    };
+    for entry in entries {
+        match ext2.get_inode(entry.inode) {
+            Ok(record) if record.size() <= MAX_FILE_SIZE => {
+                let _ = ext2.read_file_inode(entry.inode);
+            }
+            _ => {}
+        }
+    }
+});
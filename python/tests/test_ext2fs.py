@@ -0,0 +1,100 @@
+"""Reading the sample image in the repository root through the bindings."""
+
+import errno
+import os
+
+import pytest
+
+import ext2fs
+
+IMAGE = os.path.join(os.path.dirname(__file__), "..", "..", "myfs.ext2")
+
+
+@pytest.fixture(scope="module")
+def fs():
+    return ext2fs.Ext2(IMAGE)
+
+
+def test_not_an_image(tmp_path):
+    junk = tmp_path / "junk"
+    junk.write_bytes(b"\0" * 4096)
+    with pytest.raises(ValueError):
+        ext2fs.Ext2(str(junk))
+
+
+def test_missing_image(tmp_path):
+    with pytest.raises(FileNotFoundError):
+        ext2fs.Ext2(str(tmp_path / "missing"))
+
+
+def test_listdir(fs):
+    entries = {entry.name: entry for entry in fs.listdir("/")}
+    assert {"hello.txt", "lost+found", "test_directory"} <= set(entries)
+    assert "." not in entries and ".." not in entries
+    hello = entries["hello.txt"]
+    assert hello.is_file() and not hello.is_dir()
+    assert hello.path == "/hello.txt"
+    assert hello.type == "regular file"
+    assert entries["lost+found"].is_dir()
+
+
+def test_listdir_of_a_file(fs):
+    with pytest.raises(NotADirectoryError):
+        fs.listdir("/hello.txt")
+
+
+def test_read(fs):
+    contents = fs.read("/hello.txt")
+    assert contents == b"Hello, ext2 world!\n"
+    assert fs.read("/hello.txt", offset=7) == contents[7:]
+    assert fs.read("/hello.txt", 7, 4) == contents[7:11]
+    assert fs.read("/hello.txt", offset=1000) == b""
+    inode = fs.stat("/hello.txt")["inode"]
+    assert fs.read(inode) == contents
+
+
+def test_read_a_directory(fs):
+    with pytest.raises(IsADirectoryError):
+        fs.read("/lost+found")
+
+
+def test_stat(fs):
+    info = fs.stat("/hello.txt")
+    assert info["type"] == "regular file"
+    assert info["size"] == len(fs.read("/hello.txt"))
+    assert info["links"] == 1
+    root = fs.stat("/")
+    assert root["inode"] == 2 and root["type"] == "directory"
+
+
+def test_missing_path(fs):
+    with pytest.raises(FileNotFoundError) as raised:
+        fs.stat("/no/such/file")
+    assert raised.value.errno == errno.ENOENT
+
+
+def test_readlink_of_a_file(fs):
+    with pytest.raises(OSError) as raised:
+        fs.readlink("/hello.txt")
+    assert raised.value.errno == errno.EINVAL
+
+
+def test_walk(fs):
+    steps = list(fs.walk("/"))
+    top, dirnames, filenames = steps[0]
+    assert top == "/"
+    assert "hello.txt" in filenames
+    assert {"lost+found", "test_directory"} <= set(dirnames)
+    walked = [dirpath for dirpath, _, _ in steps]
+    assert "/test_directory" in walked
+    # every directory found is walked, once
+    found = {os.path.join(d, name) for d, names, _ in steps for name in names}
+    assert found == set(walked[1:])
+
+
+def test_walk_pruned(fs):
+    walked = []
+    for dirpath, dirnames, _ in fs.walk("/"):
+        walked.append(dirpath)
+        dirnames.clear()
+    assert walked == ["/"]
@@ -0,0 +1,81 @@
+// Simulated access control.
+//
+// Nothing in the library enforces permissions: `access` answers whether a
+// user may do something to an inode, the POSIX way, and it's up to the caller
+// (the shell) to ask before doing it.
+
+use crate::structs::Inode;
+use crate::{Ext2, Result};
+use bitflags::bitflags;
+
+/// Who is asking: a user and their (single) group.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Credentials {
+    pub uid: u32,
+    pub gid: u32,
+}
+
+impl Credentials {
+    pub const ROOT: Credentials = Credentials { uid: 0, gid: 0 };
+}
+
+impl Default for Credentials {
+    fn default() -> Credentials {
+        Credentials::ROOT
+    }
+}
+
+bitflags! {
+    /// What is being asked for, with the same values as the "other" bits of
+    /// a mode, like `access(2)`'s `R_OK`/`W_OK`/`X_OK`.
+    pub struct AccessMode: u16 {
+        const EXEC = 0o1;
+        const WRITE = 0o2;
+        const READ = 0o4;
+    }
+}
+
+impl Ext2 {
+    /// Whether `cred` may access `inode` in every way in `mode`.
+    ///
+    /// The permission bits of exactly one class apply: the owner's if `cred`
+    /// owns the inode, else the group's if it's in the inode's group, else
+    /// everyone else's. Root may read and write anything, and execute
+    /// anything that's a directory or executable by someone.
+    pub fn access(&self, inode: usize, cred: &Credentials, mode: AccessMode) -> Result<bool> {
+        let record = self.get_inode(inode)?;
+        let perm = record.type_perm.bits();
+        if cred.uid == 0 {
+            let executable = perm & 0o111 != 0 || record.is_dir();
+            return Ok(!mode.contains(AccessMode::EXEC) || executable);
+        }
+        let class = if cred.uid == owner(record) {
+            perm >> 6
+        } else if cred.gid == group(record) {
+            perm >> 3
+        } else {
+            perm
+        };
+        Ok(AccessMode::from_bits_truncate(class).contains(mode))
+    }
+}
+
+// the inode's owner, with the high 16 bits Linux keeps in the OS-specific area
+pub(crate) fn owner(inode: &Inode) -> u32 {
+    inode.uid as u32
+        | (u16::from_le_bytes([inode._os_specific_2[4], inode._os_specific_2[5]]) as u32) << 16
+}
+
+// the inode's group, likewise
+pub(crate) fn group(inode: &Inode) -> u32 {
+    inode.gid as u32
+        | (u16::from_le_bytes([inode._os_specific_2[6], inode._os_specific_2[7]]) as u32) << 16
+}
+
+// set the inode's owner and group, the way `owner` and `group` read them back
+pub(crate) fn set_owner(inode: &mut Inode, uid: u32, gid: u32) {
+    inode.uid = uid as u16;
+    inode._os_specific_2[4..6].copy_from_slice(&((uid >> 16) as u16).to_le_bytes());
+    inode.gid = gid as u16;
+    inode._os_specific_2[6..8].copy_from_slice(&((gid >> 16) as u16).to_le_bytes());
+}
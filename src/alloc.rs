@@ -0,0 +1,262 @@
+// Allocating and freeing blocks and inodes.
+//
+// Whether a block or inode is in use is recorded in four places that have to
+// agree: its group's bitmap, the group's free count, the superblock's free
+// count and, for a directory's inode, the group's directory count. Every
+// change to them goes through `set_block_in_use` and `set_inode_in_use`,
+// which update all of them together, so no sequence of operations can leave
+// the counts out of step with the bitmaps. The bitmaps change through
+// `block_mut` like any other block; the counts live in the in-memory
+// superblock and descriptors until `write_metadata` copies them out.
+//
+// There's no allocator type of its own: everything it would hold is `Ext2`'s
+// already, so these are `Ext2` methods like those of every other module. The
+// goals are finer than a group, too: `alloc_block` takes the block to follow,
+// so a file's blocks come out contiguous, and `alloc_inode` the parent
+// directory, whose group a file stays in; the group is worked out from them.
+
+use crate::{Bitmap, Ext2, Ext2Error, InodeNo, Result};
+use log::debug;
+
+impl Ext2 {
+    // the inode usage bitmap of block group `group`, for modifying
+    pub(crate) fn inode_bitmap_mut(&mut self, group: usize) -> Result<Bitmap<&mut [u8]>> {
+        let block_num = self.block_groups[group].inode_usage_addr as usize;
+        let len = self.superblock.inodes_per_group as usize;
+        Ok(Bitmap::new(self.block_mut(block_num)?, len))
+    }
+
+    // the block usage bitmap of block group `group`, for modifying
+    pub(crate) fn block_bitmap_mut(&mut self, group: usize) -> Result<Bitmap<&mut [u8]>> {
+        let block_num = self.block_groups[group].block_usage_addr as usize;
+        let len = self.group_blocks_count(group);
+        Ok(Bitmap::new(self.block_mut(block_num)?, len))
+    }
+
+    // allocate a free inode for a new file or directory in directory `parent`
+    // the group is picked by `inode_goal_group`, falling back to the groups
+    // after it in turn, wrapping around; `NoInodes` once every group's bitmap
+    // is full. The bitmaps are looked at whatever the free counts say, so a
+    // count that's drifted to 0 doesn't hide a free inode
+    pub fn alloc_inode(&mut self, parent: usize, is_dir: bool) -> Result<usize> {
+        self.check_writable()?;
+        // the inodes below the first usable one are reserved (root, resize
+        // inode, ...), so the search in its group starts there, and the
+        // groups before it are skipped
+        let (first_group, first_index) =
+            InodeNo::first_usable(&self.superblock).to_group_and_index(&self.superblock)?;
+        let groups = self.block_groups.len();
+        let goal_group = self.inode_goal_group(parent, is_dir);
+        for group in (0..groups).map(|i| (goal_group + i) % groups) {
+            if group < first_group {
+                continue;
+            }
+            let start = if group == first_group { first_index } else { 0 };
+            let free = self
+                .inode_bitmap(group)?
+                .find_next_clear(start)
+                .and_then(|index| InodeNo::from_group_and_index(group, index, &self.superblock));
+            if let Some(InodeNo(inode)) = free {
+                self.set_inode_in_use(inode, true, is_dir)?;
+                debug!(
+                    "allocated inode {} in group {} (goal group {})",
+                    inode, group, goal_group
+                );
+                return Ok(inode);
+            }
+        }
+        Err(Ext2Error::NoInodes)
+    }
+
+    // the classic ext2 placement: a new directory goes to the group with the
+    // most free blocks among those with at least the average number of free
+    // inodes, spreading the tree out; anything else stays in its parent's group
+    // if that has free inodes and blocks, otherwise tries groups at a quadratic
+    // distance from it, then any group with a free inode
+    fn inode_goal_group(&self, parent: usize, is_dir: bool) -> usize {
+        let groups = &self.block_groups;
+        if is_dir {
+            let average = self.superblock.free_inodes_count as usize / groups.len();
+            return (0..groups.len())
+                .filter(|&g| {
+                    groups[g].free_inodes_count > 0
+                        && groups[g].free_inodes_count as usize >= average
+                })
+                .max_by_key(|&g| (groups[g].free_blocks_count, std::cmp::Reverse(g)))
+                .unwrap_or(0);
+        }
+        let parent_group = self.inode_group(parent);
+        let roomy = |g: usize| groups[g].free_inodes_count > 0 && groups[g].free_blocks_count > 0;
+        if roomy(parent_group) {
+            return parent_group;
+        }
+        let mut distance = 1;
+        while distance < groups.len() {
+            let g = (parent_group + distance) % groups.len();
+            if roomy(g) {
+                return g;
+            }
+            distance *= 2;
+        }
+        (0..groups.len())
+            .map(|i| (parent_group + i) % groups.len())
+            .find(|&g| groups[g].free_inodes_count > 0)
+            .unwrap_or(parent_group)
+    }
+
+    // allocate a free block, as close after block `goal` as possible so a file's
+    // blocks end up contiguous: first from `goal` on in its group, then the
+    // rest of that group, then the groups after it in turn, wrapping around;
+    // `NoSpace` once every group's bitmap is full, or the current credentials
+    // may only have the reserved blocks left. The block comes back zeroed
+    pub fn alloc_block(&mut self, goal: usize) -> Result<usize> {
+        self.check_writable()?;
+        if self.available_blocks() == 0 {
+            return Err(Ext2Error::NoSpace);
+        }
+        let sb = &self.superblock;
+        let first_data_block = sb.first_data_block as usize;
+        let blocks_count = sb.blocks_count as usize;
+        let goal = goal.clamp(first_data_block, blocks_count - 1);
+        let groups = self.block_groups.len();
+        let goal_group = self.block_group(goal);
+        for group in (0..groups).map(|i| (goal_group + i) % groups) {
+            let first = self.group_first_block(group);
+            let start = if group == goal_group { goal - first } else { 0 };
+            let bitmap = self.block_bitmap(group)?;
+            let free = bitmap
+                .find_next_clear(start)
+                .or_else(|| bitmap.find_first_clear().filter(|&index| index < start));
+            if let Some(index) = free {
+                let block_num = first + index;
+                self.set_block_in_use(block_num, true)?;
+                self.block_mut(block_num)?.fill(0);
+                debug!(
+                    "allocated block {} in group {} (goal block {} in group {})",
+                    block_num, group, goal, goal_group
+                );
+                return Ok(block_num);
+            }
+        }
+        Err(Ext2Error::NoSpace)
+    }
+
+    // mark the specific, currently free, block `block` in use
+    pub(crate) fn claim_block(&mut self, block: usize) -> Result<()> {
+        self.set_block_in_use(block, true)
+    }
+
+    // mark block `block`, currently in use, free again
+    pub(crate) fn free_block(&mut self, block: usize) -> Result<()> {
+        self.set_block_in_use(block, false)
+    }
+
+    // mark the specific, currently free, inode `inode` in use, for a
+    // directory if `is_dir`
+    pub(crate) fn claim_inode(&mut self, inode: usize, is_dir: bool) -> Result<()> {
+        self.set_inode_in_use(inode, true, is_dir)
+    }
+
+    // mark inode `inode`, currently in use, free again; `is_dir` says whether
+    // it was a directory's. The root directory's inode is never freed.
+    pub(crate) fn free_inode(&mut self, inode: usize, is_dir: bool) -> Result<()> {
+        if InodeNo(inode) == InodeNo::ROOT {
+            return Err(Ext2Error::NotPermitted {
+                name: "/".to_string(),
+            });
+        }
+        self.set_inode_in_use(inode, false, is_dir)
+    }
+
+    // the block group a (1-indexed) inode belongs to; group 0 for a number
+    // that isn't an inode's, since this is only ever a goal
+    pub(crate) fn inode_group(&self, inode: usize) -> usize {
+        InodeNo(inode)
+            .to_group_and_index(&self.superblock)
+            .map_or(0, |(group, _)| group)
+    }
+
+    // the block group block `block`, at or after `first_data_block`, is in
+    pub(crate) fn block_group(&self, block: usize) -> usize {
+        (block - self.superblock.first_data_block as usize)
+            / self.superblock.blocks_per_group as usize
+    }
+
+    // mark `block` in use or free in its group's bitmap, along with the free
+    // counts. A block outside the groups is `BlockOutOfRange`, and one
+    // already marked that way is `AlreadyMarked`, with nothing changed:
+    // claiming a block twice or freeing it twice is a bug in the caller, or a
+    // corrupt pointer, and the counts never drift from the bitmap either way
+    fn set_block_in_use(&mut self, block: usize, in_use: bool) -> Result<()> {
+        let sb = &self.superblock;
+        if block < sb.first_data_block as usize || block >= sb.blocks_count as usize {
+            return Err(Ext2Error::BlockOutOfRange {
+                block,
+                blocks_count: sb.blocks_count as usize,
+            });
+        }
+        let group = self.block_group(block);
+        let index = block - self.group_first_block(group);
+        if self.block_bitmap(group)?.is_set(index) == in_use {
+            return Err(already_marked("block", block, in_use));
+        }
+        let mut bitmap = self.block_bitmap_mut(group)?;
+        if in_use {
+            bitmap.set(index);
+        } else {
+            bitmap.clear(index);
+        }
+        let (descriptor, sb) = (&mut self.block_groups[group], &mut self.superblock);
+        if in_use {
+            // saturating, in case a damaged image's counts were already off
+            descriptor.free_blocks_count = descriptor.free_blocks_count.saturating_sub(1);
+            sb.free_blocks_count = sb.free_blocks_count.saturating_sub(1);
+        } else {
+            descriptor.free_blocks_count += 1;
+            sb.free_blocks_count += 1;
+        }
+        Ok(())
+    }
+
+    // mark `inode` in use or free in its group's bitmap, along with the free
+    // counts and, if `is_dir`, the group's directory count; like
+    // `set_block_in_use`, an inode already marked that way is `AlreadyMarked`
+    fn set_inode_in_use(&mut self, inode: usize, in_use: bool, is_dir: bool) -> Result<()> {
+        let (group, index) = InodeNo(inode).to_group_and_index(&self.superblock)?;
+        if self.inode_bitmap(group)?.is_set(index) == in_use {
+            return Err(already_marked("inode", inode, in_use));
+        }
+        let mut bitmap = self.inode_bitmap_mut(group)?;
+        if in_use {
+            bitmap.set(index);
+        } else {
+            bitmap.clear(index);
+        }
+        let (descriptor, sb) = (&mut self.block_groups[group], &mut self.superblock);
+        if in_use {
+            descriptor.free_inodes_count = descriptor.free_inodes_count.saturating_sub(1);
+            sb.free_inodes_count = sb.free_inodes_count.saturating_sub(1);
+        } else {
+            descriptor.free_inodes_count += 1;
+            sb.free_inodes_count += 1;
+        }
+        if is_dir {
+            descriptor.dirs_count = if in_use {
+                descriptor.dirs_count + 1
+            } else {
+                descriptor.dirs_count.saturating_sub(1)
+            };
+        }
+        Ok(())
+    }
+}
+
+// the error for block or inode `number` being marked `in_use` when it
+// already is, or free when it already is
+fn already_marked(kind: &'static str, number: usize, in_use: bool) -> Ext2Error {
+    Ext2Error::AlreadyMarked {
+        kind,
+        number,
+        state: if in_use { "in use" } else { "free" },
+    }
+}
@@ -0,0 +1,233 @@
+// Appending to a file, for log-style writers that add a little at a time.
+// `write_file` finds its way to the block before the one it writes by
+// counting through the file's blocks from the start, and to each block it
+// writes by going down the pointer tree from the inode, then updates the
+// inode twice, for the times and the size. An `AppendHandle` remembers where
+// the last append ended instead: the last block and the indirect block whose
+// pointers it's among, so the next append fills what's left of that block
+// and points new ones from that indirect block straight away, going down the
+// tree only when it moves on to the next indirect block. Everything the
+// inode needs is gathered on the way and written to it once per append.
+//
+// What the handle remembers is only trusted while nothing else has modified
+// the filesystem since; otherwise it's found again, from the inode, before
+// the append starts.
+
+use crate::structs::FeatureRoCompat;
+use crate::{Ext2, Ext2Error, Result};
+use log::debug;
+
+/// Where appends to a file carry on from, for `Ext2::append`. It borrows
+/// nothing, so it can be kept alongside the filesystem between appends.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AppendHandle {
+    inode: usize,
+    tail: Option<Tail>,
+}
+
+// the end of the file as the last append left it
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Tail {
+    // the filesystem's generation after the append, so a later one can tell
+    // whether anything could have changed since
+    generation: u64,
+    size: u64,
+    // the last block, and the physical block holding it
+    logical: usize,
+    block: usize,
+    // the indirect block that points at it, and the logical block its first
+    // pointer is for; `None` for a block reached through a direct pointer
+    leaf: Option<(usize, usize)>,
+}
+
+impl AppendHandle {
+    pub fn new(inode: usize) -> AppendHandle {
+        AppendHandle { inode, tail: None }
+    }
+
+    pub fn inode(&self) -> usize {
+        self.inode
+    }
+}
+
+impl Ext2 {
+    /// Add `data` to the end of the handle's file, like a write(2) to a file
+    /// opened with O_APPEND, and return how much was added. The file is
+    /// left as `write_file` at its size would leave it, and fails the same
+    /// way, including with a short count if the filesystem fills up part
+    /// way; an append-only file can be appended to.
+    pub fn append(&mut self, handle: &mut AppendHandle, data: &[u8]) -> Result<usize> {
+        self.check_writable()?;
+        let inode = handle.inode;
+        let record = self.get_inode(inode)?;
+        if record.is_dir() {
+            return Err(Ext2Error::IsADirectory {
+                name: format!("inode {}", inode),
+            });
+        }
+        if !record.is_regular() {
+            return Err(Ext2Error::NotPermitted {
+                name: format!("inode {}", inode),
+            });
+        }
+        let size = record.size();
+        self.check_write(inode, size)?;
+        if data.is_empty() {
+            return Ok(0);
+        }
+        let mut tail = match handle.tail.take() {
+            Some(tail) if tail.generation == self.generation && tail.size == size => Some(tail),
+            _ => self.find_tail(inode, size)?,
+        };
+
+        let block_size = self.block_size as u64;
+        let mut direct = self.get_inode(inode)?.direct_pointer;
+        let mut new_sectors = 0;
+        let mut goal = match &tail {
+            Some(tail) if tail.block != 0 => tail.block + 1,
+            _ => self.group_first_block(self.inode_group(inode)),
+        };
+        let mut written = 0;
+        while written < data.len() {
+            let position = size + written as u64;
+            let logical = (position / block_size) as usize;
+            let within = (position % block_size) as usize;
+            let len = (self.block_size - within).min(data.len() - written);
+            let block_num = match &tail {
+                // what's left of the last block
+                Some(tail) if tail.logical == logical && tail.block != 0 => tail.block,
+                _ => {
+                    let leaf = tail.as_ref().and_then(|tail| tail.leaf);
+                    let placed = self.append_block(
+                        inode,
+                        logical,
+                        leaf,
+                        goal,
+                        &mut direct,
+                        &mut new_sectors,
+                    );
+                    let (block_num, leaf) = match placed {
+                        Ok(placed) => placed,
+                        Err(Ext2Error::NoSpace) if written > 0 => break,
+                        Err(err) => return Err(err),
+                    };
+                    tail = Some(Tail {
+                        generation: 0,
+                        size: 0,
+                        logical,
+                        block: block_num,
+                        leaf,
+                    });
+                    block_num
+                }
+            };
+            self.block_mut(block_num)?[within..within + len]
+                .copy_from_slice(&data[written..written + len]);
+            goal = block_num + 1;
+            written += len;
+        }
+
+        let end = size + written as u64;
+        if end > i32::MAX as u64 {
+            self.superblock.features_ronly |= FeatureRoCompat::LARGE_FILE.bits();
+        }
+        let now = self.now();
+        let record = self.inode_mut(inode)?;
+        record.direct_pointer = direct;
+        record.sectors_count += new_sectors;
+        record.mtime = now;
+        record.ctime = now;
+        record.set_size(end);
+        handle.tail = tail.map(|tail| Tail {
+            generation: self.generation,
+            size: end,
+            ..tail
+        });
+        debug!("appended {} byte(s) to inode {}", written, inode);
+        Ok(written)
+    }
+
+    // the end of a file of `size` bytes, found from its inode: its last
+    // block, and the indirect block that points at it; `None` if it's empty
+    fn find_tail(&self, inode: usize, size: u64) -> Result<Option<Tail>> {
+        if size == 0 {
+            return Ok(None);
+        }
+        let logical = ((size - 1) / self.block_size as u64) as usize;
+        let block = self.file_blocks(inode)?.lookup(logical)? as usize;
+        let leaf = if logical < 12 {
+            None
+        } else {
+            self.leaf_indirect(inode, logical)?
+        };
+        Ok(Some(Tail {
+            generation: self.generation,
+            size,
+            logical,
+            block,
+            leaf,
+        }))
+    }
+
+    // the indirect block whose pointers include the one for `logical`, as
+    // (block, the logical block of its first pointer), if it's there
+    fn leaf_indirect(&self, inode: usize, logical: usize) -> Result<Option<(usize, usize)>> {
+        let path = self.block_path(logical)?;
+        let record = self.get_inode(inode)?;
+        let mut block_num = match path.len() {
+            1 => record.indirect_pointer,
+            2 => record.doubly_indirect,
+            _ => record.triply_indirect,
+        } as usize;
+        for &slot in &path[..path.len() - 1] {
+            if block_num == 0 {
+                return Ok(None);
+            }
+            let at = slot * 4;
+            block_num =
+                u32::from_le_bytes(self.block(block_num)?[at..at + 4].try_into().unwrap()) as usize;
+        }
+        let first = logical - path[path.len() - 1];
+        Ok(Some(block_num).filter(|&b| b != 0).map(|b| (b, first)))
+    }
+
+    // allocate logical block `logical` of `inode`, which is a hole, as close
+    // after `goal` as possible, and point at it: from `direct`, the inode's
+    // direct pointers the append will write back, or from `leaf` if it's
+    // among that indirect block's pointers, or else by going down the tree,
+    // making indirect blocks as needed. The sectors of a block pointed at
+    // without going down the tree are added to `new_sectors` for the append
+    // to count in the inode too. Returns the block and the indirect block
+    // that now points at it.
+    fn append_block(
+        &mut self,
+        inode: usize,
+        logical: usize,
+        leaf: Option<(usize, usize)>,
+        goal: usize,
+        direct: &mut [u32; 12],
+        new_sectors: &mut u32,
+    ) -> Result<(usize, Option<(usize, usize)>)> {
+        let sectors = self.block_size as u32 / 512;
+        if logical < 12 {
+            let block_num = self.alloc_block(goal)?;
+            direct[logical] = block_num as u32;
+            *new_sectors += sectors;
+            return Ok((block_num, None));
+        }
+        let per_block = self.block_size / 4;
+        if let Some((leaf_block, first)) = leaf {
+            if (first..first + per_block).contains(&logical) {
+                let block_num = self.alloc_block(goal)?;
+                *new_sectors += sectors;
+                let at = (logical - first) * 4;
+                self.block_mut(leaf_block)?[at..at + 4]
+                    .copy_from_slice(&(block_num as u32).to_le_bytes());
+                return Ok((block_num, leaf));
+            }
+        }
+        // counts itself, and the indirect blocks it makes, in the inode
+        let block_num = self.map_block(inode, logical, goal)?;
+        Ok((block_num, self.leaf_indirect(inode, logical)?))
+    }
+}
@@ -0,0 +1,180 @@
+// Inode and block usage bitmaps.
+//
+// Bit n of a bitmap is bit n % 8 of byte n / 8. A group's bitmap block usually
+// has more bits than the group has inodes or blocks; `len` is the number that
+// mean something, and the padding after it is never read or written.
+// Translating between bits and inode or block numbers (inodes are 1-indexed,
+// both are per-group) is up to the callers.
+
+/// A view of an inode or block bitmap, read-only over `&[u8]` or modifiable
+/// over `&mut [u8]`.
+#[derive(Debug)]
+pub struct Bitmap<B> {
+    bytes: B,
+    len: usize,
+}
+
+impl<B: AsRef<[u8]>> Bitmap<B> {
+    /// Wrap the first `len` bits of `bytes`.
+    pub fn new(bytes: B, len: usize) -> Bitmap<B> {
+        assert!(
+            len <= bytes.as_ref().len() * 8,
+            "bitmap of {} bits in {} bytes",
+            len,
+            bytes.as_ref().len()
+        );
+        Bitmap { bytes, len }
+    }
+
+    /// The number of bits in the bitmap.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Whether the bitmap has no bits at all.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Whether bit `n` is set. Panics if `n` is out of range.
+    pub fn is_set(&self, n: usize) -> bool {
+        assert!(n < self.len, "bit {} of a {}-bit bitmap", n, self.len);
+        self.bytes.as_ref()[n / 8] & (1 << (n % 8)) != 0
+    }
+
+    /// The first clear bit, if any.
+    pub fn find_first_clear(&self) -> Option<usize> {
+        self.find_next_clear(0)
+    }
+
+    /// The first clear bit at or after bit `from`, if any.
+    pub fn find_next_clear(&self, from: usize) -> Option<usize> {
+        let bytes = self.bytes.as_ref();
+        let mut n = from;
+        while n < self.len {
+            // skip whole bytes that are full
+            if n % 8 == 0 && bytes[n / 8] == 0xff {
+                n += 8;
+                continue;
+            }
+            if !self.is_set(n) {
+                return Some(n);
+            }
+            n += 1;
+        }
+        None
+    }
+
+    /// The number of set bits.
+    pub fn count_set(&self) -> usize {
+        let bytes = self.bytes.as_ref();
+        let full = bytes[..self.len / 8]
+            .iter()
+            .map(|byte| byte.count_ones() as usize)
+            .sum::<usize>();
+        let tail = (self.len / 8 * 8..self.len)
+            .filter(|&n| self.is_set(n))
+            .count();
+        full + tail
+    }
+
+    /// The number of clear bits.
+    pub fn count_clear(&self) -> usize {
+        self.len - self.count_set()
+    }
+}
+
+impl<B: AsRef<[u8]> + AsMut<[u8]>> Bitmap<B> {
+    /// Set bit `n`. Panics if `n` is out of range.
+    pub fn set(&mut self, n: usize) {
+        assert!(n < self.len, "bit {} of a {}-bit bitmap", n, self.len);
+        self.bytes.as_mut()[n / 8] |= 1 << (n % 8);
+    }
+
+    /// Clear bit `n`. Panics if `n` is out of range.
+    pub fn clear(&mut self, n: usize) {
+        assert!(n < self.len, "bit {} of a {}-bit bitmap", n, self.len);
+        self.bytes.as_mut()[n / 8] &= !(1 << (n % 8));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Bitmap;
+
+    #[test]
+    fn bit_zero_is_the_low_bit_of_byte_zero() {
+        let mut bitmap = Bitmap::new([0u8; 2], 16);
+        bitmap.set(0);
+        assert_eq!(bitmap.bytes, [0x01, 0x00]);
+        assert!(bitmap.is_set(0));
+        assert_eq!(bitmap.find_first_clear(), Some(1));
+        bitmap.set(15);
+        assert_eq!(bitmap.bytes, [0x01, 0x80]);
+        bitmap.clear(0);
+        assert_eq!(bitmap.bytes, [0x00, 0x80]);
+        assert_eq!(bitmap.find_first_clear(), Some(0));
+        assert_eq!(bitmap.count_set(), 1);
+
+        // bit 0 the only one clear
+        let bitmap = Bitmap::new([0xfe, 0xff], 16);
+        assert_eq!(bitmap.find_first_clear(), Some(0));
+        assert_eq!(bitmap.find_next_clear(1), None);
+        assert_eq!(bitmap.count_clear(), 1);
+    }
+
+    #[test]
+    fn all_bits_set() {
+        let bitmap = Bitmap::new([0xff; 4], 32);
+        assert_eq!(bitmap.find_first_clear(), None);
+        assert_eq!(bitmap.find_next_clear(31), None);
+        assert_eq!((bitmap.count_set(), bitmap.count_clear()), (32, 0));
+
+        // with the padding after `len` clear, which doesn't count
+        let bitmap = Bitmap::new([0xff, 0x1f], 13);
+        assert_eq!(bitmap.find_first_clear(), None);
+        assert_eq!((bitmap.count_set(), bitmap.count_clear()), (13, 0));
+    }
+
+    #[test]
+    fn padding_is_ignored() {
+        // set padding in a bitmap with nothing set
+        let bitmap = Bitmap::new([0x00, 0xe0], 13);
+        assert_eq!(bitmap.count_set(), 0);
+        assert_eq!(bitmap.find_next_clear(12), Some(12));
+        let bitmap = Bitmap::new([0x00, 0x00, 0xff], 16);
+        assert_eq!(bitmap.count_set(), 0);
+    }
+
+    #[test]
+    fn next_clear_skips_full_bytes() {
+        let bitmap = Bitmap::new([0xff, 0xff, 0xf7, 0x00], 32);
+        assert_eq!(bitmap.find_first_clear(), Some(19));
+        assert_eq!(bitmap.find_next_clear(19), Some(19));
+        assert_eq!(bitmap.find_next_clear(20), Some(24));
+        // from the middle of a byte that's full from there on
+        let bitmap = Bitmap::new([0xf0, 0xff, 0x01], 24);
+        assert_eq!(bitmap.find_next_clear(4), Some(17));
+        assert_eq!(bitmap.find_next_clear(24), None);
+    }
+
+    #[test]
+    fn empty() {
+        let bitmap = Bitmap::new([0u8; 0], 0);
+        assert!(bitmap.is_empty());
+        assert_eq!(bitmap.find_first_clear(), None);
+        assert_eq!(bitmap.count_clear(), 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "bit 13 of a 13-bit bitmap")]
+    fn setting_padding_panics() {
+        Bitmap::new([0u8; 2], 13).set(13);
+    }
+
+    #[test]
+    #[should_panic(expected = "bitmap of 17 bits in 2 bytes")]
+    fn too_few_bytes_panics() {
+        Bitmap::new([0u8; 2], 17);
+    }
+}
@@ -0,0 +1,169 @@
+// The tree of a file's block pointers as it is on disk, for seeing how a
+// file is laid out: the direct pointers, then each indirect block with what
+// it points to. Pointers are read straight from the indirect blocks rather
+// than through `FileBlocks`, so the tree shows every block the inode holds on
+// to, whatever its size says, and each level's runs of consecutive pointers
+// are merged so a big file stays a short tree.
+
+use crate::{Ext2, Result};
+
+/// A run of consecutive pointers in a `BlockMap`: either all holes, or
+/// pointing at consecutive blocks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockRun {
+    /// Index within the file of the block the first pointer is for
+    pub logical: usize,
+    /// The block the first pointer points at; 0 for a run of holes
+    pub physical: usize,
+    pub len: usize,
+}
+
+impl BlockRun {
+    pub fn is_hole(&self) -> bool {
+        self.physical == 0
+    }
+}
+
+/// What an indirect block points to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IndirectPointers {
+    /// A (singly) indirect block's data blocks
+    Data(Vec<BlockRun>),
+    /// A doubly or triply indirect block's indirect blocks, one level down;
+    /// a missing one leaves its part of the file a hole
+    Indirect(Vec<IndirectBlock>),
+}
+
+/// An indirect block in a `BlockMap`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndirectBlock {
+    pub block: usize,
+    /// Index within the file of the first block it can point to
+    pub logical: usize,
+    pub pointers: IndirectPointers,
+}
+
+/// The block pointers of an inode, from `Ext2::block_map`. The unused
+/// pointers at the end of each level are left out: those past both the end
+/// of the file and the last pointer in use. Holes inside the file are kept.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BlockMap {
+    pub direct: Vec<BlockRun>,
+    pub indirect: Option<IndirectBlock>,
+    pub doubly_indirect: Option<IndirectBlock>,
+    pub triply_indirect: Option<IndirectBlock>,
+}
+
+// `pointers`, for the blocks from `logical` on, as runs, up to the last one
+// in use or the file's `end`, whichever is further
+fn runs(logical: usize, pointers: &[u32], end: usize) -> Vec<BlockRun> {
+    let used = pointers
+        .iter()
+        .rposition(|&p| p != 0)
+        .map_or(0, |i| i + 1)
+        .max(end.saturating_sub(logical).min(pointers.len()));
+    let mut runs: Vec<BlockRun> = Vec::new();
+    for (i, &pointer) in pointers[..used].iter().enumerate() {
+        let physical = pointer as usize;
+        if let Some(last) = runs.last_mut() {
+            let continues = if last.is_hole() {
+                physical == 0
+            } else {
+                physical == last.physical + last.len
+            };
+            if continues {
+                last.len += 1;
+                continue;
+            }
+        }
+        runs.push(BlockRun {
+            logical: logical + i,
+            physical,
+            len: 1,
+        });
+    }
+    runs
+}
+
+impl Ext2 {
+    /// The tree of block pointers of `inode`. Special files and fast
+    /// symlinks, which keep no blocks, have an empty one. In lenient mode an
+    /// indirect block that can't be read is shown pointing at nothing.
+    pub fn block_map(&self, inode: usize) -> Result<BlockMap> {
+        let record = self.get_inode(inode)?;
+        let is_fast_symlink = record.is_symlink() && record.size() < 60;
+        if record.is_special() || is_fast_symlink {
+            return Ok(BlockMap::default());
+        }
+        let size = if record.is_dir() {
+            // size_high is the directory ACL for directories
+            record.size_low as u64
+        } else {
+            record.size()
+        };
+        let end = size.div_ceil(self.block_size as u64) as usize;
+        let per_block = self.block_size / 4;
+        let first_indirect = 12;
+        let first_doubly = first_indirect + per_block;
+        let first_triply = first_doubly + per_block * per_block;
+        Ok(BlockMap {
+            direct: runs(0, &record.direct_pointer, end),
+            indirect: self.indirect_block(record.indirect_pointer, first_indirect, 1, end)?,
+            doubly_indirect: self.indirect_block(record.doubly_indirect, first_doubly, 2, end)?,
+            triply_indirect: self.indirect_block(record.triply_indirect, first_triply, 3, end)?,
+        })
+    }
+
+    // the indirect block `block`, `depth` levels above the data, whose first
+    // pointer leads to logical block `logical`, in a file of `end` blocks;
+    // `None` if it's missing
+    fn indirect_block(
+        &self,
+        block: u32,
+        logical: usize,
+        depth: u32,
+        end: usize,
+    ) -> Result<Option<IndirectBlock>> {
+        if block == 0 {
+            return Ok(None);
+        }
+        let kind = match depth {
+            1 => "indirect",
+            2 => "doubly indirect",
+            _ => "triply indirect",
+        };
+        let pointers: Vec<u32> = match self.block(block as usize) {
+            Ok(bytes) => bytes
+                .chunks_exact(4)
+                .map(|p| u32::from_le_bytes(p.try_into().unwrap()))
+                .collect(),
+            Err(e) => {
+                let e = e.in_block(kind, block as usize);
+                if !self.tolerate(&e) {
+                    return Err(e);
+                }
+                Vec::new()
+            }
+        };
+        let pointers = if depth == 1 {
+            IndirectPointers::Data(runs(logical, &pointers, end))
+        } else {
+            // how many of the file's blocks each child covers
+            let span = (self.block_size / 4).pow(depth - 1);
+            let mut children = Vec::new();
+            for (i, &child) in pointers.iter().enumerate() {
+                if let Some(child) =
+                    self.indirect_block(child, logical + i * span, depth - 1, end)?
+                {
+                    children.push(child);
+                }
+            }
+            IndirectPointers::Indirect(children)
+        };
+        Ok(Some(IndirectBlock {
+            block: block as usize,
+            logical,
+            pointers,
+        }))
+    }
+}
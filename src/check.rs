@@ -0,0 +1,788 @@
+// A consistency checker, in the spirit of `e2fsck`.
+//
+// `Ext2::check` walks the directory tree from the root and every allocated
+// inode, works out which inodes and blocks are actually in use, and compares
+// that against the bitmaps, the free counts and the link counts on disk.
+// `Ext2::repair` fixes the mechanical subset of what it finds.
+
+use crate::progress::check_cancel;
+use crate::structs::{self, FeatureIncompat};
+use crate::{Ext2, InodeNo, NameKind, NoProgress, Progress, RawDirEntry, Result};
+use log::info;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
+
+/// One problem found by `Ext2::check`, with enough detail to locate it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Inconsistency {
+    /// Reading an inode (or the directory it describes) failed outright
+    Unreadable {
+        inode: usize,
+        path: Option<String>,
+        error: String,
+    },
+    /// A group's block or inode bitmap couldn't be read
+    UnreadableBitmap { group: usize, error: String },
+    /// A directory entry names an inode number outside the filesystem
+    EntryOutOfRange { path: String, inode: usize },
+    /// A directory entry points at an inode the inode bitmap says is free
+    EntryToUnallocated { path: String, inode: usize },
+    /// A directory entry's type indicator disagrees with the inode's mode
+    EntryTypeMismatch {
+        path: String,
+        inode: usize,
+        indicator: u8,
+        mode: u16,
+    },
+    /// A directory's `.` entry is missing, misplaced or points elsewhere
+    BadDot { dir: usize, path: String },
+    /// A directory's `..` entry is missing or doesn't point at its parent
+    BadDotDot {
+        dir: usize,
+        path: String,
+        found: Option<usize>,
+        parent: usize,
+    },
+    /// The link count stored in the inode disagrees with the directory entries
+    LinkCount {
+        inode: usize,
+        path: Option<String>,
+        recorded: u16,
+        actual: u32,
+    },
+    /// The inode is allocated but no directory entry reaches it
+    Unreferenced { inode: usize },
+    /// A reserved inode (below the first usable one) is marked free
+    ReservedInodeFree { inode: usize },
+    /// A reserved inode has a mode it can't have: the root isn't a directory,
+    /// or one of the others is something other than a regular file
+    ReservedInodeMode { inode: usize, mode: u16 },
+    /// An inode points at a block number outside the filesystem
+    BadBlockPointer { inode: usize, block: usize },
+    /// More than one inode claims the same block
+    DuplicateBlock { block: usize, inodes: Vec<usize> },
+    /// A block in use is marked free in the block bitmap
+    BlockMarkedFree { group: usize, block: usize },
+    /// A block nothing uses is marked in use in the block bitmap
+    BlockMarkedUsed { group: usize, block: usize },
+    /// A free block count (of a group, or the superblock when `group` is None)
+    /// disagrees with the block bitmap
+    FreeBlocksCount {
+        group: Option<usize>,
+        recorded: u32,
+        actual: u32,
+    },
+    /// A free inode count disagrees with the inode bitmap
+    FreeInodesCount {
+        group: Option<usize>,
+        recorded: u32,
+        actual: u32,
+    },
+    /// A group's directory count disagrees with its allocated directories
+    DirsCount {
+        group: usize,
+        recorded: u16,
+        actual: u16,
+    },
+}
+
+impl fmt::Display for Inconsistency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // "inode 12 (/a/b)" when we know a path for it
+        fn inode_at(inode: usize, path: &Option<String>) -> String {
+            match path {
+                Some(path) => format!("inode {} ({})", inode, path),
+                None => format!("inode {}", inode),
+            }
+        }
+        fn counted(group: &Option<usize>) -> String {
+            match group {
+                Some(group) => format!("group {}", group),
+                None => String::from("superblock"),
+            }
+        }
+        match self {
+            Inconsistency::Unreadable { inode, path, error } => {
+                write!(f, "{}: unreadable: {}", inode_at(*inode, path), error)
+            }
+            Inconsistency::UnreadableBitmap { group, error } => {
+                write!(f, "group {}: unreadable bitmap: {}", group, error)
+            }
+            Inconsistency::EntryOutOfRange { path, inode } => {
+                write!(f, "{}: entry points at out-of-range inode {}", path, inode)
+            }
+            Inconsistency::EntryToUnallocated { path, inode } => {
+                write!(f, "{}: entry points at unallocated inode {}", path, inode)
+            }
+            Inconsistency::EntryTypeMismatch {
+                path,
+                inode,
+                indicator,
+                mode,
+            } => write!(
+                f,
+                "{}: entry type {} doesn't match inode {} mode {:#06o}",
+                path, indicator, inode, mode
+            ),
+            Inconsistency::BadDot { dir, path } => {
+                write!(
+                    f,
+                    "{}: '.' is not the first entry pointing at inode {}",
+                    path, dir
+                )
+            }
+            Inconsistency::BadDotDot {
+                dir,
+                path,
+                found,
+                parent,
+            } => match found {
+                Some(found) => write!(
+                    f,
+                    "{}: '..' of inode {} points at inode {}, parent is inode {}",
+                    path, dir, found, parent
+                ),
+                None => write!(f, "{}: inode {} has no '..' entry", path, dir),
+            },
+            Inconsistency::LinkCount {
+                inode,
+                path,
+                recorded,
+                actual,
+            } => write!(
+                f,
+                "{}: link count is {}, should be {}",
+                inode_at(*inode, path),
+                recorded,
+                actual
+            ),
+            Inconsistency::Unreferenced { inode } => {
+                write!(
+                    f,
+                    "inode {}: allocated but not linked from any directory",
+                    inode
+                )
+            }
+            Inconsistency::ReservedInodeFree { inode } => {
+                write!(f, "inode {}: reserved but marked free", inode)
+            }
+            Inconsistency::ReservedInodeMode { inode, mode } => {
+                write!(
+                    f,
+                    "inode {}: reserved inode has bad mode {:#06o}",
+                    inode, mode
+                )
+            }
+            Inconsistency::BadBlockPointer { inode, block } => {
+                write!(f, "inode {}: points at out-of-range block {}", inode, block)
+            }
+            Inconsistency::DuplicateBlock { block, inodes } => {
+                write!(f, "block {}: claimed by inodes {:?}", block, inodes)
+            }
+            Inconsistency::BlockMarkedFree { group, block } => {
+                write!(
+                    f,
+                    "group {}: block {} is in use but marked free",
+                    group, block
+                )
+            }
+            Inconsistency::BlockMarkedUsed { group, block } => {
+                write!(
+                    f,
+                    "group {}: block {} is unused but marked in use",
+                    group, block
+                )
+            }
+            Inconsistency::FreeBlocksCount {
+                group,
+                recorded,
+                actual,
+            } => write!(
+                f,
+                "{}: free blocks count is {}, bitmap says {}",
+                counted(group),
+                recorded,
+                actual
+            ),
+            Inconsistency::FreeInodesCount {
+                group,
+                recorded,
+                actual,
+            } => write!(
+                f,
+                "{}: free inodes count is {}, bitmap says {}",
+                counted(group),
+                recorded,
+                actual
+            ),
+            Inconsistency::DirsCount {
+                group,
+                recorded,
+                actual,
+            } => write!(
+                f,
+                "group {}: directory count is {}, should be {}",
+                group, recorded, actual
+            ),
+        }
+    }
+}
+
+// the mode bits a directory entry type indicator stands for (0 = unknown)
+fn indicator_mode(indicator: u8) -> Option<u16> {
+    let mode = match indicator {
+        1 => structs::TypePerm::FILE,
+        2 => structs::TypePerm::DIRECTORY,
+        3 => structs::TypePerm::CHAR_DEVICE,
+        4 => structs::TypePerm::BLOCK_DEVICE,
+        5 => structs::TypePerm::FIFO,
+        6 => structs::TypePerm::SOCKET,
+        7 => structs::TypePerm::SYMLINK,
+        _ => return None,
+    };
+    Some(mode.bits())
+}
+
+impl Ext2 {
+    // check the filesystem's invariants and return every problem found
+    // an empty list means the filesystem is consistent; what can't be read is
+    // reported as `Unreadable` like anything else found, and should the check
+    // stop part way, that's `Unreadable` for the root, so a check that didn't
+    // finish never reads as a clean filesystem
+    pub fn check(&self) -> Vec<Inconsistency> {
+        self.check_with_progress(&NoProgress).unwrap_or_else(|err| {
+            vec![Inconsistency::Unreadable {
+                inode: 2,
+                path: Some(String::from("/")),
+                error: err.to_string(),
+            }]
+        })
+    }
+
+    // `check`, telling `progress` about each directory as it's read
+    // cancelling stops with `Ext2Error::Cancelled` and no list
+    pub fn check_with_progress(&self, progress: &dyn Progress) -> Result<Vec<Inconsistency>> {
+        let mut problems = Vec::new();
+        let sb = &self.superblock;
+        let inodes_count = sb.inodes_count as usize;
+        let typed_entries = FeatureIncompat::from_bits_truncate(sb.features_req)
+            .contains(FeatureIncompat::FILETYPE);
+
+        // walk the tree from the root, counting the entries that reference each inode
+        let mut references: HashMap<usize, u32> = HashMap::new();
+        let mut paths: HashMap<usize, String> = HashMap::new();
+        let mut visited: HashSet<usize> = HashSet::new();
+        // (directory inode, its parent, its path)
+        let mut queue = VecDeque::from([(2, 2, String::from("/"))]);
+        paths.insert(2, String::from("/"));
+        while let Some((dir, parent, dir_path)) = queue.pop_front() {
+            if !visited.insert(dir) {
+                continue;
+            }
+            check_cancel(progress)?;
+            progress.on_item(&dir_path);
+            let entries = match self.read_dir_raw(dir) {
+                Ok(entries) => entries,
+                Err(err) => {
+                    problems.push(Inconsistency::Unreadable {
+                        inode: dir,
+                        path: Some(dir_path),
+                        error: err.to_string(),
+                    });
+                    continue;
+                }
+            };
+            if !matches!(entries.first(), Some(entry) if entry.inode == dir && entry.name.0 == b".")
+            {
+                problems.push(Inconsistency::BadDot {
+                    dir,
+                    path: dir_path.clone(),
+                });
+            }
+            let dotdot = entries.iter().find(|entry| entry.name.0 == b"..");
+            if dotdot.map(|entry| entry.inode) != Some(parent) {
+                problems.push(Inconsistency::BadDotDot {
+                    dir,
+                    path: dir_path.clone(),
+                    found: dotdot.map(|entry| entry.inode),
+                    parent,
+                });
+            }
+            for RawDirEntry {
+                inode,
+                name,
+                file_type: indicator,
+                ..
+            } in entries
+            {
+                let kind = name.kind();
+                let name = name.to_string();
+                let path = if dir_path == "/" {
+                    format!("/{}", name)
+                } else {
+                    format!("{}/{}", dir_path, name)
+                };
+                if inode > inodes_count {
+                    problems.push(Inconsistency::EntryOutOfRange { path, inode });
+                    continue;
+                }
+                *references.entry(inode).or_default() += 1;
+                match self.inode_is_allocated(inode) {
+                    Ok(true) => {}
+                    Ok(false) => {
+                        problems.push(Inconsistency::EntryToUnallocated { path, inode });
+                        continue;
+                    }
+                    Err(err) => {
+                        problems.push(Inconsistency::Unreadable {
+                            inode,
+                            path: Some(path),
+                            error: err.to_string(),
+                        });
+                        continue;
+                    }
+                }
+                let mode = match self.get_inode(inode) {
+                    Ok(target) => target.type_perm.bits() & 0xF000,
+                    Err(err) => {
+                        problems.push(Inconsistency::Unreadable {
+                            inode,
+                            path: Some(path),
+                            error: err.to_string(),
+                        });
+                        continue;
+                    }
+                };
+                if typed_entries {
+                    if let Some(expected) = indicator_mode(indicator) {
+                        if expected != mode {
+                            problems.push(Inconsistency::EntryTypeMismatch {
+                                path: path.clone(),
+                                inode,
+                                indicator,
+                                mode,
+                            });
+                        }
+                    }
+                }
+                if kind == NameKind::Dot {
+                    continue;
+                }
+                paths.entry(inode).or_insert_with(|| path.clone());
+                if mode == structs::TypePerm::DIRECTORY.bits() {
+                    queue.push_back((inode, dir, path));
+                }
+            }
+        }
+
+        // look at every allocated inode: its link count and the blocks it owns
+        let mut owners: HashMap<usize, Vec<usize>> = HashMap::new();
+        let mut dirs_per_group = vec![0u16; self.block_groups.len()];
+        let first_usable = self.first_usable_inode();
+        for inode in 1..=inodes_count {
+            match self.inode_is_allocated(inode) {
+                Ok(true) => {}
+                Ok(false) => {
+                    if inode < first_usable {
+                        problems.push(Inconsistency::ReservedInodeFree { inode });
+                    }
+                    continue;
+                }
+                Err(err) => {
+                    problems.push(Inconsistency::Unreadable {
+                        inode,
+                        path: None,
+                        error: err.to_string(),
+                    });
+                    continue;
+                }
+            }
+            let record = match self.get_inode(inode) {
+                Ok(record) => record,
+                Err(err) => {
+                    problems.push(Inconsistency::Unreadable {
+                        inode,
+                        path: None,
+                        error: err.to_string(),
+                    });
+                    continue;
+                }
+            };
+            let file_type = record.type_perm.bits() & 0xF000;
+            if file_type == structs::TypePerm::DIRECTORY.bits() {
+                match InodeNo(inode).to_group_and_index(&self.superblock) {
+                    Ok((group, _)) => dirs_per_group[group] += 1,
+                    Err(err) => problems.push(Inconsistency::Unreadable {
+                        inode,
+                        path: paths.get(&inode).cloned(),
+                        error: err.to_string(),
+                    }),
+                }
+            }
+            let bad_mode = match inode {
+                2 => file_type != structs::TypePerm::DIRECTORY.bits(),
+                _ if inode < first_usable => {
+                    record.type_perm.bits() != 0 && file_type != structs::TypePerm::FILE.bits()
+                }
+                _ => false,
+            };
+            if bad_mode {
+                problems.push(Inconsistency::ReservedInodeMode {
+                    inode,
+                    mode: record.type_perm.bits(),
+                });
+            }
+            // reserved inodes (below first_inode, except the root) aren't linked from the tree
+            if inode == 2 || inode >= first_usable {
+                let actual = references.get(&inode).copied().unwrap_or(0);
+                if actual == 0 {
+                    problems.push(Inconsistency::Unreferenced { inode });
+                } else if actual != record.hard_links as u32 {
+                    problems.push(Inconsistency::LinkCount {
+                        inode,
+                        path: paths.get(&inode).cloned(),
+                        recorded: record.hard_links,
+                        actual,
+                    });
+                }
+            }
+            match self.owned_blocks(inode) {
+                Ok(blocks) => {
+                    for block in blocks {
+                        if block >= sb.blocks_count as usize {
+                            problems.push(Inconsistency::BadBlockPointer { inode, block });
+                        } else {
+                            owners.entry(block).or_default().push(inode);
+                        }
+                    }
+                }
+                Err(err) => problems.push(Inconsistency::Unreadable {
+                    inode,
+                    path: paths.get(&inode).cloned(),
+                    error: err.to_string(),
+                }),
+            }
+        }
+        let mut duplicates: Vec<_> = owners
+            .iter()
+            .filter(|(_, inodes)| inodes.len() > 1)
+            .map(|(&block, inodes)| Inconsistency::DuplicateBlock {
+                block,
+                inodes: inodes.clone(),
+            })
+            .collect();
+        duplicates.sort_by_key(|problem| match problem {
+            Inconsistency::DuplicateBlock { block, .. } => *block,
+            _ => unreachable!(),
+        });
+        problems.extend(duplicates);
+
+        // compare the bitmaps and free counts against what we found in use
+        let metadata = self.metadata_blocks();
+        let mut total_free_blocks = 0;
+        let mut total_free_inodes = 0;
+        for (group, descriptor) in self.block_groups.iter().enumerate() {
+            let first = self.group_first_block(group);
+            let bitmaps = self
+                .block_bitmap(group)
+                .and_then(|blocks| Ok((blocks, self.inode_bitmap(group)?)));
+            let (block_bitmap, inode_bitmap) = match bitmaps {
+                Ok(bitmaps) => bitmaps,
+                Err(err) => {
+                    problems.push(Inconsistency::UnreadableBitmap {
+                        group,
+                        error: err.to_string(),
+                    });
+                    continue;
+                }
+            };
+
+            for index in 0..block_bitmap.len() {
+                let block = first + index;
+                let marked = block_bitmap.is_set(index);
+                let used = metadata.contains(&block) || owners.contains_key(&block);
+                if used && !marked {
+                    problems.push(Inconsistency::BlockMarkedFree { group, block });
+                } else if marked && !used {
+                    problems.push(Inconsistency::BlockMarkedUsed { group, block });
+                }
+            }
+            let free_blocks = block_bitmap.count_clear() as u32;
+            if free_blocks != descriptor.free_blocks_count as u32 {
+                problems.push(Inconsistency::FreeBlocksCount {
+                    group: Some(group),
+                    recorded: descriptor.free_blocks_count as u32,
+                    actual: free_blocks,
+                });
+            }
+            total_free_blocks += free_blocks;
+
+            let free_inodes = inode_bitmap.count_clear() as u32;
+            if free_inodes != descriptor.free_inodes_count as u32 {
+                problems.push(Inconsistency::FreeInodesCount {
+                    group: Some(group),
+                    recorded: descriptor.free_inodes_count as u32,
+                    actual: free_inodes,
+                });
+            }
+            total_free_inodes += free_inodes;
+
+            if dirs_per_group[group] != descriptor.dirs_count {
+                problems.push(Inconsistency::DirsCount {
+                    group,
+                    recorded: descriptor.dirs_count,
+                    actual: dirs_per_group[group],
+                });
+            }
+        }
+        if total_free_blocks != sb.free_blocks_count {
+            problems.push(Inconsistency::FreeBlocksCount {
+                group: None,
+                recorded: sb.free_blocks_count,
+                actual: total_free_blocks,
+            });
+        }
+        if total_free_inodes != sb.free_inodes_count {
+            problems.push(Inconsistency::FreeInodesCount {
+                group: None,
+                recorded: sb.free_inodes_count,
+                actual: total_free_inodes,
+            });
+        }
+        Ok(problems)
+    }
+
+    // fix the mechanical problems `check` finds, through the dirty-block layer:
+    // relink orphaned inodes (allocated, with data, never deleted, but linked
+    // from nowhere) into /lost+found, creating it if needed, then fix link
+    // counts and recompute the free and directory counts from the bitmaps
+    // freeing unreferenced inodes that hold nothing and clearing bitmap bits of
+    // blocks nothing uses only happen if `confirm` agrees to the question asked
+    // returns a line describing every change made
+    pub fn repair(&mut self, mut confirm: impl FnMut(&str) -> bool) -> Result<Vec<String>> {
+        self.check_writable()?;
+        let mut changes = Vec::new();
+        let mut changed = |change: String| {
+            info!("{}", change);
+            changes.push(change);
+        };
+
+        let unreferenced: Vec<usize> = self
+            .check_with_progress(&NoProgress)?
+            .into_iter()
+            .filter_map(|problem| match problem {
+                Inconsistency::Unreferenced { inode } => Some(inode),
+                _ => None,
+            })
+            .collect();
+        // whatever an orphaned directory holds comes back along with it
+        let mut in_orphans = HashSet::new();
+        for &inode in &unreferenced {
+            if self.get_inode(inode)?.type_perm.bits() & 0xF000
+                == structs::TypePerm::DIRECTORY.bits()
+            {
+                if let Ok(entries) = self.read_dir_raw(inode) {
+                    in_orphans.extend(
+                        entries
+                            .into_iter()
+                            .filter(|entry| entry.inode != inode && entry.name.0 != b"..")
+                            .map(|entry| entry.inode),
+                    );
+                }
+            }
+        }
+        let mut empty = Vec::new();
+        for inode in unreferenced
+            .into_iter()
+            .filter(|inode| !in_orphans.contains(inode))
+        {
+            let record = self.get_inode(inode)?;
+            if record.size() == 0 || record.dtime != 0 {
+                empty.push(inode);
+                continue;
+            }
+            if self.lookup(2, "lost+found")?.is_none() {
+                let dir = self.ensure_lost_and_found()?;
+                changed(format!("created /lost+found (inode {})", dir));
+            }
+            let path = self.relink_orphan(inode)?;
+            changed(format!("inode {}: relinked as {}", inode, path));
+        }
+        if !empty.is_empty()
+            && confirm(&format!(
+                "free {} unreferenced inode(s) holding no data?",
+                empty.len()
+            ))
+        {
+            for inode in empty {
+                let (group, index) = InodeNo(inode).to_group_and_index(&self.superblock)?;
+                self.inode_bitmap_mut(group)?.clear(index);
+                let now = self.now();
+                self.inode_mut(inode)?.dtime = now;
+                changed(format!("inode {}: freed", inode));
+            }
+        }
+
+        let mut unused_blocks = Vec::new();
+        for problem in self.check_with_progress(&NoProgress)? {
+            match problem {
+                Inconsistency::LinkCount {
+                    inode,
+                    recorded,
+                    actual,
+                    ..
+                } => {
+                    self.inode_mut(inode)?.hard_links = actual as u16;
+                    changed(format!(
+                        "inode {}: link count {} -> {}",
+                        inode, recorded, actual
+                    ));
+                }
+                Inconsistency::BlockMarkedFree { group, block } => {
+                    self.set_block_bit(group, block, true)?;
+                    changed(format!("block {}: marked in use", block));
+                }
+                Inconsistency::BlockMarkedUsed { group, block } => {
+                    unused_blocks.push((group, block));
+                }
+                Inconsistency::ReservedInodeFree { inode } => {
+                    let (group, index) = InodeNo(inode).to_group_and_index(&self.superblock)?;
+                    self.inode_bitmap_mut(group)?.set(index);
+                    changed(format!("inode {}: reserved, marked in use", inode));
+                }
+                _ => {}
+            }
+        }
+        if !unused_blocks.is_empty()
+            && confirm(&format!(
+                "mark {} block(s) no inode uses as free?",
+                unused_blocks.len()
+            ))
+        {
+            for (group, block) in unused_blocks {
+                self.set_block_bit(group, block, false)?;
+                changed(format!("block {}: marked free", block));
+            }
+        }
+
+        // the counts go last, once the bitmaps are right
+        for problem in self.check_with_progress(&NoProgress)? {
+            match problem {
+                Inconsistency::FreeBlocksCount {
+                    group,
+                    recorded,
+                    actual,
+                } => {
+                    match group {
+                        Some(group) => self.block_groups[group].free_blocks_count = actual as u16,
+                        None => self.superblock.free_blocks_count = actual,
+                    }
+                    changed(format!(
+                        "{}: free blocks count {} -> {}",
+                        group.map_or(String::from("superblock"), |g| format!("group {}", g)),
+                        recorded,
+                        actual
+                    ));
+                }
+                Inconsistency::FreeInodesCount {
+                    group,
+                    recorded,
+                    actual,
+                } => {
+                    match group {
+                        Some(group) => self.block_groups[group].free_inodes_count = actual as u16,
+                        None => self.superblock.free_inodes_count = actual,
+                    }
+                    changed(format!(
+                        "{}: free inodes count {} -> {}",
+                        group.map_or(String::from("superblock"), |g| format!("group {}", g)),
+                        recorded,
+                        actual
+                    ));
+                }
+                Inconsistency::DirsCount {
+                    group,
+                    recorded,
+                    actual,
+                } => {
+                    self.block_groups[group].dirs_count = actual;
+                    changed(format!(
+                        "group {}: directory count {} -> {}",
+                        group, recorded, actual
+                    ));
+                }
+                _ => {}
+            }
+        }
+        if self.check_with_progress(&NoProgress)?.is_empty() {
+            // checked and clean, like e2fsck leaves it
+            self.superblock.mnt_count = 0;
+            self.superblock.lastcheck = self.now();
+        }
+        self.write_metadata()?;
+        Ok(changes)
+    }
+
+    /// Inodes marked in use that nothing refers to: either no entry was
+    /// found for them walking the tree from the root, or their link count is
+    /// zero. These are what `relink_orphan` can bring back into /lost+found.
+    pub fn orphans(&self) -> Result<Vec<usize>> {
+        let mut orphans: Vec<usize> = self
+            .check_with_progress(&NoProgress)?
+            .into_iter()
+            .filter_map(|problem| match problem {
+                Inconsistency::Unreferenced { inode } => Some(inode),
+                _ => None,
+            })
+            .collect();
+        orphans.extend(
+            self.inodes()
+                .filter(|(_, record)| record.hard_links == 0)
+                .map(|(inode, _)| inode),
+        );
+        orphans.sort_unstable();
+        orphans.dedup();
+        Ok(orphans)
+    }
+
+    // set or clear the block bitmap bit of `block`, which is in group `group`
+    fn set_block_bit(&mut self, group: usize, block: usize, value: bool) -> Result<()> {
+        let index = block - self.group_first_block(group);
+        let mut bitmap = self.block_bitmap_mut(group)?;
+        if value {
+            bitmap.set(index);
+        } else {
+            bitmap.clear(index);
+        }
+        Ok(())
+    }
+
+    // every block holding filesystem metadata rather than file data: superblock
+    // copies, descriptor tables (plus their reserved growth blocks), bitmaps and
+    // inode tables
+    pub fn metadata_blocks(&self) -> HashSet<usize> {
+        (0..self.block_groups.len())
+            .flat_map(|group| self.group_metadata_blocks(group))
+            .collect()
+    }
+
+    // the metadata blocks belonging to block group `group`
+    pub(crate) fn group_metadata_blocks(&self, group: usize) -> Vec<usize> {
+        let sb = &self.superblock;
+        let mut blocks = Vec::new();
+        let descriptor_blocks = (self.block_groups.len() * 32).div_ceil(self.block_size);
+        let inode_table_blocks =
+            (sb.inodes_per_group as usize * InodeNo::slot_size(sb)).div_ceil(self.block_size);
+        let descriptor = &self.block_groups[group];
+        let first = self.group_first_block(group);
+        if self.group_has_superblock(group) {
+            let copies = 1 + descriptor_blocks + sb.reserved_gdt_blocks as usize;
+            blocks.extend(first..first + copies);
+        }
+        blocks.push(descriptor.block_usage_addr as usize);
+        blocks.push(descriptor.inode_usage_addr as usize);
+        let table = descriptor.inode_table_block as usize;
+        blocks.extend(table..table + inode_table_blocks);
+        blocks
+    }
+}
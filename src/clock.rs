@@ -0,0 +1,36 @@
+// Where timestamps come from.
+//
+// Everything that stamps a time into the filesystem (inode times, mount and
+// write times, dtime) asks the `Clock` the filesystem was opened with, so a
+// fixed clock makes the resulting image byte-for-byte predictable.
+
+use std::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A source of ext2 timestamps: seconds since the Unix epoch. Shared between
+/// threads along with the filesystem, so it must be `Send` and `Sync`.
+pub trait Clock: fmt::Debug + Send + Sync {
+    fn now(&self) -> u32;
+}
+
+/// The real time, the default.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> u32 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_secs() as u32)
+    }
+}
+
+/// A clock stopped at the given time.
+#[derive(Debug, Clone, Copy)]
+pub struct FixedClock(pub u32);
+
+impl Clock for FixedClock {
+    fn now(&self) -> u32 {
+        self.0
+    }
+}
@@ -0,0 +1,111 @@
+// Command lines as the shell reads them: split into words with sh-like
+// quoting, after the first word is replaced if it's an alias.
+//
+// An alias is replaced by its text before the line is split, so quotes in
+// the alias and in the rest of the line are read together, and only once:
+// an alias whose text starts with another alias's name, or its own, runs
+// that command rather than expanding again.
+
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use thiserror::Error;
+
+/// The names that are never expanded, so aliases can always be managed.
+pub const ALIAS_BUILTINS: [&str; 2] = ["alias", "unalias"];
+
+/// A command line with a quote that's never closed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+#[error("unterminated {0} quote")]
+pub struct UnclosedQuote(pub char);
+
+/// Split `line` into words at whitespace, like sh: text in single quotes is
+/// taken as it is, in double quotes with `\` escaping `"` and `\`, and a
+/// `\` outside quotes escapes the character after it. Quotes join onto
+/// whatever they touch, e.g. `a"b c"` is one word, and `''` is an empty one.
+pub fn split_words(line: &str) -> Result<Vec<String>, UnclosedQuote> {
+    let mut words = Vec::new();
+    // the word being read, if one has started
+    let mut word: Option<String> = None;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => words.extend(word.take()),
+            '\'' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => word.push(c),
+                        None => return Err(UnclosedQuote('\'')),
+                    }
+                }
+            }
+            '"' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c @ ('"' | '\\')) => word.push(c),
+                            Some(c) => {
+                                word.push('\\');
+                                word.push(c);
+                            }
+                            None => return Err(UnclosedQuote('"')),
+                        },
+                        Some(c) => word.push(c),
+                        None => return Err(UnclosedQuote('"')),
+                    }
+                }
+            }
+            // a trailing backslash stands for itself
+            '\\' => word
+                .get_or_insert_with(String::new)
+                .push(chars.next().unwrap_or('\\')),
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    words.extend(word);
+    Ok(words)
+}
+
+/// `word` quoted so that `split_words` reads it back as one word, the way
+/// `alias` shows alias texts: as it is if nothing in it needs quoting,
+/// otherwise in single quotes, with any single quote in it as `'\''`.
+pub fn quote_word(word: &str) -> Cow<str> {
+    let plain = |c: char| c.is_alphanumeric() || "-_./=:,+@%".contains(c);
+    if !word.is_empty() && word.chars().all(plain) {
+        return Cow::Borrowed(word);
+    }
+    Cow::Owned(format!("'{}'", word.replace('\'', r"'\''")))
+}
+
+/// `line` with its first word replaced by the text of the alias it names,
+/// if there's one, and the rest of the line after it; `line` as it is
+/// otherwise. Only a plain first word is looked up, so quoting it, e.g.
+/// `'ls'`, gets the command itself, and neither of `ALIAS_BUILTINS` is ever
+/// expanded.
+pub fn expand_alias<'a>(line: &'a str, aliases: &BTreeMap<String, String>) -> Cow<'a, str> {
+    let start = line.len() - line.trim_start().len();
+    let end = line[start..]
+        .find(char::is_whitespace)
+        .map_or(line.len(), |len| start + len);
+    let name = &line[start..end];
+    if ALIAS_BUILTINS.contains(&name) {
+        return Cow::Borrowed(line);
+    }
+    match aliases.get(name) {
+        Some(text) => Cow::Owned(format!("{}{}", text, &line[end..])),
+        None => Cow::Borrowed(line),
+    }
+}
+
+/// Whether `name` can be an alias: a plain word, so that it's found as the
+/// first word of a line, and not one of `ALIAS_BUILTINS`.
+pub fn valid_alias_name(name: &str) -> bool {
+    !name.is_empty()
+        && !ALIAS_BUILTINS.contains(&name)
+        && !name
+            .chars()
+            .any(|c| c.is_whitespace() || "'\"\\=".contains(c))
+}
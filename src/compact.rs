@@ -0,0 +1,122 @@
+// Compacting a directory: packing its entries into as few blocks as they fit
+// in and giving back the rest.
+//
+// ext2 never shrinks a directory on its own, so one that once held thousands
+// of entries keeps all its blocks, and every lookup in it reads them all,
+// long after most of the entries are gone. The live entries are written out
+// again back to back, `.` and `..` first in block 0, into the directory's
+// first blocks; only once they're all in place are the blocks after them
+// freed, with any indirect block that leaves pointing at nothing, and the
+// size cut down. Like every modification it stays in the dirty-block layer
+// until `sync`, so the image never holds a half-compacted directory, and a
+// failure part way rolls back to the directory as it was.
+
+use crate::structs::InodeFlags;
+use crate::write::{entry_len, write_dir_entry};
+use crate::{rec_len_to_disk, Ext2, Ext2Error, Result};
+use log::debug;
+
+impl Ext2 {
+    /// Rewrite the entries of directory `dir` densely into as few blocks as
+    /// hold them, free the blocks that leaves unused and shrink the
+    /// directory to match, and return how many blocks that freed. The order
+    /// of the entries may change, but `.` and `..` stay first in block 0. An
+    /// indexed directory loses its hash index, which `e2fsck -D` rebuilds.
+    pub fn compact_dir(&mut self, dir: usize) -> Result<usize> {
+        self.check_writable()?;
+        let record = self.get_inode(dir)?;
+        if !record.is_dir() {
+            return Err(Ext2Error::NotADirectory {
+                name: format!("inode {}", dir),
+            });
+        }
+        let block_size = self.block_size;
+        let old_blocks = record.size_low as usize / block_size;
+
+        // the entries, `.` and `..` first, laid out block by block
+        let mut entries: Vec<(usize, u8, Vec<u8>)> = self
+            .read_dir_raw(dir)?
+            .into_iter()
+            .map(|entry| (entry.inode, entry.file_type, entry.name.0.to_vec()))
+            .collect();
+        entries.sort_by_key(|(_, _, name)| (name != b".", name != b".."));
+        if entries.len() < 2 || entries[0].2 != b"." || entries[1].2 != b".." {
+            return Err(Ext2Error::CorruptDirectory {
+                inode: dir,
+                block: record.direct_pointer[0] as usize,
+                offset: 0,
+                reason: String::from("no . or .. entry"),
+            });
+        }
+        let mut blocks = vec![vec![0; block_size]];
+        let mut offset = 0;
+        let mut last = 0;
+        for (inode, file_type, name) in &entries {
+            let len = entry_len(name.len());
+            if offset + len > block_size {
+                // the block's last entry takes up the rest of it
+                let rest = block_size - last;
+                blocks.last_mut().unwrap()[last + 4..last + 6]
+                    .copy_from_slice(&rec_len_to_disk(rest));
+                blocks.push(vec![0; block_size]);
+                offset = 0;
+            }
+            let block = blocks.last_mut().unwrap();
+            write_dir_entry(&mut block[offset..], *inode, len, name, *file_type);
+            last = offset;
+            offset += len;
+        }
+        let rest = block_size - last;
+        blocks.last_mut().unwrap()[last + 4..last + 6].copy_from_slice(&rec_len_to_disk(rest));
+        if blocks.len() >= old_blocks {
+            return Ok(0);
+        }
+
+        let saved = self.snapshot();
+        match self.replace_dir_blocks(dir, &blocks, old_blocks) {
+            Ok(freed) => {
+                debug!(
+                    "compacted directory inode {}: {} entries, {} block(s) down to {}",
+                    dir,
+                    entries.len(),
+                    old_blocks,
+                    blocks.len()
+                );
+                Ok(freed)
+            }
+            Err(err) => {
+                self.rollback(&saved);
+                Err(err)
+            }
+        }
+    }
+
+    // put `blocks` in as the first blocks of directory `dir`, then free its
+    // blocks from there to `old_blocks` and shrink it to fit; returns how
+    // many blocks were freed, indirect ones included
+    fn replace_dir_blocks(
+        &mut self,
+        dir: usize,
+        blocks: &[Vec<u8>],
+        old_blocks: usize,
+    ) -> Result<usize> {
+        let mut goal = self.group_first_block(self.inode_group(dir));
+        for (logical, contents) in blocks.iter().enumerate() {
+            // a hole, which a directory shouldn't have, is filled
+            let block_num = self.map_block(dir, logical, goal)?;
+            self.block_mut(block_num)?.copy_from_slice(contents);
+            goal = block_num + 1;
+        }
+        let freed = self.punch_blocks(dir, &(blocks.len()..old_blocks))?;
+        let sectors = self.block_size as u32 / 512;
+        let size = (blocks.len() * self.block_size) as u32;
+        let record = self.inode_mut(dir)?;
+        record.size_low = size;
+        record.sectors_count = record.sectors_count.saturating_sub(freed as u32 * sectors);
+        // the entries have all moved, so a hash index would point at the
+        // wrong blocks
+        record.flags &= !InodeFlags::INDEX.bits();
+        self.touch_modified(dir)?;
+        Ok(freed)
+    }
+}
@@ -0,0 +1,411 @@
+// Comparing files and directory trees, possibly on two different images.
+//
+// File contents are compared a block at a time straight out of both images,
+// so neither file is ever read into memory as a whole.
+//
+// Whole images are compared twice over: block by block, which only means
+// something when one image is a modified copy of the other, and by what's in
+// them, path by path, which doesn't care how either is laid out.
+
+use crate::structs::{Inode, TypePerm};
+use crate::{Ext2, Result, WalkControl, WalkOptions};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// One way two directory trees differ, found by `Ext2::diff_trees`. Paths
+/// are relative to the roots being compared.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Difference {
+    OnlyInA {
+        path: String,
+    },
+    OnlyInB {
+        path: String,
+    },
+    /// the same name is a different kind of file on each side
+    Type {
+        path: String,
+        a: &'static str,
+        b: &'static str,
+    },
+    Size {
+        path: String,
+        a: u64,
+        b: u64,
+    },
+    /// same size, but the contents differ from `offset` on
+    Content {
+        path: String,
+        offset: u64,
+    },
+}
+
+impl Difference {
+    /// The path that differs.
+    pub fn path(&self) -> &str {
+        match self {
+            Difference::OnlyInA { path }
+            | Difference::OnlyInB { path }
+            | Difference::Type { path, .. }
+            | Difference::Size { path, .. }
+            | Difference::Content { path, .. } => path,
+        }
+    }
+}
+
+impl fmt::Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Difference::OnlyInA { path } => write!(f, "only in A: {}", path),
+            Difference::OnlyInB { path } => write!(f, "only in B: {}", path),
+            Difference::Type { path, a, b } => write!(f, "{}: {} in A, {} in B", path, a, b),
+            Difference::Size { path, a, b } => {
+                write!(f, "{}: sizes differ ({} bytes in A, {} in B)", path, a, b)
+            }
+            Difference::Content { path, offset } => {
+                write!(f, "{}: contents differ at byte {}", path, offset)
+            }
+        }
+    }
+}
+
+/// How two whole images differ, found by `Ext2::diff_images`. Paths are
+/// absolute, and every list is sorted, so two runs over the same images give
+/// the same report.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ImageDiff {
+    /// Why the images weren't compared block by block, if they weren't
+    pub note: Option<String>,
+    /// Block numbers whose contents differ, including blocks past the end of
+    /// the smaller image
+    pub blocks: Vec<usize>,
+    pub only_in_a: Vec<String>,
+    pub only_in_b: Vec<String>,
+    /// Paths on both sides whose inodes differ in metadata
+    pub metadata: Vec<MetadataDifference>,
+    /// Paths on both sides whose contents differ
+    pub contents: Vec<ContentDifference>,
+}
+
+impl ImageDiff {
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+            && self.only_in_a.is_empty()
+            && self.only_in_b.is_empty()
+            && self.metadata.is_empty()
+            && self.contents.is_empty()
+    }
+}
+
+/// A path whose inode differs in metadata between two images.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MetadataDifference {
+    pub path: String,
+    /// The inode the path leads to in A
+    pub inode_a: usize,
+    /// The inode the path leads to in B
+    pub inode_b: usize,
+    /// The names of the fields that differ: any of "type", "mode", "uid",
+    /// "gid", "links", "size", "mtime" and "device"
+    pub fields: Vec<&'static str>,
+}
+
+/// A path whose contents differ between two images: the data of a regular
+/// file or the target of a symlink, each as its SHA-256 in hex.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ContentDifference {
+    pub path: String,
+    pub hash_a: String,
+    pub hash_b: String,
+}
+
+impl Ext2 {
+    /// Compare this whole image, as A, with `other`, as B.
+    ///
+    /// If both have the same block size and UUID, every block is compared;
+    /// otherwise they can't be copies of one another, so that's skipped and
+    /// `note` says why. Then both trees are walked from the root, without
+    /// following symlinks, and every path is compared: its metadata, and
+    /// the contents of regular files and symlinks. Access times, change
+    /// times and the sizes of directories are left out, since they change
+    /// without anything in the tree changing.
+    pub fn diff_images(&self, other: &Ext2) -> Result<ImageDiff> {
+        let mut diff = ImageDiff::default();
+        if self.block_size != other.block_size {
+            diff.note = Some(format!(
+                "block sizes differ ({} and {}); compared by contents only",
+                self.block_size, other.block_size
+            ));
+        } else if self.uuid != other.uuid {
+            diff.note = Some(format!(
+                "UUIDs differ ({} and {}); compared by contents only",
+                self.uuid, other.uuid
+            ));
+        } else {
+            let count = self.superblock.blocks_count as usize;
+            let other_count = other.superblock.blocks_count as usize;
+            for block_num in 0..count.min(other_count) {
+                if self.data_block(block_num)? != other.data_block(block_num)? {
+                    diff.blocks.push(block_num);
+                }
+            }
+            diff.blocks
+                .extend(count.min(other_count)..count.max(other_count));
+        }
+
+        let mine = self.tree_paths()?;
+        let theirs = other.tree_paths()?;
+        for (path, &inode) in &mine {
+            let Some(&other_inode) = theirs.get(path) else {
+                diff.only_in_a.push(path.clone());
+                continue;
+            };
+            let record = self.get_inode(inode)?;
+            let other_record = other.get_inode(other_inode)?;
+            let fields = metadata_differences(record, other_record);
+            if !fields.is_empty() {
+                diff.metadata.push(MetadataDifference {
+                    path: path.clone(),
+                    inode_a: inode,
+                    inode_b: other_inode,
+                    fields,
+                });
+            }
+            let (Some(hash_a), Some(hash_b)) =
+                (self.content_hash(inode)?, other.content_hash(other_inode)?)
+            else {
+                continue;
+            };
+            if hash_a != hash_b {
+                diff.contents.push(ContentDifference {
+                    path: path.clone(),
+                    hash_a,
+                    hash_b,
+                });
+            }
+        }
+        diff.only_in_b = theirs
+            .into_keys()
+            .filter(|path| !mine.contains_key(path))
+            .collect();
+        Ok(diff)
+    }
+
+    // every path in the tree, with the root as "/", and the inode it leads to
+    fn tree_paths(&self) -> Result<BTreeMap<String, usize>> {
+        let mut paths = BTreeMap::from([("/".to_string(), 2)]);
+        self.walk(2, &WalkOptions::new(), &mut |entry| {
+            paths.insert(format!("/{}", entry.path()), entry.inode);
+            WalkControl::Continue
+        })?;
+        Ok(paths)
+    }
+
+    // the SHA-256 of the data of a regular file or the target of a symlink,
+    // in hex, or `None` for anything else
+    pub(crate) fn content_hash(&self, inode: usize) -> Result<Option<String>> {
+        let file_type = self.get_inode(inode)?.type_perm.bits() & 0xF000;
+        let mut hasher = Sha256::new();
+        if file_type == TypePerm::FILE.bits() {
+            for chunk in self.file_chunks(inode)? {
+                hasher.update(chunk?);
+            }
+        } else if file_type == TypePerm::SYMLINK.bits() {
+            hasher.update(self.read_link(inode)?);
+        } else {
+            return Ok(None);
+        }
+        let digest = hasher.finalize();
+        Ok(Some(
+            digest.iter().map(|byte| format!("{:02x}", byte)).collect(),
+        ))
+    }
+
+    /// Compare the contents of `inode` with those of `other_inode` on `other`
+    /// (which may be `self`). Returns the offset of the first differing byte,
+    /// or `None` if they're identical. If one file is a prefix of the other,
+    /// the first differing byte is the one just past the end of the shorter.
+    pub fn first_difference(
+        &self,
+        inode: usize,
+        other: &Ext2,
+        other_inode: usize,
+    ) -> Result<Option<u64>> {
+        let size = self.get_inode(inode)?.size();
+        let other_size = other.get_inode(other_inode)?.size();
+        let common = size.min(other_size);
+        let mut blocks = self.file_blocks(inode)?;
+        let mut other_blocks = other.file_blocks(other_inode)?;
+        let zeros = vec![0; self.block_size.max(other.block_size)];
+        // the two images may not share a block size, so walk both in
+        // whichever parts are left of the current block on each side, from
+        // `at` and `other_at`
+        let (mut block, mut other_block) = (Cow::Borrowed(&[][..]), Cow::Borrowed(&[][..]));
+        let (mut at, mut other_at) = (0, 0);
+        let mut offset = 0;
+        while offset < common {
+            if at == block.len() {
+                block = match blocks.next().transpose()? {
+                    Some(0) | None => Cow::Borrowed(&zeros[..self.block_size]),
+                    Some(block_num) => self.data_block(block_num)?,
+                };
+                at = 0;
+            }
+            if other_at == other_block.len() {
+                other_block = match other_blocks.next().transpose()? {
+                    Some(0) | None => Cow::Borrowed(&zeros[..other.block_size]),
+                    Some(block_num) => other.data_block(block_num)?,
+                };
+                other_at = 0;
+            }
+            let (left, other_left) = (&block[at..], &other_block[other_at..]);
+            let len = (left.len().min(other_left.len()) as u64).min(common - offset) as usize;
+            if let Some(i) = (0..len).find(|&i| left[i] != other_left[i]) {
+                return Ok(Some(offset + i as u64));
+            }
+            at += len;
+            other_at += len;
+            offset += len as u64;
+        }
+        Ok((size != other_size).then_some(common))
+    }
+
+    /// Walk the tree under directory `dir` and the one under `other_dir` on
+    /// `other` side by side, reporting names found on only one side and
+    /// regular files whose sizes or contents differ, in path order.
+    pub fn diff_trees(
+        &self,
+        dir: usize,
+        other: &Ext2,
+        other_dir: usize,
+    ) -> Result<Vec<Difference>> {
+        let mut differences = Vec::new();
+        self.diff_dirs(dir, other, other_dir, "", &mut differences)?;
+        differences.sort_by(|a, b| a.path().cmp(b.path()));
+        Ok(differences)
+    }
+
+    // A helper function for `diff_trees`: compare one pair of directories,
+    // whose path relative to the roots is `prefix`
+    fn diff_dirs(
+        &self,
+        dir: usize,
+        other: &Ext2,
+        other_dir: usize,
+        prefix: &str,
+        differences: &mut Vec<Difference>,
+    ) -> Result<()> {
+        let children = |ext2: &Ext2, dir: usize| -> Result<BTreeMap<String, usize>> {
+            Ok(ext2
+                .read_dir_inode(dir)?
+                .into_iter()
+                .filter(|entry| !entry.is_dot())
+                .map(|entry| (entry.name_lossy().into_owned(), entry.inode))
+                .collect())
+        };
+        let mine = children(self, dir)?;
+        let theirs = children(other, other_dir)?;
+        for (name, &inode) in &mine {
+            let path = format!("{}{}", prefix, name);
+            let Some(&other_inode) = theirs.get(name) else {
+                differences.push(Difference::OnlyInA { path });
+                continue;
+            };
+            let record = self.get_inode(inode)?;
+            let other_record = other.get_inode(other_inode)?;
+            let file_type = record.type_perm.bits() & 0xF000;
+            if file_type != other_record.type_perm.bits() & 0xF000 {
+                differences.push(Difference::Type {
+                    path,
+                    a: record.type_name(),
+                    b: other_record.type_name(),
+                });
+            } else if file_type == TypePerm::DIRECTORY.bits() {
+                self.diff_dirs(
+                    inode,
+                    other,
+                    other_inode,
+                    &format!("{}/", path),
+                    differences,
+                )?;
+            } else if record.size() != other_record.size() {
+                differences.push(Difference::Size {
+                    path,
+                    a: record.size(),
+                    b: other_record.size(),
+                });
+            } else if has_data_blocks(record) && has_data_blocks(other_record) {
+                if let Some(offset) = self.first_difference(inode, other, other_inode)? {
+                    differences.push(Difference::Content { path, offset });
+                }
+            } else if pointers(record) != pointers(other_record) {
+                // fast symlinks pointing elsewhere, or devices with other numbers
+                differences.push(Difference::Content { path, offset: 0 });
+            }
+        }
+        for name in theirs.keys().filter(|name| !mine.contains_key(*name)) {
+            differences.push(Difference::OnlyInB {
+                path: format!("{}{}", prefix, name),
+            });
+        }
+        Ok(())
+    }
+}
+
+// do the block pointers of `record` point at its contents? Device nodes,
+// FIFOs, sockets and fast symlinks (targets under 60 bytes, kept in the
+// pointers themselves) hold something else there
+fn has_data_blocks(record: &Inode) -> bool {
+    let file_type = record.type_perm.bits() & 0xF000;
+    if file_type == TypePerm::SYMLINK.bits() {
+        record.size() >= 60
+    } else {
+        file_type == TypePerm::FILE.bits()
+    }
+}
+
+// the block pointers of `record` taken as plain data: the target of a fast
+// symlink or the numbers of a device node
+fn pointers(record: &Inode) -> [u32; 15] {
+    let mut pointers = [0; 15];
+    pointers[..12].copy_from_slice(&record.direct_pointer);
+    pointers[12] = record.indirect_pointer;
+    pointers[13] = record.doubly_indirect;
+    pointers[14] = record.triply_indirect;
+    pointers
+}
+
+// the names of the metadata fields, as in `MetadataDifference`, that differ
+// between `a` and `b`
+fn metadata_differences(a: &Inode, b: &Inode) -> Vec<&'static str> {
+    let file_type = a.type_perm.bits() & 0xF000;
+    let mut fields = Vec::new();
+    if file_type != b.type_perm.bits() & 0xF000 {
+        fields.push("type");
+    }
+    if a.permissions() != b.permissions() {
+        fields.push("mode");
+    }
+    if a.uid != b.uid {
+        fields.push("uid");
+    }
+    if a.gid != b.gid {
+        fields.push("gid");
+    }
+    if a.hard_links != b.hard_links {
+        fields.push("links");
+    }
+    if file_type != TypePerm::DIRECTORY.bits() && a.size() != b.size() {
+        fields.push("size");
+    }
+    if a.mtime != b.mtime {
+        fields.push("mtime");
+    }
+    if a.device() != b.device() {
+        fields.push("device");
+    }
+    fields
+}
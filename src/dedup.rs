@@ -0,0 +1,196 @@
+// Finding files with the same contents, and turning copies into hard links.
+//
+// Files are grouped by size first, which costs nothing but the walk, and only
+// files sharing a size with another are hashed, a block at a time with
+// `file_chunks`, so only candidates are ever read and never whole. Paths that
+// are hard links to the same inode are one file, not duplicates.
+
+use crate::progress::check_cancel;
+use crate::{Ext2, Ext2Error, NoProgress, Progress, Result, WalkControl, WalkOptions};
+use log::info;
+use std::collections::{BTreeMap, HashMap};
+
+// the most links an inode may have, as in the kernel's EXT2_LINK_MAX
+const LINK_MAX: u16 = 32000;
+
+/// Files with identical contents, from `Ext2::find_duplicates`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DuplicateGroup {
+    /// The size of each file in bytes
+    pub size: u64,
+    /// The SHA-256 of their contents, in hex
+    pub hash: String,
+    /// The files, two or more, by their first path
+    pub files: Vec<DuplicateFile>,
+}
+
+/// One file of a `DuplicateGroup`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DuplicateFile {
+    pub inode: usize,
+    /// Every path found linking to the inode, sorted, relative to the
+    /// directory searched; more than one for a file with hard links
+    pub paths: Vec<String>,
+}
+
+impl DuplicateGroup {
+    /// The bytes taken up by all but one of the copies, which keeping only
+    /// one would save.
+    pub fn wasted(&self) -> u64 {
+        self.size * (self.files.len() as u64 - 1)
+    }
+}
+
+impl Ext2 {
+    /// Find the regular files below directory `root` with the same contents,
+    /// most wasted space first. Empty files aren't counted, having nothing
+    /// to save.
+    pub fn find_duplicates(&self, root: usize) -> Result<Vec<DuplicateGroup>> {
+        self.find_duplicates_with_progress(root, &NoProgress)
+    }
+
+    /// `find_duplicates`, telling `progress` about each file as it's hashed
+    /// and the bytes hashed so far, out of all the files that share their
+    /// size with another.
+    pub fn find_duplicates_with_progress(
+        &self,
+        root: usize,
+        progress: &dyn Progress,
+    ) -> Result<Vec<DuplicateGroup>> {
+        let mut paths: HashMap<usize, Vec<String>> = HashMap::new();
+        let mut by_size: BTreeMap<u64, Vec<usize>> = BTreeMap::new();
+        self.walk(root, &WalkOptions::new(), &mut |entry| {
+            if progress.should_cancel() {
+                return WalkControl::Stop;
+            }
+            if entry.record.is_regular() && entry.record.size() > 0 {
+                let links = paths.entry(entry.inode).or_default();
+                if links.is_empty() {
+                    by_size
+                        .entry(entry.record.size())
+                        .or_default()
+                        .push(entry.inode);
+                }
+                links.push(entry.path());
+            }
+            WalkControl::Continue
+        })?;
+        check_cancel(progress)?;
+
+        // only files sharing their size with another need hashing
+        let total = by_size
+            .iter()
+            .filter(|(_, inodes)| inodes.len() > 1)
+            .map(|(size, inodes)| size * inodes.len() as u64)
+            .sum();
+        let mut hashed = 0;
+        let mut groups = Vec::new();
+        for (size, inodes) in by_size {
+            if inodes.len() < 2 {
+                continue;
+            }
+            let mut by_hash: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+            for inode in inodes {
+                check_cancel(progress)?;
+                if let Some(path) = paths.get(&inode).and_then(|paths| paths.first()) {
+                    progress.on_item(path);
+                }
+                let hash = self.content_hash(inode)?.unwrap_or_default();
+                by_hash.entry(hash).or_default().push(inode);
+                hashed += size;
+                progress.on_bytes(hashed, Some(total));
+            }
+            for (hash, inodes) in by_hash {
+                if inodes.len() < 2 {
+                    continue;
+                }
+                let mut files: Vec<DuplicateFile> = inodes
+                    .into_iter()
+                    .map(|inode| {
+                        let mut paths = paths.remove(&inode).unwrap_or_default();
+                        paths.sort();
+                        DuplicateFile { inode, paths }
+                    })
+                    .collect();
+                files.sort_by(|a, b| a.paths.cmp(&b.paths));
+                groups.push(DuplicateGroup { size, hash, files });
+            }
+        }
+        groups.sort_by(|a, b| {
+            b.wasted()
+                .cmp(&a.wasted())
+                .then_with(|| a.files[0].paths.cmp(&b.files[0].paths))
+        });
+        Ok(groups)
+    }
+
+    /// Make every path of `group` a hard link to its first file, `root`
+    /// being the directory it was found from. Each path is switched over
+    /// whole, its entry pointed at the first file's inode and the links of
+    /// both inodes counted again in one step, and a copy left with no links
+    /// is deleted. Only contents were compared, so the owners, permissions
+    /// and times of the copies are lost to the first file's. Returns how
+    /// many paths were switched; a file changed since `find_duplicates` is
+    /// left alone.
+    pub fn link_duplicates(&mut self, root: usize, group: &DuplicateGroup) -> Result<usize> {
+        self.check_writable()?;
+        let Some((keep, copies)) = group.files.split_first() else {
+            return Ok(0);
+        };
+        if self.content_hash(keep.inode)?.as_ref() != Some(&group.hash) {
+            return Ok(0);
+        }
+        let mut linked = 0;
+        for copy in copies {
+            if self.content_hash(copy.inode)?.as_ref() != Some(&group.hash) {
+                continue;
+            }
+            for path in &copy.paths {
+                let (dir, name) = match path.rsplit_once('/') {
+                    Some((parent, name)) => (self.resolve_path(root, parent)?, name),
+                    None => (root, path.as_str()),
+                };
+                if self.relink(dir, name, copy.inode, keep.inode)? {
+                    linked += 1;
+                }
+            }
+        }
+        Ok(linked)
+    }
+
+    // point the entry `name` in `dir`, a link to `from`, at `to` instead,
+    // moving the link from one inode to the other. Returns false, doing
+    // nothing, if the entry doesn't link to `from` any more
+    fn relink(&mut self, dir: usize, name: &str, from: usize, to: usize) -> Result<bool> {
+        let Some(found) = self.find_dir_entry(dir, name)? else {
+            return Ok(false);
+        };
+        let block = self.block(found.block)?;
+        let linked = u32::from_le_bytes(block[found.offset..found.offset + 4].try_into().unwrap());
+        if linked as usize != from {
+            return Ok(false);
+        }
+        self.check_unlink(dir, from)?;
+        self.check_unlink(dir, to)?;
+        if self.get_inode(to)?.hard_links >= LINK_MAX {
+            return Err(Ext2Error::NotPermitted {
+                name: format!("inode {}", to),
+            });
+        }
+        self.block_mut(found.block)?[found.offset..found.offset + 4]
+            .copy_from_slice(&(to as u32).to_le_bytes());
+        self.touch_modified(dir)?;
+        let now = self.now();
+        let record = self.inode_mut(to)?;
+        record.hard_links += 1;
+        record.ctime = now;
+        let record = self.inode_mut(from)?;
+        record.hard_links = record.hard_links.saturating_sub(1);
+        record.ctime = now;
+        if record.hard_links == 0 {
+            self.delete_inode(from)?;
+        }
+        info!("{} (inode {}) now links to inode {}", name, from, to);
+        Ok(true)
+    }
+}
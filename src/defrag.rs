@@ -0,0 +1,189 @@
+// Defragmenting a file: moving its blocks into as few runs of free blocks as
+// the filesystem has room for.
+//
+// The copy is laid out with the indirect blocks first and the data blocks
+// after them in file order, so the data of a file that fits in one run forms
+// a single extent. Nothing the inode points to is touched until the copy is
+// complete: the data goes to blocks that were free, the new indirect blocks
+// are filled in, the inode is switched over to them in one update, and only
+// then are the old blocks freed. Stopped anywhere before that last step the
+// file is still whole in its old blocks, with at worst some blocks leaked.
+
+use crate::{Ext2, Ext2Error, Result};
+use log::info;
+use std::collections::BTreeMap;
+use std::fmt;
+
+const ROOT_INODE: usize = 2;
+
+/// What `Ext2::defrag` did to a file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DefragReport {
+    /// Data blocks, not counting holes or indirect blocks
+    pub blocks: usize,
+    pub extents_before: usize,
+    pub extents_after: usize,
+    /// Whether the file was moved at all; it's left where it is if it's
+    /// already contiguous or no better place could be found
+    pub moved: bool,
+}
+
+impl fmt::Display for DefragReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} block(s), {} extent(s) before, {} after",
+            self.blocks, self.extents_before, self.extents_after
+        )?;
+        if !self.moved {
+            write!(f, " (left in place)")?;
+        }
+        Ok(())
+    }
+}
+
+// an indirect block of the new pointer tree, named by the way down to it:
+// which top pointer it hangs off (1 for the indirect one, up to 3 for the
+// triply indirect one) and the slots taken in each block above it
+type TreeNode = (usize, Vec<usize>);
+
+impl Ext2 {
+    /// Move the blocks of `inode` into the longest runs of free blocks there
+    /// are, using as few of them as will hold the file, so its data forms as
+    /// few extents as possible. A run may carry on from one block group into
+    /// the next. The file is only moved if that makes it less fragmented.
+    pub fn defrag(&mut self, inode: usize) -> Result<DefragReport> {
+        self.check_writable()?;
+        // the reserved inodes' blocks mean something to the filesystem itself
+        if inode < self.first_usable_inode() && inode != ROOT_INODE {
+            return Err(Ext2Error::NotPermitted {
+                name: format!("inode {}", inode),
+            });
+        }
+        let before = self.fragmentation(inode)?;
+        let mut report = DefragReport {
+            blocks: before.blocks,
+            extents_before: before.extents.len(),
+            extents_after: before.extents.len(),
+            moved: false,
+        };
+        if before.extents.len() <= 1 {
+            return Ok(report);
+        }
+
+        // (logical, physical) for each data block, and the indirect blocks
+        // needed to reach them, top down and left to right
+        let mut data = Vec::new();
+        for (logical, block) in self.file_blocks(inode)?.enumerate() {
+            let block = block?;
+            if block != 0 {
+                data.push((logical, block));
+            }
+        }
+        let mut nodes = BTreeMap::<TreeNode, usize>::new();
+        for &(logical, _) in data.iter().filter(|&&(logical, _)| logical >= 12) {
+            let path = self.block_path(logical)?;
+            for depth in 0..path.len() {
+                nodes.insert((path.len(), path[..depth].to_vec()), 0);
+            }
+        }
+        let needed = nodes.len() + data.len();
+        if needed > self.available_blocks() as usize {
+            return Err(Ext2Error::NoSpace);
+        }
+
+        // the longest runs first, as many as it takes
+        let mut runs = self.free_runs()?;
+        runs.sort_by(|a, b| b.1.cmp(&a.1));
+        let destination: Vec<usize> = runs
+            .iter()
+            .flat_map(|&(start, len)| start..start + len)
+            .take(needed)
+            .collect();
+        let (node_blocks, data_blocks) = destination.split_at(nodes.len());
+        let extents_after = 1 + data_blocks.windows(2).filter(|w| w[1] != w[0] + 1).count();
+        if extents_after >= before.extents.len() {
+            return Ok(report);
+        }
+
+        // build the new tree next to the old one
+        let mut pointers = [0u32; 15];
+        for ((node, block), &new) in nodes.iter_mut().zip(node_blocks) {
+            self.claim_block(new)?;
+            self.block_mut(new)?.fill(0);
+            *block = new;
+            if node.1.is_empty() {
+                pointers[11 + node.0] = new as u32;
+            }
+        }
+        for ((top, path), &block) in &nodes {
+            if let Some((&slot, above)) = path.split_last() {
+                let parent = nodes[&(*top, above.to_vec())];
+                self.set_pointer(parent, slot, block)?;
+            }
+        }
+        for (&(logical, old), &new) in data.iter().zip(data_blocks) {
+            self.claim_block(new)?;
+            let contents = self.block(old)?.to_vec();
+            self.block_mut(new)?.copy_from_slice(&contents);
+            if logical < 12 {
+                pointers[logical] = new as u32;
+            } else {
+                let path = self.block_path(logical)?;
+                let (&slot, above) = path.split_last().unwrap();
+                let parent = nodes[&(path.len(), above.to_vec())];
+                self.set_pointer(parent, slot, new)?;
+            }
+        }
+
+        // switch the inode over, then let go of the old blocks
+        let xattr_block = self.get_inode(inode)?.ext_attribute_block as usize;
+        let old_blocks: Vec<usize> = self
+            .owned_blocks(inode)?
+            .into_iter()
+            .filter(|&block| block != xattr_block)
+            .collect();
+        let sectors = self.block_size / 512;
+        let record = self.inode_mut(inode)?;
+        record.direct_pointer.copy_from_slice(&pointers[..12]);
+        record.indirect_pointer = pointers[12];
+        record.doubly_indirect = pointers[13];
+        record.triply_indirect = pointers[14];
+        record.sectors_count =
+            (record.sectors_count as usize - old_blocks.len() * sectors + needed * sectors) as u32;
+        for block in old_blocks {
+            self.free_block(block)?;
+        }
+        self.write_metadata()?;
+
+        report.extents_after = self.fragmentation(inode)?.extents.len();
+        report.moved = true;
+        info!(
+            "defragmented inode {}: {} extents -> {}",
+            inode, report.extents_before, report.extents_after
+        );
+        Ok(report)
+    }
+
+    // every run of free blocks, as (first block, length), in block order; a
+    // run carries on into the next group if that starts with free blocks
+    pub(crate) fn free_runs(&self) -> Result<Vec<(usize, usize)>> {
+        let mut runs: Vec<(usize, usize)> = Vec::new();
+        for group in 0..self.block_groups.len() {
+            for (block, len) in self.group_free_runs(group)? {
+                match runs.last_mut() {
+                    Some((start, run)) if *start + *run == block => *run += len,
+                    _ => runs.push((block, len)),
+                }
+            }
+        }
+        Ok(runs)
+    }
+
+    // point slot `slot` of indirect block `block` at block `target`
+    fn set_pointer(&mut self, block: usize, slot: usize, target: usize) -> Result<()> {
+        self.block_mut(block)?[slot * 4..slot * 4 + 4]
+            .copy_from_slice(&(target as u32).to_le_bytes());
+        Ok(())
+    }
+}
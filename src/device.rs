@@ -0,0 +1,499 @@
+// What a filesystem is read from: anything that can hand over runs of blocks
+// by block number and say how big it is, with the in-memory `Device` and the
+// on-demand `FileDevice` as the two that come with the crate.
+//
+// `Ext2` never writes to its device; everything it changes stays in the
+// dirty-block layer until `sync` writes it out through a `Write + Seek`. So
+// reading is all a `BlockDevice` has to do. A device that's all in memory
+// says so with `bytes`, and its blocks are handed out straight from there;
+// any other has each block read the first time it's asked for, by
+// `DeviceBlocks`, which keeps metadata blocks and only the most recently
+// read of a file's.
+
+use crate::partition::{self, Partition};
+use crate::{Ext2Error, Result};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::mem;
+use std::ops::{Deref, DerefMut};
+use std::os::unix::fs::FileExt;
+use std::path::Path;
+use std::sync::Mutex;
+
+/// A device a filesystem can be opened on, with `Ext2Options::open_device`.
+pub trait BlockDevice: Send + Sync {
+    /// How many bytes the device holds.
+    fn size(&self) -> u64;
+
+    /// Fill `buf` from the start of block `first`, counting blocks of
+    /// `block_size` bytes from the start of the device; `buf` may cover any
+    /// number of blocks, and they're read in one go.
+    fn read_blocks(&self, first: u64, block_size: usize, buf: &mut [u8]) -> io::Result<()>;
+
+    /// All of the device's bytes, if it holds them in memory, aligned to 8
+    /// bytes; blocks are then taken straight from them rather than read and
+    /// kept.
+    fn bytes(&self) -> Option<&[u8]> {
+        None
+    }
+}
+
+impl<D: BlockDevice + ?Sized> BlockDevice for Box<D> {
+    fn size(&self) -> u64 {
+        (**self).size()
+    }
+
+    fn read_blocks(&self, first: u64, block_size: usize, buf: &mut [u8]) -> io::Result<()> {
+        (**self).read_blocks(first, block_size, buf)
+    }
+
+    fn bytes(&self) -> Option<&[u8]> {
+        (**self).bytes()
+    }
+}
+
+// `buf.len()` bytes from byte `offset` of `bytes`, failing like a short read
+// if they run past the end
+fn read_from(bytes: &[u8], offset: u64, buf: &mut [u8]) -> io::Result<()> {
+    let range = usize::try_from(offset)
+        .ok()
+        .and_then(|start| Some(start..start.checked_add(buf.len())?));
+    match range.and_then(|range| bytes.get(range)) {
+        Some(bytes) => {
+            buf.copy_from_slice(bytes);
+            Ok(())
+        }
+        None => Err(io::ErrorKind::UnexpectedEof.into()),
+    }
+}
+
+/// A modified copy of a block in the dirty-block layer, or a block read from
+/// a device that isn't in memory.
+///
+/// Backed by `u64`s rather than bytes so that on-disk structures (inodes,
+/// directory entries) can be cast out of it without misaligned pointers.
+#[derive(Debug, Clone)]
+pub(crate) struct BlockBuf(Vec<u64>);
+
+impl BlockBuf {
+    pub(crate) fn zeroed(len: usize) -> BlockBuf {
+        BlockBuf(vec![0; len.div_ceil(8)])
+    }
+}
+
+impl From<&[u8]> for BlockBuf {
+    fn from(bytes: &[u8]) -> BlockBuf {
+        let mut block = BlockBuf::zeroed(bytes.len());
+        block[..bytes.len()].copy_from_slice(bytes);
+        block
+    }
+}
+
+impl Deref for BlockBuf {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self.0.as_ptr() as *const u8, self.0.len() * 8) }
+    }
+}
+
+impl DerefMut for BlockBuf {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        unsafe { std::slice::from_raw_parts_mut(self.0.as_mut_ptr() as *mut u8, self.0.len() * 8) }
+    }
+}
+
+/// A device held in memory, e.g. a `Vec<u8>` or a built-in `&'static [u8]`,
+/// which it owns.
+///
+/// On-disk structures are cast straight out of its blocks, so the bytes are
+/// kept aligned to 8 bytes: whatever was handed over is kept as it is if it
+/// already is, e.g. a built-in image or a big enough `Vec`, and copied into
+/// `u64`s if not.
+pub struct Device(Memory);
+
+enum Memory {
+    Given(Box<dyn AsRef<[u8]> + Send + Sync>),
+    Copied(BlockBuf, usize),
+}
+
+impl Device {
+    pub fn new(device: impl AsRef<[u8]> + Send + Sync + 'static) -> Device {
+        let bytes = device.as_ref();
+        if bytes.as_ptr() as usize % mem::align_of::<u64>() == 0 {
+            Device(Memory::Given(Box::new(device)))
+        } else {
+            Device::copy(bytes)
+        }
+    }
+
+    /// A device holding a copy of `bytes`, so they can go as soon as this
+    /// returns.
+    pub fn copy(bytes: &[u8]) -> Device {
+        Device(Memory::Copied(BlockBuf::from(bytes), bytes.len()))
+    }
+
+    fn as_bytes(&self) -> &[u8] {
+        match &self.0 {
+            Memory::Given(device) => (**device).as_ref(),
+            Memory::Copied(words, len) => &words[..*len],
+        }
+    }
+}
+
+impl BlockDevice for Device {
+    fn size(&self) -> u64 {
+        self.as_bytes().len() as u64
+    }
+
+    fn read_blocks(&self, first: u64, block_size: usize, buf: &mut [u8]) -> io::Result<()> {
+        read_from(self.as_bytes(), first * block_size as u64, buf)
+    }
+
+    fn bytes(&self) -> Option<&[u8]> {
+        Some(self.as_bytes())
+    }
+}
+
+impl fmt::Debug for Device {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Device({} bytes)", self.as_bytes().len())
+    }
+}
+
+/// An image file or block device read a block at a time as the filesystem
+/// needs them, rather than all at once, so opening a big one is quick and
+/// only what's looked at takes up memory: the metadata, and a bounded number
+/// of the blocks of file contents read most recently.
+#[derive(Debug)]
+pub struct FileDevice {
+    file: File,
+    size: u64,
+}
+
+impl FileDevice {
+    pub fn new(file: File) -> io::Result<FileDevice> {
+        // a block device's metadata says nothing of its size; seeking to its
+        // end works for both
+        let size = io::Seek::seek(&mut &file, io::SeekFrom::End(0))?;
+        Ok(FileDevice { file, size })
+    }
+
+    /// Open the file at `path` for reading.
+    pub fn open(path: impl AsRef<Path>) -> Result<FileDevice> {
+        let path = path.as_ref();
+        File::open(path)
+            .and_then(FileDevice::new)
+            .map_err(|source| Ext2Error::Host {
+                path: path.display().to_string(),
+                source,
+            })
+    }
+}
+
+impl BlockDevice for FileDevice {
+    fn size(&self) -> u64 {
+        self.size
+    }
+
+    fn read_blocks(&self, first: u64, block_size: usize, buf: &mut [u8]) -> io::Result<()> {
+        self.file.read_exact_at(buf, first * block_size as u64)
+    }
+}
+
+// one partition of a device, for `Ext2Options::partition`
+pub(crate) struct PartitionDevice<D> {
+    device: D,
+    offset: u64,
+    len: u64,
+}
+
+impl<D: BlockDevice> PartitionDevice<D> {
+    // the partition numbered `number` in `device`'s partition table
+    pub(crate) fn new(device: D, number: usize) -> Result<PartitionDevice<D>> {
+        let partition = device_partitions(&device)?
+            .into_iter()
+            .find(|partition| partition.number == number)
+            .ok_or_else(|| Ext2Error::NotFound {
+                name: format!("partition {}", number),
+            })?;
+        Ok(PartitionDevice {
+            device,
+            offset: partition.offset,
+            len: partition.len,
+        })
+    }
+}
+
+impl<D: BlockDevice> BlockDevice for PartitionDevice<D> {
+    fn size(&self) -> u64 {
+        self.len
+    }
+
+    fn read_blocks(&self, first: u64, block_size: usize, buf: &mut [u8]) -> io::Result<()> {
+        let start = first * block_size as u64;
+        if start + buf.len() as u64 > self.len {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        // partitions start on a sector, so count in those
+        let start = self.offset + start;
+        if start % partition::SECTOR_SIZE == 0 {
+            self.device.read_blocks(
+                start / partition::SECTOR_SIZE,
+                partition::SECTOR_SIZE as usize,
+                buf,
+            )
+        } else {
+            self.device.read_blocks(start, 1, buf)
+        }
+    }
+
+    fn bytes(&self) -> Option<&[u8]> {
+        // sectors are a multiple of 8 bytes, so this stays aligned
+        let bytes = self.device.bytes()?;
+        Some(&bytes[self.offset as usize..(self.offset + self.len) as usize])
+    }
+}
+
+/// The partitions in `device`'s partition table, like `partitions`; the
+/// table is looked for in the device's first MiB, which is where every
+/// partitioning tool puts it.
+pub fn device_partitions(device: &dyn BlockDevice) -> Result<Vec<Partition>> {
+    if let Some(bytes) = device.bytes() {
+        return Ok(partition::partitions(bytes));
+    }
+    let mut head = vec![0; device.size().min(1 << 20) as usize];
+    device.read_blocks(0, 1, &mut head)?;
+    Ok(partition::partitions_within(&head, device.size()))
+}
+
+// the blocks of a filesystem's device, handed out whether the device is in
+// memory or not. Those of a device that isn't are read as they're asked for:
+// `block` keeps what it reads, since what it hands out borrows from it,
+// until `forget`; `data_block`, for file contents, which there can be far
+// more of, hands out copies, and keeps only the most recently used
+pub(crate) struct DeviceBlocks {
+    device: Box<dyn BlockDevice>,
+    block_size: usize,
+    // blocks `block` has handed out, by block number; empty for a device in
+    // memory
+    kept: Mutex<HashMap<usize, BlockBuf>>,
+    // blocks `data_block` and `read_ahead` have read; empty for a device in
+    // memory
+    data: Mutex<DataBlocks>,
+}
+
+// how many blocks of file contents are kept at most, and so the longest run
+// read ahead is half of this, so a run doesn't push out its own start
+const DATA_BLOCKS: usize = 1024;
+
+// blocks read by number, and when each was last used, so that once there are
+// `DATA_BLOCKS` of them, the one used longest ago goes first
+#[derive(Default)]
+struct DataBlocks {
+    blocks: HashMap<usize, (BlockBuf, u64)>,
+    by_use: BTreeMap<u64, usize>,
+    clock: u64,
+}
+
+impl DataBlocks {
+    fn get(&mut self, block_num: usize) -> Option<&BlockBuf> {
+        self.clock += 1;
+        let (block, used) = self.blocks.get_mut(&block_num)?;
+        self.by_use.remove(used);
+        self.by_use.insert(self.clock, block_num);
+        *used = self.clock;
+        Some(block)
+    }
+
+    fn insert(&mut self, block_num: usize, block: BlockBuf) {
+        self.clock += 1;
+        if let Some((_, used)) = self.blocks.insert(block_num, (block, self.clock)) {
+            self.by_use.remove(&used);
+        }
+        self.by_use.insert(self.clock, block_num);
+        while self.blocks.len() > DATA_BLOCKS {
+            let (_, oldest) = self.by_use.pop_first().unwrap();
+            self.blocks.remove(&oldest);
+        }
+    }
+
+    fn remove(&mut self, block_num: usize) {
+        if let Some((_, used)) = self.blocks.remove(&block_num) {
+            self.by_use.remove(&used);
+        }
+    }
+}
+
+impl DeviceBlocks {
+    pub(crate) fn new(device: Box<dyn BlockDevice>, block_size: usize) -> DeviceBlocks {
+        DeviceBlocks {
+            device,
+            block_size,
+            kept: Mutex::new(HashMap::new()),
+            data: Mutex::new(DataBlocks::default()),
+        }
+    }
+
+    pub(crate) fn device(&self) -> &dyn BlockDevice {
+        &*self.device
+    }
+
+    // how many whole blocks the device holds
+    pub(crate) fn len(&self) -> usize {
+        (self.device.size() / self.block_size as u64) as usize
+    }
+
+    // fail unless block `block_num` is in a filesystem of `blocks_count`
+    // blocks, and on the device
+    fn check(&self, block_num: usize, blocks_count: usize) -> Result<()> {
+        // the device may be shorter than the superblock claims, or longer
+        // (`resize_grow` takes it up); a truncated image's last, partial
+        // block is as good as missing
+        if block_num >= blocks_count || block_num >= self.len() {
+            return Err(Ext2Error::BlockOutOfRange {
+                block: block_num,
+                blocks_count,
+            });
+        }
+        Ok(())
+    }
+
+    // a kept block, borrowed for as long as `self` is: its bytes are on the
+    // heap, where they stay put however the map moves the `BlockBuf` about,
+    // and it only leaves the map through `forget`, which takes `&mut self`
+    fn borrow_kept<'a>(&'a self, block: &BlockBuf) -> &'a [u8] {
+        unsafe { std::slice::from_raw_parts(block.as_ptr(), block.len()) }
+    }
+
+    // block `block_num` as it is on the device, read if it hasn't been, but
+    // not kept
+    fn read(&self, block_num: usize) -> Result<BlockBuf> {
+        if let Some(block) = self.kept.lock().unwrap().get(&block_num) {
+            return Ok(block.clone());
+        }
+        if let Some(block) = self.data.lock().unwrap().get(block_num) {
+            return Ok(block.clone());
+        }
+        let mut block = BlockBuf::zeroed(self.block_size);
+        self.device
+            .read_blocks(block_num as u64, self.block_size, &mut block)?;
+        Ok(block)
+    }
+
+    // block `block_num` of a filesystem of `blocks_count` blocks on the
+    // device, kept until `forget` if it had to be read
+    pub(crate) fn block(&self, block_num: usize, blocks_count: usize) -> Result<&[u8]> {
+        self.check(block_num, blocks_count)?;
+        let block_size = self.block_size;
+        if let Some(bytes) = self.device.bytes() {
+            return Ok(&bytes[block_num * block_size..(block_num + 1) * block_size]);
+        }
+        if let Some(block) = self.kept.lock().unwrap().get(&block_num) {
+            return Ok(self.borrow_kept(block));
+        }
+        let block = self.read(block_num)?;
+        // another thread may have got there first, with the same bytes, and
+        // what it was handed borrows from its copy
+        let mut kept = self.kept.lock().unwrap();
+        Ok(self.borrow_kept(kept.entry(block_num).or_insert(block)))
+    }
+
+    // `block`, for a block of file contents: one read from a device that
+    // isn't in memory is copied out of the most recently used rather than
+    // kept, so reading a big file doesn't keep all of it
+    pub(crate) fn data_block(
+        &self,
+        block_num: usize,
+        blocks_count: usize,
+    ) -> Result<Cow<'_, [u8]>> {
+        self.check(block_num, blocks_count)?;
+        if self.device.bytes().is_some() {
+            return self.block(block_num, blocks_count).map(Cow::Borrowed);
+        }
+        if let Some(block) = self.kept.lock().unwrap().get(&block_num) {
+            return Ok(Cow::Borrowed(self.borrow_kept(block)));
+        }
+        if let Some(block) = self.data.lock().unwrap().get(block_num) {
+            return Ok(Cow::Owned(block[..self.block_size].to_vec()));
+        }
+        let block = self.read(block_num)?;
+        let bytes = block[..self.block_size].to_vec();
+        self.data.lock().unwrap().insert(block_num, block);
+        Ok(Cow::Owned(bytes))
+    }
+
+    // a copy of block `block_num` as it is on the device, e.g. to modify,
+    // without keeping it
+    pub(crate) fn copy(&self, block_num: usize, blocks_count: usize) -> Result<BlockBuf> {
+        self.check(block_num, blocks_count)?;
+        if let Some(bytes) = self.device.bytes() {
+            let block_size = self.block_size;
+            return Ok(BlockBuf::from(
+                &bytes[block_num * block_size..(block_num + 1) * block_size],
+            ));
+        }
+        self.read(block_num)
+    }
+
+    // drop whatever's kept of block `block_num`, e.g. once a modified copy
+    // of it means the device's won't be read again
+    pub(crate) fn forget(&mut self, block_num: usize) {
+        self.kept.get_mut().unwrap().remove(&block_num);
+        self.data.get_mut().unwrap().remove(block_num);
+    }
+
+    // whether block `block_num` can be handed out without reading the device
+    pub(crate) fn is_read(&self, block_num: usize) -> bool {
+        self.device.bytes().is_some()
+            || self.kept.lock().unwrap().contains_key(&block_num)
+            || self.data.lock().unwrap().blocks.contains_key(&block_num)
+    }
+
+    // read blocks `first` on, up to `count` of them, with one read of the
+    // device, and keep them among the blocks of file contents; the run stops
+    // short at a block that's already been read, or at the end of the
+    // filesystem or the device
+    pub(crate) fn read_ahead(&self, first: usize, count: usize, blocks_count: usize) -> Result<()> {
+        if self.device.bytes().is_some() {
+            return Ok(());
+        }
+        let end = (first + count.min(DATA_BLOCKS / 2))
+            .min(blocks_count)
+            .min(self.len());
+        let count = (first..end)
+            .take_while(|&block_num| !self.is_read(block_num))
+            .count();
+        if count == 0 {
+            return Ok(());
+        }
+        let block_size = self.block_size;
+        let mut run = vec![0; count * block_size];
+        self.device
+            .read_blocks(first as u64, block_size, &mut run)?;
+        let mut data = self.data.lock().unwrap();
+        for (block_num, block) in (first..).zip(run.chunks_exact(block_size)) {
+            data.insert(block_num, BlockBuf::from(block));
+        }
+        Ok(())
+    }
+
+    // the whole device, as it was opened
+    pub(crate) fn bytes(&self) -> Result<Cow<'_, [u8]>> {
+        if let Some(bytes) = self.device.bytes() {
+            return Ok(Cow::Borrowed(bytes));
+        }
+        let mut bytes = vec![0; self.device.size() as usize];
+        self.device.read_blocks(0, self.block_size, &mut bytes)?;
+        Ok(Cow::Owned(bytes))
+    }
+}
+
+impl fmt::Debug for DeviceBlocks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Device({} bytes)", self.device.size())
+    }
+}
@@ -0,0 +1,75 @@
+// An in-memory index of one directory's names, for looking up many names in
+// the same directory without scanning it each time.
+
+use crate::{EntryName, Ext2, Result};
+use std::collections::HashMap;
+
+/// The entries of one directory, read in a single pass: in order, for
+/// listing, and hashed by name, for lookups.
+/// It's a snapshot: once anything on the filesystem is modified it may be
+/// out of date, which `is_current` tells, and should be built again.
+#[derive(Debug, Clone)]
+pub struct DirIndex {
+    dir: usize,
+    generation: u64,
+    // (inode, name) in directory order; names as their raw bytes, since they
+    // needn't be UTF-8
+    entries: Vec<(usize, Vec<u8>)>,
+    names: HashMap<Vec<u8>, usize>,
+}
+
+impl DirIndex {
+    /// The directory indexed.
+    pub fn dir(&self) -> usize {
+        self.dir
+    }
+
+    /// Whether nothing has been modified on `ext2` since the index was built.
+    pub fn is_current(&self, ext2: &Ext2) -> bool {
+        self.generation == ext2.generation()
+    }
+
+    /// The inode that `name` links to, if it's in the directory.
+    pub fn get(&self, name: &str) -> Option<usize> {
+        self.names.get(name.as_bytes()).copied()
+    }
+
+    /// Every (inode, name) entry in the directory, `.` and `..` included, in
+    /// the order they're stored, like `Ext2::read_dir_inode`.
+    pub fn entries(&self) -> impl Iterator<Item = (usize, EntryName<'_>)> {
+        self.entries
+            .iter()
+            .map(|(inode, name)| (*inode, EntryName(name)))
+    }
+
+    /// How many entries the directory has, `.` and `..` included.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+impl Ext2 {
+    /// Read directory `dir` once into an index of its names.
+    pub fn dir_index(&self, dir: usize) -> Result<DirIndex> {
+        let entries: Vec<(usize, Vec<u8>)> = self
+            .read_dir_inode(dir)?
+            .into_iter()
+            .map(|entry| (entry.inode, entry.name))
+            .collect();
+        let mut names = HashMap::new();
+        for (inode, name) in &entries {
+            // the first of any duplicates wins, as with `lookup`
+            names.entry(name.clone()).or_insert(*inode);
+        }
+        Ok(DirIndex {
+            dir,
+            generation: self.generation(),
+            entries,
+            names,
+        })
+    }
+}
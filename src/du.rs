@@ -0,0 +1,102 @@
+// How much of the disk each subtree takes up, like `du`, for browsing a
+// tree by size.
+//
+// A directory's total is its own blocks plus its entries' totals, so it's
+// summed bottom up once and then kept: going back into a directory, or up to
+// its parent, only adds up what isn't known yet. As with `PathCache`, any
+// modification drops everything, since working out which totals it changed
+// would mean knowing every directory above each block freed or allocated.
+// A file with several links is counted under each of them.
+
+use crate::{Ext2, NameKind, Result};
+use std::collections::{HashMap, HashSet};
+
+/// An entry of a directory with how much space it takes, from
+/// `DuCache::children`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SubtreeUsage {
+    pub inode: usize,
+    /// Exactly the bytes on disk, which needn't be UTF-8
+    pub name: Vec<u8>,
+    pub is_dir: bool,
+    /// Bytes of blocks allocated to the entry and, for a directory,
+    /// everything under it, indirect blocks included
+    pub bytes: u64,
+}
+
+/// The totals of directories summed since the filesystem was last modified.
+#[derive(Debug, Clone, Default)]
+pub struct DuCache {
+    generation: u64,
+    totals: HashMap<usize, u64>,
+}
+
+impl DuCache {
+    pub fn new() -> DuCache {
+        DuCache::default()
+    }
+
+    /// How many bytes of blocks `inode` takes, and if it's a directory,
+    /// everything under it.
+    pub fn total(&mut self, ext2: &Ext2, inode: usize) -> Result<u64> {
+        if self.generation != ext2.generation() {
+            self.totals.clear();
+            self.generation = ext2.generation();
+        }
+        self.sum(ext2, inode, &mut HashSet::new())
+    }
+
+    /// The entries of directory `dir`, without `.` and `..`, each with its
+    /// total, biggest first and then by name.
+    pub fn children(&mut self, ext2: &Ext2, dir: usize) -> Result<Vec<SubtreeUsage>> {
+        let mut children = Vec::new();
+        for entry in ext2.read_dir_inode(dir)? {
+            if entry.is_dot() {
+                continue;
+            }
+            children.push(SubtreeUsage {
+                inode: entry.inode,
+                is_dir: ext2.get_inode(entry.inode)?.is_dir(),
+                bytes: self.total(ext2, entry.inode)?,
+                name: entry.name,
+            });
+        }
+        children.sort_by(|a, b| b.bytes.cmp(&a.bytes).then_with(|| a.name.cmp(&b.name)));
+        Ok(children)
+    }
+
+    /// How many directories' totals are kept.
+    pub fn len(&self) -> usize {
+        self.totals.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.totals.is_empty()
+    }
+
+    // `total` without the generation check; `summing` holds the directories
+    // being summed further up, so a damaged tree that loops back on itself
+    // counts each of them once rather than recursing forever
+    fn sum(&mut self, ext2: &Ext2, inode: usize, summing: &mut HashSet<usize>) -> Result<u64> {
+        if let Some(&total) = self.totals.get(&inode) {
+            return Ok(total);
+        }
+        let record = ext2.get_inode(inode)?;
+        let own = record.sectors_count as u64 * 512;
+        if !record.is_dir() {
+            return Ok(own);
+        }
+        if !summing.insert(inode) {
+            return Ok(0);
+        }
+        let mut total = own;
+        for entry in ext2.read_dir_raw(inode)? {
+            if entry.name.kind() != NameKind::Dot {
+                total += self.sum(ext2, entry.inode, summing)?;
+            }
+        }
+        summing.remove(&inode);
+        self.totals.insert(inode, total);
+        Ok(total)
+    }
+}
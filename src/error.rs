@@ -0,0 +1,193 @@
+use crate::Escaped;
+use std::io;
+use thiserror::Error;
+
+/// Everything that can go wrong reading or modifying an ext2 filesystem.
+///
+/// Errors deep inside a traversal get wrapped in `Inode`/`Block` as they
+/// bubble up, so the final message says where it happened, e.g.
+/// `reading inode 14: indirect block 90: block 90210 out of range (fs has 4096 blocks)`.
+#[derive(Debug, Error)]
+pub enum Ext2Error {
+    #[error("block {block} out of range (fs has {blocks_count} blocks)")]
+    BlockOutOfRange { block: usize, blocks_count: usize },
+    #[error("inode {inode} out of range (fs has {inodes_count} inodes)")]
+    InodeOutOfRange { inode: usize, inodes_count: usize },
+    #[error("{}: No such file or directory", Escaped(.name.as_bytes()))]
+    NotFound { name: String },
+    #[error("{}: Not a directory", Escaped(.name.as_bytes()))]
+    NotADirectory { name: String },
+    #[error("{}: Is a directory", Escaped(.name.as_bytes()))]
+    IsADirectory { name: String },
+    #[error("{}: Directory not empty", Escaped(.name.as_bytes()))]
+    NotEmpty { name: String },
+    /// A directory can't be moved below itself
+    #[error("{}: Can't move a directory into itself", Escaped(.name.as_bytes()))]
+    IntoItself { name: String },
+    #[error("{}: File exists", Escaped(.name.as_bytes()))]
+    AlreadyExists { name: String },
+    #[error("{}: ambiguous name, more than one entry matches it but for case", Escaped(.name.as_bytes()))]
+    AmbiguousName { name: String },
+    #[error("{}: Invalid file name", Escaped(.name.as_bytes()))]
+    InvalidName { name: String },
+    /// No free block is left in any group
+    #[error("No space left on device")]
+    NoSpace,
+    /// No free inode is left in any group, though there may be blocks
+    #[error("No space left on device")]
+    NoInodes,
+    #[error("File too large")]
+    FileTooLarge,
+    /// What's being opened isn't an ext2 filesystem, or not one whose layout
+    /// makes sense
+    #[error("bad superblock: {reason}")]
+    BadSuperblock { reason: String },
+    /// `mkfs` or `resize_grow` can't lay the filesystem out as asked
+    #[error("can't lay out the filesystem: {reason}")]
+    BadLayout { reason: String },
+    /// `Ext2Options` asks for things that don't go together
+    #[error("bad options: {reason}")]
+    BadOptions { reason: String },
+    /// A tar archive being imported doesn't hold what its headers say
+    #[error("bad tar archive: {reason}")]
+    BadArchive { reason: String },
+    #[error("{}: Permission denied", Escaped(.name.as_bytes()))]
+    PermissionDenied { name: String },
+    #[error("{}: Operation not permitted", Escaped(.name.as_bytes()))]
+    NotPermitted { name: String },
+    #[error("inode {inode} can't be recovered: {reason}")]
+    NotRecoverable { inode: usize, reason: String },
+    #[error("Read-only file system")]
+    ReadOnly,
+    /// A long operation's `Progress` asked it to stop
+    #[error("operation cancelled")]
+    Cancelled,
+    #[error("corrupt directory inode {inode}: block {block} offset {offset}: {reason}")]
+    CorruptDirectory {
+        inode: usize,
+        block: usize,
+        offset: usize,
+        reason: String,
+    },
+    #[error("corrupt extended attributes of inode {inode}: {reason}")]
+    CorruptXattrs { inode: usize, reason: String },
+    /// An inode's own fields contradict each other, e.g. a symlink longer
+    /// than a block
+    #[error("corrupt inode {inode}: {reason}")]
+    CorruptInode { inode: usize, reason: String },
+    /// A block or inode being marked in use already is, or one being freed
+    /// already is free: the bitmap and whatever's changing it disagree about
+    /// who has it
+    #[error("{kind} {number} is already {state}")]
+    AlreadyMarked {
+        kind: &'static str,
+        number: usize,
+        state: &'static str,
+    },
+    /// `source` happened while performing `op` on `inode`
+    #[error("{op} inode {inode}: {source}")]
+    Inode {
+        op: &'static str,
+        inode: usize,
+        source: Box<Ext2Error>,
+    },
+    /// `source` happened while following the `kind` block `block`
+    #[error("{kind} block {block}: {source}")]
+    Block {
+        kind: &'static str,
+        block: usize,
+        source: Box<Ext2Error>,
+    },
+    /// Writing the filesystem back to its image failed
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+    /// Reading the host file or directory `path` failed, e.g. while importing it
+    #[error("{path}: {source}")]
+    Host { path: String, source: io::Error },
+}
+
+pub type Result<T, E = Ext2Error> = std::result::Result<T, E>;
+
+impl Ext2Error {
+    /// Wrap this error with the operation and inode it happened in.
+    pub fn in_inode(self, op: &'static str, inode: usize) -> Ext2Error {
+        Ext2Error::Inode {
+            op,
+            inode,
+            source: Box::new(self),
+        }
+    }
+
+    /// Wrap this error with the (indirect) block it was found through.
+    pub fn in_block(self, kind: &'static str, block: usize) -> Ext2Error {
+        Ext2Error::Block {
+            kind,
+            block,
+            source: Box::new(self),
+        }
+    }
+
+    /// The innermost error, with all the context wrappers peeled off.
+    pub fn root_cause(&self) -> &Ext2Error {
+        match self {
+            Ext2Error::Inode { source, .. } | Ext2Error::Block { source, .. } => {
+                source.root_cause()
+            }
+            err => err,
+        }
+    }
+
+    /// The errno that describes this error best, e.g. `ENOENT` for
+    /// `NotFound`, for reporting it to C or the kernel. Positive, like
+    /// `errno` itself.
+    pub fn errno(&self) -> i32 {
+        match self.root_cause() {
+            Ext2Error::Io(err) | Ext2Error::Host { source: err, .. } => {
+                err.raw_os_error().unwrap_or(libc::EIO)
+            }
+            Ext2Error::NotFound { .. } | Ext2Error::InodeOutOfRange { .. } => libc::ENOENT,
+            Ext2Error::NotADirectory { .. } => libc::ENOTDIR,
+            Ext2Error::IsADirectory { .. } => libc::EISDIR,
+            Ext2Error::NotEmpty { .. } => libc::ENOTEMPTY,
+            Ext2Error::AlreadyExists { .. } => libc::EEXIST,
+            Ext2Error::InvalidName { .. }
+            | Ext2Error::IntoItself { .. }
+            | Ext2Error::BadLayout { .. }
+            | Ext2Error::BadOptions { .. }
+            | Ext2Error::BadArchive { .. }
+            | Ext2Error::BadSuperblock { .. } => libc::EINVAL,
+            Ext2Error::NoSpace | Ext2Error::NoInodes => libc::ENOSPC,
+            Ext2Error::FileTooLarge => libc::EFBIG,
+            Ext2Error::PermissionDenied { .. } => libc::EACCES,
+            Ext2Error::NotPermitted { .. } => libc::EPERM,
+            Ext2Error::ReadOnly => libc::EROFS,
+            Ext2Error::Cancelled => libc::ECANCELED,
+            _ => libc::EIO,
+        }
+    }
+}
+
+impl From<Ext2Error> for io::Error {
+    fn from(err: Ext2Error) -> io::Error {
+        let kind = match err.root_cause() {
+            Ext2Error::Io(err) | Ext2Error::Host { source: err, .. } => err.kind(),
+            Ext2Error::NotFound { .. } => io::ErrorKind::NotFound,
+            Ext2Error::AlreadyExists { .. } => io::ErrorKind::AlreadyExists,
+            Ext2Error::InvalidName { .. }
+            | Ext2Error::BadLayout { .. }
+            | Ext2Error::BadOptions { .. } => io::ErrorKind::InvalidInput,
+            Ext2Error::PermissionDenied { .. }
+            | Ext2Error::NotPermitted { .. }
+            | Ext2Error::ReadOnly => io::ErrorKind::PermissionDenied,
+            Ext2Error::BlockOutOfRange { .. }
+            | Ext2Error::InodeOutOfRange { .. }
+            | Ext2Error::BadSuperblock { .. }
+            | Ext2Error::CorruptDirectory { .. }
+            | Ext2Error::CorruptXattrs { .. }
+            | Ext2Error::CorruptInode { .. }
+            | Ext2Error::AlreadyMarked { .. } => io::ErrorKind::InvalidData,
+            _ => io::ErrorKind::Other,
+        };
+        io::Error::new(kind, err)
+    }
+}
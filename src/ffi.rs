@@ -0,0 +1,183 @@
+// A C interface to reading images, for linking the library into C programs.
+// `ffi/ext2.h` is generated from this file by cbindgen, see `cbindgen.toml`.
+//
+// Every function returns 0 on success or a negative errno, e.g. -ENOENT,
+// and never unwinds into C: a panic anywhere below comes back as -EIO.
+// Images are opened read-only, from a copy of the caller's buffer, so the
+// buffer can be freed as soon as `ext2_open` returns.
+
+use crate::{DirEntry, Ext2};
+use std::ffi::{c_char, c_int, c_void, CStr, CString};
+use std::panic::{self, AssertUnwindSafe};
+use std::slice;
+
+/// An open image, `ext2_t` in C.
+pub struct Ext2Handle(Ext2);
+
+/// Called by `ext2_readdir` for each entry with the `ctx` it was given, the
+/// entry's inode, its name (`name_len` bytes, NUL-terminated) and its file
+/// type as a `DT_*` value. Returning anything but 0 stops the listing, and
+/// `ext2_readdir` returns that.
+pub type Ext2DirentCb = Option<
+    unsafe extern "C" fn(
+        ctx: *mut c_void,
+        inode: u32,
+        name: *const c_char,
+        name_len: usize,
+        file_type: u8,
+    ) -> c_int,
+>;
+
+// run one entry point's body, turning a panic into -EIO
+fn guard(body: impl FnOnce() -> c_int) -> c_int {
+    panic::catch_unwind(AssertUnwindSafe(body)).unwrap_or(-libc::EIO)
+}
+
+/// Open the image in the `len` bytes at `buf`, and store a handle to it in
+/// `*out` for the other functions. Returns -EINVAL if it isn't an ext2
+/// filesystem. The handle must be released with `ext2_close`.
+///
+/// # Safety
+///
+/// `buf` must point to `len` readable bytes and `out` to a writable pointer.
+#[no_mangle]
+pub unsafe extern "C" fn ext2_open(buf: *const u8, len: usize, out: *mut *mut Ext2Handle) -> c_int {
+    guard(|| {
+        if buf.is_null() || out.is_null() {
+            return -libc::EINVAL;
+        }
+        let image = slice::from_raw_parts(buf, len);
+        match Ext2::options().read_only(true).open_bytes(image) {
+            Ok(ext2) => {
+                *out = Box::into_raw(Box::new(Ext2Handle(ext2)));
+                0
+            }
+            Err(err) => -err.errno(),
+        }
+    })
+}
+
+/// Read up to `len` bytes of inode `inode`'s contents from byte `off` into
+/// `buf`, and store how many were read in `*read`: fewer than `len` only at
+/// the end of the file, and 0 past it.
+///
+/// # Safety
+///
+/// `fs` must come from `ext2_open`, `buf` must point to `len` writable bytes
+/// and `read` to a writable `size_t`.
+#[no_mangle]
+pub unsafe extern "C" fn ext2_read_file(
+    fs: *mut Ext2Handle,
+    inode: u32,
+    buf: *mut u8,
+    off: usize,
+    len: usize,
+    read: *mut usize,
+) -> c_int {
+    guard(|| {
+        if fs.is_null() || (buf.is_null() && len > 0) || read.is_null() {
+            return -libc::EINVAL;
+        }
+        let buf = if len == 0 {
+            &mut []
+        } else {
+            slice::from_raw_parts_mut(buf, len)
+        };
+        match (*fs).0.read_at(inode as usize, off as u64, buf) {
+            Ok(count) => {
+                *read = count;
+                0
+            }
+            Err(err) => -err.errno(),
+        }
+    })
+}
+
+/// Call `cb` for each entry of directory `inode`, "." and ".." included, in
+/// the order they're stored.
+///
+/// # Safety
+///
+/// `fs` must come from `ext2_open`; `ctx` is only passed on to `cb`.
+#[no_mangle]
+pub unsafe extern "C" fn ext2_readdir(
+    fs: *mut Ext2Handle,
+    inode: u32,
+    cb: Ext2DirentCb,
+    ctx: *mut c_void,
+) -> c_int {
+    guard(|| {
+        let (Some(cb), false) = (cb, fs.is_null()) else {
+            return -libc::EINVAL;
+        };
+        let ext2 = &(*fs).0;
+        let entries = match ext2.read_dir_inode(inode as usize) {
+            Ok(entries) => entries,
+            Err(err) => return -err.errno(),
+        };
+        for DirEntry {
+            inode: entry, name, ..
+        } in entries
+        {
+            // the type bits of the mode, shifted down, are the DT_* values
+            let file_type = match ext2.get_inode(entry) {
+                Ok(record) => (record.type_perm.bits() >> 12) as u8,
+                Err(err) => return -err.errno(),
+            };
+            // names can't hold a NUL, so this only appends one
+            let len = name.len();
+            let Ok(c_name) = CString::new(name) else {
+                return -libc::EIO;
+            };
+            let stop = cb(ctx, entry as u32, c_name.as_ptr(), len, file_type);
+            if stop != 0 {
+                return stop;
+            }
+        }
+        0
+    })
+}
+
+/// Look up `path`, taken from the root whether it starts with '/' or not,
+/// and store the inode it names in `*inode`.
+///
+/// # Safety
+///
+/// `fs` must come from `ext2_open`, `path` must be a NUL-terminated string
+/// and `inode` must point to a writable `uint32_t`.
+#[no_mangle]
+pub unsafe extern "C" fn ext2_lookup_path(
+    fs: *mut Ext2Handle,
+    path: *const c_char,
+    inode: *mut u32,
+) -> c_int {
+    guard(|| {
+        if fs.is_null() || path.is_null() || inode.is_null() {
+            return -libc::EINVAL;
+        }
+        let Ok(path) = CStr::from_ptr(path).to_str() else {
+            return -libc::EINVAL;
+        };
+        match (*fs).0.resolve_path(2, path) {
+            Ok(found) => {
+                *inode = found as u32;
+                0
+            }
+            Err(err) => -err.errno(),
+        }
+    })
+}
+
+/// Release a handle from `ext2_open`. Does nothing with NULL.
+///
+/// # Safety
+///
+/// `fs` must come from `ext2_open` and not be used again.
+#[no_mangle]
+pub unsafe extern "C" fn ext2_close(fs: *mut Ext2Handle) {
+    if !fs.is_null() {
+        // dropping an open image can't panic past a read-only one, but
+        // don't let it into C if it ever does
+        let _ = panic::catch_unwind(AssertUnwindSafe(|| drop(Box::from_raw(fs))));
+    }
+}
@@ -0,0 +1,212 @@
+// Serving an image to the host kernel through FUSE, read-only.
+//
+// ext2 inode numbers are used as FUSE inode numbers as they are, except the
+// root: FUSE expects it to be 1, which in ext2 is the bad blocks inode that
+// no directory ever links to, so the two just trade places. Permissions are
+// left to the kernel (`default_permissions`), which checks them against the
+// modes and owners `getattr` reports.
+
+use crate::structs::{self, Inode};
+use crate::{Ext2, Ext2Error};
+use fuser::{
+    FileAttr, FileType, Filesystem, MountOption, ReplyAttr, ReplyData, ReplyDirectory, ReplyEntry,
+    ReplyStatfs, Request,
+};
+use log::debug;
+use std::ffi::OsStr;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// how long the kernel may cache what it's told; nothing changes under a
+// read-only mount
+const TTL: Duration = Duration::from_secs(60);
+
+const FUSE_ROOT: u64 = 1;
+const EXT2_ROOT: usize = 2;
+
+/// An image mounted on the host, returned by `Ext2::mount_fuse`. Dropping it
+/// unmounts the image.
+pub struct FuseMount {
+    _session: fuser::BackgroundSession,
+}
+
+impl Ext2 {
+    /// Mount this filesystem read-only at the host directory `mountpoint`,
+    /// served by a background thread until the returned `FuseMount` is
+    /// dropped.
+    pub fn mount_fuse(self, mountpoint: &Path) -> io::Result<FuseMount> {
+        let options = [
+            MountOption::RO,
+            MountOption::FSName("ext2".to_string()),
+            MountOption::DefaultPermissions,
+        ];
+        let session = fuser::spawn_mount2(FuseFs { ext2: self }, mountpoint, &options)?;
+        Ok(FuseMount { _session: session })
+    }
+}
+
+struct FuseFs {
+    ext2: Ext2,
+}
+
+fn to_ext2(ino: u64) -> usize {
+    match ino {
+        FUSE_ROOT => EXT2_ROOT,
+        ino if ino == EXT2_ROOT as u64 => FUSE_ROOT as usize,
+        ino => ino as usize,
+    }
+}
+
+fn to_fuse(inode: usize) -> u64 {
+    to_ext2(inode as u64) as u64
+}
+
+fn file_type(record: &Inode) -> FileType {
+    match record.file_type() {
+        structs::FileType::Fifo => FileType::NamedPipe,
+        structs::FileType::Char => FileType::CharDevice,
+        structs::FileType::Dir => FileType::Directory,
+        structs::FileType::Block => FileType::BlockDevice,
+        structs::FileType::Symlink => FileType::Symlink,
+        structs::FileType::Socket => FileType::Socket,
+        structs::FileType::Regular | structs::FileType::Unknown => FileType::RegularFile,
+    }
+}
+
+fn time(seconds: u32) -> SystemTime {
+    UNIX_EPOCH + Duration::from_secs(seconds as u64)
+}
+
+impl FuseFs {
+    fn attr(&self, inode: usize) -> Result<FileAttr, Ext2Error> {
+        let record = self.ext2.get_inode(inode)?;
+        let size = if record.is_dir() {
+            record.size_low as u64
+        } else {
+            record.size()
+        };
+        // the old encoding, which every device number ext2 can hold fits
+        let rdev = record.device().map_or(0, |(major, minor)| {
+            (major & 0xfff) << 8 | (minor & 0xff) | (minor & !0xff) << 12
+        });
+        Ok(FileAttr {
+            ino: to_fuse(inode),
+            size,
+            blocks: record.sectors_count as u64,
+            atime: time(record.atime),
+            mtime: time(record.mtime),
+            ctime: time(record.ctime),
+            crtime: time(record.ctime),
+            kind: file_type(record),
+            perm: record.permissions(),
+            nlink: record.hard_links as u32,
+            uid: crate::access::owner(record),
+            gid: crate::access::group(record),
+            rdev,
+            blksize: self.ext2.block_size as u32,
+            flags: 0,
+        })
+    }
+}
+
+impl Filesystem for FuseFs {
+    fn lookup(&mut self, _req: &Request<'_>, parent: u64, name: &OsStr, reply: ReplyEntry) {
+        let Some(name) = name.to_str() else {
+            reply.error(libc::ENOENT);
+            return;
+        };
+        let found = self.ext2.lookup(to_ext2(parent), name).and_then(|inode| {
+            inode.ok_or_else(|| Ext2Error::NotFound {
+                name: name.to_string(),
+            })
+        });
+        match found.and_then(|inode| self.attr(inode)) {
+            Ok(attr) => reply.entry(&TTL, &attr, 0),
+            Err(err) => reply.error(err.errno()),
+        }
+    }
+
+    fn getattr(&mut self, _req: &Request<'_>, ino: u64, reply: ReplyAttr) {
+        match self.attr(to_ext2(ino)) {
+            Ok(attr) => reply.attr(&TTL, &attr),
+            Err(err) => reply.error(err.errno()),
+        }
+    }
+
+    fn readlink(&mut self, _req: &Request<'_>, ino: u64, reply: ReplyData) {
+        match self.ext2.read_link(to_ext2(ino)) {
+            Ok(target) => reply.data(&target),
+            Err(err) => reply.error(err.errno()),
+        }
+    }
+
+    fn read(
+        &mut self,
+        _req: &Request<'_>,
+        ino: u64,
+        _fh: u64,
+        offset: i64,
+        size: u32,
+        _flags: i32,
+        _lock_owner: Option<u64>,
+        reply: ReplyData,
+    ) {
+        let mut buf = vec![0; size as usize];
+        match self.ext2.read_at(to_ext2(ino), offset as u64, &mut buf) {
+            Ok(len) => reply.data(&buf[..len]),
+            Err(err) => reply.error(err.errno()),
+        }
+    }
+
+    fn readdir(
+        &mut self,
+        _req: &Request<'_>,
+        ino: u64,
+        _fh: u64,
+        offset: i64,
+        mut reply: ReplyDirectory,
+    ) {
+        let entries = match self.ext2.read_dir_inode(to_ext2(ino)) {
+            Ok(entries) => entries,
+            Err(err) => {
+                reply.error(err.errno());
+                return;
+            }
+        };
+        // an entry's offset is where the next call picks up after it
+        for (i, entry) in entries.iter().enumerate().skip(offset as usize) {
+            let kind = match self.ext2.get_inode(entry.inode) {
+                Ok(record) => file_type(record),
+                Err(err) => {
+                    debug!("fuse: readdir of inode {}: {}", to_ext2(ino), err);
+                    continue;
+                }
+            };
+            if reply.add(
+                to_fuse(entry.inode),
+                i as i64 + 1,
+                kind,
+                OsStr::from_bytes(&entry.name),
+            ) {
+                break;
+            }
+        }
+        reply.ok();
+    }
+
+    fn statfs(&mut self, _req: &Request<'_>, _ino: u64, reply: ReplyStatfs) {
+        let sb = &self.ext2.superblock;
+        reply.statfs(
+            sb.blocks_count as u64,
+            sb.free_blocks_count as u64,
+            self.ext2.available_blocks() as u64,
+            sb.inodes_count as u64,
+            sb.free_inodes_count as u64,
+            self.ext2.block_size as u32,
+            255,
+            self.ext2.block_size as u32,
+        );
+    }
+}
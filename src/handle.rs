@@ -0,0 +1,123 @@
+// Open files, like a process's file descriptors: a `FileReader` is an inode
+// and an offset into it, which reads advance. The filesystem counts the
+// readers open on each inode so that, as in POSIX, removing a file's last
+// link while it's open only removes the name: the inode and its blocks stay
+// until the last reader is closed, and reads go on working until then.
+//
+// Nothing on disk records that a file is open, so a filesystem synced while
+// a removed file is still open has an inode with no links left in use,
+// exactly what a crash at that moment would leave; e2fsck frees it.
+
+use crate::{Ext2, Ext2Error, Result};
+use log::debug;
+use std::io::{self, SeekFrom};
+
+/// An open file, from `Ext2::open_file`: the inode, and where the next read
+/// starts. It borrows nothing, so it can be kept alongside the filesystem
+/// and used with it for each read; give it back with `Ext2::close_file`.
+#[derive(Debug, PartialEq, Eq)]
+pub struct FileReader {
+    inode: usize,
+    offset: u64,
+    // where the last read ended, so a read that starts there is taken to be
+    // part of a sequential pass through the file and reads ahead
+    last_end: u64,
+}
+
+impl FileReader {
+    pub fn inode(&self) -> usize {
+        self.inode
+    }
+
+    /// Where the next read starts, in bytes from the start of the file.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Read from the offset into `buf`, and move the offset past what was
+    /// read, like read(2): as many bytes as fit, fewer at the end of the
+    /// file, and none past it. Fails with `NotFound` if the inode was freed
+    /// after all, e.g. by a rollback or a repair. A read that starts where
+    /// the last one ended reads ahead (see `Ext2Options::readahead_blocks`).
+    pub fn read(&mut self, ext2: &Ext2, buf: &mut [u8]) -> Result<usize> {
+        self.check_open(ext2)?;
+        let sequential = self.offset == self.last_end;
+        let len = ext2.read_at_sequential(self.inode, self.offset, buf, sequential)?;
+        self.offset += len as u64;
+        self.last_end = self.offset;
+        Ok(len)
+    }
+
+    /// Move the offset, like lseek(2), returning where it ends up. Seeking
+    /// past the end is allowed, and reads there find nothing; seeking to
+    /// before the start isn't.
+    pub fn seek(&mut self, ext2: &Ext2, pos: SeekFrom) -> Result<u64> {
+        self.check_open(ext2)?;
+        let offset = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::Current(delta) => self.offset.checked_add_signed(delta),
+            SeekFrom::End(delta) => ext2.get_inode(self.inode)?.size().checked_add_signed(delta),
+        };
+        self.offset = offset.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "seek to before the start of the file",
+            )
+        })?;
+        Ok(self.offset)
+    }
+
+    fn check_open(&self, ext2: &Ext2) -> Result<()> {
+        if ext2.inode_is_allocated(self.inode)? {
+            Ok(())
+        } else {
+            Err(Ext2Error::NotFound {
+                name: format!("inode {}", self.inode),
+            })
+        }
+    }
+}
+
+impl Ext2 {
+    /// Open `inode` for reading, at offset 0. Directories are refused; their
+    /// contents are read with `read_dir_inode`.
+    pub fn open_file(&mut self, inode: usize) -> Result<FileReader> {
+        if self.get_inode(inode)?.is_dir() {
+            return Err(Ext2Error::IsADirectory {
+                name: format!("inode {}", inode),
+            });
+        }
+        *self.open_files.entry(inode).or_insert(0) += 1;
+        Ok(FileReader {
+            inode,
+            offset: 0,
+            last_end: 0,
+        })
+    }
+
+    /// Close `reader`. If it was the last one open on a file whose last link
+    /// has been removed since, the file is deleted now.
+    pub fn close_file(&mut self, reader: FileReader) -> Result<()> {
+        let inode = reader.inode;
+        match self.open_files.get_mut(&inode) {
+            Some(count) if *count > 1 => {
+                *count -= 1;
+                return Ok(());
+            }
+            Some(_) => {
+                self.open_files.remove(&inode);
+            }
+            None => return Ok(()),
+        }
+        if self.inode_is_allocated(inode)? && self.get_inode(inode)?.hard_links == 0 {
+            debug!("last reader of removed inode {} closed", inode);
+            self.delete_inode(inode)?;
+        }
+        Ok(())
+    }
+
+    /// How many readers are open on `inode`.
+    pub fn open_count(&self, inode: usize) -> usize {
+        self.open_files.get(&inode).copied().unwrap_or(0)
+    }
+}
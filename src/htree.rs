@@ -0,0 +1,383 @@
+// Hashed directory indexes (htree).
+//
+// A directory with the index flag keeps a B-tree of name hashes over its
+// ordinary entry blocks. Block 0 holds the root: "." and ".." entries, the
+// second spanning the rest of the block so that linear readers skip what
+// follows, then a small info header and an array of (hash, block) pairs.
+// Interior nodes are a block with one empty entry spanning the whole block,
+// followed by the same array. Each array's first slot holds its count and
+// limit in place of a hash, which is implicitly 0 for that entry:
+//
+//   root:  . | .. | reserved (4) hash_version indirect_levels ... | limit count block | hash block ...
+//   node:  empty entry (8)                                         | limit count block | hash block ...
+//
+// Leaf blocks are ordinary directory blocks holding the names whose hashes
+// fall between their index entry's hash and the next one's, so a lookup
+// reads one block per level plus one leaf instead of the whole directory.
+// https://www.kernel.org/doc/html/latest/filesystems/ext4/directory.html#hash-tree-directories
+
+use crate::structs::InodeFlags;
+use crate::{dir_block_entries, Ext2, Ext2Error, Result};
+use log::{debug, warn};
+use std::sync::atomic::Ordering;
+
+// superblock misc flags: which signedness of char the hash was computed with
+const FLAGS_UNSIGNED_HASH: u32 = 0x2;
+// where the root's info header and entry array start, after "." and ".."
+const DX_ROOT_INFO: usize = 24;
+// where a node's entry array starts, after its empty entry
+const DX_NODE_ENTRIES: usize = 8;
+// the low bit of an index hash marks a leaf continuing the previous one's hash
+const HASH_CONTINUED: u32 = 1;
+
+#[derive(Debug, Clone, Copy)]
+enum HashVersion {
+    Legacy,
+    HalfMd4,
+    Tea,
+}
+
+impl Ext2 {
+    /// Find `name` in directory `dir` and return its inode number, if present.
+    ///
+    /// Indexed directories are searched through their htree, reading one
+    /// block per index level and then a leaf; everything else, or an index
+    /// that can't be used, is scanned entry by entry.
+    ///
+    /// With `Ext2Options::case_insensitive_lookup`, a name with no exact
+    /// match finds the entry that matches it but for case, and fails with
+    /// `AmbiguousName` if more than one does.
+    pub fn lookup(&self, dir: usize, name: &str) -> Result<Option<usize>> {
+        debug!("looking up {:?} in directory {}", name, dir);
+        self.lookups.fetch_add(1, Ordering::Relaxed);
+        let found = match self.htree_lookup(dir, name)? {
+            Some(found) => found,
+            None => self.linear_lookup(dir, name)?,
+        };
+        if found.is_some() || !self.case_insensitive_lookup() || name == "." || name == ".." {
+            return Ok(found);
+        }
+        self.caseless_lookup(dir, name)
+    }
+
+    // scan all of `dir` for the one entry whose name is `name` but for case;
+    // the hashes of an index are of the names as they are, so are no help
+    fn caseless_lookup(&self, dir: usize, name: &str) -> Result<Option<usize>> {
+        Ok(self.caseless_match(dir, name)?.map(|(inode, _)| inode))
+    }
+
+    // the inode and the name on disk of the one entry of `dir` whose name is
+    // `name` but for case, failing with `AmbiguousName` if more than one
+    // inode's is; for finding the entry itself once `lookup` has found it
+    // that way
+    pub(crate) fn caseless_match(
+        &self,
+        dir: usize,
+        name: &str,
+    ) -> Result<Option<(usize, Vec<u8>)>> {
+        let folded = name.to_lowercase();
+        let mut found: Option<(usize, Vec<u8>)> = None;
+        for entry in self.read_dir_raw(dir)? {
+            let matches = match std::str::from_utf8(entry.name.0) {
+                Ok(entry_name) => entry_name.to_lowercase() == folded,
+                Err(_) => entry.name.0.eq_ignore_ascii_case(name.as_bytes()),
+            };
+            if !matches || found.as_ref().map(|(inode, _)| *inode) == Some(entry.inode) {
+                continue;
+            }
+            if found.is_some() {
+                return Err(Ext2Error::AmbiguousName {
+                    name: name.to_string(),
+                });
+            }
+            found = Some((entry.inode, entry.name.0.to_vec()));
+        }
+        Ok(found)
+    }
+
+    // scan `dir` a block at a time, stopping at the first match rather than
+    // reading the whole listing
+    fn linear_lookup(&self, dir: usize, name: &str) -> Result<Option<usize>> {
+        let blocks = self
+            .file_blocks(dir)
+            .map_err(|e| e.in_inode("reading", dir))?;
+        for block_num in blocks {
+            let block_num = block_num.map_err(|e| e.in_inode("reading", dir))?;
+            if block_num == 0 {
+                continue;
+            }
+            let block = self
+                .block(block_num)
+                .map_err(|e| e.in_inode("reading", dir))?;
+            let entries = dir_block_entries(block).map_err(|(offset, reason)| {
+                Ext2Error::CorruptDirectory {
+                    inode: dir,
+                    block: block_num,
+                    offset,
+                    reason,
+                }
+            })?;
+            if let Some(entry) = entries.iter().find(|entry| entry.name.0 == name.as_bytes()) {
+                return Ok(Some(entry.inode));
+            }
+        }
+        Ok(None)
+    }
+
+    // search the index of `dir`; `None` if it has none we understand, so the
+    // caller has to scan
+    fn htree_lookup(&self, dir: usize, name: &str) -> Result<Option<Option<usize>>> {
+        let record = self.get_inode(dir)?;
+        if !InodeFlags::from_bits_truncate(record.flags).contains(InodeFlags::INDEX)
+            || name == "."
+            || name == ".."
+        {
+            return Ok(None);
+        }
+        let mut blocks = self.file_blocks(dir)?;
+        let block_count = blocks.len();
+        let Some(root_block) = blocks.next().transpose()? else {
+            return Ok(None);
+        };
+        let root = self.block(root_block)?;
+        // the signedness usually comes from the superblock, but versions 3-5
+        // are the unsigned variants of 0-2
+        let unsigned = self.superblock.misc_flags & FLAGS_UNSIGNED_HASH != 0;
+        let (hash_version, unsigned) = match root[DX_ROOT_INFO + 4] {
+            0 => (HashVersion::Legacy, unsigned),
+            1 => (HashVersion::HalfMd4, unsigned),
+            2 => (HashVersion::Tea, unsigned),
+            3 => (HashVersion::Legacy, true),
+            4 => (HashVersion::HalfMd4, true),
+            5 => (HashVersion::Tea, true),
+            version => {
+                warn!("inode {}: unknown directory hash version {}", dir, version);
+                return Ok(None);
+            }
+        };
+        let info_length = root[DX_ROOT_INFO + 5] as usize;
+        let levels = root[DX_ROOT_INFO + 6] as usize;
+        if levels > 2 {
+            return Ok(None);
+        }
+        let hash = dx_hash(
+            name.as_bytes(),
+            hash_version,
+            &self.superblock.hash_seed,
+            unsigned,
+        );
+
+        let corrupt = |block: usize, reason: String| Ext2Error::CorruptDirectory {
+            inode: dir,
+            block,
+            offset: 0,
+            reason,
+        };
+        // walk down the index, remembering each level's entries and where we
+        // went, so a leaf continuing into the next block can be followed
+        let mut path = Vec::with_capacity(levels + 1);
+        let (mut block_num, mut start) = (root_block, DX_ROOT_INFO + info_length);
+        for _ in 0..=levels {
+            let entries = dx_entries(self.block(block_num)?, start)
+                .ok_or_else(|| corrupt(block_num, String::from("bad index entry count")))?;
+            // the last entry whose hash is <= ours; the first always qualifies
+            let at = entries
+                .partition_point(|&(entry_hash, _)| entry_hash <= hash)
+                .max(1)
+                - 1;
+            let logical = entries[at].1 as usize;
+            block_num = self.dir_block(dir, logical, block_count)?;
+            path.push((entries, at));
+            start = DX_NODE_ENTRIES;
+        }
+
+        loop {
+            let leaf = self.block(block_num)?;
+            let entries = dir_block_entries(leaf).map_err(|(offset, reason)| {
+                Ext2Error::CorruptDirectory {
+                    inode: dir,
+                    block: block_num,
+                    offset,
+                    reason,
+                }
+            })?;
+            if let Some(entry) = entries.iter().find(|entry| entry.name.0 == name.as_bytes()) {
+                return Ok(Some(Some(entry.inode)));
+            }
+            // names with the same hash can spill into the next leaf, which is
+            // then indexed by the hash with its low bit set
+            let (entries, at) = path.last_mut().unwrap();
+            match entries.get(*at + 1) {
+                Some(&(next_hash, logical))
+                    if next_hash & !HASH_CONTINUED == hash && next_hash & HASH_CONTINUED != 0 =>
+                {
+                    *at += 1;
+                    block_num = self.dir_block(dir, logical as usize, block_count)?;
+                }
+                _ => return Ok(Some(None)),
+            }
+        }
+    }
+
+    // the physical block holding logical block `logical` of directory `dir`
+    fn dir_block(&self, dir: usize, logical: usize, block_count: usize) -> Result<usize> {
+        // the top bits of an index block number are reserved
+        let logical = logical & 0x0fff_ffff;
+        match self.file_blocks(dir)?.nth(logical).transpose()? {
+            Some(block) if block != 0 => Ok(block),
+            _ => Err(Ext2Error::CorruptDirectory {
+                inode: dir,
+                block: logical,
+                offset: 0,
+                reason: format!(
+                    "index points at logical block {} of {}",
+                    logical, block_count
+                ),
+            }),
+        }
+    }
+}
+
+// the (hash, logical block) entries of the index array at `start` in `block`,
+// with the first entry's implicit hash of 0
+fn dx_entries(block: &[u8], start: usize) -> Option<Vec<(u32, u32)>> {
+    let limit = u16::from_le_bytes(block.get(start..start + 2)?.try_into().unwrap()) as usize;
+    let count = u16::from_le_bytes(block.get(start + 2..start + 4)?.try_into().unwrap()) as usize;
+    if count == 0 || count > limit || start + count * 8 > block.len() {
+        return None;
+    }
+    let word = |offset: usize| u32::from_le_bytes(block[offset..offset + 4].try_into().unwrap());
+    Some(
+        (0..count)
+            .map(|i| {
+                let offset = start + i * 8;
+                (if i == 0 { 0 } else { word(offset) }, word(offset + 4))
+            })
+            .collect(),
+    )
+}
+
+// the directory index hash of `name`, as the kernel's ext4fs_dirhash
+fn dx_hash(name: &[u8], version: HashVersion, seed: &[u32; 4], unsigned: bool) -> u32 {
+    let mut buf = if seed.iter().any(|&word| word != 0) {
+        *seed
+    } else {
+        [0x6745_2301, 0xefcd_ab89, 0x98ba_dcfe, 0x1032_5476]
+    };
+    // names are hashed as chars, whose signedness depends on the platform
+    // the filesystem was made on
+    let chars: Vec<u32> = name
+        .iter()
+        .map(|&b| {
+            if unsigned {
+                b as u32
+            } else {
+                b as i8 as i32 as u32
+            }
+        })
+        .collect();
+    let hash = match version {
+        HashVersion::Legacy => {
+            let (mut hash0, mut hash1) = (0x12a3_fe2d_u32, 0x37ab_e8f9_u32);
+            for &c in &chars {
+                let mut hash = hash1.wrapping_add(hash0 ^ c.wrapping_mul(7_152_373));
+                if hash & 0x8000_0000 != 0 {
+                    hash = hash.wrapping_sub(0x7fff_ffff);
+                }
+                hash1 = hash0;
+                hash0 = hash;
+            }
+            hash0 << 1
+        }
+        HashVersion::HalfMd4 => {
+            for chunk in chunks(&chars, 32) {
+                half_md4_transform(&mut buf, &str_to_hash_buf::<8>(chunk));
+            }
+            buf[1]
+        }
+        HashVersion::Tea => {
+            for chunk in chunks(&chars, 16) {
+                tea_transform(&mut buf, &str_to_hash_buf::<4>(chunk));
+            }
+            buf[0]
+        }
+    };
+    hash & !1
+}
+
+// the tails of `chars` starting every `size` chars, like the kernel's
+// advancing pointer and remaining length
+fn chunks(chars: &[u32], size: usize) -> impl Iterator<Item = &[u32]> {
+    (0..chars.len()).step_by(size).map(|start| &chars[start..])
+}
+
+// pack the first 4 * N of `chars` into N words, padded with a pattern made
+// from the number of chars left
+fn str_to_hash_buf<const N: usize>(chars: &[u32]) -> [u32; N] {
+    let len = chars.len() as u32;
+    let mut pad = len | (len << 8);
+    pad |= pad << 16;
+    let mut out = [pad; N];
+    let mut val = pad;
+    let mut word = 0;
+    for (i, &c) in chars.iter().take(N * 4).enumerate() {
+        val = c.wrapping_add(val << 8);
+        if i % 4 == 3 {
+            out[word] = val;
+            word += 1;
+            val = pad;
+        }
+    }
+    if word < N {
+        out[word] = val;
+    }
+    out
+}
+
+fn tea_transform(buf: &mut [u32; 4], input: &[u32; 4]) {
+    const DELTA: u32 = 0x9e37_79b9;
+    let (mut b0, mut b1) = (buf[0], buf[1]);
+    let [a, b, c, d] = *input;
+    let mut sum = 0u32;
+    for _ in 0..16 {
+        sum = sum.wrapping_add(DELTA);
+        b0 = b0.wrapping_add(
+            (b1 << 4).wrapping_add(a) ^ b1.wrapping_add(sum) ^ (b1 >> 5).wrapping_add(b),
+        );
+        b1 = b1.wrapping_add(
+            (b0 << 4).wrapping_add(c) ^ b0.wrapping_add(sum) ^ (b0 >> 5).wrapping_add(d),
+        );
+    }
+    buf[0] = buf[0].wrapping_add(b0);
+    buf[1] = buf[1].wrapping_add(b1);
+}
+
+fn half_md4_transform(buf: &mut [u32; 4], input: &[u32; 8]) {
+    const K2: u32 = 0x5a82_7999;
+    const K3: u32 = 0x6ed9_eba1;
+    let f = |x: u32, y: u32, z: u32| z ^ (x & (y ^ z));
+    let g = |x: u32, y: u32, z: u32| (x & y).wrapping_add((x ^ y) & z);
+    let h = |x: u32, y: u32, z: u32| x ^ y ^ z;
+    let [mut a, mut b, mut c, mut d] = *buf;
+    // each round is 8 steps rotating through (a, b, c, d) as the target
+    let mut round =
+        |func: &dyn Fn(u32, u32, u32) -> u32, k: u32, order: [usize; 8], shifts: [u32; 4]| {
+            for (step, &i) in order.iter().enumerate() {
+                let x = input[i].wrapping_add(k);
+                let s = shifts[step % 4];
+                match step % 4 {
+                    0 => a = a.wrapping_add(func(b, c, d)).wrapping_add(x).rotate_left(s),
+                    1 => d = d.wrapping_add(func(a, b, c)).wrapping_add(x).rotate_left(s),
+                    2 => c = c.wrapping_add(func(d, a, b)).wrapping_add(x).rotate_left(s),
+                    _ => b = b.wrapping_add(func(c, d, a)).wrapping_add(x).rotate_left(s),
+                }
+            }
+        };
+    round(&f, 0, [0, 1, 2, 3, 4, 5, 6, 7], [3, 7, 11, 19]);
+    round(&g, K2, [1, 3, 5, 7, 0, 2, 4, 6], [3, 5, 9, 13]);
+    round(&h, K3, [3, 7, 2, 6, 1, 5, 0, 4], [3, 9, 11, 15]);
+    buf[0] = buf[0].wrapping_add(a);
+    buf[1] = buf[1].wrapping_add(b);
+    buf[2] = buf[2].wrapping_add(c);
+    buf[3] = buf[3].wrapping_add(d);
+}
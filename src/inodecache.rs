@@ -0,0 +1,111 @@
+// Inodes as they are on the device, by inode number, for devices that
+// aren't in memory, so looking one up again is an index into a table rather
+// than finding its group and table block and going through the block cache.
+// On a device in memory an inode is a cast into its table block already,
+// and copying it would only cost more, so there's no cache there.
+//
+// Only inodes in table blocks that haven't been modified are kept: a
+// modified inode's one copy is in the dirty-block layer, where `inode_mut`
+// changes it, and `get_inode` goes there for it. So an entry is good for
+// as long as its block stays unmodified, and the mutation layer has two
+// hooks: `block_mut` forgets a table block's inodes the first time the block
+// is modified, and `rollback`, which can leave different blocks modified,
+// forgets the lot.
+//
+// The table is in chunks, made the first time one of their inodes is looked
+// up, so it takes up room only for the parts of the inode tables in use.
+
+use crate::structs::Inode;
+use std::fmt;
+use std::ops::Range;
+use std::ptr;
+use std::sync::OnceLock;
+
+const CHUNK_INODES: usize = 256;
+
+type Chunk = Box<[OnceLock<Inode>]>;
+
+pub(crate) struct InodeCache {
+    // by (inode number - 1) / CHUNK_INODES; empty if there's no cache
+    chunks: Vec<OnceLock<Chunk>>,
+}
+
+impl InodeCache {
+    // a cache for `inodes_count` inodes
+    pub(crate) fn new(inodes_count: usize) -> InodeCache {
+        InodeCache {
+            chunks: std::iter::repeat_with(OnceLock::new)
+                .take(inodes_count.div_ceil(CHUNK_INODES))
+                .collect(),
+        }
+    }
+
+    // no cache: nothing is kept, and nothing is ever found
+    pub(crate) fn disabled() -> InodeCache {
+        InodeCache { chunks: Vec::new() }
+    }
+
+    pub(crate) fn is_enabled(&self) -> bool {
+        !self.chunks.is_empty()
+    }
+
+    fn slot(&self, inode: usize) -> Option<&OnceLock<Inode>> {
+        let index = inode.checked_sub(1)?;
+        let chunk = self.chunks.get(index / CHUNK_INODES)?.get()?;
+        Some(&chunk[index % CHUNK_INODES])
+    }
+
+    pub(crate) fn get(&self, inode: usize) -> Option<&Inode> {
+        self.slot(inode)?.get()
+    }
+
+    // keep a copy of `record` as inode `inode`, and return it, or `None` if
+    // there's no cache
+    pub(crate) fn insert(&self, inode: usize, record: &Inode) -> Option<&Inode> {
+        let index = inode.checked_sub(1)?;
+        let chunk = self.chunks.get(index / CHUNK_INODES)?.get_or_init(|| {
+            std::iter::repeat_with(OnceLock::new)
+                .take(CHUNK_INODES)
+                .collect()
+        });
+        let slot = &chunk[index % CHUNK_INODES];
+        // another thread may have got there first, with the same inode
+        let _ = slot.set(unsafe { ptr::read(record) });
+        slot.get()
+    }
+
+    // forget inodes `range`, e.g. those of a table block about to change
+    pub(crate) fn forget(&mut self, range: Range<usize>) {
+        for inode in range {
+            let index = inode - 1;
+            if let Some(chunk) = self
+                .chunks
+                .get_mut(index / CHUNK_INODES)
+                .and_then(OnceLock::get_mut)
+            {
+                chunk[index % CHUNK_INODES].take();
+            }
+        }
+    }
+
+    pub(crate) fn clear(&mut self) {
+        for chunk in &mut self.chunks {
+            chunk.take();
+        }
+    }
+
+    // how many inodes are kept
+    pub(crate) fn len(&self) -> usize {
+        self.chunks
+            .iter()
+            .filter_map(OnceLock::get)
+            .map(|chunk| chunk.iter().filter(|slot| slot.get().is_some()).count())
+            .sum()
+    }
+}
+
+impl fmt::Debug for InodeCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "InodeCache({} inodes)", self.len())
+    }
+}
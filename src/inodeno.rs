@@ -0,0 +1,108 @@
+// Inode numbers, and where the inode each one names is kept.
+//
+// Inode numbers count from 1 across the whole filesystem, while each group's
+// inode bitmap and inode table count from 0: inode n is bit and slot
+// (n - 1) % inodes_per_group of group (n - 1) / inodes_per_group. Getting the
+// 1 wrong only shows at the edges of a group, e.g. as the last inode of
+// group 0 read from the first slot of group 1, so every conversion between
+// the two goes through `InodeNo` instead of being written out again.
+//
+// The numbers below the first usable one are reserved for the bad blocks
+// list, the root directory, the journal and the like, and are never handed
+// out by the allocator; the root directory is always inode 2.
+
+use crate::structs::Superblock;
+use crate::{Ext2Error, Result};
+use std::fmt;
+
+/// An inode number, as directory entries and the rest of the API count
+/// them: from 1, across every group.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct InodeNo(pub usize);
+
+impl InodeNo {
+    /// The root directory's inode.
+    pub const ROOT: InodeNo = InodeNo(2);
+
+    /// The first inode number files may use on a revision 0 filesystem,
+    /// which doesn't record it, and the lowest any filesystem may say.
+    pub const GOOD_OLD_FIRST: InodeNo = InodeNo(11);
+
+    /// The size of an inode on a revision 0 filesystem, which doesn't record
+    /// it, and the smallest any filesystem may say: the fields of `Inode`.
+    pub const GOOD_OLD_SIZE: usize = 128;
+
+    /// The group the inode is in and its index there, i.e. its bit in the
+    /// group's inode bitmap and its slot in the group's inode table. Fails
+    /// with `InodeOutOfRange` for 0 and for numbers past `inodes_count`.
+    pub fn to_group_and_index(self, sb: &Superblock) -> Result<(usize, usize)> {
+        let inodes_count = sb.inodes_count as usize;
+        if self.0 == 0 || self.0 > inodes_count {
+            return Err(Ext2Error::InodeOutOfRange {
+                inode: self.0,
+                inodes_count,
+            });
+        }
+        let inodes_per_group = sb.inodes_per_group as usize;
+        Ok((
+            (self.0 - 1) / inodes_per_group,
+            (self.0 - 1) % inodes_per_group,
+        ))
+    }
+
+    /// The inode at `index` of group `group`, the other way from
+    /// `to_group_and_index`; `None` if the index is past the end of a group
+    /// or the number past `inodes_count`.
+    pub fn from_group_and_index(group: usize, index: usize, sb: &Superblock) -> Option<InodeNo> {
+        let inodes_per_group = sb.inodes_per_group as usize;
+        if index >= inodes_per_group {
+            return None;
+        }
+        let inode = group
+            .checked_mul(inodes_per_group)?
+            .checked_add(index + 1)?;
+        (inode <= sb.inodes_count as usize).then_some(InodeNo(inode))
+    }
+
+    /// The first inode number files may use: `first_inode` from the
+    /// superblock, or `GOOD_OLD_FIRST` on revision 0 or if the superblock
+    /// says less, so a damaged one can't put the root directory up for
+    /// allocation.
+    pub fn first_usable(sb: &Superblock) -> InodeNo {
+        if sb.rev_major == 0 {
+            InodeNo::GOOD_OLD_FIRST
+        } else {
+            InodeNo((sb.first_inode as usize).max(InodeNo::GOOD_OLD_FIRST.0))
+        }
+    }
+
+    /// The size of each inode's slot in the inode tables: `inode_size` from
+    /// the superblock, or `GOOD_OLD_SIZE` on revision 0. Only the first
+    /// `GOOD_OLD_SIZE` bytes are `Inode`'s fields; the rest holds extended
+    /// attributes and the like.
+    pub fn slot_size(sb: &Superblock) -> usize {
+        if sb.rev_major == 0 {
+            InodeNo::GOOD_OLD_SIZE
+        } else {
+            sb.inode_size as usize
+        }
+    }
+
+    /// Whether the inode is one of the reserved ones below `first_usable`,
+    /// the root directory included.
+    pub fn is_reserved(self, sb: &Superblock) -> bool {
+        self < InodeNo::first_usable(sb)
+    }
+}
+
+impl From<InodeNo> for usize {
+    fn from(inode: InodeNo) -> usize {
+        inode.0
+    }
+}
+
+impl fmt::Display for InodeNo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
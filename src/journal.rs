@@ -0,0 +1,114 @@
+// Noticing ext3's journal. The crate doesn't replay or write journals, so an
+// image with one is only ever opened read-only, and one whose journal still
+// holds transactions gets a warning: the metadata they change is stale on
+// disk until they're replayed, by mounting the filesystem or by e2fsck.
+//
+// The journal is usually the reserved inode 8, starting with its own
+// superblock, big-endian unlike the rest of the filesystem:
+//
+//   0x00 magic 0xc03b3998 | blocktype | sequence | block size | blocks |
+//   first log block | first sequence to replay | first block to replay | ...
+//
+// where the block to replay is 0 once everything has been checkpointed.
+// https://www.kernel.org/doc/html/latest/filesystems/ext4/journal.html
+
+use crate::structs::{FeatureCompat, FeatureIncompat};
+use crate::Ext2;
+use serde::Serialize;
+
+const JOURNAL_MAGIC: u32 = 0xc03b_3998;
+// the block types of the version 1 and 2 journal superblocks
+const SUPERBLOCK_V1: u32 = 3;
+const SUPERBLOCK_V2: u32 = 4;
+
+/// The journal of an ext3 filesystem, as `Ext2::journal` finds it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct JournalInfo {
+    /// The journal's inode, or 0 if it's on a device of its own
+    pub inode: u32,
+    /// The number of the device the journal is on, if it's external
+    pub device: Option<u32>,
+    /// The journal's size in bytes, 0 if it's external
+    pub size: u64,
+    /// Whether the superblock says the journal has to be replayed before the
+    /// filesystem is used, i.e. it was in use when the system went down
+    pub needs_recovery: bool,
+    /// The journal block of the first transaction still to be replayed, 0 if
+    /// there's none
+    pub start: u32,
+    /// The sequence number of the first transaction still to be replayed,
+    /// or the next one to be written
+    pub sequence: u32,
+    /// Why the journal's own superblock couldn't be read, if it couldn't
+    pub problem: Option<String>,
+}
+
+impl JournalInfo {
+    /// Whether there's nothing in the journal that hasn't made it to the
+    /// filesystem, as far as can be told.
+    pub fn is_clean(&self) -> bool {
+        !self.needs_recovery && self.start == 0 && self.problem.is_none()
+    }
+
+    /// "clean" or what's wrong, for `fsinfo`.
+    pub fn state(&self) -> String {
+        if let Some(problem) = &self.problem {
+            format!("unreadable: {}", problem)
+        } else if self.needs_recovery || self.start != 0 {
+            format!(
+                "needs recovery, from transaction {} at journal block {}",
+                self.sequence, self.start
+            )
+        } else {
+            String::from("clean")
+        }
+    }
+}
+
+impl Ext2 {
+    /// The filesystem's journal, or `None` if it has none, i.e. it's plain
+    /// ext2 rather than ext3.
+    pub fn journal(&self) -> Option<JournalInfo> {
+        let sb = &self.superblock;
+        if sb.features_opt & FeatureCompat::HAS_JOURNAL.bits() == 0 {
+            return None;
+        }
+        let mut info = JournalInfo {
+            inode: sb.journal_inode,
+            device: None,
+            size: 0,
+            needs_recovery: sb.features_req & FeatureIncompat::RECOVER.bits() != 0,
+            start: 0,
+            sequence: 0,
+            problem: None,
+        };
+        if sb.journal_inode == 0 {
+            // there's nothing to look at but the superblock's say-so
+            info.device = Some(sb.journal_dev);
+            return Some(info);
+        }
+        let mut header = [0; 32];
+        let read = self
+            .get_inode(sb.journal_inode as usize)
+            .and_then(|record| {
+                info.size = record.size();
+                self.read_at(sb.journal_inode as usize, 0, &mut header)
+            });
+        let field = |at: usize| u32::from_be_bytes(header[at..at + 4].try_into().unwrap());
+        match read {
+            Err(err) => info.problem = Some(err.to_string()),
+            Ok(len) if len < header.len() => info.problem = Some(String::from("it's empty")),
+            Ok(_) if field(0) != JOURNAL_MAGIC => {
+                info.problem = Some(format!("bad magic number {:#x}", field(0)))
+            }
+            Ok(_) if ![SUPERBLOCK_V1, SUPERBLOCK_V2].contains(&field(4)) => {
+                info.problem = Some(format!("unknown superblock type {}", field(4)))
+            }
+            Ok(_) => {
+                info.sequence = field(0x18);
+                info.start = field(0x1c);
+            }
+        }
+        Some(info)
+    }
+}
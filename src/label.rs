@@ -0,0 +1,58 @@
+// Reading and changing the volume label and UUID, like e2label and
+// `tune2fs -U`. Both live only in the superblock, so changing them rewrites
+// the primary and every backup copy; otherwise e2fsck would find the backups
+// disagreeing with the primary.
+
+use crate::mkfs::random_bytes;
+use crate::{c_string, Ext2, Ext2Error, Result};
+use log::info;
+use uuid::Uuid;
+
+// the size of the superblock's volume name field
+const LABEL_LEN: usize = 16;
+
+impl Ext2 {
+    /// The volume name, up to its first NUL.
+    pub fn label(&self) -> String {
+        c_string(&self.superblock.volume_name)
+    }
+
+    /// Set the volume name. It has to fit the superblock's 16 bytes and
+    /// can't contain a NUL; an empty one clears it.
+    pub fn set_label(&mut self, label: &str) -> Result<()> {
+        self.check_writable()?;
+        if label.len() > LABEL_LEN || label.contains('\0') {
+            return Err(Ext2Error::InvalidName {
+                name: label.to_string(),
+            });
+        }
+        let mut volume_name = [0; LABEL_LEN];
+        volume_name[..label.len()].copy_from_slice(label.as_bytes());
+        self.superblock.volume_name = volume_name;
+        self.write_superblocks()?;
+        info!("set the volume name to {:?}", label);
+        Ok(())
+    }
+
+    /// Set the filesystem's UUID.
+    pub fn set_uuid(&mut self, uuid: Uuid) -> Result<()> {
+        self.check_writable()?;
+        self.superblock.fs_id = *uuid.as_bytes();
+        self.uuid = uuid;
+        self.write_superblocks()?;
+        info!("set the UUID to {}", uuid);
+        Ok(())
+    }
+
+    /// Give the filesystem a fresh random (version 4) UUID and return it.
+    pub fn regenerate_uuid(&mut self) -> Result<Uuid> {
+        let uuid = Uuid::from_bytes(random_bytes());
+        self.set_uuid(uuid)?;
+        Ok(uuid)
+    }
+
+    fn write_superblocks(&mut self) -> Result<()> {
+        self.write_metadata()?;
+        self.write_backups()
+    }
+}
@@ -0,0 +1,2357 @@
+#![feature(int_roundings)]
+
+mod access;
+mod alloc;
+mod append;
+mod bitmap;
+mod blockmap;
+mod check;
+mod clock;
+mod cmdline;
+mod compact;
+mod compare;
+mod dedup;
+mod defrag;
+mod device;
+mod dirindex;
+mod du;
+mod error;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "fuse")]
+mod fuse;
+mod handle;
+mod htree;
+mod inodecache;
+mod inodeno;
+mod journal;
+mod label;
+mod mkfs;
+mod owned;
+#[cfg(feature = "parallel")]
+mod parallel;
+mod partition;
+mod pathcache;
+mod populate;
+mod prealloc;
+mod progress;
+mod punch;
+#[cfg(feature = "python")]
+mod python;
+mod remove;
+mod rename;
+mod report;
+mod reserved;
+mod resize;
+mod reverse;
+mod snapshot;
+mod stats;
+mod strings;
+pub mod structs;
+mod tar;
+mod timeline;
+mod top;
+mod undelete;
+mod usage;
+mod walk;
+mod write;
+mod xattr;
+pub use crate::access::{AccessMode, Credentials};
+pub use crate::append::AppendHandle;
+pub use crate::bitmap::Bitmap;
+pub use crate::blockmap::{BlockMap, BlockRun, IndirectBlock, IndirectPointers};
+pub use crate::check::Inconsistency;
+pub use crate::clock::{Clock, FixedClock, SystemClock};
+pub use crate::cmdline::{
+    expand_alias, quote_word, split_words, valid_alias_name, UnclosedQuote, ALIAS_BUILTINS,
+};
+pub use crate::compare::{ContentDifference, Difference, ImageDiff, MetadataDifference};
+pub use crate::dedup::{DuplicateFile, DuplicateGroup};
+pub use crate::defrag::DefragReport;
+pub use crate::device::{device_partitions, BlockDevice, Device, FileDevice};
+use crate::device::{BlockBuf, DeviceBlocks, PartitionDevice};
+pub use crate::dirindex::DirIndex;
+pub use crate::du::{DuCache, SubtreeUsage};
+pub use crate::error::{Ext2Error, Result};
+#[cfg(feature = "fuse")]
+pub use crate::fuse::FuseMount;
+pub use crate::handle::FileReader;
+use crate::inodecache::InodeCache;
+pub use crate::inodeno::InodeNo;
+pub use crate::journal::JournalInfo;
+pub use crate::mkfs::{mkfs, MkfsOptions};
+pub use crate::owned::{GroupDescriptorOwned, InodeOwned, SuperblockOwned};
+pub use crate::partition::{partitions, OffsetDevice, Partition, PartitionType};
+pub use crate::pathcache::PathCache;
+pub use crate::populate::PopulateSummary;
+pub use crate::progress::{NoProgress, Progress};
+pub use crate::report::{EntryInfo, FsInfo, GroupInfo, InodeInfo, SpaceInfo, WithPath};
+use crate::reserved::BAD_BLOCKS_INODE;
+pub use crate::reserved::{ReservedInode, ReservedInodeUse, RESERVED_INODES};
+pub use crate::snapshot::Snapshot;
+pub use crate::stats::{FsStats, TypeStats};
+use crate::structs::{BlockGroupDescriptor, FileType, Inode, Superblock};
+pub use crate::top::RankedFile;
+pub use crate::undelete::DeletedInode;
+pub use crate::usage::{
+    Extent, FragReport, FreeRunBucket, FreeSpaceReport, GroupFreeSpace, GroupUsage,
+};
+pub use crate::walk::{WalkControl, WalkEntry, WalkOptions, WalkOrder};
+pub use crate::xattr::decode_posix_acl;
+use log::{debug, warn};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::OsStr;
+use std::fmt;
+use std::io::Write;
+use std::mem;
+use std::ops::Range;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use uuid::Uuid;
+
+/// An ext2 filesystem on a `BlockDevice`, e.g. one held in memory.
+///
+/// # Sharing between threads
+///
+/// `Ext2` is `Send` and `Sync`. Everything that only reads (lookups,
+/// listings, `file_chunks`, `check`, ...) takes `&self`, so a `&Ext2` can be
+/// handed to any number of threads at once, e.g. by `walk_parallel`.
+/// Everything that modifies takes `&mut self`, so while a change is being made
+/// nothing else can be reading, and there's no locking inside beyond the list
+/// of warnings `Strictness::Lenient` reads collect and the blocks read from a
+/// device that isn't in memory. Changes live in the dirty-block layer until
+/// `sync`, so readers see them as soon as the `&mut` borrow ends. Opening
+/// with `Ext2Options::read_only` makes every modification fail with
+/// `Ext2Error::ReadOnly` too.
+#[repr(C)]
+#[derive(Debug)]
+pub struct Ext2 {
+    // in-memory copies of the superblock and descriptor table, written back to
+    // the device's blocks by `write_metadata`
+    pub superblock: Superblock,
+    pub block_groups: Vec<BlockGroupDescriptor>,
+    // the whole device, never written to; block numbers count from its
+    // start, the boot block and superblock included, whatever
+    // `first_data_block` is
+    device: DeviceBlocks,
+    pub block_size: usize,
+    pub uuid: Uuid,
+    // modified copies of blocks, by block number; the device itself is never
+    // written, these shadow it until `sync` writes them out; shared with
+    // any `Snapshot` taken since they were last modified
+    dirty: BTreeMap<usize, Arc<BlockBuf>>,
+    // bumped every time a block is handed out for writing, so caches of what's
+    // on disk, like `DirIndex`, can tell they might be stale
+    generation: u64,
+    // the generation each dirty block was last handed out for writing at, so
+    // `sync` can write them in the order they were modified
+    modified: HashMap<usize, u64>,
+    // the dirty blocks as `sync` last wrote them, so the next one only
+    // writes those that have changed since; shared like a `Snapshot`'s
+    synced: HashMap<usize, Arc<BlockBuf>>,
+    // every mutating operation fails with `Ext2Error::ReadOnly` when set
+    read_only: bool,
+    // why the filesystem was opened read-only even though that wasn't asked for
+    forced_read_only: Option<String>,
+    // where every timestamp written comes from
+    clock: Arc<dyn Clock>,
+    // leave atime alone when files are read
+    noatime: bool,
+    // who allocations are made for, which decides whether the blocks
+    // reserved for root may be used
+    cred: Credentials,
+    // whether reads fail at inconsistencies or work around them
+    strictness: Strictness,
+    // what lenient reads worked around, for `warnings`; behind a lock since
+    // they're recorded by readers, which only have `&self`
+    warnings: Mutex<Vec<String>>,
+    // how many `FileReader`s are open on each inode, which keeps a removed
+    // file's inode and blocks around until the last is closed
+    open_files: HashMap<usize, usize>,
+    // whether `lookup` falls back to names that differ only by case
+    case_insensitive: bool,
+    // how many blocks sequential reads fetch from the device at once
+    readahead: usize,
+    // copies of inodes in table blocks that haven't been modified, for a
+    // device that isn't in memory
+    inodes: InodeCache,
+    // how many directories `lookup` has searched, so what a cache of lookups
+    // like `PathCache` saves can be seen; counted with only `&self`
+    lookups: AtomicU64,
+}
+
+// keep the guarantees above: this stops compiling if a field ever makes
+// `Ext2` lose `Send` or `Sync`
+const _: () = {
+    fn assert_send_sync<T: Send + Sync>() {}
+    let _ = assert_send_sync::<Ext2>;
+};
+
+/// How to open a filesystem, for the knobs `Ext2::new` doesn't have, e.g.
+/// `Ext2::options().read_only(true).open_path("disk.img")`. Options that
+/// don't go together fail the open with `Ext2Error::BadOptions`.
+#[derive(Debug, Clone)]
+pub struct Ext2Options {
+    /// Refuse every modification, so the image can't change even by `sync`.
+    pub read_only: bool,
+    /// Don't update a file's access time when it's read, which would
+    /// otherwise dirty its inode's block on every read.
+    pub noatime: bool,
+    /// Where timestamps come from; the system time unless replaced, e.g. by a
+    /// `FixedClock` to get the same image on every run.
+    pub clock: Arc<dyn Clock>,
+    /// What reads do about inconsistencies in the image.
+    pub strictness: Strictness,
+    /// Have `lookup`, and so every path, find a name that differs only by
+    /// case when there's no exact match, e.g. `README.TXT` for `readme.txt`.
+    pub case_insensitive_lookup: bool,
+    /// Which partition of a disk image to open, by its number in the
+    /// partition table; the whole image if `None`.
+    pub partition: Option<usize>,
+    /// How many blocks a sequential read fetches from the device at once,
+    /// when they follow each other on it; 0 reads a block at a time. Only
+    /// devices that aren't in memory, like a `FileDevice`, are read ahead.
+    pub readahead_blocks: usize,
+}
+
+impl Default for Ext2Options {
+    fn default() -> Ext2Options {
+        Ext2Options {
+            read_only: false,
+            noatime: false,
+            clock: Arc::new(SystemClock),
+            strictness: Strictness::Strict,
+            case_insensitive_lookup: false,
+            partition: None,
+            readahead_blocks: 0,
+        }
+    }
+}
+
+/// What reads do about inconsistencies in what they read, e.g. a block
+/// pointer past the end of the filesystem or a directory entry that runs
+/// off its block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Strictness {
+    /// Fail with an error at the first one.
+    #[default]
+    Strict,
+    /// Log it, add it to `Ext2::warnings`, and make do with what can be read:
+    /// an unreadable block of a file reads as zeros, an unreadable directory
+    /// block holds no entries, and a directory block's entries stop at the
+    /// first bad one. For getting what's left off a damaged image, so best
+    /// opened `read_only` too: changes made on the strength of half-read
+    /// directories can only make the damage worse.
+    Lenient,
+}
+
+impl Ext2Options {
+    pub fn new() -> Ext2Options {
+        Ext2Options::default()
+    }
+
+    pub fn read_only(mut self, read_only: bool) -> Ext2Options {
+        self.read_only = read_only;
+        self
+    }
+
+    pub fn noatime(mut self, noatime: bool) -> Ext2Options {
+        self.noatime = noatime;
+        self
+    }
+
+    pub fn clock(mut self, clock: impl Clock + 'static) -> Ext2Options {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    pub fn strictness(mut self, strictness: Strictness) -> Ext2Options {
+        self.strictness = strictness;
+        self
+    }
+
+    pub fn case_insensitive_lookup(mut self, case_insensitive: bool) -> Ext2Options {
+        self.case_insensitive_lookup = case_insensitive;
+        self
+    }
+
+    pub fn partition(mut self, number: usize) -> Ext2Options {
+        self.partition = Some(number);
+        self
+    }
+
+    pub fn readahead_blocks(mut self, blocks: usize) -> Ext2Options {
+        self.readahead_blocks = blocks;
+        self
+    }
+
+    /// Open the filesystem on `device`, like `Ext2::new`.
+    pub fn open(&self, device: impl AsRef<[u8]> + Send + Sync + 'static) -> Result<Ext2> {
+        self.open_device(Device::new(device))
+    }
+
+    /// Open the filesystem in a copy of `bytes`, so they can go as soon as
+    /// this returns.
+    pub fn open_bytes(&self, bytes: &[u8]) -> Result<Ext2> {
+        self.open_device(Device::copy(bytes))
+    }
+
+    /// Open the filesystem in the image file at `path`, reading its blocks
+    /// as they're needed through a `FileDevice`.
+    pub fn open_path(&self, path: impl AsRef<Path>) -> Result<Ext2> {
+        self.open_device(FileDevice::open(path)?)
+    }
+
+    /// Open the filesystem on `device`, or on the partition of it given by
+    /// `partition`.
+    pub fn open_device(&self, device: impl BlockDevice + 'static) -> Result<Ext2> {
+        self.check()?;
+        Ext2::with_superblock_at(self.partition_of(device)?, EXT2_START_OF_SUPERBLOCK, self)
+    }
+
+    // `device`, or the partition of it given by `partition`
+    fn partition_of(&self, device: impl BlockDevice + 'static) -> Result<Box<dyn BlockDevice>> {
+        Ok(match self.partition {
+            Some(number) => Box::new(PartitionDevice::new(device, number)?),
+            None => Box::new(device),
+        })
+    }
+
+    // fail with `BadOptions` if the options don't go together
+    fn check(&self) -> Result<()> {
+        // changes made on the strength of half-read directories can only
+        // make the damage worse
+        if self.strictness == Strictness::Lenient && !self.read_only {
+            return Err(Ext2Error::BadOptions {
+                reason: String::from("lenient reads are only for opening read-only"),
+            });
+        }
+        Ok(())
+    }
+
+    /// Open the filesystem from a backup superblock, like `Ext2::from_backup`;
+    /// with `partition`, `superblock_offset` counts from the partition's
+    /// start.
+    pub fn open_backup(
+        &self,
+        device: impl AsRef<[u8]> + Send + Sync + 'static,
+        superblock_offset: usize,
+    ) -> Result<Ext2> {
+        self.check()?;
+        let device = self.partition_of(Device::new(device))?;
+        let mut ext2 = Ext2::with_superblock_at(device, superblock_offset, self)?;
+        // the backup records which group it's in, the primary belongs to group 0
+        ext2.superblock.block_group = 0;
+        Ok(ext2)
+    }
+}
+
+const EXT2_MAGIC: u16 = 0xef53;
+const EXT2_STATE_CLEAN: u16 = 1;
+const EXT2_START_OF_SUPERBLOCK: usize = 1024;
+const EXT2_END_OF_SUPERBLOCK: usize = 2048;
+const EXT2_SUPERBLOCK_SIZE: usize = EXT2_END_OF_SUPERBLOCK - EXT2_START_OF_SUPERBLOCK;
+
+impl Ext2 {
+    /// How to open a filesystem, to set before opening it, e.g.
+    /// `Ext2::options().noatime(true).open_path(path)`.
+    pub fn options() -> Ext2Options {
+        Ext2Options::new()
+    }
+
+    /// Open the filesystem on `device`, e.g. a `Vec<u8>` or a built-in
+    /// `&'static [u8]`, which the `Ext2` then owns. Fails with
+    /// `Ext2Error::BadSuperblock` if it doesn't hold an ext2 filesystem this
+    /// can make sense of; anything else wrong is only found when it's read.
+    pub fn new(device: impl AsRef<[u8]> + Send + Sync + 'static) -> Result<Ext2> {
+        Ext2Options::new().open(device)
+    }
+
+    // like `new`, but take the superblock, and the block group descriptor table
+    // that follows it, from the backup copy at byte `superblock_offset` of the
+    // device (see `find_backup_superblock`)
+    // `sync` then writes them back over the primary copies
+    pub fn from_backup(
+        device: impl AsRef<[u8]> + Send + Sync + 'static,
+        superblock_offset: usize,
+    ) -> Result<Ext2> {
+        Ext2Options::new().open_backup(device, superblock_offset)
+    }
+
+    fn with_superblock_at(
+        device: Box<dyn BlockDevice>,
+        superblock_offset: usize,
+        options: &Ext2Options,
+    ) -> Result<Ext2> {
+        // https://wiki.osdev.org/Ext2#Superblock
+
+        let bad = |reason: &str| Ext2Error::BadSuperblock {
+            reason: reason.to_string(),
+        };
+        let device_len = device.size() as usize;
+        if device_len < EXT2_END_OF_SUPERBLOCK.max(superblock_offset + EXT2_SUPERBLOCK_SIZE) {
+            return Err(bad("the device is too short to hold one"));
+        }
+        if superblock_offset % EXT2_SUPERBLOCK_SIZE != 0 {
+            return Err(bad("it isn't on a 1 KiB boundary"));
+        }
+
+        // the superblock goes from bytes 1024 -> 2047; `BlockBuf` keeps the
+        // bytes aligned, so it can be cast out of them
+        let mut superblock_buf = BlockBuf::zeroed(EXT2_SUPERBLOCK_SIZE);
+        device.read_blocks(
+            (superblock_offset / EXT2_SUPERBLOCK_SIZE) as u64,
+            EXT2_SUPERBLOCK_SIZE,
+            &mut superblock_buf,
+        )?;
+        let superblock = unsafe { &*(superblock_buf.as_ptr() as *const Superblock) };
+        if superblock.magic != EXT2_MAGIC {
+            return Err(bad("no ext2 magic number"));
+        }
+        // at this point, we strongly suspect these bytes are indeed an ext2
+        // filesystem; check the geometry everything else is found by
+        if superblock.log_block_size > 6 {
+            return Err(bad("block size over 64 KiB"));
+        }
+        // not sure about the unit of block_size, bits or bytes?
+        let block_size: usize = 1024 << superblock.log_block_size;
+        let bitmap_bits = block_size as u32 * 8;
+        if superblock.blocks_per_group == 0 || superblock.blocks_per_group > bitmap_bits {
+            return Err(bad("blocks per group don't fit a bitmap block"));
+        }
+        if superblock.inodes_per_group == 0 || superblock.inodes_per_group > bitmap_bits {
+            return Err(bad("inodes per group don't fit a bitmap block"));
+        }
+        if superblock.first_data_block >= superblock.blocks_count {
+            return Err(bad("the first data block is past the end"));
+        }
+
+        // inodes are read through `Inode`, so a slot must hold one, and a
+        // block a whole number of slots
+        let inode_size = InodeNo::slot_size(superblock);
+        if inode_size < mem::size_of::<Inode>()
+            || !inode_size.is_power_of_two()
+            || inode_size > block_size
+        {
+            return Err(bad(&format!("inode size {} is invalid", inode_size)));
+        }
+        debug!("inode size: {}", inode_size);
+
+        // group 0 starts at first_data_block, so that many blocks are in none
+        let block_group_count = (superblock.blocks_count - superblock.first_data_block)
+            .div_ceil(superblock.blocks_per_group) as usize;
+        if superblock.inodes_count as u64
+            > superblock.inodes_per_group as u64 * block_group_count as u64
+        {
+            return Err(bad("more inodes than the groups hold"));
+        }
+        debug!(
+            "there are {} block groups and block_size = {}",
+            block_group_count, block_size
+        );
+        // the descriptor table starts in the block after the superblock's
+        let table_start = (superblock_offset / block_size + 1) * block_size;
+        let table_len = block_group_count * mem::size_of::<BlockGroupDescriptor>();
+        if table_start + table_len > device_len {
+            return Err(bad(
+                "the block group descriptors are past the end of the device",
+            ));
+        }
+        let mut table = BlockBuf::zeroed(table_len);
+        device.read_blocks(
+            (table_start / block_size) as u64,
+            block_size,
+            &mut table[..table_len],
+        )?;
+        let block_groups = unsafe {
+            std::slice::from_raw_parts(
+                table.as_ptr() as *const BlockGroupDescriptor,
+                block_group_count,
+            )
+        }
+        .to_vec();
+
+        debug!("block group 0: {:?}", block_groups[0]);
+        let uuid = Uuid::from_bytes(superblock.fs_id);
+
+        // don't write to what we can't fully understand, or what wasn't
+        // unmounted cleanly and may be mid-change
+        let unknown_ro_compat = superblock.features_ronly & !structs::FeatureRoCompat::all().bits();
+        let forced_read_only = if unknown_ro_compat != 0 {
+            Some(format!(
+                "unknown read-only features {:#x}",
+                unknown_ro_compat
+            ))
+        } else if superblock.features_opt & structs::FeatureCompat::HAS_JOURNAL.bits() != 0 {
+            // writing around the journal would leave it to replay stale
+            // metadata over ours the next time the filesystem is mounted
+            Some(String::from("the filesystem has an ext3 journal"))
+        } else if superblock.state != EXT2_STATE_CLEAN {
+            Some(String::from("the filesystem was not cleanly unmounted"))
+        } else {
+            None
+        };
+        if let Some(reason) = &forced_read_only {
+            if !options.read_only {
+                warn!("opening read-only: {}", reason);
+            }
+        }
+        let mut superblock = superblock.clone();
+        let read_only = options.read_only || forced_read_only.is_some();
+        if !read_only {
+            // mounting: count it, and mark the filesystem in use until `sync`
+            // writes it out consistent again
+            superblock.mnt_count = superblock.mnt_count.wrapping_add(1);
+            superblock.mtime = options.clock.now();
+            superblock.state &= !EXT2_STATE_CLEAN;
+        }
+        let inodes = if device.bytes().is_some() {
+            InodeCache::disabled()
+        } else {
+            InodeCache::new(superblock.inodes_count as usize)
+        };
+        Ok(Ext2 {
+            superblock,
+            block_groups,
+            device: DeviceBlocks::new(device, block_size),
+            block_size,
+            uuid,
+            dirty: BTreeMap::new(),
+            generation: 0,
+            modified: HashMap::new(),
+            synced: HashMap::new(),
+            read_only,
+            clock: options.clock.clone(),
+            noatime: options.noatime,
+            cred: Credentials::ROOT,
+            strictness: options.strictness,
+            warnings: Mutex::new(Vec::new()),
+            open_files: HashMap::new(),
+            case_insensitive: options.case_insensitive_lookup,
+            readahead: options.readahead_blocks,
+            inodes,
+            lookups: AtomicU64::new(0),
+            forced_read_only: forced_read_only.filter(|_| !options.read_only),
+        })
+    }
+
+    // given a (1-indexed) inode number, return that #'s inode structure
+    // the inode number is a unique identifier among the entire filesystem
+    // an unmodified inode looked up before comes from the inode cache; a
+    // modified one always comes from its table block in the dirty-block
+    // layer, which is the one copy `inode_mut` changes
+    pub fn get_inode(&self, inode: usize) -> Result<&Inode> {
+        if let Some(record) = self.inodes.get(inode) {
+            return Ok(record);
+        }
+        let (block_num, offset) = self.inode_location(inode)?;
+        let inode_bytes = &self.block(block_num)?[offset..];
+        let record = unsafe { &*(inode_bytes.as_ptr() as *const Inode) };
+        if self.dirty.contains_key(&block_num) {
+            return Ok(record);
+        }
+        Ok(self.inodes.insert(inode, record).unwrap_or(record))
+    }
+
+    // how many inodes the inode cache holds
+    pub fn cached_inodes(&self) -> usize {
+        self.inodes.len()
+    }
+
+    // like `get_inode`, but for modifying the inode through the dirty-block layer
+    pub fn inode_mut(&mut self, inode: usize) -> Result<&mut Inode> {
+        let (block_num, offset) = self.inode_location(inode)?;
+        let inode_bytes = &mut self.block_mut(block_num)?[offset..];
+        Ok(unsafe { &mut *(inode_bytes.as_mut_ptr() as *mut Inode) })
+    }
+
+    // the `lsattr` flags of `inode`
+    pub fn inode_flags(&self, inode: usize) -> Result<structs::InodeFlags> {
+        Ok(structs::InodeFlags::from_bits_truncate(
+            self.get_inode(inode)?.flags,
+        ))
+    }
+
+    // the inodes in block `block_num`, if it's part of an inode table
+    fn inodes_in_block(&self, block_num: usize) -> Option<Range<usize>> {
+        let inodes_per_group = self.superblock.inodes_per_group as usize;
+        let per_block = self.block_size / InodeNo::slot_size(&self.superblock);
+        let table_blocks = inodes_per_group.div_ceil(per_block);
+        self.block_groups
+            .iter()
+            .enumerate()
+            .find_map(|(group, descriptor)| {
+                let table = descriptor.inode_table_block as usize;
+                let index = block_num.checked_sub(table)?;
+                (index < table_blocks).then(|| {
+                    let first = group * inodes_per_group + index * per_block + 1;
+                    first..first + per_block
+                })
+            })
+    }
+
+    // given a (1-indexed) inode number, find the inode table block holding it
+    // and the byte offset of the inode within that block
+    fn inode_location(&self, inode: usize) -> Result<(usize, usize)> {
+        // find the block group that contains the inode, and its index there
+        let (group, index) = InodeNo(inode).to_group_and_index(&self.superblock)?;
+
+        let inode_table_block = self.block_groups[group].inode_table_block as usize;
+        // the inode table spans several blocks, find the one holding our inode
+        let byte_offset = index * InodeNo::slot_size(&self.superblock);
+        Ok((
+            inode_table_block + byte_offset / self.block_size,
+            byte_offset % self.block_size,
+        ))
+    }
+
+    // given a (1-indexed) inode number, check its bit in the inode usage bitmap
+    // of the block group it belongs to
+    pub fn inode_is_allocated(&self, inode: usize) -> Result<bool> {
+        let (group, index) = InodeNo(inode).to_group_and_index(&self.superblock)?;
+        Ok(self.inode_bitmap(group)?.is_set(index))
+    }
+
+    /// Every allocated inode, as (inode number, inode), in order, found from
+    /// the inode bitmaps. The reserved inodes below `first_usable_inode`
+    /// are left out, except the root directory. Groups whose bitmap or inode
+    /// table can't be read are skipped over; `check` reports those.
+    pub fn inodes(&self) -> impl Iterator<Item = (usize, &Inode)> + '_ {
+        let inodes_per_group = self.superblock.inodes_per_group as usize;
+        let sb = &self.superblock;
+        (0..self.block_groups.len())
+            .filter_map(move |group| Some((group, self.inode_bitmap(group).ok()?)))
+            .flat_map(move |(group, bitmap)| {
+                (0..inodes_per_group)
+                    .filter(move |&index| bitmap.is_set(index))
+                    .filter_map(move |index| InodeNo::from_group_and_index(group, index, sb))
+            })
+            .filter(move |&inode| inode == InodeNo::ROOT || !inode.is_reserved(sb))
+            .filter_map(move |InodeNo(inode)| Some((inode, self.get_inode(inode).ok()?)))
+    }
+
+    // the inode usage bitmap of block group `group`: bit n is inode
+    // group * inodes_per_group + n + 1
+    pub fn inode_bitmap(&self, group: usize) -> Result<Bitmap<&[u8]>> {
+        let block_num = self.block_groups[group].inode_usage_addr as usize;
+        let len = self.superblock.inodes_per_group as usize;
+        Ok(Bitmap::new(self.block(block_num)?, len))
+    }
+
+    // the block usage bitmap of block group `group`: bit n is the group's
+    // n'th block, counting from `group_first_block`
+    pub fn block_bitmap(&self, group: usize) -> Result<Bitmap<&[u8]>> {
+        let block_num = self.block_groups[group].block_usage_addr as usize;
+        Ok(Bitmap::new(
+            self.block(block_num)?,
+            self.group_blocks_count(group),
+        ))
+    }
+
+    // the first block of block group `group`
+    pub fn group_first_block(&self, group: usize) -> usize {
+        self.superblock.first_data_block as usize
+            + group * self.superblock.blocks_per_group as usize
+    }
+
+    // the number of blocks in block group `group`; the last one may be short
+    pub fn group_blocks_count(&self, group: usize) -> usize {
+        let first = self.group_first_block(group);
+        (first + self.superblock.blocks_per_group as usize)
+            .min(self.superblock.blocks_count as usize)
+            - first
+    }
+
+    // given a (1-indexed) inode number, return the entries of that directory,
+    // in the order they're stored, `.` and `..` included
+    pub fn read_dir_inode(&self, inode: usize) -> Result<Vec<DirEntry>> {
+        let file_types = structs::FeatureIncompat::from_bits_truncate(self.superblock.features_req)
+            .contains(structs::FeatureIncompat::FILETYPE);
+        Ok(self
+            .read_dir_raw(inode)?
+            .into_iter()
+            .map(|entry| DirEntry {
+                inode: entry.inode,
+                name: entry.name.0.to_vec(),
+                file_type: file_types
+                    .then(|| FileType::from_indicator(entry.file_type))
+                    .flatten(),
+            })
+            .collect())
+    }
+
+    // like `read_dir_inode`, but without copying: the names point into the
+    // directory's blocks, and each entry comes with its slot's offset and
+    // size and its raw type indicator byte
+    pub fn read_dir_raw(&self, inode: usize) -> Result<Vec<RawDirEntry<'_>>> {
+        let mut ret = Vec::new();
+        // walk the directory's data blocks in order, through direct and indirect pointers alike
+        let blocks = self
+            .file_blocks(inode)
+            .map_err(|e| e.in_inode("reading", inode))?;
+        // a directory has no business being sparse, let alone this sparse; it
+        // would take forever to find out there's nothing in it
+        if blocks.count > self.superblock.blocks_count as usize {
+            return Err(Ext2Error::CorruptDirectory {
+                inode,
+                block: 0,
+                offset: 0,
+                reason: String::from("bigger than the filesystem"),
+            });
+        }
+        for block_num in blocks {
+            let block_num = match block_num {
+                Ok(block_num) => block_num,
+                Err(err) => {
+                    let err = err.in_inode("reading", inode);
+                    if self.tolerate(&err) {
+                        continue;
+                    }
+                    return Err(err);
+                }
+            };
+            if block_num == 0 {
+                // a hole in a directory holds no entries
+                continue;
+            }
+            let block = match self.block(block_num) {
+                Ok(block) => block,
+                Err(err) => {
+                    let err = err.in_inode("reading", inode);
+                    if self.tolerate(&err) {
+                        continue;
+                    }
+                    return Err(err);
+                }
+            };
+            match dir_block_entries(block) {
+                Ok(entries) => ret.extend(entries),
+                Err((offset, reason)) => {
+                    let err = Ext2Error::CorruptDirectory {
+                        inode,
+                        block: block_num,
+                        offset,
+                        reason,
+                    };
+                    if !self.tolerate(&err) {
+                        return Err(err);
+                    }
+                    // the entries in front of the bad one parse on their own
+                    ret.extend(dir_block_entries(&block[..offset]).unwrap_or_default());
+                }
+            }
+        }
+        Ok(ret)
+    }
+
+    // given a (1-indexed) inode number, return the contents of that file
+    pub fn read_file_inode(&self, inode: usize) -> Result<Vec<u8>> {
+        let mut ret = Vec::new();
+        for chunk in self.file_chunks(inode)? {
+            ret.extend_from_slice(&chunk?);
+        }
+        Ok(ret)
+    }
+
+    // given a (1-indexed) inode number of a symlink, return its target: kept in
+    // the block pointers themselves when it's under 60 bytes, and in its one
+    // data block otherwise
+    // a size past a block can only be corruption, and is refused rather than
+    // read, since that would allocate whatever the inode claims
+    pub fn read_link(&self, inode: usize) -> Result<Vec<u8>> {
+        let record = self
+            .get_inode(inode)
+            .map_err(|e| e.in_inode("reading", inode))?;
+        let size = record.size();
+        if size > self.block_size as u64 {
+            return Err(Ext2Error::CorruptInode {
+                inode,
+                reason: format!(
+                    "symlink of {} bytes is longer than a block ({} bytes)",
+                    size, self.block_size
+                ),
+            });
+        }
+        if size >= 60 {
+            let mut target = vec![0; size as usize];
+            let read = self.read_at(inode, 0, &mut target)?;
+            target.truncate(read);
+            return Ok(target);
+        }
+        let mut target = Vec::with_capacity(60);
+        let pointers = record.direct_pointer.iter().chain([
+            &record.indirect_pointer,
+            &record.doubly_indirect,
+            &record.triply_indirect,
+        ]);
+        for pointer in pointers {
+            target.extend_from_slice(&pointer.to_le_bytes());
+        }
+        target.truncate(record.size() as usize);
+        Ok(target)
+    }
+
+    // given a (1-indexed) inode number, iterate over its contents a block at a
+    // time, borrowed straight from the dirty-block layer or a device in memory
+    // and copied from any other (see `FileChunks`): holes come out as a shared
+    // block of zeros and the last block stops at the file size. Device nodes,
+    // FIFOs, sockets and fast symlinks have no data blocks and yield nothing.
+    pub fn file_chunks(&self, inode: usize) -> Result<FileChunks<'_>> {
+        let record = self
+            .get_inode(inode)
+            .map_err(|e| e.in_inode("reading", inode))?;
+        let is_fast_symlink = record.is_symlink() && record.size() < 60;
+        let mut blocks = self
+            .file_blocks(inode)
+            .map_err(|e| e.in_inode("reading", inode))?;
+        if record.is_special() || is_fast_symlink {
+            blocks.count = 0;
+        }
+        let size = if record.is_dir() {
+            // size_high is the directory ACL for directories
+            record.size_low as u64
+        } else {
+            record.size()
+        };
+        Ok(FileChunks {
+            blocks,
+            inode,
+            remaining: size,
+        })
+    }
+
+    // write the contents of `inode` to `out` a block at a time, using
+    // `file_chunks`, so nothing the size of the file is ever allocated.
+    // Returns how many bytes were written.
+    pub fn copy_file_to<W: Write>(&self, inode: usize, out: &mut W) -> Result<u64> {
+        self.copy_file_to_with_progress(inode, out, &NoProgress)
+    }
+
+    // `copy_file_to`, telling `progress` how many bytes are written so far
+    // out of the file's size, and stopping between blocks with
+    // `Ext2Error::Cancelled` if it asks to
+    pub fn copy_file_to_with_progress<W: Write>(
+        &self,
+        inode: usize,
+        out: &mut W,
+        progress: &dyn Progress,
+    ) -> Result<u64> {
+        let size = self.get_inode(inode)?.size();
+        let mut written = 0;
+        for chunk in self.file_chunks(inode)? {
+            progress::check_cancel(progress)?;
+            let chunk = chunk?;
+            out.write_all(&chunk)?;
+            written += chunk.len() as u64;
+            progress.on_bytes(written, Some(size));
+        }
+        Ok(written)
+    }
+
+    // read the contents of `inode` from byte `offset` into `buf`, returning how
+    // many bytes that was: as many as fit, less at the end of the file and
+    // none past it. Only the blocks the range covers are looked at, and holes
+    // read as zeros. Like `file_chunks`, device nodes, FIFOs, sockets and fast
+    // symlinks have no contents to read.
+    pub fn read_at(&self, inode: usize, offset: u64, buf: &mut [u8]) -> Result<usize> {
+        self.read_at_sequential(inode, offset, buf, false)
+    }
+
+    // `read_at`, reading ahead (see `read_ahead`) if `sequential`, i.e. the
+    // read carries on from where the last one of the file ended
+    pub(crate) fn read_at_sequential(
+        &self,
+        inode: usize,
+        offset: u64,
+        buf: &mut [u8],
+        sequential: bool,
+    ) -> Result<usize> {
+        let record = self
+            .get_inode(inode)
+            .map_err(|e| e.in_inode("reading", inode))?;
+        let is_fast_symlink = record.is_symlink() && record.size() < 60;
+        if record.is_special() || is_fast_symlink {
+            return Ok(0);
+        }
+        let size = if record.is_dir() {
+            record.size_low as u64
+        } else {
+            record.size()
+        };
+        if offset >= size {
+            return Ok(0);
+        }
+        let len = (buf.len() as u64).min(size - offset) as usize;
+        let block_size = self.block_size as u64;
+        let mut blocks = self
+            .file_blocks(inode)
+            .map_err(|e| e.in_inode("reading", inode))?;
+        let mut block_num = blocks.nth((offset / block_size) as usize);
+        let mut within = (offset % block_size) as usize;
+        let mut done = 0;
+        while done < len {
+            let block = match block_num.transpose().and_then(|block_num| match block_num {
+                Some(0) | None => Ok(Cow::Borrowed(&ZERO_BLOCK[..self.block_size])),
+                Some(block_num) => {
+                    if sequential {
+                        self.read_ahead(block_num, &blocks);
+                    }
+                    self.data_block(block_num)
+                }
+            }) {
+                Ok(block) => block,
+                Err(err) => {
+                    let err = err.in_inode("reading", inode);
+                    if !self.tolerate(&err) {
+                        return Err(err);
+                    }
+                    Cow::Borrowed(&ZERO_BLOCK[..self.block_size])
+                }
+            };
+            let count = (self.block_size - within).min(len - done);
+            buf[done..done + count].copy_from_slice(&block[within..within + count]);
+            done += count;
+            within = 0;
+            block_num = blocks.next();
+        }
+        Ok(done)
+    }
+
+    // given the inode of the directory a relative path starts from, follow
+    // `path` one component at a time and return the inode it names
+    // absolute paths start from the root (inode 2) instead
+    pub fn resolve_path(&self, cwd: usize, path: &str) -> Result<usize> {
+        let mut current = if path.starts_with('/') { 2 } else { cwd };
+        for component in path.split('/').filter(|c| !c.is_empty()) {
+            current = self.resolve_component(current, component, path)?;
+        }
+        Ok(current)
+    }
+
+    // one step of resolving `path`: look `component` up in directory `dir`
+    pub(crate) fn resolve_component(
+        &self,
+        dir: usize,
+        component: &str,
+        path: &str,
+    ) -> Result<usize> {
+        let record = self.get_inode(dir)?;
+        if !record.is_dir() {
+            return Err(Ext2Error::NotADirectory {
+                name: path.to_string(),
+            });
+        }
+        self.lookup(dir, component)?
+            .ok_or_else(|| Ext2Error::NotFound {
+                name: path.to_string(),
+            })
+    }
+
+    // describe the superblock in labeled sections, followed by a table of the
+    // block groups, in the spirit of dumpe2fs
+    pub fn describe(&self) -> String {
+        use std::fmt::Write;
+        let sb = &self.superblock;
+        let mut out = String::new();
+
+        writeln!(out, "Volume").unwrap();
+        writeln!(out, "  Volume name:          {}", c_string(&sb.volume_name)).unwrap();
+        writeln!(out, "  UUID:                 {}", self.uuid).unwrap();
+        writeln!(
+            out,
+            "  Revision:             {}.{}",
+            sb.rev_major, sb.rev_minor
+        )
+        .unwrap();
+        writeln!(
+            out,
+            "  Creator OS:           {}",
+            report::creator_os(sb.creator_os)
+        )
+        .unwrap();
+        writeln!(
+            out,
+            "  Last mounted on:      {}",
+            c_string(&sb.last_mnt_path)
+        )
+        .unwrap();
+
+        writeln!(out, "Features").unwrap();
+        writeln!(
+            out,
+            "  Optional:             {}",
+            feature_names(
+                structs::FeatureCompat::from_bits_truncate(sb.features_opt),
+                sb.features_opt & !structs::FeatureCompat::all().bits()
+            )
+        )
+        .unwrap();
+        writeln!(
+            out,
+            "  Required:             {}",
+            feature_names(
+                structs::FeatureIncompat::from_bits_truncate(sb.features_req),
+                sb.features_req & !structs::FeatureIncompat::all().bits()
+            )
+        )
+        .unwrap();
+        writeln!(
+            out,
+            "  Read-only:            {}",
+            feature_names(
+                structs::FeatureRoCompat::from_bits_truncate(sb.features_ronly),
+                sb.features_ronly & !structs::FeatureRoCompat::all().bits()
+            )
+        )
+        .unwrap();
+
+        if let Some(journal) = self.journal() {
+            writeln!(out, "Journal").unwrap();
+            match journal.device {
+                Some(device) => writeln!(out, "  Device:               {:#x}", device).unwrap(),
+                None => {
+                    writeln!(out, "  Inode:                {}", journal.inode).unwrap();
+                    writeln!(out, "  Size:                 {}", journal.size).unwrap();
+                }
+            }
+            writeln!(out, "  State:                {}", journal.state()).unwrap();
+        }
+
+        writeln!(out, "Geometry").unwrap();
+        writeln!(out, "  Block size:           {}", self.block_size).unwrap();
+        writeln!(out, "  Fragment size:        {}", 1024 << sb.log_frag_size).unwrap();
+        writeln!(out, "  Inode size:           {}", sb.inode_size).unwrap();
+        writeln!(out, "  Block count:          {}", sb.blocks_count).unwrap();
+        writeln!(out, "  Reserved block count: {}", sb.r_blocks_count).unwrap();
+        writeln!(out, "  Free blocks:          {}", sb.free_blocks_count).unwrap();
+        writeln!(out, "  Inode count:          {}", sb.inodes_count).unwrap();
+        writeln!(out, "  Free inodes:          {}", sb.free_inodes_count).unwrap();
+        writeln!(out, "  First data block:     {}", sb.first_data_block).unwrap();
+        writeln!(out, "  First inode:          {}", sb.first_inode).unwrap();
+        writeln!(out, "  Blocks per group:     {}", sb.blocks_per_group).unwrap();
+        writeln!(out, "  Fragments per group:  {}", sb.frags_per_group).unwrap();
+        writeln!(out, "  Inodes per group:     {}", sb.inodes_per_group).unwrap();
+
+        writeln!(out, "Mounts").unwrap();
+        writeln!(out, "  Mount count:          {}", sb.mnt_count).unwrap();
+        writeln!(out, "  Maximum mount count:  {}", sb.max_mnt_count).unwrap();
+        writeln!(out, "  Last mount time:      {}", format_time(sb.mtime)).unwrap();
+        writeln!(out, "  Last write time:      {}", format_time(sb.wtime)).unwrap();
+        writeln!(out, "  Last checked:         {}", format_time(sb.lastcheck)).unwrap();
+        writeln!(out, "  Check interval:       {} seconds", sb.checkinterval).unwrap();
+        writeln!(
+            out,
+            "  State:                {}",
+            report::fs_state(sb.state)
+        )
+        .unwrap();
+        writeln!(
+            out,
+            "  Errors behavior:      {}",
+            report::errors_behavior(sb.errors)
+        )
+        .unwrap();
+
+        writeln!(out, "Block groups").unwrap();
+        writeln!(
+            out,
+            "  {:>5}  {:>13}  {:>8}  {:>8}  {:>11}  {:>11}  {:>11}  {:>4}",
+            "group",
+            "blocks",
+            "bbitmap",
+            "ibitmap",
+            "inode table",
+            "free blocks",
+            "free inodes",
+            "dirs"
+        )
+        .unwrap();
+        for (i, group) in self.block_groups.iter().enumerate() {
+            let first = sb.first_data_block as usize + i * sb.blocks_per_group as usize;
+            let last = (first + sb.blocks_per_group as usize).min(sb.blocks_count as usize) - 1;
+            writeln!(
+                out,
+                "  {:>5}  {:>13}  {:>8}  {:>8}  {:>11}  {:>11}  {:>11}  {:>4}",
+                i,
+                format!("{}-{}", first, last),
+                group.block_usage_addr,
+                group.inode_usage_addr,
+                group.inode_table_block,
+                group.free_blocks_count,
+                group.free_inodes_count,
+                group.dirs_count
+            )
+            .unwrap();
+        }
+        out
+    }
+
+    // the backup copies of the superblock: (group, block, superblock) for every
+    // group after the first that carries one, see `group_has_superblock`
+    pub fn backup_superblocks(&self) -> Vec<(usize, usize, Result<&Superblock>)> {
+        (1..self.block_groups.len())
+            .filter(|&group| self.group_has_superblock(group))
+            .map(|group| {
+                let block_num = self.superblock.first_data_block as usize
+                    + group * self.superblock.blocks_per_group as usize;
+                let superblock = self
+                    .block(block_num)
+                    .map(|block| unsafe { &*(block.as_ptr() as *const Superblock) });
+                (group, block_num, superblock)
+            })
+            .collect()
+    }
+
+    // compare every backup superblock against the primary, one line each
+    // the free counts and times in backups are only updated by fsck and
+    // resizing, so differences there are reported but expected
+    pub fn describe_backups(&self) -> String {
+        use std::fmt::Write;
+        let sb = &self.superblock;
+        let mut out = String::new();
+        writeln!(out, "  {:>5}  {:>8}  status", "group", "block").unwrap();
+        for (group, block_num, backup) in self.backup_superblocks() {
+            let status = match backup {
+                Err(err) => format!("unreadable: {}", err),
+                Ok(backup) if backup.magic != EXT2_MAGIC => {
+                    format!("bad magic {:#06x}", backup.magic)
+                }
+                Ok(backup) => {
+                    let mut differ = Vec::new();
+                    let mut field = |name: &'static str, same: bool| {
+                        if !same {
+                            differ.push(name);
+                        }
+                    };
+                    field("inodes_count", backup.inodes_count == sb.inodes_count);
+                    field("blocks_count", backup.blocks_count == sb.blocks_count);
+                    field(
+                        "first_data_block",
+                        backup.first_data_block == sb.first_data_block,
+                    );
+                    field("log_block_size", backup.log_block_size == sb.log_block_size);
+                    field(
+                        "blocks_per_group",
+                        backup.blocks_per_group == sb.blocks_per_group,
+                    );
+                    field(
+                        "inodes_per_group",
+                        backup.inodes_per_group == sb.inodes_per_group,
+                    );
+                    field("inode_size", backup.inode_size == sb.inode_size);
+                    field("features_opt", backup.features_opt == sb.features_opt);
+                    field("features_req", backup.features_req == sb.features_req);
+                    field("features_ronly", backup.features_ronly == sb.features_ronly);
+                    field("fs_id", backup.fs_id == sb.fs_id);
+                    field("volume_name", backup.volume_name == sb.volume_name);
+                    field("block_group", backup.block_group as usize == group);
+                    let mut status = if differ.is_empty() {
+                        String::from("ok")
+                    } else {
+                        format!("MISMATCH: {}", differ.join(", "))
+                    };
+                    if backup.free_blocks_count != sb.free_blocks_count
+                        || backup.free_inodes_count != sb.free_inodes_count
+                    {
+                        write!(
+                            status,
+                            " (stale free counts: {} blocks, {} inodes)",
+                            backup.free_blocks_count, backup.free_inodes_count
+                        )
+                        .unwrap();
+                    }
+                    status
+                }
+            };
+            writeln!(out, "  {:>5}  {:>8}  {}", group, block_num, status).unwrap();
+        }
+        out
+    }
+
+    // given a filesystem block number, return that block's bytes
+    // every block access goes through here, so a corrupt block number anywhere
+    // turns into a BlockOutOfRange error rather than a panic or a stray read
+    // blocks modified since opening come from the dirty-block layer instead
+    // of the device
+    pub fn block(&self, block_num: usize) -> Result<&[u8]> {
+        if let Some(block) = self.dirty.get(&block_num) {
+            return Ok(block);
+        }
+        self.device_block(block_num)
+    }
+
+    // given a filesystem block number, return that block's bytes for modifying
+    // the first call copies the block into the dirty-block layer, and every
+    // read after that sees the modified copy
+    pub fn block_mut(&mut self, block_num: usize) -> Result<&mut [u8]> {
+        self.check_writable()?;
+        if self.inodes.is_enabled() && !self.dirty.contains_key(&block_num) {
+            // the inode cache only holds inodes of unmodified blocks
+            if let Some(inodes) = self.inodes_in_block(block_num) {
+                self.inodes.forget(inodes);
+            }
+        }
+        let blocks_count = self.superblock.blocks_count as usize;
+        if !self.dirty.contains_key(&block_num) {
+            let block = self.device.copy(block_num, blocks_count)?;
+            // the device's copy isn't read again while this one's dirty
+            self.device.forget(block_num);
+            self.dirty.insert(block_num, Arc::new(block));
+        } else if block_num >= blocks_count {
+            return Err(Ext2Error::BlockOutOfRange {
+                block: block_num,
+                blocks_count,
+            });
+        }
+        self.generation += 1;
+        self.modified.insert(block_num, self.generation);
+        let block = self.dirty.get_mut(&block_num).unwrap();
+        // a copy of its own if a snapshot still has this one
+        Ok(Arc::make_mut(block).as_mut())
+    }
+
+    // a count that changes whenever anything on the filesystem may have been
+    // modified; equal counts mean nothing has been
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    // how many times `lookup` has searched a directory since opening
+    pub fn lookups(&self) -> u64 {
+        self.lookups.load(Ordering::Relaxed)
+    }
+
+    // whether every modification is refused
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    // why the filesystem was opened read-only without being asked to, if it was
+    pub fn forced_read_only(&self) -> Option<&str> {
+        self.forced_read_only.as_deref()
+    }
+
+    /// What lenient reads have worked around since the filesystem was opened,
+    /// or since the last `take_warnings`, oldest first. Always empty with
+    /// `Strictness::Strict`, where the same problems are errors instead.
+    pub fn warnings(&self) -> Vec<String> {
+        self.warnings
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// Like `warnings`, but also empty the list, so the next call only has
+    /// what's new.
+    pub fn take_warnings(&self) -> Vec<String> {
+        mem::take(&mut *self.warnings.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+
+    // whether to carry on past `err` with best-effort data rather than return
+    // it: only when lenient, and then it's logged and kept as a warning. The
+    // same problem right again, like each block under one broken indirect
+    // pointer, is only kept once
+    pub(crate) fn tolerate(&self, err: &Ext2Error) -> bool {
+        if self.strictness == Strictness::Strict {
+            return false;
+        }
+        let message = err.to_string();
+        let mut warnings = self.warnings.lock().unwrap_or_else(|e| e.into_inner());
+        if warnings.last() != Some(&message) {
+            warn!("{}", message);
+            warnings.push(message);
+        }
+        true
+    }
+
+    // who allocations are made for
+    pub fn credentials(&self) -> Credentials {
+        self.cred
+    }
+
+    // make allocations for `cred` from now on; only root, or the user or group
+    // the superblock names, may allocate the reserved blocks
+    pub fn set_credentials(&mut self, cred: Credentials) {
+        self.cred = cred;
+    }
+
+    // whether names are looked up case-insensitively when there's no exact
+    // match (see `Ext2Options::case_insensitive_lookup`)
+    pub fn case_insensitive_lookup(&self) -> bool {
+        self.case_insensitive
+    }
+
+    // turn case-insensitive lookups on or off from now on
+    pub fn set_case_insensitive_lookup(&mut self, case_insensitive: bool) {
+        self.case_insensitive = case_insensitive;
+        // what a name finds may change with it, so caches of lookups, like
+        // `PathCache`, have to start again
+        self.generation += 1;
+    }
+
+    // whether reading a file leaves its access time alone (see
+    // `Ext2Options::noatime`)
+    pub fn noatime(&self) -> bool {
+        self.noatime
+    }
+
+    pub fn set_noatime(&mut self, noatime: bool) {
+        self.noatime = noatime;
+    }
+
+    // how many blocks sequential reads fetch at once (see
+    // `Ext2Options::readahead_blocks`)
+    pub fn readahead_blocks(&self) -> usize {
+        self.readahead
+    }
+
+    pub fn set_readahead_blocks(&mut self, blocks: usize) {
+        self.readahead = blocks;
+    }
+
+    // what reads do about inconsistencies (see `Ext2Options::strictness`)
+    pub fn strictness(&self) -> Strictness {
+        self.strictness
+    }
+
+    // change what reads do about inconsistencies from now on; lenient reads
+    // fail with `BadOptions` unless the filesystem is read-only, as when
+    // opening it
+    pub fn set_strictness(&mut self, strictness: Strictness) -> Result<()> {
+        if strictness == Strictness::Lenient && !self.read_only {
+            return Err(Ext2Error::BadOptions {
+                reason: String::from("lenient reads are only for opening read-only"),
+            });
+        }
+        self.strictness = strictness;
+        // a read that failed before may find something now, or the other
+        // way round
+        self.generation += 1;
+        Ok(())
+    }
+
+    // whether the current credentials may allocate the reserved blocks
+    pub fn can_use_reserved(&self) -> bool {
+        self.cred.uid == 0
+            || self.cred.uid == self.superblock.block_uid as u32
+            || self.cred.gid == self.superblock.block_gid as u32
+    }
+
+    // how many blocks the current credentials can still allocate
+    pub fn available_blocks(&self) -> u32 {
+        let free = self.superblock.free_blocks_count;
+        if self.can_use_reserved() {
+            free
+        } else {
+            free.saturating_sub(self.superblock.r_blocks_count)
+        }
+    }
+
+    // the first inode number files may use; the ones below are reserved for
+    // the bad blocks list, the root directory, the journal and so on. Never
+    // less than 11, whatever the superblock says
+    pub fn first_usable_inode(&self) -> usize {
+        InodeNo::first_usable(&self.superblock).0
+    }
+
+    // fail with `Ext2Error::ReadOnly` if modifications are refused; every
+    // mutating operation checks this before touching anything
+    pub(crate) fn check_writable(&self) -> Result<()> {
+        if self.read_only {
+            return Err(Ext2Error::ReadOnly);
+        }
+        Ok(())
+    }
+
+    // the advice to run fsck that ext2 gives when mounting a filesystem that
+    // has been mounted too many times, or too long ago, since it was checked
+    pub fn check_advisory(&self) -> Option<String> {
+        let sb = &self.superblock;
+        if sb.max_mnt_count > 0 && sb.mnt_count >= sb.max_mnt_count as u16 {
+            return Some(format!(
+                "filesystem has been mounted {} times without being checked, running fsck is recommended",
+                sb.mnt_count
+            ));
+        }
+        if sb.checkinterval > 0 && self.now() >= sb.lastcheck.saturating_add(sb.checkinterval) {
+            return Some(format!(
+                "filesystem has gone {} days without being checked, running fsck is recommended",
+                (self.now() - sb.lastcheck) / 86400
+            ));
+        }
+        None
+    }
+
+    /// The device the filesystem was opened on, none of the changes since
+    /// included; `sync_all` writes those out over a copy of it.
+    pub fn device(&self) -> &dyn BlockDevice {
+        self.device.device()
+    }
+
+    /// All of the device's bytes, read in if it isn't in memory.
+    pub fn device_bytes(&self) -> Result<Cow<'_, [u8]>> {
+        self.device.bytes()
+    }
+
+    // a block as it is on the device, ignoring any modifications
+    fn device_block(&self, block_num: usize) -> Result<&[u8]> {
+        let blocks_count = self.superblock.blocks_count as usize;
+        self.device.block(block_num, blocks_count)
+    }
+
+    // `block`, for a block of file contents: from a device that isn't in
+    // memory, a copy (see `DeviceBlocks::data_block`)
+    pub(crate) fn data_block(&self, block_num: usize) -> Result<Cow<'_, [u8]>> {
+        if let Some(block) = self.dirty.get(&block_num) {
+            return Ok(Cow::Borrowed(block));
+        }
+        let blocks_count = self.superblock.blocks_count as usize;
+        self.device.data_block(block_num, blocks_count)
+    }
+
+    // about to read block `block_num` of a file sequentially, with `blocks`
+    // at the block after it: if it hasn't been read from the device yet,
+    // read it along with as many of the blocks after it as follow it on the
+    // device, up to `readahead` in all, in one go rather than one by one as
+    // they're asked for
+    fn read_ahead(&self, block_num: usize, blocks: &FileBlocks<'_>) {
+        if self.readahead <= 1 || self.dirty.contains_key(&block_num) {
+            return;
+        }
+        if self.device.is_read(block_num) {
+            return;
+        }
+        let count = 1 + blocks.contiguous_after(block_num, self.readahead - 1);
+        let blocks_count = self.superblock.blocks_count as usize;
+        // a failure here is found again, and reported, reading the block
+        if let Err(err) = self.device.read_ahead(block_num, count, blocks_count) {
+            debug!("reading ahead from block {}: {}", block_num, err);
+        }
+    }
+
+    // given a (1-indexed) inode number, iterate over the physical block numbers
+    // of its data in logical order, with 0 standing for a hole
+    pub fn file_blocks(&self, inode: usize) -> Result<FileBlocks<'_>> {
+        let root = self.get_inode(inode)?;
+        let size = if root.is_dir() {
+            // size_high is the directory ACL for directories
+            root.size_low as u64
+        } else {
+            root.size()
+        };
+        Ok(FileBlocks {
+            ext2: self,
+            inode: root,
+            next: 0,
+            count: size.div_ceil(self.block_size as u64) as usize,
+        })
+    }
+
+    // given a (1-indexed) inode number, list every block it owns: its data blocks,
+    // the indirect blocks pointing at them and its extended attribute block,
+    // skipping holes. Unlike `file_blocks` this walks the pointer tree itself,
+    // so it's cheap for huge sparse files and ignores the file size.
+    pub fn owned_blocks(&self, inode: usize) -> Result<Vec<usize>> {
+        let root = self.get_inode(inode)?;
+        let mut ret = Vec::new();
+        if root.ext_attribute_block != 0 {
+            ret.push(root.ext_attribute_block as usize);
+        }
+        // devices, FIFOs, sockets and fast symlinks keep other data in the pointers
+        // the bad blocks inode has no mode, but its pointers are the bad blocks
+        let acl_sectors = if root.ext_attribute_block != 0 {
+            self.block_size as u32 / 512
+        } else {
+            0
+        };
+        let has_block_pointers = if inode == BAD_BLOCKS_INODE {
+            true
+        } else if root.is_symlink() {
+            root.sectors_count > acl_sectors
+        } else {
+            root.is_regular() || root.is_dir()
+        };
+        if !has_block_pointers {
+            return Ok(ret);
+        }
+        ret.extend(
+            root.direct_pointer
+                .iter()
+                .filter(|&&ptr| ptr != 0)
+                .map(|&ptr| ptr as usize),
+        );
+        self.collect_indirect(root.indirect_pointer, 1, &mut ret)
+            .map_err(|e| e.in_block("indirect", root.indirect_pointer as usize))?;
+        self.collect_indirect(root.doubly_indirect, 2, &mut ret)
+            .map_err(|e| e.in_block("doubly indirect", root.doubly_indirect as usize))?;
+        self.collect_indirect(root.triply_indirect, 3, &mut ret)
+            .map_err(|e| e.in_block("triply indirect", root.triply_indirect as usize))?;
+        Ok(ret)
+    }
+
+    // A helper function for `owned_blocks`: push an indirect block and everything
+    // under it, `depth` levels down (1 for a singly indirect block)
+    fn collect_indirect(&self, block_num: u32, depth: u32, ret: &mut Vec<usize>) -> Result<()> {
+        if block_num == 0 {
+            return Ok(());
+        }
+        ret.push(block_num as usize);
+        let block = self.block(block_num as usize)?;
+        for entry in block.chunks_exact(4) {
+            let ptr = u32::from_le_bytes(entry.try_into().unwrap());
+            if ptr == 0 {
+                continue;
+            }
+            if depth == 1 {
+                ret.push(ptr as usize);
+            } else {
+                self.collect_indirect(ptr, depth - 1, ret)?;
+            }
+        }
+        Ok(())
+    }
+
+    // does block group `group` carry a copy of the superblock and descriptor table?
+    // with the sparse_super feature only groups 0, 1 and powers of 3, 5 and 7 do
+    pub fn group_has_superblock(&self, group: usize) -> bool {
+        let sparse = structs::FeatureRoCompat::from_bits_truncate(self.superblock.features_ronly)
+            .contains(structs::FeatureRoCompat::SPARSE_SUPER);
+        if !sparse || group <= 1 {
+            return true;
+        }
+        [3, 5, 7].iter().any(|&base| {
+            let mut n = base;
+            while n < group {
+                n *= base;
+            }
+            n == group
+        })
+    }
+
+    // read the idx'th 32-bit block number out of an indirect block
+    fn indirect_entry(&self, block_num: u32, idx: usize) -> Result<u32> {
+        if block_num == 0 {
+            // the whole subtree under a missing indirect block is a hole
+            return Ok(0);
+        }
+        let block = self.block(block_num as usize)?;
+        Ok(u32::from_le_bytes(
+            block[idx * 4..idx * 4 + 4].try_into().unwrap(),
+        ))
+    }
+}
+
+// inode (4 bytes) + entry_size (2) + name_length (1) + type_indicator (1)
+const DIR_ENTRY_HEADER: usize = 8;
+
+// the entry_size stored in `bytes` of a directory block of `block_size`:
+// 16 bits don't reach 65536, so a slot taking up the whole of a 64 KiB block
+// is stored as 0xFFFF, or by some as 0, the way ext4 reads it
+pub(crate) fn rec_len_from_disk(bytes: [u8; 2], block_size: usize) -> usize {
+    match u16::from_le_bytes(bytes) {
+        0 | 0xFFFF if block_size == 1 << 16 => block_size,
+        rec_len => rec_len as usize,
+    }
+}
+
+// `rec_len` as `rec_len_from_disk` reads it back, with a whole 64 KiB block
+// as 0xFFFF, as e2fsprogs writes it
+pub(crate) fn rec_len_to_disk(rec_len: usize) -> [u8; 2] {
+    debug_assert!(rec_len <= 1 << 16);
+    if rec_len == 1 << 16 {
+        [0xFF, 0xFF]
+    } else {
+        (rec_len as u16).to_le_bytes()
+    }
+}
+
+/// A directory entry from `read_dir_inode`, copied out of the directory so
+/// it outlives changes to it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirEntry {
+    pub inode: usize,
+    /// Exactly the bytes on disk, which needn't be UTF-8
+    pub name: Vec<u8>,
+    /// What the entry's type indicator says the inode is, if the filesystem
+    /// keeps them and this one holds a valid type
+    pub file_type: Option<FileType>,
+}
+
+impl DirEntry {
+    /// The name as text, invalid UTF-8 replaced.
+    pub fn name_lossy(&self) -> std::borrow::Cow<'_, str> {
+        String::from_utf8_lossy(&self.name)
+    }
+
+    /// The name, safe to print: see `Escaped`.
+    pub fn escaped(&self) -> Escaped<'_> {
+        Escaped(&self.name)
+    }
+
+    /// What the name makes the entry to a listing, see `NameKind`.
+    pub fn kind(&self) -> NameKind {
+        NameKind::of(&self.name)
+    }
+
+    /// Whether this is the `.` or `..` entry.
+    pub fn is_dot(&self) -> bool {
+        self.kind() == NameKind::Dot
+    }
+}
+
+/// What a directory entry's name makes it to a listing. Everything that
+/// lists or recurses decides by this, so they all agree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NameKind {
+    /// `.` or `..`, the links to the directory itself and to its parent,
+    /// which are never recursed into
+    Dot,
+    /// Any other name starting with `.`, which `ls` only shows with `-a`
+    /// or `-A`
+    Hidden,
+    Visible,
+}
+
+impl NameKind {
+    pub fn of(name: &[u8]) -> NameKind {
+        match name {
+            b"." | b".." => NameKind::Dot,
+            [b'.', ..] => NameKind::Hidden,
+            _ => NameKind::Visible,
+        }
+    }
+}
+
+/// One slot of a directory block as it is on disk, from `DirSlots`: an entry,
+/// or an unused slot if its inode is 0.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RawDirEntry<'a> {
+    /// Where the slot starts in its block
+    pub offset: usize,
+    pub inode: usize,
+    /// The slot's size, `rec_len`: how far it is to the next slot, or to the
+    /// end of the block for the last
+    pub rec_len: usize,
+    /// The type indicator, as a raw byte: on disk it may hold any value
+    pub file_type: u8,
+    /// Exactly `name_len` bytes
+    pub name: EntryName<'a>,
+}
+
+impl RawDirEntry<'_> {
+    /// The bytes of the slot after the name, up to the next slot: padding to
+    /// a multiple of 4, and whatever space was left to the slot, e.g. by
+    /// merging the slots after it into it when their entries were removed.
+    pub fn padding(&self) -> usize {
+        self.rec_len - DIR_ENTRY_HEADER - self.name.0.len()
+    }
+}
+
+/// Iterator over every slot of one directory data block, unused ones
+/// included, from `DirSlots::new`.
+///
+/// Every slot is checked before it's used, so a corrupt `rec_len` can neither
+/// loop forever nor walk off the end of the block. At the first bad one it
+/// yields the slot's offset and what's wrong with it, and stops: where the
+/// slots after it start can't be known.
+#[derive(Debug, Clone)]
+pub struct DirSlots<'a> {
+    block: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> DirSlots<'a> {
+    pub fn new(block: &'a [u8]) -> DirSlots<'a> {
+        DirSlots { block, offset: 0 }
+    }
+
+    // the slot at `offset`, or what's wrong with it
+    fn slot(&self) -> std::result::Result<RawDirEntry<'a>, String> {
+        let block = self.block;
+        let offset = self.offset;
+        if block.len() - offset < DIR_ENTRY_HEADER {
+            return Err(String::from("entry header crosses the block boundary"));
+        }
+        // read field by field, since `block` may be any slice, aligned or not
+        let header = &block[offset..offset + DIR_ENTRY_HEADER];
+        let inode = u32::from_le_bytes(header[..4].try_into().unwrap());
+        let rec_len = rec_len_from_disk([header[4], header[5]], block.len());
+        if rec_len < DIR_ENTRY_HEADER || rec_len % 4 != 0 {
+            return Err(format!("invalid entry_size {}", rec_len));
+        }
+        if rec_len > block.len() - offset {
+            return Err(format!("entry_size {} crosses the block boundary", rec_len));
+        }
+        let name_len = header[6] as usize;
+        if DIR_ENTRY_HEADER + name_len > rec_len {
+            return Err(format!(
+                "name_length {} doesn't fit in entry_size {}",
+                name_len, rec_len
+            ));
+        }
+        let name_start = offset + DIR_ENTRY_HEADER;
+        Ok(RawDirEntry {
+            offset,
+            inode: inode as usize,
+            rec_len,
+            file_type: header[7],
+            name: EntryName(&block[name_start..name_start + name_len]),
+        })
+    }
+}
+
+impl<'a> Iterator for DirSlots<'a> {
+    type Item = std::result::Result<RawDirEntry<'a>, (usize, String)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.offset >= self.block.len() {
+            return None;
+        }
+        match self.slot() {
+            Ok(slot) => {
+                self.offset += slot.rec_len;
+                Some(Ok(slot))
+            }
+            Err(reason) => {
+                let offset = self.offset;
+                self.offset = self.block.len();
+                Some(Err((offset, reason)))
+            }
+        }
+    }
+}
+
+/// The name of a directory entry: exactly its `name_length` bytes. Names
+/// aren't NUL-terminated on disk, one that fills its entry runs straight into
+/// the next, and needn't be UTF-8; `Display` replaces invalid sequences, for
+/// using the name as a `String`. To show it, use `escaped`.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct EntryName<'a>(pub &'a [u8]);
+
+impl<'a> EntryName<'a> {
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.0
+    }
+
+    pub fn as_os_str(&self) -> &'a OsStr {
+        OsStr::from_bytes(self.0)
+    }
+
+    /// The name, safe to print: see `Escaped`.
+    pub fn escaped(&self) -> Escaped<'a> {
+        Escaped(self.0)
+    }
+
+    /// What the name makes the entry to a listing, see `NameKind`.
+    pub fn kind(&self) -> NameKind {
+        NameKind::of(self.0)
+    }
+}
+
+impl fmt::Display for EntryName<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&String::from_utf8_lossy(self.0))
+    }
+}
+
+impl fmt::Debug for EntryName<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&String::from_utf8_lossy(self.0), f)
+    }
+}
+
+/// A name shown so that it can't upset a terminal, and reads back the same:
+/// a name may hold any byte but `/` and NUL, newlines and escape sequences
+/// included. Control characters and bytes that aren't UTF-8 are shown as
+/// `\xNN`, and a backslash as `\\`; everything else as it is.
+#[derive(Clone, Copy)]
+pub struct Escaped<'a>(pub &'a [u8]);
+
+impl fmt::Display for Escaped<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn hex(f: &mut fmt::Formatter<'_>, bytes: &[u8]) -> fmt::Result {
+            bytes
+                .iter()
+                .try_for_each(|byte| write!(f, "\\x{:02x}", byte))
+        }
+        let mut rest = self.0;
+        while !rest.is_empty() {
+            let (valid, bad) = match std::str::from_utf8(rest) {
+                Ok(valid) => (valid, 0),
+                Err(err) => {
+                    let valid = std::str::from_utf8(&rest[..err.valid_up_to()]).unwrap();
+                    let bad = err.error_len().unwrap_or(rest.len() - valid.len());
+                    (valid, bad)
+                }
+            };
+            for c in valid.chars() {
+                match c {
+                    '\\' => f.write_str("\\\\")?,
+                    c if c.is_control() => hex(f, c.encode_utf8(&mut [0; 4]).as_bytes())?,
+                    c => write!(f, "{}", c)?,
+                }
+            }
+            hex(f, &rest[valid.len()..valid.len() + bad])?;
+            rest = &rest[valid.len() + bad..];
+        }
+        Ok(())
+    }
+}
+
+/// Parse the entries in use out of one directory data block, with
+/// `DirSlots`. On failure, returns the byte offset of the bad entry and what's
+/// wrong with it.
+fn dir_block_entries(block: &[u8]) -> std::result::Result<Vec<RawDirEntry<'_>>, (usize, String)> {
+    let mut ret = Vec::new();
+    for slot in DirSlots::new(block) {
+        let slot = slot?;
+        // an inode number of 0 marks an unused entry, skip over it
+        if slot.inode != 0 {
+            ret.push(slot);
+        }
+    }
+    Ok(ret)
+}
+
+/// Whether the primary superblock, 1024 bytes into `device`, has the ext2 magic.
+pub fn primary_superblock_ok(device: &[u8]) -> bool {
+    superblock_magic(device, EXT2_START_OF_SUPERBLOCK) == Some(EXT2_MAGIC)
+}
+
+/// Look for a valid backup superblock when the primary one is damaged, the way
+/// `e2fsck` does: without a superblock the geometry is unknown, so try each
+/// block size with the default of 8 blocks per group per byte of bitmap, and
+/// check the groups that carry backups under sparse_super. Returns the group
+/// and the byte offset of the first backup that checks out, for `from_backup`.
+pub fn find_backup_superblock(device: &[u8]) -> Option<(usize, usize)> {
+    for log_block_size in 0..=6 {
+        let block_size = 1024usize << log_block_size;
+        let blocks_per_group = block_size * 8;
+        let first_data_block = usize::from(block_size == 1024);
+        for group in [1, 3, 5, 7, 9, 25, 27, 49] {
+            let offset = (first_data_block + group * blocks_per_group) * block_size;
+            if superblock_magic(device, offset) != Some(EXT2_MAGIC) {
+                continue;
+            }
+            let backup = unsafe { &*(device[offset..].as_ptr() as *const Superblock) };
+            if backup.log_block_size == log_block_size
+                && backup.blocks_per_group as usize == blocks_per_group
+                && backup.block_group as usize == group
+            {
+                debug!(
+                    "found a backup superblock in group {} at byte {}",
+                    group, offset
+                );
+                return Some((group, offset));
+            }
+        }
+    }
+    None
+}
+
+// the magic number of the superblock at byte `offset` of the device, if it fits
+fn superblock_magic(device: &[u8], offset: usize) -> Option<u16> {
+    // the magic sits 56 bytes into the superblock
+    let magic = device.get(offset + 56..offset + 58)?;
+    Some(u16::from_le_bytes(magic.try_into().unwrap()))
+}
+
+/// Interpret a fixed-size, NUL-padded superblock field as a string.
+fn c_string(bytes: &[u8]) -> String {
+    let len = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..len]).into_owned()
+}
+
+/// Name the set flags of a feature bitfield, plus any bits we don't know.
+fn feature_names<F: fmt::Debug>(known: F, unknown: u32) -> String {
+    let mut names = format!("{:?}", known);
+    if names == "(empty)" {
+        names = String::from("none");
+    }
+    if unknown != 0 {
+        names.push_str(&format!(" | unknown {:#x}", unknown));
+    }
+    names
+}
+
+/// Format a POSIX timestamp as a UTC date, or `never` for 0.
+pub fn format_time(time: u32) -> String {
+    if time == 0 {
+        return String::from("never");
+    }
+    let days = time as i64 / 86400;
+    let secs = time as i64 % 86400;
+    // civil-from-days, https://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+        year,
+        month,
+        day,
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
+
+/// Format a size in bytes like coreutils' `-h`: in powers of 1024 with a
+/// one-letter unit, e.g. `4.0K` or `213M`, with one decimal below 10, and
+/// rounded up so nothing looks smaller than it is. Under 1024 bytes it's
+/// just the number.
+pub fn human_size(bytes: u64) -> String {
+    const UNITS: [&str; 6] = ["K", "M", "G", "T", "P", "E"];
+    if bytes < 1024 {
+        return bytes.to_string();
+    }
+    // wide enough that rounding up at the top unit can't overflow
+    let bytes = bytes as u128;
+    let mut scale = 1024;
+    for unit in UNITS {
+        let tenths = (bytes * 10).div_ceil(scale);
+        if tenths < 100 {
+            return format!("{}.{}{}", tenths / 10, tenths % 10, unit);
+        }
+        // rounding up can make it a whole unit bigger, e.g. 1024K is 1.0M
+        let whole = bytes.div_ceil(scale);
+        if whole < 1024 || unit == "E" {
+            return format!("{}{}", whole, unit);
+        }
+        scale *= 1024;
+    }
+    unreachable!("u64 sizes stop at 16E")
+}
+
+/// Iterator over the data blocks of an inode, returned by `Ext2::file_blocks`.
+/// Walks the direct pointers, then the singly, doubly and triply indirect trees,
+/// yielding one physical block number per logical block (0 for holes), or an
+/// error if an indirect block along the way is out of range.
+pub struct FileBlocks<'a> {
+    ext2: &'a Ext2,
+    inode: &'a Inode,
+    /// next logical block to yield
+    next: usize,
+    /// number of logical blocks in the file
+    count: usize,
+}
+
+impl FileBlocks<'_> {
+    /// How many of the blocks still to come, up to `max`, follow on from
+    /// physical block `block_num` without a gap, so that one read of the
+    /// device fetches them all; a hole or a pointer that can't be read ends
+    /// the run.
+    pub fn contiguous_after(&self, block_num: usize, max: usize) -> usize {
+        (self.next..self.count.min(self.next.saturating_add(max)))
+            .zip(1..)
+            .take_while(|&(logical, i)| {
+                matches!(self.lookup(logical), Ok(next) if next as usize == block_num + i)
+            })
+            .count()
+    }
+
+    // translate a logical block index into a physical block number
+    fn lookup(&self, logical: usize) -> Result<u32> {
+        // each indirect block holds block_size / 4 pointers
+        let per_block = self.ext2.block_size / 4;
+        let mut idx = logical;
+        if idx < 12 {
+            return Ok(self.inode.direct_pointer[idx]);
+        }
+        idx -= 12;
+        if idx < per_block {
+            let indir = self.inode.indirect_pointer;
+            return self
+                .ext2
+                .indirect_entry(indir, idx)
+                .map_err(|e| e.in_block("indirect", indir as usize));
+        }
+        idx -= per_block;
+        if idx < per_block * per_block {
+            let doubly = self.inode.doubly_indirect;
+            let indir = self
+                .ext2
+                .indirect_entry(doubly, idx / per_block)
+                .map_err(|e| e.in_block("doubly indirect", doubly as usize))?;
+            return self
+                .ext2
+                .indirect_entry(indir, idx % per_block)
+                .map_err(|e| e.in_block("indirect", indir as usize));
+        }
+        idx -= per_block * per_block;
+        let triply = self.inode.triply_indirect;
+        let doubly = self
+            .ext2
+            .indirect_entry(triply, idx / (per_block * per_block))
+            .map_err(|e| e.in_block("triply indirect", triply as usize))?;
+        let indir = self
+            .ext2
+            .indirect_entry(doubly, idx / per_block % per_block)
+            .map_err(|e| e.in_block("doubly indirect", doubly as usize))?;
+        self.ext2
+            .indirect_entry(indir, idx % per_block)
+            .map_err(|e| e.in_block("indirect", indir as usize))
+    }
+}
+
+impl ExactSizeIterator for FileBlocks<'_> {}
+
+impl Iterator for FileBlocks<'_> {
+    type Item = Result<usize>;
+
+    fn next(&mut self) -> Option<Result<usize>> {
+        if self.next >= self.count {
+            return None;
+        }
+        self.next += 1;
+        Some(self.lookup(self.next - 1).map(|block| block as usize))
+    }
+
+    // jump straight to the n'th block instead of resolving every block before it
+    fn nth(&mut self, n: usize) -> Option<Result<usize>> {
+        let logical = self.next.checked_add(n)?;
+        if logical >= self.count {
+            self.next = self.count;
+            return None;
+        }
+        self.next = logical + 1;
+        Some(self.lookup(logical).map(|block| block as usize))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.count - self.next;
+        (remaining, Some(remaining))
+    }
+}
+
+/// The largest block size ext2 allows; holes are yielded as a slice of this.
+const MAX_BLOCK_SIZE: usize = 65536;
+static ZERO_BLOCK: [u8; MAX_BLOCK_SIZE] = [0; MAX_BLOCK_SIZE];
+
+/// Iterator over the contents of an inode a block at a time, returned by
+/// `Ext2::file_chunks`. Each slice borrows from the filesystem where it can,
+/// so nothing on a device in memory is copied, and is a copy of the block
+/// read otherwise; holes are a shared block of zeros and the last slice is
+/// cut off at the file size.
+pub struct FileChunks<'a> {
+    blocks: FileBlocks<'a>,
+    inode: usize,
+    /// bytes of the file not yet yielded
+    remaining: u64,
+}
+
+impl<'a> Iterator for FileChunks<'a> {
+    type Item = Result<Cow<'a, [u8]>>;
+
+    fn next(&mut self) -> Option<Result<Cow<'a, [u8]>>> {
+        let ext2 = self.blocks.ext2;
+        let block = match self.blocks.next()? {
+            Ok(0) => Ok(Cow::Borrowed(&ZERO_BLOCK[..ext2.block_size])),
+            Ok(block_num) => {
+                // chunks are always read in order
+                ext2.read_ahead(block_num, &self.blocks);
+                ext2.data_block(block_num)
+            }
+            Err(err) => Err(err),
+        };
+        match block {
+            Ok(block) => {
+                let len = (block.len() as u64).min(self.remaining) as usize;
+                self.remaining -= len as u64;
+                Some(Ok(match block {
+                    Cow::Borrowed(block) => Cow::Borrowed(&block[..len]),
+                    Cow::Owned(mut block) => {
+                        block.truncate(len);
+                        Cow::Owned(block)
+                    }
+                }))
+            }
+            Err(err) => {
+                let err = err.in_inode("reading", self.inode);
+                if ext2.tolerate(&err) {
+                    // the block reads as zeros, and the rest of the file as usual
+                    let len = (ext2.block_size as u64).min(self.remaining) as usize;
+                    self.remaining -= len as u64;
+                    return Some(Ok(Cow::Borrowed(&ZERO_BLOCK[..len])));
+                }
+                // nothing after a broken pointer can be trusted
+                self.blocks.next = self.blocks.count;
+                Some(Err(err))
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.blocks.size_hint()
+    }
+}
+
+impl fmt::Debug for Inode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.size_low == 0 && self.size_high == 0 {
+            f.debug_struct("").finish()
+        } else {
+            f.debug_struct("Inode")
+                .field("type_perm", &self.type_perm)
+                .field("size_low", &self.size_low)
+                .field("direct_pointers", &self.direct_pointer)
+                .field("indirect_pointer", &self.indirect_pointer)
+                .finish()
+        }
+    }
+}
+
+impl Inode {
+    /// Size in bytes of the file this inode describes.
+    pub fn size(&self) -> u64 {
+        ((self.size_high as u64) << 32) | self.size_low as u64
+    }
+
+    /// Set the size in bytes of a regular file, across both size fields.
+    pub fn set_size(&mut self, size: u64) {
+        self.size_low = size as u32;
+        self.size_high = (size >> 32) as u32;
+    }
+
+    /// What kind of file this is.
+    pub fn file_type(&self) -> FileType {
+        FileType::from_mode(self.type_perm.bits())
+    }
+
+    /// What kind of file this is, in words, e.g. "character device".
+    pub fn type_name(&self) -> &'static str {
+        match self.file_type() {
+            FileType::Fifo => "FIFO",
+            FileType::Char => "character device",
+            FileType::Dir => "directory",
+            FileType::Block => "block device",
+            FileType::Regular => "regular file",
+            FileType::Symlink => "symbolic link",
+            FileType::Socket => "socket",
+            FileType::Unknown => "unknown file type",
+        }
+    }
+
+    /// Whether this is a directory.
+    pub fn is_dir(&self) -> bool {
+        self.file_type() == FileType::Dir
+    }
+
+    /// Whether this is a regular file.
+    pub fn is_regular(&self) -> bool {
+        self.file_type() == FileType::Regular
+    }
+
+    /// Whether this is a symbolic link.
+    pub fn is_symlink(&self) -> bool {
+        self.file_type() == FileType::Symlink
+    }
+
+    /// Whether this is a device node, FIFO or socket, which have no data of
+    /// their own.
+    pub fn is_special(&self) -> bool {
+        matches!(
+            self.file_type(),
+            FileType::Fifo | FileType::Char | FileType::Block | FileType::Socket
+        )
+    }
+
+    /// The permission bits, setuid, setgid and sticky included: the mode
+    /// without the file type.
+    pub fn permissions(&self) -> u16 {
+        self.type_perm.bits() & 0o7777
+    }
+
+    /// The (major, minor) numbers of a character or block device.
+    ///
+    /// Linux keeps them in the first block pointer when both fit in a byte,
+    /// and otherwise in the second, in the "new" 32-bit encoding.
+    pub fn device(&self) -> Option<(u32, u32)> {
+        if !matches!(self.file_type(), FileType::Char | FileType::Block) {
+            return None;
+        }
+        let old = self.direct_pointer[0];
+        if old != 0 {
+            return Some(((old >> 8) & 0xff, old & 0xff));
+        }
+        let new = self.direct_pointer[1];
+        Some(((new >> 8) & 0xfff, (new & 0xff) | ((new >> 12) & 0xfff00)))
+    }
+
+    /// Store device numbers the way `device` reads them back.
+    pub fn set_device(&mut self, major: u32, minor: u32) {
+        if major < 256 && minor < 256 {
+            self.direct_pointer[0] = (major << 8) | minor;
+            self.direct_pointer[1] = 0;
+        } else {
+            self.direct_pointer[0] = 0;
+            self.direct_pointer[1] = (minor & 0xff) | (major << 8) | ((minor & !0xff) << 12);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a slot: inode, rec_len, name_len, type, name, padded with zeros to rec_len
+    fn slot(inode: u32, rec_len: u16, name_len: u8, name: &[u8]) -> Vec<u8> {
+        let mut slot = inode.to_le_bytes().to_vec();
+        slot.extend(rec_len.to_le_bytes());
+        slot.extend([name_len, 1]);
+        slot.extend(name);
+        slot.resize((rec_len as usize).max(slot.len()), 0);
+        slot
+    }
+
+    // "." and "..", then `rest`, in a 64-byte block
+    fn block(rest: &[u8]) -> Vec<u8> {
+        let mut block = slot(12, 12, 1, b".");
+        block.extend(slot(2, 12, 2, b".."));
+        block.extend(rest);
+        block.resize(64, 0);
+        block
+    }
+
+    fn names(block: &[u8]) -> Vec<&[u8]> {
+        dir_block_entries(block)
+            .unwrap()
+            .iter()
+            .map(|entry| entry.name.0)
+            .collect()
+    }
+
+    #[test]
+    fn well_formed() {
+        // an unused slot, its name left behind, and one to the end of the block
+        let mut rest = slot(0, 16, 3, b"old");
+        rest.extend(slot(13, 24, 5, b"a.txt"));
+        assert_eq!(names(&block(&rest)), [&b"."[..], b"..", b"a.txt"]);
+        // a name filling its slot exactly
+        assert_eq!(names(&block(&slot(13, 40, 32, &[b'n'; 32])))[2].len(), 32);
+    }
+
+    #[test]
+    fn zero_rec_len() {
+        // would loop forever on the same slot if it were believed
+        let rest = slot(13, 0, 1, b"a");
+        assert_eq!(
+            dir_block_entries(&block(&rest)).unwrap_err(),
+            (24, String::from("invalid entry_size 0"))
+        );
+        // and a block of nothing but zeros
+        assert_eq!(
+            dir_block_entries(&[0; 64]).unwrap_err(),
+            (0, String::from("invalid entry_size 0"))
+        );
+    }
+
+    #[test]
+    fn whole_64k_block_rec_len() {
+        // 0xFFFF, or 0, is the whole block, which 16 bits can't say
+        for stored in [0xFFFF, 0] {
+            let mut block = slot(13, stored, 1, b"a");
+            block.resize(1 << 16, 0);
+            let entries = dir_block_entries(&block).unwrap();
+            assert_eq!(entries[0].rec_len, 1 << 16);
+            assert_eq!(entries.len(), 1);
+        }
+        assert_eq!(rec_len_to_disk(1 << 16), [0xFF, 0xFF]);
+        assert_eq!(rec_len_to_disk(65532), 65532u16.to_le_bytes());
+        // but only in a 64 KiB block
+        assert_eq!(rec_len_from_disk([0xFF, 0xFF], 4096), 0xFFFF);
+    }
+
+    #[test]
+    fn rec_len_not_a_multiple_of_4() {
+        for rec_len in [9, 14, 39] {
+            let rest = slot(13, rec_len, 1, b"a");
+            assert_eq!(
+                dir_block_entries(&block(&rest)).unwrap_err(),
+                (24, format!("invalid entry_size {}", rec_len))
+            );
+        }
+    }
+
+    #[test]
+    fn rec_len_past_the_block() {
+        // 40 bytes are left after "." and ".."
+        let mut rest = slot(13, 44, 1, b"a");
+        rest.truncate(40);
+        assert_eq!(
+            dir_block_entries(&block(&rest)).unwrap_err(),
+            (24, String::from("entry_size 44 crosses the block boundary"))
+        );
+        let mut rest = slot(13, 0xfffc, 1, b"a");
+        rest.truncate(40);
+        assert_eq!(
+            dir_block_entries(&block(&rest)).unwrap_err(),
+            (
+                24,
+                String::from("entry_size 65532 crosses the block boundary")
+            )
+        );
+        // the last slot ending 4 bytes short leaves half a header
+        let mut rest = slot(13, 36, 1, b"a");
+        rest.extend([0xff; 4]);
+        assert_eq!(
+            dir_block_entries(&block(&rest)).unwrap_err(),
+            (60, String::from("entry header crosses the block boundary"))
+        );
+    }
+
+    #[test]
+    fn name_longer_than_its_slot() {
+        // 8 bytes of name in a slot with room for 8, but claiming 9
+        let rest = slot(13, 16, 9, b"too-long");
+        assert_eq!(
+            dir_block_entries(&block(&rest)).unwrap_err(),
+            (
+                24,
+                String::from("name_length 9 doesn't fit in entry_size 16")
+            )
+        );
+        // an unused slot is checked the same way
+        let rest = slot(0, 12, 255, b"x");
+        assert_eq!(
+            dir_block_entries(&block(&rest)).unwrap_err(),
+            (
+                24,
+                String::from("name_length 255 doesn't fit in entry_size 12")
+            )
+        );
+    }
+
+    #[test]
+    fn read_dir_inode_says_where() {
+        let mut device = vec![0; 1 << 20];
+        mkfs(&mut device, &MkfsOptions::new().block_size(1024)).unwrap();
+        let mut ext2 = Ext2::new(device).unwrap();
+        let dir = ext2.create_dir(2, "dir", 0o755).unwrap();
+        ext2.create_file(dir, "a", 0o644).unwrap();
+        let block_num = ext2.get_inode(dir).unwrap().direct_pointer[0] as usize;
+        // "a"'s rec_len, after "." and ".."
+        ext2.block_mut(block_num).unwrap()[28..30].copy_from_slice(&0u16.to_le_bytes());
+        match ext2.read_dir_inode(dir) {
+            Err(Ext2Error::CorruptDirectory {
+                inode,
+                block,
+                offset,
+                reason,
+            }) => {
+                assert_eq!((inode, block, offset), (dir, block_num, 24));
+                assert_eq!(reason, "invalid entry_size 0");
+            }
+            other => panic!("{:?}", other.map(|entries| entries.len())),
+        }
+    }
+}
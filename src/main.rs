@@ -1,610 +1,4964 @@
 #![feature(int_roundings)]
+#![feature(is_terminal)]
 
-mod structs;
-use crate::structs::{BlockGroupDescriptor, DirectoryEntry, Inode, Superblock};
-use null_terminated::Nul;
-use null_terminated::NulStr;
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use crossterm::style::{self, Stylize};
+use crossterm::terminal::{self, ClearType};
+use crossterm::{cursor, execute, queue};
+use ext2::structs::{self, FileType, Inode, InodeFlags};
+use ext2::{
+    AccessMode, BlockDevice, Credentials, DirIndex, DuCache, EntryInfo, Escaped, Ext2, Ext2Error,
+    Ext2Options, FileDevice, FileReader, GroupDescriptorOwned, InodeInfo, InodeOwned, MkfsOptions,
+    NameKind, OffsetDevice, Partition, PathCache, Progress, ReservedInode, Snapshot, Strictness,
+    SubtreeUsage, SuperblockOwned, WalkControl, WalkOptions, WithPath,
+};
+use rustyline::error::ReadlineError;
 use rustyline::{DefaultEditor, Result};
-use std::fmt;
-use std::io::{self, Write};
-use std::mem;
-use uuid::Uuid;
-use zerocopy::ByteSlice;
-
-#[repr(C)]
-#[derive(Debug)]
-pub struct Ext2 {
-    pub superblock: &'static Superblock,
-    pub block_groups: &'static mut [BlockGroupDescriptor],
-    pub blocks: Vec<&'static [u8]>,
-    pub block_size: usize,
-    pub uuid: Uuid,
-    pub block_offset: usize, // <- our "device data" actually starts at this index'th block of the device
-                             // so we have to subtract this number before indexing blocks[]
-}
-
-const EXT2_MAGIC: u16 = 0xef53;
-const EXT2_START_OF_SUPERBLOCK: usize = 1024;
-const EXT2_END_OF_SUPERBLOCK: usize = 2048;
-
-impl Ext2 {
-    pub fn new<B: ByteSlice + std::fmt::Debug>(device_bytes: B, start_addr: usize) -> Ext2 {
-        // https://wiki.osdev.org/Ext2#Superblock
-        // parse into Ext2 struct - without copying
-
-        // the superblock goes from bytes 1024 -> 2047
-        let header_body_bytes = device_bytes.split_at(EXT2_END_OF_SUPERBLOCK);
-
-        let superblock = unsafe {
-            &*(header_body_bytes
-                .0
-                .split_at(EXT2_START_OF_SUPERBLOCK)
-                .1
-                .as_ptr() as *const Superblock)
+use serde::Serialize;
+use std::cell::Cell;
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, IsTerminal, SeekFrom, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use terminal_size::{terminal_size, Height, Width};
+
+/// Shell state shared by every command handler.
+struct Shell {
+    ext2: Ext2,
+    /// inode number of the current working directory
+    cwd: usize,
+    /// the entries of the cwd, for listing it and looking up plain names
+    /// without reading it each time; built when first needed and again after
+    /// `cd` or once anything has been modified (see `cwd_index`)
+    cwd_index: Option<DirIndex>,
+    /// paths with more than one component resolved since anything was last
+    /// modified
+    paths: PathCache,
+    /// set by `quit`/`exit` to leave the REPL
+    done: bool,
+    /// the image file the filesystem was loaded from, which `sync` writes
+    /// back to
+    image: String,
+    /// the partition of the image the filesystem is in, if it's a disk image
+    /// with a partition table rather than a bare filesystem
+    partition: Option<Partition>,
+    /// images mounted over directories of this one with `mount`
+    mounts: Vec<Mount>,
+    /// what `rollback` goes back to, taken by `snapshot`
+    snapshot: Option<Snapshot>,
+    /// the cwd before the last change of directory, for `cd -`
+    previous_dir: Option<SavedDir>,
+    /// the directories saved by `pushd`, the top last
+    dir_stack: Vec<SavedDir>,
+    /// the settings `set` changes; the pager is always off for a command
+    /// given on the command line
+    config: ShellConfig,
+    /// the files opened with `open`, by descriptor
+    files: BTreeMap<usize, OpenFile>,
+    /// directory totals summed by `browse`, kept until anything is modified
+    du: DuCache,
+    /// the text each alias defined with `alias` stands for, by name
+    aliases: BTreeMap<String, String>,
+}
+
+/// A file opened with `open`. It's kept open by inode, so it stays the same
+/// file whatever happens to its path, and it can still be read after it's
+/// removed, until it's closed.
+struct OpenFile {
+    reader: FileReader,
+    /// its path from the root when it was opened, for `lsof`
+    path: String,
+}
+
+/// The lowest descriptor `open` hands out: 0 to 2 are a process's stdin,
+/// stdout and stderr.
+const FIRST_FD: usize = 3;
+
+/// A directory saved to go back to, by `cd -` or `pushd`. It's checked to
+/// still be there before it's used, since it may have been removed since.
+struct SavedDir {
+    inode: usize,
+    /// its path when it was saved, for showing
+    path: String,
+}
+
+/// A second image, opened read-only and mounted over a directory.
+struct Mount {
+    /// the directory it's mounted over
+    point: usize,
+    /// the mountpoint path as given to `mount`
+    path: String,
+    /// the host file it was loaded from
+    image: String,
+    ext2: Ext2,
+}
+
+/// Why a command handler failed.
+enum CommandError {
+    /// The arguments don't parse; the dispatcher then prints the usage line
+    /// from `COMMANDS`, so the two can't drift apart.
+    Usage,
+    /// The arguments parse but don't make sense, e.g. an unknown setting;
+    /// the dispatcher prints why after the command's name.
+    Invalid(String),
+    /// The filesystem operation itself failed.
+    Fs(Ext2Error),
+}
+
+impl From<Ext2Error> for CommandError {
+    fn from(err: Ext2Error) -> CommandError {
+        CommandError::Fs(err)
+    }
+}
+
+impl From<io::Error> for CommandError {
+    fn from(err: io::Error) -> CommandError {
+        CommandError::Fs(err.into())
+    }
+}
+
+type CommandResult = std::result::Result<(), CommandError>;
+
+/// The settings `set` shows and changes and `unset` puts back, each under
+/// its key in `ShellConfig::KEYS`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ShellConfig {
+    /// show long output of `cat`, `ls` and `help` a screenful at a time
+    /// (see `Output`)
+    pager: bool,
+    /// whether `ls` colors names when it isn't given --color
+    color: ColorMode,
+    /// print the reports that have a --json flag as JSON without it
+    json: bool,
+    /// find a name that differs only by case when there's no exact match
+    icase: bool,
+    /// leave a file's access time alone when it's read
+    noatime: bool,
+    /// read what can be read of a damaged image instead of stopping at
+    /// inconsistencies; only while the filesystem is read-only
+    lenient: bool,
+}
+
+impl Default for ShellConfig {
+    fn default() -> ShellConfig {
+        ShellConfig {
+            pager: true,
+            color: ColorMode::Auto,
+            json: false,
+            icase: false,
+            noatime: false,
+            lenient: false,
+        }
+    }
+}
+
+impl ShellConfig {
+    const KEYS: &'static [&'static str] =
+        &["pager", "color", "json", "icase", "noatime", "lenient"];
+
+    /// The value of `key` as `set` shows and takes it, or `None` for a key
+    /// there's no such setting for.
+    fn get(&self, key: &str) -> Option<&'static str> {
+        let on_off = |on| if on { "on" } else { "off" };
+        Some(match key {
+            "pager" => on_off(self.pager),
+            "color" => self.color.name(),
+            "json" => on_off(self.json),
+            "icase" => on_off(self.icase),
+            "noatime" => on_off(self.noatime),
+            "lenient" => on_off(self.lenient),
+            _ => return None,
+        })
+    }
+
+    /// Change `key` to `value`, or say why it can't be.
+    fn set(&mut self, key: &str, value: &str) -> std::result::Result<(), String> {
+        let flag = match key {
+            "color" => {
+                self.color = match value {
+                    "auto" => ColorMode::Auto,
+                    "always" => ColorMode::Always,
+                    "never" => ColorMode::Never,
+                    _ => return Err(format!("color is auto, always or never, not '{}'", value)),
+                };
+                return Ok(());
+            }
+            "pager" => &mut self.pager,
+            "json" => &mut self.json,
+            "icase" => &mut self.icase,
+            "noatime" => &mut self.noatime,
+            "lenient" => &mut self.lenient,
+            _ => {
+                return Err(format!(
+                    "no setting '{}'; there's {}",
+                    key,
+                    ShellConfig::KEYS.join(", ")
+                ))
+            }
+        };
+        *flag = match value {
+            "on" => true,
+            "off" => false,
+            _ => return Err(format!("{} is on or off, not '{}'", key, value)),
+        };
+        Ok(())
+    }
+
+    /// Put `key` back to its default.
+    fn reset(&mut self, key: &str) -> std::result::Result<(), String> {
+        let default = ShellConfig::default().get(key).unwrap_or_default();
+        self.set(key, default)
+    }
+}
+
+/// When `ls` colors names.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ColorMode {
+    /// when stdout is a terminal
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorMode {
+    fn name(self) -> &'static str {
+        match self {
+            ColorMode::Auto => "auto",
+            ColorMode::Always => "always",
+            ColorMode::Never => "never",
+        }
+    }
+
+    fn enabled(self) -> bool {
+        match self {
+            ColorMode::Auto => io::stdout().is_terminal(),
+            ColorMode::Always => true,
+            ColorMode::Never => false,
+        }
+    }
+}
+
+struct Command {
+    name: &'static str,
+    /// one-line usage string, shown by `help` and on argument errors
+    usage: &'static str,
+    /// one-line summary, shown in the `help` listing
+    summary: &'static str,
+    /// longer description, shown by `help <cmd>`
+    details: &'static str,
+    run: fn(&mut Shell, &[&str]) -> CommandResult,
+}
+
+const COMMANDS: &[Command] = &[
+    Command {
+        name: "help",
+        usage: "help [command]",
+        summary: "list commands, or describe one",
+        details: "With no argument, list every command with its usage.\n\
+                  With a command name, print that command's usage and details.",
+        run: cmd_help,
+    },
+    Command {
+        name: "ls",
+        usage: "ls [-aAilhStr] [--color=auto|always|never] [--json] [--raw] [dir]",
+        summary: "list the children of a directory",
+        details: "Print the name of every entry in dir, or the current directory,\n\
+                  sorted by name and laid out in columns to fit the terminal. Names\n\
+                  starting with '.' are left out unless asked for:\n\
+                  \x20 -a  list every entry, . and .. included\n\
+                  \x20 -A  list names starting with '.', but not . and ..\n\
+                  \x20 -i  prefix each entry with its inode number\n\
+                  \x20 -l  one entry per line with its type and permissions, link\n\
+                  \x20     count, owner, group, size (major, minor for devices) and mtime\n\
+                  \x20 -h  with -l, sizes like 1.5K rather than in bytes\n\
+                  \x20 -S  sort by size, largest first\n\
+                  \x20 -t  sort by modification time, newest first\n\
+                  \x20 -r  reverse the sort order\n\
+                  When stdout is a terminal, directories are blue, symlinks cyan and\n\
+                  executables green; --color=never turns this off and --color=always\n\
+                  forces it on. Control characters and bytes that aren't UTF-8 in\n\
+                  names show as \\xNN, and backslashes as \\\\, so that no name can\n\
+                  mess up the terminal; --raw prints names as they are. With --json,\n\
+                  print the sorted entries as a JSON array of objects with each one's\n\
+                  name, inode, type, mode, owner, links, size and times instead.",
+        run: cmd_ls,
+    },
+    Command {
+        name: "cd",
+        usage: "cd [path | -]",
+        summary: "change the current directory",
+        details: "With no argument, go back to the root directory.\n\
+                  With a path, move into that directory. Paths starting with `/`\n\
+                  are relative to the root, everything else to the cwd.\n\
+                  With -, go back to the directory before the last change.",
+        run: cmd_cd,
+    },
+    Command {
+        name: "pushd",
+        usage: "pushd [path]",
+        summary: "save the current directory on the stack and change to another",
+        details: "Push the cwd onto the directory stack and move into path, then print\n\
+                  the stack like 'dirs'. With no argument, swap the cwd with the\n\
+                  directory on top of the stack instead.",
+        run: cmd_pushd,
+    },
+    Command {
+        name: "popd",
+        usage: "popd",
+        summary: "change to the directory on top of the stack",
+        details: "Take the directory on top of the directory stack off it and move\n\
+                  into it, then print the stack like 'dirs'. One that has been removed\n\
+                  since it was pushed is dropped, with a message, and the cwd stays.",
+        run: cmd_popd,
+    },
+    Command {
+        name: "dirs",
+        usage: "dirs [-v | -c]",
+        summary: "show the directory stack",
+        details: "Print the cwd, then the directory stack from the top, on one line.\n\
+                  With -v, print them one to a line, numbered from 0 for the cwd;\n\
+                  with -c, empty the stack.",
+        run: cmd_dirs,
+    },
+    Command {
+        name: "mkdir",
+        usage: "mkdir dirname",
+        summary: "create a directory",
+        details: "Create a new directory named dirname in the cwd. It stays in memory\n\
+                  until 'sync'.",
+        run: cmd_mkdir,
+    },
+    Command {
+        name: "mknod",
+        usage: "mknod name c|b major minor | mknod name p|s",
+        summary: "create a device node, FIFO or socket",
+        details: "Create a character (c) or block (b) device with the given major and\n\
+                  minor numbers, a FIFO (p) or a socket (s), named name in the cwd,\n\
+                  with permissions rw-r--r--. It stays in memory until 'sync'.",
+        run: cmd_mknod,
+    },
+    Command {
+        name: "touch",
+        usage: "touch path...",
+        summary: "create empty files, or update their times",
+        details: "Create each path that doesn't exist as an empty regular file, with\n\
+                  permissions rw-r--r--, and set the access and modification times of\n\
+                  each one that does to now. It stays in memory until 'sync'.",
+        run: cmd_touch,
+    },
+    Command {
+        name: "cat",
+        usage: "cat path",
+        summary: "print the contents of a file",
+        details: "Write the contents of the file at path to stdout.",
+        run: cmd_cat,
+    },
+    Command {
+        name: "strings",
+        usage: "strings [-t x] path [min_len]",
+        summary: "print the text in a binary file",
+        details: "Print every run of at least min_len (4 by default) printable ASCII\n\
+                  characters in the file at path, one to a line, like strings(1). With\n\
+                  -t x, each is preceded by its offset in the file, in hex.",
+        run: cmd_strings,
+    },
+    Command {
+        name: "icat",
+        usage: "icat inode",
+        summary: "print the contents of an inode by number",
+        details: "Write the data of the given inode number to stdout, whether or not\n\
+                  any directory links to it.",
+        run: cmd_icat,
+    },
+    Command {
+        name: "open",
+        usage: "open path",
+        summary: "open a file for reading, printing its descriptor",
+        details: "Open the file at path for reading at offset 0 and print its file
+                  descriptor, the lowest number from 3 not in use, for read, seek and
+                  close. It stays open across cd, and like in POSIX, removing the file
+                  only removes its name: it can still be read, and its inode and blocks
+                  are only freed when it's closed.",
+        run: cmd_open,
+    },
+    Command {
+        name: "read",
+        usage: "read fd count",
+        summary: "print the next bytes of an open file",
+        details: "Write up to count bytes of the open file fd, from its offset, to
+                  stdout, and move the offset past them. At the end of the file
+                  nothing is written.",
+        run: cmd_read,
+    },
+    Command {
+        name: "seek",
+        usage: "seek fd [+|-]pos",
+        summary: "move the offset of an open file",
+        details: "Move the offset of the open file fd to pos, or by pos from where it
+                  is with + or -. It may go past the end of the file, but not before
+                  the start.",
+        run: cmd_seek,
+    },
+    Command {
+        name: "close",
+        usage: "close fd",
+        summary: "close an open file",
+        details: "Close the open file fd, freeing its number for the next open. If the
+                  file was removed while open, it's deleted now.",
+        run: cmd_close,
+    },
+    Command {
+        name: "lsof",
+        usage: "lsof",
+        summary: "list open files",
+        details: "List the files opened with open: their descriptors, inodes, offsets
+                  and the paths they were opened by, marked (deleted) once removed.",
+        run: cmd_lsof,
+    },
+    Command {
+        name: "istat",
+        usage: "istat [-h | --json] inode",
+        summary: "dump an inode's raw fields by number",
+        details: "Print every field of the given inode number: type and permissions,\n\
+                  owner, size, timestamps (including dtime), link count, flags and\n\
+                  all block pointers. With -h, the size is shown like 1.5K rather than\n\
+                  in bytes; with --json, as one JSON object.",
+        run: cmd_istat,
+    },
+    Command {
+        name: "stat",
+        usage: "stat [-h | --json] path",
+        summary: "show a file's inode by path",
+        details: "Print the path given, then every field of the inode it leads to, as\n\
+                  istat does: type and permissions, owner, size, timestamps, link\n\
+                  count, flags, device numbers and block pointers. With -h, the size\n\
+                  is shown like 1.5K rather than in bytes; with --json, as one JSON\n\
+                  object, with the path added.",
+        run: cmd_stat,
+    },
+    Command {
+        name: "dump",
+        usage: "dump [--format json|ron] superblock|group N|inode N",
+        summary: "serialize a raw on-disk structure field by field",
+        details: "Print every field of the superblock, block group descriptor N or inode\n\
+                  N exactly as stored, in JSON (the default) or RON, e.g. for diffing\n\
+                  two images' metadata or saving expected values. Unlike istat, the\n\
+                  inode needn't be allocated.",
+        run: cmd_dump,
+    },
+    Command {
+        name: "icheck",
+        usage: "icheck block...",
+        summary: "find the inodes owning blocks",
+        details: "Print the inode owning each block given, as data, as an indirect\n\
+                  block or as its extended attribute block. Blocks holding superblocks,\n\
+                  descriptor tables, bitmaps or inode tables are shown as metadata;\n\
+                  blocks with more than one owner mean the filesystem is damaged.",
+        run: cmd_icheck,
+    },
+    Command {
+        name: "ncheck",
+        usage: "ncheck inode...",
+        summary: "find the paths linking to inodes",
+        details: "Print every path that links to each inode number given, walking the\n\
+                  tree from the root, so a file with several hard links shows up once\n\
+                  per name.",
+        run: cmd_ncheck,
+    },
+    Command {
+        name: "getfattr",
+        usage: "getfattr [-d] path",
+        summary: "list a file's extended attributes",
+        details: "Print the name of every extended attribute of the file at path, in\n\
+                  every namespace. With -d, print each value too: in quotes if it's\n\
+                  text, in hex otherwise, with POSIX ACLs also decoded like getfacl.",
+        run: cmd_getfattr,
+    },
+    Command {
+        name: "blkcat",
+        usage: "blkcat block",
+        summary: "hexdump a filesystem block by number",
+        details: "Print a hexdump of the given filesystem block, in the style of\n\
+                  `hexdump -C`. Runs of identical lines are collapsed to `*`.",
+        run: cmd_blkcat,
+    },
+    Command {
+        name: "bmap",
+        usage: "bmap path logical_block",
+        summary: "map a file's logical block to a physical block",
+        details: "Print the physical block holding the given logical block of the\n\
+                  file at path, or `hole` if that block is sparse.",
+        run: cmd_bmap,
+    },
+    Command {
+        name: "blocktree",
+        usage: "blocktree path",
+        summary: "show the tree of a file's block pointers",
+        details: "Print the block pointers of the file at path as an indented tree:\n\
+                  the direct pointers, then the indirect block and the data blocks it\n\
+                  points to, then the doubly and triply indirect blocks and the\n\
+                  indirect blocks under them. Pointers to consecutive blocks are shown\n\
+                  as one range, e.g. `logical 12-267: blocks 270-525, contiguous`, and\n\
+                  so are holes. Indirect blocks that each sit right before their data,\n\
+                  one after another, are summed up in a line, so even a big file's\n\
+                  tree stays short. Pointers past the end of the file that aren't in\n\
+                  use are left out; holes inside it are shown.",
+        run: cmd_blocktree,
+    },
+    Command {
+        name: "dirblocks",
+        usage: "dirblocks path",
+        summary: "show the entry slots in each block of a directory",
+        details: "For each data block of the directory at path, list every slot in\n\
+                  order, as it is on disk: its offset in the block, inode, rec_len,\n\
+                  name_len, type indicator, the padding after the name up to the next\n\
+                  slot, and the name. Unused slots (inode 0) are shown too, with\n\
+                  whatever name is left in them. A slot that doesn't make sense, e.g.\n\
+                  with a rec_len that isn't a multiple of 4 or a name that runs past\n\
+                  its rec_len, is marked with what's wrong, and ends the block.",
+        run: cmd_dirblocks,
+    },
+    Command {
+        name: "frag",
+        usage: "frag path [-v]",
+        summary: "show how fragmented a file is",
+        details: "Print how many data blocks the file at path has and how many runs of\n\
+                  contiguous blocks (extents) they form. With -v, also list each extent\n\
+                  as its first logical block, first physical block and length.",
+        run: cmd_frag,
+    },
+    Command {
+        name: "fsinfo",
+        usage: "fsinfo [--backups | --json]",
+        summary: "describe the superblock and block groups",
+        details: "Print the superblock in labeled sections (volume, features, geometry,\n\
+                  mounts), followed by a table of every block group's bitmap and\n\
+                  inode table locations and free counts, like dumpe2fs.\n\
+                  With --backups, compare each backup superblock to the primary instead;\n\
+                  with --json, print the same fields as one JSON object.",
+        run: cmd_fsinfo,
+    },
+    Command {
+        name: "df",
+        usage: "df [-h | --json]",
+        summary: "show free space and inodes",
+        details: "Print the total, used and available blocks (in KiB) and inodes. Blocks\n\
+                  reserved for root are shown separately, and don't count as available\n\
+                  unless the current user may use them (see 'su'). With -h, sizes are\n\
+                  shown like 1.5G instead; with --json, as one JSON object.",
+        run: cmd_df,
+    },
+    Command {
+        name: "defrag",
+        usage: "defrag path",
+        summary: "move a file's blocks together",
+        details: "Copy the blocks of the file at path into the longest runs of free\n\
+                  blocks there are, using as few as it takes, and free the old ones.\n\
+                  The file is left where it is unless that leaves it in fewer extents\n\
+                  (see 'frag'). It stays in memory until 'sync'.",
+        run: cmd_defrag,
+    },
+    Command {
+        name: "compactdir",
+        usage: "compactdir path",
+        summary: "shrink a directory to the blocks its entries need",
+        details: "Pack the entries of the directory at path into as few blocks as they\n\
+                  fit in, with . and .. first, free the blocks after them and cut the\n\
+                  directory's size to match. Worth it after most of a big directory's\n\
+                  entries are removed, which otherwise keeps all its blocks. Entries\n\
+                  may change order, and a hash index is dropped (e2fsck -D rebuilds\n\
+                  it). It stays in memory until 'sync'.",
+        run: cmd_compactdir,
+    },
+    Command {
+        name: "punch",
+        usage: "punch path offset len",
+        summary: "free the blocks of part of a file, leaving a hole",
+        details: "Make the len bytes of the file at path from byte offset a hole: the\n\
+                  blocks the range covers whole are freed, along with any indirect\n\
+                  block left pointing at nothing, and the rest of the range is zeroed.\n\
+                  The size doesn't change, and the range reads as zeros. It stays in\n\
+                  memory until 'sync'.",
+        run: cmd_punch,
+    },
+    Command {
+        name: "fallocate",
+        usage: "fallocate [--keep-size=false] path len",
+        summary: "reserve blocks for a file ahead of writing it",
+        details: "Give the file at path blocks for its first len bytes wherever it has\n\
+                  none, in one run of free blocks if there's one long enough, so that\n\
+                  writing it later fills blocks that are one after another. The size\n\
+                  stays as it is unless --keep-size=false is given, which grows a\n\
+                  shorter file to len bytes of zeros. It stays in memory until 'sync'.",
+        run: cmd_fallocate,
+    },
+    Command {
+        name: "stats",
+        usage: "stats [count]",
+        summary: "summarize the files on the filesystem",
+        details: "Print how many files of each type there are and their total size, the\n\
+                  count biggest regular files (10 by default), how many entries\n\
+                  directories hold on average, and a histogram of regular file sizes\n\
+                  in powers of two.",
+        run: cmd_stats,
+    },
+    Command {
+        name: "fsmap",
+        usage: "fsmap [--bar]",
+        summary: "show how each block group's blocks are used",
+        details: "Print, for every block group, its first block and how many of its\n\
+                  blocks hold metadata (superblock and descriptor copies, bitmaps, inode\n\
+                  table), how many hold other data, and how many are free. With --bar,\n\
+                  also draw each group left to right: `M` where there's metadata, `#`\n\
+                  where every block is in use, `+` where some are, `.` where none are.",
+        run: cmd_fsmap,
+    },
+    Command {
+        name: "freemap",
+        usage: "freemap",
+        summary: "show how fragmented the free space is",
+        details: "Print, for every block group, how many blocks are free, how many\n\
+                  runs of consecutive free blocks (extents) they make, and the longest\n\
+                  run with where it starts. Then print how many runs there are of each\n\
+                  range of lengths, 1, 2-3, 4-7 and so on, with the free blocks in them\n\
+                  and their share of all the free blocks. Runs stop at the end of a\n\
+                  group.",
+        run: cmd_freemap,
+    },
+    Command {
+        name: "ilist",
+        usage: "ilist reserved",
+        summary: "list the reserved inodes and what they're for",
+        details: "List the inodes below the first usable one, 1 to 10 unless the\n\
+                  superblock keeps back more: what each is for by convention, whether\n\
+                  the inode bitmap has it allocated (mke2fs allocates them all), and\n\
+                  whether it holds anything on this image, with its size and blocks.\n\
+                  Then the first inode files may use, and lost+found's.",
+        run: cmd_ilist,
+    },
+    Command {
+        name: "badblocks",
+        usage: "badblocks",
+        summary: "list the blocks marked unusable",
+        details: "Print the blocks of the bad blocks inode (inode 1), which are the\n\
+                  blocks mke2fs or e2fsck found unreadable and took out of use.",
+        run: cmd_badblocks,
+    },
+    Command {
+        name: "fsck",
+        usage: "fsck [--repair]",
+        summary: "check the filesystem for inconsistencies",
+        details: "Walk the directory tree and every allocated inode, then compare what\n\
+                  is actually in use against the bitmaps, free counts, directory counts\n\
+                  and link counts. Prints one line per problem found; nothing is changed.\n\
+                  With --repair, fix the mechanical problems: relink orphaned inodes into\n\
+                  /lost+found, fix link counts and recompute the free and directory\n\
+                  counts. Freeing unreferenced inodes and unused blocks asks first.\n\
+                  Repairs stay in memory until 'sync'.",
+        run: cmd_fsck,
+    },
+    Command {
+        name: "sync",
+        usage: "sync",
+        summary: "write changes back to the image file",
+        details: "Write every block modified since the last sync (or since the image\n\
+                  was loaded) back to the image file given on the command line; with\n\
+                  nothing modified, nothing is written. Syncing drops any snapshot,\n\
+                  asking first if 'rollback' would still undo something.",
+        run: cmd_sync,
+    },
+    Command {
+        name: "snapshot",
+        usage: "snapshot",
+        summary: "remember the current state, for 'rollback'",
+        details: "Remember the filesystem as it is now, in memory, replacing any earlier\n\
+                  snapshot. Nothing is copied until it's next modified.",
+        run: cmd_snapshot,
+    },
+    Command {
+        name: "rollback",
+        usage: "rollback",
+        summary: "undo everything since the last 'snapshot'",
+        details: "Discard every modification made since 'snapshot', which stays, so it\n\
+                  can be rolled back to again. The image file isn't touched.",
+        run: cmd_rollback,
+    },
+    Command {
+        name: "lsattr",
+        usage: "lsattr path",
+        summary: "show a file's inode flags",
+        details: "Print the flags of the file at path as one letter per flag, or `-`\n\
+                  where it's not set, in lsattr's order: s (secure deletion),\n\
+                  u (undeletable), S (sync), D (dirsync), i (immutable), a (append only),\n\
+                  d (no dump), A (no atime), c (compressed), j (data journaling),\n\
+                  I (hash-indexed directory), t (no tail merging), T (top of directory\n\
+                  hierarchy), e (extents).",
+        run: cmd_lsattr,
+    },
+    Command {
+        name: "chattr",
+        usage: "chattr +i|-i|+a|-a path",
+        summary: "set or clear the immutable or append-only flag",
+        details: "Set (+) or clear (-) the immutable (i) or append-only (a) flag of the\n\
+                  file at path; both letters can be given at once, e.g. +ia. Nothing can\n\
+                  change an immutable file, and an append-only one can only grow. Only\n\
+                  root may change these flags.",
+        run: cmd_chattr,
+    },
+    Command {
+        name: "su",
+        usage: "su uid [gid]",
+        summary: "run commands as another user",
+        details: "Check permissions as user uid from now on, in group gid, or in the\n\
+                  group with the same number as the user if gid isn't given. The shell\n\
+                  starts as root (uid 0), which may do almost anything.",
+        run: cmd_su,
+    },
+    Command {
+        name: "whoami",
+        usage: "whoami",
+        summary: "print the current user and group",
+        details: "Print the uid and gid that permissions are checked against.",
+        run: cmd_whoami,
+    },
+    Command {
+        name: "access",
+        usage: "access path r|w|x",
+        summary: "check whether the current user may access a file",
+        details: "Print whether the current user may read (r), write (w) or execute (x)\n\
+                  the file at path, judged by its owner, group and mode like POSIX.\n\
+                  Letters can be combined, e.g. `access path rw`.",
+        run: cmd_access,
+    },
+    Command {
+        name: "undelete",
+        usage: "undelete list | undelete inode [newname]",
+        summary: "find and recover deleted files",
+        details: "`undelete list` shows every deleted inode whose block pointers survive,\n\
+                  with its size, deletion time, any names still legible in directory\n\
+                  slack, and whether its blocks are still free.\n\
+                  `undelete inode newname` links that inode into the cwd as newname and\n\
+                  marks it and its blocks in use again; it's refused if any of the\n\
+                  blocks have been reused. Without a newname it goes in /lost+found as\n\
+                  #inode instead. It stays in memory until 'sync'.",
+        run: cmd_undelete,
+    },
+    Command {
+        name: "orphans",
+        usage: "orphans | orphans relink inode",
+        summary: "list or relink inodes no directory refers to",
+        details: "`orphans` lists every inode marked in use that has a zero link count or\n\
+                  that no entry reachable from the root refers to.\n\
+                  `orphans relink inode` links one of them into /lost+found as #inode,\n\
+                  creating /lost+found first if needed. Only root may relink.",
+        run: cmd_orphans,
+    },
+    Command {
+        name: "rm",
+        usage: "rm [-rif] path...",
+        summary: "remove files, or whole directory trees with -r",
+        details: "Unlink each path. A file's data is freed once its last link is gone;\n\
+                  a symlink is removed itself, not what it points to. Directories\n\
+                  are refused unless -r is given, which removes everything under\n\
+                  them first, deepest first, stopping at the first thing that can't\n\
+                  be removed. -i asks before removing each entry, and -f says\n\
+                  nothing about paths that don't exist. It stays in memory until\n\
+                  'sync'.",
+        run: cmd_rm,
+    },
+    Command {
+        name: "rmdir",
+        usage: "rmdir path...",
+        summary: "remove empty directories",
+        details: "Remove each path, which must be an empty directory. It stays in\n\
+                  memory until 'sync'.",
+        run: cmd_rmdir,
+    },
+    Command {
+        name: "mv",
+        usage: "mv source dest",
+        summary: "rename or move a file or directory",
+        details: "Rename source to dest, or if dest is a directory, move source into it\n\
+                  under its own name. An existing file at the destination is replaced,\n\
+                  and so is an empty directory when source is a directory. It stays in\n\
+                  memory until 'sync'.",
+        run: cmd_mv,
+    },
+    Command {
+        name: "mount",
+        usage: "mount [host_filename mountpoint]",
+        summary: "mount another image read-only, or list mounts",
+        details: "Open the ext2 image host_filename read-only and mount it over the empty\n\
+                  directory mountpoint. For now only 'cmp' and 'diff' look through\n\
+                  mountpoints. With no arguments, list what's mounted.",
+        run: cmd_mount,
+    },
+    Command {
+        name: "mkfs",
+        usage: "mkfs host_filename size_kb [block_size]",
+        summary: "create a new, empty ext2 image on the host",
+        details: "Write a fresh ext2 filesystem of size_kb KiB to the host file\n\
+                  host_filename, which is created or overwritten. block_size is 1024\n\
+                  (the default), 2048 or 4096. The shell keeps using the current\n\
+                  image; start another on the new one, or 'mount' it.",
+        run: cmd_mkfs,
+    },
+    Command {
+        name: "resize",
+        usage: "resize [blocks]",
+        summary: "grow the filesystem into the rest of the image file",
+        details: "Grow the filesystem to the given number of blocks, or to the whole\n\
+                  image file, adding block groups after the current ones. Make the file\n\
+                  bigger on the host first, e.g. with `truncate -s`. Shrinking isn't\n\
+                  supported. It stays in memory until 'sync'.",
+        run: cmd_resize,
+    },
+    Command {
+        name: "label",
+        usage: "label [name]",
+        summary: "print or set the volume name",
+        details: "Print the volume name, or set it to name, like e2label. It holds at\n\
+                  most 16 bytes; a longer name is cut short, with a warning. The backup\n\
+                  superblocks are updated too. It stays in memory until 'sync'.",
+        run: cmd_label,
+    },
+    Command {
+        name: "uuid",
+        usage: "uuid [--regenerate]",
+        summary: "print or replace the filesystem UUID",
+        details: "Print the filesystem's UUID. With --regenerate, give it a fresh\n\
+                  random one, in the backup superblocks too, and print that. It stays in\n\
+                  memory until 'sync'.",
+        run: cmd_uuid,
+    },
+    Command {
+        name: "populate",
+        usage: "populate host_dir [dest]",
+        summary: "copy a directory tree from the host into the image",
+        details: "Copy everything under host_dir into the directory dest (an absolute\n\
+                  path, / by default): directories, files and symlinks, with their\n\
+                  permission bits and times. If it fails part way, e.g. for lack of\n\
+                  space, nothing of it is kept. It stays in memory until 'sync'.",
+        run: cmd_populate,
+    },
+    Command {
+        name: "tar",
+        usage: "tar output.tar [path]",
+        summary: "export a directory tree to the host as a tar archive",
+        details: "Write everything under the directory path (the current directory by\n\
+                  default) to the host file output.tar, as a POSIX ustar archive with\n\
+                  paths relative to it. Hard links are kept as links to the first name\n\
+                  stored; sockets are left out.",
+        run: cmd_tar,
+    },
+    Command {
+        name: "untar",
+        usage: "untar archive.tar [destdir]",
+        summary: "import a tar archive from the host into the image",
+        details: "Recreate what's in the host tar archive under the directory destdir\n\
+                  (the current directory by default): directories, files, symlinks,\n\
+                  hard links, devices and FIFOs, with their permission bits, owners and\n\
+                  modification times. Entries of other kinds are skipped. If it fails\n\
+                  part way, nothing of it is kept. It stays in memory until 'sync'.",
+        run: cmd_untar,
+    },
+    Command {
+        name: "timeline",
+        usage: "timeline [path] [--deleted] [--output file.csv]",
+        summary: "list every file's timestamps, oldest change first, as CSV",
+        details: "Write a CSV row for every entry below path (the cwd by default),\n\
+                  sorted by modification time: full path, inode, size, uid, gid, mode\n\
+                  in octal, and atime, mtime, ctime and dtime in seconds since the\n\
+                  epoch. With --deleted, deleted files undelete could still recover\n\
+                  are included, marked (deleted). Written to stdout, or to the host\n\
+                  file given with --output.",
+        run: cmd_timeline,
+    },
+    Command {
+        name: "cmp",
+        usage: "cmp path1 path2",
+        summary: "compare two files byte by byte",
+        details: "Print the first byte at which the two files differ, or that they're\n\
+                  identical. Either may be on a mounted image.",
+        run: cmd_cmp,
+    },
+    Command {
+        name: "diff",
+        usage: "diff -r [--skip-lost+found] dir1 dir2",
+        summary: "compare two directory trees",
+        details: "Walk both trees and list names found in only one of them, and files\n\
+                  whose type, size or contents differ. Either may be on a mounted image.\n\
+                  With --skip-lost+found, a root's lost+found is left out.",
+        run: cmd_diff,
+    },
+    Command {
+        name: "image-diff",
+        usage: "image-diff [--json] [--skip-lost+found] other.ext2",
+        summary: "compare this image with another, block by block and file by file",
+        details: "Load the image file other.ext2 and compare it, as B, with this one, as\n\
+                  A: which blocks differ, which paths exist on one side only, which\n\
+                  inodes differ in metadata and which files differ in contents, by\n\
+                  SHA-256. Images with different block sizes or UUIDs are compared by\n\
+                  contents only. One difference is printed per line, in a fixed order,\n\
+                  and nothing at all for identical images:\n\
+                  \n\
+                  \x20   block N | blocks N-M\n\
+                  \x20   only-a PATH | only-b PATH\n\
+                  \x20   metadata INODE_A INODE_B FIELD,... PATH\n\
+                  \x20   content HASH_A HASH_B PATH\n\
+                  \n\
+                  With --json, the same report is printed as one JSON object. With\n\
+                  --skip-lost+found, paths in /lost+found are left out.",
+        run: cmd_image_diff,
+    },
+    Command {
+        name: "biggest",
+        usage: "biggest [-a] [N] [path]",
+        summary: "list the largest files",
+        details: "List the N (10 by default) largest regular files below path (the cwd\n\
+                  by default), largest first, with their sizes. A file with hard links\n\
+                  is listed once. /lost+found is left out unless -a is given.",
+        run: cmd_biggest,
+    },
+    Command {
+        name: "recent",
+        usage: "recent [-a] [N] [path]",
+        summary: "list the most recently modified files",
+        details: "List the N (10 by default) most recently modified regular files below\n\
+                  path (the cwd by default), newest first, with their modification\n\
+                  times. A file with hard links is listed once. /lost+found is left\n\
+                  out unless -a is given.",
+        run: cmd_recent,
+    },
+    Command {
+        name: "find",
+        usage: "find [--json] [path] [-name pattern] [-type f|d|l|c|b|p|s]",
+        summary: "list the entries below a directory",
+        details: "Print the path of every entry below path (the cwd by default), depth\n\
+                  first, like find(1). -name keeps the entries whose name matches\n\
+                  pattern, where * stands for any run of characters and ? for any one;\n\
+                  -type keeps those of one type: f regular file, d directory, l\n\
+                  symlink, c and b character and block device, p FIFO, s socket. With\n\
+                  --json, print them as a JSON array of objects with each one's path,\n\
+                  name, inode, type, mode, owner, links, size and times instead.",
+        run: cmd_find,
+    },
+    Command {
+        name: "browse",
+        usage: "browse [path]",
+        summary: "browse disk usage interactively, like ncdu",
+        details: "Show the entries of path (the cwd by default) with the disk space each\n\
+                  takes up, everything below it included for a directory, biggest\n\
+                  first. Up and down (or k and j) move, Enter (or right or l) goes into\n\
+                  a directory, Backspace (or left or h) goes back up as far as path,\n\
+                  and q quits. Totals are kept until the filesystem is modified, so\n\
+                  going back into a directory is instant. A file with hard links is\n\
+                  counted under each of them. When stdin or stdout isn't a terminal,\n\
+                  the entries of path and its total are printed instead.",
+        run: cmd_browse,
+    },
+    Command {
+        name: "dedup-report",
+        usage: "dedup-report [path]",
+        summary: "find files with the same contents",
+        details: "List the groups of regular files below path (the cwd by default) with\n\
+                  identical contents, most wasted space first, and the total space\n\
+                  taken up by the extra copies. Hard links to one file count as one file\n\
+                  and are shown together on a line. Empty files are left out.",
+        run: cmd_dedup_report,
+    },
+    Command {
+        name: "dedup",
+        usage: "dedup --link [path]",
+        summary: "replace copies of files with hard links",
+        details: "Find the duplicates dedup-report shows, and make every copy in each\n\
+                  group a hard link to the first one listed, freeing the copies'\n\
+                  blocks. Only contents are compared: the copies' owners, permissions\n\
+                  and times are lost to the first file's.",
+        run: cmd_dedup,
+    },
+    Command {
+        name: "link",
+        usage: "link arg_1 arg_2",
+        summary: "create a hard link (not yet implemented)",
+        details: "Create a hard link from arg_1 to arg_2.",
+        run: cmd_link,
+    },
+    Command {
+        name: "set",
+        usage: "set [key value]",
+        summary: "show or change shell settings",
+        details: "Print every setting with its value, marking those left at their\n\
+                  default, or change one:\n\
+                  \x20 pager    on|off  show long output of cat, ls and help a screenful\n\
+                  \x20                  at a time, like less (on by default; never when\n\
+                  \x20                  stdin or stdout isn't a terminal, or for a command\n\
+                  \x20                  given on the command line)\n\
+                  \x20 color    auto|always|never\n\
+                  \x20                  when ls colors names without --color (auto, when\n\
+                  \x20                  stdout is a terminal, by default)\n\
+                  \x20 json     on|off  print what ls, stat, istat, find, fsinfo, df and\n\
+                  \x20                  image-diff show as JSON without --json (off by\n\
+                  \x20                  default)\n\
+                  \x20 icase    on|off  find a name that differs only by case when there's\n\
+                  \x20                  no exact match, e.g. README.TXT for readme.txt, in\n\
+                  \x20                  every path; a name more than one entry matches that\n\
+                  \x20                  way is an error (off by default)\n\
+                  \x20 noatime  on|off  leave access times alone when files are read (off\n\
+                  \x20                  by default, or on with --noatime)\n\
+                  \x20 lenient  on|off  read what can be read of a damaged image rather\n\
+                  \x20                  than stopping at errors; only while it's open\n\
+                  \x20                  read-only (off by default, or on with --lenient)\n\
+                  Settings usually go in ~/.ext2shrc, which the shell runs at startup.",
+        run: cmd_set,
+    },
+    Command {
+        name: "unset",
+        usage: "unset key",
+        summary: "put a shell setting back to its default",
+        details: "Put the setting `key` back to its default; see `help set` for the\n\
+                  settings and their defaults.",
+        run: cmd_unset,
+    },
+    Command {
+        name: "alias",
+        usage: "alias [name[=text]...]",
+        summary: "define or show command aliases",
+        details: "With name=text, make `name` at the start of a line stand for `text`,\n\
+                  e.g. alias ll='ls -l', so `ll dir` runs `ls -l dir`; with just a\n\
+                  name, show what it stands for; with nothing, list every alias. An\n\
+                  alias is expanded once, before the line is split into words, so\n\
+                  quotes in its text work as if typed: an alias starting with its own\n\
+                  name, or another alias's, runs that command rather than expanding\n\
+                  again. Quoting the first word, e.g. 'ls', skips expansion.\n\
+                  `alias` and `unalias` can't be aliases. Aliases last until the shell\n\
+                  exits, so ones wanted every time go in ~/.ext2shrc.",
+        run: cmd_alias,
+    },
+    Command {
+        name: "unalias",
+        usage: "unalias -a | unalias name...",
+        summary: "remove command aliases",
+        details: "Remove the aliases named, or with -a, every alias.",
+        run: cmd_unalias,
+    },
+    Command {
+        name: "quit",
+        usage: "quit",
+        summary: "leave the shell",
+        details: "Leave the shell. `exit` and Ctrl-D do the same.",
+        run: cmd_quit,
+    },
+    Command {
+        name: "exit",
+        usage: "exit",
+        summary: "leave the shell",
+        details: "Leave the shell. `quit` and Ctrl-D do the same.",
+        run: cmd_quit,
+    },
+];
+
+fn find_command(name: &str) -> Option<&'static Command> {
+    COMMANDS.iter().find(|cmd| cmd.name == name)
+}
+
+fn cmd_help(shell: &mut Shell, args: &[&str]) -> CommandResult {
+    let mut out = Output::new(shell);
+    match args {
+        [] => {
+            let width = COMMANDS
+                .iter()
+                .map(|cmd| cmd.usage.len())
+                .max()
+                .unwrap_or(0);
+            for cmd in COMMANDS {
+                writeln!(
+                    out,
+                    "  {:width$}  {}",
+                    cmd.usage,
+                    cmd.summary,
+                    width = width
+                )?;
+            }
+        }
+        [name] => match find_command(name) {
+            Some(cmd) => {
+                writeln!(out, "usage: {}", cmd.usage)?;
+                writeln!(out, "{}", cmd.details)?;
+            }
+            None => writeln!(out, "help: no such command: {}", name)?,
+        },
+        _ => return Err(CommandError::Usage),
+    }
+    out.finish()?;
+    Ok(())
+}
+
+fn cmd_ls(shell: &mut Shell, args: &[&str]) -> CommandResult {
+    // `ls` prints our cwd's children, or those of the directory given
+    let mut color = shell.config.color.enabled();
+    let mut show_inode = false;
+    let mut long = false;
+    let mut human = false;
+    let mut sort = LsSort::Name;
+    let mut reverse = false;
+    let mut json = shell.config.json;
+    let mut raw = false;
+    // the last of -a and -A wins, like in coreutils
+    let mut all = false;
+    let mut almost_all = false;
+    let mut path = None;
+    for arg in args {
+        match *arg {
+            "--json" => json = true,
+            "--raw" => raw = true,
+            "--color" | "--color=always" => color = true,
+            "--color=never" => color = false,
+            "--color=auto" => color = io::stdout().is_terminal(),
+            flags if flags.starts_with('-') && !flags.starts_with("--") && flags.len() > 1 => {
+                for flag in flags[1..].chars() {
+                    match flag {
+                        'a' => (all, almost_all) = (true, false),
+                        'A' => (all, almost_all) = (false, true),
+                        'i' => show_inode = true,
+                        'l' => long = true,
+                        'h' => human = true,
+                        'S' => sort = LsSort::Size,
+                        't' => sort = LsSort::Mtime,
+                        'r' => reverse = true,
+                        _ => return Err(CommandError::Usage),
+                    }
+                }
+            }
+            _ if !arg.starts_with('-') && path.is_none() => path = Some(*arg),
+            _ => return Err(CommandError::Usage),
+        }
+    }
+
+    let (dir, path) = match path {
+        Some(path) => (resolve(shell, path)?, path),
+        None => (shell.cwd, "."),
+    };
+    if !shell.ext2.get_inode(dir)?.is_dir() {
+        return Err(Ext2Error::NotADirectory {
+            name: path.to_string(),
+        }
+        .into());
+    }
+    require_access(shell, dir, path, AccessMode::READ)?;
+
+    // fetch each entry's inode once, then sort the (name, inode_no, inode) triples
+    let names: Vec<(usize, Vec<u8>)> = cwd_index(&shell.ext2, dir, &mut shell.cwd_index)?
+        .entries()
+        .filter(|(_, name)| match name.kind() {
+            NameKind::Dot => all,
+            NameKind::Hidden => all || almost_all,
+            NameKind::Visible => true,
+        })
+        .map(|(inode, name)| (inode, name.as_bytes().to_vec()))
+        .collect();
+    let mut entries: Vec<(&[u8], usize, &Inode)> = Vec::with_capacity(names.len());
+    for (inode, name) in &names {
+        entries.push((name, *inode, shell.ext2.get_inode(*inode)?));
+    }
+    entries.sort_by(|a, b| {
+        let by_name = a.0.cmp(b.0);
+        match sort {
+            LsSort::Name => by_name,
+            LsSort::Size => b.2.size().cmp(&a.2.size()).then(by_name),
+            LsSort::Mtime => b.2.mtime.cmp(&a.2.mtime).then(by_name),
+        }
+    });
+    if reverse {
+        entries.reverse();
+    }
+    if json {
+        let infos: Vec<EntryInfo> = entries
+            .iter()
+            .map(|(name, inode_no, inode)| {
+                EntryInfo::new(&String::from_utf8_lossy(name), *inode_no, inode)
+            })
+            .collect();
+        print_json(&infos);
+        return Ok(());
+    }
+
+    let inode_width = entries
+        .iter()
+        .map(|e| e.1.to_string().len())
+        .max()
+        .unwrap_or(0);
+    let mut out = Output::new(shell);
+    if long {
+        print_long(
+            &mut out,
+            &entries,
+            show_inode.then_some(inode_width),
+            color,
+            human,
+            raw,
+        )?;
+        out.finish()?;
+        return Ok(());
+    }
+    let mut names = Vec::with_capacity(entries.len());
+    let mut colors = Vec::with_capacity(entries.len());
+    for (name, inode_no, inode) in &entries {
+        let name = show_name(name, raw);
+        if show_inode {
+            names.push(format!(
+                "{:>width$} {}",
+                inode_no,
+                name,
+                width = inode_width
+            ));
+        } else {
+            names.push(name);
+        }
+        colors.push(if color { ls_color(inode) } else { None });
+    }
+    let width = terminal_size().map_or(80, |(Width(w), _)| w as usize);
+    let layout = ColumnLayout::new(&names, width);
+    for row in 0..layout.rows {
+        for (col, col_width) in layout.widths.iter().enumerate() {
+            let idx = col * layout.rows + row;
+            if idx >= names.len() {
+                break;
+            }
+            let name = &names[idx];
+            match colors[idx] {
+                Some(code) => write!(out, "\x1b[{}m{}\x1b[0m", code, name)?,
+                None => write!(out, "{}", name)?,
+            }
+            // pad every column but the last one on this row
+            if (col + 1) * layout.rows + row < names.len() {
+                write!(out, "{:1$}", "", col_width - name.chars().count())?;
+            }
+        }
+        writeln!(out)?;
+    }
+    out.finish()?;
+    Ok(())
+}
+
+/// Write `ls -l` lines for `entries` to `out`, with inode numbers in a
+/// column of `inode_width` if given, sizes like `1.5K` if `human`, and names
+/// unescaped if `raw`.
+fn print_long(
+    out: &mut impl Write,
+    entries: &[(&[u8], usize, &Inode)],
+    inode_width: Option<usize>,
+    color: bool,
+    human: bool,
+    raw: bool,
+) -> io::Result<()> {
+    // devices show "major, minor" where files show their size
+    let sizes: Vec<String> = entries
+        .iter()
+        .map(|(_, _, inode)| match inode.device() {
+            Some((major, minor)) => format!("{}, {}", major, minor),
+            None if human => ext2::human_size(inode.size()),
+            None => inode.size().to_string(),
+        })
+        .collect();
+    let column = |f: &dyn Fn(&Inode) -> String| {
+        entries
+            .iter()
+            .map(|(_, _, inode)| f(inode).len())
+            .max()
+            .unwrap_or(0)
+    };
+    let links_width = column(&|inode| inode.hard_links.to_string());
+    let uid_width = column(&|inode| inode.uid.to_string());
+    let gid_width = column(&|inode| inode.gid.to_string());
+    let size_width = sizes.iter().map(String::len).max().unwrap_or(0);
+    for ((name, inode_no, inode), size) in entries.iter().zip(&sizes) {
+        if let Some(width) = inode_width {
+            write!(out, "{:>width$} ", inode_no, width = width)?;
+        }
+        // "YYYY-MM-DD HH:MM", without the seconds and time zone
+        let mtime = ext2::format_time(inode.mtime);
+        let name = show_name(name, raw);
+        let name = match ls_color(inode).filter(|_| color) {
+            Some(code) => format!("\x1b[{}m{}\x1b[0m", code, name),
+            None => name,
+        };
+        writeln!(
+            out,
+            "{} {:>lw$} {:>uw$} {:>gw$} {:>sw$} {} {}",
+            mode_string(inode),
+            inode.hard_links,
+            inode.uid,
+            inode.gid,
+            size,
+            mtime.get(..16).unwrap_or(&mtime),
+            name,
+            lw = links_width,
+            uw = uid_width,
+            gw = gid_width,
+            sw = size_width,
+        )?;
+    }
+    Ok(())
+}
+
+/// The letter `ls -l` shows an inode's type with, `-` for a regular file.
+fn type_letter(inode: &Inode) -> char {
+    match inode.file_type() {
+        FileType::Fifo => 'p',
+        FileType::Char => 'c',
+        FileType::Dir => 'd',
+        FileType::Block => 'b',
+        FileType::Regular => '-',
+        FileType::Symlink => 'l',
+        FileType::Socket => 's',
+        FileType::Unknown => '?',
+    }
+}
+
+/// The `ls -l` mode column, e.g. `drwxr-xr-x` or `crw-rw-rw-`.
+fn mode_string(inode: &Inode) -> String {
+    let bits = inode.permissions();
+    let mut mode = String::from(type_letter(inode));
+    // owner, group, other; the setuid, setgid and sticky bits show in the
+    // execute column, lowercase if it's executable too
+    for (shift, special, special_letter) in [(6, 0o4000, 's'), (3, 0o2000, 's'), (0, 0o1000, 't')] {
+        let class = (bits >> shift) & 0o7;
+        mode.push(if class & 0o4 != 0 { 'r' } else { '-' });
+        mode.push(if class & 0o2 != 0 { 'w' } else { '-' });
+        mode.push(match (class & 0o1 != 0, bits & special != 0) {
+            (true, true) => special_letter,
+            (false, true) => special_letter.to_ascii_uppercase(),
+            (true, false) => 'x',
+            (false, false) => '-',
+        });
+    }
+    mode
+}
+
+/// Sort order for `ls`.
+enum LsSort {
+    Name,
+    Size,
+    Mtime,
+}
+
+/// ANSI color for an `ls` entry: directories blue, symlinks cyan and
+/// executables (any execute bit set) green, like coreutils' defaults.
+fn ls_color(inode: &Inode) -> Option<&'static str> {
+    if inode.is_dir() {
+        Some("01;34")
+    } else if inode.is_symlink() {
+        Some("01;36")
+    } else if inode.type_perm.intersects(
+        structs::TypePerm::U_EXEC | structs::TypePerm::G_EXEC | structs::TypePerm::O_EXEC,
+    ) {
+        Some("01;32")
+    } else {
+        None
+    }
+}
+
+/// A name as `ls` shows it: escaped so it can't garble the terminal, or as
+/// it is if `raw`.
+fn show_name(name: &[u8], raw: bool) -> String {
+    if raw {
+        String::from_utf8_lossy(name).into_owned()
+    } else {
+        Escaped(name).to_string()
+    }
+}
+
+/// Column layout for `ls`: names fill each column top to bottom, using as
+/// many columns as fit in the terminal width, like coreutils.
+struct ColumnLayout {
+    rows: usize,
+    /// width of each column, including the two spaces separating it from the next
+    widths: Vec<usize>,
+}
+
+impl ColumnLayout {
+    fn new(names: &[String], term_width: usize) -> ColumnLayout {
+        const SEPARATOR: usize = 2;
+        let lens: Vec<usize> = names.iter().map(|name| name.chars().count()).collect();
+        for cols in (1..=names.len().max(1)).rev() {
+            let rows = names.len().div_ceil(cols);
+            let widths: Vec<usize> = lens
+                .chunks(rows.max(1))
+                .map(|col| col.iter().max().unwrap_or(&0) + SEPARATOR)
+                .collect();
+            if cols == 1 || widths.iter().sum::<usize>() - SEPARATOR <= term_width {
+                return ColumnLayout { rows, widths };
+            }
+        }
+        unreachable!("a single column always fits")
+    }
+}
+
+/// Where `cat`, `ls` and `help` write. Straight to stdout, unless the pager
+/// is on and there's someone at a terminal to press keys; then into a buffer
+/// that `finish` shows in the pager, if it's more than a screenful.
+enum Output {
+    Direct(BufWriter<io::StdoutLock<'static>>),
+    Paged(Vec<u8>),
+}
+
+impl Output {
+    fn new(shell: &Shell) -> Output {
+        if shell.config.pager && io::stdin().is_terminal() && io::stdout().is_terminal() {
+            Output::Paged(Vec::new())
+        } else {
+            Output::Direct(BufWriter::new(io::stdout().lock()))
+        }
+    }
+
+    fn finish(self) -> io::Result<()> {
+        match self {
+            Output::Direct(mut out) => out.flush(),
+            Output::Paged(text) => page(&String::from_utf8_lossy(&text)),
+        }
+    }
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // so ctrl-C stops a command however much it has left to print
+        if INTERRUPTED.load(Ordering::Relaxed) {
+            return Err(io::Error::new(io::ErrorKind::Other, "interrupted"));
+        }
+        match self {
+            Output::Direct(out) => out.write(buf),
+            Output::Paged(text) => text.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Output::Direct(out) => out.flush(),
+            Output::Paged(_) => Ok(()),
+        }
+    }
+}
+
+/// Show `text` like `less` if it doesn't fit on the terminal, or just print
+/// it if it does: space and b page forward and back, enter and the arrows
+/// move a line, g and G go to the start and end, /pattern finds the next
+/// line containing pattern and n the one after, and q quits.
+fn page(text: &str) -> io::Result<()> {
+    let mut stdout = io::stdout().lock();
+    let Some((Width(width), Height(height))) = terminal_size() else {
+        return stdout.write_all(text.as_bytes());
+    };
+    let rows = screen_rows(text, width as usize);
+    // the bottom line is for the status
+    let page = (height as usize).saturating_sub(1).max(1);
+    if rows.len() <= page {
+        return stdout.write_all(text.as_bytes());
+    }
+    let last_top = rows.len() - page;
+    let _raw = RawMode::enable()?;
+    // the alternate screen, so the shell's own screen comes back on quitting
+    write!(stdout, "\x1b[?1049h")?;
+    let mut keys = Keys(Vec::new());
+    let mut top = 0;
+    let mut pattern = String::new();
+    let mut message = None;
+    loop {
+        write!(stdout, "\x1b[H\x1b[2J")?;
+        for (_, row) in &rows[top..top + page] {
+            if pattern.is_empty() {
+                writeln!(stdout, "{}", row)?;
+            } else {
+                let marked = format!("\x1b[7m{}\x1b[27m", pattern);
+                writeln!(stdout, "{}", row.replace(&pattern, &marked))?;
+            }
+        }
+        let status = match message.take() {
+            Some(message) => message,
+            None if top == last_top => String::from("(END)"),
+            None => format!(
+                "lines {}-{} of {} ({}%)",
+                top + 1,
+                top + page,
+                rows.len(),
+                percent((top + page) as u64, rows.len() as u64)
+            ),
+        };
+        write!(stdout, "\x1b[7m{}\x1b[0m", status)?;
+        stdout.flush()?;
+
+        match keys.next()?.as_slice() {
+            b" " | b"f" | b"\x1b[6~" => top = (top + page).min(last_top),
+            b"b" | b"\x1b[5~" => top = top.saturating_sub(page),
+            b"\n" | b"\r" | b"j" | b"\x1b[B" => top = (top + 1).min(last_top),
+            b"k" | b"\x1b[A" => top = top.saturating_sub(1),
+            b"g" | b"<" | b"\x1b[H" => top = 0,
+            b"G" | b">" | b"\x1b[F" => top = last_top,
+            b"/" => {
+                write!(stdout, "\r\x1b[K/")?;
+                stdout.flush()?;
+                if let Some(typed) = read_line(&mut keys, &mut stdout)? {
+                    if !typed.is_empty() {
+                        pattern = typed;
+                    }
+                    message = search(&rows, &mut top, last_top, &pattern);
+                }
+            }
+            b"n" if !pattern.is_empty() => message = search(&rows, &mut top, last_top, &pattern),
+            // q, ctrl-C and the end of input
+            b"q" | b"Q" | b"\x03" | b"" => break,
+            _ => {}
+        }
+    }
+    write!(stdout, "\x1b[?1049l")?;
+    stdout.flush()
+}
+
+/// The lines of `text` as they fill a terminal `width` columns wide, with
+/// long ones wrapped onto several rows, each with the line it's part of.
+fn screen_rows(text: &str, width: usize) -> Vec<(usize, &str)> {
+    let mut rows = Vec::new();
+    for (line_num, line) in text.lines().enumerate() {
+        let mut start = 0;
+        let mut column = 0;
+        let mut chars = line.char_indices();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                // color escapes, e.g. from `ls`, take no room
+                '\x1b' => {
+                    for (_, c) in chars.by_ref() {
+                        if c.is_ascii_alphabetic() {
+                            break;
+                        }
+                    }
+                    continue;
+                }
+                '\t' => column = (column / 8 + 1) * 8,
+                _ => column += 1,
+            }
+            if column > width {
+                rows.push((line_num, &line[start..i]));
+                start = i;
+                column = if c == '\t' { 8 } else { 1 };
+            }
+        }
+        rows.push((line_num, &line[start..]));
+    }
+    rows
+}
+
+/// Move `top` to the next line after it containing `pattern`, or say it
+/// isn't there.
+fn search(
+    rows: &[(usize, &str)],
+    top: &mut usize,
+    last_top: usize,
+    pattern: &str,
+) -> Option<String> {
+    let current = rows[*top].0;
+    let found = rows
+        .iter()
+        .enumerate()
+        .skip(*top + 1)
+        .find(|(_, (line_num, row))| *line_num != current && row.contains(pattern));
+    match found {
+        Some((row, _)) => {
+            *top = row.min(last_top);
+            None
+        }
+        None => Some(format!("Pattern not found: {}", pattern)),
+    }
+}
+
+/// The keys pressed in the pager, read straight from the terminal: not
+/// through `io::stdin()`, whose buffer would keep what's typed after the
+/// pager quits from rustyline.
+struct Keys(Vec<u8>);
+
+impl Keys {
+    /// The next key, as the bytes the terminal sends for it: several for
+    /// keys like the arrows, none at the end of input. Text that arrives all
+    /// at once, e.g. pasted, is taken a key at a time.
+    fn next(&mut self) -> io::Result<Vec<u8>> {
+        if self.0.is_empty() {
+            let mut read = [0; 64];
+            let len =
+                unsafe { libc::read(libc::STDIN_FILENO, read.as_mut_ptr().cast(), read.len()) };
+            if len < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            self.0.extend_from_slice(&read[..len as usize]);
+        }
+        let len = match self.0[..] {
+            [] => 0,
+            // an escape sequence, up to its final letter or ~
+            [0x1b, b'[', ref rest @ ..] => rest
+                .iter()
+                .position(|&b| b.is_ascii_alphabetic() || b == b'~')
+                .map_or(self.0.len(), |end| end + 3),
+            // one UTF-8 character
+            [first, ..] => match first {
+                0xc0..=0xdf => 2,
+                0xe0..=0xef => 3,
+                0xf0..=0xf7 => 4,
+                _ => 1,
+            }
+            .min(self.0.len()),
+        };
+        Ok(self.0.drain(..len).collect())
+    }
+}
+
+/// Read a search pattern typed on the status line, echoing it. `None` if
+/// it's abandoned with escape or ctrl-C.
+fn read_line(keys: &mut Keys, stdout: &mut impl Write) -> io::Result<Option<String>> {
+    let mut line = String::new();
+    loop {
+        let key = keys.next()?;
+        match key.as_slice() {
+            b"\n" | b"\r" => return Ok(Some(line)),
+            b"\x1b" | b"\x03" | b"" => return Ok(None),
+            b"\x7f" | b"\x08" => {
+                if line.pop().is_some() {
+                    write!(stdout, "\x08 \x08")?;
+                }
+            }
+            _ => {
+                let typed = String::from_utf8_lossy(&key);
+                if !typed.starts_with(|c: char| c.is_control()) {
+                    line.push_str(&typed);
+                    write!(stdout, "{}", typed)?;
+                }
+            }
+        }
+        stdout.flush()?;
+    }
+}
+
+/// The terminal taking keys as they're pressed, without echoing them or
+/// turning ctrl-C into a signal, until this is dropped.
+struct RawMode(libc::termios);
+
+impl RawMode {
+    fn enable() -> io::Result<RawMode> {
+        let mut termios = unsafe { std::mem::zeroed::<libc::termios>() };
+        if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut termios) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let saved = RawMode(termios);
+        termios.c_lflag &= !(libc::ICANON | libc::ECHO | libc::ISIG);
+        termios.c_cc[libc::VMIN] = 1;
+        termios.c_cc[libc::VTIME] = 0;
+        if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &termios) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(saved)
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        unsafe {
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.0);
+        }
+    }
+}
+
+/// Set by ctrl-C while an `Interrupt` is around, i.e. while a command runs.
+/// Long commands pass it to the library as their `Progress`, or to walks
+/// with `WalkOptions::cancel_on`, to stop part way through.
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_interrupt(_: libc::c_int) {
+    INTERRUPTED.store(true, Ordering::Relaxed);
+}
+
+/// Ctrl-C setting `INTERRUPTED` instead of ending the shell, until this is
+/// dropped and whatever it did before is put back.
+struct Interrupt(libc::sigaction);
+
+impl Interrupt {
+    fn catch() -> Interrupt {
+        INTERRUPTED.store(false, Ordering::Relaxed);
+        unsafe {
+            let mut action = std::mem::zeroed::<libc::sigaction>();
+            action.sa_sigaction = on_interrupt as extern "C" fn(libc::c_int) as libc::sighandler_t;
+            libc::sigemptyset(&mut action.sa_mask);
+            let mut saved = std::mem::zeroed::<libc::sigaction>();
+            libc::sigaction(libc::SIGINT, &action, &mut saved);
+            Interrupt(saved)
+        }
+    }
+}
+
+impl Drop for Interrupt {
+    fn drop(&mut self) {
+        unsafe {
+            libc::sigaction(libc::SIGINT, &self.0, std::ptr::null_mut());
+        }
+    }
+}
+
+/// How a long command is getting on, as a line on stderr redrawn in place,
+/// if stderr is a terminal; otherwise nothing is shown. It cancels the
+/// command on ctrl-C, like `INTERRUPTED`.
+struct TerminalProgress {
+    /// What the items are, e.g. "files"
+    what: &'static str,
+    shown: bool,
+    started: Instant,
+    items: Cell<u64>,
+    bytes: Cell<(u64, Option<u64>)>,
+    drawn: Cell<Option<Instant>>,
+}
+
+impl TerminalProgress {
+    // how often the line is redrawn at most
+    const EVERY: Duration = Duration::from_millis(100);
+
+    fn new(what: &'static str) -> TerminalProgress {
+        TerminalProgress {
+            what,
+            shown: io::stderr().is_terminal(),
+            started: Instant::now(),
+            items: Cell::new(0),
+            bytes: Cell::new((0, None)),
+            drawn: Cell::new(None),
+        }
+    }
+
+    fn draw(&self) {
+        if !self.shown
+            || self
+                .drawn
+                .get()
+                .map_or(false, |at| at.elapsed() < Self::EVERY)
+        {
+            return;
+        }
+        self.drawn.set(Some(Instant::now()));
+        let (done, total) = self.bytes.get();
+        let mut line = format!("{} {}", self.items.get(), self.what);
+        if done > 0 {
+            let secs = self.started.elapsed().as_secs_f64().max(0.001);
+            line += &format!(
+                ", {} at {:.1} MB/s",
+                ext2::human_size(done),
+                done as f64 / secs / 1e6
+            );
+            if let Some(total) = total.filter(|&total| total > 0) {
+                line += &format!(" ({}%)", done * 100 / total);
+            }
+        }
+        let mut stderr = io::stderr();
+        let _ = queue!(
+            stderr,
+            cursor::MoveToColumn(0),
+            terminal::Clear(ClearType::CurrentLine),
+            style::Print(line)
+        );
+        let _ = stderr.flush();
+    }
+}
+
+impl Progress for TerminalProgress {
+    fn on_item(&self, _path: &str) {
+        self.items.set(self.items.get() + 1);
+        self.draw();
+    }
+
+    fn on_bytes(&self, done: u64, total: Option<u64>) {
+        self.bytes.set((done, total));
+        self.draw();
+    }
+
+    fn should_cancel(&self) -> bool {
+        INTERRUPTED.load(Ordering::Relaxed)
+    }
+}
+
+impl Drop for TerminalProgress {
+    fn drop(&mut self) {
+        // the command's own output goes where the line was
+        if self.drawn.get().is_some() {
+            let _ = execute!(
+                io::stderr(),
+                cursor::MoveToColumn(0),
+                terminal::Clear(ClearType::CurrentLine)
+            );
+        }
+    }
+}
+
+fn cmd_cd(shell: &mut Shell, args: &[&str]) -> CommandResult {
+    // `cd` with no arguments, cd goes back to root
+    // `cd path` moves cwd to that directory, e.g., cd dir_1/dir_2 moves
+    // down 2 directories deeper into dir_2
+    // `cd -` goes back to the directory before
+    let inode = match args {
+        [] => 2,
+        ["-"] => {
+            let Some(previous) = shell.previous_dir.take() else {
+                println!("cd: no previous directory");
+                return Ok(());
+            };
+            if !still_a_dir(shell, "cd", &previous) {
+                return Ok(());
+            }
+            require_access(shell, previous.inode, &previous.path, AccessMode::EXEC)?;
+            println!("{}", previous.path);
+            previous.inode
+        }
+        [path] => dir_arg(shell, path)?,
+        _ => return Err(CommandError::Usage),
+    };
+    change_dir(shell, inode);
+    Ok(())
+}
+
+/// Resolve `path` as a directory to move into.
+fn dir_arg(shell: &mut Shell, path: &str) -> std::result::Result<usize, CommandError> {
+    let inode = resolve(shell, path)?;
+    // if the inode is not a dir, print an error
+    if !shell.ext2.get_inode(inode)?.is_dir() {
+        return Err(Ext2Error::NotADirectory {
+            name: path.to_string(),
+        }
+        .into());
+    }
+    require_access(shell, inode, path, AccessMode::EXEC)?;
+    Ok(inode)
+}
+
+/// Make `dir` the cwd, remembering the one before for `cd -`.
+fn change_dir(shell: &mut Shell, dir: usize) {
+    shell.previous_dir = Some(saved_cwd(shell));
+    shell.cwd = dir;
+}
+
+/// The cwd, with its path as it is now.
+fn saved_cwd(shell: &Shell) -> SavedDir {
+    let path = shell
+        .ext2
+        .dir_path(shell.cwd)
+        .unwrap_or_else(|_| format!("(inode {})", shell.cwd));
+    SavedDir {
+        inode: shell.cwd,
+        path,
+    }
+}
+
+/// Whether `saved` is still a directory, rather than removed since it was
+/// saved, or removed and its inode reused for something else. Says so if
+/// it's gone, prefixed with `cmd`.
+fn still_a_dir(shell: &Shell, cmd: &str, saved: &SavedDir) -> bool {
+    let is_dir = shell.ext2.inode_is_allocated(saved.inode).unwrap_or(false)
+        && shell
+            .ext2
+            .get_inode(saved.inode)
+            .map_or(false, |inode| inode.is_dir());
+    if !is_dir {
+        println!("{}: {} no longer exists, forgetting it", cmd, saved.path);
+    }
+    is_dir
+}
+
+fn cmd_pushd(shell: &mut Shell, args: &[&str]) -> CommandResult {
+    let inode = match args {
+        // swap the cwd and the top of the stack
+        [] => {
+            let Some(top) = shell.dir_stack.pop() else {
+                println!("pushd: no other directory");
+                return Ok(());
+            };
+            if !still_a_dir(shell, "pushd", &top) {
+                return Ok(());
+            }
+            if let Err(err) = require_access(shell, top.inode, &top.path, AccessMode::EXEC) {
+                shell.dir_stack.push(top);
+                return Err(err);
+            }
+            top.inode
+        }
+        [path] => dir_arg(shell, path)?,
+        _ => return Err(CommandError::Usage),
+    };
+    shell.dir_stack.push(saved_cwd(shell));
+    change_dir(shell, inode);
+    print_dirs(shell, false);
+    Ok(())
+}
+
+fn cmd_popd(shell: &mut Shell, args: &[&str]) -> CommandResult {
+    if !args.is_empty() {
+        return Err(CommandError::Usage);
+    }
+    let Some(top) = shell.dir_stack.pop() else {
+        println!("popd: directory stack empty");
+        return Ok(());
+    };
+    if !still_a_dir(shell, "popd", &top) {
+        return Ok(());
+    }
+    if let Err(err) = require_access(shell, top.inode, &top.path, AccessMode::EXEC) {
+        shell.dir_stack.push(top);
+        return Err(err);
+    }
+    change_dir(shell, top.inode);
+    print_dirs(shell, false);
+    Ok(())
+}
+
+fn cmd_dirs(shell: &mut Shell, args: &[&str]) -> CommandResult {
+    match args {
+        [] => print_dirs(shell, false),
+        ["-v"] => print_dirs(shell, true),
+        ["-c"] => shell.dir_stack.clear(),
+        _ => return Err(CommandError::Usage),
+    }
+    Ok(())
+}
+
+/// Print the cwd then the directory stack from the top, on one line like
+/// bash, or numbered one to a line if `numbered`.
+fn print_dirs(shell: &Shell, numbered: bool) {
+    let cwd = saved_cwd(shell);
+    let dirs = std::iter::once(&cwd).chain(shell.dir_stack.iter().rev());
+    if numbered {
+        for (i, dir) in dirs.enumerate() {
+            println!("{:2}  {}", i, dir.path);
+        }
+    } else {
+        let paths: Vec<&str> = dirs.map(|dir| dir.path.as_str()).collect();
+        println!("{}", paths.join(" "));
+    }
+}
+
+fn cmd_mkdir(shell: &mut Shell, args: &[&str]) -> CommandResult {
+    // `mkdir childname`
+    // consider supporting `-p path/to_file` to create a path of directories
+    let dirname = match args {
+        [dirname] => *dirname,
+        _ => return Err(CommandError::Usage),
+    };
+    // adding an entry to the cwd is writing to it
+    require_access(shell, shell.cwd, ".", AccessMode::WRITE | AccessMode::EXEC)?;
+    // allocate an inode and a block, create the directory and add a link to cwd
+    shell.ext2.create_dir(shell.cwd, dirname, 0o755)?;
+    Ok(())
+}
+
+fn cmd_mknod(shell: &mut Shell, args: &[&str]) -> CommandResult {
+    let (name, file_type, device) = match args {
+        [name, "p"] => (*name, structs::TypePerm::FIFO, None),
+        [name, "s"] => (*name, structs::TypePerm::SOCKET, None),
+        [name, kind @ ("c" | "b"), major, minor] => {
+            let (Ok(major), Ok(minor)) = (major.parse::<u32>(), minor.parse::<u32>()) else {
+                return Err(CommandError::Usage);
+            };
+            // the encoding has 12 bits of major and 20 of minor
+            if major >= 1 << 12 || minor >= 1 << 20 {
+                return Err(CommandError::Usage);
+            }
+            let file_type = if *kind == "c" {
+                structs::TypePerm::CHAR_DEVICE
+            } else {
+                structs::TypePerm::BLOCK_DEVICE
+            };
+            (*name, file_type, Some((major, minor)))
+        }
+        _ => return Err(CommandError::Usage),
+    };
+    require_access(shell, shell.cwd, ".", AccessMode::WRITE | AccessMode::EXEC)?;
+    shell
+        .ext2
+        .create_node(shell.cwd, name, file_type.bits() | 0o644, device)?;
+    Ok(())
+}
+
+fn cmd_touch(shell: &mut Shell, args: &[&str]) -> CommandResult {
+    if args.is_empty() {
+        return Err(CommandError::Usage);
+    }
+    for path in args {
+        let (dir, name) = parent_and_name(shell, path)?;
+        match shell.ext2.lookup(dir, name)? {
+            Some(inode) => {
+                require_access(shell, inode, path, AccessMode::WRITE)?;
+                shell.ext2.touch(inode)?;
+            }
+            None => {
+                require_access(shell, dir, path, AccessMode::WRITE | AccessMode::EXEC)?;
+                shell.ext2.create_file(dir, name, 0o644)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn cmd_cat(shell: &mut Shell, args: &[&str]) -> CommandResult {
+    // `cat path`
+    // print the contents of the file to stdout
+    // if it's a directory, print a nice error
+    let path = match args {
+        [path] => *path,
+        _ => return Err(CommandError::Usage),
+    };
+    let inode = resolve(shell, path)?;
+    // if the inode is a directory, print an error
+    if shell.ext2.get_inode(inode)?.is_dir() {
+        return Err(Ext2Error::IsADirectory {
+            name: path.to_string(),
+        }
+        .into());
+    }
+    let record = shell.ext2.get_inode(inode)?;
+    if record.is_special() {
+        // their block pointers hold device numbers, not data
+        println!(
+            "cat: {}: is a {}, not a regular file",
+            path,
+            record.type_name()
+        );
+        return Ok(());
+    }
+    require_access(shell, inode, path, AccessMode::READ)?;
+    // print the contents of the file
+    write_to_stdout(shell, inode)?;
+    shell.ext2.touch_accessed(inode)?;
+    Ok(())
+}
+
+fn cmd_strings(shell: &mut Shell, args: &[&str]) -> CommandResult {
+    let (offsets, args) = match args {
+        ["-t", "x", rest @ ..] => (true, rest),
+        _ => (false, args),
+    };
+    let (path, min_len) = match args {
+        [path] => (*path, 4),
+        [path, min_len] => match min_len.parse::<usize>() {
+            Ok(min_len) if min_len > 0 => (*path, min_len),
+            _ => return Err(CommandError::Usage),
+        },
+        _ => return Err(CommandError::Usage),
+    };
+    let inode = resolve(shell, path)?;
+    if shell.ext2.get_inode(inode)?.is_dir() {
+        return Err(Ext2Error::IsADirectory {
+            name: path.to_string(),
+        }
+        .into());
+    }
+    require_access(shell, inode, path, AccessMode::READ)?;
+    let mut out = Output::new(shell);
+    let found = shell.ext2.strings_to(inode, min_len, offsets, &mut out);
+    out.finish()?;
+    found?;
+    shell.ext2.touch_accessed(inode)?;
+    Ok(())
+}
+
+/// Stream the contents of `inode` to stdout, or the pager.
+fn write_to_stdout(shell: &mut Shell, inode: usize) -> ext2::Result<()> {
+    let mut out = Output::new(shell);
+    let copied = shell
+        .ext2
+        .copy_file_to_with_progress(inode, &mut out, &INTERRUPTED);
+    // whatever was read before an error is still worth showing
+    out.finish()?;
+    copied?;
+    Ok(())
+}
+
+fn cmd_open(shell: &mut Shell, args: &[&str]) -> CommandResult {
+    let [path] = args else {
+        return Err(CommandError::Usage);
+    };
+    let inode = resolve(shell, path)?;
+    if shell.ext2.get_inode(inode)?.is_dir() {
+        return Err(Ext2Error::IsADirectory {
+            name: path.to_string(),
+        }
+        .into());
+    }
+    require_access(shell, inode, path, AccessMode::READ)?;
+    let (dir, name) = parent_and_name(shell, path)?;
+    let path = format!(
+        "{}/{}",
+        shell.ext2.dir_path(dir)?.trim_end_matches('/'),
+        name
+    );
+    let reader = shell.ext2.open_file(inode)?;
+    let fd = (FIRST_FD..)
+        .find(|fd| !shell.files.contains_key(fd))
+        .unwrap();
+    shell.files.insert(fd, OpenFile { reader, path });
+    println!("{}", fd);
+    Ok(())
+}
+
+fn cmd_read(shell: &mut Shell, args: &[&str]) -> CommandResult {
+    let [fd, count] = args else {
+        return Err(CommandError::Usage);
+    };
+    let Ok(count) = count.parse::<usize>() else {
+        return Err(CommandError::Usage);
+    };
+    let Some(file) = fd_arg(&mut shell.files, "read", fd) else {
+        return Ok(());
+    };
+    // a block at a time, so a huge count doesn't need a buffer as big
+    let mut buf = vec![0; count.min(shell.ext2.block_size)];
+    let mut out = io::stdout().lock();
+    let mut left = count;
+    while left > 0 {
+        let want = left.min(buf.len());
+        let len = file.reader.read(&shell.ext2, &mut buf[..want])?;
+        if len == 0 {
+            break;
+        }
+        out.write_all(&buf[..len])?;
+        left -= len;
+    }
+    out.flush()?;
+    let inode = file.reader.inode();
+    shell.ext2.touch_accessed(inode)?;
+    Ok(())
+}
+
+fn cmd_seek(shell: &mut Shell, args: &[&str]) -> CommandResult {
+    let [fd, pos] = args else {
+        return Err(CommandError::Usage);
+    };
+    let pos = if let Some(delta) = pos.strip_prefix('+') {
+        delta.parse::<i64>().map(SeekFrom::Current)
+    } else if pos.starts_with('-') {
+        pos.parse::<i64>().map(SeekFrom::Current)
+    } else {
+        pos.parse::<u64>().map(SeekFrom::Start)
+    };
+    let Ok(pos) = pos else {
+        return Err(CommandError::Usage);
+    };
+    let Some(file) = fd_arg(&mut shell.files, "seek", fd) else {
+        return Ok(());
+    };
+    file.reader.seek(&shell.ext2, pos)?;
+    Ok(())
+}
+
+fn cmd_close(shell: &mut Shell, args: &[&str]) -> CommandResult {
+    let [fd] = args else {
+        return Err(CommandError::Usage);
+    };
+    if fd_arg(&mut shell.files, "close", fd).is_none() {
+        return Ok(());
+    }
+    let file = shell.files.remove(&fd.parse::<usize>().unwrap()).unwrap();
+    shell.ext2.close_file(file.reader)?;
+    Ok(())
+}
+
+fn cmd_lsof(shell: &mut Shell, args: &[&str]) -> CommandResult {
+    if !args.is_empty() {
+        return Err(CommandError::Usage);
+    }
+    if shell.files.is_empty() {
+        println!("no open files");
+        return Ok(());
+    }
+    println!("{:>3} {:>7} {:>10}  path", "fd", "inode", "offset");
+    for (fd, file) in &shell.files {
+        let inode = file.reader.inode();
+        // freed from under it, e.g. by a rollback, if it isn't allocated
+        let removed =
+            !shell.ext2.inode_is_allocated(inode)? || shell.ext2.get_inode(inode)?.hard_links == 0;
+        println!(
+            "{:>3} {:>7} {:>10}  {}{}",
+            fd,
+            inode,
+            file.reader.offset(),
+            file.path,
+            if removed { " (deleted)" } else { "" }
+        );
+    }
+    Ok(())
+}
+
+/// Look up a file descriptor argument. Prints an error prefixed with `cmd`
+/// and returns `None` if it isn't one `open` handed out.
+fn fd_arg<'a>(
+    files: &'a mut BTreeMap<usize, OpenFile>,
+    cmd: &str,
+    arg: &str,
+) -> Option<&'a mut OpenFile> {
+    let file = arg.parse::<usize>().ok().and_then(|fd| files.get_mut(&fd));
+    if file.is_none() {
+        println!("{}: {}: bad file descriptor", cmd, arg);
+    }
+    file
+}
+
+/// Parse an inode number argument, checking it names an allocated inode.
+/// Prints an error prefixed with `cmd` and returns `None` otherwise.
+fn parse_inode_arg(shell: &Shell, cmd: &str, arg: &str) -> Option<usize> {
+    let Ok(inode) = arg.parse::<usize>() else {
+        println!("{}: invalid inode number: {}", cmd, arg);
+        return None;
+    };
+    match shell.ext2.inode_is_allocated(inode) {
+        Ok(true) => Some(inode),
+        Ok(false) => {
+            println!("{}: inode {} is not allocated", cmd, inode);
+            None
+        }
+        Err(err) => {
+            println!("{}: {}", cmd, err);
+            None
+        }
+    }
+}
+
+fn cmd_dump(shell: &mut Shell, args: &[&str]) -> CommandResult {
+    let (ron, args) = match args {
+        ["--format", "json", rest @ ..] => (false, rest),
+        ["--format", "ron", rest @ ..] => (true, rest),
+        rest => (false, rest),
+    };
+    match args {
+        ["superblock"] => print_dump(&SuperblockOwned::from(&shell.ext2.superblock), ron),
+        ["group", n] => {
+            let Some(group) = n
+                .parse::<usize>()
+                .ok()
+                .and_then(|n| shell.ext2.block_groups.get(n))
+            else {
+                println!("dump: no block group {}", n);
+                return Ok(());
+            };
+            print_dump(&GroupDescriptorOwned::from(group), ron)
+        }
+        ["inode", n] => {
+            let Ok(inode) = n.parse::<usize>() else {
+                println!("dump: invalid inode number: {}", n);
+                return Ok(());
+            };
+            print_dump(&InodeOwned::from(shell.ext2.get_inode(inode)?), ron)
+        }
+        _ => return Err(CommandError::Usage),
+    }
+    Ok(())
+}
+
+/// Print `value` for `dump`, as RON if `ron`, otherwise as JSON.
+fn print_dump<T: Serialize>(value: &T, ron: bool) {
+    if ron {
+        let config = ron::ser::PrettyConfig::default();
+        println!(
+            "{}",
+            ron::ser::to_string_pretty(value, config).expect("structures always serialize")
+        );
+    } else {
+        print_json(value);
+    }
+}
+
+fn cmd_icheck(shell: &mut Shell, args: &[&str]) -> CommandResult {
+    let Ok(blocks) = args
+        .iter()
+        .map(|arg| arg.parse())
+        .collect::<std::result::Result<Vec<usize>, _>>()
+    else {
+        return Err(CommandError::Usage);
+    };
+    if blocks.is_empty() {
+        return Err(CommandError::Usage);
+    }
+    let metadata = shell.ext2.metadata_blocks();
+    for (block, owners) in shell.ext2.block_owners(&blocks)? {
+        if !owners.is_empty() {
+            let owners: Vec<String> = owners.iter().map(|inode| inode.to_string()).collect();
+            println!("{}: inode {}", block, owners.join(", "));
+        } else if metadata.contains(&block) {
+            println!("{}: filesystem metadata", block);
+        } else {
+            println!("{}: not owned", block);
+        }
+    }
+    Ok(())
+}
+
+fn cmd_ncheck(shell: &mut Shell, args: &[&str]) -> CommandResult {
+    let Ok(inodes) = args
+        .iter()
+        .map(|arg| arg.parse())
+        .collect::<std::result::Result<Vec<usize>, _>>()
+    else {
+        return Err(CommandError::Usage);
+    };
+    if inodes.is_empty() {
+        return Err(CommandError::Usage);
+    }
+    for (inode, paths) in shell.ext2.inode_paths(&inodes)? {
+        if paths.is_empty() {
+            println!("{}: not linked", inode);
+        }
+        for path in paths {
+            println!("{}: {}", inode, Escaped(path.as_bytes()));
+        }
+    }
+    Ok(())
+}
+
+fn cmd_icat(shell: &mut Shell, args: &[&str]) -> CommandResult {
+    // `icat inode` bypasses the namespace, like debugfs
+    let [arg] = args else {
+        return Err(CommandError::Usage);
+    };
+    let Some(inode) = parse_inode_arg(shell, "icat", arg) else {
+        return Ok(());
+    };
+    write_to_stdout(shell, inode)?;
+    Ok(())
+}
+
+fn cmd_istat(shell: &mut Shell, args: &[&str]) -> CommandResult {
+    let (json, human, arg) = match args {
+        ["--json", arg] => (true, false, arg),
+        ["-h", arg] => (false, true, arg),
+        [arg] => (shell.config.json, false, arg),
+        _ => return Err(CommandError::Usage),
+    };
+    let Some(inode_no) = parse_inode_arg(shell, "istat", arg) else {
+        return Ok(());
+    };
+    let inode = shell.ext2.get_inode(inode_no)?;
+    if json {
+        print_json(&InodeInfo::new(inode_no, inode));
+        return Ok(());
+    }
+    print_inode(shell, inode_no, human)
+}
+
+fn cmd_stat(shell: &mut Shell, args: &[&str]) -> CommandResult {
+    let (json, human, path) = match args {
+        ["--json", path] => (true, false, *path),
+        ["-h", path] => (false, true, *path),
+        [path] => (shell.config.json, false, *path),
+        _ => return Err(CommandError::Usage),
+    };
+    let inode_no = resolve(shell, path)?;
+    if json {
+        print_json(&WithPath {
+            path: path.to_string(),
+            info: InodeInfo::new(inode_no, shell.ext2.get_inode(inode_no)?),
+        });
+        return Ok(());
+    }
+    println!("File: {}", Escaped(path.as_bytes()));
+    print_inode(shell, inode_no, human)
+}
+
+/// Every field of inode `inode_no`, as `istat` and `stat` print them.
+fn print_inode(shell: &Shell, inode_no: usize, human: bool) -> CommandResult {
+    let inode = shell.ext2.get_inode(inode_no)?;
+    println!("Inode: {}", inode_no);
+    println!(
+        "Type/perm: {:#06x} ({:?})",
+        inode.type_perm.bits(),
+        inode.type_perm
+    );
+    println!("Type: {}", inode.type_name());
+    if inode_no < shell.ext2.first_usable_inode() {
+        match ReservedInode::of(inode_no) {
+            Some(reserved) => println!("Reserved: {}, {}", reserved.role, reserved.description),
+            None => println!("Reserved: yes"),
+        }
+    }
+    if let Some((major, minor)) = inode.device() {
+        println!("Device type: {},{}", major, minor);
+    }
+    println!("Uid: {}  Gid: {}", inode.uid, inode.gid);
+    let size = if human {
+        ext2::human_size(inode.size())
+    } else {
+        inode.size().to_string()
+    };
+    println!(
+        "Size: {} (size_low {}, size_high {})",
+        size, inode.size_low, inode.size_high
+    );
+    println!("Links: {}", inode.hard_links);
+    println!("Sectors: {}", inode.sectors_count);
+    println!(
+        "Flags: {:#010x} ({})",
+        inode.flags,
+        InodeFlags::from_bits_truncate(inode.flags).letters()
+    );
+    println!("Generation: {}", inode.gen_number);
+    println!("File ACL: {}", inode.ext_attribute_block);
+    println!("Fragment address: {}", inode.frag_block_addr);
+    println!("atime: {}", inode.atime);
+    println!("ctime: {}", inode.ctime);
+    println!("mtime: {}", inode.mtime);
+    println!("dtime: {}", inode.dtime);
+    println!("Direct blocks: {:?}", inode.direct_pointer);
+    println!("Indirect block: {}", inode.indirect_pointer);
+    println!("Doubly indirect block: {}", inode.doubly_indirect);
+    println!("Triply indirect block: {}", inode.triply_indirect);
+    if inode_no == 1 {
+        let blocks = bad_blocks(&shell.ext2)?;
+        let blocks: Vec<String> = blocks.iter().map(usize::to_string).collect();
+        if blocks.is_empty() {
+            println!("Bad blocks: none");
+        } else {
+            println!("Bad blocks: {}", blocks.join(" "));
+        }
+    }
+    Ok(())
+}
+
+fn cmd_getfattr(shell: &mut Shell, args: &[&str]) -> CommandResult {
+    let (dump, path) = match args {
+        [path] => (false, *path),
+        ["-d", path] => (true, *path),
+        _ => return Err(CommandError::Usage),
+    };
+    let inode = resolve(shell, path)?;
+    require_access(shell, inode, path, AccessMode::READ)?;
+    println!("# file: {}", path);
+    for name in shell.ext2.list_xattrs(inode)? {
+        if !dump {
+            println!("{}", name);
+            continue;
+        }
+        let value = shell.ext2.get_xattr(inode, &name)?.unwrap_or_default();
+        println!("{}={}", name, xattr_value(value));
+        if name.starts_with("system.posix_acl_") {
+            for line in ext2::decode_posix_acl(value).unwrap_or_default() {
+                println!("  {}", line);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// An attribute value as getfattr shows it: quoted if it's printable text
+/// (ignoring a trailing NUL), otherwise as `0x` and hex digits.
+fn xattr_value(value: &[u8]) -> String {
+    let text = value.strip_suffix(b"\0").unwrap_or(value);
+    if !text.is_empty() && text.iter().all(|&b| b.is_ascii_graphic() || b == b' ') {
+        let text = String::from_utf8_lossy(text);
+        format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+    } else {
+        let hex: String = value.iter().map(|b| format!("{:02x}", b)).collect();
+        format!("0x{}", hex)
+    }
+}
+
+fn cmd_blkcat(shell: &mut Shell, args: &[&str]) -> CommandResult {
+    let [arg] = args else {
+        return Err(CommandError::Usage);
+    };
+    let blocks_count = shell.ext2.superblock.blocks_count as usize;
+    let block_num = match arg.parse::<usize>() {
+        Ok(n) if n < blocks_count => n,
+        Ok(n) => {
+            println!(
+                "blkcat: block {} out of range (fs has {} blocks)",
+                n, blocks_count
+            );
+            return Ok(());
+        }
+        Err(_) => {
+            println!("blkcat: invalid block number: {}", arg);
+            return Ok(());
+        }
+    };
+    hexdump(shell.ext2.block(block_num)?);
+    Ok(())
+}
+
+/// Print `bytes` like `hexdump -C`: offset, 16 hex bytes, then the printable
+/// ASCII, collapsing runs of identical lines into a single `*`.
+fn hexdump(bytes: &[u8]) {
+    let mut previous: Option<&[u8]> = None;
+    let mut skipping = false;
+    for (i, line) in bytes.chunks(16).enumerate() {
+        if previous == Some(line) {
+            if !skipping {
+                println!("*");
+                skipping = true;
+            }
+            continue;
+        }
+        previous = Some(line);
+        skipping = false;
+        print!("{:08x} ", i * 16);
+        for (j, byte) in line.iter().enumerate() {
+            if j == 8 {
+                print!(" ");
+            }
+            print!(" {:02x}", byte);
+        }
+        let ascii: String = line
+            .iter()
+            .map(|&b| {
+                if b.is_ascii_graphic() || b == b' ' {
+                    b as char
+                } else {
+                    '.'
+                }
+            })
+            .collect();
+        println!("  |{}|", ascii);
+    }
+    println!("{:08x}", bytes.len());
+}
+
+fn cmd_bmap(shell: &mut Shell, args: &[&str]) -> CommandResult {
+    let [path, logical] = args else {
+        return Err(CommandError::Usage);
+    };
+    let Ok(logical) = logical.parse::<usize>() else {
+        return Err(CommandError::Usage);
+    };
+    let inode = resolve(shell, path)?;
+    let mut blocks = shell.ext2.file_blocks(inode)?;
+    let count = blocks.len();
+    match blocks.nth(logical).transpose()? {
+        Some(0) => println!("hole"),
+        Some(physical) => println!("{}", physical),
+        None => println!(
+            "bmap: {}: logical block {} past end of file ({} blocks)",
+            path, logical, count
+        ),
+    }
+    Ok(())
+}
+
+fn cmd_blocktree(shell: &mut Shell, args: &[&str]) -> CommandResult {
+    let [path] = args else {
+        return Err(CommandError::Usage);
+    };
+    let inode = resolve(shell, path)?;
+    let map = shell.ext2.block_map(inode)?;
+    let mut out = Output::new(shell);
+    let shown = print_block_map(&map, &mut out);
+    out.finish()?;
+    shown
+}
+
+/// Print `map` as an indented tree, for `blocktree`.
+fn print_block_map(map: &ext2::BlockMap, out: &mut impl Write) -> CommandResult {
+    if map.direct.is_empty()
+        && map.indirect.is_none()
+        && map.doubly_indirect.is_none()
+        && map.triply_indirect.is_none()
+    {
+        writeln!(out, "no blocks")?;
+        return Ok(());
+    }
+    if !map.direct.is_empty() {
+        writeln!(out, "direct")?;
+        for run in &map.direct {
+            writeln!(out, "  {}", block_run(run))?;
+        }
+    }
+    let levels = [
+        ("indirect", &map.indirect),
+        ("doubly indirect", &map.doubly_indirect),
+        ("triply indirect", &map.triply_indirect),
+    ];
+    for (kind, block) in levels {
+        if let Some(block) = block {
+            print_indirect_block(kind, block, 0, out)?;
+        }
+    }
+    Ok(())
+}
+
+/// Print indirect block `block` and what's under it, `depth` levels in.
+fn print_indirect_block(
+    kind: &str,
+    block: &ext2::IndirectBlock,
+    depth: usize,
+    out: &mut impl Write,
+) -> CommandResult {
+    let indent = "  ".repeat(depth);
+    writeln!(out, "{}{} block {}", indent, kind, block.block)?;
+    match &block.pointers {
+        ext2::IndirectPointers::Data(runs) => {
+            for run in runs {
+                writeln!(out, "{}  {}", indent, block_run(run))?;
+            }
+        }
+        ext2::IndirectPointers::Indirect(children) => {
+            let mut i = 0;
+            while i < children.len() {
+                // the indirect blocks from here on that each come right
+                // before their data, and right after the data of the one
+                // before, with no hole between
+                let mut runs = Vec::new();
+                while let Some(run) = children.get(i + runs.len()).and_then(data_after) {
+                    let follows = runs.last().map_or(true, |last: &ext2::BlockRun| {
+                        run.physical == last.physical + last.len + 1
+                            && run.logical == last.logical + last.len
+                    });
+                    if !follows {
+                        break;
+                    }
+                    runs.push(run);
+                }
+                if runs.len() >= 2 {
+                    let (first, last) = (runs[0], runs[runs.len() - 1]);
+                    writeln!(
+                        out,
+                        "{}  {} indirect blocks, each followed by its data: blocks {}-{}, contiguous (logical {}-{})",
+                        indent,
+                        runs.len(),
+                        children[i].block,
+                        last.physical + last.len - 1,
+                        first.logical,
+                        last.logical + last.len - 1
+                    )?;
+                    i += runs.len();
+                    continue;
+                }
+                let child_kind = match &children[i].pointers {
+                    ext2::IndirectPointers::Data(_) => "indirect",
+                    ext2::IndirectPointers::Indirect(_) => "doubly indirect",
+                };
+                print_indirect_block(child_kind, &children[i], depth + 1, out)?;
+                i += 1;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The data of indirect block `block`, if it's one run of data blocks that
+/// starts right after it.
+fn data_after(block: &ext2::IndirectBlock) -> Option<ext2::BlockRun> {
+    match &block.pointers {
+        ext2::IndirectPointers::Data(runs) => match runs[..] {
+            [run] if run.physical == block.block + 1 => Some(run),
+            _ => None,
+        },
+        ext2::IndirectPointers::Indirect(_) => None,
+    }
+}
+
+/// A run of pointers as a line of `blocktree`.
+fn block_run(run: &ext2::BlockRun) -> String {
+    let logical = if run.len == 1 {
+        format!("logical {}", run.logical)
+    } else {
+        format!("logical {}-{}", run.logical, run.logical + run.len - 1)
+    };
+    if run.is_hole() {
+        format!("{}: hole", logical)
+    } else if run.len == 1 {
+        format!("{}: block {}", logical, run.physical)
+    } else {
+        format!(
+            "{}: blocks {}-{}, contiguous",
+            logical,
+            run.physical,
+            run.physical + run.len - 1
+        )
+    }
+}
+
+fn cmd_dirblocks(shell: &mut Shell, args: &[&str]) -> CommandResult {
+    let [path] = args else {
+        return Err(CommandError::Usage);
+    };
+    let dir = resolve(shell, path)?;
+    if !shell.ext2.get_inode(dir)?.is_dir() {
+        return Err(Ext2Error::NotADirectory {
+            name: path.to_string(),
+        }
+        .into());
+    }
+    require_access(shell, dir, path, AccessMode::READ)?;
+    let mut out = Output::new(shell);
+    let shown = print_dir_slots(&shell.ext2, dir, &mut out);
+    out.finish()?;
+    shown
+}
+
+/// List the slots of every block of directory `dir`, for `dirblocks`.
+fn print_dir_slots(ext2: &Ext2, dir: usize, out: &mut impl Write) -> CommandResult {
+    for (logical, block_num) in ext2.file_blocks(dir)?.enumerate() {
+        let block_num = block_num?;
+        if block_num == 0 {
+            writeln!(out, "logical block {}: a hole", logical)?;
+            continue;
+        }
+        let block = ext2.block(block_num)?;
+        writeln!(out, "logical block {}, block {}:", logical, block_num)?;
+        writeln!(
+            out,
+            "  {:>6} {:>7} {:>7} {:>8} {:<10} {:>7}  name",
+            "offset", "inode", "rec_len", "name_len", "type", "padding"
+        )?;
+        for slot in ext2::DirSlots::new(block) {
+            match slot {
+                Ok(slot) => {
+                    let mut notes = Vec::new();
+                    if slot.inode == 0 {
+                        notes.push("unused");
+                    }
+                    if slot.offset + slot.rec_len == block.len() {
+                        notes.push("to the end of the block");
+                    }
+                    let notes = if notes.is_empty() {
+                        String::new()
+                    } else {
+                        format!("  ({})", notes.join(", "))
+                    };
+                    writeln!(
+                        out,
+                        "  {:>6} {:>7} {:>7} {:>8} {:<10} {:>7}  {}{}",
+                        slot.offset,
+                        slot.inode,
+                        slot.rec_len,
+                        slot.name.0.len(),
+                        entry_type_name(slot.file_type),
+                        slot.padding(),
+                        slot.name.escaped(),
+                        notes
+                    )?;
+                }
+                Err((offset, reason)) => {
+                    // what the header says, if there's room for one
+                    let header = block.get(offset..offset + 8).map_or(String::new(), |h| {
+                        format!(
+                            " (inode {}, rec_len {}, name_len {})",
+                            u32::from_le_bytes(h[..4].try_into().unwrap()),
+                            u16::from_le_bytes(h[4..6].try_into().unwrap()),
+                            h[6]
+                        )
+                    });
+                    writeln!(
+                        out,
+                        "  {:>6} !! {}{}; the rest of the block can't be read",
+                        offset, reason, header
+                    )?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// What a directory entry's type indicator byte says, e.g. `2 dir`.
+fn entry_type_name(indicator: u8) -> String {
+    const NAMES: [&str; 8] = [
+        "unknown", "file", "dir", "chrdev", "blkdev", "fifo", "socket", "symlink",
+    ];
+    match NAMES.get(indicator as usize) {
+        Some(name) => format!("{} {}", indicator, name),
+        None => format!("{} ?", indicator),
+    }
+}
+
+fn cmd_fsinfo(shell: &mut Shell, args: &[&str]) -> CommandResult {
+    match args {
+        [] if shell.config.json => print_json(&shell.ext2.fs_info()),
+        [] => {
+            if let Some(partition) = &shell.partition {
+                println!("Partition");
+                println!("  Number:               {}", partition.number);
+                println!("  Type:                 {}", partition.kind);
+                println!(
+                    "  Offset:               {} bytes (sector {})",
+                    partition.offset,
+                    partition.offset / 512
+                );
+                println!("  Size:                 {} bytes", partition.len);
+            }
+            print!("{}", shell.ext2.describe());
+        }
+        ["--backups"] => print!("{}", shell.ext2.describe_backups()),
+        ["--json"] => print_json(&shell.ext2.fs_info()),
+        _ => return Err(CommandError::Usage),
+    }
+    Ok(())
+}
+
+fn cmd_df(shell: &mut Shell, args: &[&str]) -> CommandResult {
+    let (json, human) = match args {
+        [] => (shell.config.json, false),
+        ["--json"] => (true, false),
+        ["-h"] => (false, true),
+        _ => return Err(CommandError::Usage),
+    };
+    let space = shell.ext2.space_info();
+    if json {
+        print_json(&space);
+        return Ok(());
+    }
+    let kib = |kib: u64| {
+        if human {
+            ext2::human_size(kib * 1024)
+        } else {
+            kib.to_string()
+        }
+    };
+    println!(
+        "{:>10} {:>10} {:>10} {:>10} {:>5}",
+        if human { "Size" } else { "1K-blocks" },
+        "Used",
+        "Available",
+        "Reserved",
+        "Use%"
+    );
+    println!(
+        "{:>10} {:>10} {:>10} {:>10} {:>4}%",
+        kib(space.total_kib),
+        kib(space.used_kib),
+        kib(space.available_kib),
+        kib(space.reserved_kib),
+        percent(space.used_kib, space.used_kib + space.available_kib)
+    );
+    println!(
+        "{:>10} {:>10} {:>10} {:>10} {:>5}",
+        "Inodes", "IUsed", "IFree", "IReserved", "IUse%"
+    );
+    println!(
+        "{:>10} {:>10} {:>10} {:>10} {:>4}%",
+        space.inodes,
+        space.used_inodes,
+        space.free_inodes,
+        space.reserved_inodes,
+        percent(space.used_inodes as u64, space.inodes as u64)
+    );
+    Ok(())
+}
+
+/// Print `value` as one JSON document, for the `--json` flags. Logging goes
+/// to stderr, so nothing else ends up in the middle of it.
+fn print_json<T: Serialize>(value: &T) {
+    println!(
+        "{}",
+        serde_json::to_string_pretty(value).expect("reports always serialize")
+    );
+}
+
+fn cmd_fsmap(shell: &mut Shell, args: &[&str]) -> CommandResult {
+    let map_width = match args {
+        [] => 0,
+        ["--bar"] => 48,
+        _ => return Err(CommandError::Usage),
+    };
+    println!(
+        "{:>5} {:>10} {:>8} {:>8} {:>8} {:>8}",
+        "Group", "First", "Blocks", "Meta", "Data", "Free"
+    );
+    for group in 0..shell.ext2.block_groups.len() {
+        let usage = shell.ext2.group_usage(group, map_width)?;
+        print!(
+            "{:>5} {:>10} {:>8} {:>8} {:>8} {:>8}",
+            usage.group, usage.first_block, usage.blocks, usage.metadata, usage.data, usage.free
+        );
+        if map_width > 0 {
+            print!("  [{}]", usage.map);
+        }
+        println!();
+    }
+    Ok(())
+}
+
+fn cmd_freemap(shell: &mut Shell, args: &[&str]) -> CommandResult {
+    let [] = args else {
+        return Err(CommandError::Usage);
+    };
+    let report = shell.ext2.free_space()?;
+    let mut out = Output::new(shell);
+    writeln!(
+        out,
+        "{:>5} {:>8} {:>8} {:>8} {:>10}",
+        "Group", "Free", "Extents", "Largest", "At"
+    )?;
+    for group in &report.groups {
+        let (start, len) = group.largest;
+        writeln!(
+            out,
+            "{:>5} {:>8} {:>8} {:>8} {:>10}",
+            group.group,
+            group.free,
+            group.extents,
+            len,
+            if len == 0 {
+                String::from("-")
+            } else {
+                start.to_string()
+            }
+        )?;
+    }
+    let free: usize = report.groups.iter().map(|group| group.free).sum();
+    writeln!(out)?;
+    writeln!(
+        out,
+        "{:>15} {:>8} {:>10} {:>5}",
+        "Run length", "Runs", "Blocks", "Free%"
+    )?;
+    for bucket in &report.histogram {
+        writeln!(
+            out,
+            "{:>15} {:>8} {:>10} {:>4}%",
+            format!("{}-{}", bucket.min_len, bucket.max_len),
+            bucket.runs,
+            bucket.blocks,
+            percent(bucket.blocks as u64, free as u64)
+        )?;
+    }
+    out.finish()?;
+    Ok(())
+}
+
+fn cmd_defrag(shell: &mut Shell, args: &[&str]) -> CommandResult {
+    let [path] = args else {
+        return Err(CommandError::Usage);
+    };
+    let inode = resolve(shell, path)?;
+    require_access(shell, inode, path, AccessMode::WRITE)?;
+    let report = shell.ext2.defrag(inode)?;
+    println!("{}: {}", path, report);
+    Ok(())
+}
+
+fn cmd_compactdir(shell: &mut Shell, args: &[&str]) -> CommandResult {
+    let [path] = args else {
+        return Err(CommandError::Usage);
+    };
+    let dir = resolve(shell, path)?;
+    if !shell.ext2.get_inode(dir)?.is_dir() {
+        return Err(Ext2Error::NotADirectory {
+            name: path.to_string(),
+        }
+        .into());
+    }
+    require_access(shell, dir, path, AccessMode::WRITE)?;
+    let before = shell.ext2.get_inode(dir)?.size_low;
+    let freed = shell.ext2.compact_dir(dir)?;
+    let after = shell.ext2.get_inode(dir)?.size_low;
+    println!(
+        "{}: {} block{} freed, size {} -> {}",
+        path,
+        freed,
+        if freed == 1 { "" } else { "s" },
+        before,
+        after
+    );
+    Ok(())
+}
+
+fn cmd_punch(shell: &mut Shell, args: &[&str]) -> CommandResult {
+    let [path, offset, len] = args else {
+        return Err(CommandError::Usage);
+    };
+    let (Ok(offset), Ok(len)) = (offset.parse::<u64>(), len.parse::<u64>()) else {
+        return Err(CommandError::Usage);
+    };
+    let inode = resolve(shell, path)?;
+    require_access(shell, inode, path, AccessMode::WRITE)?;
+    let freed = shell.ext2.punch_hole(inode, offset, len)?;
+    println!(
+        "{}: {} block{} freed",
+        path,
+        freed,
+        if freed == 1 { "" } else { "s" }
+    );
+    Ok(())
+}
+
+fn cmd_fallocate(shell: &mut Shell, args: &[&str]) -> CommandResult {
+    let (path, len, keep_size) = match args {
+        [path, len] => (path, len, true),
+        ["--keep-size=false", path, len] => (path, len, false),
+        _ => return Err(CommandError::Usage),
+    };
+    let Ok(len) = len.parse::<u64>() else {
+        return Err(CommandError::Usage);
+    };
+    let inode = resolve(shell, path)?;
+    require_access(shell, inode, path, AccessMode::WRITE)?;
+    let allocated = shell.ext2.preallocate(inode, len, keep_size)?;
+    println!(
+        "{}: {} block{} allocated",
+        path,
+        allocated,
+        if allocated == 1 { "" } else { "s" }
+    );
+    Ok(())
+}
+
+fn cmd_frag(shell: &mut Shell, args: &[&str]) -> CommandResult {
+    let (path, verbose) = match args {
+        [path] => (path, false),
+        [path, "-v"] | ["-v", path] => (path, true),
+        _ => return Err(CommandError::Usage),
+    };
+    let inode = resolve(shell, path)?;
+    let report = shell.ext2.fragmentation(inode)?;
+    println!(
+        "{}: {} block{} in {} extent{}",
+        path,
+        report.blocks,
+        if report.blocks == 1 { "" } else { "s" },
+        report.extents.len(),
+        if report.extents.len() == 1 { "" } else { "s" }
+    );
+    if verbose {
+        println!("{:>10} {:>10} {:>8}", "Logical", "Physical", "Length");
+        for extent in &report.extents {
+            println!(
+                "{:>10} {:>10} {:>8}",
+                extent.logical, extent.physical, extent.len
+            );
+        }
+    }
+    Ok(())
+}
+
+fn cmd_stats(shell: &mut Shell, args: &[&str]) -> CommandResult {
+    let top = match args {
+        [] => 10,
+        [count] => count.parse().map_err(|_| CommandError::Usage)?,
+        _ => return Err(CommandError::Usage),
+    };
+    let stats = shell.ext2.stats(top)?;
+    println!("{:<18} {:>8} {:>14}", "Type", "Count", "Bytes");
+    for (name, kind) in &stats.by_type {
+        println!("{:<18} {:>8} {:>14}", name, kind.count, kind.bytes);
+    }
+    println!(
+        "\n{} directories, {:.1} entries each on average",
+        stats.dirs,
+        stats.average_dir_entries()
+    );
+    if !stats.largest.is_empty() {
+        println!("\nLargest files:");
+        for (inode, size) in &stats.largest {
+            println!("{:>14}  inode {}", size, inode);
+        }
+    }
+    let most = stats.size_histogram.iter().copied().max().unwrap_or(0);
+    if most > 0 {
+        println!("\nFile sizes:");
+    }
+    for (bucket, &count) in stats.size_histogram.iter().enumerate() {
+        if count == 0 {
+            continue;
+        }
+        let range = match bucket {
+            0 => String::from("empty"),
+            _ => format!("[{}, {})", size_label(bucket - 1), size_label(bucket)),
+        };
+        let bar = "#".repeat(count.div_ceil(most.div_ceil(40)));
+        println!("{:>15} {:>8}  {}", range, count, bar);
+    }
+    Ok(())
+}
+
+/// 2^`power` bytes in the biggest binary unit that keeps it whole, e.g.
+/// 12 -> "4K".
+fn size_label(power: usize) -> String {
+    let units = ["", "K", "M", "G", "T", "P", "E"];
+    let unit = (power / 10).min(units.len() - 1);
+    format!("{}{}", 1u64 << (power - 10 * unit), units[unit])
+}
+
+/// `part` as a percentage of `whole`, rounded up like df does.
+fn percent(part: u64, whole: u64) -> u64 {
+    if whole == 0 {
+        0
+    } else {
+        (part * 100).div_ceil(whole)
+    }
+}
+
+fn cmd_badblocks(shell: &mut Shell, args: &[&str]) -> CommandResult {
+    if !args.is_empty() {
+        return Err(CommandError::Usage);
+    }
+    let blocks = bad_blocks(&shell.ext2)?;
+    for block in &blocks {
+        println!("{}", block);
+    }
+    if blocks.is_empty() {
+        println!("no bad blocks");
+    }
+    Ok(())
+}
+
+/// The blocks marked unusable: the bad blocks inode's data blocks are the
+/// bad blocks themselves.
+fn bad_blocks(ext2: &Ext2) -> ext2::Result<Vec<usize>> {
+    let blocks = ext2.file_blocks(1)?.collect::<ext2::Result<Vec<_>>>()?;
+    Ok(blocks.into_iter().filter(|&block| block != 0).collect())
+}
+
+fn cmd_ilist(shell: &mut Shell, args: &[&str]) -> CommandResult {
+    let ["reserved"] = args else {
+        return Err(CommandError::Usage);
+    };
+    let yes_no = |yes| if yes { "yes" } else { "no" };
+    println!(
+        "{:>5}  {:18}  {:9}  {:6}  {:>10}  {:>6}",
+        "inode", "role", "allocated", "in use", "size", "blocks"
+    );
+    for reserved in shell.ext2.reserved_inodes()? {
+        println!(
+            "{:>5}  {:18}  {:9}  {:6}  {:>10}  {:>6}",
+            reserved.inode,
+            reserved.role.map_or("reserved", |role| role.role),
+            yes_no(reserved.allocated),
+            yes_no(reserved.in_use),
+            reserved.size,
+            reserved.blocks
+        );
+    }
+    println!("first usable inode: {}", shell.ext2.first_usable_inode());
+    match shell.ext2.lookup(2, "lost+found")? {
+        Some(inode) => println!("lost+found: inode {}", inode),
+        None => println!("lost+found: missing"),
+    }
+    Ok(())
+}
+
+fn cmd_fsck(shell: &mut Shell, args: &[&str]) -> CommandResult {
+    let repair = match args {
+        [] => false,
+        ["--repair"] => true,
+        _ => return Err(CommandError::Usage),
+    };
+    if repair {
+        let changes = shell.ext2.repair(confirm)?;
+        for change in &changes {
+            println!("fixed: {}", change);
+        }
+        if !changes.is_empty() {
+            println!("{} change(s) made, run 'sync' to save them", changes.len());
+        }
+    }
+    let problems = shell
+        .ext2
+        .check_with_progress(&TerminalProgress::new("directories"))?;
+    for problem in &problems {
+        println!("{}", problem);
+    }
+    if problems.is_empty() {
+        println!("clean");
+    } else {
+        println!("{} problem(s) found", problems.len());
+    }
+    Ok(())
+}
+
+fn cmd_sync(shell: &mut Shell, args: &[&str]) -> CommandResult {
+    if !args.is_empty() {
+        return Err(CommandError::Usage);
+    }
+    let path = &shell.image;
+    let file = match OpenOptions::new().write(true).open(path) {
+        Ok(file) => file,
+        Err(err) => {
+            println!("sync: {}: {}", path, err);
+            return Ok(());
+        }
+    };
+    // rolling back past a sync would leave the image with blocks that
+    // nothing in memory says to rewrite
+    if let Some(snapshot) = &shell.snapshot {
+        if shell.ext2.modified_since(snapshot) {
+            println!("sync: there are changes since the snapshot, which 'rollback' could undo");
+            if !confirm("sync them anyway, and drop the snapshot?") {
+                return Ok(());
+            }
+        }
+        shell.snapshot = None;
+    }
+    let offset = shell
+        .partition
+        .as_ref()
+        .map_or(0, |partition| partition.offset);
+    let written = shell.ext2.sync(&mut OffsetDevice::new(file, offset))?;
+    println!("wrote {} block(s) to {}", written, path);
+    Ok(())
+}
+
+fn cmd_snapshot(shell: &mut Shell, args: &[&str]) -> CommandResult {
+    if !args.is_empty() {
+        return Err(CommandError::Usage);
+    }
+    let snapshot = shell.ext2.snapshot();
+    println!(
+        "snapshot taken, with {} modified block(s) so far",
+        snapshot.dirty_blocks()
+    );
+    shell.snapshot = Some(snapshot);
+    Ok(())
+}
+
+fn cmd_rollback(shell: &mut Shell, args: &[&str]) -> CommandResult {
+    if !args.is_empty() {
+        return Err(CommandError::Usage);
+    }
+    let Some(snapshot) = &shell.snapshot else {
+        println!("rollback: no snapshot taken");
+        return Ok(());
+    };
+    if !shell.ext2.modified_since(snapshot) {
+        println!("rollback: nothing changed since the snapshot");
+        return Ok(());
+    }
+    shell.ext2.rollback(snapshot);
+    println!("rolled back to the snapshot");
+    if !shell.ext2.inode_is_allocated(shell.cwd)? {
+        println!("rollback: the cwd didn't exist then; now in /");
+        shell.cwd = 2;
+    }
+    Ok(())
+}
+
+fn cmd_lsattr(shell: &mut Shell, args: &[&str]) -> CommandResult {
+    let [path] = args else {
+        return Err(CommandError::Usage);
+    };
+    let inode = resolve(shell, path)?;
+    println!("{} {}", shell.ext2.inode_flags(inode)?.letters(), path);
+    Ok(())
+}
+
+fn cmd_chattr(shell: &mut Shell, args: &[&str]) -> CommandResult {
+    let [change, path] = args else {
+        return Err(CommandError::Usage);
+    };
+    let (set, letters) = match change.split_at(change.len().min(1)) {
+        ("+", letters) => (true, letters),
+        ("-", letters) => (false, letters),
+        _ => return Err(CommandError::Usage),
+    };
+    let mut changed = InodeFlags::empty();
+    for letter in letters.chars() {
+        changed |= match letter {
+            'i' => InodeFlags::IMMUTABLE,
+            'a' => InodeFlags::APPEND,
+            _ => return Err(CommandError::Usage),
+        };
+    }
+    if changed.is_empty() {
+        return Err(CommandError::Usage);
+    }
+    let inode = resolve(shell, path)?;
+    // like CAP_LINUX_IMMUTABLE, which only root has
+    if shell.ext2.credentials().uid != 0 {
+        return Err(Ext2Error::NotPermitted {
+            name: path.to_string(),
+        }
+        .into());
+    }
+    let mut flags = shell.ext2.inode_flags(inode)?;
+    flags.set(changed, set);
+    shell.ext2.set_inode_flags(inode, flags)?;
+    Ok(())
+}
+
+fn cmd_su(shell: &mut Shell, args: &[&str]) -> CommandResult {
+    let (uid, gid) = match args {
+        [uid] => (*uid, *uid),
+        [uid, gid] => (*uid, *gid),
+        _ => return Err(CommandError::Usage),
+    };
+    let (Ok(uid), Ok(gid)) = (uid.parse(), gid.parse()) else {
+        return Err(CommandError::Usage);
+    };
+    // allocations are made for this user too, which decides whether the
+    // blocks reserved for root may be used
+    shell.ext2.set_credentials(Credentials { uid, gid });
+    Ok(())
+}
+
+fn cmd_whoami(shell: &mut Shell, args: &[&str]) -> CommandResult {
+    if !args.is_empty() {
+        return Err(CommandError::Usage);
+    }
+    let cred = shell.ext2.credentials();
+    println!("uid={} gid={}", cred.uid, cred.gid);
+    Ok(())
+}
+
+fn cmd_access(shell: &mut Shell, args: &[&str]) -> CommandResult {
+    let [path, letters] = args else {
+        return Err(CommandError::Usage);
+    };
+    let mut mode = AccessMode::empty();
+    for letter in letters.chars() {
+        mode |= match letter {
+            'r' => AccessMode::READ,
+            'w' => AccessMode::WRITE,
+            'x' => AccessMode::EXEC,
+            _ => return Err(CommandError::Usage),
+        };
+    }
+    let inode = resolve(shell, path)?;
+    let allowed = shell.ext2.access(inode, &shell.ext2.credentials(), mode)?;
+    println!("{}: {}", path, if allowed { "allowed" } else { "denied" });
+    Ok(())
+}
+
+/// Fail with "Permission denied" for `name` unless the current user may
+/// access `inode` in every way in `mode`.
+/// The index of directory `cwd` cached in `cache`, first rebuilt if it's of
+/// another directory or anything was modified since it was built; the
+/// generation count catches changes made through any path, e.g. a hard link
+/// to the cwd. Takes the shell's fields apart so `ext2` stays usable.
+fn cwd_index<'a>(
+    ext2: &Ext2,
+    cwd: usize,
+    cache: &'a mut Option<DirIndex>,
+) -> ext2::Result<&'a DirIndex> {
+    let stale = cache
+        .as_ref()
+        .map_or(true, |index| index.dir() != cwd || !index.is_current(ext2));
+    if stale {
+        *cache = Some(ext2.dir_index(cwd)?);
+    }
+    Ok(cache.as_ref().unwrap())
+}
+
+/// Resolve `path` from the cwd, looking a plain name up in the cwd's index
+/// and anything longer up through the path cache, as is a plain name the
+/// index doesn't have when lookups ignore case.
+fn resolve(shell: &mut Shell, path: &str) -> ext2::Result<usize> {
+    if path.is_empty() || path.contains('/') {
+        return shell.paths.resolve(&shell.ext2, shell.cwd, path);
+    }
+    let found = cwd_index(&shell.ext2, shell.cwd, &mut shell.cwd_index)?.get(path);
+    match found {
+        Some(inode) => Ok(inode),
+        // the index only has the names as they are
+        None if shell.ext2.case_insensitive_lookup() => {
+            shell.paths.resolve(&shell.ext2, shell.cwd, path)
+        }
+        None => Err(Ext2Error::NotFound {
+            name: path.to_string(),
+        }),
+    }
+}
+
+fn require_access(shell: &Shell, inode: usize, name: &str, mode: AccessMode) -> CommandResult {
+    if shell.ext2.access(inode, &shell.ext2.credentials(), mode)? {
+        Ok(())
+    } else {
+        Err(Ext2Error::PermissionDenied {
+            name: name.to_string(),
+        }
+        .into())
+    }
+}
+
+/// Ask a yes/no question on stdin; anything but yes is no.
+fn confirm(question: &str) -> bool {
+    print!("{} [y/N] ", question);
+    io::stdout().flush().unwrap();
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer).is_ok() && matches!(answer.trim(), "y" | "Y" | "yes")
+}
+
+fn cmd_undelete(shell: &mut Shell, args: &[&str]) -> CommandResult {
+    match args {
+        ["list"] => {
+            let deleted = shell.ext2.deleted_inodes()?;
+            if deleted.is_empty() {
+                println!("no deleted inodes found");
+                return Ok(());
+            }
+            println!(
+                "{:>7} {:>10} {:<23} {:<18} names",
+                "inode", "size", "deleted", "status"
+            );
+            for found in deleted {
+                let status = if found.reused_blocks.is_empty() {
+                    String::from("recoverable")
+                } else {
+                    format!("{} block(s) reused", found.reused_blocks.len())
+                };
+                println!(
+                    "{:>7} {:>10} {:<23} {:<18} {}",
+                    found.inode,
+                    found.size,
+                    ext2::format_time(found.dtime),
+                    status,
+                    found
+                        .names
+                        .iter()
+                        .map(|name| Escaped(name.as_bytes()).to_string())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                );
+            }
+        }
+        [inode, name] => {
+            let Ok(inode) = inode.parse::<usize>() else {
+                return Err(CommandError::Usage);
+            };
+            require_access(shell, shell.cwd, ".", AccessMode::WRITE | AccessMode::EXEC)?;
+            shell.ext2.undelete(inode, shell.cwd, name)?;
+        }
+        [inode] => {
+            let Ok(inode) = inode.parse::<usize>() else {
+                return Err(CommandError::Usage);
+            };
+            let dir = shell.ext2.ensure_lost_and_found()?;
+            require_access(
+                shell,
+                dir,
+                "/lost+found",
+                AccessMode::WRITE | AccessMode::EXEC,
+            )?;
+            shell.ext2.undelete(inode, dir, &format!("#{}", inode))?;
+            println!("recovered as /lost+found/#{}", inode);
+        }
+        _ => return Err(CommandError::Usage),
+    }
+    Ok(())
+}
+
+fn cmd_orphans(shell: &mut Shell, args: &[&str]) -> CommandResult {
+    match args {
+        [] => {
+            let orphans = shell.ext2.orphans()?;
+            if orphans.is_empty() {
+                println!("no orphaned inodes found");
+                return Ok(());
+            }
+            println!(
+                "{:>7} {:<10} {:>10} {:>5}",
+                "inode", "type", "size", "links"
+            );
+            for inode in orphans {
+                let record = shell.ext2.get_inode(inode)?;
+                println!(
+                    "{:>7} {:<10} {:>10} {:>5}",
+                    inode,
+                    record.type_name(),
+                    record.size(),
+                    record.hard_links
+                );
+            }
+        }
+        ["relink", inode] => {
+            let Ok(inode) = inode.parse::<usize>() else {
+                return Err(CommandError::Usage);
+            };
+            if shell.ext2.credentials().uid != 0 {
+                return Err(Ext2Error::NotPermitted {
+                    name: format!("inode {}", inode),
+                }
+                .into());
+            }
+            // relinking is refused either way, so say that rather than
+            // whether it's an orphan
+            if shell.ext2.is_read_only() {
+                return Err(Ext2Error::ReadOnly.into());
+            }
+            if !shell.ext2.orphans()?.contains(&inode) {
+                println!("inode {} isn't an orphan", inode);
+                return Ok(());
+            }
+            println!("relinked as {}", shell.ext2.relink_orphan(inode)?);
+        }
+        _ => return Err(CommandError::Usage),
+    }
+    Ok(())
+}
+
+fn cmd_rm(shell: &mut Shell, args: &[&str]) -> CommandResult {
+    let mut recursive = false;
+    let mut interactive = false;
+    let mut force = false;
+    let mut paths = Vec::new();
+    for arg in args {
+        match *arg {
+            flags if flags.starts_with('-') && flags.len() > 1 => {
+                for flag in flags[1..].chars() {
+                    match flag {
+                        'r' | 'R' => recursive = true,
+                        'i' => interactive = true,
+                        'f' => force = true,
+                        _ => return Err(CommandError::Usage),
+                    }
+                }
+            }
+            path => paths.push(path),
+        }
+    }
+    if paths.is_empty() {
+        return Err(CommandError::Usage);
+    }
+    for path in paths {
+        let (dir, name) = match parent_and_name(shell, path) {
+            Err(err) if force && matches!(err.root_cause(), Ext2Error::NotFound { .. }) => continue,
+            found => found?,
+        };
+        let inode = match shell.ext2.lookup(dir, name)? {
+            Some(inode) => inode,
+            None if force => continue,
+            None => {
+                return Err(Ext2Error::NotFound {
+                    name: path.to_string(),
+                }
+                .into())
+            }
         };
-        assert_eq!(superblock.magic, EXT2_MAGIC);
-        // at this point, we strongly suspect these bytes are indeed an ext2 filesystem
+        require_access(shell, dir, path, AccessMode::WRITE | AccessMode::EXEC)?;
+        if shell.mounts.iter().any(|mount| mount.point == inode) {
+            println!("rm: {}: something is mounted there", path);
+            continue;
+        }
+        if !recursive {
+            if !interactive || confirm(&format!("remove {}?", path)) {
+                shell.ext2.unlink(dir, name)?;
+            }
+            continue;
+        }
+        // the paths from `remove_tree` start at `name`; show them from `path`
+        let trimmed = path.trim_end_matches('/');
+        let prefix = &trimmed[..trimmed.len() - name.len()];
+        let mut last = String::new();
+        let removed = shell.ext2.remove_tree(dir, name, |entry, record| {
+            last = format!("{}{}", prefix, entry);
+            !interactive || confirm(&format!("remove {} {}?", record.type_name(), last))
+        });
+        if let Err(err) = removed {
+            println!("rm: stopped at {}", last);
+            return Err(err.into());
+        }
+    }
+    if !shell.ext2.inode_is_allocated(shell.cwd)? {
+        println!("rm: the cwd was removed; now in /");
+        shell.cwd = 2;
+    }
+    Ok(())
+}
+
+fn cmd_rmdir(shell: &mut Shell, args: &[&str]) -> CommandResult {
+    if args.is_empty() {
+        return Err(CommandError::Usage);
+    }
+    for path in args {
+        let (dir, name) = parent_and_name(shell, path)?;
+        require_access(shell, dir, path, AccessMode::WRITE | AccessMode::EXEC)?;
+        if let Some(inode) = shell.ext2.lookup(dir, name)? {
+            if shell.mounts.iter().any(|mount| mount.point == inode) {
+                println!("rmdir: {}: something is mounted there", path);
+                continue;
+            }
+        }
+        shell.ext2.remove_dir(dir, name)?;
+    }
+    if !shell.ext2.inode_is_allocated(shell.cwd)? {
+        println!("rmdir: the cwd was removed; now in /");
+        shell.cwd = 2;
+    }
+    Ok(())
+}
+
+fn cmd_mv(shell: &mut Shell, args: &[&str]) -> CommandResult {
+    let [source, dest] = args else {
+        return Err(CommandError::Usage);
+    };
+    let (old_parent, old_name) = parent_and_name(shell, source)?;
+    let inode = shell
+        .ext2
+        .lookup(old_parent, old_name)?
+        .ok_or_else(|| Ext2Error::NotFound {
+            name: source.to_string(),
+        })?;
+    if shell.mounts.iter().any(|mount| mount.point == inode) {
+        println!("mv: {}: something is mounted there", source);
+        return Ok(());
+    }
+    // into an existing directory, keeping the name
+    let (new_parent, new_name) = match resolve(shell, dest) {
+        Ok(dir) if dir != inode && shell.ext2.get_inode(dir)?.is_dir() => (dir, old_name),
+        _ => parent_and_name(shell, dest)?,
+    };
+    require_access(
+        shell,
+        old_parent,
+        source,
+        AccessMode::WRITE | AccessMode::EXEC,
+    )?;
+    require_access(
+        shell,
+        new_parent,
+        dest,
+        AccessMode::WRITE | AccessMode::EXEC,
+    )?;
+    shell
+        .ext2
+        .rename(old_parent, old_name, new_parent, new_name)?;
+    Ok(())
+}
+
+/// Split `path` into the directory it's in, resolved, and its last component.
+fn parent_and_name<'a>(shell: &mut Shell, path: &'a str) -> ext2::Result<(usize, &'a str)> {
+    let trimmed = path.trim_end_matches('/');
+    match trimmed.rsplit_once('/') {
+        Some((parent, name)) => {
+            // `/name`'s parent is the root
+            let parent = if parent.is_empty() { "/" } else { parent };
+            Ok((resolve(shell, parent)?, name))
+        }
+        None => Ok((shell.cwd, trimmed)),
+    }
+}
+
+fn cmd_mount(shell: &mut Shell, args: &[&str]) -> CommandResult {
+    let [image, path] = args else {
+        if !args.is_empty() {
+            return Err(CommandError::Usage);
+        }
+        for mount in &shell.mounts {
+            println!("{} on {} (read-only)", mount.image, mount.path);
+        }
+        return Ok(());
+    };
+    let point = resolve(shell, path)?;
+    if !shell.ext2.get_inode(point)?.is_dir() {
+        return Err(Ext2Error::NotADirectory {
+            name: path.to_string(),
+        }
+        .into());
+    }
+    if shell.mounts.iter().any(|mount| mount.point == point) {
+        println!("mount: {}: something is already mounted there", path);
+        return Ok(());
+    }
+    if shell.ext2.read_dir_inode(point)?.len() > 2 {
+        println!("mount: {}: directory isn't empty", path);
+        return Ok(());
+    }
+    let disk = match fs::read(image) {
+        Ok(disk) => disk,
+        Err(err) => {
+            println!("mount: {}: {}", image, err);
+            return Ok(());
+        }
+    };
+    let ext2 = match Ext2Options::new().read_only(true).open(disk) {
+        Ok(ext2) => ext2,
+        Err(err) => {
+            println!("mount: {}: {}", image, err);
+            return Ok(());
+        }
+    };
+    shell.mounts.push(Mount {
+        point,
+        path: path.to_string(),
+        image: image.to_string(),
+        ext2,
+    });
+    Ok(())
+}
+
+/// Resolve `path` like `Ext2::resolve_path`, but step onto a mounted image
+/// on reaching its mountpoint. Returns the filesystem the path ends on and
+/// the inode there.
+fn resolve_mounted<'a>(shell: &'a Shell, path: &str) -> ext2::Result<(&'a Ext2, usize)> {
+    let mut ext2 = &shell.ext2;
+    let mut current = if path.starts_with('/') { 2 } else { shell.cwd };
+    let mut mounted = false;
+    for component in path.split('/').filter(|c| !c.is_empty()) {
+        if !ext2.get_inode(current)?.is_dir() {
+            return Err(Ext2Error::NotADirectory {
+                name: path.to_string(),
+            });
+        }
+        current = ext2
+            .lookup(current, component)?
+            .ok_or_else(|| Ext2Error::NotFound {
+                name: path.to_string(),
+            })?;
+        // mounts don't nest, so only the primary image has mountpoints
+        if let Some(mount) = shell
+            .mounts
+            .iter()
+            .find(|mount| !mounted && mount.point == current)
+        {
+            ext2 = &mount.ext2;
+            current = 2;
+            mounted = true;
+        }
+    }
+    Ok((ext2, current))
+}
+
+fn cmd_mkfs(_shell: &mut Shell, args: &[&str]) -> CommandResult {
+    let (path, size_kb, block_size) = match args {
+        [path, size_kb] => (*path, *size_kb, "1024"),
+        [path, size_kb, block_size] => (*path, *size_kb, *block_size),
+        _ => return Err(CommandError::Usage),
+    };
+    let (Ok(size_kb), Ok(block_size)) = (size_kb.parse::<usize>(), block_size.parse::<usize>())
+    else {
+        return Err(CommandError::Usage);
+    };
+    let mut image = vec![0; size_kb * 1024];
+    ext2::mkfs(&mut image, &MkfsOptions::new().block_size(block_size))?;
+    if let Err(err) = fs::write(path, &image) {
+        println!("mkfs: {}: {}", path, err);
+        return Ok(());
+    }
+    println!("wrote a {} KiB filesystem to {}", size_kb, path);
+    Ok(())
+}
+
+fn cmd_resize(shell: &mut Shell, args: &[&str]) -> CommandResult {
+    let blocks = match args {
+        [] => shell.ext2.device_blocks(),
+        [blocks] => match blocks.parse::<usize>() {
+            Ok(blocks) => blocks,
+            Err(_) => return Err(CommandError::Usage),
+        },
+        _ => return Err(CommandError::Usage),
+    };
+    let old_blocks = shell.ext2.superblock.blocks_count;
+    let old_groups = shell.ext2.block_groups.len();
+    let new_blocks = shell.ext2.resize_grow(blocks)?;
+    println!(
+        "grew from {} to {} blocks ({} to {} groups)",
+        old_blocks,
+        new_blocks,
+        old_groups,
+        shell.ext2.block_groups.len()
+    );
+    Ok(())
+}
+
+fn cmd_label(shell: &mut Shell, args: &[&str]) -> CommandResult {
+    match args {
+        [] => println!("{}", shell.ext2.label()),
+        [name] => {
+            let mut len = name.len().min(16);
+            while !name.is_char_boundary(len) {
+                len -= 1;
+            }
+            if len < name.len() {
+                eprintln!(
+                    "warning: volume name {:?} is over 16 bytes, using {:?}",
+                    name,
+                    &name[..len]
+                );
+            }
+            shell.ext2.set_label(&name[..len])?;
+        }
+        _ => return Err(CommandError::Usage),
+    }
+    Ok(())
+}
+
+fn cmd_uuid(shell: &mut Shell, args: &[&str]) -> CommandResult {
+    match args {
+        [] => println!("{}", shell.ext2.uuid),
+        ["--regenerate"] => println!("{}", shell.ext2.regenerate_uuid()?),
+        _ => return Err(CommandError::Usage),
+    }
+    Ok(())
+}
+
+fn cmd_populate(shell: &mut Shell, args: &[&str]) -> CommandResult {
+    let (host_dir, dest) = match args {
+        [host_dir] => (*host_dir, "/"),
+        [host_dir, dest] if dest.starts_with('/') => (*host_dir, *dest),
+        _ => return Err(CommandError::Usage),
+    };
+    let dir = shell.ext2.resolve_path(2, dest)?;
+    require_access(shell, dir, dest, AccessMode::WRITE | AccessMode::EXEC)?;
+    let summary = shell.ext2.populate_from_host_with_progress(
+        Path::new(host_dir),
+        dest,
+        &TerminalProgress::new("files"),
+    )?;
+    println!("imported {}", summary);
+    for path in summary.skipped {
+        println!(
+            "skipped {}: not a file, directory or symlink",
+            path.display()
+        );
+    }
+    Ok(())
+}
+
+fn cmd_tar(shell: &mut Shell, args: &[&str]) -> CommandResult {
+    let (output, path) = match args {
+        [output] => (*output, "."),
+        [output, path] => (*output, *path),
+        _ => return Err(CommandError::Usage),
+    };
+    let dir = resolve(shell, path)?;
+    if !shell.ext2.get_inode(dir)?.is_dir() {
+        return Err(Ext2Error::NotADirectory {
+            name: path.to_string(),
+        }
+        .into());
+    }
+    require_access(shell, dir, path, AccessMode::READ | AccessMode::EXEC)?;
+    let file = match File::create(output) {
+        Ok(file) => file,
+        Err(err) => {
+            println!("tar: {}: {}", output, err);
+            return Ok(());
+        }
+    };
+    let progress = TerminalProgress::new("files");
+    let entries = match shell
+        .ext2
+        .export_tar_with_progress(dir, BufWriter::new(file), &progress)
+    {
+        Ok(entries) => entries,
+        Err(err) => {
+            // an archive cut short would pass for a whole one
+            if matches!(err, Ext2Error::Cancelled) {
+                let _ = fs::remove_file(output);
+            }
+            return Err(err.into());
+        }
+    };
+    println!("wrote {} entries to {}", entries, output);
+    Ok(())
+}
+
+fn cmd_untar(shell: &mut Shell, args: &[&str]) -> CommandResult {
+    let (archive, path) = match args {
+        [archive] => (*archive, "."),
+        [archive, path] => (*archive, *path),
+        _ => return Err(CommandError::Usage),
+    };
+    let dir = resolve(shell, path)?;
+    if !shell.ext2.get_inode(dir)?.is_dir() {
+        return Err(Ext2Error::NotADirectory {
+            name: path.to_string(),
+        }
+        .into());
+    }
+    require_access(shell, dir, path, AccessMode::WRITE | AccessMode::EXEC)?;
+    let file = match File::open(archive) {
+        Ok(file) => file,
+        Err(err) => {
+            println!("untar: {}: {}", archive, err);
+            return Ok(());
+        }
+    };
+    let summary = shell.ext2.import_tar_with_progress(
+        dir,
+        BufReader::new(file),
+        &TerminalProgress::new("files"),
+    )?;
+    println!("imported {}", summary);
+    for skipped in summary.skipped {
+        println!(
+            "skipped {}: an unsupported kind of entry, or outside {}",
+            skipped.display(),
+            path
+        );
+    }
+    Ok(())
+}
+
+fn cmd_timeline(shell: &mut Shell, args: &[&str]) -> CommandResult {
+    let mut path = None;
+    let mut deleted = false;
+    let mut output = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match *arg {
+            "--deleted" => deleted = true,
+            "--output" => output = Some(*args.next().ok_or(CommandError::Usage)?),
+            _ if arg.starts_with("--") || path.is_some() => return Err(CommandError::Usage),
+            _ => path = Some(*arg),
+        }
+    }
+    let path = path.unwrap_or(".");
+    let dir = resolve(shell, path)?;
+    if !shell.ext2.get_inode(dir)?.is_dir() {
+        return Err(Ext2Error::NotADirectory {
+            name: path.to_string(),
+        }
+        .into());
+    }
+    require_access(shell, dir, path, AccessMode::READ | AccessMode::EXEC)?;
+    let Some(output) = output else {
+        shell
+            .ext2
+            .export_timeline(dir, deleted, BufWriter::new(io::stdout().lock()))?;
+        return Ok(());
+    };
+    let file = match File::create(output) {
+        Ok(file) => file,
+        Err(err) => {
+            println!("timeline: {}: {}", output, err);
+            return Ok(());
+        }
+    };
+    let rows = shell
+        .ext2
+        .export_timeline(dir, deleted, BufWriter::new(file))?;
+    println!("wrote {} rows to {}", rows, output);
+    Ok(())
+}
+
+fn cmd_cmp(shell: &mut Shell, args: &[&str]) -> CommandResult {
+    let [path1, path2] = args else {
+        return Err(CommandError::Usage);
+    };
+    let mut files = Vec::new();
+    for path in [path1, path2] {
+        let (ext2, inode) = resolve_mounted(shell, path)?;
+        if ext2.get_inode(inode)?.is_dir() {
+            return Err(Ext2Error::IsADirectory {
+                name: path.to_string(),
+            }
+            .into());
+        }
+        if !ext2.access(inode, &shell.ext2.credentials(), AccessMode::READ)? {
+            return Err(Ext2Error::PermissionDenied {
+                name: path.to_string(),
+            }
+            .into());
+        }
+        files.push((ext2, inode));
+    }
+    let (ext2, inode) = files[0];
+    let (other, other_inode) = files[1];
+    match ext2.first_difference(inode, other, other_inode)? {
+        None => println!("{} and {} are identical", path1, path2),
+        Some(offset) => {
+            let size1 = ext2.get_inode(inode)?.size();
+            let size2 = other.get_inode(other_inode)?.size();
+            // like cmp(1), running out of one file isn't a differing byte
+            if offset == size1.min(size2) {
+                let shorter = if size1 < size2 { path1 } else { path2 };
+                println!("cmp: EOF on {} after byte {}", shorter, offset);
+            } else {
+                println!("{} {} differ: byte {}", path1, path2, offset + 1);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn cmd_diff(shell: &mut Shell, args: &[&str]) -> CommandResult {
+    let (skip_lost_and_found, path1, path2) = match args {
+        ["-r", "--skip-lost+found", path1, path2] => (true, path1, path2),
+        ["-r", path1, path2] => (false, path1, path2),
+        _ => return Err(CommandError::Usage),
+    };
+    let mut dirs = Vec::new();
+    for path in [path1, path2] {
+        let (ext2, inode) = resolve_mounted(shell, path)?;
+        if !ext2.get_inode(inode)?.is_dir() {
+            return Err(Ext2Error::NotADirectory {
+                name: path.to_string(),
+            }
+            .into());
+        }
+        dirs.push((ext2, inode));
+    }
+    let (ext2, dir) = dirs[0];
+    let (other, other_dir) = dirs[1];
+    let mut differences = ext2.diff_trees(dir, other, other_dir)?;
+    // the paths are relative to the two directories, so lost+found is only
+    // among them if one is a root
+    if skip_lost_and_found && (dir == 2 || other_dir == 2) {
+        differences.retain(|difference| !in_lost_and_found(difference.path()));
+    }
+    if differences.is_empty() {
+        println!("{} and {} are identical", path1, path2);
+    }
+    for difference in differences {
+        println!("{}", difference);
+    }
+    Ok(())
+}
 
-        println!("superblock:\n{:?}", superblock);
-        println!("size of Inode struct: {}", mem::size_of::<Inode>());
+/// Whether `path`, from the root, is `lost+found` or something in it.
+fn in_lost_and_found(path: &str) -> bool {
+    let path = path.trim_start_matches('/');
+    path == "lost+found" || path.starts_with("lost+found/")
+}
 
-        let block_group_count = superblock
-            .blocks_count
-            .div_ceil(superblock.blocks_per_group) as usize;
+fn cmd_biggest(shell: &mut Shell, args: &[&str]) -> CommandResult {
+    let (root, prefix, n, options) = top_args(shell, args)?;
+    for file in shell.ext2.biggest_files(root, n, &options)? {
+        println!(
+            "{:>12}  {}{}",
+            file.size,
+            prefix,
+            Escaped(file.path.as_bytes())
+        );
+    }
+    Ok(())
+}
 
-        // not sure about the unit of block_size, bits or bytes?
-        let block_size: usize = 1024 << superblock.log_block_size;
+fn cmd_recent(shell: &mut Shell, args: &[&str]) -> CommandResult {
+    let (root, prefix, n, options) = top_args(shell, args)?;
+    for file in shell.ext2.recent_files(root, n, &options)? {
         println!(
-            "there are {} block groups and block_size = {}",
-            block_group_count, block_size
+            "{}  {}{}",
+            ext2::format_time(file.mtime),
+            prefix,
+            Escaped(file.path.as_bytes())
         );
-        let block_groups_rest_bytes = header_body_bytes.1.split_at(block_size);
+    }
+    Ok(())
+}
 
-        let block_groups = unsafe {
-            std::slice::from_raw_parts_mut(
-                block_groups_rest_bytes.0.as_ptr() as *mut BlockGroupDescriptor,
-                block_group_count,
-            )
-        };
+fn cmd_find(shell: &mut Shell, args: &[&str]) -> CommandResult {
+    let mut json = shell.config.json;
+    let mut name = None;
+    let mut file_type = None;
+    let mut path = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match *arg {
+            "--json" => json = true,
+            "-name" => name = Some(*args.next().ok_or(CommandError::Usage)?),
+            "-type" => {
+                // find's letters, which are ls's but for `f`
+                let letter = match *args.next().ok_or(CommandError::Usage)? {
+                    "f" => '-',
+                    letter @ ("d" | "l" | "c" | "b" | "p" | "s") => letter.chars().next().unwrap(),
+                    _ => return Err(CommandError::Usage),
+                };
+                file_type = Some(letter);
+            }
+            _ if !arg.starts_with('-') && path.is_none() => path = Some(*arg),
+            _ => return Err(CommandError::Usage),
+        }
+    }
+    let (root, prefix) = search_root(shell, path.as_slice())?;
+    let mut found = Vec::new();
+    let options = WalkOptions::new().cancel_on(&INTERRUPTED);
+    shell.ext2.walk(root, &options, &mut |entry| {
+        let wanted = name.is_none_or(|pattern| wildcard_match(pattern, entry.name))
+            && file_type.is_none_or(|letter| type_letter(entry.record) == letter);
+        if wanted {
+            let path = format!("{}{}", prefix, entry.path());
+            found.push(WithPath {
+                path,
+                info: EntryInfo::new(entry.name, entry.inode, entry.record),
+            });
+        }
+        WalkControl::Continue
+    })?;
+    if json {
+        print_json(&found);
+    } else {
+        for entry in &found {
+            println!("{}", Escaped(entry.path.as_bytes()));
+        }
+    }
+    Ok(())
+}
 
-        println!("block group 0: {:?}", block_groups[0]);
-
-        let blocks = unsafe {
-            std::slice::from_raw_parts(
-                block_groups_rest_bytes.1.as_ptr() as *const u8,
-                // would rather use: device_bytes.as_ptr(),
-                superblock.blocks_count as usize * block_size,
-            )
-        }
-        .chunks(block_size)
-        .collect::<Vec<_>>();
-
-        let offset_bytes = (blocks[0].as_ptr() as usize) - start_addr;
-        let block_offset = offset_bytes / block_size;
-        let uuid = Uuid::from_bytes(superblock.fs_id);
-        Ext2 {
-            superblock,
-            block_groups,
-            blocks,
-            block_size,
-            uuid,
-            block_offset,
-        }
-    }
-
-    // given a (1-indexed) inode number, return that #'s inode structure
-    // the inode number is a unique identifier among the entire filesystem
-    pub fn get_inode(&self, inode: usize) -> &Inode {
-        // find the block group that contains the inode
-        let group: usize = (inode - 1) / self.superblock.inodes_per_group as usize;
-        // find the index of the inode within the block group
-        let index: usize = (inode - 1) % self.superblock.inodes_per_group as usize;
-
-        // println!("in get_inode, inode num = {}, index = {}, group = {}", inode, index, group);
-        let inode_table_block =
-            (self.block_groups[group].inode_table_block) as usize - self.block_offset;
-        // println!("in get_inode, block number of inode table {}", inode_table_block);
-        let inode_table = unsafe {
-            std::slice::from_raw_parts(
-                self.blocks[inode_table_block].as_ptr() as *const Inode,
-                self.superblock.inodes_per_group as usize,
-            )
+/// Whether `name` matches the shell pattern `pattern`, where `*` stands for
+/// any run of characters and `?` for any one, as `find -name` takes them.
+fn wildcard_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    // where the last `*` was, and where in `name` it's matched up to
+    let (mut p, mut n) = (0, 0);
+    let mut star = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, n));
+                p += 1;
+            }
+            Some('?') => (p, n) = (p + 1, n + 1),
+            Some(&c) if c == name[n] => (p, n) = (p + 1, n + 1),
+            _ => match star {
+                // let the `*` take one more character and try again
+                Some((star_p, star_n)) => {
+                    star = Some((star_p, star_n + 1));
+                    (p, n) = (star_p + 1, star_n + 1);
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+fn cmd_browse(shell: &mut Shell, args: &[&str]) -> CommandResult {
+    let (root, _) = search_root(shell, args)?;
+    let path = args.first().copied().unwrap_or(".");
+    if !io::stdin().is_terminal() || !io::stdout().is_terminal() {
+        let total = shell.du.total(&shell.ext2, root)?;
+        for entry in shell.du.children(&shell.ext2, root)? {
+            println!(
+                "{:>6}  {}",
+                ext2::human_size(entry.bytes),
+                browse_name(&entry)
+            );
+        }
+        println!("{:>6}  total", ext2::human_size(total));
+        return Ok(());
+    }
+
+    let _screen = FullScreen::enter()?;
+    let mut stack = vec![BrowseDir::read(shell, root, path.to_string())?];
+    loop {
+        let dir = stack.last_mut().unwrap();
+        dir.draw()?;
+        let Event::Key(key) = event::read()? else {
+            continue;
         };
-        // probably want a Vec of BlockGroups in our Ext structure so we don't have to slice each time,
-        // but this works for now.
-        // println!("{:?}", inode_table);
-        &inode_table[index]
-    }
-
-    // A helper function for `read_dir_inode` to read  direct pointers and return the data as a Vec<u8>
-    fn read_dir_indir_ptr(&self, block_num: usize) -> std::io::Result<Vec<(usize, &NulStr)>> {
-        // indirect pointer points to a block full of direct block numbers/addresses
-        // block addresses/numbers stored in the block are all 32-bit
-        let indir_block = self.blocks[block_num];
-        // this pointer points to the head of the indirect block
-        let entry_ptr = indir_block.as_ptr();
-        // byte_offset is the offset in bytes from the head of the indirect block, like the index of an array
-        let mut byte_offset: isize = 0;
-        let mut ret = Vec::new();
-        while byte_offset < self.block_size as isize {
-            // get direct block number from indirect ptr one at a time
-            let directory = unsafe { &*(entry_ptr.offset(byte_offset) as *const DirectoryEntry) };
-            // if the inode number is 0, then the entry is empty
-            if directory.inode == 0 {
-                // println!("inode num: {}", directory.inode_num);
-                // println!("name: {}", directory.name);
-                return Ok(ret);
-            }
-            ret.push((directory.inode as usize, &directory.name));
-            // move the byte_offset to the next entry
-            byte_offset += directory.entry_size as isize;
-        }
-        Ok(ret)
-    }
-
-    // A helper function for `read_dir_inode` read the doubly indirect pointer and return the data as a Vec<u8>
-    fn read_dir_doubly_ptr(&self, block_num: usize) -> std::io::Result<Vec<(usize, &NulStr)>> {
-        // stores a bunch of singly indirect pointer block numbers
-        let doub_block = self.blocks[block_num];
-        let entry_ptr = doub_block.as_ptr();
-        let mut byte_offset: isize = 0;
-        let mut ret = Vec::new();
-        while byte_offset < self.block_size as isize {
-            let directory = unsafe { &*(entry_ptr.offset(byte_offset) as *const DirectoryEntry) };
-            if directory.inode == 0 {
-                return Ok(ret);
-            }
-            let data_from_indir = &(self.read_dir_indir_ptr(directory.inode as usize))
-                .expect("error reading indirect pointer");
-            ret.extend_from_slice(data_from_indir);
-            byte_offset += directory.entry_size as isize;
-        }
-        Ok(ret)
-    }
-
-    // A helper function for `read_file_inode` read the triply indirect pointer and return the data as a Vec<u8>
-    fn read_dir_triply_ptr(&self, block_num: usize) -> std::io::Result<Vec<(usize, &NulStr)>> {
-        let triply_indir_block = self.blocks[block_num];
-        let entry_ptr = triply_indir_block.as_ptr();
-        let mut byte_offset: isize = 0;
-        let mut ret = Vec::new();
-        while byte_offset < self.block_size as isize {
-            let directory = unsafe { &*(entry_ptr.offset(byte_offset) as *const DirectoryEntry) };
-            if directory.inode == 0 {
-                return Ok(ret);
-            }
-            let data_from_doubly = &(self.read_dir_doubly_ptr(directory.inode as usize))
-                .expect("error reading doubly indirect pointer");
-            ret.extend_from_slice(data_from_doubly);
-            byte_offset += directory.entry_size as isize;
-        }
-        Ok(ret)
-    }
-
-    // given a (1-indexed) inode number, return a list of (inode, name) pairs
-    pub fn read_dir_inode(&self, inode: usize) -> std::io::Result<Vec<(usize, &NulStr)>> {
-        let mut ret = Vec::new();
-        // root is the inode of the directory we're reading
-        let root = self.get_inode(inode);
-        // println!("in read_dir_inode, #{} : {:?}", inode, root);
-        // println!("following direct pointer to data block: {}", root.direct_pointer[0]);
-        // entry_ptr is a pointer to the first entry in the directory
-
-        // iterate over all the direct pointers
-        for direct_ptr in root.direct_pointer.iter() {
-            // <- todo, support large directories
-            // if block_num is 0, there are no more blocks -- invalid
-            let block_num = *direct_ptr;
-            if block_num == 0 {
-                return Ok(ret);
-            }
-            // get the pointer to the first entry in the directory
-            let entry_ptr = self.blocks[block_num as usize - self.block_offset].as_ptr();
-            // byte_offset is the offset from the start of the directory to the current entry
-            let mut byte_offset: isize = 0;
-            while byte_offset < self.block_size as isize {
-                // <- todo, support large directories
-                let directory =
-                    unsafe { &*(entry_ptr.offset(byte_offset) as *const DirectoryEntry) };
-                // if the directory is empty, we're done
-                if directory.inode == 0 {
-                    return Ok(ret);
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+        match key.code {
+            KeyCode::Up | KeyCode::Char('k') => dir.selected = dir.selected.saturating_sub(1),
+            KeyCode::Down | KeyCode::Char('j') => {
+                dir.selected = (dir.selected + 1).min(dir.entries.len().saturating_sub(1))
+            }
+            KeyCode::Enter | KeyCode::Right | KeyCode::Char('l') => {
+                if let Some(entry) = dir.entries.get(dir.selected).filter(|entry| entry.is_dir) {
+                    let name = String::from_utf8_lossy(&entry.name);
+                    let path = match dir.path.trim_end_matches('/') {
+                        "." => name.into_owned(),
+                        parent => format!("{}/{}", parent, name),
+                    };
+                    let inode = entry.inode;
+                    stack.push(BrowseDir::read(shell, inode, path)?);
                 }
-                // println!("{:?}", directory);
-                byte_offset += directory.entry_size as isize;
-                ret.push((directory.inode as usize, &directory.name));
-            }
-        }
-
-        // read indirect pointer
-        let indirect_ptr = root.indirect_pointer;
-        if indirect_ptr == 0 {
-            return Ok(ret);
-        }
-        let indir_block_num = indirect_ptr as usize - self.block_offset;
-        let data = self
-            .read_dir_indir_ptr(indir_block_num)
-            .expect("error reading indirect pointer");
-        ret.extend_from_slice(&data);
-
-        // read doubly indirect pointer
-        let doub_indir_ptr = root.doubly_indirect;
-        if doub_indir_ptr == 0 {
-            return Ok(ret);
-        }
-        let doub_block_num = doub_indir_ptr as usize - self.block_offset;
-        let data = self
-            .read_dir_doubly_ptr(doub_block_num)
-            .expect("error reading doubly indirect pointer");
-        ret.extend_from_slice(&data);
-
-        // read triply indirect pointer
-        let triply_indir_ptr = root.triply_indirect;
-        if triply_indir_ptr == 0 {
-            return Ok(ret);
-        }
-        let triply_block_num = triply_indir_ptr as usize - self.block_offset;
-        let data = self
-            .read_dir_triply_ptr(triply_block_num)
-            .expect("error reading triply indirect pointer");
-        ret.extend_from_slice(&data);
-
-        Ok(ret)
-    }
-
-    // A helper function for `read_file_inode` to read the indirect pointer and return the data as a Vec<u8>
-    fn read_file_indir_ptr(&self, block_num: usize) -> std::io::Result<Vec<u8>> {
-        // indirect pointer points to a block full of direct block numbers/addresses
-        // block addresses/numbers stored in the block are all 32-bit
-        let indir_block = self.blocks[block_num];
-        // entry_ptr points to the head of the indirect block
-        let entry_ptr = indir_block.as_ptr();
-        // byte_offset is the offset in bytes from the head of the indirect block, like the index of an array
-        let mut byte_offset: isize = 0;
-        let mut ret = Vec::new();
-        while byte_offset < self.block_size as isize {
-            // get direct block number from indirect ptr one at a time
-            let dir_block_num = unsafe { *(entry_ptr.offset(byte_offset) as *const u32) };
-            if dir_block_num == 0 {
-                return Ok(ret);
-            }
-            let data = self.blocks[dir_block_num as usize];
-            ret.extend_from_slice(data);
-            // since the block number is 32-bit, we increment by 4 bytes
-            byte_offset += 4;
-        }
-        Ok(ret)
-    }
-
-    // A helper function for `read_file_inode` read the doubly indirect pointer and return the data as a Vec<u8>
-    fn read_file_doubly_ptr(&self, block_num: usize) -> std::io::Result<Vec<u8>> {
-        // stores a bunch of singly indirect pointer block numbers
-        let doub_block = self.blocks[block_num];
-        let entry_ptr = doub_block.as_ptr();
-        let mut byte_offset: isize = 0;
-        let mut ret = Vec::new();
-        while byte_offset < self.block_size as isize {
-            let indir_block_num = unsafe { *(entry_ptr.offset(byte_offset) as *const u32) };
-            if indir_block_num == 0 {
-                return Ok(ret);
-            }
-            let data_from_indir = &(self.read_file_indir_ptr(indir_block_num as usize))
-                .expect("error reading indirect pointer");
-            ret.extend_from_slice(data_from_indir);
-            byte_offset += 4;
-        }
-        Ok(ret)
-    }
-
-    // A helper function for `read_file_inode` read the triply indirect pointer and return the data as a Vec<u8>
-    fn read_file_triply_ptr(&self, block_num: usize) -> std::io::Result<Vec<u8>> {
-        let triply_indir_block = self.blocks[block_num];
-        let entry_ptr = triply_indir_block.as_ptr();
-        let mut byte_offset: isize = 0;
-        let mut ret = Vec::new();
-        while byte_offset < self.block_size as isize {
-            let doub_indir_block_num = unsafe { *(entry_ptr.offset(byte_offset) as *const u32) };
-            if doub_indir_block_num == 0 {
-                return Ok(ret);
-            }
-            let data_from_doubly = &(self.read_file_doubly_ptr(doub_indir_block_num as usize))
-                .expect("error reading doubly indirect pointer");
-            ret.extend_from_slice(data_from_doubly);
-            byte_offset += 4;
-        }
-        Ok(ret)
-    }
-
-    // given a (1-indexed) inode number, return the contents of that file
-    pub fn read_file_inode(&self, inode: usize) -> std::io::Result<Vec<u8>> {
-        // root is the inode we want to read
-        let root = self.get_inode(inode);
-        // traverse the direct pointers and get the data
-        let mut ret = Vec::new();
-        // iterate over all the direct pointers
-        for direct_ptr in root.direct_pointer.iter() {
-            // <- todo, support large directories
-            // if block_num is 0, there are no more blocks -- invalid
-            let block_num = *direct_ptr;
-            if block_num == 0 {
-                return Ok(ret);
-            }
-            // get the data from the block
-            // direct pointers store block numbers
-            // self.blocks[block_number] gives us the data in bytes
-            let data = self.blocks[block_num as usize - self.block_offset];
-            ret.extend_from_slice(data);
-        }
-
-        // read indirect pointer
-        let indirect_ptr = root.indirect_pointer;
-        if indirect_ptr == 0 {
-            return Ok(ret);
-        }
-        let indir_block_num = indirect_ptr as usize - self.block_offset;
-        let data = self
-            .read_file_indir_ptr(indir_block_num)
-            .expect("error reading indirect pointer");
-        ret.extend_from_slice(&data);
-
-        // read doubly indirect pointer
-        let doub_indir_ptr = root.doubly_indirect;
-        if doub_indir_ptr == 0 {
-            return Ok(ret);
-        }
-        let doub_block_num = doub_indir_ptr as usize - self.block_offset;
-        let data = self
-            .read_file_doubly_ptr(doub_block_num)
-            .expect("error reading doubly indirect pointer");
-        ret.extend_from_slice(&data);
-
-        // read triply indirect pointer
-        let triply_indir_ptr = root.triply_indirect;
-        if triply_indir_ptr == 0 {
-            return Ok(ret);
-        }
-        let triply_block_num = triply_indir_ptr as usize - self.block_offset;
-        let data = self
-            .read_file_triply_ptr(triply_block_num)
-            .expect("error reading triply indirect pointer");
-        ret.extend_from_slice(&data);
-
-        Ok(ret)
-    }
-}
-
-impl fmt::Debug for Inode {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        if self.size_low == 0 && self.size_high == 0 {
-            f.debug_struct("").finish()
-        } else {
-            f.debug_struct("Inode")
-                .field("type_perm", &self.type_perm)
-                .field("size_low", &self.size_low)
-                .field("direct_pointers", &self.direct_pointer)
-                .field("indirect_pointer", &self.indirect_pointer)
-                .finish()
+            }
+            KeyCode::Backspace | KeyCode::Left | KeyCode::Char('h') => {
+                if stack.len() > 1 {
+                    stack.pop();
+                }
+            }
+            KeyCode::Char('q') | KeyCode::Esc => break,
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => break,
+            _ => {}
         }
     }
+    Ok(())
 }
 
-fn main() -> Result<()> {
-    let disk = include_bytes!("../myfsplusbeemovie.ext2");
-    let start_addr: usize = disk.as_ptr() as usize;
-    let ext2 = Ext2::new(&disk[..], start_addr);
+/// A directory `browse` has gone into: its entries by size, and which one
+/// is selected and how far down the list is scrolled.
+struct BrowseDir {
+    path: String,
+    total: u64,
+    entries: Vec<SubtreeUsage>,
+    selected: usize,
+    scroll: usize,
+}
+
+impl BrowseDir {
+    fn read(shell: &mut Shell, dir: usize, path: String) -> ext2::Result<BrowseDir> {
+        Ok(BrowseDir {
+            path,
+            total: shell.du.total(&shell.ext2, dir)?,
+            entries: shell.du.children(&shell.ext2, dir)?,
+            selected: 0,
+            scroll: 0,
+        })
+    }
 
-    let mut current_working_inode: usize = 2; // 2 is the root inode
+    /// Draw the directory over the whole screen: its path and total, as many
+    /// entries as fit with the selected one among them, and the keys.
+    fn draw(&mut self) -> io::Result<()> {
+        let (width, height) = terminal::size()?;
+        let (width, rows) = (width as usize, (height as usize).saturating_sub(2).max(1));
+        if self.selected < self.scroll {
+            self.scroll = self.selected;
+        } else if self.selected >= self.scroll + rows {
+            self.scroll = self.selected + 1 - rows;
+        }
+        let fit = |line: String| line.chars().take(width).collect::<String>();
 
-    let mut rl = DefaultEditor::new()?;
-    loop {
-        // fetch the children of the current working directory
-        let dirs = match ext2.read_dir_inode(current_working_inode) {
-            Ok(dir_listing) => {
-                dir_listing // the result is a vector of (inode, name) tuples
+        let mut out = io::stdout();
+        queue!(out, terminal::Clear(ClearType::All), cursor::MoveTo(0, 0))?;
+        let header = format!(
+            "{}  {} total",
+            Escaped(self.path.as_bytes()),
+            ext2::human_size(self.total)
+        );
+        queue!(out, style::PrintStyledContent(fit(header).bold()))?;
+        if self.entries.is_empty() {
+            queue!(out, cursor::MoveTo(0, 1), style::Print("(empty)"))?;
+        }
+        let biggest = self.entries.first().map_or(0, |entry| entry.bytes);
+        for (row, entry) in self.entries.iter().enumerate().skip(self.scroll).take(rows) {
+            // a bar of ten, as a share of the biggest
+            let bar = if biggest == 0 {
+                0
+            } else {
+                (entry.bytes * 10 / biggest) as usize
+            };
+            let line = fit(format!(
+                "{:>6} [{:<10}] {}",
+                ext2::human_size(entry.bytes),
+                "#".repeat(bar),
+                browse_name(entry)
+            ));
+            queue!(out, cursor::MoveTo(0, (row - self.scroll + 1) as u16))?;
+            if row == self.selected {
+                queue!(out, style::PrintStyledContent(line.reverse()))?;
+            } else {
+                queue!(out, style::Print(line))?;
             }
-            Err(_) => {
-                println!("unable to read cwd");
-                break;
+        }
+        let keys = "up/down move  enter open  backspace back  q quit";
+        queue!(
+            out,
+            cursor::MoveTo(0, height.saturating_sub(1)),
+            style::Print(fit(keys.to_string()))
+        )?;
+        out.flush()
+    }
+}
+
+/// An entry's name as `browse` shows it, with a `/` after a directory's.
+fn browse_name(entry: &SubtreeUsage) -> String {
+    let slash = if entry.is_dir { "/" } else { "" };
+    format!("{}{}", Escaped(&entry.name), slash)
+}
+
+/// The terminal in raw mode on the alternate screen, for as long as this
+/// lives: it's put back however `browse` ends, errors included.
+struct FullScreen;
+
+impl FullScreen {
+    fn enter() -> io::Result<FullScreen> {
+        terminal::enable_raw_mode()?;
+        execute!(io::stdout(), terminal::EnterAlternateScreen, cursor::Hide)?;
+        Ok(FullScreen)
+    }
+}
+
+impl Drop for FullScreen {
+    fn drop(&mut self) {
+        let _ = execute!(io::stdout(), cursor::Show, terminal::LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+/// The arguments of `biggest` and `recent`, `[-a] [N] [path]`: the directory
+/// to search and the prefix for the paths found, as from `search_root`, how
+/// many files to list, and how to walk.
+fn top_args(
+    shell: &mut Shell,
+    args: &[&str],
+) -> std::result::Result<(usize, String, usize, WalkOptions), CommandError> {
+    let (all, args) = match args {
+        ["-a", rest @ ..] => (true, rest),
+        _ => (false, args),
+    };
+    let (n, args) = match args {
+        [n, rest @ ..] if n.parse::<usize>().is_ok() => (n.parse().unwrap(), rest),
+        _ => (10, args),
+    };
+    let (root, prefix) = search_root(shell, args)?;
+    let options = WalkOptions::new()
+        .skip_lost_and_found(!all)
+        .cancel_on(&INTERRUPTED);
+    Ok((root, prefix, n, options))
+}
+
+fn cmd_dedup_report(shell: &mut Shell, args: &[&str]) -> CommandResult {
+    let (root, prefix) = search_root(shell, args)?;
+    let groups = shell
+        .ext2
+        .find_duplicates_with_progress(root, &TerminalProgress::new("files hashed"))?;
+    if groups.is_empty() {
+        println!("no duplicate files found");
+        return Ok(());
+    }
+    for group in &groups {
+        println!(
+            "{} copies of {} bytes, {} wasted (sha256 {}):",
+            group.files.len(),
+            group.size,
+            group.wasted(),
+            &group.hash[..16]
+        );
+        for file in &group.files {
+            let paths: Vec<String> = file
+                .paths
+                .iter()
+                .map(|path| format!("{}{}", prefix, Escaped(path.as_bytes())))
+                .collect();
+            let links = if paths.len() > 1 { " (hard links)" } else { "" };
+            println!("  {}{}", paths.join(", "), links);
+        }
+    }
+    let wasted: u64 = groups.iter().map(|group| group.wasted()).sum();
+    println!(
+        "{} group(s), {} bytes ({}) wasted",
+        groups.len(),
+        wasted,
+        ext2::human_size(wasted)
+    );
+    Ok(())
+}
+
+fn cmd_dedup(shell: &mut Shell, args: &[&str]) -> CommandResult {
+    let ["--link", args @ ..] = args else {
+        return Err(CommandError::Usage);
+    };
+    // rather than hashing everything first, or saying nothing when there
+    // turns out to be nothing to link
+    if shell.ext2.is_read_only() {
+        return Err(Ext2Error::ReadOnly.into());
+    }
+    let (root, _) = search_root(shell, args)?;
+    let free = shell.ext2.superblock.free_blocks_count as u64;
+    let mut linked = 0;
+    let groups = shell
+        .ext2
+        .find_duplicates_with_progress(root, &TerminalProgress::new("files hashed"))?;
+    for group in groups {
+        linked += shell.ext2.link_duplicates(root, &group)?;
+    }
+    let freed = (shell.ext2.superblock.free_blocks_count as u64).saturating_sub(free);
+    println!(
+        "linked {} file(s), freeing {} blocks ({})",
+        linked,
+        freed,
+        ext2::human_size(freed * shell.ext2.block_size as u64)
+    );
+    Ok(())
+}
+
+/// The directory `dedup`, `biggest` and the like search, from their optional
+/// path argument, and what to put in front of the paths found in it to show
+/// them.
+fn search_root(
+    shell: &mut Shell,
+    args: &[&str],
+) -> std::result::Result<(usize, String), CommandError> {
+    let path = match args {
+        [] => ".",
+        [path] => *path,
+        _ => return Err(CommandError::Usage),
+    };
+    let root = resolve(shell, path)?;
+    if !shell.ext2.get_inode(root)?.is_dir() {
+        return Err(Ext2Error::NotADirectory {
+            name: path.to_string(),
+        }
+        .into());
+    }
+    let prefix = match path.trim_end_matches('/') {
+        "." => String::new(),
+        trimmed => format!("{}/", trimmed),
+    };
+    Ok((root, prefix))
+}
+
+fn cmd_image_diff(shell: &mut Shell, args: &[&str]) -> CommandResult {
+    let mut json = shell.config.json;
+    let mut skip_lost_and_found = false;
+    let mut image = None;
+    for arg in args {
+        match *arg {
+            "--json" => json = true,
+            "--skip-lost+found" => skip_lost_and_found = true,
+            _ if !arg.starts_with('-') && image.is_none() => image = Some(*arg),
+            _ => return Err(CommandError::Usage),
+        }
+    }
+    let Some(image) = image else {
+        return Err(CommandError::Usage);
+    };
+    let disk = match fs::read(image) {
+        Ok(disk) => disk,
+        Err(err) => {
+            println!("image-diff: {}: {}", image, err);
+            return Ok(());
+        }
+    };
+    let other = match Ext2Options::new().read_only(true).open(disk) {
+        Ok(other) => other,
+        Err(err) => {
+            println!("image-diff: {}: {}", image, err);
+            return Ok(());
+        }
+    };
+    let mut diff = shell.ext2.diff_images(&other)?;
+    if skip_lost_and_found {
+        diff.only_in_a.retain(|path| !in_lost_and_found(path));
+        diff.only_in_b.retain(|path| !in_lost_and_found(path));
+        diff.metadata
+            .retain(|difference| !in_lost_and_found(&difference.path));
+        diff.contents
+            .retain(|difference| !in_lost_and_found(&difference.path));
+    }
+    if json {
+        print_json(&diff);
+        return Ok(());
+    }
+    if let Some(note) = &diff.note {
+        println!("note: {}", note);
+    }
+    let mut blocks = diff.blocks.iter().peekable();
+    while let Some(&first) = blocks.next() {
+        let mut last = first;
+        while blocks.next_if(|&&next| next == last + 1).is_some() {
+            last += 1;
+        }
+        if first == last {
+            println!("block {}", first);
+        } else {
+            println!("blocks {}-{}", first, last);
+        }
+    }
+    for path in &diff.only_in_a {
+        println!("only-a {}", Escaped(path.as_bytes()));
+    }
+    for path in &diff.only_in_b {
+        println!("only-b {}", Escaped(path.as_bytes()));
+    }
+    for difference in &diff.metadata {
+        println!(
+            "metadata {} {} {} {}",
+            difference.inode_a,
+            difference.inode_b,
+            difference.fields.join(","),
+            Escaped(difference.path.as_bytes())
+        );
+    }
+    for difference in &diff.contents {
+        println!(
+            "content {} {} {}",
+            difference.hash_a,
+            difference.hash_b,
+            Escaped(difference.path.as_bytes())
+        );
+    }
+    Ok(())
+}
+
+fn cmd_link(_shell: &mut Shell, _args: &[&str]) -> CommandResult {
+    // `link arg_1 arg_2`
+    // create a hard link from arg_1 to arg_2
+    // consider what to do if arg2 does- or does-not end in "/"
+    // and/or if arg2 is an existing directory name
+    println!("link not yet implemented");
+    Ok(())
+}
+
+fn cmd_set(shell: &mut Shell, args: &[&str]) -> CommandResult {
+    match args {
+        [] => {
+            let defaults = ShellConfig::default();
+            for key in ShellConfig::KEYS {
+                let value = shell.config.get(key).unwrap_or_default();
+                let default = if defaults.get(key) == Some(value) {
+                    " (default)"
+                } else {
+                    ""
+                };
+                println!("{:<8} {}{}", key, value, default);
             }
+            Ok(())
+        }
+        [key, value] => change_config(shell, |config| config.set(key, value)),
+        _ => Err(CommandError::Usage),
+    }
+}
+
+fn cmd_unset(shell: &mut Shell, args: &[&str]) -> CommandResult {
+    let [key] = args else {
+        return Err(CommandError::Usage);
+    };
+    change_config(shell, |config| config.reset(key))
+}
+
+/// Change the settings with `change` and have the filesystem follow them.
+/// If it can't, e.g. lenient reads on an image open for writing, they're
+/// left as they were.
+fn change_config(
+    shell: &mut Shell,
+    change: impl FnOnce(&mut ShellConfig) -> std::result::Result<(), String>,
+) -> CommandResult {
+    let saved = shell.config.clone();
+    change(&mut shell.config).map_err(CommandError::Invalid)?;
+    if let Err(err) = apply_config(shell) {
+        shell.config = saved;
+        apply_config(shell)?;
+        let reason = match err {
+            Ext2Error::BadOptions { reason } => reason,
+            err => err.to_string(),
         };
+        return Err(CommandError::Invalid(reason));
+    }
+    Ok(())
+}
 
-        let buffer = rl.readline(":> ");
-        if let Ok(line) = buffer {
-            if line.starts_with("ls") {
-                // `ls` prints our cwd's children
-                // TODO: support arguments to ls (print that directory's children instead)
-                for dir in &dirs {
-                    print!("{}\t", dir.1); //dir.1 is the name of the directory
-                }
-                println!();
-            } else if line.starts_with("cd") {
-                // `cd` with no arguments, cd goes back to root
-                // `cd dir_name` moves cwd to that directory
-                let elts: Vec<&str> = line.split(' ').collect();
-                if elts.len() == 1 {
-                    // go back to root
-                    current_working_inode = 2;
-                } else {
-                    // TODO: if the argument is a path, follow the path
-                    // e.g., cd dir_1/dir_2 should move you down 2 directories
-                    // deeper into dir_2
-                    let to_dir = elts[1];
-                    let mut found = false;
-                    for dir in &dirs {
-                        if dir.1.to_string().eq(to_dir) {
-                            // TODO: maybe don't just assume this is a directory
-                            // if the inode is not a dir, print an error
-                            if (ext2.get_inode(dir.0).type_perm & structs::TypePerm::DIRECTORY)
-                                == structs::TypePerm::DIRECTORY
-                            {
-                                found = true;
-                                current_working_inode = dir.0;
-                            } else {
-                                found = true;
-                                println!("cd: not a directory: {}", dir.1);
-                            }
-                        }
-                    }
-                    if !found {
-                        println!("unable to locate {}, cwd unchanged", to_dir);
-                    }
-                }
-            } else if line.starts_with("mkdir") {
-                // `mkdir childname`
-                // consider supporting `-p path/to_file` to create a path of directories
-                let elts: Vec<&str> = line.split(' ').collect();
-                // check valid argument
-                if elts.len() != 2 {
-                    println!("usage: mkdir dirname");
-                    continue;
-                }
-                let dirname = elts[1];
-                // check directory name unique in cwd
-                for dir in &dirs {
-                    // dir.0 is inode number
-                    // dir.1 is the name of the directory
-                    // 
-                    if dir.1.to_string() == dirname
-                        && (ext2.get_inode(dir.0).type_perm & structs::TypePerm::DIRECTORY)
-                            == structs::TypePerm::DIRECTORY
-                    {
-                        println!("directory name already exists in cwd");
-                        continue;
-                    }
-                }
-                // check if at least one unallocated inode in the whole filesystem
-                if ext2.superblock.free_inodes_count < 1 {
-                    println!("no unallocated inodes available");
-                    continue;
+/// Make the filesystem follow the settings that are about it.
+fn apply_config(shell: &mut Shell) -> ext2::Result<()> {
+    let config = &shell.config;
+    // changing either starts the caches of lookups again, so only if it changed
+    if shell.ext2.case_insensitive_lookup() != config.icase {
+        shell.ext2.set_case_insensitive_lookup(config.icase);
+    }
+    let strictness = if config.lenient {
+        Strictness::Lenient
+    } else {
+        Strictness::Strict
+    };
+    if shell.ext2.strictness() != strictness {
+        shell.ext2.set_strictness(strictness)?;
+    }
+    shell.ext2.set_noatime(config.noatime);
+    Ok(())
+}
+
+fn cmd_alias(shell: &mut Shell, args: &[&str]) -> CommandResult {
+    let show = |name: &str, text: &str| println!("alias {}={}", name, ext2::quote_word(text));
+    if args.is_empty() {
+        for (name, text) in &shell.aliases {
+            show(name, text);
+        }
+        return Ok(());
+    }
+    for arg in args {
+        match arg.split_once('=') {
+            Some((name, text)) => {
+                if ext2::ALIAS_BUILTINS.contains(&name) {
+                    return Err(CommandError::Invalid(format!(
+                        "{} can't be an alias, it's needed to manage them",
+                        name
+                    )));
                 }
-                // find the first block group with an unallocated inode
-                // block_groups is an array of BlockGroupDescriptors
-                let mut group_idx = 0;
-                for i in 0..ext2.block_groups.len() {
-                    if ext2.block_groups[i].free_inodes_count > 0 {
-                        group_idx = i;
-                        break;
-                    }
+                if !ext2::valid_alias_name(name) {
+                    return Err(CommandError::Invalid(format!(
+                        "'{}' can't be an alias name",
+                        name
+                    )));
                 }
-                let mut block_group = &mut ext2.block_groups[group_idx];
-
-                // find the first unallocated inode in that block group by using the inode usage bitmap of the block group
-                // inode_usage_addr is the block address of inode usage bitmap
-
-                let inode_usage_bitmap = ext2.blocks[block_group.inode_usage_addr as usize];
-                println!("inode_usage_bitmap: {:?}", inode_usage_bitmap); // this line prints out the bitmap for debugging purposes
-                println!("inode_usage_bitmap length: {:?}", inode_usage_bitmap.len()); // this line prints out the bitmap length for debugging purposes
-
-                // Read bitmap, figure out the first unallocated inode
-                // Each byte represents the allocation status of 8 inodes
-                // For each byte, use bitwise operations to check allocation status of inode bit
-                // if bit is 0 --> inode is unallocated
-                // if bit is 1 --> the inode is allocated
-                // should read the bitmap from back to front
-                
-                let mut first_unallocated_inode;
-                // read bitmap from the back
-                // we have 2 block groups, each with an inode usage bitmap
-                // each inode usage bitmap has a length of 1024 which can represent 1024*8 inodes
-                // 
-                // we only have 2560 inodes, so space is wasted
-                // 2560/8 = only 320 bytes needed to represent all inodes in filesystem
-                for i in (0..inode_usage_bitmap.len()).rev() {
-                    // inode is 1-indexed
-                    const MASK: u8 = 1;
-                    let len = inode_usage_bitmap.len();
-                    for bit in 1..9 {
-                        // check if inode is unallocated
-                        if (inode_usage_bitmap[i] & (MASK << (bit - 1))) == 0 {
-                            println!("{}", inode_usage_bitmap[i]);
-                            println!("{}", MASK << (bit - 1));
-                            // inode is unallocated
-                            // inode number is 1-indexed
-                            first_unallocated_inode = ((len - i) * 8) + bit;
-                            break;
-                        }
-                    }
+                shell.aliases.insert(name.to_string(), text.to_string());
+            }
+            None => match shell.aliases.get(*arg) {
+                Some(text) => show(arg, text),
+                None => return Err(CommandError::Invalid(format!("no alias '{}'", arg))),
+            },
+        }
+    }
+    Ok(())
+}
+
+fn cmd_unalias(shell: &mut Shell, args: &[&str]) -> CommandResult {
+    match args {
+        [] => return Err(CommandError::Usage),
+        ["-a"] => shell.aliases.clear(),
+        names => {
+            for name in names {
+                if shell.aliases.remove(*name).is_none() {
+                    return Err(CommandError::Invalid(format!("no alias '{}'", name)));
                 }
+            }
+        }
+    }
+    Ok(())
+}
 
-                // Create DirectoryEntry
-                // let mut new_dir = structs::DirectoryEntry {
-                //     inode: first_unallocated_inode as u32,
-                //     entry_size: 123,
-                //     name_length: dirname.len() as u8,
-                //     type_indicator: structs::TypeIndicator::Directory,
-                //     name: NulStr::from(dirname).unwrap(),
-                // };
-                
-
-                // Update block group information
-                block_group.free_inodes_count -= 1;
-                block_group.dirs_count += 1;
-
-                // allocate an inode
-                // create a directory with the given name, add a link to cwd
-                    // current_working_inode
-
-            } else if line.starts_with("cat") {
-                // `cat filename`
-                // print the contents of filename to stdout
-                // if it's a directory, print a nice error
-                // get the arguments
-                let elts: Vec<&str> = line.split(' ').collect();
-                if elts.len() != 2 {
-                    println!("usage: cat filename");
-                    continue;
+fn cmd_quit(shell: &mut Shell, _args: &[&str]) -> CommandResult {
+    shell.done = true;
+    Ok(())
+}
+
+/// Report that there's no partition `number` among `partitions`, and exit.
+fn no_such_partition(number: usize, partitions: &[Partition]) -> ! {
+    if partitions.is_empty() {
+        eprintln!(
+            "there's no partition table in the image, so no partition {}",
+            number
+        );
+    } else {
+        eprintln!("there's no partition {}; the image has:", number);
+        for partition in partitions {
+            eprintln!(
+                "  {}: {}, {} bytes at {}",
+                partition.number, partition.kind, partition.len, partition.offset
+            );
+        }
+    }
+    std::process::exit(1);
+}
+
+/// Run the commands in the rc file at `path`, one to a line, as if they were
+/// typed at the prompt, aliases included; blank lines and lines starting
+/// with # are skipped.
+/// A command that fails is reported with its line, and the rest still run.
+/// A missing file is only worth mentioning if it was asked for by name.
+fn run_rcfile(shell: &mut Shell, path: &Path, named: bool) {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(err) if err.kind() == io::ErrorKind::NotFound && !named => return,
+        Err(err) => {
+            eprintln!("{}: {}", path.display(), err);
+            return;
+        }
+    };
+    for (number, line) in text.lines().enumerate() {
+        if line.trim_start().starts_with('#') {
+            continue;
+        }
+        if let Err((_, message)) = run_line(shell, line) {
+            eprintln!("{}:{}: {}", path.display(), number + 1, message);
+        }
+        if shell.done {
+            return;
+        }
+    }
+}
+
+/// Run the command on `line`, as typed: its alias expanded if the first
+/// word is one, then split into words with `split_words`. Fails like
+/// `run_command`, or with status 2 if a quote isn't closed; a blank line
+/// does nothing.
+fn run_line(shell: &mut Shell, line: &str) -> std::result::Result<(), (i32, String)> {
+    let line = ext2::expand_alias(line, &shell.aliases).into_owned();
+    let words = ext2::split_words(&line).map_err(|err| (2, err.to_string()))?;
+    let Some((name, args)) = words.split_first() else {
+        return Ok(());
+    };
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    run_command(shell, name, &args)
+}
+
+/// Run the command `name` with `args`. If it fails, returns what to say
+/// about it and the exit status that means: 2 for an unknown command or bad
+/// arguments, 130 if ctrl-C stopped it, 1 for anything else.
+fn run_command(
+    shell: &mut Shell,
+    name: &str,
+    args: &[&str],
+) -> std::result::Result<(), (i32, String)> {
+    let Some(cmd) = find_command(name) else {
+        return Err((2, format!("unknown command: {} (try 'help')", name)));
+    };
+    // ctrl-C stops the command rather than the shell
+    let interrupt = Interrupt::catch();
+    let result = (cmd.run)(shell, args);
+    drop(interrupt);
+    // on stderr, like the warnings when the image is opened
+    for warning in shell.ext2.take_warnings() {
+        eprintln!("warning: {}", warning);
+    }
+    match result {
+        Ok(()) => Ok(()),
+        // whoever reads the output went away, so there's no one left to print
+        // anything to: quit, as SIGPIPE would have, instead of panicking on
+        // the next `println!`
+        Err(CommandError::Fs(Ext2Error::Io(err))) if err.kind() == io::ErrorKind::BrokenPipe => {
+            shell.done = true;
+            Ok(())
+        }
+        Err(CommandError::Usage) => Err((2, format!("usage: {}", cmd.usage))),
+        Err(CommandError::Invalid(reason)) => Err((2, format!("{}: {}", cmd.name, reason))),
+        Err(CommandError::Fs(Ext2Error::ReadOnly)) => {
+            Err((1, format!("{}: the filesystem is open read-only", cmd.name)))
+        }
+        // whatever failed once ctrl-C was pressed failed because of it, e.g.
+        // writing to `Output`
+        Err(CommandError::Fs(err))
+            if matches!(err, Ext2Error::Cancelled) || INTERRUPTED.load(Ordering::Relaxed) =>
+        {
+            Err((130, format!("{}: interrupted", cmd.name)))
+        }
+        Err(CommandError::Fs(err)) => Err((1, format!("error: {}", err))),
+    }
+}
+
+// Mount the image at `dir` and serve it until interrupted, then unmount.
+#[cfg(feature = "fuse")]
+fn serve_fuse(ext2: Ext2, dir: &str) -> ! {
+    // blocked before the session's thread starts so it inherits the mask,
+    // leaving this thread the only one to take them, in sigwait
+    let mut signals = unsafe { std::mem::zeroed::<libc::sigset_t>() };
+    unsafe {
+        libc::sigemptyset(&mut signals);
+        libc::sigaddset(&mut signals, libc::SIGINT);
+        libc::sigaddset(&mut signals, libc::SIGTERM);
+        libc::pthread_sigmask(libc::SIG_BLOCK, &signals, std::ptr::null_mut());
+    }
+    let mount = match ext2.mount_fuse(std::path::Path::new(dir)) {
+        Ok(mount) => mount,
+        Err(err) => {
+            eprintln!("{}: {}", dir, err);
+            std::process::exit(1);
+        }
+    };
+    eprintln!("mounted read-only on {}, interrupt to unmount", dir);
+    let mut signal = 0;
+    unsafe {
+        libc::sigwait(&signals, &mut signal);
+    }
+    drop(mount);
+    eprintln!("unmounted {}", dir);
+    std::process::exit(0);
+}
+
+#[cfg(not(feature = "fuse"))]
+fn serve_fuse(_ext2: Ext2, _dir: &str) -> ! {
+    eprintln!("--fuse: this ext2 was built without FUSE support, rebuild with --features fuse");
+    std::process::exit(2);
+}
+
+// how many blocks reading a file through fetches from the image at once
+// unless `--readahead` says otherwise: 128 KiB of 4 KiB blocks, as much as
+// Linux reads ahead by default
+const READAHEAD_BLOCKS: usize = 32;
+
+fn main() -> Result<()> {
+    // silent by default; RUST_LOG=debug shows what the library is doing
+    env_logger::init();
+
+    // `ext2 [--read-only] [--noatime] image [command [arg...]]`: open the
+    // given image file, and run the one command given
+    // after it, or the shell if there's none. `--fuse dir` in place of the
+    // command mounts the image on the host instead. `--lenient` reads what it
+    // can of a damaged image, with warnings, rather than stopping at errors,
+    // and so opens it read-only. The shell runs the commands in
+    // `~/.ext2shrc`, or the file given with `--rcfile`, before its first prompt.
+    // Reading a file through reads `--readahead` blocks at a time from the
+    // image where they follow on, 0 or 1 for one at a time
+    const USAGE: &str = "usage: ext2 [--read-only] [--noatime] [--lenient] [--partition N] \
+         [--readahead N] [--rcfile file] image [command [arg...] | --fuse dir]";
+    let mut options = Ext2Options::new().readahead_blocks(READAHEAD_BLOCKS);
+    let mut image = None;
+    let mut partition_number = None;
+    let mut rcfile = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--read-only" => options = options.read_only(true),
+            "--noatime" => options = options.noatime(true),
+            "--lenient" => {
+                options = options.strictness(Strictness::Lenient).read_only(true);
+            }
+            "--rcfile" => match args.next() {
+                Some(path) => rcfile = Some(path),
+                None => {
+                    eprintln!("{}", USAGE);
+                    std::process::exit(2);
                 }
-                let filename = elts[1];
-                // check if the file exists
-                let mut found = false;
-                for dir in &dirs {
-                    // if the file exists, print it
-                    if dir.1.to_string().eq(filename) {
-                        found = !found;
-                        let inode = ext2.get_inode(dir.0);
-                        // if the inode is a directory, print an error
-                        if (inode.type_perm & structs::TypePerm::DIRECTORY)
-                            == structs::TypePerm::DIRECTORY
-                        {
-                            println!("cat: {}: Is a directory", filename);
-                        } else {
-                            // print the contents of the file
-                            let content = ext2.read_file_inode(dir.0);
-                            match content {
-                                Ok(content) => {
-                                    io::stdout().write_all(&content).unwrap();
-                                }
-                                Err(_) => {
-                                    println!("cat: {}: No such file or directory", filename);
-                                }
-                            }
-                        }
-                    }
+            },
+            "--partition" => match args.next().and_then(|n| n.parse::<usize>().ok()) {
+                Some(n) => partition_number = Some(n),
+                None => {
+                    eprintln!("{}", USAGE);
+                    std::process::exit(2);
                 }
-                // if not found, print an error
-                if !found {
-                    println!("cat: {}: No such file or directory", filename);
+            },
+            "--readahead" => match args.next().and_then(|n| n.parse::<usize>().ok()) {
+                Some(n) => options = options.readahead_blocks(n),
+                None => {
+                    eprintln!("{}", USAGE);
+                    std::process::exit(2);
                 }
-            } else if line.starts_with("rm") {
-                // `rm target`
-                // unlink a file or empty directory
-                println!("rm not yet implemented");
-            } else if line.starts_with("mount") {
-                // `mount host_filename mountpoint`
-                // mount an ext2 filesystem over an existing empty directory
-                println!("mount not yet implemented");
-            } else if line.starts_with("link") {
-                // `link arg_1 arg_2`
-                // create a hard link from arg_1 to arg_2
-                // consider what to do if arg2 does- or does-not end in "/"
-                // and/or if arg2 is an existing directory name
-                println!("link not yet implemented");
-            } else if line.starts_with("quit") || line.starts_with("exit") {
+            },
+            _ if arg.starts_with('-') => {
+                eprintln!("{}", USAGE);
+                std::process::exit(2);
+            }
+            _ => {
+                image = Some(arg);
                 break;
             }
-        } else {
-            println!("bye!");
-            break;
+        }
+    }
+    let Some(image) = image else {
+        eprintln!("{}", USAGE);
+        std::process::exit(2);
+    };
+    let command: Vec<String> = args.collect();
+    let fuse_mountpoint = match command.as_slice() {
+        [flag, dir] if flag == "--fuse" => {
+            options = options.read_only(true);
+            Some(dir.clone())
+        }
+        [flag, ..] if flag == "--fuse" => {
+            eprintln!("{}", USAGE);
+            std::process::exit(2);
+        }
+        _ => None,
+    };
+    // the image is read a block at a time as it's needed, rather than all
+    // at once, so a big one opens as quickly as a small one
+    let device = match FileDevice::open(&image) {
+        Ok(device) => device,
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+    };
+    let partitions = match ext2::device_partitions(&device) {
+        Ok(partitions) => partitions,
+        Err(err) => {
+            eprintln!("{}: {}", image, err);
+            std::process::exit(1);
+        }
+    };
+    // whether the primary superblock of the filesystem `offset` bytes into
+    // the image has the magic number
+    let primary_ok = |offset: u64| {
+        let mut head = [0; 2048];
+        device.read_blocks(offset, 1, &mut head).is_ok() && ext2::primary_superblock_ok(&head)
+    };
+    // a disk image rather than a bare filesystem: open its first Linux
+    // partition unless another was asked for
+    if partition_number.is_none() && !primary_ok(0) {
+        if let Some(partition) = partitions.iter().find(|p| p.is_linux()) {
+            eprintln!(
+                "opening partition {} ({}), {} bytes in",
+                partition.number, partition.kind, partition.offset
+            );
+            partition_number = Some(partition.number);
+        }
+    }
+    let partition =
+        partition_number.map(
+            |number| match partitions.iter().find(|p| p.number == number) {
+                Some(partition) => partition.clone(),
+                None => no_such_partition(number, &partitions),
+            },
+        );
+    if let Some(partition) = &partition {
+        options = options.partition(partition.number);
+    }
+    let ext2 = if primary_ok(partition.as_ref().map_or(0, |p| p.offset)) {
+        options.open_path(&image)
+    } else {
+        // looking for a backup superblock takes the whole image
+        let disk = match fs::read(&image) {
+            Ok(disk) => disk,
+            Err(err) => {
+                eprintln!("{}: {}", image, err);
+                std::process::exit(1);
+            }
+        };
+        let filesystem = partition.as_ref().map_or(&disk[..], |p| p.slice(&disk));
+        let Some((group, offset)) = ext2::find_backup_superblock(filesystem) else {
+            eprintln!("no valid superblock found, this doesn't look like an ext2 filesystem");
+            std::process::exit(1);
+        };
+        if !confirm(&format!(
+            "the primary superblock is damaged; open using the backup in group {}?",
+            group
+        )) {
+            std::process::exit(1);
+        }
+        eprintln!(
+            "backup counts are often stale, 'fsck --repair' then 'sync' rewrites the primary"
+        );
+        options.open_backup(disk, offset)
+    };
+    let ext2 = match ext2 {
+        Ok(ext2) => ext2,
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+    };
+    // on stderr, so they don't end up in the output of a single command
+    if let Some(reason) = ext2.forced_read_only() {
+        eprintln!("warning: opened read-only: {}", reason);
+    }
+    if let Some(advisory) = ext2.check_advisory() {
+        eprintln!("warning: {}", advisory);
+    }
+    if let Some(journal) = ext2.journal().filter(|journal| !journal.is_clean()) {
+        eprintln!(
+            "WARNING: the journal {}: changes in it haven't reached the filesystem, so what's \
+             shown may be out of date or inconsistent; mount it or run e2fsck to replay them",
+            journal.state()
+        );
+    }
+
+    if let Some(dir) = fuse_mountpoint {
+        serve_fuse(ext2, &dir);
+    }
+
+    // what was asked for on the command line, so `set` shows it
+    let config = ShellConfig {
+        noatime: ext2.noatime(),
+        lenient: ext2.strictness() == Strictness::Lenient,
+        ..ShellConfig::default()
+    };
+    let mut shell = Shell {
+        ext2,
+        cwd: 2, // 2 is the root inode
+        cwd_index: None,
+        paths: PathCache::new(),
+        done: false,
+        image,
+        partition,
+        mounts: Vec::new(),
+        snapshot: None,
+        previous_dir: None,
+        dir_stack: Vec::new(),
+        config,
+        files: BTreeMap::new(),
+        du: DuCache::new(),
+        aliases: BTreeMap::new(),
+    };
+
+    if let Some((name, args)) = command.split_first() {
+        // die quietly when whatever reads the output goes away, e.g. `| head`,
+        // like any other command line tool, rather than panicking in println!
+        unsafe {
+            libc::signal(libc::SIGPIPE, libc::SIG_DFL);
+        }
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        shell.config.pager = false;
+        if let Err((status, message)) = run_command(&mut shell, name, &args) {
+            eprintln!("{}", message);
+            std::process::exit(status);
+        }
+        return Ok(());
+    }
+
+    match rcfile {
+        Some(path) => run_rcfile(&mut shell, Path::new(&path), true),
+        None => {
+            if let Some(home) = std::env::var_os("HOME") {
+                run_rcfile(&mut shell, &Path::new(&home).join(".ext2shrc"), false);
+            }
+        }
+    }
+
+    let mut rl = DefaultEditor::new()?;
+    while !shell.done {
+        let line = match rl.readline(":> ") {
+            Ok(line) => line,
+            // ctrl-C drops the line being typed, as in bash; ctrl-D quits
+            Err(ReadlineError::Interrupted) => {
+                println!("^C");
+                continue;
+            }
+            Err(_) => {
+                println!("bye!");
+                break;
+            }
+        };
+        if let Err((_, message)) = run_line(&mut shell, &line) {
+            println!("{}", message);
         }
     }
     Ok(())
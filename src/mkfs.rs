@@ -0,0 +1,406 @@
+// Making a new filesystem, like a minimal `mke2fs -t ext2`.
+//
+// The layout is the classic one: every group starts with a copy of the
+// superblock and descriptor table if it carries one (sparse_super: groups
+// 0, 1 and powers of 3, 5 and 7), then its block bitmap, inode bitmap and
+// inode table, and the rest is data. The only files are the root directory
+// and /lost+found, in the first data blocks of group 0.
+
+use crate::clock::{Clock, SystemClock};
+use crate::structs::{
+    BlockGroupDescriptor, FeatureIncompat, FeatureRoCompat, Inode, Superblock, TypePerm,
+};
+use crate::write::write_dir_entry;
+use crate::{
+    rec_len_to_disk, Ext2Error, Result, EXT2_MAGIC, EXT2_START_OF_SUPERBLOCK, EXT2_STATE_CLEAN,
+};
+use log::info;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::mem;
+use std::sync::Arc;
+use uuid::Uuid;
+
+// the size of an inode table slot, like mke2fs's default: `Inode` and room
+// after it for extended attributes
+const INODE_SIZE: usize = 256;
+const ROOT_INODE: usize = 2;
+const LOST_AND_FOUND_INODE: usize = 11;
+// inodes below this are reserved: bad blocks, root, ACLs, boot loader, ...
+const FIRST_INODE: usize = 11;
+
+/// How to lay out a new filesystem, e.g.
+/// `mkfs(&mut image, &MkfsOptions::new().block_size(4096))`.
+#[derive(Debug, Clone)]
+pub struct MkfsOptions {
+    /// 1024, 2048 or 4096
+    pub block_size: usize,
+    /// One inode is made for every this many bytes of the device.
+    pub bytes_per_inode: usize,
+    /// The percentage of blocks only root may allocate.
+    pub reserved_percent: u32,
+    /// At most 16 bytes.
+    pub volume_name: String,
+    /// The filesystem's UUID; a random one unless given.
+    pub uuid: Option<Uuid>,
+    /// Where the creation time comes from.
+    pub clock: Arc<dyn Clock>,
+}
+
+impl Default for MkfsOptions {
+    fn default() -> MkfsOptions {
+        MkfsOptions {
+            block_size: 1024,
+            bytes_per_inode: 4096,
+            reserved_percent: 5,
+            volume_name: String::new(),
+            uuid: None,
+            clock: Arc::new(SystemClock),
+        }
+    }
+}
+
+impl MkfsOptions {
+    pub fn new() -> MkfsOptions {
+        MkfsOptions::default()
+    }
+
+    pub fn block_size(mut self, block_size: usize) -> MkfsOptions {
+        self.block_size = block_size;
+        self
+    }
+
+    pub fn bytes_per_inode(mut self, bytes_per_inode: usize) -> MkfsOptions {
+        self.bytes_per_inode = bytes_per_inode;
+        self
+    }
+
+    pub fn reserved_percent(mut self, reserved_percent: u32) -> MkfsOptions {
+        self.reserved_percent = reserved_percent;
+        self
+    }
+
+    pub fn volume_name(mut self, volume_name: &str) -> MkfsOptions {
+        self.volume_name = volume_name.to_string();
+        self
+    }
+
+    pub fn uuid(mut self, uuid: Uuid) -> MkfsOptions {
+        self.uuid = Some(uuid);
+        self
+    }
+
+    pub fn clock(mut self, clock: impl Clock + 'static) -> MkfsOptions {
+        self.clock = Arc::new(clock);
+        self
+    }
+}
+
+// where everything goes, worked out before anything is written
+#[derive(Debug)]
+struct Layout {
+    block_size: usize,
+    blocks_count: usize,
+    first_data_block: usize,
+    blocks_per_group: usize,
+    inodes_per_group: usize,
+    groups: usize,
+    descriptor_blocks: usize,
+    inode_table_blocks: usize,
+}
+
+impl Layout {
+    fn new(device_len: usize, opts: &MkfsOptions) -> Result<Layout> {
+        let bad = |reason: String| Ext2Error::BadLayout { reason };
+        let block_size = opts.block_size;
+        if ![1024, 2048, 4096].contains(&block_size) {
+            return Err(bad(format!("unsupported block size {}", block_size)));
+        }
+        if opts.bytes_per_inode < block_size {
+            return Err(bad(format!(
+                "{} bytes per inode is less than a block",
+                opts.bytes_per_inode
+            )));
+        }
+        let first_data_block = usize::from(block_size == 1024);
+        // one bitmap block covers the group
+        let blocks_per_group = 8 * block_size;
+        let mut blocks_count = (device_len / block_size).min(u32::MAX as usize);
+        loop {
+            if blocks_count <= first_data_block {
+                return Err(bad(format!("{} bytes is too small", device_len)));
+            }
+            let groups = (blocks_count - first_data_block).div_ceil(blocks_per_group);
+            let inodes_per_block = block_size / INODE_SIZE;
+            let inodes = blocks_count * block_size / opts.bytes_per_inode;
+            let inodes_per_group = inodes
+                .div_ceil(groups)
+                .max(FIRST_INODE + 5)
+                .next_multiple_of(inodes_per_block.max(8))
+                .min(8 * block_size);
+            let layout = Layout {
+                block_size,
+                blocks_count,
+                first_data_block,
+                blocks_per_group,
+                inodes_per_group,
+                groups,
+                descriptor_blocks: (groups * mem::size_of::<BlockGroupDescriptor>())
+                    .div_ceil(block_size),
+                inode_table_blocks: inodes_per_group / inodes_per_block,
+            };
+            let last = groups - 1;
+            // group 0 also needs room for the root directory and lost+found
+            let needed = layout.overhead(last)
+                + if last == 0 {
+                    1 + layout.lost_and_found_blocks()
+                } else {
+                    1
+                };
+            if layout.group_blocks(last) >= needed {
+                return Ok(layout);
+            }
+            if last == 0 {
+                return Err(bad(format!("{} bytes is too small", device_len)));
+            }
+            // like mke2fs, drop a last group too small to hold its own metadata
+            blocks_count = layout.group_first_block(last);
+        }
+    }
+
+    fn group_first_block(&self, group: usize) -> usize {
+        self.first_data_block + group * self.blocks_per_group
+    }
+
+    fn group_blocks(&self, group: usize) -> usize {
+        (self.blocks_count - self.group_first_block(group)).min(self.blocks_per_group)
+    }
+
+    // sparse_super: only groups 0, 1 and powers of 3, 5 and 7 carry copies
+    fn has_superblock(&self, group: usize) -> bool {
+        group <= 1
+            || [3, 5, 7].iter().any(|&base| {
+                let mut n = base;
+                while n < group {
+                    n *= base;
+                }
+                n == group
+            })
+    }
+
+    // the blocks at the start of `group` taken by metadata
+    fn overhead(&self, group: usize) -> usize {
+        let copies = if self.has_superblock(group) {
+            1 + self.descriptor_blocks
+        } else {
+            0
+        };
+        copies + 2 + self.inode_table_blocks
+    }
+
+    // as `Ext2::ensure_lost_and_found` makes it
+    fn lost_and_found_blocks(&self) -> usize {
+        (16 * 1024 / self.block_size).min(12)
+    }
+}
+
+/// Lay down a new, empty ext2 filesystem over the whole of `device`: the
+/// superblock and its backups, the descriptor table, bitmaps and inode
+/// tables, the root directory and /lost+found. The result opens with
+/// `Ext2::new` and passes `e2fsck -f`.
+pub fn mkfs(device: &mut [u8], opts: &MkfsOptions) -> Result<()> {
+    let layout = Layout::new(device.len(), opts)?;
+    if opts.volume_name.len() > 16 {
+        return Err(Ext2Error::BadLayout {
+            reason: format!("volume name {:?} is over 16 bytes", opts.volume_name),
+        });
+    }
+    let block_size = layout.block_size;
+    let now = opts.clock.now();
+    let block = |block_num: usize| block_num * block_size..(block_num + 1) * block_size;
+
+    // group 0's first data blocks hold the root directory and lost+found
+    let root_block = layout.group_first_block(0) + layout.overhead(0);
+    let lost_and_found: Vec<usize> =
+        (root_block + 1..root_block + 1 + layout.lost_and_found_blocks()).collect();
+
+    let mut descriptors = Vec::new();
+    let mut free_blocks = 0;
+    for group in 0..layout.groups {
+        let first = layout.group_first_block(group);
+        let copies = layout.overhead(group) - 2 - layout.inode_table_blocks;
+        let block_bitmap = first + copies;
+        let inode_bitmap = block_bitmap + 1;
+        let inode_table = inode_bitmap + 1;
+        let mut used_blocks = layout.overhead(group);
+        let mut used_inodes = 0;
+        let mut dirs = 0;
+        if group == 0 {
+            used_blocks += 1 + lost_and_found.len();
+            used_inodes = FIRST_INODE;
+            dirs = 2;
+        }
+        for block_num in block_bitmap..inode_table + layout.inode_table_blocks {
+            device[block(block_num)].fill(0);
+        }
+        // the bits past the end of the group, and of the inodes, stay set
+        let bitmap = &mut device[block(block_bitmap)];
+        set_bits(bitmap, 0..used_blocks);
+        set_bits(bitmap, layout.group_blocks(group)..8 * block_size);
+        let bitmap = &mut device[block(inode_bitmap)];
+        set_bits(bitmap, 0..used_inodes);
+        set_bits(bitmap, layout.inodes_per_group..8 * block_size);
+
+        let free = layout.group_blocks(group) - used_blocks;
+        free_blocks += free;
+        let mut descriptor: BlockGroupDescriptor = unsafe { mem::zeroed() };
+        descriptor.block_usage_addr = block_bitmap as u32;
+        descriptor.inode_usage_addr = inode_bitmap as u32;
+        descriptor.inode_table_block = inode_table as u32;
+        descriptor.free_blocks_count = free as u16;
+        descriptor.free_inodes_count = (layout.inodes_per_group - used_inodes) as u16;
+        descriptor.dirs_count = dirs;
+        descriptors.push(descriptor);
+    }
+
+    // the root directory: `.`, `..` (itself) and lost+found
+    let dir_type = 2;
+    let root = &mut device[block(root_block)];
+    root.fill(0);
+    write_dir_entry(root, ROOT_INODE, 12, ".", dir_type);
+    write_dir_entry(&mut root[12..], ROOT_INODE, 12, "..", dir_type);
+    write_dir_entry(
+        &mut root[24..],
+        LOST_AND_FOUND_INODE,
+        block_size - 24,
+        "lost+found",
+        dir_type,
+    );
+    for (i, &block_num) in lost_and_found.iter().enumerate() {
+        let dir_block = &mut device[block(block_num)];
+        dir_block.fill(0);
+        if i == 0 {
+            write_dir_entry(dir_block, LOST_AND_FOUND_INODE, 12, ".", dir_type);
+            write_dir_entry(
+                &mut dir_block[12..],
+                ROOT_INODE,
+                block_size - 12,
+                "..",
+                dir_type,
+            );
+        } else {
+            // one unused entry spanning the block
+            dir_block[4..6].copy_from_slice(&rec_len_to_disk(block_size));
+        }
+    }
+    let inode_table = descriptors[0].inode_table_block as usize;
+    let mut write_inode = |inode: usize, mode: u16, links: u16, blocks: &[usize]| {
+        let mut record: Inode = unsafe { mem::zeroed() };
+        record.type_perm = TypePerm::from_bits_truncate(mode);
+        record.hard_links = links;
+        record.size_low = (blocks.len() * block_size) as u32;
+        record.sectors_count = (blocks.len() * block_size / 512) as u32;
+        for (pointer, &block_num) in record.direct_pointer.iter_mut().zip(blocks) {
+            *pointer = block_num as u32;
+        }
+        record.atime = now;
+        record.ctime = now;
+        record.mtime = now;
+        let at = inode_table * block_size + (inode - 1) * INODE_SIZE;
+        device[at..at + mem::size_of::<Inode>()].copy_from_slice(as_bytes(&record));
+    };
+    // `..` of lost+found is the root's third link
+    write_inode(
+        ROOT_INODE,
+        TypePerm::DIRECTORY.bits() | 0o755,
+        3,
+        &[root_block],
+    );
+    write_inode(
+        LOST_AND_FOUND_INODE,
+        TypePerm::DIRECTORY.bits() | 0o700,
+        2,
+        &lost_and_found,
+    );
+
+    let mut sb: Superblock = unsafe { mem::zeroed() };
+    let inodes_count = layout.inodes_per_group * layout.groups;
+    sb.inodes_count = inodes_count as u32;
+    sb.blocks_count = layout.blocks_count as u32;
+    sb.r_blocks_count = (layout.blocks_count as u64 * opts.reserved_percent as u64 / 100) as u32;
+    sb.free_blocks_count = free_blocks as u32;
+    sb.free_inodes_count = (inodes_count - FIRST_INODE) as u32;
+    sb.first_data_block = layout.first_data_block as u32;
+    sb.log_block_size = block_size.trailing_zeros() - 10;
+    sb.log_frag_size = sb.log_block_size as i32;
+    sb.blocks_per_group = layout.blocks_per_group as u32;
+    sb.frags_per_group = layout.blocks_per_group as u32;
+    sb.inodes_per_group = layout.inodes_per_group as u32;
+    sb.wtime = now;
+    sb.max_mnt_count = -1;
+    sb.magic = EXT2_MAGIC;
+    sb.state = EXT2_STATE_CLEAN;
+    // continue on errors
+    sb.errors = 1;
+    sb.lastcheck = now;
+    sb.rev_major = 1;
+    sb.first_inode = FIRST_INODE as u32;
+    sb.inode_size = INODE_SIZE as u16;
+    sb.features_req = FeatureIncompat::FILETYPE.bits();
+    sb.features_ronly = (FeatureRoCompat::SPARSE_SUPER | FeatureRoCompat::LARGE_FILE).bits();
+    sb.fs_id = *opts
+        .uuid
+        .unwrap_or_else(|| Uuid::from_bytes(random_bytes()))
+        .as_bytes();
+    sb.volume_name[..opts.volume_name.len()].copy_from_slice(opts.volume_name.as_bytes());
+    sb.mkfs_time = now;
+
+    let descriptor_bytes: Vec<u8> = descriptors.iter().flat_map(as_bytes).copied().collect();
+    for group in (0..layout.groups).filter(|&group| layout.has_superblock(group)) {
+        sb.block_group = group as u16;
+        let first = layout.group_first_block(group);
+        // the primary sits 1024 bytes in whatever the block size, the
+        // backups at the start of their group's first block
+        let at = if group == 0 {
+            EXT2_START_OF_SUPERBLOCK
+        } else {
+            first * block_size
+        };
+        device[at..at + 1024].fill(0);
+        device[at..at + mem::size_of::<Superblock>()].copy_from_slice(as_bytes(&sb));
+        let table = block(first + 1);
+        device[table.start..table.start + layout.descriptor_blocks * block_size].fill(0);
+        device[table.start..table.start + descriptor_bytes.len()]
+            .copy_from_slice(&descriptor_bytes);
+    }
+    info!(
+        "made a filesystem: {} blocks of {} bytes, {} inodes, {} groups",
+        layout.blocks_count, block_size, inodes_count, layout.groups
+    );
+    Ok(())
+}
+
+fn set_bits(bitmap: &mut [u8], bits: std::ops::Range<usize>) {
+    for bit in bits {
+        bitmap[bit / 8] |= 1 << (bit % 8);
+    }
+}
+
+// the bytes of an on-disk structure, as they go on the device
+fn as_bytes<T>(value: &T) -> &[u8] {
+    unsafe { std::slice::from_raw_parts(value as *const T as *const u8, mem::size_of::<T>()) }
+}
+
+// random enough for a UUID, without a dependency for it: std seeds every
+// `RandomState` from the OS
+pub(crate) fn random_bytes() -> [u8; 16] {
+    let mut bytes = [0; 16];
+    for half in bytes.chunks_exact_mut(8) {
+        let value = RandomState::new().build_hasher().finish();
+        half.copy_from_slice(&value.to_le_bytes());
+    }
+    // a version 4 (random) UUID
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    bytes
+}
@@ -0,0 +1,212 @@
+// Owned copies of the on-disk structures, for dumping them field by field.
+//
+// `Superblock`, `BlockGroupDescriptor` and `Inode` are views straight into
+// the image, so they can't be deserialized into, and some of their arrays
+// are too long for serde. These mirror them field for field, the arrays
+// that are too long as vectors, and leave out only the reserved padding.
+
+use crate::structs::{BlockGroupDescriptor, Inode, Superblock};
+use serde::{Deserialize, Serialize};
+
+/// Every field of a `Superblock`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SuperblockOwned {
+    pub inodes_count: u32,
+    pub blocks_count: u32,
+    pub r_blocks_count: u32,
+    pub free_blocks_count: u32,
+    pub free_inodes_count: u32,
+    pub first_data_block: u32,
+    pub log_block_size: u32,
+    pub log_frag_size: i32,
+    pub blocks_per_group: u32,
+    pub frags_per_group: u32,
+    pub inodes_per_group: u32,
+    pub mtime: u32,
+    pub wtime: u32,
+    pub mnt_count: u16,
+    pub max_mnt_count: i16,
+    pub magic: u16,
+    pub state: u16,
+    pub errors: u16,
+    pub rev_minor: u16,
+    pub lastcheck: u32,
+    pub checkinterval: u32,
+    pub creator_os: u32,
+    pub rev_major: u32,
+    pub block_uid: u16,
+    pub block_gid: u16,
+    pub first_inode: u32,
+    pub inode_size: u16,
+    pub block_group: u16,
+    pub features_opt: u32,
+    pub features_req: u32,
+    pub features_ronly: u32,
+    pub fs_id: [u8; 16],
+    pub volume_name: [u8; 16],
+    pub last_mnt_path: Vec<u8>,
+    pub compression: u32,
+    pub prealloc_blocks_files: u8,
+    pub prealloc_blocks_dirs: u8,
+    pub reserved_gdt_blocks: u16,
+    pub journal_id: [u8; 16],
+    pub journal_inode: u32,
+    pub journal_dev: u32,
+    pub journal_orphan_head: u32,
+    pub hash_seed: [u32; 4],
+    pub def_hash_version: u8,
+    pub journal_backup_type: u8,
+    pub desc_size: u16,
+    pub default_mount_opts: u32,
+    pub first_meta_bg: u32,
+    pub mkfs_time: u32,
+    pub journal_blocks: [u32; 17],
+    pub blocks_count_high: u32,
+    pub reserved_blocks_count_high: u32,
+    pub free_blocks_count_high: u32,
+    pub min_extra_isize: u16,
+    pub want_extra_isize: u16,
+    pub misc_flags: u32,
+}
+
+impl From<&Superblock> for SuperblockOwned {
+    fn from(raw: &Superblock) -> SuperblockOwned {
+        SuperblockOwned {
+            inodes_count: raw.inodes_count,
+            blocks_count: raw.blocks_count,
+            r_blocks_count: raw.r_blocks_count,
+            free_blocks_count: raw.free_blocks_count,
+            free_inodes_count: raw.free_inodes_count,
+            first_data_block: raw.first_data_block,
+            log_block_size: raw.log_block_size,
+            log_frag_size: raw.log_frag_size,
+            blocks_per_group: raw.blocks_per_group,
+            frags_per_group: raw.frags_per_group,
+            inodes_per_group: raw.inodes_per_group,
+            mtime: raw.mtime,
+            wtime: raw.wtime,
+            mnt_count: raw.mnt_count,
+            max_mnt_count: raw.max_mnt_count,
+            magic: raw.magic,
+            state: raw.state,
+            errors: raw.errors,
+            rev_minor: raw.rev_minor,
+            lastcheck: raw.lastcheck,
+            checkinterval: raw.checkinterval,
+            creator_os: raw.creator_os,
+            rev_major: raw.rev_major,
+            block_uid: raw.block_uid,
+            block_gid: raw.block_gid,
+            first_inode: raw.first_inode,
+            inode_size: raw.inode_size,
+            block_group: raw.block_group,
+            features_opt: raw.features_opt,
+            features_req: raw.features_req,
+            features_ronly: raw.features_ronly,
+            fs_id: raw.fs_id,
+            volume_name: raw.volume_name,
+            last_mnt_path: raw.last_mnt_path.to_vec(),
+            compression: raw.compression,
+            prealloc_blocks_files: raw.prealloc_blocks_files,
+            prealloc_blocks_dirs: raw.prealloc_blocks_dirs,
+            reserved_gdt_blocks: raw.reserved_gdt_blocks,
+            journal_id: raw.journal_id,
+            journal_inode: raw.journal_inode,
+            journal_dev: raw.journal_dev,
+            journal_orphan_head: raw.journal_orphan_head,
+            hash_seed: raw.hash_seed,
+            def_hash_version: raw.def_hash_version,
+            journal_backup_type: raw.journal_backup_type,
+            desc_size: raw.desc_size,
+            default_mount_opts: raw.default_mount_opts,
+            first_meta_bg: raw.first_meta_bg,
+            mkfs_time: raw.mkfs_time,
+            journal_blocks: raw.journal_blocks,
+            blocks_count_high: raw.blocks_count_high,
+            reserved_blocks_count_high: raw.reserved_blocks_count_high,
+            free_blocks_count_high: raw.free_blocks_count_high,
+            min_extra_isize: raw.min_extra_isize,
+            want_extra_isize: raw.want_extra_isize,
+            misc_flags: raw.misc_flags,
+        }
+    }
+}
+
+/// Every field of a `BlockGroupDescriptor`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GroupDescriptorOwned {
+    pub block_usage_addr: u32,
+    pub inode_usage_addr: u32,
+    pub inode_table_block: u32,
+    pub free_blocks_count: u16,
+    pub free_inodes_count: u16,
+    pub dirs_count: u16,
+}
+
+impl From<&BlockGroupDescriptor> for GroupDescriptorOwned {
+    fn from(raw: &BlockGroupDescriptor) -> GroupDescriptorOwned {
+        GroupDescriptorOwned {
+            block_usage_addr: raw.block_usage_addr,
+            inode_usage_addr: raw.inode_usage_addr,
+            inode_table_block: raw.inode_table_block,
+            free_blocks_count: raw.free_blocks_count,
+            free_inodes_count: raw.free_inodes_count,
+            dirs_count: raw.dirs_count,
+        }
+    }
+}
+
+/// Every field of an `Inode`, the OS-specific ones as raw bytes.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InodeOwned {
+    /// The type and permission bits, as stored
+    pub type_perm: u16,
+    pub uid: u16,
+    pub size_low: u32,
+    pub atime: u32,
+    pub ctime: u32,
+    pub mtime: u32,
+    pub dtime: u32,
+    pub gid: u16,
+    pub hard_links: u16,
+    pub sectors_count: u32,
+    pub flags: u32,
+    pub os_specific_1: [u8; 4],
+    pub direct_pointer: [u32; 12],
+    pub indirect_pointer: u32,
+    pub doubly_indirect: u32,
+    pub triply_indirect: u32,
+    pub gen_number: u32,
+    pub ext_attribute_block: u32,
+    pub size_high: u32,
+    pub frag_block_addr: u32,
+    pub os_specific_2: [u8; 12],
+}
+
+impl From<&Inode> for InodeOwned {
+    fn from(raw: &Inode) -> InodeOwned {
+        InodeOwned {
+            type_perm: raw.type_perm.bits(),
+            uid: raw.uid,
+            size_low: raw.size_low,
+            atime: raw.atime,
+            ctime: raw.ctime,
+            mtime: raw.mtime,
+            dtime: raw.dtime,
+            gid: raw.gid,
+            hard_links: raw.hard_links,
+            sectors_count: raw.sectors_count,
+            flags: raw.flags,
+            os_specific_1: raw._os_specific_1,
+            direct_pointer: raw.direct_pointer,
+            indirect_pointer: raw.indirect_pointer,
+            doubly_indirect: raw.doubly_indirect,
+            triply_indirect: raw.triply_indirect,
+            gen_number: raw.gen_number,
+            ext_attribute_block: raw.ext_attribute_block,
+            size_high: raw.size_high,
+            frag_block_addr: raw.frag_block_addr,
+            os_specific_2: raw._os_specific_2,
+        }
+    }
+}
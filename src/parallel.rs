@@ -0,0 +1,62 @@
+// Walking a directory tree on rayon's thread pool, behind the `parallel`
+// feature.
+//
+// Reading never modifies anything, so any number of threads can share
+// `&Ext2`: each directory's entries are handed out across the pool, and
+// every subdirectory found is walked the same way, so the work spreads out
+// as the tree fans out.
+
+use crate::structs::Inode;
+use crate::{Ext2, NameKind, RawDirEntry, Result};
+use rayon::prelude::*;
+use std::collections::HashSet;
+use std::sync::Mutex;
+
+impl Ext2 {
+    /// Call `visitor` with the path, inode number and inode of everything
+    /// below directory `dir`, walking subdirectories in parallel on the
+    /// current rayon thread pool. Paths are relative to `dir`, e.g.
+    /// `a/b/c`; `.` and `..` aren't visited.
+    ///
+    /// The visitor runs on many threads at once and sees entries in no
+    /// particular order. A directory linked from more than one place is
+    /// only walked the first time it's reached. The walk stops at the first
+    /// error, from reading the tree or from the visitor.
+    pub fn walk_parallel<F>(&self, dir: usize, visitor: &F) -> Result<()>
+    where
+        F: Fn(&str, usize, &Inode) -> Result<()> + Sync,
+    {
+        let visited = Mutex::new(HashSet::from([dir]));
+        self.walk_dir_parallel(dir, "", &visited, visitor)
+    }
+
+    // A helper function for `walk_parallel`: visit the entries of `dir`, whose
+    // path is `prefix`, and walk the directories among them
+    fn walk_dir_parallel<F>(
+        &self,
+        dir: usize,
+        prefix: &str,
+        visited: &Mutex<HashSet<usize>>,
+        visitor: &F,
+    ) -> Result<()>
+    where
+        F: Fn(&str, usize, &Inode) -> Result<()> + Sync,
+    {
+        self.read_dir_raw(dir)?
+            .par_iter()
+            .filter(|entry| entry.name.kind() != NameKind::Dot)
+            .try_for_each(|&RawDirEntry { inode, name, .. }| {
+                let path = if prefix.is_empty() {
+                    name.to_string()
+                } else {
+                    format!("{}/{}", prefix, name)
+                };
+                let record = self.get_inode(inode)?;
+                visitor(&path, inode, record)?;
+                if record.is_dir() && visited.lock().unwrap().insert(inode) {
+                    self.walk_dir_parallel(inode, &path, visited, visitor)?;
+                }
+                Ok(())
+            })
+    }
+}
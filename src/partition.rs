@@ -0,0 +1,185 @@
+// Finding filesystems inside whole-disk images, which start with a partition
+// table rather than a filesystem: an MBR, or a GPT behind the protective MBR
+// in front of it. Only the primary MBR partitions are listed; extended and
+// logical ones aren't followed. Sectors are taken to be 512 bytes, as in
+// practically every image file.
+// https://en.wikipedia.org/wiki/Master_boot_record#Partition_table_entries
+// https://uefi.org/specs/UEFI/2.10/05_GUID_Partition_Table_Format.html
+
+use std::fmt;
+use std::io::{self, Seek, SeekFrom, Write};
+use uuid::Uuid;
+
+pub(crate) const SECTOR_SIZE: u64 = 512;
+const MBR_ENTRIES: usize = 446;
+const MBR_SIGNATURE: usize = 510;
+const MBR_TYPE_LINUX: u8 = 0x83;
+// the single partition covering the disk in front of a GPT
+const MBR_TYPE_PROTECTIVE: u8 = 0xee;
+const GPT_SIGNATURE: &[u8; 8] = b"EFI PART";
+// the GPT type for any Linux filesystem, ext2 included
+const GPT_TYPE_LINUX: Uuid = uuid::uuid!("0fc63daf-8483-4772-8e79-3d69d8477de4");
+
+/// What a partition table says a partition holds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PartitionType {
+    /// The type byte of an MBR entry, e.g. 0x83 for Linux
+    Mbr(u8),
+    /// The type GUID of a GPT entry
+    Gpt(Uuid),
+}
+
+impl fmt::Display for PartitionType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PartitionType::Mbr(kind) => write!(f, "MBR type {:#04x}", kind),
+            PartitionType::Gpt(kind) => write!(f, "GPT type {}", kind),
+        }
+    }
+}
+
+/// One partition of a disk image, as `partitions` finds it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Partition {
+    /// Its position in the table, from 1, as in `/dev/sda1`
+    pub number: usize,
+    pub kind: PartitionType,
+    /// Where it starts, in bytes from the start of the image
+    pub offset: u64,
+    /// Its size in bytes
+    pub len: u64,
+}
+
+impl Partition {
+    /// Whether the table says it holds a Linux filesystem, which is the most
+    /// it can say about whether that's ext2.
+    pub fn is_linux(&self) -> bool {
+        match self.kind {
+            PartitionType::Mbr(kind) => kind == MBR_TYPE_LINUX,
+            PartitionType::Gpt(kind) => kind == GPT_TYPE_LINUX,
+        }
+    }
+
+    /// The partition's bytes of `disk`.
+    pub fn slice<'a>(&self, disk: &'a [u8]) -> &'a [u8] {
+        &disk[self.offset as usize..(self.offset + self.len) as usize]
+    }
+}
+
+/// The partitions in `disk`'s partition table, in table order; none if it
+/// doesn't start with one, e.g. because it's a bare filesystem. Entries that
+/// run past the end of the image are left out, since there's nothing there
+/// to open.
+pub fn partitions(disk: &[u8]) -> Vec<Partition> {
+    partitions_within(disk, disk.len() as u64)
+}
+
+// the partitions in the table at the start of `disk`, of a device
+// `disk_len` bytes long, which `disk` may be only the first part of
+pub(crate) fn partitions_within(disk: &[u8], disk_len: u64) -> Vec<Partition> {
+    if disk.len() < SECTOR_SIZE as usize || disk[MBR_SIGNATURE..MBR_SIGNATURE + 2] != [0x55, 0xaa] {
+        return Vec::new();
+    }
+    let mbr: Vec<Partition> = (0..4)
+        .filter_map(|i| {
+            let entry = &disk[MBR_ENTRIES + 16 * i..MBR_ENTRIES + 16 * (i + 1)];
+            let kind = entry[4];
+            let start = u32::from_le_bytes(entry[8..12].try_into().unwrap()) as u64;
+            let sectors = u32::from_le_bytes(entry[12..16].try_into().unwrap()) as u64;
+            (kind != 0 && sectors != 0).then_some(Partition {
+                number: i + 1,
+                kind: PartitionType::Mbr(kind),
+                offset: start * SECTOR_SIZE,
+                len: sectors * SECTOR_SIZE,
+            })
+        })
+        .collect();
+    let partitions = if mbr
+        .iter()
+        .any(|p| p.kind == PartitionType::Mbr(MBR_TYPE_PROTECTIVE))
+    {
+        gpt_partitions(disk).unwrap_or_default()
+    } else {
+        mbr
+    };
+    partitions
+        .into_iter()
+        .filter(|p| p.offset.saturating_add(p.len) <= disk_len)
+        .collect()
+}
+
+// the entries of the GPT whose header is in the second sector, or `None` if
+// there isn't one there that makes sense
+fn gpt_partitions(disk: &[u8]) -> Option<Vec<Partition>> {
+    let header = disk.get(SECTOR_SIZE as usize..2 * SECTOR_SIZE as usize)?;
+    if &header[..8] != GPT_SIGNATURE {
+        return None;
+    }
+    let u32_at = |at: usize| u32::from_le_bytes(header[at..at + 4].try_into().unwrap());
+    let entries_lba = u64::from_le_bytes(header[72..80].try_into().unwrap());
+    let count = u32_at(80) as usize;
+    let entry_size = u32_at(84) as usize;
+    if entry_size < 128 {
+        return None;
+    }
+    let start = usize::try_from(entries_lba.checked_mul(SECTOR_SIZE)?).ok()?;
+    let table = disk.get(start..start.checked_add(count.checked_mul(entry_size)?)?)?;
+    let partitions = table
+        .chunks_exact(entry_size)
+        .enumerate()
+        .filter_map(|(i, entry)| {
+            // GUIDs are stored with their first three fields little-endian
+            let kind = Uuid::from_bytes_le(entry[..16].try_into().unwrap());
+            let first = u64::from_le_bytes(entry[32..40].try_into().unwrap());
+            let last = u64::from_le_bytes(entry[40..48].try_into().unwrap());
+            (!kind.is_nil() && last >= first).then(|| Partition {
+                number: i + 1,
+                kind: PartitionType::Gpt(kind),
+                offset: first.saturating_mul(SECTOR_SIZE),
+                len: (last - first + 1).saturating_mul(SECTOR_SIZE),
+            })
+        })
+        .collect();
+    Some(partitions)
+}
+
+/// A device seen from `offset` bytes in, so that `Ext2::sync` writes a
+/// filesystem opened from a partition back into the partition rather than
+/// over the start of the disk.
+#[derive(Debug)]
+pub struct OffsetDevice<D> {
+    device: D,
+    offset: u64,
+}
+
+impl<D> OffsetDevice<D> {
+    pub fn new(device: D, offset: u64) -> OffsetDevice<D> {
+        OffsetDevice { device, offset }
+    }
+}
+
+impl<D: Write> Write for OffsetDevice<D> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.device.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.device.flush()
+    }
+}
+
+impl<D: Seek> Seek for OffsetDevice<D> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let pos = match pos {
+            SeekFrom::Start(pos) => SeekFrom::Start(self.offset + pos),
+            relative => relative,
+        };
+        let pos = self.device.seek(pos)?;
+        pos.checked_sub(self.offset).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "seek to before the start of the partition",
+            )
+        })
+    }
+}
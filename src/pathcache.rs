@@ -0,0 +1,66 @@
+// A cache of resolved paths, so a deep path used by one command after
+// another is looked up a directory at a time only the first time.
+//
+// Any modification may rename, unlink or replace a directory somewhere along
+// a cached path, so rather than working out which entries that touches, the
+// whole cache is dropped once the filesystem's generation moves on. Paths
+// are resolved without following symlinks, so a symlink is only ever the
+// last component, and what's cached for it is the symlink's own inode, which
+// retargeting it doesn't change.
+
+use crate::{Ext2, Result};
+use std::collections::HashMap;
+
+/// Inode numbers of paths resolved since the filesystem was last modified,
+/// including every directory on the way to them.
+#[derive(Debug, Clone, Default)]
+pub struct PathCache {
+    generation: u64,
+    // (starting directory, components joined by '/') -> inode; absolute
+    // paths start at the root
+    paths: HashMap<(usize, String), usize>,
+}
+
+impl PathCache {
+    pub fn new() -> PathCache {
+        PathCache::default()
+    }
+
+    /// Resolve `path` like `Ext2::resolve_path`, taking whatever prefix of
+    /// it is cached and looking up only the rest. Failed lookups aren't
+    /// cached.
+    pub fn resolve(&mut self, ext2: &Ext2, cwd: usize, path: &str) -> Result<usize> {
+        if self.generation != ext2.generation() {
+            self.paths.clear();
+            self.generation = ext2.generation();
+        }
+        let start = if path.starts_with('/') { 2 } else { cwd };
+        let components: Vec<&str> = path.split('/').filter(|c| !c.is_empty()).collect();
+        let key = |end: usize| (start, components[..end].join("/"));
+
+        // the longest cached prefix, then one lookup per component after it
+        let mut done = components.len();
+        let mut current = start;
+        while done > 0 {
+            if let Some(&inode) = self.paths.get(&key(done)) {
+                current = inode;
+                break;
+            }
+            done -= 1;
+        }
+        for end in done + 1..=components.len() {
+            current = ext2.resolve_component(current, components[end - 1], path)?;
+            self.paths.insert(key(end), current);
+        }
+        Ok(current)
+    }
+
+    /// How many paths are cached.
+    pub fn len(&self) -> usize {
+        self.paths.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.paths.is_empty()
+    }
+}
@@ -0,0 +1,263 @@
+// Importing a directory tree from the host, like `mke2fs -d`.
+//
+// Everything is created through the same calls the shell uses, so the
+// result is an ordinary tree: what makes it different is only that the
+// contents, permission bits and times come from the host. A failure part
+// way through (typically running out of inodes or blocks) puts the
+// in-memory filesystem back the way it was before the import started.
+
+use crate::progress::check_cancel;
+use crate::structs::{FeatureRoCompat, TypePerm};
+use crate::{Ext2, Ext2Error, NoProgress, Progress, Result};
+use log::info;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+// a fast symlink keeps its target in the 60 bytes of block pointers
+const FAST_SYMLINK_MAX: usize = 59;
+
+/// What `Ext2::populate_from_host` brought in.
+#[derive(Debug, Clone, Default)]
+pub struct PopulateSummary {
+    /// Regular files, counting each set of hard links once
+    pub files: usize,
+    pub dirs: usize,
+    pub symlinks: usize,
+    /// Total size of the regular files
+    pub bytes: u64,
+    /// What wasn't imported: host devices, FIFOs and sockets, or the
+    /// archive entries of a kind with no ext2 equivalent
+    pub skipped: Vec<PathBuf>,
+}
+
+impl fmt::Display for PopulateSummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} file(s), {} byte(s), {} director(y/ies), {} symlink(s)",
+            self.files, self.bytes, self.dirs, self.symlinks
+        )?;
+        if !self.skipped.is_empty() {
+            write!(f, ", {} skipped", self.skipped.len())?;
+        }
+        Ok(())
+    }
+}
+
+impl Ext2 {
+    /// Copy everything under the host directory `host_dir` into the existing
+    /// directory at `dest` (a path from the root of the image): directories,
+    /// regular files with their contents, and symlinks, with their
+    /// permission bits and access and modification times. Hard links
+    /// between host files stay hard links. On failure nothing is left of
+    /// the partial import.
+    pub fn populate_from_host(&mut self, host_dir: &Path, dest: &str) -> Result<PopulateSummary> {
+        self.populate_from_host_with_progress(host_dir, dest, &NoProgress)
+    }
+
+    /// `populate_from_host`, telling `progress` about each host path as it's
+    /// imported and the bytes of file contents copied so far. Cancelling
+    /// rolls back like any other failure.
+    pub fn populate_from_host_with_progress(
+        &mut self,
+        host_dir: &Path,
+        dest: &str,
+        progress: &dyn Progress,
+    ) -> Result<PopulateSummary> {
+        self.check_writable()?;
+        let dir = self.resolve_path(2, dest)?;
+        if !self.get_inode(dir)?.is_dir() {
+            return Err(Ext2Error::NotADirectory {
+                name: dest.to_string(),
+            });
+        }
+        // every change so far is in memory, so going back to a snapshot
+        // undoes whatever the import got through before failing
+        let saved = self.snapshot();
+        let mut summary = PopulateSummary::default();
+        let mut links = HashMap::new();
+        match self.populate_dir(host_dir, dir, &mut summary, &mut links, progress) {
+            Ok(()) => {
+                info!(
+                    "populated {} from {}: {}",
+                    dest,
+                    host_dir.display(),
+                    summary
+                );
+                Ok(summary)
+            }
+            Err(err) => {
+                self.rollback(&saved);
+                info!(
+                    "populating {} from {} failed, rolled back: {}",
+                    dest,
+                    host_dir.display(),
+                    err
+                );
+                Err(err)
+            }
+        }
+    }
+
+    // A helper function for `populate_from_host`: import the entries of
+    // host directory `host_dir` into directory `dir`
+    // `links` maps the (device, inode) of each host file with several
+    // links to the inode it was imported as
+    fn populate_dir(
+        &mut self,
+        host_dir: &Path,
+        dir: usize,
+        summary: &mut PopulateSummary,
+        links: &mut HashMap<(u64, u64), usize>,
+        progress: &dyn Progress,
+    ) -> Result<()> {
+        let mut entries = fs::read_dir(host_dir)
+            .and_then(|entries| entries.collect::<io::Result<Vec<_>>>())
+            .map_err(|err| host_error(host_dir, err))?;
+        // the same tree always gives the same image
+        entries.sort_by_key(|entry| entry.file_name());
+        for entry in entries {
+            check_cancel(progress)?;
+            let path = entry.path();
+            progress.on_item(&path.to_string_lossy());
+            let name = entry.file_name();
+            let Some(name) = name.to_str() else {
+                return Err(Ext2Error::InvalidName {
+                    name: path.display().to_string(),
+                });
+            };
+            let meta = fs::symlink_metadata(&path).map_err(|err| host_error(&path, err))?;
+            let perm = (meta.mode() & 0o7777) as u16;
+            let file_type = meta.file_type();
+            let inode = if file_type.is_dir() {
+                let inode = self.create_dir(dir, name, perm)?;
+                self.populate_dir(&path, inode, summary, links, progress)?;
+                summary.dirs += 1;
+                inode
+            } else if file_type.is_symlink() {
+                let target = fs::read_link(&path).map_err(|err| host_error(&path, err))?;
+                let inode = self.create_node(dir, name, TypePerm::SYMLINK.bits() | 0o777, None)?;
+                self.write_symlink(inode, target.as_os_str().as_bytes())?;
+                summary.symlinks += 1;
+                inode
+            } else if file_type.is_file() {
+                if let Some(&inode) = links.get(&(meta.dev(), meta.ino())) {
+                    self.check_new_name(dir, name)?;
+                    self.add_dir_entry(dir, name, inode, TypePerm::FILE.bits())?;
+                    self.inode_mut(inode)?.hard_links += 1;
+                    continue;
+                }
+                let inode = self.create_file(dir, name, perm)?;
+                let mut file = File::open(&path).map_err(|err| host_error(&path, err))?;
+                self.import_contents(inode, &mut file)
+                    .map_err(|err| match err {
+                        Ext2Error::Io(err) => host_error(&path, err),
+                        err => err,
+                    })?;
+                if meta.nlink() > 1 {
+                    links.insert((meta.dev(), meta.ino()), inode);
+                }
+                summary.files += 1;
+                summary.bytes += meta.len();
+                progress.on_bytes(summary.bytes, None);
+                inode
+            } else {
+                summary.skipped.push(path);
+                continue;
+            };
+            // last, since filling in a directory updates its times
+            let record = self.inode_mut(inode)?;
+            record.atime = meta.atime() as u32;
+            record.mtime = meta.mtime() as u32;
+        }
+        Ok(())
+    }
+
+    // copy everything `reader` has into the empty regular file `inode`, a
+    // block at a time, and return how many bytes that was
+    // blocks of nothing but zeros are left as holes; failing to read is an
+    // `Ext2Error::Io`
+    pub(crate) fn import_contents<R: Read>(&mut self, inode: usize, reader: &mut R) -> Result<u64> {
+        let mut buf = vec![0; self.block_size];
+        let mut goal = self.group_first_block(self.inode_group(inode));
+        let mut size = 0;
+        for logical in 0.. {
+            let len = read_full(reader, &mut buf)?;
+            if len == 0 {
+                break;
+            }
+            size += len as u64;
+            if buf[..len].iter().any(|&b| b != 0) {
+                let block_num = self.map_block(inode, logical, goal)?;
+                self.block_mut(block_num)?[..len].copy_from_slice(&buf[..len]);
+                goal = block_num + 1;
+            }
+            if len < buf.len() {
+                break;
+            }
+        }
+        if size > i32::MAX as u64 {
+            // older kernels take size_high for a directory ACL and can't go
+            // past 2GiB, so files that big need the feature that says otherwise
+            self.superblock.features_ronly |= FeatureRoCompat::LARGE_FILE.bits();
+        }
+        self.inode_mut(inode)?.set_size(size);
+        Ok(size)
+    }
+
+    // store `target` as the target of the new symlink `inode`, in its block
+    // pointers if it fits, otherwise in a block of its own
+    pub(crate) fn write_symlink(&mut self, inode: usize, target: &[u8]) -> Result<()> {
+        if target.len() >= self.block_size {
+            return Err(Ext2Error::InvalidName {
+                name: String::from_utf8_lossy(target).into_owned(),
+            });
+        }
+        if target.len() <= FAST_SYMLINK_MAX {
+            let mut bytes = [0; 60];
+            bytes[..target.len()].copy_from_slice(target);
+            let mut pointers = bytes
+                .chunks_exact(4)
+                .map(|chunk| u32::from_le_bytes(chunk.try_into().unwrap()));
+            let record = self.inode_mut(inode)?;
+            for pointer in record.direct_pointer.iter_mut() {
+                *pointer = pointers.next().unwrap();
+            }
+            record.indirect_pointer = pointers.next().unwrap();
+            record.doubly_indirect = pointers.next().unwrap();
+            record.triply_indirect = pointers.next().unwrap();
+        } else {
+            let goal = self.group_first_block(self.inode_group(inode));
+            let block_num = self.map_block(inode, 0, goal)?;
+            self.block_mut(block_num)?[..target.len()].copy_from_slice(target);
+        }
+        self.inode_mut(inode)?.set_size(target.len() as u64);
+        Ok(())
+    }
+}
+
+fn host_error(path: &Path, source: io::Error) -> Ext2Error {
+    Ext2Error::Host {
+        path: path.display().to_string(),
+        source,
+    }
+}
+
+// fill as much of `buf` as the rest of `file` allows, returning how much
+pub(crate) fn read_full<R: Read>(file: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match file.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(len) => filled += len,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(filled)
+}
@@ -0,0 +1,132 @@
+// Preallocating a file's blocks ahead of writing it, like fallocate(2). The
+// holes up to the length asked for get blocks now, in one run of free blocks
+// if there's one long enough, so whatever writes the file later finds its
+// blocks already there, one after another, rather than taking free blocks
+// wherever the allocator finds them as it goes. The indirect blocks the file
+// needs come first in the run and the data blocks after them, so the data
+// isn't broken up by the pointers to it.
+//
+// Ext2 has no way to mark blocks as allocated but not yet written, so the
+// new blocks are zeroed, and with the size kept, the blocks past the end
+// are there only for the file to grow into. This checker accepts those, but
+// e2fsck, for ext2, wants the size to cover every block a file has.
+
+use crate::structs::FeatureRoCompat;
+use crate::{Ext2, Ext2Error, Result};
+use log::debug;
+
+impl Ext2 {
+    /// Give `inode` blocks for its first `len` bytes wherever it has holes,
+    /// as contiguous as the free space allows, and return how many blocks
+    /// that took, indirect blocks included. With `keep_size` the file's size
+    /// stays as it is; otherwise a file shorter than `len` grows to it, the
+    /// new part reading as zeros. Fails with `NoSpace`, having allocated
+    /// nothing, if there aren't enough free blocks for the data.
+    pub fn preallocate(&mut self, inode: usize, len: u64, keep_size: bool) -> Result<usize> {
+        self.check_writable()?;
+        let record = self.get_inode(inode)?;
+        if record.is_dir() {
+            return Err(Ext2Error::IsADirectory {
+                name: format!("inode {}", inode),
+            });
+        }
+        if !record.is_regular() {
+            return Err(Ext2Error::NotPermitted {
+                name: format!("inode {}", inode),
+            });
+        }
+        let size = record.size();
+        let sectors_before = record.sectors_count;
+        self.check_write(inode, size)?;
+
+        let blocks = len.div_ceil(self.block_size as u64) as usize;
+        let mut holes = Vec::new();
+        let mut last = None;
+        {
+            let pointers = self.file_blocks(inode)?;
+            for logical in 0..blocks {
+                match pointers.lookup(logical)? {
+                    0 => holes.push(logical),
+                    block_num if holes.is_empty() => last = Some(block_num as usize),
+                    _ => {}
+                }
+            }
+        }
+        if holes.len() > self.available_blocks() as usize {
+            return Err(Ext2Error::NoSpace);
+        }
+
+        if !holes.is_empty() {
+            // room for the data and, at most, one indirect block for each
+            // block's worth of pointers and one for each level above
+            let per_block = self.block_size / 4;
+            let wanted = holes.len() + holes.len().div_ceil(per_block) + 2;
+            let mut goal = self.prealloc_goal(last, wanted)?;
+            // the indirect blocks first
+            for &logical in &holes {
+                if logical >= 12 {
+                    let before = self.get_inode(inode)?.sectors_count;
+                    let (leaf, _) = self.map_leaf(inode, logical, goal)?;
+                    if self.get_inode(inode)?.sectors_count != before {
+                        goal = leaf + 1;
+                    }
+                }
+            }
+            for &logical in &holes {
+                goal = self.map_block(inode, logical, goal)? + 1;
+            }
+        }
+
+        let now = self.now();
+        if !keep_size && len > size {
+            if len > i32::MAX as u64 {
+                self.superblock.features_ronly |= FeatureRoCompat::LARGE_FILE.bits();
+            }
+            // what's past the end of the last block must read as zeros too
+            // once the file grows over it
+            let block_size = self.block_size as u64;
+            if size % block_size != 0 {
+                let last = (size / block_size) as usize;
+                let block_num = self.file_blocks(inode)?.lookup(last)? as usize;
+                if block_num != 0 {
+                    self.block_mut(block_num)?[(size % block_size) as usize..].fill(0);
+                }
+            }
+            self.touch_modified(inode)?;
+            self.inode_mut(inode)?.set_size(len);
+        } else if !holes.is_empty() {
+            self.inode_mut(inode)?.ctime = now;
+        }
+        let allocated = (self.get_inode(inode)?.sectors_count - sectors_before) as usize
+            / (self.block_size / 512);
+        debug!(
+            "preallocated {} block(s) for the first {} byte(s) of inode {}",
+            allocated, len, inode
+        );
+        Ok(allocated)
+    }
+
+    // where to start allocating `wanted` blocks for a file whose last block
+    // before them is `last`: right after it if there's room, else the first
+    // run of free blocks long enough, else the longest
+    fn prealloc_goal(&self, last: Option<usize>, wanted: usize) -> Result<usize> {
+        let runs = self.free_runs()?;
+        if let Some(last) = last {
+            let after = runs
+                .iter()
+                .find(|&&(start, _)| start == last + 1)
+                .filter(|&&(_, len)| len >= wanted);
+            if after.is_some() {
+                return Ok(last + 1);
+            }
+        }
+        let fits = runs.iter().find(|&&(_, len)| len >= wanted);
+        let longest = runs
+            .iter()
+            .fold(None, |best: Option<&(usize, usize)>, run| match best {
+                Some(best) if best.1 >= run.1 => Some(best),
+                _ => Some(run),
+            });
+        Ok(fits.or(longest).map_or(0, |&(start, _)| start))
+    }
+}
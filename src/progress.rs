@@ -0,0 +1,54 @@
+// Reporting how far a long operation has got, and stopping it part way.
+//
+// The operations that can take minutes on a big image (importing a host
+// tree or an archive, exporting one, hashing every file, checking the
+// filesystem) take a `Progress` in their `_with_progress` forms and call it
+// as they go. Whatever they do between calls is short, so asking it whether
+// to stop at each call is enough to stop promptly.
+
+use crate::{Ext2Error, Result};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Told how a long operation is getting on, and asked whether to stop it.
+/// Every method does nothing by default, so an implementation only needs
+/// the ones it cares about.
+pub trait Progress {
+    /// The operation has got to `path`: a file or directory it's about to
+    /// import, export, hash or check.
+    fn on_item(&self, _path: &str) {}
+
+    /// `done` bytes have been read or written so far, out of `total` if
+    /// it's known up front.
+    fn on_bytes(&self, _done: u64, _total: Option<u64>) {}
+
+    /// Asked between items; once this returns true the operation stops with
+    /// `Ext2Error::Cancelled`, undoing what it can as it would for any
+    /// other error.
+    fn should_cancel(&self) -> bool {
+        false
+    }
+}
+
+/// Progress that isn't shown and never cancels, for the forms of the long
+/// operations that don't take one.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoProgress;
+
+impl Progress for NoProgress {}
+
+/// A flag that cancels once it's set, e.g. from a SIGINT handler, and
+/// shows nothing.
+impl Progress for AtomicBool {
+    fn should_cancel(&self) -> bool {
+        self.load(Ordering::Relaxed)
+    }
+}
+
+// fail with `Cancelled` if `progress` asks to stop
+pub(crate) fn check_cancel(progress: &dyn Progress) -> Result<()> {
+    if progress.should_cancel() {
+        Err(Ext2Error::Cancelled)
+    } else {
+        Ok(())
+    }
+}
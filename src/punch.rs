@@ -0,0 +1,217 @@
+// Punching holes in a file: giving back the blocks of a range of it, like
+// fallocate(2) with FALLOC_FL_PUNCH_HOLE. The blocks the range covers whole
+// are freed and the pointers to them zeroed, and so is any indirect block
+// left pointing at nothing; the blocks it only partly covers keep their
+// place and have the part in the range zeroed. The size stays as it is, so
+// the file reads the same as if the range had been overwritten with zeros,
+// only with fewer blocks. Truncating frees everything from the new end on
+// the same way, and changes the size to match.
+
+use crate::structs::FeatureRoCompat;
+use crate::{Ext2, Ext2Error, Result};
+use log::debug;
+use std::ops::Range;
+
+impl Ext2 {
+    /// Make the `len` bytes of `inode` from `offset` a hole, reading as
+    /// zeros, and return how many blocks that freed. Only the file's
+    /// existing bytes are touched: a range running past the end stops
+    /// there, and the size never changes. Fails with `NotPermitted` for
+    /// anything but a regular file, or one whose flags don't allow writing
+    /// there.
+    pub fn punch_hole(&mut self, inode: usize, offset: u64, len: u64) -> Result<usize> {
+        self.check_writable()?;
+        let record = self.get_inode(inode)?;
+        if record.is_dir() {
+            return Err(Ext2Error::IsADirectory {
+                name: format!("inode {}", inode),
+            });
+        }
+        if !record.is_regular() {
+            return Err(Ext2Error::NotPermitted {
+                name: format!("inode {}", inode),
+            });
+        }
+        let size = record.size();
+        let end = offset.saturating_add(len).min(size);
+        if offset >= end {
+            return Ok(0);
+        }
+        self.check_write(inode, offset)?;
+
+        // the blocks the range covers whole; the last block of a file that
+        // ends part way through one is, if the range goes to the end
+        let block_size = self.block_size as u64;
+        let first_whole = offset.div_ceil(block_size);
+        let end_whole = if end == size {
+            end.div_ceil(block_size)
+        } else {
+            end / block_size
+        };
+        // and what's left at either edge, zeroed in place
+        if first_whole >= end_whole {
+            self.zero_bytes(inode, offset, end)?;
+        } else {
+            self.zero_bytes(inode, offset, first_whole * block_size)?;
+            self.zero_bytes(inode, end_whole * block_size, end)?;
+        }
+
+        let range = first_whole as usize..end_whole as usize;
+        let mut freed = 0;
+        if !range.is_empty() {
+            freed = self.punch_blocks(inode, &range)?;
+        }
+        self.touch_modified(inode)?;
+        let sectors = self.block_size as u32 / 512;
+        self.inode_mut(inode)?.sectors_count -= freed as u32 * sectors;
+        debug!(
+            "punched a hole in inode {} at {} for {} byte(s): {} block(s) freed",
+            inode, offset, len, freed
+        );
+        Ok(freed)
+    }
+
+    /// Cut `inode` down to `size` bytes, or grow it to that with a hole,
+    /// like truncate(2), and return how many blocks that freed; blocks
+    /// reserved past the end are freed too. Fails with `NotPermitted` for
+    /// anything but a regular file, or one whose flags don't allow writing
+    /// at `size`: an immutable one, or an append-only one made shorter.
+    pub fn truncate(&mut self, inode: usize, size: u64) -> Result<usize> {
+        self.check_writable()?;
+        let record = self.get_inode(inode)?;
+        if record.is_dir() {
+            return Err(Ext2Error::IsADirectory {
+                name: format!("inode {}", inode),
+            });
+        }
+        if !record.is_regular() {
+            return Err(Ext2Error::NotPermitted {
+                name: format!("inode {}", inode),
+            });
+        }
+        let old_size = record.size();
+        self.check_write(inode, size)?;
+
+        // what's left of the last block past the end reads as zeros if the
+        // file grows over it again
+        let block_size = self.block_size as u64;
+        let end = size.min(old_size);
+        self.zero_bytes(inode, end, end.div_ceil(block_size) * block_size)?;
+        let freed = self.punch_blocks(inode, &(size.div_ceil(block_size) as usize..usize::MAX))?;
+        if size > i32::MAX as u64 {
+            self.superblock.features_ronly |= FeatureRoCompat::LARGE_FILE.bits();
+        }
+        self.touch_modified(inode)?;
+        let sectors = self.block_size as u32 / 512;
+        let record = self.inode_mut(inode)?;
+        record.set_size(size);
+        record.sectors_count -= freed as u32 * sectors;
+        debug!(
+            "truncated inode {} from {} to {} byte(s): {} block(s) freed",
+            inode, old_size, size, freed
+        );
+        Ok(freed)
+    }
+
+    // zero bytes `from` to `to` of `inode` where they have blocks
+    fn zero_bytes(&mut self, inode: usize, from: u64, to: u64) -> Result<()> {
+        let block_size = self.block_size as u64;
+        let mut position = from;
+        while position < to {
+            let logical = (position / block_size) as usize;
+            let within = (position % block_size) as usize;
+            let len = (self.block_size - within).min((to - position) as usize);
+            let block_num = self.file_blocks(inode)?.lookup(logical)? as usize;
+            if block_num != 0 {
+                self.block_mut(block_num)?[within..within + len].fill(0);
+            }
+            position += len as u64;
+        }
+        Ok(())
+    }
+
+    // free the blocks of logical blocks `range` of `inode`, and the indirect
+    // blocks that leaves pointing at nothing, and zero the pointers to them;
+    // returns how many blocks that was
+    pub(crate) fn punch_blocks(&mut self, inode: usize, range: &Range<usize>) -> Result<usize> {
+        let mut freed = 0;
+        for logical in range.start.min(12)..range.end.min(12) {
+            let block_num = self.get_inode(inode)?.direct_pointer[logical];
+            if block_num != 0 {
+                self.free_block(block_num as usize)?;
+                self.inode_mut(inode)?.direct_pointer[logical] = 0;
+                freed += 1;
+            }
+        }
+        let per_block = self.block_size / 4;
+        let mut first = 12;
+        for depth in 1..=3 {
+            let span = per_block.pow(depth);
+            let top = {
+                let record = self.get_inode(inode)?;
+                match depth {
+                    1 => record.indirect_pointer,
+                    2 => record.doubly_indirect,
+                    _ => record.triply_indirect,
+                }
+            } as usize;
+            if top != 0 && range.start < first + span && first < range.end {
+                let (count, empty) = self.punch_indirect(top, depth, first, range)?;
+                freed += count;
+                if empty {
+                    self.free_block(top)?;
+                    freed += 1;
+                    let record = self.inode_mut(inode)?;
+                    match depth {
+                        1 => record.indirect_pointer = 0,
+                        2 => record.doubly_indirect = 0,
+                        _ => record.triply_indirect = 0,
+                    }
+                }
+            }
+            first += span;
+        }
+        Ok(freed)
+    }
+
+    // the same for the tree under indirect block `block`, `depth` levels
+    // above the data, whose first pointer is for logical block `first`; also
+    // returns whether `block` has no pointers left
+    fn punch_indirect(
+        &mut self,
+        block: usize,
+        depth: u32,
+        first: usize,
+        range: &Range<usize>,
+    ) -> Result<(usize, bool)> {
+        let per_block = self.block_size / 4;
+        // how many of the file's blocks each pointer leads to
+        let span = per_block.pow(depth - 1);
+        let from = (range.start.max(first) - first) / span;
+        let to = ((range.end.min(first + span * per_block) - first).div_ceil(span)).min(per_block);
+        let mut freed = 0;
+        for slot in from..to {
+            let at = slot * 4;
+            let child =
+                u32::from_le_bytes(self.block(block)?[at..at + 4].try_into().unwrap()) as usize;
+            if child == 0 {
+                continue;
+            }
+            let empty = if depth == 1 {
+                true
+            } else {
+                let (count, empty) =
+                    self.punch_indirect(child, depth - 1, first + slot * span, range)?;
+                freed += count;
+                empty
+            };
+            if empty {
+                self.free_block(child)?;
+                self.block_mut(block)?[at..at + 4].fill(0);
+                freed += 1;
+            }
+        }
+        let empty = self.block(block)?.iter().all(|&byte| byte == 0);
+        Ok((freed, empty))
+    }
+}
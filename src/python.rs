@@ -0,0 +1,320 @@
+// Python bindings, the `ext2fs` module: reading images from scripts and
+// notebooks. Built with `maturin develop` in `python/`, see
+// `python/pyproject.toml`.
+//
+// Paths are taken from the root whether they start with '/' or not, and
+// aren't followed through symlinks, like everywhere else in the library.
+// Errors come out as the OSError subclass Python itself would raise, e.g.
+// FileNotFoundError, with the errno set.
+
+use crate::report::InodeInfo;
+use crate::{Ext2, Ext2Error};
+use pyo3::exceptions::{
+    PyFileExistsError, PyFileNotFoundError, PyIsADirectoryError, PyNotADirectoryError, PyOSError,
+    PyPermissionError, PyValueError,
+};
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict, PyList};
+use std::collections::HashSet;
+
+const ROOT: usize = 2;
+
+fn to_py_err(err: Ext2Error) -> PyErr {
+    let args = (err.errno(), err.to_string());
+    match err.root_cause() {
+        Ext2Error::NotFound { .. } | Ext2Error::InodeOutOfRange { .. } => {
+            PyFileNotFoundError::new_err(args)
+        }
+        Ext2Error::NotADirectory { .. } => PyNotADirectoryError::new_err(args),
+        Ext2Error::IsADirectory { .. } => PyIsADirectoryError::new_err(args),
+        Ext2Error::AlreadyExists { .. } => PyFileExistsError::new_err(args),
+        Ext2Error::PermissionDenied { .. }
+        | Ext2Error::NotPermitted { .. }
+        | Ext2Error::ReadOnly => PyPermissionError::new_err(args),
+        Ext2Error::InvalidName { .. } | Ext2Error::BadLayout { .. } => {
+            PyValueError::new_err(err.to_string())
+        }
+        _ => PyOSError::new_err(args),
+    }
+}
+
+/// An ext2 image opened read-only, e.g. `Ext2("myfs.ext2")`.
+#[pyclass(name = "Ext2", module = "ext2fs")]
+struct PyExt2 {
+    ext2: Ext2,
+}
+
+/// A path given as a string, or an inode number.
+#[derive(FromPyObject)]
+enum Target {
+    Inode(usize),
+    Path(String),
+}
+
+impl PyExt2 {
+    fn ext2(&self) -> &Ext2 {
+        &self.ext2
+    }
+
+    fn resolve(&self, path: &str) -> PyResult<usize> {
+        self.ext2().resolve_path(ROOT, path).map_err(to_py_err)
+    }
+
+    // the entries of directory `dir`, without "." and ".."
+    fn entries(&self, dir: usize) -> PyResult<Vec<(usize, String)>> {
+        let entries = self.ext2().read_dir_inode(dir).map_err(to_py_err)?;
+        Ok(entries
+            .into_iter()
+            .filter(|entry| !entry.is_dot())
+            .map(|entry| (entry.inode, entry.name_lossy().into_owned()))
+            .collect())
+    }
+
+    fn is_dir(&self, inode: usize) -> PyResult<bool> {
+        Ok(self.ext2().get_inode(inode).map_err(to_py_err)?.is_dir())
+    }
+}
+
+#[pymethods]
+impl PyExt2 {
+    #[new]
+    fn new(path: &str) -> PyResult<PyExt2> {
+        let bytes = std::fs::read(path)?;
+        let ext2 = Ext2::options()
+            .read_only(true)
+            .open(bytes)
+            .map_err(|err| PyValueError::new_err(format!("{}: {}", path, err)))?;
+        Ok(PyExt2 { ext2 })
+    }
+
+    /// The entries of the directory at `path`, without "." and "..".
+    #[pyo3(signature = (path = "/"))]
+    fn listdir(&self, path: &str) -> PyResult<Vec<DirEntry>> {
+        let dir = self.resolve(path)?;
+        if !self.is_dir(dir)? {
+            return Err(to_py_err(Ext2Error::NotADirectory {
+                name: path.to_string(),
+            }));
+        }
+        let mut listing = Vec::new();
+        for (inode, name) in self.entries(dir)? {
+            let record = self.ext2().get_inode(inode).map_err(to_py_err)?;
+            listing.push(DirEntry {
+                path: join(path, &name),
+                name,
+                inode,
+                file_type: record.type_name(),
+            });
+        }
+        Ok(listing)
+    }
+
+    /// `size` bytes of a file's contents from `offset`, or all of them to the
+    /// end if `size` is None. The file is a path or an inode number.
+    #[pyo3(signature = (target, offset = 0, size = None))]
+    fn read<'py>(
+        &self,
+        py: Python<'py>,
+        target: Target,
+        offset: u64,
+        size: Option<usize>,
+    ) -> PyResult<&'py PyBytes> {
+        let (inode, name) = match target {
+            Target::Inode(inode) => (inode, inode.to_string()),
+            Target::Path(path) => (self.resolve(&path)?, path),
+        };
+        let record = self.ext2().get_inode(inode).map_err(to_py_err)?;
+        if record.is_dir() {
+            return Err(to_py_err(Ext2Error::IsADirectory { name }));
+        }
+        let size = size.unwrap_or(record.size().saturating_sub(offset) as usize);
+        let mut buf = vec![0; size];
+        let len = self
+            .ext2()
+            .read_at(inode, offset, &mut buf)
+            .map_err(to_py_err)?;
+        Ok(PyBytes::new(py, &buf[..len]))
+    }
+
+    /// The inode at `path` as a dict, with the same keys as `istat --json`.
+    fn stat<'py>(&self, py: Python<'py>, path: &str) -> PyResult<&'py PyDict> {
+        let inode = self.resolve(path)?;
+        let record = self.ext2().get_inode(inode).map_err(to_py_err)?;
+        let info = InodeInfo::new(inode, record);
+        let dict = PyDict::new(py);
+        dict.set_item("inode", info.inode)?;
+        dict.set_item("type", info.file_type)?;
+        dict.set_item("mode", info.mode)?;
+        dict.set_item("uid", info.uid)?;
+        dict.set_item("gid", info.gid)?;
+        dict.set_item("size", info.size)?;
+        dict.set_item("links", info.links)?;
+        dict.set_item("sectors", info.sectors)?;
+        dict.set_item("flags", info.flags)?;
+        dict.set_item("generation", info.generation)?;
+        dict.set_item("file_acl", info.file_acl)?;
+        dict.set_item("device", info.device)?;
+        dict.set_item("atime", info.atime)?;
+        dict.set_item("ctime", info.ctime)?;
+        dict.set_item("mtime", info.mtime)?;
+        dict.set_item("dtime", info.dtime)?;
+        dict.set_item("direct_blocks", info.direct_blocks.to_vec())?;
+        dict.set_item("indirect_block", info.indirect_block)?;
+        dict.set_item("doubly_indirect_block", info.doubly_indirect_block)?;
+        dict.set_item("triply_indirect_block", info.triply_indirect_block)?;
+        Ok(dict)
+    }
+
+    /// The target of the symlink at `path`, as bytes. Like `os.readlink`,
+    /// raises OSError with EINVAL if it isn't a symlink.
+    fn readlink<'py>(&self, py: Python<'py>, path: &str) -> PyResult<&'py PyBytes> {
+        let inode = self.resolve(path)?;
+        let record = self.ext2().get_inode(inode).map_err(to_py_err)?;
+        if !record.is_symlink() {
+            let message = format!("{}: Not a symbolic link", path);
+            return Err(PyOSError::new_err((libc::EINVAL, message)));
+        }
+        let target = self.ext2().read_link(inode).map_err(to_py_err)?;
+        Ok(PyBytes::new(py, &target))
+    }
+
+    /// `(dirpath, dirnames, filenames)` for the directory at `path` and each
+    /// one below it, top down, like `os.walk`. Symlinks are listed in
+    /// `filenames` and not followed; removing names from `dirnames` keeps
+    /// the walk out of them.
+    #[pyo3(signature = (path = "/"))]
+    fn walk(slf: Py<PyExt2>, py: Python<'_>, path: &str) -> PyResult<Walk> {
+        let inode = slf.borrow(py).resolve(path)?;
+        Ok(Walk {
+            fs: slf,
+            pending: vec![(path.to_string(), inode)],
+            visited: HashSet::new(),
+            last: None,
+        })
+    }
+}
+
+/// One entry of `Ext2.listdir`.
+#[pyclass(module = "ext2fs")]
+struct DirEntry {
+    #[pyo3(get)]
+    name: String,
+    /// The entry's path, the directory listed joined with `name`
+    #[pyo3(get)]
+    path: String,
+    #[pyo3(get)]
+    inode: usize,
+    file_type: &'static str,
+}
+
+#[pymethods]
+impl DirEntry {
+    /// e.g. "regular file" or "directory"
+    #[getter]
+    fn r#type(&self) -> &'static str {
+        self.file_type
+    }
+
+    fn is_dir(&self) -> bool {
+        self.file_type == "directory"
+    }
+
+    fn is_file(&self) -> bool {
+        self.file_type == "regular file"
+    }
+
+    fn is_symlink(&self) -> bool {
+        self.file_type == "symbolic link"
+    }
+
+    fn __repr__(&self) -> String {
+        format!("<DirEntry {:?} inode {}>", self.name, self.inode)
+    }
+}
+
+/// The iterator `Ext2.walk` returns.
+#[pyclass(module = "ext2fs")]
+struct Walk {
+    fs: Py<PyExt2>,
+    /// Directories still to list, the next one last
+    pending: Vec<(String, usize)>,
+    /// Directories already listed, so a damaged image with a directory
+    /// linked below itself can't make the walk go round forever
+    visited: HashSet<usize>,
+    /// The last directory yielded, whose subdirectories are queued on the
+    /// next step, after the caller has had the chance to prune them
+    last: Option<WalkStep>,
+}
+
+struct WalkStep {
+    dirpath: String,
+    /// The list yielded as `dirnames`, as the caller left it
+    dirnames: Py<PyList>,
+    /// Every subdirectory's name and inode
+    subdirs: Vec<(String, usize)>,
+}
+
+#[pymethods]
+impl Walk {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(&mut self, py: Python<'_>) -> PyResult<Option<PyObject>> {
+        if let Some(WalkStep {
+            dirpath,
+            dirnames,
+            subdirs,
+        }) = self.last.take()
+        {
+            let kept: Vec<String> = dirnames.as_ref(py).extract()?;
+            for (name, inode) in subdirs.into_iter().rev() {
+                if kept.contains(&name) {
+                    self.pending.push((join(&dirpath, &name), inode));
+                }
+            }
+        }
+        let fs = self.fs.borrow(py);
+        while let Some((dirpath, dir)) = self.pending.pop() {
+            if !self.visited.insert(dir) {
+                continue;
+            }
+            let mut subdirs = Vec::new();
+            let mut filenames = Vec::new();
+            for (inode, name) in fs.entries(dir)? {
+                if fs.is_dir(inode)? {
+                    subdirs.push((name, inode));
+                } else {
+                    filenames.push(name);
+                }
+            }
+            let names: Vec<&String> = subdirs.iter().map(|(name, _)| name).collect();
+            let dirnames: Py<PyList> = PyList::new(py, names).into();
+            let step = (dirpath.clone(), dirnames.clone_ref(py), filenames).into_py(py);
+            self.last = Some(WalkStep {
+                dirpath,
+                dirnames,
+                subdirs,
+            });
+            return Ok(Some(step));
+        }
+        Ok(None)
+    }
+}
+
+// `name` in directory `dir`, without doubling the '/' after the root
+fn join(dir: &str, name: &str) -> String {
+    if dir.ends_with('/') {
+        format!("{}{}", dir, name)
+    } else {
+        format!("{}/{}", dir, name)
+    }
+}
+
+#[pymodule]
+fn ext2fs(_py: Python<'_>, module: &PyModule) -> PyResult<()> {
+    module.add_class::<PyExt2>()?;
+    module.add_class::<DirEntry>()?;
+    module.add_class::<Walk>()?;
+    Ok(())
+}
@@ -0,0 +1,284 @@
+// Removing files and directories.
+//
+// An entry is unlinked by growing the entry before it in the block over it,
+// or, for the first entry of a block, by zeroing its inode number, the way
+// the kernel does; an indexed directory's hash tree stays valid, since it
+// only says which block a name is in. Once nothing links to an inode it's
+// freed along with its blocks, leaving its block pointers in place, which is
+// what `undelete` relies on.
+//
+// Every removal is whole on its own: the entry, the link counts and the
+// bitmaps are all updated before the next one starts, so a tree removal that
+// stops part way leaves a consistent filesystem with some of the tree gone.
+
+use crate::structs::Inode;
+use crate::{
+    rec_len_from_disk, rec_len_to_disk, DirEntry, Ext2, Ext2Error, Result, WalkControl, WalkOptions,
+};
+use log::{debug, info};
+use std::collections::{HashMap, HashSet};
+
+// where a directory entry is: the block it's in, its offset and size there,
+// and the offset of the entry before it in the block, if it isn't the first
+pub(crate) struct EntrySlot {
+    pub block: usize,
+    pub offset: usize,
+    pub size: usize,
+    pub previous: Option<usize>,
+}
+
+impl Ext2 {
+    /// Remove the entry `name` from directory `dir`, like unlink(2): the
+    /// inode it links to loses a link, and is deleted along with its blocks
+    /// once it has none left and isn't open (see `open_file`). A symlink is
+    /// removed itself, never what it points to. Directories are refused; see
+    /// `remove_dir`.
+    pub fn unlink(&mut self, dir: usize, name: &str) -> Result<()> {
+        self.check_writable()?;
+        let inode = self.entry_to_remove(dir, name)?;
+        if self.get_inode(inode)?.is_dir() {
+            return Err(Ext2Error::IsADirectory {
+                name: name.to_string(),
+            });
+        }
+        self.check_unlink(dir, inode)?;
+        if self.get_inode(inode)?.hard_links <= 1 {
+            self.blocks_to_free(inode)?;
+        }
+        self.remove_dir_entry(dir, name)?;
+        let now = self.now();
+        let record = self.inode_mut(inode)?;
+        record.hard_links = record.hard_links.saturating_sub(1);
+        record.ctime = now;
+        if record.hard_links == 0 {
+            self.delete_inode(inode)?;
+        }
+        info!("unlinked {} (inode {}) from inode {}", name, inode, dir);
+        Ok(())
+    }
+
+    /// Remove the empty directory `name` from directory `dir`, like rmdir(2).
+    pub fn remove_dir(&mut self, dir: usize, name: &str) -> Result<()> {
+        self.check_writable()?;
+        let inode = self.entry_to_remove(dir, name)?;
+        if !self.get_inode(inode)?.is_dir() {
+            return Err(Ext2Error::NotADirectory {
+                name: name.to_string(),
+            });
+        }
+        let is_empty = self.read_dir_inode(inode)?.iter().all(DirEntry::is_dot);
+        if !is_empty {
+            return Err(Ext2Error::NotEmpty {
+                name: name.to_string(),
+            });
+        }
+        self.check_unlink(dir, inode)?;
+        self.blocks_to_free(inode)?;
+        self.remove_dir_entry(dir, name)?;
+        // its `..` linked to the parent
+        let record = self.inode_mut(dir)?;
+        record.hard_links = record.hard_links.saturating_sub(1);
+        self.inode_mut(inode)?.hard_links = 0;
+        self.delete_inode(inode)?;
+        info!(
+            "removed directory {} (inode {}) from inode {}",
+            name, inode, dir
+        );
+        Ok(())
+    }
+
+    /// Remove `name` from directory `dir` and, if it's a directory,
+    /// everything under it, deepest first, like `rm -r`. Returns how many
+    /// entries were removed.
+    ///
+    /// `confirm` is asked about each entry, with its path from `dir` and its
+    /// inode, before it's removed; whatever it turns down stays, and so do
+    /// the directories above it. Symlinks are removed, never followed, and a
+    /// file with other links outside the tree keeps its data. The removal
+    /// stops at the first entry that can't be removed, with everything
+    /// removed until then staying removed.
+    pub fn remove_tree(
+        &mut self,
+        dir: usize,
+        name: &str,
+        mut confirm: impl FnMut(&str, &Inode) -> bool,
+    ) -> Result<usize> {
+        self.check_writable()?;
+        let top = self.entry_to_remove(dir, name)?;
+
+        // (directory, name, inode, path) of everything to remove, each
+        // directory before what's in it
+        let mut entries = vec![(dir, name.to_string(), top, name.to_string())];
+        if self.get_inode(top)?.is_dir() {
+            let mut dirs = HashMap::from([(String::new(), top)]);
+            self.walk(top, &WalkOptions::new(), &mut |entry| {
+                let parent = dirs[&entry.parents.join("/")];
+                let path = entry.path();
+                if entry.record.is_dir() {
+                    dirs.insert(path.clone(), entry.inode);
+                }
+                entries.push((
+                    parent,
+                    entry.name.to_string(),
+                    entry.inode,
+                    format!("{}/{}", name, path),
+                ));
+                WalkControl::Continue
+            })?;
+        }
+
+        let mut removed = 0;
+        // directories left holding something `confirm` turned down
+        let mut kept = HashSet::new();
+        for (parent, name, inode, path) in entries.into_iter().rev() {
+            if kept.contains(&inode) || !confirm(&path, self.get_inode(inode)?) {
+                kept.insert(parent);
+                continue;
+            }
+            if self.get_inode(inode)?.is_dir() {
+                self.remove_dir(parent, &name)?;
+            } else {
+                self.unlink(parent, &name)?;
+            }
+            removed += 1;
+        }
+        Ok(removed)
+    }
+
+    // the inode entry `name` of directory `dir` links to, if it's something
+    // that may be removed
+    pub(crate) fn entry_to_remove(&self, dir: usize, name: &str) -> Result<usize> {
+        if name == "." || name == ".." || name.contains('/') {
+            return Err(Ext2Error::InvalidName {
+                name: name.to_string(),
+            });
+        }
+        self.lookup(dir, name)?.ok_or_else(|| Ext2Error::NotFound {
+            name: name.to_string(),
+        })
+    }
+
+    // take the entry `name` out of directory `dir`, leaving the inode it
+    // linked to alone
+    pub(crate) fn remove_dir_entry(&mut self, dir: usize, name: &str) -> Result<()> {
+        let Some(found) = self.find_dir_entry(dir, name)? else {
+            return Err(Ext2Error::NotFound {
+                name: name.to_string(),
+            });
+        };
+        let block = self.block_mut(found.block)?;
+        match found.previous {
+            Some(previous) => {
+                let merged = found.offset + found.size - previous;
+                block[previous + 4..previous + 6].copy_from_slice(&rec_len_to_disk(merged));
+            }
+            None => block[found.offset..found.offset + 4].fill(0),
+        }
+        debug!("removed entry {} from block {}", name, found.block);
+        self.touch_modified(dir)
+    }
+
+    // where the entry `name` of directory `dir` is stored: the entry `lookup`
+    // finds, so with `case_insensitive_lookup` one whose name differs only
+    // by case, if there's no exact match
+    pub(crate) fn find_dir_entry(&self, dir: usize, name: &str) -> Result<Option<EntrySlot>> {
+        if let Some(found) = self.find_dir_entry_bytes(dir, name.as_bytes())? {
+            return Ok(Some(found));
+        }
+        if !self.case_insensitive_lookup() || name == "." || name == ".." {
+            return Ok(None);
+        }
+        match self.caseless_match(dir, name)? {
+            Some((_, on_disk)) => self.find_dir_entry_bytes(dir, &on_disk),
+            None => Ok(None),
+        }
+    }
+
+    // where the entry named exactly `name` of directory `dir` is stored
+    fn find_dir_entry_bytes(&self, dir: usize, name: &[u8]) -> Result<Option<EntrySlot>> {
+        for block_num in self.file_blocks(dir)? {
+            let block_num = block_num?;
+            if block_num == 0 {
+                continue;
+            }
+            let block = self.block(block_num)?;
+            let mut offset = 0;
+            let mut previous = None;
+            while offset + 8 <= block.len() {
+                let entry_inode = u32::from_le_bytes(block[offset..offset + 4].try_into().unwrap());
+                let entry_size =
+                    rec_len_from_disk([block[offset + 4], block[offset + 5]], block.len());
+                if entry_size < 8 || offset + entry_size > block.len() {
+                    // corrupt; reading the directory reports it
+                    break;
+                }
+                let name_len = block[offset + 6] as usize;
+                let entry_name = block.get(offset + 8..offset + 8 + name_len);
+                if entry_inode != 0 && entry_name == Some(name) {
+                    return Ok(Some(EntrySlot {
+                        block: block_num,
+                        offset,
+                        size: entry_size,
+                        previous,
+                    }));
+                }
+                previous = Some(offset);
+                offset += entry_size;
+            }
+        }
+        Ok(None)
+    }
+
+    // free `inode`, which nothing links to any more, and every block it owns
+    pub(crate) fn delete_inode(&mut self, inode: usize) -> Result<()> {
+        if self.open_count(inode) > 0 {
+            // `close_file` deletes it when the last reader is closed
+            debug!("inode {} has no links left but is still open", inode);
+            return Ok(());
+        }
+        let record = self.get_inode(inode)?;
+        let xattr_block = record.ext_attribute_block as usize;
+        let is_dir = record.is_dir();
+        let blocks = self.blocks_to_free(inode)?;
+        for &block in &blocks {
+            if block == xattr_block {
+                self.release_xattr_block(block)?;
+            } else {
+                self.free_block(block)?;
+            }
+        }
+        self.free_inode(inode, is_dir)?;
+        let now = self.now();
+        let record = self.inode_mut(inode)?;
+        record.dtime = now;
+        record.ctime = now;
+        debug!("deleted inode {} ({} blocks)", inode, blocks.len());
+        Ok(())
+    }
+
+    // the blocks `delete_inode` frees for `inode`: every one it owns, each
+    // checked to be in the filesystem, `BlockOutOfRange` if one isn't, or
+    // left out with a warning under `Strictness::Lenient`. `unlink` and
+    // `remove_dir` call it before changing anything, so a corrupt pointer
+    // fails the removal whole
+    fn blocks_to_free(&self, inode: usize) -> Result<Vec<usize>> {
+        let sb = &self.superblock;
+        let in_range = sb.first_data_block as usize..sb.blocks_count as usize;
+        let mut blocks = self.owned_blocks(inode)?;
+        for &block in &blocks {
+            if in_range.contains(&block) {
+                continue;
+            }
+            let err = Ext2Error::BlockOutOfRange {
+                block,
+                blocks_count: sb.blocks_count as usize,
+            }
+            .in_inode("deleting", inode);
+            if !self.tolerate(&err) {
+                return Err(err);
+            }
+        }
+        blocks.retain(|block| in_range.contains(block));
+        Ok(blocks)
+    }
+}
@@ -0,0 +1,145 @@
+// Renaming and moving entries, with rename(2)'s semantics.
+//
+// The new entry always goes in before the old one comes out: a replaced
+// destination entry is pointed at the source inode in place, or a new entry
+// is added, and only then is the source entry removed. With `sync` writing
+// blocks in the order they were modified, an interrupted sync can leave the
+// file with both names, but never with neither.
+
+use crate::write::check_name;
+use crate::{DirEntry, Ext2, Ext2Error, Result};
+use log::info;
+
+impl Ext2 {
+    /// Rename entry `old_name` of directory `old_parent` to `new_name` in
+    /// directory `new_parent`, like rename(2). An existing destination is
+    /// replaced if it's a file and the source is too, or if it's an empty
+    /// directory and the source is a directory; anything else is an error,
+    /// as is moving a directory below itself. Renaming an entry onto another
+    /// name for the same inode does nothing.
+    pub fn rename(
+        &mut self,
+        old_parent: usize,
+        old_name: &str,
+        new_parent: usize,
+        new_name: &str,
+    ) -> Result<()> {
+        self.check_writable()?;
+        let source = self.entry_to_remove(old_parent, old_name)?;
+        check_name(new_name)?;
+        if !self.get_inode(new_parent)?.is_dir() {
+            return Err(Ext2Error::NotADirectory {
+                name: format!("inode {}", new_parent),
+            });
+        }
+        self.check_unlink(old_parent, source)?;
+        let record = self.get_inode(source)?;
+        let mode = record.type_perm.bits();
+        let is_dir = record.is_dir();
+        if is_dir && self.is_below(new_parent, source)? {
+            return Err(Ext2Error::IntoItself {
+                name: new_name.to_string(),
+            });
+        }
+
+        let replaced = self.lookup(new_parent, new_name)?;
+        if let Some(dest) = replaced {
+            if dest == source {
+                return Ok(());
+            }
+            let dest_is_dir = self.get_inode(dest)?.is_dir();
+            if is_dir && !dest_is_dir {
+                return Err(Ext2Error::NotADirectory {
+                    name: new_name.to_string(),
+                });
+            }
+            if !is_dir && dest_is_dir {
+                return Err(Ext2Error::IsADirectory {
+                    name: new_name.to_string(),
+                });
+            }
+            let dest_is_empty =
+                !dest_is_dir || self.read_dir_inode(dest)?.iter().all(DirEntry::is_dot);
+            if !dest_is_empty {
+                return Err(Ext2Error::NotEmpty {
+                    name: new_name.to_string(),
+                });
+            }
+            self.check_unlink(new_parent, dest)?;
+
+            // point the destination entry at the source, then let go of
+            // what it pointed at
+            let found =
+                self.find_dir_entry(new_parent, new_name)?
+                    .ok_or_else(|| Ext2Error::NotFound {
+                        name: new_name.to_string(),
+                    })?;
+            let type_byte = self.entry_type(mode);
+            let block = self.block_mut(found.block)?;
+            block[found.offset..found.offset + 4].copy_from_slice(&(source as u32).to_le_bytes());
+            block[found.offset + 7] = type_byte;
+            self.touch_modified(new_parent)?;
+            let now = self.now();
+            if dest_is_dir {
+                // its `..` linked to the new parent
+                let record = self.inode_mut(new_parent)?;
+                record.hard_links = record.hard_links.saturating_sub(1);
+                self.inode_mut(dest)?.hard_links = 0;
+                self.delete_inode(dest)?;
+            } else {
+                let record = self.inode_mut(dest)?;
+                record.hard_links = record.hard_links.saturating_sub(1);
+                record.ctime = now;
+                if record.hard_links == 0 {
+                    self.delete_inode(dest)?;
+                }
+            }
+        } else {
+            self.check_new_name(new_parent, new_name)?;
+            self.add_dir_entry(new_parent, new_name, source, mode)?;
+        }
+        self.remove_dir_entry(old_parent, old_name)?;
+
+        if is_dir && old_parent != new_parent {
+            self.set_parent(source, new_parent)?;
+            let record = self.inode_mut(old_parent)?;
+            record.hard_links = record.hard_links.saturating_sub(1);
+            self.inode_mut(new_parent)?.hard_links += 1;
+        }
+        let now = self.now();
+        self.inode_mut(source)?.ctime = now;
+        info!(
+            "renamed {} in inode {} to {} in inode {}{}",
+            old_name,
+            old_parent,
+            new_name,
+            new_parent,
+            match replaced {
+                Some(dest) => format!(", replacing inode {}", dest),
+                None => String::new(),
+            }
+        );
+        Ok(())
+    }
+
+    // whether directory `dir` is `ancestor` or somewhere below it, going up
+    // through the `..` entries to the root
+    fn is_below(&self, dir: usize, ancestor: usize) -> Result<bool> {
+        let mut current = dir;
+        // a damaged tree can loop; no real one is deeper than it has inodes
+        for _ in 0..self.superblock.inodes_count {
+            if current == ancestor {
+                return Ok(true);
+            }
+            if current == 2 {
+                return Ok(false);
+            }
+            current = self
+                .lookup(current, "..")?
+                .ok_or_else(|| Ext2Error::NotFound {
+                    name: String::from(".."),
+                })?;
+        }
+        Ok(false)
+    }
+}
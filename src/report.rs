@@ -0,0 +1,311 @@
+// Owned, serializable reports of what the shell lists, for `--json` output.
+//
+// The on-disk structures are views straight into the image, packed and
+// full of fields only meaningful next to others, so they're copied out into
+// these plain structs rather than serialized as they are. Times are POSIX
+// timestamps, as stored.
+
+use crate::access::{group, owner};
+use crate::structs::{FeatureCompat, FeatureIncompat, FeatureRoCompat, Inode};
+use crate::{c_string, Ext2, JournalInfo};
+use serde::Serialize;
+use std::fmt;
+
+/// A directory entry and the inode it links to, as `ls` lists it.
+#[derive(Debug, Clone, Serialize)]
+pub struct EntryInfo {
+    pub name: String,
+    pub inode: usize,
+    /// e.g. "regular file" or "directory", from `Inode::type_name`
+    #[serde(rename = "type")]
+    pub file_type: &'static str,
+    /// The permission bits, with setuid, setgid and sticky
+    pub mode: u16,
+    pub uid: u32,
+    pub gid: u32,
+    pub links: u16,
+    pub size: u64,
+    /// (major, minor) for device nodes
+    pub device: Option<(u32, u32)>,
+    pub atime: u32,
+    pub ctime: u32,
+    pub mtime: u32,
+}
+
+impl EntryInfo {
+    pub fn new(name: &str, inode: usize, record: &Inode) -> EntryInfo {
+        EntryInfo {
+            name: name.to_string(),
+            inode,
+            file_type: record.type_name(),
+            mode: record.permissions(),
+            uid: owner(record),
+            gid: group(record),
+            links: record.hard_links,
+            size: record.size(),
+            device: record.device(),
+            atime: record.atime,
+            ctime: record.ctime,
+            mtime: record.mtime,
+        }
+    }
+}
+
+/// Every field of an inode, as `istat` shows them.
+#[derive(Debug, Clone, Serialize)]
+pub struct InodeInfo {
+    pub inode: usize,
+    #[serde(rename = "type")]
+    pub file_type: &'static str,
+    pub mode: u16,
+    pub uid: u32,
+    pub gid: u32,
+    pub size: u64,
+    pub links: u16,
+    pub sectors: u32,
+    pub flags: u32,
+    pub generation: u32,
+    pub file_acl: u32,
+    pub fragment_address: u32,
+    pub device: Option<(u32, u32)>,
+    pub atime: u32,
+    pub ctime: u32,
+    pub mtime: u32,
+    pub dtime: u32,
+    pub direct_blocks: [u32; 12],
+    pub indirect_block: u32,
+    pub doubly_indirect_block: u32,
+    pub triply_indirect_block: u32,
+}
+
+impl InodeInfo {
+    pub fn new(inode: usize, record: &Inode) -> InodeInfo {
+        InodeInfo {
+            inode,
+            file_type: record.type_name(),
+            mode: record.permissions(),
+            uid: owner(record),
+            gid: group(record),
+            size: record.size(),
+            links: record.hard_links,
+            sectors: record.sectors_count,
+            flags: record.flags,
+            generation: record.gen_number,
+            file_acl: record.ext_attribute_block,
+            fragment_address: record.frag_block_addr,
+            device: record.device(),
+            atime: record.atime,
+            ctime: record.ctime,
+            mtime: record.mtime,
+            dtime: record.dtime,
+            direct_blocks: record.direct_pointer,
+            indirect_block: record.indirect_pointer,
+            doubly_indirect_block: record.doubly_indirect,
+            triply_indirect_block: record.triply_indirect,
+        }
+    }
+}
+
+/// A report on what's at a path, as `stat` and `find` print them: the
+/// fields of `info`, with `path` alongside.
+#[derive(Debug, Clone, Serialize)]
+pub struct WithPath<T> {
+    pub path: String,
+    #[serde(flatten)]
+    pub info: T,
+}
+
+/// Space and inode usage, as `df` shows it. Block counts are in KiB.
+#[derive(Debug, Clone, Serialize)]
+pub struct SpaceInfo {
+    pub total_kib: u64,
+    pub used_kib: u64,
+    /// What the current user can still allocate, see `Ext2::available_blocks`
+    pub available_kib: u64,
+    pub reserved_kib: u64,
+    pub inodes: u32,
+    pub used_inodes: u32,
+    pub free_inodes: u32,
+    /// Inodes below the first one files may use
+    pub reserved_inodes: u32,
+}
+
+/// The superblock and block group descriptors, as `fsinfo` shows them.
+#[derive(Debug, Clone, Serialize)]
+pub struct FsInfo {
+    pub volume_name: String,
+    pub uuid: String,
+    pub revision: String,
+    pub creator_os: &'static str,
+    pub last_mounted_on: String,
+    /// The names of the optional, required and read-only feature flags set
+    pub features_optional: Vec<String>,
+    pub features_required: Vec<String>,
+    pub features_read_only: Vec<String>,
+    /// Only for ext3 filesystems
+    pub journal: Option<JournalInfo>,
+    pub block_size: usize,
+    pub fragment_size: usize,
+    pub inode_size: u16,
+    pub blocks_count: u32,
+    pub reserved_blocks_count: u32,
+    pub free_blocks: u32,
+    pub inodes_count: u32,
+    pub free_inodes: u32,
+    pub first_data_block: u32,
+    pub first_inode: u32,
+    pub blocks_per_group: u32,
+    pub fragments_per_group: u32,
+    pub inodes_per_group: u32,
+    pub mount_count: u16,
+    pub max_mount_count: i16,
+    pub last_mount_time: u32,
+    pub last_write_time: u32,
+    pub last_checked: u32,
+    pub check_interval: u32,
+    pub state: &'static str,
+    pub errors_behavior: &'static str,
+    pub groups: Vec<GroupInfo>,
+}
+
+/// One block group's line of the `fsinfo` table.
+#[derive(Debug, Clone, Serialize)]
+pub struct GroupInfo {
+    pub group: usize,
+    pub first_block: usize,
+    pub last_block: usize,
+    pub block_bitmap: u32,
+    pub inode_bitmap: u32,
+    pub inode_table: u32,
+    pub free_blocks: u16,
+    pub free_inodes: u16,
+    pub dirs: u16,
+}
+
+impl Ext2 {
+    /// How much space and how many inodes are used and left.
+    pub fn space_info(&self) -> SpaceInfo {
+        let sb = &self.superblock;
+        let kib = |blocks: u32| blocks as u64 * self.block_size as u64 / 1024;
+        SpaceInfo {
+            total_kib: kib(sb.blocks_count),
+            used_kib: kib(sb.blocks_count - sb.free_blocks_count),
+            available_kib: kib(self.available_blocks()),
+            reserved_kib: kib(sb.r_blocks_count),
+            inodes: sb.inodes_count,
+            used_inodes: sb.inodes_count - sb.free_inodes_count,
+            free_inodes: sb.free_inodes_count,
+            reserved_inodes: self.first_usable_inode() as u32 - 1,
+        }
+    }
+
+    /// The superblock and block group descriptors, the same as `describe`.
+    pub fn fs_info(&self) -> FsInfo {
+        let sb = &self.superblock;
+        let groups = self
+            .block_groups
+            .iter()
+            .enumerate()
+            .map(|(i, group)| {
+                let first = sb.first_data_block as usize + i * sb.blocks_per_group as usize;
+                let last = (first + sb.blocks_per_group as usize).min(sb.blocks_count as usize) - 1;
+                GroupInfo {
+                    group: i,
+                    first_block: first,
+                    last_block: last,
+                    block_bitmap: group.block_usage_addr,
+                    inode_bitmap: group.inode_usage_addr,
+                    inode_table: group.inode_table_block,
+                    free_blocks: group.free_blocks_count,
+                    free_inodes: group.free_inodes_count,
+                    dirs: group.dirs_count,
+                }
+            })
+            .collect();
+        FsInfo {
+            volume_name: c_string(&sb.volume_name),
+            uuid: self.uuid.to_string(),
+            revision: format!("{}.{}", sb.rev_major, sb.rev_minor),
+            creator_os: creator_os(sb.creator_os),
+            last_mounted_on: c_string(&sb.last_mnt_path),
+            features_optional: flag_names(
+                FeatureCompat::from_bits_truncate(sb.features_opt),
+                sb.features_opt & !FeatureCompat::all().bits(),
+            ),
+            features_required: flag_names(
+                FeatureIncompat::from_bits_truncate(sb.features_req),
+                sb.features_req & !FeatureIncompat::all().bits(),
+            ),
+            features_read_only: flag_names(
+                FeatureRoCompat::from_bits_truncate(sb.features_ronly),
+                sb.features_ronly & !FeatureRoCompat::all().bits(),
+            ),
+            journal: self.journal(),
+            block_size: self.block_size,
+            fragment_size: 1024 << sb.log_frag_size,
+            inode_size: sb.inode_size,
+            blocks_count: sb.blocks_count,
+            reserved_blocks_count: sb.r_blocks_count,
+            free_blocks: sb.free_blocks_count,
+            inodes_count: sb.inodes_count,
+            free_inodes: sb.free_inodes_count,
+            first_data_block: sb.first_data_block,
+            first_inode: sb.first_inode,
+            blocks_per_group: sb.blocks_per_group,
+            fragments_per_group: sb.frags_per_group,
+            inodes_per_group: sb.inodes_per_group,
+            mount_count: sb.mnt_count,
+            max_mount_count: sb.max_mnt_count,
+            last_mount_time: sb.mtime,
+            last_write_time: sb.wtime,
+            last_checked: sb.lastcheck,
+            check_interval: sb.checkinterval,
+            state: fs_state(sb.state),
+            errors_behavior: errors_behavior(sb.errors),
+            groups,
+        }
+    }
+}
+
+pub(crate) fn creator_os(os: u32) -> &'static str {
+    match os {
+        0 => "Linux",
+        1 => "GNU HURD",
+        2 => "MASIX",
+        3 => "FreeBSD",
+        4 => "Lites",
+        _ => "unknown",
+    }
+}
+
+pub(crate) fn fs_state(state: u16) -> &'static str {
+    match state {
+        0 => "not clean (in use)",
+        1 => "clean",
+        2 => "has errors",
+        _ => "unknown",
+    }
+}
+
+pub(crate) fn errors_behavior(errors: u16) -> &'static str {
+    match errors {
+        1 => "continue",
+        2 => "remount read-only",
+        3 => "panic",
+        _ => "unknown",
+    }
+}
+
+// the names of the set flags of a feature bitfield, plus any bits we don't
+// know as one hex number
+fn flag_names<F: fmt::Debug>(known: F, unknown: u32) -> Vec<String> {
+    let mut names: Vec<String> = format!("{:?}", known)
+        .split(" | ")
+        .filter(|name| *name != "(empty)")
+        .map(str::to_string)
+        .collect();
+    if unknown != 0 {
+        names.push(format!("{:#x}", unknown));
+    }
+    names
+}
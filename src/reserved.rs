@@ -0,0 +1,121 @@
+// The inodes below the first usable one, which ext2 keeps for itself. Only
+// a few of them are ever used, and which depends on the features: the bad
+// blocks list and the root always, the resize inode with `resize_inode`, the
+// journal on ext3. The rest were set aside for things that never shipped or
+// only exist in ext4, but mke2fs still marks all of them allocated.
+// https://www.kernel.org/doc/html/latest/filesystems/ext4/special_inodes.html
+
+use crate::{Ext2, Result};
+
+pub(crate) const BAD_BLOCKS_INODE: usize = 1;
+pub(crate) const RESIZE_INODE: usize = 7;
+
+/// What one of the reserved inodes is for, by convention.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReservedInode {
+    pub inode: usize,
+    /// A short name for it, e.g. "bad blocks"
+    pub role: &'static str,
+    pub description: &'static str,
+}
+
+/// The reserved inodes every ext2 filesystem has, in order.
+pub const RESERVED_INODES: [ReservedInode; 10] = [
+    ReservedInode {
+        inode: BAD_BLOCKS_INODE,
+        role: "bad blocks",
+        description: "its data blocks are the blocks found unreadable",
+    },
+    ReservedInode {
+        inode: 2,
+        role: "root directory",
+        description: "the directory everything else is in",
+    },
+    ReservedInode {
+        inode: 3,
+        role: "ACL index",
+        description: "never used by ext2; the user quota file on ext4",
+    },
+    ReservedInode {
+        inode: 4,
+        role: "ACL data",
+        description: "never used by ext2; the group quota file on ext4",
+    },
+    ReservedInode {
+        inode: 5,
+        role: "boot loader",
+        description: "for a boot loader to keep itself in",
+    },
+    ReservedInode {
+        inode: 6,
+        role: "undelete directory",
+        description: "set aside for undeletion, never used",
+    },
+    ReservedInode {
+        inode: RESIZE_INODE,
+        role: "resize inode",
+        description: "owns the blocks kept for growing the group descriptor table",
+    },
+    ReservedInode {
+        inode: 8,
+        role: "journal",
+        description: "the journal, on ext3",
+    },
+    ReservedInode {
+        inode: 9,
+        role: "exclude",
+        description: "the snapshot exclude bitmap of the ext3 snapshot patches",
+    },
+    ReservedInode {
+        inode: 10,
+        role: "replica",
+        description: "set aside for replicating metadata, never used",
+    },
+];
+
+impl ReservedInode {
+    /// The conventional role of `inode`, if it's one of the reserved ones
+    /// every filesystem has.
+    pub fn of(inode: usize) -> Option<&'static ReservedInode> {
+        RESERVED_INODES.get(inode.checked_sub(1)?)
+    }
+}
+
+/// A reserved inode and what's in it on a particular image, as
+/// `Ext2::reserved_inodes` finds it.
+#[derive(Debug, Clone)]
+pub struct ReservedInodeUse {
+    pub inode: usize,
+    /// What it's for, or `None` for one past inode 10 that the superblock's
+    /// first usable inode also keeps back
+    pub role: Option<&'static ReservedInode>,
+    /// Whether the inode bitmap says so, which for the reserved inodes it
+    /// mostly does whether they're used or not
+    pub allocated: bool,
+    /// Whether it actually holds anything: a file type, a size or blocks
+    pub in_use: bool,
+    pub size: u64,
+    /// How many blocks it has, counting indirect ones
+    pub blocks: usize,
+}
+
+impl Ext2 {
+    /// Every inode below the first usable one, with what it's for and
+    /// whether it's in use on this image.
+    pub fn reserved_inodes(&self) -> Result<Vec<ReservedInodeUse>> {
+        let mut reserved = Vec::new();
+        for inode in 1..self.first_usable_inode() {
+            let record = self.get_inode(inode)?;
+            let blocks = self.owned_blocks(inode).map_or(0, |blocks| blocks.len());
+            reserved.push(ReservedInodeUse {
+                inode,
+                role: ReservedInode::of(inode),
+                allocated: self.inode_is_allocated(inode)?,
+                in_use: record.type_perm.bits() != 0 || record.size() != 0 || blocks != 0,
+                size: record.size(),
+                blocks,
+            });
+        }
+        Ok(reserved)
+    }
+}
@@ -0,0 +1,203 @@
+// Growing a filesystem into space added to the end of its device, like an
+// offline `resize2fs` that only grows.
+//
+// The last group is filled out to `blocks_per_group` first, then whole new
+// groups are laid out after it the way mke2fs would have: a superblock and
+// descriptor table copy if sparse_super says the group gets one, then the
+// bitmaps and inode table. The descriptor table itself can only grow into
+// the reserved GDT blocks that follow it in every group with a copy, which
+// the resize inode (inode 7) keeps track of; without them the new groups'
+// descriptors have to fit in the table's existing blocks.
+
+use crate::reserved::RESIZE_INODE;
+use crate::structs::{BlockGroupDescriptor, FeatureCompat};
+use crate::{Bitmap, Ext2, Ext2Error, InodeNo, Result};
+use log::info;
+use std::mem;
+
+impl Ext2 {
+    /// How many whole blocks the device holds, which is how far
+    /// `resize_grow` can take the filesystem.
+    pub fn device_blocks(&self) -> usize {
+        (self.device().size() / self.block_size as u64) as usize
+    }
+
+    /// Grow the filesystem to `new_blocks_count` blocks of its device,
+    /// adding block groups as needed. A last group too small to hold its
+    /// own bitmaps and inode table is left off, like mke2fs does, so the
+    /// filesystem may end up a little smaller; returns the new block count.
+    pub fn resize_grow(&mut self, new_blocks_count: usize) -> Result<usize> {
+        self.check_writable()?;
+        let bad = |reason: String| Ext2Error::BadLayout { reason };
+        let old_count = self.superblock.blocks_count as usize;
+        if new_blocks_count <= old_count {
+            return Err(bad(format!(
+                "{} blocks isn't bigger than the current {}",
+                new_blocks_count, old_count
+            )));
+        }
+        if new_blocks_count > self.device_blocks() {
+            return Err(bad(format!(
+                "the device only has room for {} blocks",
+                self.device_blocks()
+            )));
+        }
+        if new_blocks_count > u32::MAX as usize {
+            return Err(bad(format!("{} blocks is too many", new_blocks_count)));
+        }
+        let block_size = self.block_size;
+        let first_data_block = self.superblock.first_data_block as usize;
+        let blocks_per_group = self.superblock.blocks_per_group as usize;
+        let inodes_per_group = self.superblock.inodes_per_group as usize;
+        let old_groups = self.block_groups.len();
+        let descriptors_per_block = block_size / mem::size_of::<BlockGroupDescriptor>();
+        let old_descriptor_blocks = old_groups.div_ceil(descriptors_per_block);
+        let inode_table_blocks =
+            (inodes_per_group * InodeNo::slot_size(&self.superblock)).div_ceil(block_size);
+
+        // settle on the group count, dropping a last group with no room for
+        // data; the descriptor table growing only ever makes groups bigger
+        let mut new_count = new_blocks_count;
+        let (groups, descriptor_blocks) = loop {
+            let groups = (new_count - first_data_block).div_ceil(blocks_per_group);
+            let descriptor_blocks = groups.div_ceil(descriptors_per_block);
+            let last = groups - 1;
+            let last_size = new_count - (first_data_block + last * blocks_per_group);
+            let copies = if self.group_has_superblock(last) {
+                1 + descriptor_blocks + self.superblock.reserved_gdt_blocks as usize
+            } else {
+                0
+            };
+            if last < old_groups || last_size > copies + 2 + inode_table_blocks {
+                break (groups, descriptor_blocks);
+            }
+            new_count = first_data_block + last * blocks_per_group;
+        };
+        if new_count <= old_count {
+            return Err(bad(format!(
+                "{} blocks isn't enough for another group",
+                new_blocks_count
+            )));
+        }
+        if groups * inodes_per_group > u32::MAX as usize {
+            return Err(bad(format!("{} groups' inodes is too many", groups)));
+        }
+        let grown_by = descriptor_blocks - old_descriptor_blocks;
+        if grown_by > 0 {
+            let resize_inode = FeatureCompat::from_bits_truncate(self.superblock.features_opt)
+                .contains(FeatureCompat::RESIZE_INODE);
+            if !resize_inode || grown_by > self.superblock.reserved_gdt_blocks as usize {
+                return Err(bad(format!(
+                    "{} groups' descriptors don't fit in the {} block(s) of the table",
+                    groups, old_descriptor_blocks
+                )));
+            }
+            // the table takes over the reserved blocks right after it
+            self.superblock.reserved_gdt_blocks -= grown_by as u16;
+        }
+
+        self.superblock.blocks_count = new_count as u32;
+        let mut added_free = 0;
+        // the old last group's bitmap has its missing blocks marked in use
+        let old_last = old_groups - 1;
+        let old_last_size = old_count - self.group_first_block(old_last);
+        let new_last_size = self.group_blocks_count(old_last);
+        let mut bitmap = self.block_bitmap_mut(old_last)?;
+        for index in old_last_size..new_last_size {
+            bitmap.clear(index);
+        }
+        let grown = (new_last_size - old_last_size) as u16;
+        self.block_groups[old_last].free_blocks_count += grown;
+        added_free += grown as usize;
+
+        for group in old_groups..groups {
+            let first = first_data_block + group * blocks_per_group;
+            let size = (new_count - first).min(blocks_per_group);
+            let copies = if self.group_has_superblock(group) {
+                1 + descriptor_blocks + self.superblock.reserved_gdt_blocks as usize
+            } else {
+                0
+            };
+            let overhead = copies + 2 + inode_table_blocks;
+            let block_bitmap = first + copies;
+            let inode_bitmap = block_bitmap + 1;
+            for block_num in first..first + overhead {
+                self.block_mut(block_num)?.fill(0);
+            }
+            // past the end of the group is marked in use, as mke2fs does
+            let mut bitmap = Bitmap::new(self.block_mut(block_bitmap)?, 8 * block_size);
+            for index in (0..overhead).chain(size..8 * block_size) {
+                bitmap.set(index);
+            }
+            let mut bitmap = Bitmap::new(self.block_mut(inode_bitmap)?, 8 * block_size);
+            for index in inodes_per_group..8 * block_size {
+                bitmap.set(index);
+            }
+            let mut descriptor: BlockGroupDescriptor = unsafe { mem::zeroed() };
+            descriptor.block_usage_addr = block_bitmap as u32;
+            descriptor.inode_usage_addr = inode_bitmap as u32;
+            descriptor.inode_table_block = (inode_bitmap + 1) as u32;
+            descriptor.free_blocks_count = (size - overhead) as u16;
+            descriptor.free_inodes_count = inodes_per_group as u16;
+            self.block_groups.push(descriptor);
+            added_free += size - overhead;
+        }
+
+        let added_inodes = (groups - old_groups) * inodes_per_group;
+        let sb = &mut self.superblock;
+        sb.inodes_count += added_inodes as u32;
+        sb.free_inodes_count += added_inodes as u32;
+        sb.free_blocks_count += added_free as u32;
+        // keep the same share of the blocks reserved
+        sb.r_blocks_count = (sb.r_blocks_count as u64 * new_count as u64 / old_count as u64) as u32;
+        if FeatureCompat::from_bits_truncate(sb.features_opt).contains(FeatureCompat::RESIZE_INODE)
+        {
+            self.rebuild_resize_inode()?;
+        }
+        self.write_metadata()?;
+        self.write_backups()?;
+        info!(
+            "grew the filesystem from {} to {} blocks, {} to {} groups",
+            old_count, new_count, old_groups, groups
+        );
+        Ok(new_count)
+    }
+
+    // point the resize inode at the reserved GDT blocks as they now are: its
+    // doubly indirect block lists those after the primary descriptor table,
+    // and each of those, taken as an indirect block, lists its copies in the
+    // groups with a backup, the way mke2fs lays it out
+    fn rebuild_resize_inode(&mut self) -> Result<()> {
+        let block_size = self.block_size;
+        let per_block = block_size / 4;
+        let blocks_per_group = self.superblock.blocks_per_group as usize;
+        let descriptor_blocks =
+            (self.block_groups.len() * mem::size_of::<BlockGroupDescriptor>()).div_ceil(block_size);
+        let dind = self.get_inode(RESIZE_INODE)?.doubly_indirect as usize;
+        if dind == 0 {
+            return Err(Ext2Error::BadLayout {
+                reason: String::from("the resize inode has no doubly indirect block"),
+            });
+        }
+        let backups: Vec<usize> = (1..self.block_groups.len())
+            .filter(|&group| self.group_has_superblock(group))
+            .collect();
+        self.block_mut(dind)?.fill(0);
+        let mut blocks = 1;
+        let first_reserved = self.superblock.first_data_block as usize + 1 + descriptor_blocks;
+        for reserved in 0..self.superblock.reserved_gdt_blocks as usize {
+            let primary = first_reserved + reserved;
+            let slot = (descriptor_blocks + reserved) % per_block * 4;
+            self.block_mut(dind)?[slot..slot + 4].copy_from_slice(&(primary as u32).to_le_bytes());
+            let list = self.block_mut(primary)?;
+            list.fill(0);
+            for (i, &group) in backups.iter().enumerate() {
+                let copy = primary + group * blocks_per_group;
+                list[i * 4..i * 4 + 4].copy_from_slice(&(copy as u32).to_le_bytes());
+            }
+            blocks += 1 + backups.len();
+        }
+        self.inode_mut(RESIZE_INODE)?.sectors_count = (blocks * block_size / 512) as u32;
+        Ok(())
+    }
+}
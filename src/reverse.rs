@@ -0,0 +1,123 @@
+// Reverse lookups, like debugfs's icheck and ncheck: from a block to the
+// inodes that own it, and from an inode to the paths that link to it.
+//
+// Both are meant for looking into images that may be damaged, so an inode
+// whose blocks can't be listed, or a directory that can't be read, is passed
+// over rather than ending the search; `check` reports those.
+
+use crate::{Ext2, Ext2Error, NameKind, RawDirEntry, Result};
+use std::collections::{BTreeMap, HashSet};
+
+impl Ext2 {
+    /// Find the inodes owning each of `blocks`, as data, indirect or
+    /// extended attribute blocks, by going through every allocated inode,
+    /// the reserved ones included. Every block asked about gets an entry;
+    /// more than one owner means the filesystem is damaged.
+    pub fn block_owners(&self, blocks: &[usize]) -> Result<BTreeMap<usize, Vec<usize>>> {
+        let blocks_count = self.superblock.blocks_count as usize;
+        let mut owners: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+        for &block in blocks {
+            if block >= blocks_count {
+                return Err(Ext2Error::BlockOutOfRange {
+                    block,
+                    blocks_count,
+                });
+            }
+            owners.insert(block, Vec::new());
+        }
+        // the reserved inodes besides the root, which `inodes` leaves out
+        let mut reserved = Vec::new();
+        for inode in (1..self.first_usable_inode()).filter(|&inode| inode != 2) {
+            if self.inode_is_allocated(inode)? {
+                reserved.push(inode);
+            }
+        }
+        let inodes = reserved
+            .into_iter()
+            .chain(self.inodes().map(|(inode, _)| inode));
+        for inode in inodes {
+            let Ok(owned) = self.owned_blocks(inode) else {
+                continue;
+            };
+            for block in owned {
+                if let Some(found) = owners.get_mut(&block) {
+                    found.push(inode);
+                }
+            }
+        }
+        Ok(owners)
+    }
+
+    /// Find every path linking to each of `inodes` by walking the tree from
+    /// the root, so all the names of a file with several hard links are
+    /// found. `.` and `..` entries don't count, and the root is `/`. Every
+    /// inode asked about gets an entry, empty if nothing links to it.
+    pub fn inode_paths(&self, inodes: &[usize]) -> Result<BTreeMap<usize, Vec<String>>> {
+        let mut paths: BTreeMap<usize, Vec<String>> =
+            inodes.iter().map(|&inode| (inode, Vec::new())).collect();
+        if let Some(found) = paths.get_mut(&2) {
+            found.push(String::from("/"));
+        }
+        let mut visited = HashSet::from([2]);
+        let mut stack = vec![(2, String::new())];
+        while let Some((dir, dir_path)) = stack.pop() {
+            let Ok(entries) = self.read_dir_raw(dir) else {
+                continue;
+            };
+            for RawDirEntry { inode, name, .. } in entries {
+                if name.kind() == NameKind::Dot {
+                    continue;
+                }
+                let path = format!("{}/{}", dir_path, name);
+                if let Some(found) = paths.get_mut(&inode) {
+                    found.push(path.clone());
+                }
+                // a directory linked twice would otherwise be walked twice,
+                // or forever if it's linked below itself
+                let is_dir = self
+                    .get_inode(inode)
+                    .map_or(false, |record| record.is_dir());
+                if is_dir && visited.insert(inode) {
+                    stack.push((inode, path));
+                }
+            }
+        }
+        for found in paths.values_mut() {
+            found.sort();
+        }
+        Ok(paths)
+    }
+
+    /// The path of directory `dir`, found by going up through the `..`
+    /// entries to the root and looking for each directory's name in its
+    /// parent, which is much quicker than `inode_paths` for a single one.
+    /// The root is `/`.
+    pub fn dir_path(&self, dir: usize) -> Result<String> {
+        let mut names = Vec::new();
+        let mut current = dir;
+        // a damaged tree can loop; no real one is deeper than it has inodes
+        for _ in 0..self.superblock.inodes_count {
+            if current == 2 {
+                names.reverse();
+                return Ok(format!("/{}", names.join("/")));
+            }
+            let parent = self
+                .lookup(current, "..")?
+                .ok_or_else(|| Ext2Error::NotFound {
+                    name: String::from(".."),
+                })?;
+            let entry = self
+                .read_dir_inode(parent)?
+                .into_iter()
+                .find(|entry| entry.inode == current && !entry.is_dot())
+                .ok_or_else(|| Ext2Error::NotFound {
+                    name: format!("directory {} in its parent {}", current, parent),
+                })?;
+            names.push(entry.name_lossy().into_owned());
+            current = parent;
+        }
+        Err(Ext2Error::NotFound {
+            name: format!("the root above directory {}", dir),
+        })
+    }
+}
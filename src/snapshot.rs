@@ -0,0 +1,70 @@
+// Snapshots of the in-memory filesystem, for undoing modifications.
+//
+// Everything a modification changes lives in memory until `sync`: the dirty
+// blocks, and the superblock and descriptors they're copied from. Those
+// together are the whole state, so a snapshot is a copy of them, and rolling
+// back puts the copy back. The dirty blocks are shared rather than copied:
+// `block_mut` copies a block out of a snapshot only when it's next modified.
+
+use crate::device::BlockBuf;
+use crate::structs::{BlockGroupDescriptor, Superblock};
+use crate::Ext2;
+use log::info;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+/// The in-memory state of a filesystem at one moment, taken by
+/// `Ext2::snapshot`, for going back to with `Ext2::rollback`.
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    superblock: Superblock,
+    block_groups: Vec<BlockGroupDescriptor>,
+    dirty: BTreeMap<usize, Arc<BlockBuf>>,
+    modified: HashMap<usize, u64>,
+    generation: u64,
+}
+
+impl Snapshot {
+    /// How many blocks had been modified when the snapshot was taken.
+    pub fn dirty_blocks(&self) -> usize {
+        self.dirty.len()
+    }
+}
+
+impl Ext2 {
+    /// Capture the filesystem as it is now. This copies no blocks, however
+    /// many have been modified.
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            superblock: self.superblock.clone(),
+            block_groups: self.block_groups.clone(),
+            dirty: self.dirty.clone(),
+            modified: self.modified.clone(),
+            generation: self.generation,
+        }
+    }
+
+    /// Discard every modification made since `snapshot` was taken. Only
+    /// memory is touched; if the modifications were synced in between, the
+    /// image keeps them, and the next sync won't rewrite the blocks they
+    /// changed.
+    pub fn rollback(&mut self, snapshot: &Snapshot) {
+        self.superblock = snapshot.superblock.clone();
+        self.block_groups = snapshot.block_groups.clone();
+        self.dirty = snapshot.dirty.clone();
+        self.modified = snapshot.modified.clone();
+        // whatever was cached since is stale now, and the inode cache may
+        // hold inodes of blocks that are modified again
+        self.generation += 1;
+        self.inodes.clear();
+        info!(
+            "rolled back to a snapshot with {} modified block(s)",
+            snapshot.dirty.len()
+        );
+    }
+
+    /// Whether anything has been modified since `snapshot` was taken.
+    pub fn modified_since(&self, snapshot: &Snapshot) -> bool {
+        self.generation != snapshot.generation
+    }
+}
@@ -0,0 +1,83 @@
+// Statistics over every file on the filesystem, gathered in one pass over
+// the allocated inodes.
+
+use crate::{Ext2, Result};
+use std::collections::BTreeMap;
+
+/// How many files of one type there are and how big they are together.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TypeStats {
+    pub count: usize,
+    pub bytes: u64,
+}
+
+/// What `Ext2::stats` found.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FsStats {
+    /// Counts and total sizes by file type, keyed by `Inode::type_name`
+    pub by_type: BTreeMap<&'static str, TypeStats>,
+    /// The biggest regular files as (inode, size), biggest first
+    pub largest: Vec<(usize, u64)>,
+    pub dirs: usize,
+    /// Entries across all directories, not counting `.` and `..`
+    pub dir_entries: usize,
+    /// Regular files by size: bucket 0 holds the empty ones, and bucket `n`
+    /// those of at least 2^(n-1) bytes but under 2^n
+    pub size_histogram: Vec<usize>,
+}
+
+impl FsStats {
+    /// The average number of entries in a directory, besides `.` and `..`.
+    pub fn average_dir_entries(&self) -> f64 {
+        if self.dirs == 0 {
+            0.0
+        } else {
+            self.dir_entries as f64 / self.dirs as f64
+        }
+    }
+}
+
+impl Ext2 {
+    /// Gather statistics over every allocated inode, keeping the `top` biggest
+    /// regular files.
+    pub fn stats(&self, top: usize) -> Result<FsStats> {
+        let mut stats = FsStats {
+            by_type: BTreeMap::new(),
+            largest: Vec::new(),
+            dirs: 0,
+            dir_entries: 0,
+            size_histogram: Vec::new(),
+        };
+        for (inode, record) in self.inodes() {
+            let size = record.size();
+            let kind = stats.by_type.entry(record.type_name()).or_default();
+            kind.count += 1;
+            if record.is_dir() {
+                // size_high is the directory ACL for directories
+                kind.bytes += record.size_low as u64;
+                stats.dirs += 1;
+                stats.dir_entries += self
+                    .read_dir_inode(inode)?
+                    .iter()
+                    .filter(|entry| !entry.is_dot())
+                    .count();
+                continue;
+            }
+            kind.bytes += size;
+            if !record.is_regular() {
+                continue;
+            }
+            let bucket = (u64::BITS - size.leading_zeros()) as usize;
+            if stats.size_histogram.len() <= bucket {
+                stats.size_histogram.resize(bucket + 1, 0);
+            }
+            stats.size_histogram[bucket] += 1;
+            stats.largest.push((inode, size));
+        }
+        stats
+            .largest
+            .sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        stats.largest.truncate(top);
+        Ok(stats)
+    }
+}
@@ -0,0 +1,83 @@
+// Finding the text in binary files, like strings(1): runs of printable ASCII,
+// tabs included as GNU strings has them, ended by any other byte or by the
+// end of the file. The file is read a block at a time by `file_chunks`, so a
+// run may start in one block and go on through any number after it; at most
+// `min_len` bytes of a run are held back, until it's known to be long enough
+// to print, and the rest is written out as it's found, so memory use stays
+// the same however big the file or its runs are.
+
+use crate::{Ext2, Result};
+use std::io::Write;
+
+fn is_printable(byte: u8) -> bool {
+    matches!(byte, b' '..=b'~' | b'\t')
+}
+
+impl Ext2 {
+    /// Write every run of at least `min_len` printable ASCII bytes in the
+    /// contents of `inode` to `out`, one to a line, each after its offset in
+    /// hex when `offsets` is set, like `strings -t x`. Returns how many runs
+    /// there were.
+    pub fn strings_to<W: Write>(
+        &self,
+        inode: usize,
+        min_len: usize,
+        offsets: bool,
+        out: &mut W,
+    ) -> Result<u64> {
+        let min_len = min_len.max(1);
+        // the start of a run not yet known to be long enough
+        let mut pending = Vec::with_capacity(min_len);
+        let mut pending_at = 0;
+        // whether the current run has been written out, so the rest of it
+        // goes straight after
+        let mut printing = false;
+        let mut found = 0;
+        let mut chunk_at = 0;
+        for chunk in self.file_chunks(inode)? {
+            let chunk = chunk?;
+            let mut start = 0;
+            while start < chunk.len() {
+                let end = chunk[start..]
+                    .iter()
+                    .position(|&byte| !is_printable(byte))
+                    .map_or(chunk.len(), |len| start + len);
+                let text = &chunk[start..end];
+                if printing {
+                    out.write_all(text)?;
+                } else if !text.is_empty() {
+                    if pending.is_empty() {
+                        pending_at = chunk_at + start as u64;
+                    }
+                    let wanted = (min_len - pending.len()).min(text.len());
+                    pending.extend_from_slice(&text[..wanted]);
+                    if pending.len() == min_len {
+                        if offsets {
+                            write!(out, "{:7x} ", pending_at)?;
+                        }
+                        out.write_all(&pending)?;
+                        out.write_all(&text[wanted..])?;
+                        pending.clear();
+                        printing = true;
+                        found += 1;
+                    }
+                }
+                if end == chunk.len() {
+                    // the run may go on in the next chunk
+                    break;
+                }
+                if printing {
+                    out.write_all(b"\n")?;
+                    printing = false;
+                }
+                pending.clear();
+                start = end + 1;
+            }
+            chunk_at += chunk.len() as u64;
+        }
+        if printing {
+            out.write_all(b"\n")?;
+        }
+        Ok(found)
+    }
+}
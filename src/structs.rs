@@ -2,7 +2,7 @@ use bitflags::bitflags;
 use null_terminated::NulStr;
 
 #[repr(C)]
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 // https://wiki.osdev.org/Ext2
 pub struct Superblock {
     // taken from https://wiki.osdev.org/Ext2
@@ -94,8 +94,9 @@ pub struct Superblock {
     pub prealloc_blocks_files: u8,
     /// Number of blocks to preallocate for directories
     pub prealloc_blocks_dirs: u8,
-    #[doc(hidden)]
-    _unused: [u8; 2],
+    /// Number of reserved GDT entries for future filesystem expansion
+    /// (only meaningful with the resize_inode feature)
+    pub reserved_gdt_blocks: u16,
     /// Journal ID (same style as the File system ID above)
     pub journal_id: [u8; 16],
     /// Journal inode
@@ -104,10 +105,36 @@ pub struct Superblock {
     pub journal_dev: u32,
     /// Head of orphan inode list
     pub journal_orphan_head: u32,
+    /// Seed for the directory index hash (all zero means use the default)
+    pub hash_seed: [u32; 4],
+    /// Hash function new directory indexes use (0 legacy, 1 half MD4, 2 TEA)
+    pub def_hash_version: u8,
+    /// How the journal inode is backed up in `journal_blocks`
+    pub journal_backup_type: u8,
+    /// Size of a block group descriptor (ext4 64bit feature only)
+    pub desc_size: u16,
+    /// Default mount options
+    pub default_mount_opts: u32,
+    /// First metablock block group (with the meta_bg feature)
+    pub first_meta_bg: u32,
+    /// When the filesystem was created (in POSIX time)
+    pub mkfs_time: u32,
+    /// Backup of the journal inode's block pointers and size
+    pub journal_blocks: [u32; 17],
+    /// Upper 32 bits of the block counts (ext4 64bit feature only)
+    pub blocks_count_high: u32,
+    pub reserved_blocks_count_high: u32,
+    pub free_blocks_count_high: u32,
+    /// Minimum and desired extra inode size, beyond the first 128 bytes
+    pub min_extra_isize: u16,
+    pub want_extra_isize: u16,
+    /// Miscellaneous flags, e.g. whether directory hashes treat names as
+    /// signed or unsigned chars
+    pub misc_flags: u32,
 }
 
 #[repr(C)]
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct BlockGroupDescriptor {
     /// Block address of block usage bitmap
     pub block_usage_addr: u32,
@@ -178,7 +205,6 @@ pub struct Inode {
     pub frag_block_addr: u32,
     /// Operating System Specific Value #2
     pub _os_specific_2: [u8; 12],
-    _padding: [u8; 128], // TODO: handle inode sizes != 128 according to superblock
 }
 
 #[repr(C)]
@@ -198,6 +224,7 @@ pub struct DirectoryEntry {
 }
 
 #[derive(Debug)]
+#[allow(dead_code)] // only ever read from disk, never constructed
 pub enum TypeIndicator {
     Unknown,
     Regular,
@@ -251,3 +278,158 @@ bitflags! {
         const SET_UID = 0x800;
     }
 }
+
+/// The kind of file an inode is. The top four bits of `TypePerm` are one
+/// field rather than flags: a symlink, 0xA000, has the regular file bit
+/// 0x8000 set too, so testing for a type with `contains` gets it wrong.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FileType {
+    Fifo,
+    Char,
+    Dir,
+    Block,
+    Regular,
+    Symlink,
+    Socket,
+    /// Type bits that aren't any of the above
+    Unknown,
+}
+
+impl FileType {
+    /// The type in the top four bits of `mode`.
+    pub fn from_mode(mode: u16) -> FileType {
+        match mode & 0xF000 {
+            0x1000 => FileType::Fifo,
+            0x2000 => FileType::Char,
+            0x4000 => FileType::Dir,
+            0x6000 => FileType::Block,
+            0x8000 => FileType::Regular,
+            0xA000 => FileType::Symlink,
+            0xC000 => FileType::Socket,
+            _ => FileType::Unknown,
+        }
+    }
+
+    /// The type a directory entry's type indicator byte stands for, if any.
+    pub fn from_indicator(indicator: u8) -> Option<FileType> {
+        match indicator {
+            1 => Some(FileType::Regular),
+            2 => Some(FileType::Dir),
+            3 => Some(FileType::Char),
+            4 => Some(FileType::Block),
+            5 => Some(FileType::Fifo),
+            6 => Some(FileType::Socket),
+            7 => Some(FileType::Symlink),
+            _ => None,
+        }
+    }
+}
+
+bitflags! {
+    /// Inode `flags`, as shown by `lsattr`
+    pub struct InodeFlags: u32 {
+        /// Secure deletion: zero the blocks when the file is deleted
+        const SECURE_DELETE = 0x0000_0001;
+        /// Keep a copy of the data when the file is deleted
+        const UNDELETE = 0x0000_0002;
+        /// Compress the file's data
+        const COMPRESS = 0x0000_0004;
+        /// Write changes to the disk synchronously
+        const SYNC = 0x0000_0008;
+        /// Nothing may change the file: no writes, links, renames or deletion
+        const IMMUTABLE = 0x0000_0010;
+        /// Writes may only add to the end of the file
+        const APPEND = 0x0000_0020;
+        /// Skip the file when backing up with dump
+        const NO_DUMP = 0x0000_0040;
+        /// Don't update the access time
+        const NO_ATIME = 0x0000_0080;
+        /// Directory has a hash index (htree)
+        const INDEX = 0x0000_1000;
+        /// Journal the file's data as well as its metadata (ext3)
+        const JOURNAL_DATA = 0x0000_4000;
+        /// Don't merge the file's tail into a shared block
+        const NO_TAIL = 0x0000_8000;
+        /// Write directory changes synchronously
+        const DIR_SYNC = 0x0001_0000;
+        /// Top of a directory hierarchy, for the Orlov allocator
+        const TOP_DIR = 0x0002_0000;
+        /// File data is mapped with extents (ext4)
+        const EXTENTS = 0x0008_0000;
+    }
+}
+
+impl InodeFlags {
+    /// Each flag with its `lsattr` letter, in `lsattr`'s order.
+    pub const LETTERS: &'static [(InodeFlags, char)] = &[
+        (InodeFlags::SECURE_DELETE, 's'),
+        (InodeFlags::UNDELETE, 'u'),
+        (InodeFlags::SYNC, 'S'),
+        (InodeFlags::DIR_SYNC, 'D'),
+        (InodeFlags::IMMUTABLE, 'i'),
+        (InodeFlags::APPEND, 'a'),
+        (InodeFlags::NO_DUMP, 'd'),
+        (InodeFlags::NO_ATIME, 'A'),
+        (InodeFlags::COMPRESS, 'c'),
+        (InodeFlags::JOURNAL_DATA, 'j'),
+        (InodeFlags::INDEX, 'I'),
+        (InodeFlags::NO_TAIL, 't'),
+        (InodeFlags::TOP_DIR, 'T'),
+        (InodeFlags::EXTENTS, 'e'),
+    ];
+
+    /// The flags as `lsattr` prints them, a letter or `-` for each one
+    /// in `LETTERS`, e.g. `----ia--------`.
+    pub fn letters(self) -> String {
+        InodeFlags::LETTERS
+            .iter()
+            .map(|&(flag, letter)| if self.contains(flag) { letter } else { '-' })
+            .collect()
+    }
+}
+
+bitflags! {
+    /// Optional features (`features_opt`): safe to ignore when reading or writing
+    pub struct FeatureCompat: u32 {
+        /// Preallocate some number of blocks to a directory when creating one
+        const DIR_PREALLOC = 0x0001;
+        /// AFS server inodes exist
+        const IMAGIC_INODES = 0x0002;
+        /// File system has a journal (ext3)
+        const HAS_JOURNAL = 0x0004;
+        /// Inodes have extended attributes
+        const EXT_ATTR = 0x0008;
+        /// File system can resize itself for larger partitions
+        const RESIZE_INODE = 0x0010;
+        /// Directories use hash index
+        const DIR_INDEX = 0x0020;
+    }
+}
+
+bitflags! {
+    /// Required features (`features_req`): can't read or write without them
+    pub struct FeatureIncompat: u32 {
+        /// Compression is used
+        const COMPRESSION = 0x0001;
+        /// Directory entries contain a type field
+        const FILETYPE = 0x0002;
+        /// File system needs to replay its journal
+        const RECOVER = 0x0004;
+        /// File system uses a journal device
+        const JOURNAL_DEV = 0x0008;
+        /// Block groups use meta block groups
+        const META_BG = 0x0010;
+    }
+}
+
+bitflags! {
+    /// Read-only features (`features_ronly`): can't write without them
+    pub struct FeatureRoCompat: u32 {
+        /// Sparse superblocks and group descriptor tables
+        const SPARSE_SUPER = 0x0001;
+        /// File system uses a 64-bit file size
+        const LARGE_FILE = 0x0002;
+        /// Directory contents are stored in the form of a Binary Tree
+        const BTREE_DIR = 0x0004;
+    }
+}
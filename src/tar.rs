@@ -0,0 +1,646 @@
+// Exporting a directory tree as a POSIX ustar archive, and importing one.
+//
+// The archive is written as the tree is walked, each file's contents going
+// straight from its blocks to the writer, so nothing the size of a file is
+// ever held in memory. Names too long for ustar's name and prefix fields get
+// a GNU long name entry ahead of them, which every tar in use understands.
+//
+// Importing streams the other way, each file's contents going from the
+// reader to its blocks as they arrive. Long names are taken from GNU long
+// name entries and from pax headers, whichever the archive has.
+
+use crate::access::{group, owner, set_owner};
+use crate::populate::read_full;
+use crate::progress::check_cancel;
+use crate::structs::{Inode, TypePerm};
+use crate::{
+    Ext2, Ext2Error, NoProgress, PopulateSummary, Progress, Result, WalkControl, WalkOptions,
+};
+use log::{info, warn};
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::path::PathBuf;
+
+const BLOCK: usize = 512;
+
+// the most a long name entry or pax header is allowed to hold
+const MAX_EXTENDED: u64 = 1 << 20;
+
+impl Ext2 {
+    /// Write everything under directory `root` to `out` as a ustar archive,
+    /// with paths relative to `root`: directories, regular files, symlinks
+    /// and device nodes and FIFOs, with their permission bits, owners and
+    /// modification times. A file with several names is stored once, under
+    /// the first of them, and as link entries under the rest. Sockets can't
+    /// be archived and are left out. Returns how many entries were written.
+    pub fn export_tar<W: Write>(&self, root: usize, out: W) -> Result<usize> {
+        self.export_tar_with_progress(root, out, &NoProgress)
+    }
+
+    /// `export_tar`, telling `progress` about each path as it's written and
+    /// the bytes of file contents written so far. Cancelling stops between
+    /// entries, leaving `out` with an archive cut short.
+    pub fn export_tar_with_progress<W: Write>(
+        &self,
+        root: usize,
+        mut out: W,
+        progress: &dyn Progress,
+    ) -> Result<usize> {
+        if !self.get_inode(root)?.is_dir() {
+            return Err(Ext2Error::NotADirectory {
+                name: format!("inode {}", root),
+            });
+        }
+        // the paths are gathered first, since writing can fail and the
+        // walk's visitor has no way to say so
+        let mut entries = Vec::new();
+        self.walk(root, &WalkOptions::new(), &mut |entry| {
+            if progress.should_cancel() {
+                return WalkControl::Stop;
+            }
+            entries.push((entry.path(), entry.inode));
+            WalkControl::Continue
+        })?;
+        check_cancel(progress)?;
+
+        // inodes with more than one link, by the first path they were stored under
+        let mut stored: HashMap<usize, String> = HashMap::new();
+        let mut written = 0;
+        let mut bytes = 0;
+        for (path, inode) in entries {
+            check_cancel(progress)?;
+            progress.on_item(&path);
+            let record = self.get_inode(inode)?;
+            let file_type = record.type_perm.bits() & 0xF000;
+            if file_type == TypePerm::SOCKET.bits() {
+                warn!("tar: leaving out socket {}", path);
+                continue;
+            }
+            if file_type != TypePerm::DIRECTORY.bits() && record.hard_links > 1 {
+                if let Some(first) = stored.get(&inode) {
+                    let header = Header::new(record, path, b'1').link(first.as_bytes());
+                    header.write(&mut out)?;
+                    written += 1;
+                    continue;
+                }
+                stored.insert(inode, path.clone());
+            }
+            let header = if file_type == TypePerm::DIRECTORY.bits() {
+                Header::new(record, format!("{}/", path), b'5')
+            } else if file_type == TypePerm::SYMLINK.bits() {
+                Header::new(record, path, b'2').link(&self.read_link(inode)?)
+            } else if file_type == TypePerm::CHAR_DEVICE.bits() {
+                Header::new(record, path, b'3')
+            } else if file_type == TypePerm::BLOCK_DEVICE.bits() {
+                Header::new(record, path, b'4')
+            } else if file_type == TypePerm::FIFO.bits() {
+                Header::new(record, path, b'6')
+            } else {
+                let mut header = Header::new(record, path, b'0');
+                header.size = record.size();
+                header
+            };
+            header.write(&mut out)?;
+            if header.size > 0 {
+                let copied = self.copy_file_to(inode, &mut out)?;
+                pad(&mut out, copied)?;
+                bytes += copied;
+                progress.on_bytes(bytes, None);
+            }
+            written += 1;
+        }
+        // the end of the archive is two blocks of zeros
+        out.write_all(&[0; 2 * BLOCK])?;
+        out.flush()?;
+        info!("tar: wrote {} entries from inode {}", written, root);
+        Ok(written)
+    }
+
+    /// Read a ustar archive from `input` and recreate what's in it under
+    /// directory `dir`: directories, regular files with their contents,
+    /// symlinks, hard links, device nodes and FIFOs, with their permission
+    /// bits, owners and modification times. Directories the archive doesn't
+    /// have an entry for are made as needed. Entries of a kind ext2 has no
+    /// equivalent for, and names leading out of `dir` with `..`, are skipped
+    /// and listed in the summary. On failure nothing is left of the partial
+    /// import.
+    pub fn import_tar<R: Read>(&mut self, dir: usize, input: R) -> Result<PopulateSummary> {
+        self.import_tar_with_progress(dir, input, &NoProgress)
+    }
+
+    /// `import_tar`, telling `progress` about each path as it's imported and
+    /// the bytes of file contents copied so far. Cancelling rolls back like
+    /// any other failure.
+    pub fn import_tar_with_progress<R: Read>(
+        &mut self,
+        dir: usize,
+        mut input: R,
+        progress: &dyn Progress,
+    ) -> Result<PopulateSummary> {
+        self.check_writable()?;
+        if !self.get_inode(dir)?.is_dir() {
+            return Err(Ext2Error::NotADirectory {
+                name: format!("inode {}", dir),
+            });
+        }
+        let saved = self.snapshot();
+        let mut summary = PopulateSummary::default();
+        match self.import_entries(dir, &mut input, &mut summary, progress) {
+            Ok(()) => {
+                info!("untar into inode {}: {}", dir, summary);
+                Ok(summary)
+            }
+            Err(err) => {
+                self.rollback(&saved);
+                info!("untar into inode {} failed, rolled back: {}", dir, err);
+                Err(err)
+            }
+        }
+    }
+
+    // A helper function for `import_tar`: import every entry up to the end
+    // of the archive
+    fn import_entries<R: Read>(
+        &mut self,
+        dir: usize,
+        input: &mut R,
+        summary: &mut PopulateSummary,
+        progress: &dyn Progress,
+    ) -> Result<()> {
+        // each path imported, for hard links to find
+        let mut imported: HashMap<String, usize> = HashMap::new();
+        // directory times are set last, since filling them in changes them
+        let mut dir_times = Vec::new();
+        // names from the long name entries and pax headers before an entry
+        let (mut long_path, mut long_link) = (None, None);
+        let mut block = [0; BLOCK];
+        loop {
+            check_cancel(progress)?;
+            if !read_block(input, &mut block)? || block.iter().all(|&byte| byte == 0) {
+                break;
+            }
+            let entry = Entry::parse(&block)?;
+            match entry.type_flag {
+                b'L' | b'K' => {
+                    let mut name = read_extended(input, entry.size)?;
+                    if let Some(end) = name.iter().position(|&byte| byte == 0) {
+                        name.truncate(end);
+                    }
+                    if entry.type_flag == b'L' {
+                        long_path = Some(name);
+                    } else {
+                        long_link = Some(name);
+                    }
+                    continue;
+                }
+                b'x' => {
+                    let records = read_extended(input, entry.size)?;
+                    for (key, value) in pax_records(&records)? {
+                        match key {
+                            b"path" => long_path = Some(value.to_vec()),
+                            b"linkpath" => long_link = Some(value.to_vec()),
+                            _ => {}
+                        }
+                    }
+                    continue;
+                }
+                // pax headers for the whole archive say nothing needed here
+                b'g' => {
+                    skip(input, padded(entry.size))?;
+                    continue;
+                }
+                _ => {}
+            }
+            let path = long_path.take().unwrap_or_else(|| entry.path.clone());
+            let link = long_link.take().unwrap_or_else(|| entry.link.clone());
+            let path = String::from_utf8_lossy(&path).into_owned();
+            progress.on_item(&path);
+            let Some(components) = path_components(&path) else {
+                warn!("untar: skipping {}, which leads out of the directory", path);
+                summary.skipped.push(PathBuf::from(&path));
+                skip(input, padded(entry.size))?;
+                continue;
+            };
+            let Some((name, parents)) = components.split_last() else {
+                // the directory itself, as "./"
+                skip(input, padded(entry.size))?;
+                continue;
+            };
+            let parent = self.make_parents(dir, parents, &mut dir_times)?;
+            let key = components.join("/");
+            let mode = entry.mode & 0o7777;
+            let inode = match entry.type_flag {
+                b'0' | b'\0' | b'7' => {
+                    let inode = self.create_file(parent, name, mode)?;
+                    let copied = self.import_contents(inode, &mut input.take(entry.size))?;
+                    if copied < entry.size {
+                        return Err(Ext2Error::BadArchive {
+                            reason: format!("{} is cut short", path),
+                        });
+                    }
+                    skip(input, padded(entry.size) - entry.size)?;
+                    summary.files += 1;
+                    summary.bytes += entry.size;
+                    progress.on_bytes(summary.bytes, None);
+                    inode
+                }
+                b'1' => {
+                    let link = String::from_utf8_lossy(&link).into_owned();
+                    let target = match path_components(&link) {
+                        Some(target) => imported.get(&target.join("/")).copied(),
+                        None => None,
+                    };
+                    let Some(target) = target else {
+                        return Err(Ext2Error::BadArchive {
+                            reason: format!("{} links to {}, which isn't in it", path, link),
+                        });
+                    };
+                    let target_mode = self.get_inode(target)?.type_perm.bits();
+                    self.check_new_name(parent, name)?;
+                    self.add_dir_entry(parent, name, target, target_mode)?;
+                    let record = self.inode_mut(target)?;
+                    record.hard_links += 1;
+                    imported.insert(key, target);
+                    skip(input, padded(entry.size))?;
+                    continue;
+                }
+                b'2' => {
+                    let inode =
+                        self.create_node(parent, name, TypePerm::SYMLINK.bits() | 0o777, None)?;
+                    self.write_symlink(inode, &link)?;
+                    summary.symlinks += 1;
+                    inode
+                }
+                b'3' | b'4' | b'6' => {
+                    let (file_type, device) = match entry.type_flag {
+                        b'3' => (TypePerm::CHAR_DEVICE, Some(entry.device)),
+                        b'4' => (TypePerm::BLOCK_DEVICE, Some(entry.device)),
+                        _ => (TypePerm::FIFO, None),
+                    };
+                    self.create_node(parent, name, file_type.bits() | mode, device)?
+                }
+                b'5' => {
+                    let inode = match self.lookup(parent, name)? {
+                        Some(inode) if self.get_inode(inode)?.is_dir() => {
+                            let record = self.inode_mut(inode)?;
+                            record.type_perm =
+                                TypePerm::from_bits_truncate(TypePerm::DIRECTORY.bits() | mode);
+                            inode
+                        }
+                        _ => {
+                            summary.dirs += 1;
+                            self.create_dir(parent, name, mode)?
+                        }
+                    };
+                    dir_times.push((inode, entry.mtime));
+                    inode
+                }
+                other => {
+                    warn!(
+                        "untar: skipping {}, of unsupported type {:?}",
+                        path, other as char
+                    );
+                    summary.skipped.push(PathBuf::from(&path));
+                    skip(input, padded(entry.size))?;
+                    continue;
+                }
+            };
+            let record = self.inode_mut(inode)?;
+            set_owner(record, entry.uid, entry.gid);
+            record.atime = entry.mtime;
+            record.mtime = entry.mtime;
+            imported.insert(key, inode);
+        }
+        for (inode, mtime) in dir_times.into_iter().rev() {
+            let record = self.inode_mut(inode)?;
+            record.atime = mtime;
+            record.mtime = mtime;
+        }
+        Ok(())
+    }
+
+    // A helper function for `import_entries`: find the directory `parents`
+    // leads to under `dir`, making any that don't exist yet
+    fn make_parents(
+        &mut self,
+        dir: usize,
+        parents: &[&str],
+        dir_times: &mut Vec<(usize, u32)>,
+    ) -> Result<usize> {
+        let mut current = dir;
+        for &component in parents {
+            current = match self.lookup(current, component)? {
+                Some(inode) if self.get_inode(inode)?.is_dir() => inode,
+                Some(_) => {
+                    return Err(Ext2Error::NotADirectory {
+                        name: component.to_string(),
+                    })
+                }
+                None => {
+                    let inode = self.create_dir(current, component, 0o755)?;
+                    let now = self.now();
+                    dir_times.push((inode, now));
+                    inode
+                }
+            };
+        }
+        Ok(current)
+    }
+}
+
+// what an archive entry's header says
+struct Entry {
+    path: Vec<u8>,
+    type_flag: u8,
+    mode: u16,
+    uid: u32,
+    gid: u32,
+    size: u64,
+    mtime: u32,
+    link: Vec<u8>,
+    device: (u32, u32),
+}
+
+impl Entry {
+    fn parse(block: &[u8; BLOCK]) -> Result<Entry> {
+        let stored = parse_number(&block[148..156])?;
+        let mut sum: u64 = block.iter().map(|&byte| byte as u64).sum();
+        sum -= block[148..156].iter().map(|&byte| byte as u64).sum::<u64>();
+        sum += 8 * b' ' as u64;
+        if sum != stored {
+            return Err(Ext2Error::BadArchive {
+                reason: "header checksum doesn't match".to_string(),
+            });
+        }
+        let mut path = field(&block[..100]).to_vec();
+        // ustar's prefix field, which GNU tar's own format uses otherwise
+        if &block[257..263] == b"ustar\0" && block[345] != 0 {
+            let mut prefixed = field(&block[345..500]).to_vec();
+            prefixed.push(b'/');
+            prefixed.extend_from_slice(&path);
+            path = prefixed;
+        }
+        Ok(Entry {
+            path,
+            type_flag: block[156],
+            mode: parse_number(&block[100..108])? as u16,
+            uid: parse_number(&block[108..116])? as u32,
+            gid: parse_number(&block[116..124])? as u32,
+            size: parse_number(&block[124..136])?,
+            mtime: parse_number(&block[136..148])? as u32,
+            link: field(&block[157..257]).to_vec(),
+            device: (
+                parse_number(&block[329..337])? as u32,
+                parse_number(&block[337..345])? as u32,
+            ),
+        })
+    }
+}
+
+// a text field, up to its first NUL
+fn field(bytes: &[u8]) -> &[u8] {
+    let end = bytes
+        .iter()
+        .position(|&byte| byte == 0)
+        .unwrap_or(bytes.len());
+    &bytes[..end]
+}
+
+// a numeric field: octal digits, maybe padded with spaces and NULs, or the
+// GNU base-256 form `number` writes
+fn parse_number(field: &[u8]) -> Result<u64> {
+    if field[0] & 0x80 != 0 {
+        let mut value = (field[0] & 0x7f) as u64;
+        for &byte in &field[1..] {
+            value = value << 8 | byte as u64;
+        }
+        return Ok(value);
+    }
+    let digits = String::from_utf8_lossy(field);
+    let digits = digits.trim_matches(|c: char| c == ' ' || c == '\0');
+    if digits.is_empty() {
+        return Ok(0);
+    }
+    u64::from_str_radix(digits, 8).map_err(|_| Ext2Error::BadArchive {
+        reason: format!("bad number {:?} in a header", digits),
+    })
+}
+
+// the components of an archive path, without empty ones and ".", or `None`
+// if it has a ".." in it
+fn path_components(path: &str) -> Option<Vec<&str>> {
+    let components: Vec<&str> = path
+        .split('/')
+        .filter(|component| !component.is_empty() && *component != ".")
+        .collect();
+    (!components.contains(&"..")).then_some(components)
+}
+
+// the `key=value` records of a pax header
+fn pax_records(mut records: &[u8]) -> Result<Vec<(&[u8], &[u8])>> {
+    let bad = || Ext2Error::BadArchive {
+        reason: "bad pax header".to_string(),
+    };
+    let mut parsed = Vec::new();
+    while !records.is_empty() {
+        // each is "<length> <key>=<value>\n", the length counting it all
+        let space = records
+            .iter()
+            .position(|&byte| byte == b' ')
+            .ok_or_else(bad)?;
+        let len: usize = std::str::from_utf8(&records[..space])
+            .ok()
+            .and_then(|len| len.parse().ok())
+            .filter(|&len| len > space + 1 && len <= records.len())
+            .ok_or_else(bad)?;
+        let record = &records[space + 1..len - 1];
+        let equals = record
+            .iter()
+            .position(|&byte| byte == b'=')
+            .ok_or_else(bad)?;
+        parsed.push((&record[..equals], &record[equals + 1..]));
+        records = &records[len..];
+    }
+    Ok(parsed)
+}
+
+// read the next header block, or return false at the end of the input
+fn read_block<R: Read>(input: &mut R, block: &mut [u8; BLOCK]) -> Result<bool> {
+    match read_full(input, block)? {
+        0 => Ok(false),
+        BLOCK => Ok(true),
+        _ => Err(Ext2Error::BadArchive {
+            reason: "ends part way through a header".to_string(),
+        }),
+    }
+}
+
+// read the `size` bytes of a long name entry or pax header, and their padding
+fn read_extended<R: Read>(input: &mut R, size: u64) -> Result<Vec<u8>> {
+    if size > MAX_EXTENDED {
+        return Err(Ext2Error::BadArchive {
+            reason: format!("extended header of {} bytes", size),
+        });
+    }
+    let mut contents = vec![0; padded(size) as usize];
+    input
+        .read_exact(&mut contents)
+        .map_err(|_| Ext2Error::BadArchive {
+            reason: "ends part way through an extended header".to_string(),
+        })?;
+    contents.truncate(size as usize);
+    Ok(contents)
+}
+
+// read past `len` bytes of the input
+fn skip<R: Read>(input: &mut R, len: u64) -> Result<()> {
+    let skipped = io::copy(&mut input.take(len), &mut io::sink())?;
+    if skipped < len {
+        return Err(Ext2Error::BadArchive {
+            reason: "ends part way through an entry".to_string(),
+        });
+    }
+    Ok(())
+}
+
+// `len` rounded up to a whole number of blocks
+fn padded(len: u64) -> u64 {
+    len.next_multiple_of(BLOCK as u64)
+}
+
+// what goes into one entry's header, before it's laid out
+struct Header {
+    path: String,
+    type_flag: u8,
+    mode: u32,
+    uid: u32,
+    gid: u32,
+    size: u64,
+    mtime: u32,
+    link: Vec<u8>,
+    device: (u32, u32),
+}
+
+impl Header {
+    fn new(record: &Inode, path: String, type_flag: u8) -> Header {
+        Header {
+            path,
+            type_flag,
+            mode: record.permissions() as u32,
+            uid: owner(record),
+            gid: group(record),
+            size: 0,
+            mtime: record.mtime,
+            link: Vec::new(),
+            device: record.device().unwrap_or((0, 0)),
+        }
+    }
+
+    fn link(mut self, target: &[u8]) -> Header {
+        self.link = target.to_vec();
+        self
+    }
+
+    // write the header, after GNU long name and long link entries if the
+    // path or link target don't fit
+    fn write<W: Write>(&self, out: &mut W) -> Result<()> {
+        let path = self.path.as_bytes();
+        let split = split_path(path);
+        if split.is_none() {
+            long_entry(out, b'L', path)?;
+        }
+        if self.link.len() > 100 {
+            long_entry(out, b'K', &self.link)?;
+        }
+        let (prefix, name) = split.unwrap_or((&[], &path[..path.len().min(100)]));
+
+        let mut block = [0; BLOCK];
+        block[..name.len()].copy_from_slice(name);
+        number(&mut block[100..108], self.mode as u64);
+        number(&mut block[108..116], self.uid as u64);
+        number(&mut block[116..124], self.gid as u64);
+        number(&mut block[124..136], self.size);
+        number(&mut block[136..148], self.mtime as u64);
+        block[156] = self.type_flag;
+        let link = &self.link[..self.link.len().min(100)];
+        block[157..157 + link.len()].copy_from_slice(link);
+        block[257..263].copy_from_slice(b"ustar\0");
+        block[263..265].copy_from_slice(b"00");
+        if matches!(self.type_flag, b'3' | b'4') {
+            number(&mut block[329..337], self.device.0 as u64);
+            number(&mut block[337..345], self.device.1 as u64);
+        }
+        block[345..345 + prefix.len()].copy_from_slice(prefix);
+        checksum(&mut block);
+        out.write_all(&block)?;
+        Ok(())
+    }
+}
+
+// split `path` into ustar's prefix and name fields, at a slash, or `None`
+// if it can't be: a name of up to 100 bytes needs no prefix
+fn split_path(path: &[u8]) -> Option<(&[u8], &[u8])> {
+    if path.len() <= 100 {
+        return Some((&[], path));
+    }
+    // a directory's trailing slash can't be where it splits
+    let searched = &path[..path.len() - 1];
+    (0..searched.len())
+        .rev()
+        .filter(|&i| searched[i] == b'/')
+        .map(|i| (&path[..i], &path[i + 1..]))
+        .find(|(prefix, name)| prefix.len() <= 155 && name.len() <= 100 && !name.is_empty())
+}
+
+// a GNU long name (`L`) or long link (`K`) entry, whose contents are the
+// name of the entry that follows it
+fn long_entry<W: Write>(out: &mut W, type_flag: u8, name: &[u8]) -> Result<()> {
+    let mut block = [0; BLOCK];
+    block[..13].copy_from_slice(b"././@LongLink");
+    number(&mut block[100..108], 0o644);
+    number(&mut block[108..116], 0);
+    number(&mut block[116..124], 0);
+    number(&mut block[124..136], name.len() as u64 + 1);
+    number(&mut block[136..148], 0);
+    block[156] = type_flag;
+    // GNU tar's own magic, which says what these entries mean
+    block[257..265].copy_from_slice(b"ustar  \0");
+    checksum(&mut block);
+    out.write_all(&block)?;
+    out.write_all(name)?;
+    out.write_all(&[0])?;
+    pad(out, name.len() as u64 + 1)
+}
+
+// `value` in a numeric field: octal digits ending in a NUL if it fits, and
+// otherwise the GNU base-256 form, big-endian with the top bit set
+fn number(field: &mut [u8], value: u64) {
+    let digits = field.len() - 1;
+    if value < 1 << (3 * digits) {
+        field[..digits].copy_from_slice(format!("{:0width$o}", value, width = digits).as_bytes());
+        field[digits] = 0;
+    } else {
+        field.fill(0);
+        let bytes = value.to_be_bytes();
+        let len = field.len();
+        field[len - 8..].copy_from_slice(&bytes);
+        field[0] |= 0x80;
+    }
+}
+
+// fill in the checksum: the sum of the header's bytes, counting the
+// checksum field itself as spaces
+fn checksum(block: &mut [u8; BLOCK]) {
+    block[148..156].fill(b' ');
+    let sum: u32 = block.iter().map(|&byte| byte as u32).sum();
+    block[148..155].copy_from_slice(format!("{:06o}\0", sum).as_bytes());
+}
+
+// pad contents of `len` bytes out to a whole number of blocks
+fn pad<W: Write>(out: &mut W, len: u64) -> Result<()> {
+    let partial = len as usize % BLOCK;
+    if partial != 0 {
+        out.write_all(&[0; BLOCK][partial..])?;
+    }
+    Ok(())
+}
@@ -0,0 +1,119 @@
+// The largest and the most recently modified files in a tree, for triaging
+// an image. Both walk the tree once, keeping only the best N files found so
+// far in a heap whose top is the worst of them, so what's held doesn't grow
+// with the tree: besides the N files, only the inodes with more than one
+// link are remembered, so that each is counted once whichever of its paths
+// comes first.
+
+use crate::structs::Inode;
+use crate::{Ext2, Result, WalkControl, WalkOptions};
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, HashSet};
+
+/// A regular file found by `Ext2::biggest_files` or `Ext2::recent_files`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RankedFile {
+    pub inode: usize,
+    /// Relative to the directory searched; the first path found for a file
+    /// with hard links
+    pub path: String,
+    pub size: u64,
+    pub mtime: u32,
+}
+
+// a file in the heap, ordered by how it ranks: the higher key first, and for
+// the same key the earlier path, so ties come out the same on every run
+struct Ranked {
+    key: u64,
+    file: RankedFile,
+}
+
+impl Ranked {
+    fn rank(&self) -> (u64, Reverse<&str>) {
+        (self.key, Reverse(&self.file.path))
+    }
+}
+
+impl PartialEq for Ranked {
+    fn eq(&self, other: &Ranked) -> bool {
+        self.rank() == other.rank()
+    }
+}
+
+impl Eq for Ranked {}
+
+impl PartialOrd for Ranked {
+    fn partial_cmp(&self, other: &Ranked) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Ranked {
+    fn cmp(&self, other: &Ranked) -> Ordering {
+        self.rank().cmp(&other.rank())
+    }
+}
+
+impl Ext2 {
+    /// The `n` largest regular files below directory `root`, largest first,
+    /// walked as `options` says.
+    pub fn biggest_files(
+        &self,
+        root: usize,
+        n: usize,
+        options: &WalkOptions,
+    ) -> Result<Vec<RankedFile>> {
+        self.top_files(root, n, options, |record| record.size())
+    }
+
+    /// The `n` most recently modified regular files below directory `root`,
+    /// newest first, walked as `options` says.
+    pub fn recent_files(
+        &self,
+        root: usize,
+        n: usize,
+        options: &WalkOptions,
+    ) -> Result<Vec<RankedFile>> {
+        self.top_files(root, n, options, |record| record.mtime as u64)
+    }
+
+    // the `n` regular files with the highest `key`, highest first
+    fn top_files(
+        &self,
+        root: usize,
+        n: usize,
+        options: &WalkOptions,
+        key: impl Fn(&Inode) -> u64,
+    ) -> Result<Vec<RankedFile>> {
+        if n == 0 {
+            return Ok(Vec::new());
+        }
+        // a min-heap, so the worst of the best so far is the one to drop
+        let mut best: BinaryHeap<Reverse<Ranked>> = BinaryHeap::with_capacity(n + 1);
+        let mut linked = HashSet::new();
+        self.walk(root, options, &mut |entry| {
+            let record = entry.record;
+            if !record.is_regular() || (record.hard_links > 1 && !linked.insert(entry.inode)) {
+                return WalkControl::Continue;
+            }
+            best.push(Reverse(Ranked {
+                key: key(record),
+                file: RankedFile {
+                    inode: entry.inode,
+                    path: entry.path(),
+                    size: record.size(),
+                    mtime: record.mtime,
+                },
+            }));
+            if best.len() > n {
+                best.pop();
+            }
+            WalkControl::Continue
+        })?;
+        Ok(best
+            .into_sorted_vec()
+            .into_iter()
+            .map(|Reverse(ranked)| ranked.file)
+            .collect())
+    }
+}
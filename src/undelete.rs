@@ -0,0 +1,203 @@
+// Recovering deleted files.
+//
+// Deleting a file in ext2 clears its inode bitmap bit, sets its dtime and
+// frees its blocks, but (short of a kernel that zeroes them) leaves the block
+// pointers in the inode. Its directory entry is unlinked by growing the
+// previous entry over it, so the name and inode number survive in the slack
+// after the previous entry's name until a new entry reuses the space. As
+// long as none of the blocks have been allocated again, relinking the inode
+// and marking everything in use brings the file back.
+
+use crate::structs::TypePerm;
+use crate::{dir_block_entries, rec_len_from_disk, Ext2, Ext2Error, Result};
+use log::info;
+use std::collections::HashMap;
+
+/// A deleted inode that might be recovered with `Ext2::undelete`.
+#[derive(Debug, Clone)]
+pub struct DeletedInode {
+    pub inode: usize,
+    pub mode: u16,
+    pub size: u64,
+    pub dtime: u32,
+    /// Names found for it in the slack of directory entries, if any
+    pub names: Vec<String>,
+    /// The blocks it owned that are now in use by something else; it can
+    /// only be recovered if this is empty
+    pub reused_blocks: Vec<usize>,
+}
+
+impl Ext2 {
+    /// Every inode that was deleted but still has its data pointers, with
+    /// any names it used to have.
+    pub fn deleted_inodes(&self) -> Result<Vec<DeletedInode>> {
+        let names = self.remnant_names()?;
+        let mut ret = Vec::new();
+        let first_usable = self.first_usable_inode();
+        for inode in first_usable..=self.superblock.inodes_count as usize {
+            if self.inode_is_allocated(inode)? {
+                continue;
+            }
+            let record = self.get_inode(inode)?;
+            if record.dtime == 0 || record.type_perm.bits() == 0 {
+                continue;
+            }
+            ret.push(DeletedInode {
+                inode,
+                mode: record.type_perm.bits(),
+                size: record.size(),
+                dtime: record.dtime,
+                names: names.get(&inode).cloned().unwrap_or_default(),
+                reused_blocks: self.reused_blocks(inode)?,
+            });
+        }
+        Ok(ret)
+    }
+
+    /// Bring deleted `inode` back as `name` in directory `dir`, marking it
+    /// and its blocks in use again. Refused if any of its blocks have been
+    /// reused, since the file would then hold someone else's data.
+    pub fn undelete(&mut self, inode: usize, dir: usize, name: &str) -> Result<()> {
+        self.check_writable()?;
+        let not_recoverable = |reason: String| Ext2Error::NotRecoverable { inode, reason };
+        if inode < self.first_usable_inode() {
+            return Err(not_recoverable(String::from("it's a reserved inode")));
+        }
+        if self.inode_is_allocated(inode)? {
+            return Err(not_recoverable(String::from("it's in use")));
+        }
+        let record = self.get_inode(inode)?;
+        if record.dtime == 0 || record.type_perm.bits() == 0 {
+            return Err(not_recoverable(String::from("it was never used")));
+        }
+        let mode = record.type_perm.bits();
+        if mode & 0xF000 == TypePerm::DIRECTORY.bits() {
+            // its entries, and its parent's link count, are long gone
+            return Err(not_recoverable(String::from("it's a directory")));
+        }
+        let reused = self.reused_blocks(inode)?;
+        if !reused.is_empty() {
+            return Err(not_recoverable(format!(
+                "{} of its blocks have been reused, e.g. block {}",
+                reused.len(),
+                reused[0]
+            )));
+        }
+        self.check_new_name(dir, name)?;
+
+        let blocks = self.owned_blocks(inode)?;
+        for &block in &blocks {
+            self.claim_block(block)?;
+        }
+        // directories aren't undeleted
+        self.claim_inode(inode, false)?;
+        let now = self.now();
+        let record = self.inode_mut(inode)?;
+        record.dtime = 0;
+        record.hard_links = 1;
+        record.ctime = now;
+        self.add_dir_entry(dir, name, inode, mode)?;
+        info!(
+            "undeleted inode {} ({} blocks) as {} in inode {}",
+            inode,
+            blocks.len(),
+            name,
+            dir
+        );
+        Ok(())
+    }
+
+    // the blocks deleted `inode` owned that are now marked in use, or that
+    // it can't have owned at all
+    fn reused_blocks(&self, inode: usize) -> Result<Vec<usize>> {
+        let blocks_count = self.superblock.blocks_count as usize;
+        let first_data_block = self.superblock.first_data_block as usize;
+        let blocks_per_group = self.superblock.blocks_per_group as usize;
+        // its indirect blocks may have been overwritten, and then point anywhere
+        let Ok(blocks) = self.owned_blocks(inode) else {
+            return Ok(vec![self.get_inode(inode)?.indirect_pointer as usize]);
+        };
+        let mut reused = Vec::new();
+        for block in blocks {
+            if block < first_data_block || block >= blocks_count {
+                reused.push(block);
+                continue;
+            }
+            let group = (block - first_data_block) / blocks_per_group;
+            if self
+                .block_bitmap(group)?
+                .is_set(block - self.group_first_block(group))
+            {
+                reused.push(block);
+            }
+        }
+        Ok(reused)
+    }
+
+    // names of deleted entries still legible in the slack of every directory,
+    // by the inode they pointed at
+    fn remnant_names(&self) -> Result<HashMap<usize, Vec<String>>> {
+        let mut names: HashMap<usize, Vec<String>> = HashMap::new();
+        let inodes_count = self.superblock.inodes_count as usize;
+        for dir in 1..=inodes_count {
+            if !self.inode_is_allocated(dir)? || !self.get_inode(dir)?.is_dir() {
+                continue;
+            }
+            let Ok(blocks) = self.file_blocks(dir) else {
+                continue;
+            };
+            for block_num in blocks.filter_map(|block| block.ok()).filter(|&b| b != 0) {
+                let block = self.block(block_num)?;
+                // only blocks that parse; fsck reports the rest
+                if dir_block_entries(block).is_err() {
+                    continue;
+                }
+                for (inode, name) in remnants(block, inodes_count) {
+                    names.entry(inode).or_default().push(name);
+                }
+            }
+        }
+        Ok(names)
+    }
+}
+
+// the (inode, name) of each plausible deleted entry hiding in the slack
+// after the names of the live entries in directory block `block`
+fn remnants(block: &[u8], inodes_count: usize) -> Vec<(usize, String)> {
+    let u32_at = |at: usize| u32::from_le_bytes(block[at..at + 4].try_into().unwrap());
+    let u16_at = |at: usize| u16::from_le_bytes(block[at..at + 2].try_into().unwrap());
+    let mut ret = Vec::new();
+    let mut offset = 0;
+    while offset + 8 <= block.len() {
+        let entry_size = rec_len_from_disk([block[offset + 4], block[offset + 5]], block.len());
+        let end = offset + entry_size;
+        // a live entry with no inode (the first in a block, deleted) keeps no slack
+        let used = if u32_at(offset) == 0 {
+            8
+        } else {
+            (8 + block[offset + 6] as usize).next_multiple_of(4)
+        };
+        let mut at = offset + used;
+        while at + 8 <= end {
+            let inode = u32_at(at) as usize;
+            let size = u16_at(at + 4) as usize;
+            let name_len = block[at + 6] as usize;
+            let name = block.get(at + 8..at + 8 + name_len).unwrap_or_default();
+            let plausible = inode != 0
+                && inode <= inodes_count
+                && name_len > 0
+                && at + 8 + name_len <= end
+                && size >= 8 + name_len
+                && size % 4 == 0
+                && name.iter().all(|&b| b.is_ascii_graphic() || b >= 0x80);
+            if plausible {
+                ret.push((inode, String::from_utf8_lossy(name).into_owned()));
+                at += (8 + name_len).next_multiple_of(4);
+            } else {
+                at += 4;
+            }
+        }
+        offset = end;
+    }
+    ret
+}
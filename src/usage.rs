@@ -0,0 +1,221 @@
+// How the blocks are used: per block group, and per file; and how the free
+// ones lie, in runs of consecutive blocks.
+
+use crate::{Ext2, Result};
+
+/// How the blocks of one block group are used, from its bitmap.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GroupUsage {
+    pub group: usize,
+    pub first_block: usize,
+    /// Blocks in the group; only the last group may have fewer than
+    /// `blocks_per_group`
+    pub blocks: usize,
+    /// Superblock and descriptor copies, bitmaps and inode table
+    pub metadata: usize,
+    /// Blocks in use that aren't metadata: file data, indirect blocks,
+    /// directories and so on
+    pub data: usize,
+    pub free: usize,
+    /// One character per slice of the group, left to right: `M` if the
+    /// slice holds metadata, `#` if it's all in use, `+` if partly, `.` if
+    /// it's free; empty unless asked for
+    pub map: String,
+}
+
+/// A run of a file's blocks that are contiguous on disk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Extent {
+    /// Index of its first block within the file
+    pub logical: usize,
+    pub physical: usize,
+    pub len: usize,
+}
+
+/// How scattered a file's data blocks are, from `Ext2::fragmentation`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FragReport {
+    /// Data blocks, not counting holes or indirect blocks
+    pub blocks: usize,
+    /// The data blocks as runs of consecutive blocks, in file order. A run
+    /// continues past a hole only if the next block is the one physically
+    /// after the last.
+    pub extents: Vec<Extent>,
+}
+
+/// The free blocks of one block group, from `Ext2::free_space`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GroupFreeSpace {
+    pub group: usize,
+    pub free: usize,
+    /// Runs of consecutive free blocks in the group
+    pub extents: usize,
+    /// The longest run, as (first block, length); (0, 0) if nothing is free
+    pub largest: (usize, usize),
+}
+
+/// The free runs of one range of lengths, in `FreeSpaceReport::histogram`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FreeRunBucket {
+    /// Shortest and longest run that falls in it
+    pub min_len: usize,
+    pub max_len: usize,
+    pub runs: usize,
+    pub blocks: usize,
+}
+
+/// How fragmented the free space is, from `Ext2::free_space`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FreeSpaceReport {
+    pub groups: Vec<GroupFreeSpace>,
+    /// Every group's free runs by length, in buckets of 1, 2-3, 4-7 and so
+    /// on, doubling up to the one the longest run is in; empty buckets in
+    /// between are kept
+    pub histogram: Vec<FreeRunBucket>,
+}
+
+impl Ext2 {
+    /// Count how the blocks of `group` are used. With a nonzero
+    /// `map_width`, also draw the group as that many characters (see
+    /// `GroupUsage::map`).
+    pub fn group_usage(&self, group: usize, map_width: usize) -> Result<GroupUsage> {
+        let first_block = self.group_first_block(group);
+        let blocks = self.group_blocks_count(group);
+        let bitmap = self.block_bitmap(group)?;
+        let mut is_metadata = vec![false; blocks];
+        for block in self.group_metadata_blocks(group) {
+            if let Some(flag) = block
+                .checked_sub(first_block)
+                .and_then(|i| is_metadata.get_mut(i))
+            {
+                *flag = true;
+            }
+        }
+        let used = bitmap.count_set();
+        let metadata = is_metadata.iter().filter(|&&flag| flag).count();
+        let mut map = String::new();
+        let width = map_width.min(blocks);
+        for slot in 0..width {
+            let range = slot * blocks / width..(slot + 1) * blocks / width;
+            let in_use = range.clone().filter(|&i| bitmap.is_set(i)).count();
+            map.push(if range.clone().any(|i| is_metadata[i]) {
+                'M'
+            } else if in_use == range.len() {
+                '#'
+            } else if in_use > 0 {
+                '+'
+            } else {
+                '.'
+            });
+        }
+        Ok(GroupUsage {
+            group,
+            first_block,
+            blocks,
+            metadata,
+            data: used.saturating_sub(metadata),
+            free: blocks - used,
+            map,
+        })
+    }
+
+    /// Break the data blocks of `inode` into runs that are contiguous on
+    /// disk. Device nodes, FIFOs, sockets and fast symlinks have none.
+    pub fn fragmentation(&self, inode: usize) -> Result<FragReport> {
+        let mut report = FragReport {
+            blocks: 0,
+            extents: Vec::new(),
+        };
+        // the pointers of those hold something else
+        if self.owned_blocks(inode)?.is_empty() {
+            return Ok(report);
+        }
+        for (logical, block) in self.file_blocks(inode)?.enumerate() {
+            let block = block?;
+            if block == 0 {
+                continue;
+            }
+            report.blocks += 1;
+            match report.extents.last_mut() {
+                Some(extent) if extent.physical + extent.len == block => extent.len += 1,
+                _ => report.extents.push(Extent {
+                    logical,
+                    physical: block,
+                    len: 1,
+                }),
+            }
+        }
+        Ok(report)
+    }
+
+    /// Count the free blocks of every group, the runs they make and the
+    /// longest of them, and sort the runs by length into a histogram, all
+    /// from one pass over the block bitmaps. A run stops at the end of its
+    /// group here, whether or not the next group starts with free blocks.
+    pub fn free_space(&self) -> Result<FreeSpaceReport> {
+        let mut groups = Vec::with_capacity(self.block_groups.len());
+        let mut histogram: Vec<FreeRunBucket> = Vec::new();
+        for group in 0..self.block_groups.len() {
+            let runs = self.group_free_runs(group)?;
+            for &(_, len) in &runs {
+                // bucket n holds the lengths from 2^n to 2^(n + 1) - 1
+                let bucket = len.ilog2() as usize;
+                while histogram.len() <= bucket {
+                    let min_len = 1 << histogram.len();
+                    histogram.push(FreeRunBucket {
+                        min_len,
+                        max_len: 2 * min_len - 1,
+                        runs: 0,
+                        blocks: 0,
+                    });
+                }
+                histogram[bucket].runs += 1;
+                histogram[bucket].blocks += len;
+            }
+            groups.push(GroupFreeSpace {
+                group,
+                free: runs.iter().map(|&(_, len)| len).sum(),
+                extents: runs.len(),
+                largest: longest(&runs),
+            });
+        }
+        Ok(FreeSpaceReport { groups, histogram })
+    }
+
+    /// The longest run of free blocks in `group`, or anywhere if `None`, as
+    /// (first block, length); the first one if several are as long, and
+    /// (0, 0) if there are no free blocks. Anywhere, a run carries on from
+    /// the end of one group into the next if that starts with free blocks.
+    pub fn largest_free_run(&self, group: Option<usize>) -> Result<(usize, usize)> {
+        let runs = match group {
+            Some(group) => self.group_free_runs(group)?,
+            None => self.free_runs()?,
+        };
+        Ok(longest(&runs))
+    }
+
+    // every run of free blocks in `group`, as (first block, length), in block
+    // order. The bitmap's bits past the end of a short last group aren't
+    // blocks, and aren't looked at.
+    pub(crate) fn group_free_runs(&self, group: usize) -> Result<Vec<(usize, usize)>> {
+        let first = self.group_first_block(group);
+        let bitmap = self.block_bitmap(group)?;
+        let mut runs: Vec<(usize, usize)> = Vec::new();
+        let mut index = 0;
+        while let Some(free) = bitmap.find_next_clear(index) {
+            let block = first + free;
+            match runs.last_mut() {
+                Some((start, len)) if *start + *len == block => *len += 1,
+                _ => runs.push((block, 1)),
+            }
+            index = free + 1;
+        }
+        Ok(runs)
+    }
+}
+
+// the longest of `runs`, the first of those as long; (0, 0) if there are none
+fn longest(runs: &[(usize, usize)]) -> (usize, usize) {
+    runs.iter()
+        .fold((0, 0), |best, &run| if run.1 > best.1 { run } else { best })
+}
@@ -0,0 +1,248 @@
+// Walking a directory tree one entry at a time, for everything that needs to
+// visit a whole subtree: the visitor sees each entry with the path leading to
+// it and decides whether to go on into it, past it, or stop altogether.
+//
+// A directory is only ever walked once, however many ways there are to reach
+// it, so neither a damaged image with a directory linked below itself nor a
+// symlink to an ancestor, when symlinks are followed, can make the walk go
+// round forever.
+
+use crate::progress::check_cancel;
+use crate::structs::Inode;
+use crate::{Ext2, NameKind, RawDirEntry, Result};
+use std::collections::HashSet;
+use std::sync::atomic::AtomicBool;
+
+// how many symlinks in a row are followed before giving up, as on Linux
+const MAX_LINK_HOPS: usize = 40;
+
+/// When `Ext2::walk` visits a directory, relative to what's in it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WalkOrder {
+    /// Each directory before its children, e.g. for listing a tree
+    #[default]
+    Pre,
+    /// Each directory after its children, e.g. for adding up sizes or
+    /// deleting a tree from the bottom up
+    Post,
+}
+
+/// How `Ext2::walk` goes through a tree, e.g.
+/// `WalkOptions::new().max_depth(2).order(WalkOrder::Post)`.
+#[derive(Debug, Clone, Default)]
+pub struct WalkOptions {
+    /// Visit what symlinks point to, and walk into the directories they
+    /// point to, rather than visiting the links themselves. Off by default.
+    pub follow_symlinks: bool,
+    /// How many levels below the starting directory to visit, 1 being just
+    /// its own entries; no limit if `None`.
+    pub max_depth: Option<usize>,
+    pub order: WalkOrder,
+    /// Leave out `/lost+found` and everything in it, which only e2fsck puts
+    /// anything in, e.g. so it doesn't show up when comparing trees. Off by
+    /// default.
+    pub skip_lost_and_found: bool,
+    /// A flag that, once set, ends the walk with `Ext2Error::Cancelled`
+    /// before the next entry, e.g. one set from a SIGINT handler.
+    pub cancel: Option<&'static AtomicBool>,
+}
+
+impl WalkOptions {
+    pub fn new() -> WalkOptions {
+        WalkOptions::default()
+    }
+
+    pub fn follow_symlinks(mut self, follow_symlinks: bool) -> WalkOptions {
+        self.follow_symlinks = follow_symlinks;
+        self
+    }
+
+    pub fn max_depth(mut self, max_depth: usize) -> WalkOptions {
+        self.max_depth = Some(max_depth);
+        self
+    }
+
+    pub fn order(mut self, order: WalkOrder) -> WalkOptions {
+        self.order = order;
+        self
+    }
+
+    pub fn skip_lost_and_found(mut self, skip_lost_and_found: bool) -> WalkOptions {
+        self.skip_lost_and_found = skip_lost_and_found;
+        self
+    }
+
+    pub fn cancel_on(mut self, cancel: &'static AtomicBool) -> WalkOptions {
+        self.cancel = Some(cancel);
+        self
+    }
+}
+
+/// One entry found by `Ext2::walk`.
+#[derive(Debug, Clone, Copy)]
+pub struct WalkEntry<'a> {
+    /// The names of the directories between the starting directory and this
+    /// entry, outermost first; empty for the starting directory's own entries
+    pub parents: &'a [String],
+    pub name: &'a str,
+    /// The inode the entry links to, or when following symlinks, the one a
+    /// symlink leads to
+    pub inode: usize,
+    pub record: &'a Inode,
+}
+
+impl WalkEntry<'_> {
+    /// The entry's path relative to the starting directory, e.g. `a/b/c`.
+    pub fn path(&self) -> String {
+        let mut path = String::new();
+        for parent in self.parents {
+            path.push_str(parent);
+            path.push('/');
+        }
+        path.push_str(self.name);
+        path
+    }
+
+    /// How many levels below the starting directory the entry is, 1 for its
+    /// own entries.
+    pub fn depth(&self) -> usize {
+        self.parents.len() + 1
+    }
+}
+
+/// What `Ext2::walk` should do after visiting an entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WalkControl {
+    Continue,
+    /// Don't walk into this directory. Only meaningful in pre-order: in
+    /// post-order its children have already been visited, and it's the same
+    /// as `Continue`.
+    SkipSubtree,
+    /// End the walk here
+    Stop,
+}
+
+impl Ext2 {
+    /// Call `visitor` with every entry below directory `start`, depth first,
+    /// in the order entries are stored in each directory. `.` and `..` aren't
+    /// visited, and neither is `start` itself.
+    ///
+    /// A directory reachable more than once, through hard links in a damaged
+    /// image or symlinks when they're followed, is visited each time but only
+    /// walked into the first. Symlinks are followed from the directory they're
+    /// in, but only as the whole path to the target: one whose target goes
+    /// through another symlink, or doesn't exist, is visited as the link
+    /// itself. The walk stops at the first error reading the tree, and with
+    /// `Ext2Error::Cancelled` once `options.cancel` is set.
+    pub fn walk<F>(&self, start: usize, options: &WalkOptions, visitor: &mut F) -> Result<()>
+    where
+        F: FnMut(WalkEntry<'_>) -> WalkControl,
+    {
+        let mut visited = HashSet::from([start]);
+        let mut parents = Vec::new();
+        self.walk_dir(start, options, &mut parents, &mut visited, visitor)?;
+        Ok(())
+    }
+
+    // A helper function for `walk`: visit the entries of `dir`, reached through
+    // `parents`, and walk the directories among them. Returns false once the
+    // visitor has asked to stop.
+    fn walk_dir<F>(
+        &self,
+        dir: usize,
+        options: &WalkOptions,
+        parents: &mut Vec<String>,
+        visited: &mut HashSet<usize>,
+        visitor: &mut F,
+    ) -> Result<bool>
+    where
+        F: FnMut(WalkEntry<'_>) -> WalkControl,
+    {
+        for RawDirEntry { inode, name, .. } in self.read_dir_raw(dir)? {
+            if let Some(cancel) = options.cancel {
+                check_cancel(cancel)?;
+            }
+            if name.kind() == NameKind::Dot {
+                continue;
+            }
+            let name = name.to_string();
+            let inode = if options.follow_symlinks {
+                self.follow_links(dir, inode)?
+            } else {
+                inode
+            };
+            let record = self.get_inode(inode)?;
+            if options.skip_lost_and_found && is_lost_and_found(dir, &name, record) {
+                continue;
+            }
+            if options.order == WalkOrder::Pre {
+                match visitor(WalkEntry {
+                    parents,
+                    name: &name,
+                    inode,
+                    record,
+                }) {
+                    WalkControl::Continue => {}
+                    WalkControl::SkipSubtree => continue,
+                    WalkControl::Stop => return Ok(false),
+                }
+            }
+            let within_depth = options
+                .max_depth
+                .map_or(true, |max| parents.len() + 1 < max);
+            if record.is_dir() && within_depth && visited.insert(inode) {
+                parents.push(name.clone());
+                let going = self.walk_dir(inode, options, parents, visited, visitor)?;
+                parents.pop();
+                if !going {
+                    return Ok(false);
+                }
+            }
+            if options.order == WalkOrder::Post
+                && visitor(WalkEntry {
+                    parents,
+                    name: &name,
+                    inode,
+                    record,
+                }) == WalkControl::Stop
+            {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    // the inode that `inode`, an entry of directory `dir`, leads to once any
+    // chain of symlinks is followed; the last symlink reached if the chain is
+    // broken or too long
+    fn follow_links(&self, dir: usize, mut inode: usize) -> Result<usize> {
+        let mut dir = dir;
+        for _ in 0..MAX_LINK_HOPS {
+            let record = self.get_inode(inode)?;
+            if !record.is_symlink() {
+                break;
+            }
+            let target = String::from_utf8_lossy(&self.read_link(inode)?).into_owned();
+            // the target's directory, for resolving the next link in the chain
+            let (target_dir, _) = target.rsplit_once('/').unwrap_or(("", ""));
+            let Ok(next) = self.resolve_path(dir, &target) else {
+                break;
+            };
+            if target.contains('/') {
+                let from = if target_dir.is_empty() && target.starts_with('/') {
+                    "/"
+                } else {
+                    target_dir
+                };
+                dir = self.resolve_path(dir, from)?;
+            }
+            inode = next;
+        }
+        Ok(inode)
+    }
+}
+
+/// Whether the entry `name` of directory `dir` is `/lost+found`.
+fn is_lost_and_found(dir: usize, name: &str, record: &Inode) -> bool {
+    dir == 2 && name == "lost+found" && record.is_dir()
+}
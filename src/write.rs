@@ -0,0 +1,835 @@
+// Modifying the filesystem.
+//
+// Nothing here writes to the device: every change goes through `block_mut`
+// into the dirty-block layer, and reads see the modified blocks from then on.
+// `sync` is the only way the changes reach the image.
+
+use crate::structs::{
+    self, BlockGroupDescriptor, FeatureIncompat, FeatureRoCompat, Inode, InodeFlags, Superblock,
+};
+use crate::{
+    rec_len_from_disk, rec_len_to_disk, DirSlots, Ext2, Ext2Error, InodeNo, Result,
+    EXT2_START_OF_SUPERBLOCK, EXT2_STATE_CLEAN, EXT2_SUPERBLOCK_SIZE,
+};
+use log::{debug, info};
+use std::io::{Seek, SeekFrom, Write};
+use std::mem;
+use std::sync::Arc;
+
+impl Ext2 {
+    // copy the in-memory superblock and block group descriptors into the
+    // blocks they live in, so the dirty-block layer holds the whole change
+    pub fn write_metadata(&mut self) -> Result<()> {
+        self.check_writable()?;
+        let superblock = unsafe {
+            std::slice::from_raw_parts(
+                &self.superblock as *const Superblock as *const u8,
+                mem::size_of::<Superblock>(),
+            )
+        }
+        .to_vec();
+        self.write_bytes(EXT2_START_OF_SUPERBLOCK, &superblock)?;
+        let descriptors = unsafe {
+            std::slice::from_raw_parts(
+                self.block_groups.as_ptr() as *const u8,
+                self.block_groups.len() * mem::size_of::<BlockGroupDescriptor>(),
+            )
+        }
+        .to_vec();
+        // the descriptor table starts in the block after the superblock's
+        let table = (self.superblock.first_data_block as usize + 1) * self.block_size;
+        self.write_bytes(table, &descriptors)
+    }
+
+    // copy the superblock and descriptor table to every group that keeps a
+    // backup of them, marked clean like mke2fs leaves them
+    pub(crate) fn write_backups(&mut self) -> Result<()> {
+        let descriptors = unsafe {
+            std::slice::from_raw_parts(
+                self.block_groups.as_ptr() as *const u8,
+                self.block_groups.len() * mem::size_of::<BlockGroupDescriptor>(),
+            )
+        }
+        .to_vec();
+        let backups: Vec<usize> = (1..self.block_groups.len())
+            .filter(|&group| self.group_has_superblock(group))
+            .collect();
+        for group in backups {
+            let mut superblock = self.superblock.clone();
+            superblock.block_group = group as u16;
+            superblock.state |= EXT2_STATE_CLEAN;
+            let bytes = unsafe {
+                std::slice::from_raw_parts(
+                    &superblock as *const Superblock as *const u8,
+                    mem::size_of::<Superblock>(),
+                )
+            };
+            let first = self.group_first_block(group);
+            // a backup starts its group's first block, whatever the block size
+            self.block_mut(first)?[..EXT2_SUPERBLOCK_SIZE].fill(0);
+            self.write_bytes(first * self.block_size, bytes)?;
+            self.write_bytes((first + 1) * self.block_size, &descriptors)?;
+        }
+        Ok(())
+    }
+
+    // write every block modified since the last sync to `device`, which
+    // should hold the image as that sync left it (the image the filesystem
+    // was opened from, the first time), and return how many blocks were
+    // written; with nothing modified since, nothing is, not even the write time
+    // the modified blocks stay in the dirty-block layer, since the device the
+    // filesystem reads from is never written
+    // what's on the device afterwards is consistent, so it's marked clean there,
+    // like unmounting does, while the in-memory superblock stays in use
+    // blocks are written in the order they were last modified, so if writing
+    // stops part way, a block changed by one step of an operation reaches the
+    // device before one changed only by a later step; `rename` puts the new
+    // entry in place before taking the old one away, and relies on that
+    pub fn sync<D: Write + Seek>(&mut self, device: &mut D) -> Result<usize> {
+        let written = self.write_out(device, false)?;
+        // every write went through, so these are what the device holds
+        self.synced = self
+            .dirty
+            .iter()
+            .map(|(&block_num, block)| (block_num, block.clone()))
+            .collect();
+        Ok(written)
+    }
+
+    // like `sync`, but write every block modified since opening, for a fresh
+    // copy of the image the filesystem was opened from; what the next `sync`
+    // writes is left as it was
+    pub fn sync_all<D: Write + Seek>(&mut self, device: &mut D) -> Result<usize> {
+        self.write_out(device, true)
+    }
+
+    fn write_out<D: Write + Seek>(&mut self, device: &mut D, all: bool) -> Result<usize> {
+        self.check_writable()?;
+        let state = self.superblock.state;
+        self.superblock.state |= EXT2_STATE_CLEAN;
+        // the write time only changes along with something else
+        let mut written = self.write_metadata();
+        let mut blocks = Vec::new();
+        if written.is_ok() {
+            blocks = self.unsynced(all);
+            if !blocks.is_empty() {
+                self.superblock.wtime = self.now();
+                written = self.write_metadata();
+                blocks = self.unsynced(all);
+            }
+        }
+        self.superblock.state = state;
+        written?;
+        for &block_num in &blocks {
+            device.seek(SeekFrom::Start((block_num * self.block_size) as u64))?;
+            device.write_all(&self.dirty[&block_num])?;
+        }
+        device.flush()?;
+        info!("synced {} block(s)", blocks.len());
+        Ok(blocks.len())
+    }
+
+    // the dirty blocks that differ from what `sync` last wrote, or all of them
+    // if `all`, in the order they were last modified
+    fn unsynced(&self, all: bool) -> Vec<usize> {
+        let mut blocks: Vec<usize> = self
+            .dirty
+            .iter()
+            .filter(|(block_num, block)| {
+                all || self
+                    .synced
+                    .get(block_num)
+                    .is_none_or(|synced| !Arc::ptr_eq(synced, block) && synced[..] != block[..])
+            })
+            .map(|(&block_num, _)| block_num)
+            .collect();
+        blocks.sort_by_key(|block_num| self.modified.get(block_num));
+        blocks
+    }
+
+    // write `bytes` at byte `offset` of the device, through the dirty-block layer
+    pub(crate) fn write_bytes(&mut self, offset: usize, bytes: &[u8]) -> Result<()> {
+        let block_size = self.block_size;
+        let mut written = 0;
+        while written < bytes.len() {
+            let start = (offset + written) % block_size;
+            let len = (block_size - start).min(bytes.len() - written);
+            let block = self.block_mut((offset + written) / block_size)?;
+            block[start..start + len].copy_from_slice(&bytes[written..written + len]);
+            written += len;
+        }
+        Ok(())
+    }
+
+    // create an empty directory `name` in directory `parent`, with permission
+    // bits `perm`, and return its inode number
+    pub fn create_dir(&mut self, parent: usize, name: &str, perm: u16) -> Result<usize> {
+        self.check_writable()?;
+        self.check_new_name(parent, name)?;
+        // fail before taking an inode that would then have no block
+        if self.available_blocks() == 0 {
+            return Err(Ext2Error::NoSpace);
+        }
+        let inode = self.alloc_inode(parent, true)?;
+        // keep the directory's block in the same group as its inode
+        let block_num = self.alloc_block(self.group_first_block(self.inode_group(inode)))?;
+        let block_size = self.block_size;
+        let dir_type = self.entry_type(structs::TypePerm::DIRECTORY.bits());
+        let block = self.block_mut(block_num)?;
+        write_dir_entry(block, inode, 12, ".", dir_type);
+        write_dir_entry(&mut block[12..], parent, block_size - 12, "..", dir_type);
+
+        let now = self.now();
+        let record = self.new_inode(inode)?;
+        record.type_perm =
+            structs::TypePerm::from_bits_truncate(structs::TypePerm::DIRECTORY.bits() | perm);
+        record.hard_links = 2;
+        record.size_low = block_size as u32;
+        record.sectors_count = block_size as u32 / 512;
+        record.direct_pointer[0] = block_num as u32;
+        record.atime = now;
+        record.ctime = now;
+        record.mtime = now;
+
+        if let Err(err) =
+            self.add_dir_entry(parent, name, inode, structs::TypePerm::DIRECTORY.bits())
+        {
+            // the parent is full and can't grow: give everything back
+            self.free_block(block_num)?;
+            self.free_inode(inode, true)?;
+            return Err(err);
+        }
+        // the new directory's `..` links back to the parent
+        self.inode_mut(parent)?.hard_links += 1;
+        info!(
+            "created directory {} (inode {}) in inode {}",
+            name, inode, parent
+        );
+        Ok(inode)
+    }
+
+    // return the inode of /lost+found, creating it if it doesn't exist, with
+    // blocks preallocated like mke2fs does (16KiB, or as many blocks as the
+    // direct pointers hold), so fsck can link orphans into it without having
+    // to allocate anything
+    pub fn ensure_lost_and_found(&mut self) -> Result<usize> {
+        match self.lookup(2, "lost+found")? {
+            Some(dir) => {
+                if !self.get_inode(dir)?.is_dir() {
+                    return Err(Ext2Error::NotADirectory {
+                        name: String::from("/lost+found"),
+                    });
+                }
+                Ok(dir)
+            }
+            None => {
+                let dir = self.create_dir(2, "lost+found", 0o700)?;
+                let mut size = self.block_size;
+                while size < 16 * 1024 && size / self.block_size < 12 {
+                    self.append_dir_block(dir)?;
+                    size += self.block_size;
+                }
+                Ok(dir)
+            }
+        }
+    }
+
+    // link the allocated but unreferenced `inode` into /lost+found as
+    // `#<inode>`, creating that if needed, and return the path it now has
+    pub fn relink_orphan(&mut self, inode: usize) -> Result<String> {
+        self.check_writable()?;
+        if !self.inode_is_allocated(inode)? {
+            return Err(Ext2Error::NotFound {
+                name: format!("inode {}", inode),
+            });
+        }
+        let dir = self.ensure_lost_and_found()?;
+        let name = format!("#{}", inode);
+        self.check_new_name(dir, &name)?;
+        let mode = self.get_inode(inode)?.type_perm.bits();
+        self.add_dir_entry(dir, &name, inode, mode)?;
+        let now = self.now();
+        if mode & 0xF000 == structs::TypePerm::DIRECTORY.bits() {
+            // its `..` now links to lost+found instead of its old parent,
+            // which is one link down if it's still a directory
+            let old_parent = self.lookup(inode, "..")?;
+            self.set_parent(inode, dir)?;
+            if old_parent != Some(dir) {
+                self.inode_mut(dir)?.hard_links += 1;
+            }
+            if let Some(old) = old_parent.filter(|&old| old != dir && old != inode) {
+                if matches!(self.inode_is_allocated(old), Ok(true)) && self.get_inode(old)?.is_dir()
+                {
+                    let record = self.inode_mut(old)?;
+                    record.hard_links = record.hard_links.saturating_sub(1).max(2);
+                    record.ctime = now;
+                }
+            }
+            let record = self.inode_mut(inode)?;
+            record.hard_links = record.hard_links.max(2);
+            record.ctime = now;
+        } else {
+            // the new entry is the only one
+            let record = self.inode_mut(inode)?;
+            record.hard_links = 1;
+            record.ctime = now;
+        }
+        info!("relinked inode {} as /lost+found/{}", inode, name);
+        Ok(format!("/lost+found/{}", name))
+    }
+
+    // give directory `dir` another, empty, block at the end, and return it
+    // past the 12 direct pointers the block goes under the indirect ones,
+    // which are allocated as they're needed, like a file's
+    pub(crate) fn append_dir_block(&mut self, dir: usize) -> Result<usize> {
+        let block_size = self.block_size;
+        let index = self.get_inode(dir)?.size_low as usize / block_size;
+        // after the directory's last block, or at the start of its group
+        let goal = match index.checked_sub(1) {
+            Some(last) => self.file_block(dir, last)?.map(|block_num| block_num + 1),
+            None => None,
+        }
+        .unwrap_or_else(|| self.group_first_block(self.inode_group(dir)));
+        let block_num = self.map_block(dir, index, goal)?;
+        // one unused entry spanning the whole block
+        self.block_mut(block_num)?[4..6].copy_from_slice(&rec_len_to_disk(block_size));
+        let record = self.inode_mut(dir)?;
+        record.size_low += block_size as u32;
+        debug!(
+            "added block {} (block {} of it) to directory inode {}",
+            block_num, index, dir
+        );
+        Ok(block_num)
+    }
+
+    // write `data` into regular file `inode` at byte `offset` and return how
+    // many bytes were written, allocating data blocks, and the indirect blocks
+    // to reach them, as the write needs them; blocks the file already has are
+    // written in place
+    // a write starting past the end of the file leaves a hole up to `offset`,
+    // which reads as zeros, rather than filling it with zeroed blocks
+    // if the filesystem fills up part way the file keeps what was written by
+    // then, and a short count is returned, like write(2)
+    pub fn write_file(&mut self, inode: usize, offset: u64, data: &[u8]) -> Result<usize> {
+        self.check_writable()?;
+        let record = self.get_inode(inode)?;
+        if record.is_dir() {
+            return Err(Ext2Error::IsADirectory {
+                name: format!("inode {}", inode),
+            });
+        }
+        if !record.is_regular() {
+            return Err(Ext2Error::NotPermitted {
+                name: format!("inode {}", inode),
+            });
+        }
+        self.check_write(inode, offset)?;
+        let size = record.size();
+        if data.is_empty() {
+            return Ok(0);
+        }
+        let block_size = self.block_size as u64;
+        if offset > size && size % block_size != 0 {
+            // what's left of the last block past the end must read as zeros too
+            // once the file grows over it
+            let last = (size / block_size) as usize;
+            if let Some(block_num) = self.file_block(inode, last)? {
+                let start = (size % block_size) as usize;
+                let end = if offset / block_size == size / block_size {
+                    (offset % block_size) as usize
+                } else {
+                    self.block_size
+                };
+                self.block_mut(block_num)?[start..end].fill(0);
+            }
+        }
+
+        // carry on from the block before, if there is one
+        let first = (offset / block_size) as usize;
+        let mut goal = match first.checked_sub(1) {
+            Some(before) => self
+                .file_block(inode, before)?
+                .map(|block_num| block_num + 1),
+            None => None,
+        }
+        .unwrap_or_else(|| self.group_first_block(self.inode_group(inode)));
+        let mut written = 0;
+        while written < data.len() {
+            let position = offset + written as u64;
+            let logical = (position / block_size) as usize;
+            let within = (position % block_size) as usize;
+            let len = (self.block_size - within).min(data.len() - written);
+            let block_num = match self.map_block(inode, logical, goal) {
+                Ok(block_num) => block_num,
+                Err(Ext2Error::NoSpace) if written > 0 => break,
+                Err(err) => return Err(err),
+            };
+            self.block_mut(block_num)?[within..within + len]
+                .copy_from_slice(&data[written..written + len]);
+            goal = block_num + 1;
+            written += len;
+        }
+
+        let end = offset + written as u64;
+        if end > i32::MAX as u64 {
+            // as with imported files, past 2GiB needs the large file feature
+            self.superblock.features_ronly |= FeatureRoCompat::LARGE_FILE.bits();
+        }
+        self.touch_modified(inode)?;
+        if end > size {
+            self.inode_mut(inode)?.set_size(end);
+        }
+        debug!(
+            "wrote {} byte(s) to inode {} at offset {}",
+            written, inode, offset
+        );
+        Ok(written)
+    }
+
+    // the physical block holding logical block `logical` of `inode`, or
+    // `None` if it's a hole or past the end of the file
+    fn file_block(&self, inode: usize, logical: usize) -> Result<Option<usize>> {
+        match self.file_blocks(inode)?.nth(logical) {
+            Some(block_num) => Ok(Some(block_num?).filter(|&block_num| block_num != 0)),
+            None => Ok(None),
+        }
+    }
+
+    // return the physical block holding logical block `logical` of `inode`,
+    // first allocating it, and any indirect blocks on the way to it, as close
+    // after block `goal` as possible if it's a hole
+    // the file's size is left alone; new blocks are zeroed and counted in its
+    // sectors
+    pub(crate) fn map_block(&mut self, inode: usize, logical: usize, goal: usize) -> Result<usize> {
+        let sectors = self.block_size as u32 / 512;
+        if logical < 12 {
+            let existing = self.get_inode(inode)?.direct_pointer[logical];
+            if existing != 0 {
+                return Ok(existing as usize);
+            }
+            let block_num = self.alloc_block(goal)?;
+            let record = self.inode_mut(inode)?;
+            record.direct_pointer[logical] = block_num as u32;
+            record.sectors_count += sectors;
+            return Ok(block_num);
+        }
+        let (leaf, slot) = self.map_leaf(inode, logical, goal)?;
+        let at = slot * 4;
+        let existing = u32::from_le_bytes(self.block(leaf)?[at..at + 4].try_into().unwrap());
+        if existing != 0 {
+            return Ok(existing as usize);
+        }
+        let block_num = self.alloc_block(goal)?;
+        self.block_mut(leaf)?[at..at + 4].copy_from_slice(&(block_num as u32).to_le_bytes());
+        self.inode_mut(inode)?.sectors_count += sectors;
+        Ok(block_num)
+    }
+
+    // return the indirect block holding the pointer to logical block
+    // `logical` of `inode`, which is at least 12 in, and the pointer's index
+    // in it, first allocating it and any indirect blocks above it, as close
+    // after block `goal` as possible, if they're missing
+    // the new blocks are zeroed and counted in the inode's sectors
+    pub(crate) fn map_leaf(
+        &mut self,
+        inode: usize,
+        logical: usize,
+        goal: usize,
+    ) -> Result<(usize, usize)> {
+        let sectors = self.block_size as u32 / 512;
+        let mut path = self.block_path(logical)?;
+        let depth = path.len();
+        let slot = path.pop().unwrap();
+
+        let record = self.get_inode(inode)?;
+        let top = match depth {
+            1 => record.indirect_pointer,
+            2 => record.doubly_indirect,
+            _ => record.triply_indirect,
+        };
+        let mut block_num = top as usize;
+        if block_num == 0 {
+            block_num = self.alloc_block(goal)?;
+            let record = self.inode_mut(inode)?;
+            match depth {
+                1 => record.indirect_pointer = block_num as u32,
+                2 => record.doubly_indirect = block_num as u32,
+                _ => record.triply_indirect = block_num as u32,
+            }
+            record.sectors_count += sectors;
+        }
+        for index in path {
+            let at = index * 4;
+            let next = u32::from_le_bytes(self.block(block_num)?[at..at + 4].try_into().unwrap());
+            if next != 0 {
+                block_num = next as usize;
+                continue;
+            }
+            let new_block = self.alloc_block(goal)?;
+            self.block_mut(block_num)?[at..at + 4]
+                .copy_from_slice(&(new_block as u32).to_le_bytes());
+            self.inode_mut(inode)?.sectors_count += sectors;
+            block_num = new_block;
+        }
+        Ok((block_num, slot))
+    }
+
+    // the way down the pointer tree to logical block `logical` of a file at
+    // least 12 blocks in: the index into each indirect block, top first, so
+    // there's one for each level of indirection it's under
+    pub(crate) fn block_path(&self, logical: usize) -> Result<Vec<usize>> {
+        let per_block = self.block_size / 4;
+        // its index among the blocks those levels reach
+        let mut index = logical - 12;
+        let mut depth = 1;
+        let mut reach = per_block;
+        while index >= reach {
+            index -= reach;
+            depth += 1;
+            reach *= per_block;
+            if depth > 3 {
+                return Err(Ext2Error::FileTooLarge);
+            }
+        }
+        let mut path = vec![0; depth];
+        for slot in path.iter_mut().rev() {
+            *slot = index % per_block;
+            index /= per_block;
+        }
+        Ok(path)
+    }
+
+    // create a device node, FIFO or socket `name` in directory `parent` and
+    // return its inode number; `mode` is its type and permission bits, and
+    // `device` its (major, minor) numbers if it's a device
+    // also makes the empty inode of a regular file or symlink to fill in
+    pub fn create_node(
+        &mut self,
+        parent: usize,
+        name: &str,
+        mode: u16,
+        device: Option<(u32, u32)>,
+    ) -> Result<usize> {
+        self.check_writable()?;
+        self.check_new_name(parent, name)?;
+        let inode = self.alloc_inode(parent, false)?;
+        let now = self.now();
+        let record = self.new_inode(inode)?;
+        record.type_perm = structs::TypePerm::from_bits_truncate(mode);
+        record.hard_links = 1;
+        record.atime = now;
+        record.ctime = now;
+        record.mtime = now;
+        if let Some((major, minor)) = device {
+            record.set_device(major, minor);
+        }
+        if let Err(err) = self.add_dir_entry(parent, name, inode, mode) {
+            // the parent is full and can't grow: give the inode back
+            self.free_inode(inode, false)?;
+            return Err(err);
+        }
+        info!("created {} (inode {}) in inode {}", name, inode, parent);
+        Ok(inode)
+    }
+
+    // create an empty regular file `name` in directory `parent`, with
+    // permission bits `perm`, and return its inode number
+    // if it can't be linked into the parent nothing is left allocated
+    pub fn create_file(&mut self, parent: usize, name: &str, perm: u16) -> Result<usize> {
+        self.create_node(
+            parent,
+            name,
+            structs::TypePerm::FILE.bits() | (perm & 0o7777),
+            None,
+        )
+    }
+
+    // create a symlink `name` in directory `parent` pointing at `target`, and
+    // return its inode number; like `create_file`, a failure leaves nothing
+    // allocated
+    pub fn create_symlink(&mut self, parent: usize, name: &str, target: &str) -> Result<usize> {
+        let mode = structs::TypePerm::SYMLINK.bits() | 0o777;
+        let inode = self.create_node(parent, name, mode, None)?;
+        if let Err(err) = self.write_symlink(inode, target.as_bytes()) {
+            // too long, or no block for it: take the entry out again and give
+            // back the inode and anything it got
+            self.remove_dir_entry(parent, name)?;
+            self.inode_mut(inode)?.hard_links = 0;
+            self.delete_inode(inode)?;
+            return Err(err);
+        }
+        Ok(inode)
+    }
+
+    // make sure `name` can be added to directory `parent`
+    pub(crate) fn check_new_name(&self, parent: usize, name: &str) -> Result<()> {
+        check_name(name)?;
+        let dir = self.get_inode(parent)?;
+        if !dir.is_dir() {
+            return Err(Ext2Error::NotADirectory {
+                name: format!("inode {}", parent),
+            });
+        }
+        // an append-only directory may still gain entries
+        if self.inode_flags(parent)?.contains(InodeFlags::IMMUTABLE) {
+            return Err(Ext2Error::NotPermitted {
+                name: format!("inode {}", parent),
+            });
+        }
+        if self.lookup(parent, name)?.is_some() {
+            return Err(Ext2Error::AlreadyExists {
+                name: name.to_string(),
+            });
+        }
+        Ok(())
+    }
+
+    // add an entry `name` -> `inode` to directory `dir`, in the first gap big
+    // enough to hold it: an unused entry, or the slack after an entry's name
+    // `mode` is the inode's type and permissions, for the entry's type indicator
+    pub(crate) fn add_dir_entry(
+        &mut self,
+        dir: usize,
+        name: &str,
+        inode: usize,
+        mode: u16,
+    ) -> Result<()> {
+        let needed = entry_len(name.len());
+        let type_byte = self.entry_type(mode);
+        let blocks = self.file_blocks(dir)?.collect::<Result<Vec<_>>>()?;
+        for block_num in blocks.into_iter().filter(|&block_num| block_num != 0) {
+            let mut slot = None;
+            // every slot up to the one taken is checked, so the entry written
+            // can't cross the end of the block
+            for entry in DirSlots::new(self.block(block_num)?) {
+                let entry = entry.map_err(|(offset, reason)| Ext2Error::CorruptDirectory {
+                    inode: dir,
+                    block: block_num,
+                    offset,
+                    reason,
+                })?;
+                let used = if entry.inode == 0 {
+                    0
+                } else {
+                    entry_len(entry.name.as_bytes().len())
+                };
+                if entry.rec_len >= used + needed {
+                    slot = Some((entry.offset, used, entry.rec_len));
+                    break;
+                }
+            }
+            if let Some((offset, used, entry_size)) = slot {
+                let block = self.block_mut(block_num)?;
+                if used > 0 {
+                    // shrink the existing entry to its name and take the rest
+                    block[offset + 4..offset + 6].copy_from_slice(&rec_len_to_disk(used));
+                }
+                write_dir_entry(
+                    &mut block[offset + used..],
+                    inode,
+                    entry_size - used,
+                    name,
+                    type_byte,
+                );
+                debug!(
+                    "added entry {} -> inode {} to block {}",
+                    name, inode, block_num
+                );
+                self.touch_modified(dir)?;
+                // the new entry isn't in the directory's hash index, so stop
+                // using it, as kernels that can't update one do; fsck -D rebuilds it
+                self.inode_mut(dir)?.flags &= !InodeFlags::INDEX.bits();
+                return Ok(());
+            }
+        }
+        // every block is full: the entry gets a new block to itself
+        let block_num = self.append_dir_block(dir)?;
+        let block_size = self.block_size;
+        write_dir_entry(
+            self.block_mut(block_num)?,
+            inode,
+            block_size,
+            name,
+            type_byte,
+        );
+        debug!(
+            "added entry {} -> inode {} to new block {}",
+            name, inode, block_num
+        );
+        self.touch_modified(dir)?;
+        self.inode_mut(dir)?.flags &= !InodeFlags::INDEX.bits();
+        Ok(())
+    }
+
+    // point the `..` entry of directory `dir` at `parent`
+    pub(crate) fn set_parent(&mut self, dir: usize, parent: usize) -> Result<()> {
+        let Some(block_num) = self.file_blocks(dir)?.next().transpose()? else {
+            return Err(Ext2Error::NotFound {
+                name: String::from(".."),
+            });
+        };
+        let block = self.block(block_num)?;
+        let mut offset = 0;
+        while offset + 8 <= block.len() {
+            let entry_size = rec_len_from_disk([block[offset + 4], block[offset + 5]], block.len());
+            if block[offset + 6] == 2 && &block[offset + 8..offset + 10] == b".." {
+                self.block_mut(block_num)?[offset..offset + 4]
+                    .copy_from_slice(&(parent as u32).to_le_bytes());
+                return Ok(());
+            }
+            if entry_size < 8 {
+                break;
+            }
+            offset += entry_size;
+        }
+        Err(Ext2Error::NotFound {
+            name: String::from(".."),
+        })
+    }
+
+    // the current time, by the filesystem's clock
+    pub(crate) fn now(&self) -> u32 {
+        self.clock.now()
+    }
+
+    // record that the contents of `inode` changed (which changes the inode too)
+    pub(crate) fn touch_modified(&mut self, inode: usize) -> Result<()> {
+        let now = self.now();
+        let record = self.inode_mut(inode)?;
+        record.mtime = now;
+        record.ctime = now;
+        Ok(())
+    }
+
+    // record that `inode` was read, unless the filesystem can't be written or
+    // was opened with `noatime`, in which case this does nothing
+    pub fn touch_accessed(&mut self, inode: usize) -> Result<()> {
+        if self.read_only || self.noatime || self.inode_flags(inode)?.contains(InodeFlags::NO_ATIME)
+        {
+            return Ok(());
+        }
+        let now = self.now();
+        self.inode_mut(inode)?.atime = now;
+        Ok(())
+    }
+
+    // set the access and modification times of `inode` to now, like touch(1)
+    // on a file that exists
+    pub fn touch(&mut self, inode: usize) -> Result<()> {
+        self.check_writable()?;
+        self.touch_modified(inode)?;
+        let now = self.now();
+        self.inode_mut(inode)?.atime = now;
+        Ok(())
+    }
+
+    // replace the `lsattr` flags of `inode` with `flags`
+    pub fn set_inode_flags(&mut self, inode: usize, flags: InodeFlags) -> Result<()> {
+        self.check_writable()?;
+        let now = self.now();
+        let record = self.inode_mut(inode)?;
+        record.flags = flags.bits() | (record.flags & !InodeFlags::all().bits());
+        record.ctime = now;
+        Ok(())
+    }
+
+    // fail with `Ext2Error::NotPermitted` unless the flags of `inode` allow
+    // writing its data at byte `offset`: never if it's immutable, and only
+    // at or past the end if it's append-only
+    pub fn check_write(&self, inode: usize, offset: u64) -> Result<()> {
+        let flags = self.inode_flags(inode)?;
+        if flags.contains(InodeFlags::IMMUTABLE)
+            || (flags.contains(InodeFlags::APPEND) && offset < self.get_inode(inode)?.size())
+        {
+            return Err(Ext2Error::NotPermitted {
+                name: format!("inode {}", inode),
+            });
+        }
+        Ok(())
+    }
+
+    // fail with `Ext2Error::NotPermitted` unless `inode` may be unlinked from
+    // directory `dir`: neither may be immutable or append-only
+    pub fn check_unlink(&self, dir: usize, inode: usize) -> Result<()> {
+        for checked in [dir, inode] {
+            if self
+                .inode_flags(checked)?
+                .intersects(InodeFlags::IMMUTABLE | InodeFlags::APPEND)
+            {
+                return Err(Ext2Error::NotPermitted {
+                    name: format!("inode {}", checked),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    // zero the on-disk inode `inode`, its whole slot, and return it for
+    // filling in
+    fn new_inode(&mut self, inode: usize) -> Result<&mut Inode> {
+        let (block_num, offset) = self.inode_location(inode)?;
+        let slot_size = InodeNo::slot_size(&self.superblock);
+        self.block_mut(block_num)?[offset..offset + slot_size].fill(0);
+        self.inode_mut(inode)
+    }
+
+    // the type indicator byte for a directory entry pointing at an inode with
+    // `mode`, or 0 if the filesystem doesn't store types in its entries
+    pub(crate) fn entry_type(&self, mode: u16) -> u8 {
+        if !FeatureIncompat::from_bits_truncate(self.superblock.features_req)
+            .contains(FeatureIncompat::FILETYPE)
+        {
+            return 0;
+        }
+        let type_bits = mode & 0xF000;
+        [
+            structs::TypePerm::FILE,
+            structs::TypePerm::DIRECTORY,
+            structs::TypePerm::CHAR_DEVICE,
+            structs::TypePerm::BLOCK_DEVICE,
+            structs::TypePerm::FIFO,
+            structs::TypePerm::SOCKET,
+            structs::TypePerm::SYMLINK,
+        ]
+        .iter()
+        .position(|t| t.bits() == type_bits)
+        .map_or(0, |i| i as u8 + 1)
+    }
+}
+
+// make sure `name` can be a directory entry's name at all
+pub(crate) fn check_name(name: &str) -> Result<()> {
+    if name.is_empty()
+        || name == "."
+        || name == ".."
+        || name.contains(['/', '\0'])
+        || name.len() > 255
+    {
+        return Err(Ext2Error::InvalidName {
+            name: name.to_string(),
+        });
+    }
+    Ok(())
+}
+
+// the space a directory entry with a `name_len`-byte name takes up: the 8-byte
+// header plus the name, rounded up to a multiple of 4
+pub(crate) fn entry_len(name_len: usize) -> usize {
+    (8 + name_len).next_multiple_of(4)
+}
+
+// write a directory entry header and name at the start of `buf`
+pub(crate) fn write_dir_entry(
+    buf: &mut [u8],
+    inode: usize,
+    entry_size: usize,
+    name: impl AsRef<[u8]>,
+    type_byte: u8,
+) {
+    let name = name.as_ref();
+    buf[0..4].copy_from_slice(&(inode as u32).to_le_bytes());
+    buf[4..6].copy_from_slice(&rec_len_to_disk(entry_size));
+    buf[6] = name.len() as u8;
+    buf[7] = type_byte;
+    buf[8..8 + name.len()].copy_from_slice(name);
+}
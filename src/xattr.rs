@@ -0,0 +1,199 @@
+// Extended attributes.
+//
+// An inode's attributes live in up to two places: the space after the fixed
+// part of a large (> 128 byte) inode, and the block its `ext_attribute_block`
+// points at. Both hold a list of entry descriptors, each naming an attribute
+// by a namespace index plus a suffix and pointing at its value elsewhere in
+// the same region:
+//
+//   block:    header (32 bytes) | entries ... 0u32 | ... values
+//   in-inode: magic (4 bytes)   | entries ... 0u32 | ... values
+//
+// Value offsets count from the start of the block, or from the first entry
+// in the inode. https://www.nongnu.org/ext2-doc/ext2.html#extended-attribute-layout
+
+use crate::{Ext2, Ext2Error, InodeNo, Result};
+
+const XATTR_MAGIC: u32 = 0xEA02_0000;
+const XATTR_BLOCK_HEADER_SIZE: usize = 32;
+const XATTR_ENTRY_HEADER_SIZE: usize = 16;
+// the fixed part of an inode, after which `i_extra_isize` starts
+const GOOD_OLD_INODE_SIZE: usize = 128;
+
+// name prefix for each namespace index; the two ACL indexes name the whole
+// attribute, so their entries have an empty suffix
+const NAME_PREFIXES: &[(u8, &str)] = &[
+    (1, "user."),
+    (2, "system.posix_acl_access"),
+    (3, "system.posix_acl_default"),
+    (4, "trusted."),
+    (6, "security."),
+    (7, "system."),
+    (8, "system.richacl"),
+];
+
+impl Ext2 {
+    // let go of one inode's use of extended attribute block `block`, which
+    // may be shared by inodes with the same attributes: the last one to let
+    // go frees it
+    pub(crate) fn release_xattr_block(&mut self, block: usize) -> Result<()> {
+        let refcount = u32_at(self.block(block)?, 4);
+        if refcount > 1 {
+            self.block_mut(block)?[4..8].copy_from_slice(&(refcount - 1).to_le_bytes());
+            Ok(())
+        } else {
+            self.free_block(block)
+        }
+    }
+
+    // every extended attribute of `inode` as (full name, value), the ones
+    // stored in the inode first
+    fn xattrs(&self, inode: usize) -> Result<Vec<(String, &[u8])>> {
+        let mut ret = Vec::new();
+        let record = self.get_inode(inode)?;
+        let corrupt = |reason: String| Ext2Error::CorruptXattrs { inode, reason };
+
+        let inode_size = InodeNo::slot_size(&self.superblock);
+        if inode_size > GOOD_OLD_INODE_SIZE + 4 {
+            let (block_num, offset) = self.inode_location(inode)?;
+            let bytes = self
+                .block(block_num)?
+                .get(offset..offset + inode_size)
+                .ok_or_else(|| corrupt(format!("inode size {} is too big", inode_size)))?;
+            let extra_isize = u16_at(bytes, GOOD_OLD_INODE_SIZE) as usize;
+            let start = GOOD_OLD_INODE_SIZE + extra_isize;
+            if start + 4 <= bytes.len() && u32_at(bytes, start) == XATTR_MAGIC {
+                let region = &bytes[start + 4..];
+                parse_entries(region, 0, &mut ret)
+                    .map_err(|reason| corrupt(format!("in inode: {}", reason)))?;
+            }
+        }
+
+        let block_num = record.ext_attribute_block as usize;
+        if block_num != 0 {
+            let block = self
+                .block(block_num)
+                .map_err(|e| e.in_inode("reading xattrs of", inode))?;
+            if u32_at(block, 0) != XATTR_MAGIC {
+                return Err(corrupt(format!(
+                    "block {}: bad magic {:#010x}",
+                    block_num,
+                    u32_at(block, 0)
+                )));
+            }
+            parse_entries(block, XATTR_BLOCK_HEADER_SIZE, &mut ret)
+                .map_err(|reason| corrupt(format!("block {}: {}", block_num, reason)))?;
+        }
+        Ok(ret)
+    }
+
+    /// The names of every extended attribute of `inode`, e.g. `user.comment`.
+    pub fn list_xattrs(&self, inode: usize) -> Result<Vec<String>> {
+        Ok(self
+            .xattrs(inode)?
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect())
+    }
+
+    /// The value of the extended attribute `name` of `inode`, if it has one.
+    pub fn get_xattr(&self, inode: usize, name: &str) -> Result<Option<&[u8]>> {
+        Ok(self
+            .xattrs(inode)?
+            .into_iter()
+            .find(|(entry_name, _)| entry_name == name)
+            .map(|(_, value)| value))
+    }
+}
+
+// parse the entry list starting at `first_entry` in `region`, whose value
+// offsets count from the start of `region`
+fn parse_entries<'a>(
+    region: &'a [u8],
+    first_entry: usize,
+    out: &mut Vec<(String, &'a [u8])>,
+) -> std::result::Result<(), String> {
+    let mut offset = first_entry;
+    loop {
+        if offset + 4 > region.len() {
+            return Err(format!("entry list runs past the end at offset {}", offset));
+        }
+        // the list ends with a zero where the next entry's header would be
+        if u32_at(region, offset) == 0 {
+            return Ok(());
+        }
+        if offset + XATTR_ENTRY_HEADER_SIZE > region.len() {
+            return Err(format!("entry header at offset {} crosses the end", offset));
+        }
+        let name_len = region[offset] as usize;
+        let name_index = region[offset + 1];
+        let value_offset = u16_at(region, offset + 2) as usize;
+        let value_inode = u32_at(region, offset + 4);
+        let value_size = u32_at(region, offset + 8) as usize;
+        let name_start = offset + XATTR_ENTRY_HEADER_SIZE;
+        let suffix = region
+            .get(name_start..name_start + name_len)
+            .ok_or_else(|| format!("name at offset {} crosses the end", offset))?;
+        if value_inode != 0 {
+            // values in their own inode are an ext4 feature
+            return Err(format!(
+                "entry at offset {} keeps its value in inode {}",
+                offset, value_inode
+            ));
+        }
+        let value = region
+            .get(value_offset..value_offset + value_size)
+            .ok_or_else(|| format!("value of entry at offset {} crosses the end", offset))?;
+        let prefix = NAME_PREFIXES
+            .iter()
+            .find(|(index, _)| *index == name_index)
+            .map_or(String::new(), |(_, prefix)| prefix.to_string());
+        out.push((prefix + &String::from_utf8_lossy(suffix), value));
+        offset = (name_start + name_len).next_multiple_of(4);
+    }
+}
+
+/// Render a `system.posix_acl_access`/`_default` value the way `getfacl` does,
+/// one `user::rw-` style line per entry, or `None` if it isn't a valid ACL.
+pub fn decode_posix_acl(value: &[u8]) -> Option<Vec<String>> {
+    const ACL_VERSION: u32 = 1;
+    if value.len() < 4 || u32_at(value, 0) != ACL_VERSION {
+        return None;
+    }
+    let mut lines = Vec::new();
+    let mut offset = 4;
+    while offset < value.len() {
+        let tag = u16_at(value.get(offset..offset + 4)?, 0);
+        let perm = u16_at(value, offset + 2);
+        // the owner, group, mask and other entries are short: no id
+        let (kind, id) = match tag {
+            0x01 => ("user", None),
+            0x02 => ("user", Some(u32_at(value.get(offset..offset + 8)?, 4))),
+            0x04 => ("group", None),
+            0x08 => ("group", Some(u32_at(value.get(offset..offset + 8)?, 4))),
+            0x10 => ("mask", None),
+            0x20 => ("other", None),
+            _ => return None,
+        };
+        offset += if id.is_some() { 8 } else { 4 };
+        let perm: String = [(4, 'r'), (2, 'w'), (1, 'x')]
+            .iter()
+            .map(|&(bit, letter)| if perm & bit != 0 { letter } else { '-' })
+            .collect();
+        lines.push(format!(
+            "{}:{}:{}",
+            kind,
+            id.map_or(String::new(), |id| id.to_string()),
+            perm
+        ));
+    }
+    Some(lines)
+}
+
+fn u16_at(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes(bytes[offset..offset + 2].try_into().unwrap())
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
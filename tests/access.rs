@@ -0,0 +1,112 @@
+//! `access`: which one of the owner, group and other classes of the mode
+//! applies to a user, and root's exemption from all of them except that
+//! executing needs someone to be allowed to.
+
+mod common;
+
+use common::{fixture, Image, ROOT};
+use ext2::structs::TypePerm;
+use ext2::{AccessMode, Credentials};
+
+const OWNER: Credentials = Credentials {
+    uid: 1000,
+    gid: 1000,
+};
+// not the owner, but in the file's group
+const MEMBER: Credentials = Credentials { uid: 1001, gid: 50 };
+const OTHER: Credentials = Credentials {
+    uid: 1002,
+    gid: 1002,
+};
+
+const R: AccessMode = AccessMode::READ;
+const W: AccessMode = AccessMode::WRITE;
+const X: AccessMode = AccessMode::EXEC;
+
+// a file in the root owned by 1000, in group 50
+fn file(image: &mut Image, name: &str, perm: u16) -> usize {
+    let inode = image.ext2.create_file(ROOT, name, perm).unwrap();
+    let record = image.ext2.inode_mut(inode).unwrap();
+    (record.uid, record.gid) = (1000, 50);
+    inode
+}
+
+#[test]
+fn exactly_one_class_applies() {
+    let mut image = fixture().build();
+    // (mode, who, asking for, allowed)
+    let table = [
+        (0o640, OWNER, R | W, true),
+        (0o640, OWNER, X, false),
+        (0o640, MEMBER, R, true),
+        (0o640, MEMBER, W, false),
+        (0o640, OTHER, R, false),
+        (0o604, OTHER, R, true),
+        (0o604, OTHER, R | W, false),
+        // the owner's bits apply to the owner even when the others' are
+        // more generous
+        (0o077, OWNER, R, false),
+        (0o077, MEMBER, R | W | X, true),
+        // and likewise the group's to a member
+        (0o707, MEMBER, R, false),
+        (0o707, OTHER, R | W | X, true),
+        (0o755, OTHER, R | X, true),
+        (0o755, OTHER, W, false),
+        (0o000, OWNER, AccessMode::empty(), true),
+    ];
+    for (i, &(perm, cred, mode, allowed)) in table.iter().enumerate() {
+        let inode = file(&mut image, &format!("f{}", i), perm);
+        assert_eq!(
+            image.ext2.access(inode, &cred, mode).unwrap(),
+            allowed,
+            "{:o} {:?} {:?}",
+            perm,
+            cred,
+            mode
+        );
+    }
+}
+
+#[test]
+fn root_reads_and_writes_anything_but_executes_only_what_someone_can() {
+    let mut image = fixture().dir("d", |d| d).build();
+    let root = Credentials::ROOT;
+    let table = [
+        (0o000, R | W, true),
+        (0o000, X, false),
+        (0o600, X, false),
+        // anyone's execute bit will do
+        (0o100, X, true),
+        (0o010, R | W | X, true),
+        (0o001, X, true),
+    ];
+    for (i, &(perm, mode, allowed)) in table.iter().enumerate() {
+        let inode = file(&mut image, &format!("f{}", i), perm);
+        assert_eq!(
+            image.ext2.access(inode, &root, mode).unwrap(),
+            allowed,
+            "{:o} {:?}",
+            perm,
+            mode
+        );
+    }
+    // a directory can always be searched by root, even with no bits set
+    let d = image.inode("/d");
+    image.ext2.inode_mut(d).unwrap().type_perm = TypePerm::from_bits_truncate(0o040000);
+    assert!(image.ext2.access(d, &root, R | W | X).unwrap());
+    assert!(!image.ext2.access(d, &OTHER, X).unwrap());
+}
+
+#[test]
+fn ids_past_sixteen_bits_are_compared_whole() {
+    let mut image = fixture().build();
+    let inode = file(&mut image, "f", 0o600);
+    // uid 0x1_03e8, whose low half is 1000
+    image.ext2.inode_mut(inode).unwrap()._os_specific_2[4..6].copy_from_slice(&1u16.to_le_bytes());
+    assert!(!image.ext2.access(inode, &OWNER, R).unwrap());
+    let wide = Credentials {
+        uid: 0x1_03e8,
+        gid: 1000,
+    };
+    assert!(image.ext2.access(inode, &wide, R | W).unwrap());
+}
@@ -0,0 +1,107 @@
+//! Appending through an `AppendHandle`, against writing the same bytes with
+//! `write_file`.
+
+mod common;
+
+use common::{fixture, Image, ROOT};
+use ext2::structs::InodeFlags;
+use ext2::{AppendHandle, Ext2Error};
+
+// 1,000 records, 300 bytes each, far enough to need the doubly indirect
+// block with 1K blocks
+fn records() -> Vec<Vec<u8>> {
+    (0..1000)
+        .map(|i| format!("{:>299}\n", format!("record {}", i)).into_bytes())
+        .collect()
+}
+
+fn empty_log() -> (Image, usize) {
+    empty_log_in(&fixture().block_size(1024).build().synced_bytes())
+}
+
+// in a copy of the image `bytes`, so two logs can start from the same bytes
+// however the fixtures are made (mke2fs picks a random hash seed)
+fn empty_log_in(bytes: &[u8]) -> (Image, usize) {
+    let mut image = Image::from_bytes(bytes);
+    let log = image.ext2.create_file(ROOT, "log", 0o644).unwrap();
+    (image, log)
+}
+
+#[test]
+fn same_bytes_as_one_write() {
+    let records = records();
+    let all = records.concat();
+    let base = fixture().block_size(1024).build().synced_bytes();
+
+    let (mut appended, log) = empty_log_in(&base);
+    let ext2 = &mut appended.ext2;
+    let mut handle = AppendHandle::new(log);
+    let before = ext2.generation();
+    for record in &records {
+        assert_eq!(ext2.append(&mut handle, record).unwrap(), record.len());
+    }
+    let append_touches = ext2.generation() - before;
+    assert_eq!(ext2.read_file_inode(log).unwrap(), all);
+
+    let (mut written, log) = empty_log_in(&base);
+    written.ext2.write_file(log, 0, &all).unwrap();
+    // the same blocks, allocated in the same order, so the same image
+    assert!(appended.synced_bytes() == written.synced_bytes());
+
+    let (mut by_record, log) = empty_log();
+    let ext2 = &mut by_record.ext2;
+    let before = ext2.generation();
+    for record in &records {
+        let size = ext2.get_inode(log).unwrap().size();
+        ext2.write_file(log, size, record).unwrap();
+    }
+    let write_touches = ext2.generation() - before;
+    assert_eq!(ext2.read_file_inode(log).unwrap(), all);
+    // one modification of a data block for each record either way; past
+    // those, appending updates the inode once a record where write_file
+    // does twice
+    let data = records.len() as u64;
+    assert!(
+        (append_touches - data) * 3 < (write_touches - data) * 2,
+        "{} against {}",
+        append_touches,
+        write_touches
+    );
+}
+
+#[test]
+fn after_other_changes() {
+    let (mut image, log) = empty_log();
+    let ext2 = &mut image.ext2;
+    let mut handle = AppendHandle::new(log);
+    ext2.append(&mut handle, &[b'a'; 5000]).unwrap();
+    // behind the handle's back: overwrite the end, and grow the file past
+    // a hole
+    ext2.write_file(log, 4990, &[b'b'; 10]).unwrap();
+    ext2.write_file(log, 20_000, b"c").unwrap();
+    ext2.append(&mut handle, b"de").unwrap();
+
+    let contents = ext2.read_file_inode(log).unwrap();
+    assert_eq!(contents.len(), 20_003);
+    assert_eq!(contents[4989..4991], *b"ab");
+    assert!(contents[5000..20_000].iter().all(|&byte| byte == 0));
+    assert_eq!(contents[20_000..], *b"cde");
+}
+
+#[test]
+fn append_only_and_directories() {
+    let (mut image, log) = empty_log();
+    let ext2 = &mut image.ext2;
+    ext2.write_file(log, 0, b"first\n").unwrap();
+    ext2.set_inode_flags(log, InodeFlags::APPEND).unwrap();
+    assert!(ext2.write_file(log, 0, b"x").is_err());
+    let mut handle = AppendHandle::new(log);
+    ext2.append(&mut handle, b"second\n").unwrap();
+    assert_eq!(ext2.read_file_inode(log).unwrap(), b"first\nsecond\n");
+
+    let mut handle = AppendHandle::new(ROOT);
+    assert!(matches!(
+        ext2.append(&mut handle, b"x"),
+        Err(Ext2Error::IsADirectory { .. })
+    ));
+}
@@ -0,0 +1,68 @@
+//! A filesystem whose primary superblock has been wiped is found again from
+//! a backup copy, opens through it with everything readable, and, when the
+//! backup was clean, `sync` writes the primary back so it opens normally
+//! afterwards.
+
+mod common;
+
+use common::{fixture, pattern, Image, ROOT};
+use ext2::{Ext2, Ext2Error, Inconsistency};
+
+#[test]
+fn a_zeroed_primary_superblock_opens_from_a_backup() {
+    // big enough at 1 KiB blocks for several groups, so there are backups
+    let mut image = fixture()
+        .block_size(1024)
+        .size(20 << 20)
+        .dir("docs", |d| d.file("a.txt", b"hello"))
+        .file("big", &pattern(40 << 10))
+        .build();
+    let mut bytes = image.synced_bytes();
+    bytes[1024..2048].fill(0);
+
+    assert!(!ext2::primary_superblock_ok(&bytes));
+    assert!(matches!(
+        Ext2::new(bytes.clone()),
+        Err(Ext2Error::BadSuperblock { .. })
+    ));
+    // the first backup, at the start of group 1: block 1 + 8192
+    let (group, offset) = ext2::find_backup_superblock(&bytes).unwrap();
+    assert_eq!((group, offset), (1, 8193 * 1024));
+
+    // e2fsprogs clears the clean flag in every backup it writes, to force a
+    // check when one is used, and a filesystem that isn't clean opens
+    // read-only; the backups mkfs writes are clean
+    let clean = u16::from_le_bytes([bytes[offset + 58], bytes[offset + 59]]) == 1;
+    let mut restored = Image {
+        ext2: Ext2::from_backup(bytes, offset).unwrap(),
+    };
+    let ext2 = &mut restored.ext2;
+    assert_eq!(ext2.superblock.block_group, 0);
+    assert_eq!(ext2.block_groups.len(), image.ext2.block_groups.len());
+    let a = ext2.resolve_path(ROOT, "/docs/a.txt").unwrap();
+    assert_eq!(ext2.read_file_inode(a).unwrap(), b"hello");
+    let big = ext2.resolve_path(ROOT, "/big").unwrap();
+    assert_eq!(ext2.read_file_inode(big).unwrap(), pattern(40 << 10));
+    // the backup group descriptors may still have the counts from when they
+    // were written, as e2fsck finds too, but nothing else can be off
+    assert!(ext2.check().iter().all(|problem| matches!(
+        problem,
+        Inconsistency::FreeBlocksCount { .. }
+            | Inconsistency::FreeInodesCount { .. }
+            | Inconsistency::DirsCount { .. }
+    )));
+    assert_eq!(ext2.is_read_only(), !clean);
+    if !clean {
+        return;
+    }
+    ext2.repair(|_| true).unwrap();
+    assert_eq!(ext2.check(), []);
+
+    // syncing puts the primary back
+    let synced = restored.synced_bytes();
+    assert!(ext2::primary_superblock_ok(&synced));
+    let reopened = Image::from_bytes(&synced);
+    assert_eq!(reopened.ext2.superblock.block_group, 0);
+    assert_eq!(reopened.inode("/docs/a.txt"), a);
+    assert_eq!(reopened.ext2.check(), []);
+}
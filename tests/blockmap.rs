@@ -0,0 +1,117 @@
+//! The tree of a file's block pointers, checked against `file_blocks`.
+
+mod common;
+
+use common::{fixture, ROOT};
+use ext2::{BlockMap, BlockRun, IndirectBlock, IndirectPointers};
+
+// every run of the map's data pointers, in order, holes included
+fn data_runs(map: &BlockMap) -> Vec<BlockRun> {
+    fn walk(block: &IndirectBlock, runs: &mut Vec<BlockRun>) {
+        match &block.pointers {
+            IndirectPointers::Data(data) => runs.extend(data),
+            IndirectPointers::Indirect(children) => {
+                for child in children {
+                    walk(child, runs);
+                }
+            }
+        }
+    }
+    let mut runs = map.direct.clone();
+    for block in [&map.indirect, &map.doubly_indirect, &map.triply_indirect]
+        .into_iter()
+        .flatten()
+    {
+        walk(block, &mut runs);
+    }
+    runs
+}
+
+// the block of each logical block the map has a pointer for
+fn flatten(map: &BlockMap) -> Vec<(usize, usize)> {
+    data_runs(map)
+        .iter()
+        .flat_map(|run| {
+            (0..run.len).map(move |i| {
+                let physical = if run.is_hole() { 0 } else { run.physical + i };
+                (run.logical + i, physical)
+            })
+        })
+        .collect()
+}
+
+#[test]
+fn through_the_doubly_indirect_block() {
+    // 12 direct, 256 through the indirect block, the rest through two
+    // indirect blocks under the doubly indirect one
+    let blocks = 12 + 256 + 300;
+    let image = fixture()
+        .block_size(1024)
+        .file_with_size("big", blocks * 1024)
+        .build();
+    let ext2 = &image.ext2;
+    let inode = image.inode("/big");
+    let map = ext2.block_map(inode).unwrap();
+
+    let expected: Vec<(usize, usize)> = ext2
+        .file_blocks(inode)
+        .unwrap()
+        .map(Result::unwrap)
+        .enumerate()
+        .collect();
+    assert_eq!(flatten(&map), expected);
+
+    let indirect = map.indirect.as_ref().unwrap();
+    assert_eq!(indirect.logical, 12);
+    let doubly = map.doubly_indirect.as_ref().unwrap();
+    assert_eq!(doubly.logical, 12 + 256);
+    let IndirectPointers::Indirect(children) = &doubly.pointers else {
+        panic!("{:?}", doubly.pointers);
+    };
+    let starts: Vec<usize> = children.iter().map(|child| child.logical).collect();
+    assert_eq!(starts, [268, 524]);
+    assert!(map.triply_indirect.is_none());
+    // no indirect block is also a data block
+    let data: Vec<usize> = expected.iter().map(|&(_, physical)| physical).collect();
+    assert!(!data.contains(&indirect.block));
+    assert!(!data.contains(&doubly.block));
+}
+
+#[test]
+fn holes_and_unused_pointers() {
+    let mut image = fixture().block_size(1024).file("sparse", b"").build();
+    let ext2 = &mut image.ext2;
+    let inode = ext2.resolve_path(ROOT, "/sparse").unwrap();
+    ext2.write_file(inode, 2 * 1024, b"a").unwrap();
+    ext2.write_file(inode, 20 * 1024, b"b").unwrap();
+    let map = ext2.block_map(inode).unwrap();
+
+    // holes inside the file are there, up to block 20
+    assert_eq!(map.direct.len(), 3);
+    assert!(map.direct[0].is_hole());
+    assert_eq!((map.direct[0].logical, map.direct[0].len), (0, 2));
+    assert_eq!((map.direct[1].logical, map.direct[1].len), (2, 1));
+    assert!(map.direct[2].is_hole());
+    assert_eq!((map.direct[2].logical, map.direct[2].len), (3, 9));
+    let IndirectPointers::Data(runs) = &map.indirect.as_ref().unwrap().pointers else {
+        panic!();
+    };
+    // and the pointers after it, which aren't in use, aren't
+    assert_eq!(runs.len(), 2);
+    assert!(runs[0].is_hole());
+    assert_eq!((runs[0].logical, runs[0].len), (12, 8));
+    assert_eq!((runs[1].logical, runs[1].len), (20, 1));
+    assert!(map.doubly_indirect.is_none());
+}
+
+#[test]
+fn no_blocks() {
+    let image = fixture()
+        .file("empty", b"")
+        .symlink("link", "empty")
+        .build();
+    for path in ["/empty", "/link"] {
+        let map = image.ext2.block_map(image.inode(path)).unwrap();
+        assert_eq!(map, BlockMap::default());
+    }
+}
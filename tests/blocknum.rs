@@ -0,0 +1,56 @@
+//! Block numbers count from the start of the device whatever the block
+//! size, so block N is always bytes N * block_size of the image.
+
+mod common;
+
+use common::{fixture, ROOT};
+
+#[test]
+fn block_n_is_at_n_times_block_size() {
+    for block_size in [1024, 4096] {
+        let mut image = fixture()
+            .file("hello.txt", b"hello\n")
+            .block_size(block_size)
+            .build();
+        let bytes = image.synced_bytes();
+        let ext2 = &image.ext2;
+        assert_eq!(ext2.block_size, block_size);
+        assert_eq!(ext2.device_blocks(), bytes.len() / block_size);
+        for block_num in 0..ext2.superblock.blocks_count as usize {
+            let on_device = &bytes[block_num * block_size..][..block_size];
+            assert_eq!(ext2.block(block_num).unwrap(), on_device, "{}", block_num);
+        }
+
+        // the superblock is 1024 bytes in, in block 1 or in block 0
+        let superblock_block = ext2.superblock.first_data_block as usize;
+        let superblock = ext2.block(superblock_block).unwrap();
+        let magic_at = 1024 % block_size + 56;
+        assert_eq!(superblock[magic_at..magic_at + 2], [0x53, 0xef]);
+        // and the descriptor table is in the block after it
+        let descriptors = ext2.block(superblock_block + 1).unwrap();
+        let block_bitmap = u32::from_le_bytes(descriptors[..4].try_into().unwrap());
+        assert_eq!(block_bitmap, ext2.block_groups[0].block_usage_addr);
+    }
+}
+
+#[test]
+fn writes_land_on_their_block() {
+    for block_size in [1024, 4096] {
+        let mut image = fixture()
+            .file("hello.txt", b"hello\n")
+            .block_size(block_size)
+            .build();
+        let hello = image.inode("/hello.txt");
+        let ext2 = &mut image.ext2;
+        let data_block = ext2.get_inode(hello).unwrap().direct_pointer[0] as usize;
+        ext2.block_mut(data_block).unwrap()[..6].copy_from_slice(b"howdy\n");
+        assert_eq!(ext2.read_file_inode(hello).unwrap(), b"howdy\n");
+
+        let bytes = image.synced_bytes();
+        assert_eq!(&bytes[data_block * block_size..][..6], b"howdy\n");
+        let reopened = common::Image::from_bytes(&bytes);
+        let hello = reopened.ext2.resolve_path(ROOT, "/hello.txt").unwrap();
+        assert_eq!(reopened.ext2.read_file_inode(hello).unwrap(), b"howdy\n");
+        assert_eq!(reopened.ext2.check(), []);
+    }
+}
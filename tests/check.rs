@@ -0,0 +1,288 @@
+//! `check` on images corrupted one way at a time: each kind of damage is
+//! reported as exactly the inconsistencies it causes, with the group, block
+//! or inode it's at, and nothing else.
+
+mod common;
+
+use common::{fixture, pattern, Image, ROOT};
+use ext2::{Ext2, Inconsistency};
+
+// two one-block files and an empty directory, in one group
+fn image() -> Image {
+    fixture()
+        .file("a", &pattern(1024))
+        .file("b", &pattern(1024))
+        .dir("d", |d| d)
+        .build()
+}
+
+// the group `block` is in and its bit in the group's block bitmap
+fn block_bit(ext2: &Ext2, block: usize) -> (usize, usize) {
+    let sb = &ext2.superblock;
+    let group = (block - sb.first_data_block as usize) / sb.blocks_per_group as usize;
+    (group, block - ext2.group_first_block(group))
+}
+
+// flip the block bitmap bit of `block` without touching any count
+fn flip_block_bit(ext2: &mut Ext2, block: usize) {
+    let (group, index) = block_bit(ext2, block);
+    let bitmap = ext2.block_groups[group].block_usage_addr as usize;
+    ext2.block_mut(bitmap).unwrap()[index / 8] ^= 1 << (index % 8);
+}
+
+fn first_block(ext2: &Ext2, inode: usize) -> usize {
+    ext2.get_inode(inode).unwrap().direct_pointer[0] as usize
+}
+
+#[test]
+fn a_block_in_use_marked_free() {
+    let mut image = image();
+    let a = image.inode("/a");
+    let ext2 = &mut image.ext2;
+    let block = first_block(ext2, a);
+    let (group, _) = block_bit(ext2, block);
+    let group_free = ext2.block_groups[group].free_blocks_count as u32;
+    let free = ext2.superblock.free_blocks_count;
+    flip_block_bit(ext2, block);
+
+    // and both free counts are one short of the bitmap now
+    assert_eq!(
+        ext2.check(),
+        [
+            Inconsistency::BlockMarkedFree { group, block },
+            Inconsistency::FreeBlocksCount {
+                group: Some(group),
+                recorded: group_free,
+                actual: group_free + 1,
+            },
+            Inconsistency::FreeBlocksCount {
+                group: None,
+                recorded: free,
+                actual: free + 1,
+            },
+        ]
+    );
+}
+
+#[test]
+fn an_unused_block_marked_in_use() {
+    let mut image = image();
+    let ext2 = &mut image.ext2;
+    let (group, index) = (0, ext2.block_bitmap(0).unwrap().find_first_clear().unwrap());
+    let block = ext2.group_first_block(group) + index;
+    let group_free = ext2.block_groups[group].free_blocks_count as u32;
+    let free = ext2.superblock.free_blocks_count;
+    flip_block_bit(ext2, block);
+
+    assert_eq!(
+        ext2.check(),
+        [
+            Inconsistency::BlockMarkedUsed { group, block },
+            Inconsistency::FreeBlocksCount {
+                group: Some(group),
+                recorded: group_free,
+                actual: group_free - 1,
+            },
+            Inconsistency::FreeBlocksCount {
+                group: None,
+                recorded: free,
+                actual: free - 1,
+            },
+        ]
+    );
+}
+
+#[test]
+fn free_block_counts_off() {
+    let mut image = image();
+    let ext2 = &mut image.ext2;
+    let group_free = ext2.block_groups[0].free_blocks_count as u32;
+    ext2.block_groups[0].free_blocks_count -= 3;
+    assert_eq!(
+        ext2.check(),
+        [Inconsistency::FreeBlocksCount {
+            group: Some(0),
+            recorded: group_free - 3,
+            actual: group_free,
+        }]
+    );
+
+    ext2.block_groups[0].free_blocks_count += 3;
+    let free = ext2.superblock.free_blocks_count;
+    ext2.superblock.free_blocks_count += 5;
+    assert_eq!(
+        ext2.check(),
+        [Inconsistency::FreeBlocksCount {
+            group: None,
+            recorded: free + 5,
+            actual: free,
+        }]
+    );
+}
+
+#[test]
+fn free_inode_counts_off() {
+    let mut image = image();
+    let ext2 = &mut image.ext2;
+    let group_free = ext2.block_groups[0].free_inodes_count as u32;
+    ext2.block_groups[0].free_inodes_count += 2;
+    assert_eq!(
+        ext2.check(),
+        [Inconsistency::FreeInodesCount {
+            group: Some(0),
+            recorded: group_free + 2,
+            actual: group_free,
+        }]
+    );
+
+    ext2.block_groups[0].free_inodes_count -= 2;
+    let free = ext2.superblock.free_inodes_count;
+    ext2.superblock.free_inodes_count -= 1;
+    assert_eq!(
+        ext2.check(),
+        [Inconsistency::FreeInodesCount {
+            group: None,
+            recorded: free - 1,
+            actual: free,
+        }]
+    );
+}
+
+#[test]
+fn directory_count_off() {
+    let mut image = image();
+    let ext2 = &mut image.ext2;
+    let dirs = ext2.block_groups[0].dirs_count;
+    ext2.block_groups[0].dirs_count += 1;
+    assert_eq!(
+        ext2.check(),
+        [Inconsistency::DirsCount {
+            group: 0,
+            recorded: dirs + 1,
+            actual: dirs,
+        }]
+    );
+}
+
+#[test]
+fn link_count_off() {
+    let mut image = image();
+    let (a, d) = (image.inode("/a"), image.inode("/d"));
+    let ext2 = &mut image.ext2;
+    ext2.inode_mut(a).unwrap().hard_links = 3;
+    // a directory's `.` and its entry in the parent
+    ext2.inode_mut(d).unwrap().hard_links = 1;
+    assert_eq!(
+        ext2.check(),
+        [
+            Inconsistency::LinkCount {
+                inode: a,
+                path: Some(String::from("/a")),
+                recorded: 3,
+                actual: 1,
+            },
+            Inconsistency::LinkCount {
+                inode: d,
+                path: Some(String::from("/d")),
+                recorded: 1,
+                actual: 2,
+            },
+        ]
+    );
+}
+
+#[test]
+fn an_allocated_inode_nothing_links_to() {
+    let mut image = image();
+    let ext2 = &mut image.ext2;
+    // marked in use, with the counts, but given no entry
+    let orphan = ext2.alloc_inode(ROOT, false).unwrap();
+    assert_eq!(
+        ext2.check(),
+        [Inconsistency::Unreferenced { inode: orphan }]
+    );
+}
+
+#[test]
+fn a_block_two_inodes_claim() {
+    let mut image = image();
+    let (a, b) = (image.inode("/a"), image.inode("/b"));
+    let ext2 = &mut image.ext2;
+    let shared = first_block(ext2, a);
+    let dropped = first_block(ext2, b);
+    ext2.inode_mut(b).unwrap().direct_pointer[0] = shared as u32;
+
+    // and the block b had is left marked in use by nothing
+    let (group, _) = block_bit(ext2, dropped);
+    assert_eq!(
+        ext2.check(),
+        [
+            Inconsistency::DuplicateBlock {
+                block: shared,
+                inodes: vec![a, b],
+            },
+            Inconsistency::BlockMarkedUsed {
+                group,
+                block: dropped,
+            },
+        ]
+    );
+}
+
+#[test]
+fn a_pointer_outside_the_filesystem() {
+    let mut image = image();
+    let a = image.inode("/a");
+    let ext2 = &mut image.ext2;
+    let block = ext2.superblock.blocks_count as usize + 10;
+    ext2.inode_mut(a).unwrap().direct_pointer[1] = block as u32;
+    assert_eq!(
+        ext2.check(),
+        [Inconsistency::BadBlockPointer { inode: a, block }]
+    );
+}
+
+#[test]
+fn a_directory_that_cannot_be_read_is_not_clean() {
+    let mut image = image();
+    let d = image.inode("/d");
+    let ext2 = &mut image.ext2;
+    let dropped = first_block(ext2, d);
+    let block = ext2.superblock.blocks_count as usize + 10;
+    ext2.inode_mut(d).unwrap().direct_pointer[0] = block as u32;
+
+    let problems = ext2.check();
+    assert!(
+        matches!(
+            &problems[0],
+            Inconsistency::Unreadable { inode, path: Some(path), .. } if *inode == d && path == "/d"
+        ),
+        "{:?}",
+        problems
+    );
+    // and the links from its `.` and `..` go uncounted
+    let root_links = ext2.get_inode(ROOT).unwrap().hard_links;
+    let (group, _) = block_bit(ext2, dropped);
+    assert_eq!(
+        problems[1..],
+        [
+            Inconsistency::LinkCount {
+                inode: ROOT,
+                path: Some(String::from("/")),
+                recorded: root_links,
+                actual: root_links as u32 - 1,
+            },
+            Inconsistency::LinkCount {
+                inode: d,
+                path: Some(String::from("/d")),
+                recorded: 2,
+                actual: 1,
+            },
+            Inconsistency::BadBlockPointer { inode: d, block },
+            Inconsistency::BlockMarkedUsed {
+                group,
+                block: dropped,
+            },
+        ]
+    );
+}
@@ -0,0 +1,97 @@
+//! Inode timestamps come from the clock the filesystem was opened with:
+//! each step below reopens the image with a `FixedClock` at a later time,
+//! so every atime, mtime, ctime and dtime can be checked to the second.
+
+mod common;
+
+use common::{fixture, pattern, Image, FIXTURE_TIME, ROOT};
+use ext2::{Ext2, Ext2Options, FixedClock};
+
+const CREATED: u32 = FIXTURE_TIME + 100;
+const WRITTEN: u32 = FIXTURE_TIME + 200;
+const READ: u32 = FIXTURE_TIME + 300;
+const UNLINKED: u32 = FIXTURE_TIME + 400;
+
+// the image as synced so far, opened with its clock stopped at `time`
+fn reopen(image: &mut Image, time: u32, noatime: bool) -> Image {
+    let ext2 = Ext2Options::new()
+        .clock(FixedClock(time))
+        .noatime(noatime)
+        .open(image.synced_bytes())
+        .unwrap();
+    Image { ext2 }
+}
+
+// (atime, mtime, ctime, dtime) of `inode`
+fn times(ext2: &Ext2, inode: usize) -> (u32, u32, u32, u32) {
+    let record = ext2.get_inode(inode).unwrap();
+    (record.atime, record.mtime, record.ctime, record.dtime)
+}
+
+#[test]
+fn create_write_and_unlink_stamp_the_clock() {
+    let mut image = fixture().block_size(1024).dir("dir", |d| d).build();
+    let dir = image.inode("/dir");
+    // whatever the fixture was built with: mke2fs takes the host tree's times
+    let (built, _, _, _) = times(&image.ext2, dir);
+    let root_built = times(&image.ext2, ROOT).1;
+
+    // a new file has every time set to now, and its directory was modified
+    let mut image = reopen(&mut image, CREATED, false);
+    let ext2 = &mut image.ext2;
+    let file = ext2.create_file(dir, "file", 0o644).unwrap();
+    assert_eq!(times(ext2, file), (CREATED, CREATED, CREATED, 0));
+    assert_eq!(times(ext2, dir), (built, CREATED, CREATED, 0));
+    assert_eq!(times(ext2, ROOT).1, root_built);
+
+    // writing modifies the file, and only the file
+    let mut image = reopen(&mut image, WRITTEN, false);
+    let ext2 = &mut image.ext2;
+    ext2.write_file(file, 0, &pattern(3000)).unwrap();
+    assert_eq!(times(ext2, file), (CREATED, WRITTEN, WRITTEN, 0));
+    assert_eq!(times(ext2, dir), (built, CREATED, CREATED, 0));
+
+    // reading is an access, unless the filesystem was opened with noatime
+    let mut noatime = reopen(&mut image, READ, true);
+    noatime.ext2.touch_accessed(file).unwrap();
+    assert_eq!(times(&noatime.ext2, file).0, CREATED);
+    let mut image = reopen(&mut image, READ, false);
+    let ext2 = &mut image.ext2;
+    ext2.touch_accessed(file).unwrap();
+    assert_eq!(times(ext2, file), (READ, WRITTEN, WRITTEN, 0));
+
+    // unlinking the last name deletes the inode, and modifies the directory
+    let mut image = reopen(&mut image, UNLINKED, false);
+    let ext2 = &mut image.ext2;
+    ext2.unlink(dir, "file").unwrap();
+    assert_eq!(times(ext2, file), (READ, WRITTEN, UNLINKED, UNLINKED));
+    assert_eq!(times(ext2, dir), (built, UNLINKED, UNLINKED, 0));
+
+    // and the times are what was written out
+    let reopened = Image::from_bytes(&image.synced_bytes());
+    assert_eq!(
+        times(&reopened.ext2, file),
+        (READ, WRITTEN, UNLINKED, UNLINKED)
+    );
+    assert_eq!(times(&reopened.ext2, dir).1, UNLINKED);
+}
+
+#[test]
+fn unlinking_one_of_two_names_changes_only_the_ctime() {
+    let mut image = fixture()
+        .block_size(1024)
+        .file("a", b"twice")
+        .file("b", b"twice")
+        .build();
+    let groups = image.ext2.find_duplicates(ROOT).unwrap();
+    image.ext2.link_duplicates(ROOT, &groups[0]).unwrap();
+    let file = image.inode("/a");
+    let before = times(&image.ext2, file);
+
+    let mut image = reopen(&mut image, UNLINKED, false);
+    let ext2 = &mut image.ext2;
+    ext2.unlink(ROOT, "b").unwrap();
+    assert_eq!(ext2.get_inode(file).unwrap().hard_links, 1);
+    assert_eq!(times(ext2, file), (before.0, before.1, UNLINKED, 0));
+    assert_eq!(times(ext2, ROOT).1, UNLINKED);
+}
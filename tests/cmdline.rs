@@ -0,0 +1,91 @@
+//! Splitting shell command lines into words, and expanding aliases in them.
+
+use ext2::{expand_alias, quote_word, split_words, valid_alias_name, UnclosedQuote};
+use std::collections::BTreeMap;
+
+fn words(line: &str) -> Vec<String> {
+    split_words(line).unwrap()
+}
+
+fn aliases(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+    pairs
+        .iter()
+        .map(|(name, text)| (name.to_string(), text.to_string()))
+        .collect()
+}
+
+#[test]
+fn plain_words() {
+    assert_eq!(words("ls -l  dir"), ["ls", "-l", "dir"]);
+    assert_eq!(words("  cd\t/ "), ["cd", "/"]);
+    assert!(words("").is_empty());
+    assert!(words("   ").is_empty());
+}
+
+#[test]
+fn quotes() {
+    assert_eq!(words("cat 'a file'"), ["cat", "a file"]);
+    assert_eq!(words(r#"cat "a file""#), ["cat", "a file"]);
+    // backslashes only escape quotes and themselves in double quotes
+    assert_eq!(
+        words(r#"echo "say \"hi\" \\ \n""#),
+        ["echo", r#"say "hi" \ \n"#]
+    );
+    // and nothing in single quotes
+    assert_eq!(words(r"echo 'a\b'"), ["echo", r"a\b"]);
+    assert_eq!(words(r"cat a\ file"), ["cat", "a file"]);
+    // quotes join onto what they touch
+    assert_eq!(words(r#"a"b c"'d'e"#), ["ab cde"]);
+    assert_eq!(words("touch '' x"), ["touch", "", "x"]);
+    assert_eq!(split_words("cat 'oops"), Err(UnclosedQuote('\'')));
+    assert_eq!(split_words(r#"cat "oops\""#), Err(UnclosedQuote('"')));
+}
+
+#[test]
+fn quoted_words_split_back() {
+    for word in ["plain", "a file", "it's", "", r#"say "hi""#, r"back\slash"] {
+        let line = format!("cmd {}", quote_word(word));
+        assert_eq!(words(&line), ["cmd", word], "{}", line);
+    }
+    assert_eq!(quote_word("ls"), "ls");
+    assert_eq!(quote_word("ls -l"), "'ls -l'");
+}
+
+#[test]
+fn alias_expansion() {
+    let aliases = aliases(&[
+        ("ll", "ls -l"),
+        ("greet", r#"write hello.txt "hi there""#),
+        ("ls", "ls -A"),
+        ("lll", "ll -h"),
+    ]);
+    assert_eq!(expand_alias("ll dir", &aliases), "ls -l dir");
+    assert_eq!(expand_alias("  ll", &aliases), "ls -l");
+    // the alias's quotes are read with the rest of the line
+    assert_eq!(
+        words(&expand_alias("greet 'and more'", &aliases)),
+        ["write", "hello.txt", "hi there", "and more"]
+    );
+    // once only: an alias naming itself or another runs that command
+    assert_eq!(expand_alias("ls /", &aliases), "ls -A /");
+    assert_eq!(expand_alias("lll", &aliases), "ll -h");
+    // only the first word, and only as it's typed
+    assert_eq!(expand_alias("cat ll", &aliases), "cat ll");
+    assert_eq!(expand_alias("'ll' dir", &aliases), "'ll' dir");
+    assert_eq!(expand_alias("llama", &aliases), "llama");
+    assert_eq!(expand_alias("", &aliases), "");
+}
+
+#[test]
+fn builtins_are_never_aliased() {
+    let aliases = aliases(&[("alias", "ls"), ("unalias", "ls")]);
+    assert_eq!(expand_alias("alias", &aliases), "alias");
+    assert_eq!(expand_alias("unalias ll", &aliases), "unalias ll");
+    assert!(!valid_alias_name("alias"));
+    assert!(!valid_alias_name("unalias"));
+    assert!(!valid_alias_name(""));
+    assert!(!valid_alias_name("a b"));
+    assert!(!valid_alias_name("'q'"));
+    assert!(valid_alias_name("ll"));
+    assert!(valid_alias_name("ls"));
+}
@@ -0,0 +1,122 @@
+//! The shell's `cmp` and `diff -r`: the first byte two files differ at,
+//! counted from 1 as cmp(1) does, and every name added, removed or changed
+//! between two trees, in path order.
+
+mod common;
+
+use common::{fixture, pattern, Image};
+use std::path::Path;
+use std::process::Command;
+
+fn run(image: &Path, command: &[&str]) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_ext2"))
+        .arg(image)
+        .args(command)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", command);
+    String::from_utf8(output.stdout).unwrap()
+}
+
+// `pattern(5000)` with byte `at` changed
+fn changed_at(at: usize) -> Vec<u8> {
+    let mut bytes = pattern(5000);
+    bytes[at] ^= 0xff;
+    bytes
+}
+
+fn image() -> Image {
+    fixture()
+        .block_size(1024)
+        .dir("x", |d| {
+            d.file("same", &pattern(5000))
+                .file("late", &pattern(5000))
+                .file("short", &pattern(5000))
+                .file("removed", b"only in x")
+                .file("kind", b"a file in x")
+                .dir("sub", |d| d.file("deep", b"before").dir("gone", |d| d))
+        })
+        .dir("y", |d| {
+            d.file("same", &pattern(5000))
+                // in the fifth block, past anything the first four have
+                .file("late", &changed_at(4321))
+                .file("short", &pattern(3000))
+                .file("added", b"only in y")
+                .dir("kind", |d| d)
+                .dir("sub", |d| d.file("deep", b"after!").file("new", b""))
+        })
+        .build()
+}
+
+#[test]
+fn cmp_reports_the_first_differing_byte() {
+    let mut image = image();
+    let (_dir, path) = image.dump();
+    assert_eq!(
+        run(&path, &["cmp", "/x/same", "/y/same"]),
+        "/x/same and /y/same are identical\n"
+    );
+    assert_eq!(
+        run(&path, &["cmp", "/x/late", "/y/late"]),
+        "/x/late /y/late differ: byte 4322\n"
+    );
+    // a file and a prefix of it differ only in where they end
+    assert_eq!(
+        run(&path, &["cmp", "/x/short", "/y/short"]),
+        "cmp: EOF on /y/short after byte 3000\n"
+    );
+    assert_eq!(
+        run(&path, &["cmp", "/x/same", "/x/same"]),
+        "/x/same and /x/same are identical\n"
+    );
+}
+
+#[test]
+fn first_difference_across_block_sizes() {
+    let image = image();
+    let ext2 = &image.ext2;
+    let inode = |path| image.inode(path);
+    for at in [0, 1023, 1024, 4999] {
+        let mut other = fixture()
+            .block_size(4096)
+            .file("f", &changed_at(at))
+            .build();
+        let f = other.inode("/f");
+        // the other image's blocks are four times the size
+        assert_eq!(
+            ext2.first_difference(inode("/x/same"), &other.ext2, f)
+                .unwrap(),
+            Some(at as u64)
+        );
+        other
+            .ext2
+            .write_file(f, at as u64, &pattern(5000)[at..at + 1])
+            .unwrap();
+        assert_eq!(
+            ext2.first_difference(inode("/x/same"), &other.ext2, f)
+                .unwrap(),
+            None
+        );
+    }
+}
+
+#[test]
+fn diff_lists_what_was_added_removed_and_changed() {
+    let mut image = image();
+    let (_dir, path) = image.dump();
+    assert_eq!(
+        run(&path, &["diff", "-r", "/x", "/y"]),
+        "only in B: added\n\
+         kind: regular file in A, directory in B\n\
+         late: contents differ at byte 4321\n\
+         only in A: removed\n\
+         short: sizes differ (5000 bytes in A, 3000 in B)\n\
+         sub/deep: contents differ at byte 0\n\
+         only in A: sub/gone\n\
+         only in B: sub/new\n"
+    );
+    assert_eq!(
+        run(&path, &["diff", "-r", "/x/sub", "/x/sub"]),
+        "/x/sub and /x/sub are identical\n"
+    );
+}
@@ -0,0 +1,203 @@
+//! Running e2fsprogs on image files and reading what it says, loosely: only
+//! the fields the tests compare are picked out, so a different version
+//! laying its output out a little differently doesn't break anything.
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::process::{Command, Output};
+
+/// Whether `e2fsck` and `debugfs` can be run; when they can't, the tests
+/// that need them pass without doing anything.
+pub fn available() -> bool {
+    ["e2fsck", "debugfs"].iter().all(|tool| {
+        Command::new(tool)
+            .arg("-V")
+            .output()
+            .map_or(false, |output| output.status.success())
+    })
+}
+
+fn run(command: &mut Command) -> Output {
+    command
+        .env("TZ", "UTC")
+        .output()
+        .unwrap_or_else(|err| panic!("running {:?}: {}", command, err))
+}
+
+/// `e2fsck -fn` on `image`: what it had to say if it found anything wrong,
+/// which it tells by its exit status.
+pub fn fsck(image: &Path) -> Result<(), String> {
+    let output = run(Command::new("e2fsck").arg("-fn").arg(image));
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(format!(
+            "e2fsck exited with {}:\n{}{}",
+            output.status,
+            String::from_utf8_lossy(&output.stdout),
+            String::from_utf8_lossy(&output.stderr)
+        ))
+    }
+}
+
+/// What `debugfs -R request image` writes to stdout; it always exits 0, so
+/// errors only show up as missing output.
+pub fn debugfs(image: &Path, request: &str) -> Vec<u8> {
+    run(Command::new("debugfs").arg("-R").arg(request).arg(image)).stdout
+}
+
+/// `e2fsck -fyD` on `image`, which indexes every directory big enough for
+/// it, as the kernel would have as they grew. Panics if e2fsck left anything
+/// unfixed.
+pub fn index_dirs(image: &Path) {
+    let output = run(Command::new("e2fsck").arg("-fyD").arg(image));
+    // 1: fixed something, which rebuilding the directories counts as
+    assert!(
+        matches!(output.status.code(), Some(0 | 1)),
+        "e2fsck -fyD exited with {}:\n{}{}",
+        output.status,
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+/// `debugfs -w -R request image`, for changing the image the way e2fsprogs
+/// would, e.g. `ea_set`; like `debugfs`, errors only show on stderr.
+pub fn debugfs_write(image: &Path, request: &str) {
+    let output = run(Command::new("debugfs")
+        .arg("-w")
+        .arg("-R")
+        .arg(request)
+        .arg(image));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    // it always names itself first
+    let complaints: Vec<&str> = stderr
+        .lines()
+        .filter(|line| !line.starts_with("debugfs "))
+        .collect();
+    assert!(complaints.is_empty(), "{}: {}", request, stderr);
+}
+
+/// One line of `debugfs -R "ls -l dir"`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Listed {
+    pub inode: usize,
+    pub mode: u16,
+    pub uid: u16,
+    pub gid: u16,
+    pub size: u64,
+    pub name: String,
+}
+
+/// The entries of directory `dir`, as `debugfs -R "ls -l"` lists them:
+///
+///      12  100644 (1)      0      0       3 11-May-2023 00:00 a.txt
+pub fn ls(image: &Path, dir: &str) -> Vec<Listed> {
+    let output = debugfs(image, &format!("ls -l {}", dir));
+    String::from_utf8_lossy(&output)
+        .lines()
+        .filter_map(|line| {
+            let mut rest = line;
+            let mut next = || {
+                let trimmed = rest.trim_start();
+                let end = trimmed.find(' ').unwrap_or(trimmed.len());
+                let (field, after) = trimmed.split_at(end);
+                rest = after;
+                Some(field).filter(|field| !field.is_empty())
+            };
+            let inode = next()?.parse().ok()?;
+            let mode = u16::from_str_radix(next()?, 8).ok()?;
+            let _entry_type = next()?;
+            let uid = next()?.parse().ok()?;
+            let gid = next()?.parse().ok()?;
+            let size = next()?.parse().ok()?;
+            let (_date, _time) = (next()?, next()?);
+            // names may hold spaces, so everything after the one following
+            // the time is the name
+            let name = rest.strip_prefix(' ')?.to_string();
+            Some(Listed {
+                inode,
+                mode,
+                uid,
+                gid,
+                size,
+                name,
+            })
+        })
+        .collect()
+}
+
+/// The `Key: value` fields of `debugfs -R "stat <inode>"`, e.g. "Links" ->
+/// "1" or "mtime" -> "0x6454b200:00000000". Only the first of a repeated
+/// key is kept, since the fragment line has another "Size".
+pub fn stat(image: &Path, inode: usize) -> HashMap<String, String> {
+    let output = debugfs(image, &format!("stat <{}>", inode));
+    let mut fields = HashMap::new();
+    for line in String::from_utf8_lossy(&output).lines() {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        for pair in tokens.windows(2) {
+            if let Some(key) = pair[0].strip_suffix(':') {
+                fields
+                    .entry(key.to_string())
+                    .or_insert_with(|| pair[1].to_string());
+            }
+        }
+    }
+    fields
+}
+
+/// Every block `debugfs -R "stat <inode>"` lists under BLOCKS, data and
+/// indirect alike, e.g. from "(0-11):1025-1036, (IND):1037, (12):1038".
+pub fn blocks(image: &Path, inode: usize) -> Vec<usize> {
+    let output = debugfs(image, &format!("stat <{}>", inode));
+    let output = String::from_utf8_lossy(&output);
+    let Some((_, list)) = output.split_once("BLOCKS:") else {
+        return Vec::new();
+    };
+    let mut blocks = Vec::new();
+    for range in list
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter_map(|item| item.split_once("):").map(|(_, range)| range))
+    {
+        let (first, last) = range.split_once('-').unwrap_or((range, range));
+        let (first, last): (usize, usize) = (first.parse().unwrap(), last.parse().unwrap());
+        blocks.extend(first..=last);
+    }
+    blocks.sort_unstable();
+    blocks
+}
+
+/// The contents of the file at `path`, as `debugfs -R "cat path"` reads them.
+pub fn cat(image: &Path, path: &str) -> Vec<u8> {
+    debugfs(image, &format!("cat {}", path))
+}
+
+/// The target of symlink `inode`: from the "Fast link dest" line of
+/// `debugfs -R "stat <inode>"` if it's kept in the inode, otherwise its block.
+pub fn readlink(image: &Path, inode: usize) -> Vec<u8> {
+    let output = debugfs(image, &format!("stat <{}>", inode));
+    let output = String::from_utf8_lossy(&output);
+    let fast = output.lines().find_map(|line| {
+        let (_, quoted) = line.split_once("Fast link dest: ")?;
+        Some(quoted.trim_matches('"').as_bytes().to_vec())
+    });
+    fast.unwrap_or_else(|| cat(image, &format!("<{}>", inode)))
+}
+
+/// A new image of `size` bytes made by `mke2fs` with `options`, e.g.
+/// `["-t", "ext3"]`.
+pub fn mke2fs(options: &[&str], size: usize) -> Vec<u8> {
+    let dir = super::TempDir::new("mke2fs");
+    let file = dir.path().join("image");
+    let output = run(Command::new("mke2fs")
+        .args(["-q", "-F"])
+        .args(options)
+        .arg(&file)
+        .arg(format!("{}k", size / 1024)));
+    assert!(
+        output.status.success(),
+        "mke2fs failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    std::fs::read(&file).unwrap()
+}
@@ -0,0 +1,410 @@
+//! Test images built from a description of what goes in them, rather than
+//! committed as binary files, e.g.
+//!
+//!     let image = fixture()
+//!         .dir("docs", |d| d.file("a.txt", b"hello").symlink("b", "a.txt"))
+//!         .file_with_size("big.bin", 3 * 1024 * 1024)
+//!         .build();
+//!
+//! Images are made with the crate's own mkfs and write path, with a fixed
+//! clock and UUID, so the same description always gives the same bytes. With
+//! `EXT2_FIXTURES=e2fsprogs` in the environment they're made by `mke2fs -d`
+//! from the tree written out to a temporary directory instead, so that the
+//! same tests run against images the crate had no hand in.
+
+// each test binary uses its own part of this
+#![allow(dead_code)]
+
+pub mod e2fsprogs;
+
+use ext2::{mkfs, BlockDevice, Device, Ext2, Ext2Options, FixedClock, MkfsOptions};
+use std::collections::BTreeMap;
+use std::io::{self, Cursor, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::{env, fs};
+
+pub const ROOT: usize = 2;
+/// The time every fixture is made at, 2023-05-11 00:00:00 UTC
+pub const FIXTURE_TIME: u32 = 1_683_763_200;
+const FIXTURE_UUID: &str = "6b8b4567-327b-23c6-643c-986966334873";
+const MIN_SIZE: usize = 4 << 20;
+
+/// A description of a directory's contents.
+#[derive(Debug, Clone, Default)]
+pub struct Dir {
+    entries: Vec<(String, Node)>,
+}
+
+#[derive(Debug, Clone)]
+enum Node {
+    File(Vec<u8>),
+    Symlink(String),
+    Dir(Dir),
+}
+
+impl Dir {
+    pub fn file(mut self, name: &str, contents: &[u8]) -> Dir {
+        self.entries
+            .push((name.to_string(), Node::File(contents.to_vec())));
+        self
+    }
+
+    /// A file of `size` bytes of `pattern`.
+    pub fn file_with_size(self, name: &str, size: usize) -> Dir {
+        self.file(name, &pattern(size))
+    }
+
+    pub fn symlink(mut self, name: &str, target: &str) -> Dir {
+        self.entries
+            .push((name.to_string(), Node::Symlink(target.to_string())));
+        self
+    }
+
+    /// A subdirectory, with what `contents` adds to an empty one.
+    pub fn dir(mut self, name: &str, contents: impl FnOnce(Dir) -> Dir) -> Dir {
+        let dir = contents(Dir::default());
+        self.entries.push((name.to_string(), Node::Dir(dir)));
+        self
+    }
+
+    // a generous guess at how many bytes everything below takes up
+    fn footprint(&self, block_size: usize) -> usize {
+        let mut total = 0;
+        for (_, node) in &self.entries {
+            total += block_size
+                + match node {
+                    Node::File(contents) => contents.len() + contents.len() / 64,
+                    Node::Symlink(_) => 0,
+                    Node::Dir(dir) => dir.footprint(block_size),
+                };
+        }
+        total
+    }
+}
+
+/// Bytes that are the same for the same `size`, with no block of zeros that
+/// could end up as a hole, and no two neighbouring blocks alike.
+pub fn pattern(size: usize) -> Vec<u8> {
+    (0..size).map(|i| (i % 251) as u8).collect()
+}
+
+/// A whole image: the root directory's contents and how to lay it out.
+#[derive(Debug, Clone)]
+pub struct Fixture {
+    root: Dir,
+    block_size: usize,
+    size: Option<usize>,
+}
+
+pub fn fixture() -> Fixture {
+    Fixture {
+        root: Dir::default(),
+        block_size: 1024,
+        size: None,
+    }
+}
+
+impl Fixture {
+    pub fn file(mut self, name: &str, contents: &[u8]) -> Fixture {
+        self.root = self.root.file(name, contents);
+        self
+    }
+
+    pub fn file_with_size(mut self, name: &str, size: usize) -> Fixture {
+        self.root = self.root.file_with_size(name, size);
+        self
+    }
+
+    pub fn symlink(mut self, name: &str, target: &str) -> Fixture {
+        self.root = self.root.symlink(name, target);
+        self
+    }
+
+    pub fn dir(mut self, name: &str, contents: impl FnOnce(Dir) -> Dir) -> Fixture {
+        self.root = self.root.dir(name, contents);
+        self
+    }
+
+    /// 1024 unless set.
+    pub fn block_size(mut self, block_size: usize) -> Fixture {
+        self.block_size = block_size;
+        self
+    }
+
+    /// The image's size in bytes; unless set, twice what the contents need,
+    /// and at least 4 MiB.
+    pub fn size(mut self, size: usize) -> Fixture {
+        self.size = Some(size);
+        self
+    }
+
+    pub fn build(&self) -> Image {
+        let size = self.size.unwrap_or_else(|| {
+            let needed = 2 * self.root.footprint(self.block_size);
+            (needed.max(MIN_SIZE) + (1 << 20) - 1) & !((1 << 20) - 1)
+        });
+        match env::var("EXT2_FIXTURES").as_deref() {
+            Ok("e2fsprogs") => self.build_with_mke2fs(size),
+            _ => self.build_with_mkfs(size),
+        }
+    }
+
+    fn build_with_mkfs(&self, size: usize) -> Image {
+        let mut device = vec![0; size];
+        let options = MkfsOptions::new()
+            .block_size(self.block_size)
+            .uuid(FIXTURE_UUID.parse().unwrap())
+            .clock(FixedClock(FIXTURE_TIME));
+        mkfs(&mut device, &options).unwrap();
+        let mut image = Image::open(device);
+        add_entries(&mut image.ext2, ROOT, &self.root);
+        image
+    }
+
+    fn build_with_mke2fs(&self, size: usize) -> Image {
+        let dir = TempDir::new("fixture");
+        let tree = dir.path().join("tree");
+        fs::create_dir(&tree).unwrap();
+        write_host_tree(&tree, &self.root);
+        let file = dir.path().join("image");
+        let output = Command::new("mke2fs")
+            .args(["-q", "-F", "-t", "ext2", "-U", FIXTURE_UUID, "-b"])
+            .arg(self.block_size.to_string())
+            .arg("-d")
+            .arg(&tree)
+            .arg(&file)
+            .arg(format!("{}k", size / 1024))
+            .env("E2FSPROGS_FAKE_TIME", FIXTURE_TIME.to_string())
+            .output()
+            .expect("running mke2fs");
+        assert!(
+            output.status.success(),
+            "mke2fs failed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        Image::open(fs::read(&file).unwrap())
+    }
+}
+
+fn add_entries(ext2: &mut Ext2, dir: usize, contents: &Dir) {
+    for (name, node) in &contents.entries {
+        match node {
+            Node::File(bytes) => {
+                let inode = ext2.create_file(dir, name, 0o644).unwrap();
+                ext2.write_file(inode, 0, bytes).unwrap();
+            }
+            Node::Symlink(target) => {
+                ext2.create_symlink(dir, name, target).unwrap();
+            }
+            Node::Dir(subdir) => {
+                let inode = ext2.create_dir(dir, name, 0o755).unwrap();
+                add_entries(ext2, inode, subdir);
+            }
+        }
+    }
+}
+
+fn write_host_tree(path: &Path, contents: &Dir) {
+    for (name, node) in &contents.entries {
+        let path = path.join(name);
+        match node {
+            Node::File(bytes) => fs::write(&path, bytes).unwrap(),
+            Node::Symlink(target) => std::os::unix::fs::symlink(target, &path).unwrap(),
+            Node::Dir(subdir) => {
+                fs::create_dir(&path).unwrap();
+                write_host_tree(&path, subdir);
+            }
+        }
+    }
+}
+
+/// A fixture image.
+pub struct Image {
+    pub ext2: Ext2,
+}
+
+impl Image {
+    /// Open the filesystem in a copy of `bytes`, e.g. an image file.
+    pub fn from_bytes(bytes: &[u8]) -> Image {
+        Image::open(bytes.to_vec())
+    }
+
+    // open the filesystem mkfs or mke2fs left in `device`
+    fn open(device: Vec<u8>) -> Image {
+        let ext2 = Ext2Options::new()
+            .clock(FixedClock(FIXTURE_TIME))
+            .open(device)
+            .unwrap();
+        Image { ext2 }
+    }
+
+    /// The inode at `path`.
+    pub fn inode(&self, path: &str) -> usize {
+        self.ext2.resolve_path(ROOT, path).unwrap()
+    }
+
+    /// The image as it would be on a device, every change so far included.
+    pub fn synced_bytes(&mut self) -> Vec<u8> {
+        let mut device = Cursor::new(self.ext2.device_bytes().unwrap().into_owned());
+        self.ext2.sync_all(&mut device).unwrap();
+        device.into_inner()
+    }
+
+    /// Write the image as it would be on a device to a temporary file, which
+    /// is removed when the returned `TempDir` is dropped.
+    pub fn dump(&mut self) -> (TempDir, PathBuf) {
+        let dir = TempDir::new("dump");
+        let path = dir.path().join("image.ext2");
+        fs::write(&path, self.synced_bytes()).unwrap();
+        (dir, path)
+    }
+}
+
+/// A device that doesn't let on that it's in memory, so `Ext2` reads it a
+/// block at a time, and that records every read made of it.
+pub struct CountingDevice {
+    bytes: Vec<u8>,
+    reads: Reads,
+    // set if it does let on, for `in_memory`
+    memory: Option<Device>,
+}
+
+/// The reads made of a `CountingDevice`, as (first byte, length), in the
+/// order they were made; kept after the device is handed over.
+#[derive(Clone, Default)]
+pub struct Reads(Arc<Mutex<Vec<(u64, usize)>>>);
+
+impl CountingDevice {
+    pub fn new(bytes: Vec<u8>) -> (CountingDevice, Reads) {
+        let reads = Reads::default();
+        let device = CountingDevice {
+            bytes,
+            reads: reads.clone(),
+            memory: None,
+        };
+        (device, reads)
+    }
+
+    /// A device that does let on that it's in memory, so `Ext2` should take
+    /// its blocks straight from its bytes, and read nothing once it's open.
+    pub fn in_memory(bytes: Vec<u8>) -> (CountingDevice, Reads) {
+        let (mut device, reads) = CountingDevice::new(bytes);
+        device.memory = Some(Device::copy(&device.bytes));
+        (device, reads)
+    }
+}
+
+impl BlockDevice for CountingDevice {
+    fn size(&self) -> u64 {
+        self.bytes.len() as u64
+    }
+
+    fn read_blocks(&self, first: u64, block_size: usize, buf: &mut [u8]) -> io::Result<()> {
+        let start = first as usize * block_size;
+        let bytes = self
+            .bytes
+            .get(start..start + buf.len())
+            .ok_or(io::ErrorKind::UnexpectedEof)?;
+        buf.copy_from_slice(bytes);
+        self.reads.0.lock().unwrap().push((start as u64, buf.len()));
+        Ok(())
+    }
+
+    fn bytes(&self) -> Option<&[u8]> {
+        self.memory.as_ref()?.bytes()
+    }
+}
+
+impl Reads {
+    pub fn all(&self) -> Vec<(u64, usize)> {
+        self.0.lock().unwrap().clone()
+    }
+
+    /// How many reads have been made.
+    pub fn count(&self) -> usize {
+        self.0.lock().unwrap().len()
+    }
+
+    /// How many times each block of `block_size` bytes has been read, by
+    /// block number, for those read at all.
+    pub fn per_block(&self, block_size: usize) -> BTreeMap<u64, usize> {
+        let mut counts = BTreeMap::new();
+        for (start, len) in self.all() {
+            let first = start / block_size as u64;
+            let last = (start + len as u64 + block_size as u64 - 1) / block_size as u64;
+            for block in first..last {
+                *counts.entry(block).or_default() += 1;
+            }
+        }
+        counts
+    }
+
+    pub fn clear(&self) {
+        self.0.lock().unwrap().clear();
+    }
+}
+
+/// An image in memory for `sync` to write to, recording every write made
+/// to it as (first byte, length).
+pub struct CountingWriter {
+    pub image: Cursor<Vec<u8>>,
+    pub writes: Vec<(u64, usize)>,
+}
+
+impl CountingWriter {
+    pub fn new(bytes: Vec<u8>) -> CountingWriter {
+        CountingWriter {
+            image: Cursor::new(bytes),
+            writes: Vec::new(),
+        }
+    }
+}
+
+impl Write for CountingWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let start = self.image.position();
+        let written = self.image.write(buf)?;
+        self.writes.push((start, written));
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.image.flush()
+    }
+}
+
+impl Seek for CountingWriter {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.image.seek(pos)
+    }
+}
+
+/// A directory under the system's temporary directory, removed on drop.
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    pub fn new(purpose: &str) -> TempDir {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        let path = env::temp_dir().join(format!(
+            "ext2-{}-{}-{}",
+            purpose,
+            std::process::id(),
+            COUNT.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::create_dir_all(&path).unwrap();
+        TempDir(path)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
@@ -0,0 +1,101 @@
+//! Compacting a directory after most of its entries are gone gives back the
+//! blocks they took up.
+
+mod common;
+
+use common::{fixture, Image, ROOT};
+use ext2::{Ext2, Ext2Error};
+
+// 200 bytes, so four entries to a 1K block
+fn long_name(i: usize) -> String {
+    format!("{:0>200}", i)
+}
+
+fn names(ext2: &Ext2, dir: usize) -> Vec<String> {
+    let mut names: Vec<String> = ext2
+        .read_dir_inode(dir)
+        .unwrap()
+        .iter()
+        .map(|entry| entry.name_lossy().into_owned())
+        .collect();
+    names.sort();
+    names
+}
+
+#[test]
+fn shrinks_after_mass_deletion() {
+    let mut image = fixture().build();
+    let ext2 = &mut image.ext2;
+    let dir = ext2.create_dir(ROOT, "dir", 0o755).unwrap();
+    for i in 0..200 {
+        ext2.create_file(dir, &long_name(i), 0o644).unwrap();
+    }
+    // every ninth one survives, scattered over all 50 blocks
+    for i in (0..200).filter(|i| i % 9 != 0) {
+        ext2.unlink(dir, &long_name(i)).unwrap();
+    }
+    let kept = names(ext2, dir);
+    let before = ext2.get_inode(dir).unwrap();
+    assert_eq!(before.size_low, 50 * 1024);
+    assert_ne!(before.indirect_pointer, 0);
+    let free_before = ext2.superblock.free_blocks_count;
+
+    // 23 names and the dots fit in 6 blocks; 44 data blocks and the
+    // indirect one are freed
+    assert_eq!(ext2.compact_dir(dir).unwrap(), 45);
+    let after = ext2.get_inode(dir).unwrap();
+    assert_eq!(after.size_low, 6 * 1024);
+    assert_eq!(after.indirect_pointer, 0);
+    assert_eq!(after.sectors_count, 6 * 2);
+    assert_eq!(ext2.superblock.free_blocks_count, free_before + 45);
+    assert_eq!(names(ext2, dir), kept);
+    let raw = ext2.read_dir_raw(dir).unwrap();
+    assert_eq!((raw[0].name.0, raw[0].inode), (&b"."[..], dir));
+    assert_eq!((raw[1].name.0, raw[1].inode), (&b".."[..], ROOT));
+    assert_eq!(ext2.check(), []);
+
+    // and it grows again as before
+    ext2.create_file(dir, "new", 0o644).unwrap();
+    for i in 200..210 {
+        ext2.create_file(dir, &long_name(i), 0o644).unwrap();
+    }
+    assert_eq!(ext2.check(), []);
+
+    let reopened = Image::from_bytes(&image.synced_bytes());
+    assert_eq!(names(&reopened.ext2, dir), names(&image.ext2, dir));
+    assert_eq!(reopened.ext2.check(), []);
+}
+
+#[test]
+fn a_compact_directory_is_left_alone() {
+    let mut image = fixture().dir("dir", |d| d.file("a", b"a")).build();
+    let dir = image.inode("/dir");
+    let generation = image.ext2.generation();
+    assert_eq!(image.ext2.compact_dir(dir).unwrap(), 0);
+    assert_eq!(image.ext2.generation(), generation);
+    assert_eq!(image.ext2.get_inode(dir).unwrap().size_low, 1024);
+
+    let a = image.inode("/dir/a");
+    assert!(matches!(
+        image.ext2.compact_dir(a),
+        Err(Ext2Error::NotADirectory { .. })
+    ));
+}
+
+#[test]
+fn emptied_directory_keeps_one_block() {
+    let mut image = fixture().build();
+    let ext2 = &mut image.ext2;
+    let dir = ext2.create_dir(ROOT, "dir", 0o755).unwrap();
+    for i in 0..20 {
+        ext2.create_file(dir, &long_name(i), 0o644).unwrap();
+    }
+    for i in 0..20 {
+        ext2.unlink(dir, &long_name(i)).unwrap();
+    }
+    assert_eq!(ext2.compact_dir(dir).unwrap(), 4);
+    assert_eq!(ext2.get_inode(dir).unwrap().size_low, 1024);
+    assert_eq!(names(ext2, dir), [".", ".."]);
+    ext2.remove_dir(ROOT, "dir").unwrap();
+    assert_eq!(ext2.check(), []);
+}
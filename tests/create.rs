@@ -0,0 +1,279 @@
+//! `create_file`: the inode it starts with, and where its entry goes, from
+//! the slack after an entry, to a removed entry's slot, to a new block when
+//! no block of the directory has room, and nothing left allocated when the
+//! directory can't grow, is corrupt, or a symlink's target can't be written.
+
+mod common;
+
+use common::{fixture, pattern, Image, FIXTURE_TIME, ROOT};
+use ext2::structs::InodeFlags;
+use ext2::{DirSlots, Ext2, Ext2Error};
+
+const BLOCK: usize = 1024;
+
+fn image() -> Image {
+    fixture().block_size(BLOCK).build()
+}
+
+// a name taking `len` bytes of a slot, a multiple of 4 up to 260: 8 of
+// header, the rest name
+fn name(c: char, len: usize) -> String {
+    c.to_string().repeat(len - 8)
+}
+
+// (inode, rec_len, name) of each slot of each block of `dir`
+fn slots(ext2: &Ext2, dir: usize) -> Vec<Vec<(usize, usize, String)>> {
+    ext2.file_blocks(dir)
+        .unwrap()
+        .map(|block| {
+            DirSlots::new(ext2.block(block.unwrap()).unwrap())
+                .map(|slot| {
+                    let slot = slot.unwrap();
+                    (slot.inode, slot.rec_len, slot.name.to_string())
+                })
+                .collect()
+        })
+        .collect()
+}
+
+fn free_inodes(ext2: &Ext2) -> u32 {
+    ext2.superblock.free_inodes_count
+}
+
+#[test]
+fn a_new_file_starts_empty() {
+    let mut image = image();
+    let ext2 = &mut image.ext2;
+    let (inodes, blocks) = (free_inodes(ext2), ext2.superblock.free_blocks_count);
+    let file = ext2.create_file(ROOT, "new", 0o640).unwrap();
+
+    let record = ext2.get_inode(file).unwrap();
+    assert_eq!(record.type_perm.bits(), 0o100640);
+    assert_eq!(record.hard_links, 1);
+    assert_eq!((record.size(), record.sectors_count), (0, 0));
+    assert_eq!(
+        (record.atime, record.mtime, record.ctime, record.dtime),
+        (FIXTURE_TIME, FIXTURE_TIME, FIXTURE_TIME, 0)
+    );
+    assert_eq!(record.direct_pointer, [0; 12]);
+    assert_eq!(
+        (
+            record.indirect_pointer,
+            record.doubly_indirect,
+            record.triply_indirect
+        ),
+        (0, 0, 0)
+    );
+    // an inode, and no block: the root had room for the entry
+    assert_eq!(free_inodes(ext2), inodes - 1);
+    assert_eq!(ext2.superblock.free_blocks_count, blocks);
+    let first = ext2.file_blocks(ROOT).unwrap().next().unwrap().unwrap();
+    let entry = DirSlots::new(ext2.block(first).unwrap())
+        .map(Result::unwrap)
+        .find(|slot| slot.inode == file)
+        .unwrap();
+    assert_eq!(entry.name.as_bytes(), b"new");
+    // the type indicator of a regular file
+    assert_eq!(entry.file_type, 1);
+    assert_eq!(ext2.check(), []);
+}
+
+#[test]
+fn the_slack_after_an_entry_is_split() {
+    let mut image = image();
+    let ext2 = &mut image.ext2;
+    let dir = ext2.create_dir(ROOT, "dir", 0o755).unwrap();
+    let size = ext2.get_inode(dir).unwrap().size();
+    assert_eq!(slots(ext2, dir)[0][1].1, BLOCK - 12);
+
+    let a = ext2.create_file(dir, "a", 0o644).unwrap();
+    let blocks = slots(ext2, dir);
+    assert_eq!(
+        blocks,
+        [vec![
+            (dir, 12, String::from(".")),
+            (ROOT, 12, String::from("..")),
+            (a, BLOCK - 24, String::from("a")),
+        ]]
+    );
+    assert_eq!(ext2.get_inode(dir).unwrap().size(), size);
+}
+
+#[test]
+fn a_removed_entry_is_reused() {
+    let mut image = image();
+    let ext2 = &mut image.ext2;
+    let dir = ext2.create_dir(ROOT, "dir", 0o755).unwrap();
+    // four 208-byte entries fill the first block, the fifth starts another
+    for c in ['a', 'b', 'c', 'd', 'e'] {
+        ext2.create_file(dir, &name(c, 208), 0o644).unwrap();
+    }
+    assert_eq!(slots(ext2, dir).len(), 2);
+
+    // the second name's slot goes to the first, and a new name fits there
+    ext2.unlink(dir, &name('b', 208)).unwrap();
+    let f = ext2.create_file(dir, &name('f', 208), 0o644).unwrap();
+    let blocks = slots(ext2, dir);
+    assert_eq!(blocks.len(), 2);
+    assert!(blocks[0].iter().any(|slot| slot.0 == f));
+    assert_eq!(ext2.get_inode(dir).unwrap().size(), 2 * BLOCK as u64);
+
+    // and a removed first entry of a block leaves an unused slot, reused too
+    ext2.unlink(dir, &name('e', 208)).unwrap();
+    assert_eq!(slots(ext2, dir)[1][0].0, 0);
+    let g = ext2.create_file(dir, "g", 0o644).unwrap();
+    // block 0 still had 168 bytes spare, which come first
+    assert!(slots(ext2, dir)[0].iter().any(|slot| slot.0 == g));
+    let h = ext2.create_file(dir, &name('h', 260), 0o644).unwrap();
+    assert_eq!(slots(ext2, dir)[1][0], (h, BLOCK, name('h', 260)));
+    assert_eq!(ext2.check(), []);
+}
+
+#[test]
+fn no_slot_fits_so_a_block_is_appended() {
+    let mut image = image();
+    let ext2 = &mut image.ext2;
+    let dir = ext2.create_dir(ROOT, "dir", 0o755).unwrap();
+    // `.` and `..`, four entries of 208 and 168 bytes spare in the last
+    for c in ['a', 'b', 'c', 'd'] {
+        ext2.create_file(dir, &name(c, 208), 0o644).unwrap();
+    }
+    // 172 bytes is 4 too many for that, so it starts a second block...
+    ext2.create_file(dir, &name('e', 172), 0o644).unwrap();
+    assert_eq!(slots(ext2, dir).len(), 2);
+    // ...while 168 is just right
+    let f = ext2.create_file(dir, &name('f', 168), 0o644).unwrap();
+    let blocks = slots(ext2, dir);
+    assert_eq!(blocks[0].last().unwrap(), &(f, 168, name('f', 168)));
+    assert_eq!(blocks[0].iter().map(|slot| slot.1).sum::<usize>(), BLOCK);
+
+    // fill the second block exactly too
+    for (c, len) in [('g', 260), ('h', 260), ('i', 260), ('j', 72)] {
+        ext2.create_file(dir, &name(c, len), 0o644).unwrap();
+    }
+    let blocks = slots(ext2, dir);
+    assert_eq!(blocks.len(), 2);
+    assert!(blocks.iter().all(|block| {
+        block
+            .iter()
+            .all(|&(_, rec_len, ref name)| rec_len == (8 + name.len() + 3) & !3)
+    }));
+
+    // so even the shortest name gets a block of its own, at the end
+    let record = ext2.get_inode(dir).unwrap();
+    let (size, sectors) = (record.size(), record.sectors_count);
+    let free = ext2.superblock.free_blocks_count;
+    let x = ext2.create_file(dir, "x", 0o644).unwrap();
+    let after = ext2.get_inode(dir).unwrap();
+    assert_eq!(after.size(), size + BLOCK as u64);
+    assert_eq!(after.sectors_count, sectors + BLOCK as u32 / 512);
+    assert_eq!(ext2.superblock.free_blocks_count, free - 1);
+    let blocks = slots(ext2, dir);
+    assert_eq!(blocks.len(), 3);
+    assert_eq!(blocks[2], [(x, BLOCK, String::from("x"))]);
+    assert_eq!(ext2.lookup(dir, "x").unwrap(), Some(x));
+    assert_eq!(ext2.check(), []);
+
+    let reopened = Image::from_bytes(&image.synced_bytes());
+    assert_eq!(reopened.ext2.read_dir_inode(dir).unwrap().len(), 2 + 11);
+    assert_eq!(reopened.ext2.check(), []);
+}
+
+#[test]
+fn nothing_is_left_allocated_when_the_directory_cannot_grow() {
+    let mut image = fixture().block_size(BLOCK).size(2 << 20).build();
+    let ext2 = &mut image.ext2;
+    let dir = ext2.create_dir(ROOT, "dir", 0o755).unwrap();
+    // the block full, to its last byte
+    for (c, len) in [('a', 260), ('b', 260), ('c', 260), ('d', 220)] {
+        ext2.create_file(dir, &name(c, len), 0o644).unwrap();
+    }
+    assert_eq!(slots(ext2, dir)[0].last().unwrap().1, 220);
+    // and the filesystem full
+    let fill = ext2.create_file(ROOT, "fill", 0o644).unwrap();
+    let written = ext2.write_file(fill, 0, &pattern(2 << 20)).unwrap();
+    assert!(written < 2 << 20);
+    assert_eq!(ext2.superblock.free_blocks_count, 0);
+
+    let inodes = free_inodes(ext2);
+    let groups: Vec<u16> = ext2
+        .block_groups
+        .iter()
+        .map(|group| group.free_inodes_count)
+        .collect();
+    assert!(matches!(
+        ext2.create_file(dir, "x", 0o644),
+        Err(Ext2Error::NoSpace)
+    ));
+    assert_eq!(free_inodes(ext2), inodes);
+    assert!(ext2
+        .block_groups
+        .iter()
+        .map(|group| group.free_inodes_count)
+        .eq(groups));
+    assert_eq!(ext2.lookup(dir, "x").unwrap(), None);
+    assert_eq!(slots(ext2, dir).len(), 1);
+    assert_eq!(ext2.check(), []);
+
+    // and the inode it had is handed out again once there's room
+    ext2.unlink(ROOT, "fill").unwrap();
+    let x = ext2.create_file(dir, "x", 0o644).unwrap();
+    assert_eq!(ext2.lookup(dir, "x").unwrap(), Some(x));
+    assert_eq!(ext2.check(), []);
+}
+
+#[test]
+fn a_corrupt_slot_is_an_error_not_a_panic() {
+    let mut image = image();
+    let ext2 = &mut image.ext2;
+    let dir = ext2.create_dir(ROOT, "dir", 0o755).unwrap();
+    for c in ['a', 'b', 'c', 'd', 'e'] {
+        ext2.create_file(dir, &name(c, 208), 0o644).unwrap();
+    }
+    // made an indexed directory by hand: the first block its root, sending
+    // every name to the second, and its `..` running past the end of the
+    // block. A lookup reads only the root's index and the leaf, so it's
+    // adding the entry that comes across the bad slot
+    let root = ext2.file_blocks(dir).unwrap().next().unwrap().unwrap();
+    let block = ext2.block_mut(root).unwrap();
+    block[16..18].copy_from_slice(&2000u16.to_le_bytes());
+    block[24..].fill(0);
+    // half-MD4 hashes, 8 bytes of root info, no levels below the root
+    block[28..31].copy_from_slice(&[1, 8, 0]);
+    let limit = (BLOCK as u16 - 32) / 8;
+    block[32..34].copy_from_slice(&limit.to_le_bytes());
+    block[34..36].copy_from_slice(&1u16.to_le_bytes());
+    block[36..40].copy_from_slice(&1u32.to_le_bytes());
+    ext2.inode_mut(dir).unwrap().flags |= InodeFlags::INDEX.bits();
+    assert_eq!(ext2.lookup(dir, "x").unwrap(), None);
+
+    let inodes = free_inodes(ext2);
+    let err = ext2.create_file(dir, "x", 0o644).unwrap_err();
+    assert!(
+        matches!(
+            err.root_cause(),
+            Ext2Error::CorruptDirectory { inode, block, offset: 12, .. }
+                if *inode == dir && *block == root
+        ),
+        "{}",
+        err
+    );
+    assert_eq!(free_inodes(ext2), inodes);
+    assert_eq!(ext2.lookup(dir, "x").unwrap(), None);
+}
+
+#[test]
+fn a_symlink_that_cannot_be_written_is_taken_back() {
+    let mut image = image();
+    let ext2 = &mut image.ext2;
+    let (inodes, blocks) = (free_inodes(ext2), ext2.superblock.free_blocks_count);
+    let target = "t".repeat(BLOCK);
+    assert!(matches!(
+        ext2.create_symlink(ROOT, "link", &target),
+        Err(Ext2Error::InvalidName { .. })
+    ));
+    assert_eq!(ext2.lookup(ROOT, "link").unwrap(), None);
+    assert_eq!(free_inodes(ext2), inodes);
+    assert_eq!(ext2.superblock.free_blocks_count, blocks);
+    assert_eq!(ext2.check(), []);
+}
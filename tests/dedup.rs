@@ -0,0 +1,65 @@
+//! Finding duplicate files, and replacing the copies with hard links.
+
+mod common;
+
+use common::{fixture, pattern, ROOT};
+
+const SIZE: usize = 3000;
+
+#[test]
+fn find_and_link() {
+    let mut other = pattern(SIZE);
+    other[SIZE - 1] ^= 1;
+    let mut image = fixture()
+        .file_with_size("a", SIZE)
+        .dir("dir", |d| d.file_with_size("b", SIZE))
+        // the same size, but not the same contents
+        .file("c", &other)
+        .file("empty1", b"")
+        .file("empty2", b"")
+        .build();
+    let (a, b) = (image.inode("/a"), image.inode("/dir/b"));
+    let ext2 = &mut image.ext2;
+
+    let groups = ext2.find_duplicates(ROOT).unwrap();
+    assert_eq!(groups.len(), 1);
+    let group = &groups[0];
+    assert_eq!(group.size, SIZE as u64);
+    assert_eq!(group.wasted(), SIZE as u64);
+    let paths: Vec<&[String]> = group.files.iter().map(|f| &f.paths[..]).collect();
+    assert_eq!(paths, [["a"], ["dir/b"]]);
+    assert_eq!(group.files[0].inode, a);
+
+    let free = ext2.superblock.free_blocks_count;
+    assert_eq!(ext2.link_duplicates(ROOT, group).unwrap(), 1);
+    assert_eq!(ext2.resolve_path(ROOT, "/dir/b").unwrap(), a);
+    assert_eq!(ext2.get_inode(a).unwrap().hard_links, 2);
+    assert!(!ext2.inode_is_allocated(b).unwrap());
+    assert!(ext2.superblock.free_blocks_count > free);
+    assert_eq!(ext2.read_file_inode(a).unwrap(), pattern(SIZE));
+    assert!(ext2.find_duplicates(ROOT).unwrap().is_empty());
+    assert_eq!(ext2.check(), []);
+
+    // hard links are one file, shown with all its paths
+    let copy = ext2.create_file(ROOT, "copy", 0o644).unwrap();
+    ext2.write_file(copy, 0, &pattern(SIZE)).unwrap();
+    let groups = ext2.find_duplicates(ROOT).unwrap();
+    assert_eq!(groups.len(), 1);
+    assert_eq!(groups[0].files.len(), 2);
+    assert_eq!(groups[0].files[0].paths, ["a", "dir/b"]);
+    assert_eq!(groups[0].files[1].paths, ["copy"]);
+}
+
+#[test]
+fn changed_files_are_left_alone() {
+    let mut image = fixture()
+        .file_with_size("a", SIZE)
+        .file_with_size("b", SIZE)
+        .build();
+    let b = image.inode("/b");
+    let ext2 = &mut image.ext2;
+    let group = ext2.find_duplicates(ROOT).unwrap().remove(0);
+    ext2.write_file(b, 0, b"changed").unwrap();
+    assert_eq!(ext2.link_duplicates(ROOT, &group).unwrap(), 0);
+    assert_eq!(ext2.resolve_path(ROOT, "/b").unwrap(), b);
+}
@@ -0,0 +1,74 @@
+//! `defrag` on a file written in interleaved pieces: afterwards it's in
+//! fewer extents with the same contents, and nothing it pointed to was let
+//! go of before the inode was switched over to the copy.
+
+mod common;
+
+use common::{fixture, pattern, CountingWriter, ROOT};
+use ext2::{Ext2, InodeNo};
+
+const BLOCKS: usize = 40;
+
+// the block of the inode table holding `inode`
+fn table_block(ext2: &Ext2, inode: usize) -> usize {
+    let (group, index) = InodeNo(inode).to_group_and_index(&ext2.superblock).unwrap();
+    let offset = index * InodeNo::slot_size(&ext2.superblock);
+    ext2.block_groups[group].inode_table_block as usize + offset / ext2.block_size
+}
+
+#[test]
+fn defrag_joins_a_file_up_and_frees_the_old_blocks_last() {
+    let mut image = fixture().block_size(1024).build();
+    let ext2 = &mut image.ext2;
+    // a block of f then a block of g, over and over, and then g goes, so
+    // f is left every other block, into its indirect block
+    let f = ext2.create_file(ROOT, "f", 0o644).unwrap();
+    let g = ext2.create_file(ROOT, "g", 0o644).unwrap();
+    let contents = pattern(BLOCKS * 1024);
+    for (i, chunk) in contents.chunks(1024).enumerate() {
+        ext2.write_file(f, i as u64 * 1024, chunk).unwrap();
+        ext2.write_file(g, i as u64 * 1024, chunk).unwrap();
+    }
+    ext2.unlink(ROOT, "g").unwrap();
+    let before = ext2.fragmentation(f).unwrap().extents.len();
+    assert!(before >= BLOCKS / 2, "{}", before);
+    let old_blocks = ext2.owned_blocks(f).unwrap();
+    let free = ext2.superblock.free_blocks_count;
+
+    // everything so far on the device, so the next sync writes only what
+    // defrag changes, in the order it last changed each block
+    let mut device = CountingWriter::new(ext2.device_bytes().unwrap().into_owned());
+    ext2.sync(&mut device).unwrap();
+    device.writes.clear();
+
+    let report = ext2.defrag(f).unwrap();
+    assert!(report.moved);
+    assert_eq!(report.blocks, BLOCKS);
+    assert_eq!(report.extents_before, before);
+    assert_eq!(report.extents_after, 1);
+    assert_eq!(ext2.fragmentation(f).unwrap().extents.len(), 1);
+    assert_eq!(ext2.read_file_inode(f).unwrap(), contents);
+    let new_blocks = ext2.owned_blocks(f).unwrap();
+    assert_eq!(new_blocks.len(), old_blocks.len());
+    assert!(new_blocks.iter().all(|block| !old_blocks.contains(block)));
+    assert_eq!(ext2.superblock.free_blocks_count, free);
+    assert_eq!(ext2.check(), []);
+
+    ext2.sync(&mut device).unwrap();
+    let order: Vec<usize> = device
+        .writes
+        .iter()
+        .map(|&(offset, _)| offset as usize / 1024)
+        .collect();
+    let at = |block: usize| order.iter().position(|&b| b == block).unwrap();
+    let inode_at = at(table_block(ext2, f));
+    // the copy was finished before the inode pointed at it
+    for &block in &new_blocks {
+        assert!(at(block) < inode_at, "{} in {:?}", block, order);
+    }
+    // and the bitmap last changed, freeing the old blocks, after that
+    let bitmap = ext2.block_groups[0].block_usage_addr as usize;
+    assert!(at(bitmap) > inode_at, "{:?}", order);
+    // nothing was written over the old blocks themselves
+    assert!(old_blocks.iter().all(|block| !order.contains(block)));
+}
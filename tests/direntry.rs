@@ -0,0 +1,59 @@
+//! Directory listings as owned `DirEntry`s, which keep the type indicator
+//! and outlive changes to the directory.
+
+mod common;
+
+use common::{fixture, ROOT};
+use ext2::structs::FileType;
+use ext2::DirEntry;
+
+#[test]
+fn owned_and_typed() {
+    let mut image = fixture()
+        .file("a.txt", b"a")
+        .dir("docs", |dir| dir)
+        .symlink("link", "a.txt")
+        .build();
+    let ext2 = &mut image.ext2;
+    let listing = ext2.read_dir_inode(ROOT).unwrap();
+    let typed: Vec<(&[u8], Option<FileType>)> = listing
+        .iter()
+        .map(|entry| (entry.name.as_slice(), entry.file_type))
+        .collect();
+    for expected in [
+        (&b"."[..], Some(FileType::Dir)),
+        (b"..", Some(FileType::Dir)),
+        (b"a.txt", Some(FileType::Regular)),
+        (b"docs", Some(FileType::Dir)),
+        (b"link", Some(FileType::Symlink)),
+    ] {
+        assert!(typed.contains(&expected), "{:?}", expected);
+    }
+    assert_eq!(listing.iter().filter(|entry| entry.is_dot()).count(), 2);
+
+    // still there after the directory changes, and the same as the raw entries
+    ext2.unlink(ROOT, "a.txt").unwrap();
+    let a = listing.iter().find(|entry| entry.name == b"a.txt").unwrap();
+    assert_eq!(a.name_lossy(), "a.txt");
+    assert!(ext2.lookup(ROOT, "a.txt").unwrap().is_none());
+    let raw = ext2.read_dir_raw(ROOT).unwrap();
+    assert_eq!(raw.len(), listing.len() - 1);
+    assert!(raw.iter().all(|entry| entry.name.0 != b"a.txt"));
+
+    // and can go to another thread
+    let handle = std::thread::spawn(move || listing.into_iter().map(|entry| entry.inode).sum());
+    let total: usize = handle.join().unwrap();
+    assert!(total > 0);
+}
+
+#[test]
+fn type_indicators() {
+    let entry = DirEntry {
+        inode: 12,
+        name: b"x".to_vec(),
+        file_type: FileType::from_indicator(0),
+    };
+    assert_eq!(entry.file_type, None);
+    assert_eq!(FileType::from_indicator(7), Some(FileType::Symlink));
+    assert_eq!(FileType::from_indicator(8), None);
+}
@@ -0,0 +1,116 @@
+//! A directory that fills its blocks gets another, a block at a time, under
+//! the indirect pointers once the direct ones run out, and at 64 KiB blocks
+//! an entry as long as the block round-trips.
+
+mod common;
+
+use common::{e2fsprogs, fixture, Image, ROOT};
+use ext2::Ext2;
+
+// 200 bytes, so four entries to a 1K block
+fn long_name(i: usize) -> String {
+    format!("{:0>200}", i)
+}
+
+fn dir_blocks(ext2: &Ext2, dir: usize) -> usize {
+    let size = ext2.get_inode(dir).unwrap().size_low as usize;
+    assert_eq!(size % ext2.block_size, 0);
+    size / ext2.block_size
+}
+
+// every entry of `dir` listed and found by name, and nothing else
+fn assert_lists(ext2: &Ext2, dir: usize, count: usize) {
+    let mut names: Vec<String> = ext2
+        .read_dir_inode(dir)
+        .unwrap()
+        .iter()
+        .map(|entry| entry.name_lossy().into_owned())
+        .filter(|name| name != "." && name != "..")
+        .collect();
+    names.sort();
+    assert_eq!(names, (0..count).map(long_name).collect::<Vec<_>>());
+    for i in [0, count / 2, count - 1] {
+        let inode = ext2.lookup(dir, &long_name(i)).unwrap().unwrap();
+        assert!(ext2.get_inode(inode).unwrap().is_regular());
+    }
+}
+
+#[test]
+fn grows_a_block_at_a_time() {
+    let mut image = fixture().build();
+    let ext2 = &mut image.ext2;
+    let dir = ext2.create_dir(ROOT, "dir", 0o755).unwrap();
+    assert_eq!(dir_blocks(ext2, dir), 1);
+    // `.` and `..` are short, so the first block holds four names too
+    let mut blocks = 1;
+    for i in 0..60 {
+        ext2.create_file(dir, &long_name(i), 0o644).unwrap();
+        let now = dir_blocks(ext2, dir);
+        assert!(now == blocks || now == blocks + 1, "{} -> {}", blocks, now);
+        blocks = now;
+    }
+    assert_eq!(blocks, 15);
+    let record = ext2.get_inode(dir).unwrap();
+    assert_ne!(record.indirect_pointer, 0);
+    // each block, and the indirect one, counted in the sectors
+    assert_eq!(record.sectors_count as usize, (blocks + 1) * 2);
+    assert_lists(ext2, dir, 60);
+    assert_eq!(ext2.check(), []);
+
+    let reopened = Image::from_bytes(&image.synced_bytes());
+    assert_lists(&reopened.ext2, dir, 60);
+    assert_eq!(reopened.ext2.check(), []);
+}
+
+#[test]
+fn into_the_doubly_indirect_blocks() {
+    // 12 direct blocks and 256 under the indirect one at 1K blocks, and
+    // enough groups for an inode for every entry
+    let mut image = fixture().size(16 << 20).build();
+    let ext2 = &mut image.ext2;
+    let dir = ext2.create_dir(ROOT, "dir", 0o755).unwrap();
+    let count = (12 + 256) * 4 + 10;
+    for i in 0..count {
+        ext2.create_file(dir, &long_name(i), 0o644).unwrap();
+    }
+    assert!(dir_blocks(ext2, dir) > 12 + 256);
+    assert_ne!(ext2.get_inode(dir).unwrap().doubly_indirect, 0);
+    assert_lists(ext2, dir, count);
+    assert_eq!(ext2.check(), []);
+}
+
+#[test]
+fn a_whole_64k_block_entry_round_trips() {
+    // mkfs only goes up to 4 KiB, so it takes mke2fs
+    if !e2fsprogs::available() {
+        return;
+    }
+    let bytes = e2fsprogs::mke2fs(&["-t", "ext2", "-b", "65536", "-N", "1024"], 8 << 20);
+    let mut image = Image::from_bytes(&bytes);
+    let ext2 = &mut image.ext2;
+    assert_eq!(ext2.block_size, 1 << 16);
+    let dir = ext2.create_dir(ROOT, "dir", 0o755).unwrap();
+    // 208 bytes an entry, so 314 fill the first block beside `.` and `..`,
+    // and the next gets a block of its own, as one slot the size of it
+    let count = 315;
+    for i in 0..count {
+        ext2.create_file(dir, &long_name(i), 0o644).unwrap();
+    }
+    assert_eq!(dir_blocks(ext2, dir), 2);
+    let second = ext2.file_blocks(dir).unwrap().nth(1).unwrap().unwrap();
+    // 65536 doesn't fit in the 16 bits, so it's stored as ext4 stores it
+    assert_eq!(ext2.block(second).unwrap()[4..6], [0xFF, 0xFF]);
+    assert_lists(ext2, dir, count);
+    assert_eq!(ext2.check(), []);
+
+    // removing the block's only entry leaves its slot spanning the block
+    ext2.unlink(dir, &long_name(count - 1)).unwrap();
+    ext2.create_file(dir, &long_name(count - 1), 0o644).unwrap();
+    assert_eq!(dir_blocks(ext2, dir), 2);
+
+    let (_dir, path) = image.dump();
+    e2fsprogs::fsck(&path).unwrap();
+    let reopened = Image::from_bytes(&std::fs::read(&path).unwrap());
+    assert_lists(&reopened.ext2, dir, count);
+    assert_eq!(reopened.ext2.check(), []);
+}
@@ -0,0 +1,71 @@
+//! The slots of a directory block as `DirSlots` finds them, unused and
+//! corrupt ones included.
+
+mod common;
+
+use common::{fixture, ROOT};
+use ext2::DirSlots;
+
+// a slot: inode, rec_len, name_len, type, name, padded with zeros to rec_len
+fn slot(inode: u32, rec_len: u16, name_len: u8, file_type: u8, name: &[u8]) -> Vec<u8> {
+    let mut slot = inode.to_le_bytes().to_vec();
+    slot.extend(rec_len.to_le_bytes());
+    slot.extend([name_len, file_type]);
+    slot.extend(name);
+    slot.resize(rec_len.max(8) as usize, 0);
+    slot
+}
+
+#[test]
+fn every_slot_in_order() {
+    let mut block = slot(2, 12, 1, 2, b".");
+    // removed, its name left behind
+    block.extend(slot(0, 20, 7, 1, b"old.txt"));
+    block.extend(slot(12, 32, 5, 1, b"a.txt"));
+    let slots: Vec<_> = DirSlots::new(&block).map(Result::unwrap).collect();
+    assert_eq!(slots.len(), 3);
+    assert_eq!((slots[1].offset, slots[1].inode), (12, 0));
+    assert_eq!(slots[1].name.0, b"old.txt");
+    assert_eq!(slots[1].padding(), 5);
+    assert_eq!(slots[2].offset + slots[2].rec_len, block.len());
+    assert_eq!(slots[2].padding(), 32 - 8 - 5);
+    assert_eq!(slots[2].file_type, 1);
+}
+
+#[test]
+fn stops_at_a_bad_slot() {
+    let mut block = slot(2, 12, 1, 2, b".");
+    // not a multiple of 4
+    block.extend(slot(12, 14, 5, 1, b"a.txt"));
+    block.resize(64, 0);
+    let slots: Vec<_> = DirSlots::new(&block).collect();
+    assert_eq!(slots.len(), 2);
+    assert!(slots[0].is_ok());
+    assert_eq!(slots[1], Err((12, String::from("invalid entry_size 14"))));
+
+    // a name longer than its slot
+    let mut block = slot(12, 12, 9, 1, b"name");
+    block.resize(12, 0);
+    let slots: Vec<_> = DirSlots::new(&block).collect();
+    assert_eq!(
+        slots,
+        [Err((
+            0,
+            String::from("name_length 9 doesn't fit in entry_size 12")
+        ))]
+    );
+}
+
+#[test]
+fn a_real_directory() {
+    let image = fixture().file("a.txt", b"a").build();
+    let ext2 = &image.ext2;
+    let first = ext2.file_blocks(ROOT).unwrap().next().unwrap().unwrap();
+    let names: Vec<String> = DirSlots::new(ext2.block(first).unwrap())
+        .map(|slot| slot.unwrap())
+        .filter(|slot| slot.inode != 0)
+        .map(|slot| slot.name.to_string())
+        .collect();
+    assert_eq!(names[..2], [".", ".."]);
+    assert!(names.contains(&String::from("a.txt")));
+}
@@ -0,0 +1,64 @@
+//! `.` and `..` against names that merely start with a dot: one
+//! classification, `NameKind`, for listings and everything that recurses.
+
+mod common;
+
+use common::{fixture, ROOT};
+use ext2::{NameKind, WalkControl, WalkOptions};
+
+#[test]
+fn classified_by_name() {
+    for (name, kind) in [
+        (&b"."[..], NameKind::Dot),
+        (b"..", NameKind::Dot),
+        (b".hidden", NameKind::Hidden),
+        (b"...", NameKind::Hidden),
+        (b"..x", NameKind::Hidden),
+        (b"visible", NameKind::Visible),
+        (b"x.", NameKind::Visible),
+    ] {
+        assert_eq!(NameKind::of(name), kind, "{:?}", name);
+    }
+
+    let image = fixture()
+        .file(".hidden", b"secret")
+        .file("shown", b"")
+        .build();
+    let listing = image.ext2.read_dir_inode(ROOT).unwrap();
+    let kind = |name: &[u8]| {
+        let entry = listing.iter().find(|entry| entry.name == name).unwrap();
+        (entry.kind(), entry.is_dot())
+    };
+    assert_eq!(kind(b"."), (NameKind::Dot, true));
+    assert_eq!(kind(b".."), (NameKind::Dot, true));
+    assert_eq!(kind(b".hidden"), (NameKind::Hidden, false));
+    assert_eq!(kind(b"shown"), (NameKind::Visible, false));
+}
+
+#[test]
+fn walks_into_dotfiles_but_not_dots() {
+    let image = fixture()
+        .file(".hidden", b"secret")
+        .dir(".config", |dir| dir.file("app", b"").file(".rc", b""))
+        .build();
+    let mut paths = Vec::new();
+    image
+        .ext2
+        .walk(ROOT, &WalkOptions::new(), &mut |entry| {
+            paths.push(entry.path());
+            WalkControl::Continue
+        })
+        .unwrap();
+    paths.sort();
+    assert_eq!(
+        paths,
+        [
+            ".config",
+            ".config/.rc",
+            ".config/app",
+            ".hidden",
+            "lost+found"
+        ]
+    );
+    assert_eq!(image.ext2.check(), []);
+}
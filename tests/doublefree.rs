@@ -0,0 +1,57 @@
+//! Marking a block or inode in use or free when it already is: a block two
+//! files point at, as on a corrupt image, is freed with the first and
+//! refused with the second, rather than counted free twice; one pointing
+//! outside the filesystem fails the removal before anything changes.
+
+mod common;
+
+use common::{fixture, pattern, ROOT};
+use ext2::Ext2Error;
+
+#[test]
+fn a_shared_block_is_freed_once() {
+    let mut image = fixture()
+        .file("a", &pattern(1024))
+        .file("b", &pattern(1024))
+        .build();
+    let (a, b) = (image.inode("/a"), image.inode("/b"));
+    let ext2 = &mut image.ext2;
+    let shared = ext2.get_inode(a).unwrap().direct_pointer[0];
+    ext2.inode_mut(b).unwrap().direct_pointer[0] = shared;
+
+    ext2.unlink(2, "a").unwrap();
+    let free_blocks = ext2.superblock.free_blocks_count;
+    let err = ext2.unlink(2, "b").unwrap_err();
+    assert!(
+        matches!(
+            err.root_cause(),
+            Ext2Error::AlreadyMarked { kind: "block", number, .. } if *number == shared as usize
+        ),
+        "{}",
+        err
+    );
+    assert_eq!(ext2.superblock.free_blocks_count, free_blocks);
+}
+
+#[test]
+fn an_out_of_range_pointer_is_refused_before_unlinking() {
+    let mut image = fixture().file("a", &pattern(1024)).build();
+    let a = image.inode("/a");
+    let ext2 = &mut image.ext2;
+    let blocks_count = ext2.superblock.blocks_count;
+    ext2.inode_mut(a).unwrap().direct_pointer[0] = blocks_count + 7;
+
+    let free_blocks = ext2.superblock.free_blocks_count;
+    let err = ext2.unlink(ROOT, "a").unwrap_err();
+    assert!(
+        matches!(
+            err.root_cause(),
+            Ext2Error::BlockOutOfRange { block, .. } if *block == blocks_count as usize + 7
+        ),
+        "{}",
+        err
+    );
+    assert_eq!(ext2.resolve_path(ROOT, "/a").unwrap(), a);
+    assert_eq!(ext2.get_inode(a).unwrap().hard_links, 1);
+    assert_eq!(ext2.superblock.free_blocks_count, free_blocks);
+}
@@ -0,0 +1,68 @@
+//! Disk usage of subtrees for `browse`, summed once and kept until the
+//! filesystem changes.
+
+mod common;
+
+use common::{fixture, ROOT};
+use ext2::DuCache;
+
+const BLOCK_SIZE: usize = 1024;
+
+#[test]
+fn subtree_totals() {
+    let image = fixture()
+        .block_size(BLOCK_SIZE)
+        .file("small", b"x")
+        .dir("docs", |dir| {
+            dir.file_with_size("big", 20 * BLOCK_SIZE)
+                .dir("sub", |dir| dir.file_with_size("mid", 3 * BLOCK_SIZE))
+        })
+        .build();
+    let docs = image.inode("/docs");
+    let ext2 = &image.ext2;
+    let disk = |path: &str| ext2.get_inode(image.inode(path)).unwrap().sectors_count as u64 * 512;
+
+    let mut du = DuCache::new();
+    let sub = disk("/docs/sub") + disk("/docs/sub/mid");
+    let expected = disk("/docs") + disk("/docs/big") + sub;
+    assert_eq!(du.total(ext2, docs).unwrap(), expected);
+    // the 20 blocks of data and the indirect block pointing at 8 of them
+    assert_eq!(disk("/docs/big"), 21 * BLOCK_SIZE as u64);
+    assert_eq!(du.len(), 2);
+
+    let children = du.children(ext2, docs).unwrap();
+    let shown: Vec<(&[u8], bool, u64)> = children
+        .iter()
+        .map(|child| (child.name.as_slice(), child.is_dir, child.bytes))
+        .collect();
+    assert_eq!(
+        shown,
+        [(&b"big"[..], false, disk("/docs/big")), (b"sub", true, sub)]
+    );
+
+    let root = du.children(ext2, ROOT).unwrap();
+    assert_eq!(root[0].name, b"docs");
+    assert_eq!(root.last().unwrap().name, b"small");
+}
+
+#[test]
+fn dropped_once_modified() {
+    let mut image = fixture()
+        .block_size(BLOCK_SIZE)
+        .dir("docs", |dir| dir.file("a", b"a"))
+        .build();
+    let docs = image.inode("/docs");
+    let mut du = DuCache::new();
+    let before = du.total(&image.ext2, docs).unwrap();
+    assert!(!du.is_empty());
+
+    let a = image.inode("/docs/a");
+    image
+        .ext2
+        .write_file(a, 0, &vec![1; 4 * BLOCK_SIZE])
+        .unwrap();
+    assert_eq!(
+        du.total(&image.ext2, docs).unwrap(),
+        before + 3 * BLOCK_SIZE as u64
+    );
+}
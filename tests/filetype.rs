@@ -0,0 +1,184 @@
+//! File types from the top four bits of the mode, which are one field and
+//! not flags to test one at a time, and the special files `mknod` makes.
+
+mod common;
+
+use common::{e2fsprogs, fixture, Image, TempDir, ROOT};
+use ext2::structs::{FileType, TypePerm};
+use ext2::{Ext2, Ext2Error};
+use std::fs;
+use std::process::Command;
+
+#[test]
+fn every_type() {
+    let types = [
+        (0x1000, FileType::Fifo),
+        (0x2000, FileType::Char),
+        (0x4000, FileType::Dir),
+        (0x6000, FileType::Block),
+        (0x8000, FileType::Regular),
+        (0xA000, FileType::Symlink),
+        (0xC000, FileType::Socket),
+        (0x0000, FileType::Unknown),
+        (0xE000, FileType::Unknown),
+    ];
+    for (bits, file_type) in types {
+        // whatever the permissions
+        assert_eq!(FileType::from_mode(bits | 0o7777), file_type, "{:#x}", bits);
+    }
+
+    let mut image = fixture()
+        .file("file", b"data")
+        .dir("dir", |dir| dir)
+        .build();
+    let ext2 = &mut image.ext2;
+    ext2.create_symlink(ROOT, "link", "file").unwrap();
+    for (name, bits, device) in [
+        ("fifo", 0x1000, None),
+        ("char", 0x2000, Some((1, 3))),
+        ("block", 0x6000, Some((8, 0))),
+        ("socket", 0xC000, None),
+    ] {
+        ext2.create_node(ROOT, name, bits | 0o640, device).unwrap();
+    }
+    for (name, file_type) in [
+        ("/file", FileType::Regular),
+        ("/dir", FileType::Dir),
+        ("/link", FileType::Symlink),
+        ("/fifo", FileType::Fifo),
+        ("/char", FileType::Char),
+        ("/block", FileType::Block),
+        ("/socket", FileType::Socket),
+    ] {
+        let record = ext2
+            .get_inode(ext2.resolve_path(ROOT, name).unwrap())
+            .unwrap();
+        assert_eq!(record.file_type(), file_type, "{}", name);
+        assert_eq!(record.is_dir(), file_type == FileType::Dir, "{}", name);
+        assert_eq!(
+            record.is_regular(),
+            file_type == FileType::Regular,
+            "{}",
+            name
+        );
+        assert_eq!(
+            record.is_symlink(),
+            file_type == FileType::Symlink,
+            "{}",
+            name
+        );
+    }
+    let fifo = ext2.resolve_path(ROOT, "/fifo").unwrap();
+    assert_eq!(ext2.get_inode(fifo).unwrap().permissions(), 0o640);
+}
+
+#[test]
+fn symlink_is_not_a_regular_file() {
+    // the symlink type has the regular file bit in it
+    assert!(TypePerm::SYMLINK.contains(TypePerm::FILE));
+    assert_eq!(
+        FileType::from_mode(TypePerm::SYMLINK.bits()),
+        FileType::Symlink
+    );
+
+    let mut image = fixture().file("file", b"data").build();
+    let ext2 = &mut image.ext2;
+    let link = ext2.create_symlink(ROOT, "link", "file").unwrap();
+    let record = ext2.get_inode(link).unwrap();
+    assert!(record.is_symlink());
+    assert!(!record.is_regular());
+    assert_eq!(record.permissions(), 0o777);
+    // so writing to it as a file isn't allowed
+    assert!(ext2.punch_hole(link, 0, 1).is_err());
+}
+
+#[test]
+fn block_device_is_not_a_directory() {
+    // nor a socket, though both have the directory bit in them
+    let mut image = fixture().build();
+    let ext2 = &mut image.ext2;
+    for (name, bits) in [("block", 0x6000), ("socket", 0xC000)] {
+        let node = ext2.create_node(ROOT, name, bits | 0o600, None).unwrap();
+        assert!(matches!(
+            ext2.create_file(node, "inside", 0o644),
+            Err(Ext2Error::NotADirectory { .. })
+        ));
+    }
+    assert_eq!(ext2.check(), []);
+}
+
+// the shell, one command to a line, for what it prints
+fn shell(image: &std::path::Path, commands: &str) -> String {
+    let dir = TempDir::new("rc");
+    let rcfile = dir.path().join("rc");
+    fs::write(&rcfile, commands).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_ext2"))
+        .arg("--rcfile")
+        .arg(&rcfile)
+        .arg(image)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn special_files_keep_their_mode_and_device_numbers() {
+    let mut image = fixture().build();
+    let (dir, path) = image.dump();
+    shell(
+        &path,
+        "mknod tty c 4 64\nmknod sda b 8 0\nmknod pipe p\nmknod sock s\nsync\n",
+    );
+    let mut image = Image {
+        ext2: Ext2::new(fs::read(&path).unwrap()).unwrap(),
+    };
+    let ext2 = &mut image.ext2;
+    // past what fits in a byte each, so in the newer encoding
+    ext2.create_node(ROOT, "big", 0x2000 | 0o600, Some((300, 70000)))
+        .unwrap();
+    for (name, mode, device) in [
+        ("tty", 0o020644, Some((4, 64))),
+        ("sda", 0o060644, Some((8, 0))),
+        ("pipe", 0o010644, None),
+        ("sock", 0o140644, None),
+        ("big", 0o020600, Some((300, 70000))),
+    ] {
+        let inode = ext2.lookup(ROOT, name).unwrap().unwrap();
+        let record = ext2.get_inode(inode).unwrap();
+        assert_eq!(record.type_perm.bits(), mode, "{}", name);
+        assert_eq!(record.device(), device, "{}", name);
+        assert_eq!((record.size(), record.sectors_count), (0, 0), "{}", name);
+        assert_eq!(ext2.owned_blocks(inode).unwrap(), [], "{}", name);
+    }
+    assert_eq!(ext2.check(), []);
+    let (_dumped, path) = image.dump();
+    drop(dir);
+    if e2fsprogs::available() {
+        e2fsprogs::fsck(&path).unwrap();
+    }
+
+    // and istat and stat say what they are
+    for (name, kind, device) in [
+        ("tty", "character device", Some("4,64")),
+        ("sda", "block device", Some("8,0")),
+        ("pipe", "FIFO", None),
+        ("sock", "socket", None),
+        ("big", "character device", Some("300,70000")),
+    ] {
+        let printed = shell(&path, &format!("stat {}\n", name));
+        assert!(
+            printed.contains(&format!("Type: {}\n", kind)),
+            "{}",
+            printed
+        );
+        match device {
+            Some(device) => assert!(
+                printed.contains(&format!("Device type: {}\n", device)),
+                "{}",
+                printed
+            ),
+            None => assert!(!printed.contains("Device type"), "{}", printed),
+        }
+    }
+}
@@ -0,0 +1,64 @@
+//! The fixture builder in `common` makes what it's asked to, whichever way
+//! it makes it.
+
+mod common;
+
+use common::{fixture, pattern, Fixture, ROOT};
+use std::env;
+
+fn sample() -> Fixture {
+    fixture()
+        .dir("docs", |d| {
+            d.file("a.txt", b"hello")
+                .symlink("b", "a.txt")
+                .dir("empty", |d| d)
+        })
+        .file_with_size("big.bin", 3 * 1024 * 1024)
+}
+
+#[test]
+fn builds_what_it_describes() {
+    let image = sample().build();
+    let ext2 = &image.ext2;
+    let mut names: Vec<String> = ext2
+        .read_dir_inode(ROOT)
+        .unwrap()
+        .into_iter()
+        .map(|entry| entry.name_lossy().into_owned())
+        .collect();
+    names.sort();
+    assert_eq!(names, [".", "..", "big.bin", "docs", "lost+found"]);
+
+    let a = image.inode("/docs/a.txt");
+    assert_eq!(ext2.read_file_inode(a).unwrap(), b"hello");
+    let link = image.inode("/docs/b");
+    assert_eq!(ext2.get_inode(link).unwrap().type_name(), "symbolic link");
+    assert_eq!(ext2.read_link(link).unwrap(), b"a.txt");
+    let empty = image.inode("/docs/empty");
+    assert_eq!(ext2.read_dir_inode(empty).unwrap().len(), 2);
+    let big = image.inode("/big.bin");
+    // past the direct and indirect blocks
+    assert_eq!(ext2.read_file_inode(big).unwrap(), pattern(3 * 1024 * 1024));
+
+    assert_eq!(ext2.check(), []);
+}
+
+#[test]
+fn same_description_same_bytes() {
+    // mke2fs picks a random directory hash seed
+    if env::var("EXT2_FIXTURES").as_deref() == Ok("e2fsprogs") {
+        return;
+    }
+    assert!(sample().build().synced_bytes() == sample().build().synced_bytes());
+}
+
+#[test]
+fn block_size_and_size() {
+    let image = fixture()
+        .block_size(4096)
+        .size(8 << 20)
+        .file("f", b"x")
+        .build();
+    assert_eq!(image.ext2.block_size, 4096);
+    assert_eq!(image.ext2.superblock.blocks_count as usize * 4096, 8 << 20);
+}
@@ -0,0 +1,142 @@
+//! The immutable and append-only inode flags: an immutable file can't be
+//! written, truncated, unlinked or renamed, an append-only one only grows at
+//! the end, and `istat` and `stat` show the flags set.
+
+mod common;
+
+use common::{fixture, pattern, Image, ROOT};
+use ext2::structs::InodeFlags;
+use ext2::{AppendHandle, Ext2, Ext2Error, Result};
+use std::process::Command;
+
+fn image() -> Image {
+    fixture()
+        .dir("d", |d| d.file("f", &pattern(3000)).file("g", b"g"))
+        .build()
+}
+
+fn set_flags(ext2: &mut Ext2, inode: usize, flags: InodeFlags) {
+    ext2.set_inode_flags(inode, flags).unwrap();
+    assert_eq!(ext2.inode_flags(inode).unwrap(), flags);
+}
+
+// what writing, truncating or linking it would change
+fn state(ext2: &Ext2, inode: usize) -> (u64, u32, u32, u16) {
+    let record = ext2.get_inode(inode).unwrap();
+    (
+        record.size(),
+        record.sectors_count,
+        record.mtime,
+        record.hard_links,
+    )
+}
+
+fn refused<T: std::fmt::Debug>(result: Result<T>) {
+    assert!(
+        matches!(result, Err(Ext2Error::NotPermitted { .. })),
+        "{:?}",
+        result
+    );
+}
+
+#[test]
+fn an_immutable_file_is_left_alone() {
+    let mut image = image();
+    let (d, f) = (image.inode("/d"), image.inode("/d/f"));
+    let ext2 = &mut image.ext2;
+    set_flags(ext2, f, InodeFlags::IMMUTABLE);
+    let before = state(ext2, f);
+
+    refused(ext2.write_file(f, 0, b"over"));
+    refused(ext2.write_file(f, 3000, b"past the end"));
+    refused(ext2.append(&mut AppendHandle::new(f), b"more"));
+    refused(ext2.truncate(f, 10));
+    refused(ext2.truncate(f, 5000));
+    refused(ext2.punch_hole(f, 0, 1024));
+    refused(ext2.preallocate(f, 8192, false));
+    refused(ext2.unlink(d, "f"));
+    refused(ext2.rename(d, "f", ROOT, "moved"));
+    refused(ext2.rename(d, "f", d, "renamed"));
+    // nor can another file take its place
+    refused(ext2.rename(d, "g", d, "f"));
+
+    assert_eq!(ext2.read_file_inode(f).unwrap(), pattern(3000));
+    assert_eq!(state(ext2, f), before);
+    assert_eq!(ext2.lookup(d, "f").unwrap(), Some(f));
+    assert!(ext2.lookup(d, "g").unwrap().is_some());
+
+    // and once the flag's cleared, all of it goes through again
+    set_flags(ext2, f, InodeFlags::empty());
+    assert_eq!(ext2.truncate(f, 10).unwrap(), 2);
+    assert_eq!(ext2.read_file_inode(f).unwrap(), pattern(10));
+    ext2.rename(d, "f", ROOT, "moved").unwrap();
+    ext2.unlink(ROOT, "moved").unwrap();
+    assert_eq!(ext2.check(), []);
+}
+
+#[test]
+fn an_immutable_directory_keeps_its_entries() {
+    let mut image = image();
+    let d = image.inode("/d");
+    let ext2 = &mut image.ext2;
+    set_flags(ext2, d, InodeFlags::IMMUTABLE);
+    refused(ext2.create_file(d, "new", 0o644));
+    refused(ext2.unlink(d, "g"));
+    refused(ext2.rename(d, "g", ROOT, "g"));
+    refused(ext2.rename(ROOT, "lost+found", d, "lf"));
+    assert_eq!(ext2.check(), []);
+}
+
+#[test]
+fn an_append_only_file_only_grows() {
+    let mut image = image();
+    let (d, f) = (image.inode("/d"), image.inode("/d/f"));
+    let ext2 = &mut image.ext2;
+    set_flags(ext2, f, InodeFlags::APPEND);
+
+    refused(ext2.write_file(f, 0, b"over"));
+    refused(ext2.write_file(f, 2999, b"straddling the end"));
+    refused(ext2.truncate(f, 10));
+    refused(ext2.punch_hole(f, 0, 1024));
+    refused(ext2.unlink(d, "f"));
+    refused(ext2.rename(d, "f", d, "renamed"));
+    assert_eq!(ext2.read_file_inode(f).unwrap(), pattern(3000));
+
+    // at the end it's fine, however it's done
+    let mut expected = pattern(3000);
+    assert_eq!(ext2.write_file(f, 3000, b"written").unwrap(), 7);
+    expected.extend_from_slice(b"written");
+    assert_eq!(
+        ext2.append(&mut AppendHandle::new(f), b" appended")
+            .unwrap(),
+        9
+    );
+    expected.extend_from_slice(b" appended");
+    assert_eq!(ext2.read_file_inode(f).unwrap(), expected);
+    // and growing it with a hole is an append of zeros
+    ext2.truncate(f, 5000).unwrap();
+    expected.resize(5000, 0);
+    assert_eq!(ext2.read_file_inode(f).unwrap(), expected);
+    assert_eq!(ext2.check(), []);
+}
+
+#[test]
+fn istat_and_stat_show_the_flags() {
+    let mut image = image();
+    let f = image.inode("/d/f");
+    let flags = InodeFlags::IMMUTABLE | InodeFlags::APPEND;
+    set_flags(&mut image.ext2, f, flags);
+    let (_dir, path) = image.dump();
+    let line = format!("Flags: {:#010x} ({})\n", flags.bits(), flags.letters());
+    let f = f.to_string();
+    for command in [["istat", &f], ["stat", "/d/f"]] {
+        let output = Command::new(env!("CARGO_BIN_EXE_ext2"))
+            .arg(&path)
+            .args(command)
+            .output()
+            .unwrap();
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        assert!(stdout.contains(&line), "{:?}: {}", command, stdout);
+    }
+    assert!(flags.letters().contains('i') && flags.letters().contains('a'));
+}
@@ -0,0 +1,63 @@
+//! How the free blocks lie: per group, as a histogram, and the longest run.
+
+mod common;
+
+use common::fixture;
+
+#[test]
+fn counts_match_the_superblock() {
+    // 1K blocks, so a second group of 2047 blocks where 8192 would fit, the
+    // rest of its bitmap set
+    let image = fixture()
+        .block_size(1024)
+        .size(10 << 20)
+        .file_with_size("a", 50 * 1024)
+        .build();
+    let ext2 = &image.ext2;
+    let report = ext2.free_space().unwrap();
+    assert_eq!(report.groups.len(), 2);
+    let free: usize = report.groups.iter().map(|group| group.free).sum();
+    assert_eq!(free, ext2.superblock.free_blocks_count as usize);
+
+    let last = &report.groups[1];
+    let (start, len) = last.largest;
+    assert!(start >= ext2.group_first_block(1));
+    assert!(start + len <= ext2.superblock.blocks_count as usize);
+    assert_eq!(ext2.largest_free_run(Some(1)).unwrap(), last.largest);
+
+    let runs: usize = report.histogram.iter().map(|bucket| bucket.runs).sum();
+    let extents: usize = report.groups.iter().map(|group| group.extents).sum();
+    assert_eq!(runs, extents);
+    let blocks: usize = report.histogram.iter().map(|bucket| bucket.blocks).sum();
+    assert_eq!(blocks, free);
+    let lengths: Vec<(usize, usize)> = report
+        .histogram
+        .iter()
+        .map(|bucket| (bucket.min_len, bucket.max_len))
+        .collect();
+    assert_eq!(lengths[..3], [(1, 1), (2, 3), (4, 7)]);
+    // the longest run is in the last bucket
+    let longest = ext2.largest_free_run(None).unwrap().1;
+    let bucket = report.histogram.last().unwrap();
+    assert!(bucket.min_len <= longest && longest <= bucket.max_len);
+}
+
+#[test]
+fn splitting_the_longest_run() {
+    let mut image = fixture().block_size(1024).build();
+    let ext2 = &mut image.ext2;
+    let (start, len) = ext2.largest_free_run(Some(0)).unwrap();
+    let extents = ext2.free_space().unwrap().groups[0].extents;
+    assert!(len > 100);
+
+    let middle = start + len / 2;
+    assert_eq!(ext2.alloc_block(middle).unwrap(), middle);
+    let group = ext2.free_space().unwrap().groups.remove(0);
+    assert_eq!(group.extents, extents + 1);
+    // the first half if the two are as long
+    let second = (middle + 1, start + len - middle - 1);
+    let first = (start, len / 2);
+    let expected = if first.1 >= second.1 { first } else { second };
+    assert_eq!(group.largest, expected);
+    assert_eq!(ext2.largest_free_run(Some(0)).unwrap(), expected);
+}
@@ -0,0 +1,71 @@
+//! Open files: reading and seeking through a `FileReader`, and removed files
+//! staying readable until the last reader is closed.
+
+mod common;
+
+use common::{fixture, pattern, ROOT};
+use ext2::Ext2Error;
+use std::io::SeekFrom;
+
+const SIZE: usize = 5000;
+
+#[test]
+fn read_and_seek() {
+    let mut image = fixture().file_with_size("f", SIZE).build();
+    let f = image.inode("/f");
+    let ext2 = &mut image.ext2;
+    let mut reader = ext2.open_file(f).unwrap();
+    let mut buf = [0; 3000];
+    assert_eq!(reader.read(ext2, &mut buf).unwrap(), 3000);
+    assert_eq!(reader.read(ext2, &mut buf).unwrap(), SIZE - 3000);
+    assert_eq!(buf[..SIZE - 3000], pattern(SIZE)[3000..]);
+    assert_eq!(reader.read(ext2, &mut buf).unwrap(), 0);
+    assert_eq!(reader.offset(), SIZE as u64);
+
+    assert_eq!(
+        reader.seek(ext2, SeekFrom::End(-10)).unwrap(),
+        SIZE as u64 - 10
+    );
+    assert_eq!(
+        reader.seek(ext2, SeekFrom::Current(-90)).unwrap(),
+        SIZE as u64 - 100
+    );
+    assert!(matches!(
+        reader.seek(ext2, SeekFrom::Current(-(SIZE as i64))),
+        Err(Ext2Error::Io(_))
+    ));
+    assert_eq!(reader.offset(), SIZE as u64 - 100);
+
+    assert_eq!(ext2.open_count(f), 1);
+    ext2.close_file(reader).unwrap();
+    assert_eq!(ext2.open_count(f), 0);
+    assert!(matches!(
+        ext2.open_file(ROOT),
+        Err(Ext2Error::IsADirectory { .. })
+    ));
+}
+
+#[test]
+fn removed_while_open() {
+    let mut image = fixture().file_with_size("f", SIZE).build();
+    let f = image.inode("/f");
+    let ext2 = &mut image.ext2;
+    let free = ext2.superblock.free_blocks_count;
+    let first = ext2.open_file(f).unwrap();
+    let mut second = ext2.open_file(f).unwrap();
+    ext2.unlink(ROOT, "f").unwrap();
+
+    // the name is gone, but the file is still there to read
+    assert!(ext2.lookup(ROOT, "f").unwrap().is_none());
+    let mut buf = vec![0; SIZE];
+    assert_eq!(second.read(ext2, &mut buf).unwrap(), SIZE);
+    assert_eq!(buf, pattern(SIZE));
+    assert!(ext2.inode_is_allocated(f).unwrap());
+
+    ext2.close_file(first).unwrap();
+    assert!(ext2.inode_is_allocated(f).unwrap());
+    ext2.close_file(second).unwrap();
+    assert!(!ext2.inode_is_allocated(f).unwrap());
+    assert!(ext2.superblock.free_blocks_count > free);
+    assert_eq!(ext2.check(), []);
+}
@@ -0,0 +1,34 @@
+//! Sizes as `ls -lh` and `df -h` show them, rounded like coreutils.
+
+use ext2::human_size;
+
+#[test]
+fn under_a_kibibyte() {
+    assert_eq!(human_size(0), "0");
+    assert_eq!(human_size(1023), "1023");
+}
+
+#[test]
+fn kibibytes() {
+    assert_eq!(human_size(1024), "1.0K");
+    // rounded up, never down
+    assert_eq!(human_size(1025), "1.1K");
+    assert_eq!(human_size(4096), "4.0K");
+    assert_eq!(human_size(10 * 1024 - 1), "10K");
+    assert_eq!(human_size(10 * 1024 + 1), "11K");
+}
+
+#[test]
+fn whole_units() {
+    assert_eq!(human_size(1 << 20), "1.0M");
+    // rounds up to 1024K, which is a mebibyte
+    assert_eq!(human_size((1 << 20) - 1), "1.0M");
+    assert_eq!(human_size((1 << 20) + 1), "1.1M");
+    assert_eq!(human_size(1 << 30), "1.0G");
+    assert_eq!(human_size(3 << 40), "3.0T");
+}
+
+#[test]
+fn largest() {
+    assert_eq!(human_size(u64::MAX), "16E");
+}
@@ -0,0 +1,138 @@
+//! `diff_images` and the shell's `image-diff`: a modified copy is compared
+//! block by block as well as path by path, every list comes out sorted
+//! whatever order things were made in, and images that can't be copies of
+//! each other are compared by what's in them alone.
+
+mod common;
+
+use common::{fixture, pattern, Fixture, Image, ROOT};
+use ext2::structs::TypePerm;
+use ext2::ImageDiff;
+use serde_json::Value;
+use std::fs;
+use std::process::Command;
+
+fn contents(fixture: Fixture) -> Fixture {
+    fixture
+        .dir("docs", |d| {
+            d.file("a.txt", &pattern(3000))
+                .file("b.md", b"# b")
+                .file("gone", b"gone")
+        })
+        .symlink("link", "docs/a.txt")
+}
+
+// A's tree as B has it after a few changes, made out of path order
+fn modified() -> Image {
+    let mut image = contents(fixture()).build();
+    let (docs, a, b) = (
+        image.inode("/docs"),
+        image.inode("/docs/a.txt"),
+        image.inode("/docs/b.md"),
+    );
+    let ext2 = &mut image.ext2;
+    let mtimes = [docs, a].map(|inode| ext2.get_inode(inode).unwrap().mtime);
+    ext2.create_file(ROOT, "zz", 0o644).unwrap();
+    ext2.create_file(docs, "new", 0o644).unwrap();
+    ext2.create_file(ROOT, "aa", 0o644).unwrap();
+    ext2.unlink(docs, "gone").unwrap();
+    ext2.write_file(a, 1000, b"changed").unwrap();
+    ext2.inode_mut(b).unwrap().type_perm = TypePerm::from_bits_truncate(0o100600);
+    // whatever clock made the fixture, only b.md's metadata is to differ
+    for (inode, mtime) in [docs, a].into_iter().zip(mtimes) {
+        ext2.inode_mut(inode).unwrap().mtime = mtime;
+    }
+    image
+}
+
+#[test]
+fn a_modified_copy_differs_in_blocks_and_paths() {
+    let a = contents(fixture()).build();
+    let b = modified();
+    let diff = a.ext2.diff_images(&b.ext2).unwrap();
+    assert_eq!(diff.note, None);
+    assert!(!diff.blocks.is_empty());
+    assert!(diff.blocks.windows(2).all(|pair| pair[0] < pair[1]));
+    assert_eq!(diff.only_in_a, ["/docs/gone"]);
+    assert_eq!(diff.only_in_b, ["/aa", "/docs/new", "/zz"]);
+    let metadata: Vec<_> = diff
+        .metadata
+        .iter()
+        .map(|difference| (difference.path.as_str(), difference.fields.clone()))
+        .collect();
+    assert_eq!(metadata, [("/docs/b.md", vec!["mode"])]);
+    let changed: Vec<_> = diff.contents.iter().map(|c| c.path.as_str()).collect();
+    assert_eq!(changed, ["/docs/a.txt"]);
+
+    // and the other way round, the same things from the other side
+    let reverse = b.ext2.diff_images(&a.ext2).unwrap();
+    assert_eq!(reverse.blocks, diff.blocks);
+    assert_eq!(reverse.only_in_a, diff.only_in_b);
+    assert_eq!(reverse.only_in_b, diff.only_in_a);
+    // and nothing against itself
+    assert!(a.ext2.diff_images(&a.ext2).unwrap().is_empty());
+}
+
+#[test]
+fn json_output_is_the_same_every_time() {
+    let mut a = contents(fixture()).build();
+    let mut b = modified();
+    let (_a_dir, a_path) = a.dump();
+    let (_b_dir, b_path) = b.dump();
+    // as written out, superblock and descriptors included
+    let written = |path| Image::from_bytes(&fs::read(path).unwrap());
+    let expected = written(&a_path)
+        .ext2
+        .diff_images(&written(&b_path).ext2)
+        .unwrap();
+    let run = || {
+        let output = Command::new(env!("CARGO_BIN_EXE_ext2"))
+            .arg(&a_path)
+            .args(["image-diff", "--json"])
+            .arg(&b_path)
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        String::from_utf8(output.stdout).unwrap()
+    };
+    let printed = run();
+    assert_eq!(run(), printed);
+
+    // the fields in the order they're declared, and the lists as sorted
+    let keys = [
+        "\"note\"",
+        "\"blocks\"",
+        "\"only_in_a\"",
+        "\"only_in_b\"",
+        "\"metadata\"",
+        "\"contents\"",
+    ];
+    let at: Vec<usize> = keys.iter().map(|key| printed.find(key).unwrap()).collect();
+    assert!(at.windows(2).all(|pair| pair[0] < pair[1]), "{}", printed);
+    let value: Value = serde_json::from_str(&printed).unwrap();
+    assert_eq!(value, serde_json::to_value(&expected).unwrap());
+}
+
+#[test]
+fn different_block_sizes_are_compared_by_contents_only() {
+    let a = contents(fixture().block_size(1024)).build();
+    let mut b = contents(fixture().block_size(4096)).build();
+    let diff = a.ext2.diff_images(&b.ext2).unwrap();
+    assert_eq!(
+        diff,
+        ImageDiff {
+            note: Some(String::from(
+                "block sizes differ (1024 and 4096); compared by contents only"
+            )),
+            ..ImageDiff::default()
+        }
+    );
+
+    let inode = b.inode("/docs/a.txt");
+    b.ext2.write_file(inode, 0, b"different").unwrap();
+    let diff = a.ext2.diff_images(&b.ext2).unwrap();
+    assert!(diff.note.is_some());
+    assert_eq!(diff.blocks, Vec::<usize>::new());
+    let changed: Vec<_> = diff.contents.iter().map(|c| c.path.as_str()).collect();
+    assert_eq!(changed, ["/docs/a.txt"]);
+}
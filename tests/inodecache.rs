@@ -0,0 +1,107 @@
+//! The inode cache: `ls -l` of a big directory reads each inode-table block
+//! once, and inodes modified after they were cached, or rolled back, are
+//! never served stale.
+
+mod common;
+
+use common::{fixture, CountingDevice, Image, ROOT};
+use ext2::{Ext2, Ext2Options};
+
+const FILES: usize = 300;
+
+// what `ls -l` looks at: every entry of `dir`, and its inode
+fn ls_l(ext2: &Ext2, dir: usize) -> Vec<u64> {
+    ext2.read_dir_inode(dir)
+        .unwrap()
+        .iter()
+        .map(|entry| ext2.get_inode(entry.inode).unwrap().size())
+        .collect()
+}
+
+#[test]
+fn ls_l_reads_each_table_block_once() {
+    let mut image = fixture()
+        .block_size(1024)
+        .dir("many", |mut d| {
+            for i in 0..FILES {
+                d = d.file(&format!("f{:03}", i), &vec![b'x'; i]);
+            }
+            d
+        })
+        .build();
+    let (device, reads) = CountingDevice::new(image.synced_bytes());
+    let ext2 = Ext2Options::new()
+        .read_only(true)
+        .open_device(device)
+        .unwrap();
+    let many = ext2.resolve_path(ROOT, "/many").unwrap();
+
+    let sizes = ls_l(&ext2, many);
+    assert_eq!(sizes.len(), FILES + 2);
+    assert!(ext2.cached_inodes() > FILES);
+    // 8 inodes to a 1 KiB block, so the files' inodes span dozens of table
+    // blocks, and each was read once
+    let per_block = reads.per_block(1024);
+    let table_len = ext2.superblock.inodes_per_group as u64 * 128 / 1024;
+    let in_table = |block: u64| {
+        ext2.block_groups.iter().any(|group| {
+            let table = group.inode_table_block as u64;
+            (table..table + table_len).contains(&block)
+        })
+    };
+    let table_reads: Vec<usize> = per_block
+        .iter()
+        .filter(|&(&block, _)| in_table(block))
+        .map(|(_, &count)| count)
+        .collect();
+    assert!(table_reads.len() >= FILES / 8);
+    assert!(table_reads.iter().all(|&count| count == 1));
+
+    // and listing again reads nothing
+    reads.clear();
+    assert_eq!(ls_l(&ext2, many), sizes);
+    assert_eq!(reads.count(), 0);
+}
+
+#[test]
+fn modified_inodes_are_not_stale() {
+    // synced and opened again on a device that isn't in memory, so the
+    // table blocks start out unmodified, and the cache is used
+    let bytes = fixture()
+        .file("a", b"a")
+        .file("b", b"bb")
+        .build()
+        .synced_bytes();
+    let (device, _) = CountingDevice::new(bytes);
+    let mut image = Image {
+        ext2: Ext2Options::new().open_device(device).unwrap(),
+    };
+    let (a, b) = (image.inode("/a"), image.inode("/b"));
+    let ext2 = &mut image.ext2;
+    let cached = ext2.cached_inodes();
+    assert_eq!(ext2.get_inode(a).unwrap().size(), 1);
+    assert_eq!(ext2.get_inode(b).unwrap().size(), 2);
+    assert_eq!(ext2.cached_inodes(), cached + 2);
+    let before = ext2.snapshot();
+
+    // `a` and `b` share a table block, so changing one has both read afresh
+    ext2.write_file(a, 0, b"aaaa").unwrap();
+    assert_eq!(ext2.get_inode(a).unwrap().size(), 4);
+    assert_eq!(ext2.get_inode(b).unwrap().size(), 2);
+    ext2.write_file(b, 0, b"bbbbbb").unwrap();
+    assert_eq!(ext2.get_inode(b).unwrap().size(), 6);
+    let after = ext2.snapshot();
+
+    ext2.rollback(&before);
+    assert_eq!(ext2.get_inode(a).unwrap().size(), 1);
+    assert_eq!(ext2.get_inode(b).unwrap().size(), 2);
+    // forward again, to modified blocks no `block_mut` went through
+    ext2.rollback(&after);
+    assert_eq!(ext2.get_inode(a).unwrap().size(), 4);
+    assert_eq!(ext2.get_inode(b).unwrap().size(), 6);
+    assert_eq!(ext2.check(), []);
+
+    let image = Image::from_bytes(&image.synced_bytes());
+    assert_eq!(image.ext2.get_inode(a).unwrap().size(), 4);
+    assert_eq!(image.inode("/b"), b);
+}
@@ -0,0 +1,188 @@
+//! Inode numbers count from 1 and group bitmaps from 0; `InodeNo` converts
+//! between the two, and the allocator keeps off the reserved numbers. Inode
+//! table slots are as big as the superblock says, 128 bytes on revision 0.
+
+mod common;
+
+use common::{e2fsprogs, fixture, pattern, TempDir, ROOT};
+use ext2::{Ext2, Ext2Error, InodeNo};
+use std::fs;
+use std::process::Command;
+
+// big enough at 1KiB blocks for several groups
+const SIZE: usize = 20 << 20;
+
+#[test]
+fn every_inode_round_trips() {
+    let image = fixture().size(SIZE).build();
+    let sb = &image.ext2.superblock;
+    let inodes_per_group = sb.inodes_per_group as usize;
+    let inodes_count = sb.inodes_count as usize;
+    assert!(image.ext2.block_groups.len() > 2);
+    for inode in 1..=inodes_count {
+        let (group, index) = InodeNo(inode).to_group_and_index(sb).unwrap();
+        assert!(index < inodes_per_group);
+        assert_eq!(
+            InodeNo::from_group_and_index(group, index, sb),
+            Some(InodeNo(inode))
+        );
+    }
+
+    // the last of group 0 and the first of group 1
+    let last = InodeNo(inodes_per_group);
+    assert_eq!(
+        last.to_group_and_index(sb).unwrap(),
+        (0, inodes_per_group - 1)
+    );
+    let first = InodeNo(inodes_per_group + 1);
+    assert_eq!(first.to_group_and_index(sb).unwrap(), (1, 0));
+    assert_eq!(InodeNo(1).to_group_and_index(sb).unwrap(), (0, 0));
+    assert_eq!(ROOT, usize::from(InodeNo::ROOT));
+}
+
+#[test]
+fn out_of_range_numbers() {
+    let image = fixture().size(SIZE).build();
+    let sb = &image.ext2.superblock;
+    let inodes_per_group = sb.inodes_per_group as usize;
+    let inodes_count = sb.inodes_count as usize;
+    for inode in [0, inodes_count + 1] {
+        assert!(matches!(
+            InodeNo(inode).to_group_and_index(sb),
+            Err(Ext2Error::InodeOutOfRange { .. })
+        ));
+        assert!(matches!(
+            image.ext2.inode_is_allocated(inode),
+            Err(Ext2Error::InodeOutOfRange { .. })
+        ));
+    }
+    assert_eq!(InodeNo::from_group_and_index(0, inodes_per_group, sb), None);
+    let groups = image.ext2.block_groups.len();
+    assert_eq!(InodeNo::from_group_and_index(groups, 0, sb), None);
+}
+
+#[test]
+fn first_usable_is_never_below_eleven() {
+    let mut image = fixture().build();
+    let sb = &mut image.ext2.superblock;
+    sb.rev_major = 1;
+    sb.first_inode = 20;
+    assert_eq!(InodeNo::first_usable(sb), InodeNo(20));
+    assert!(InodeNo(19).is_reserved(sb));
+    assert!(!InodeNo(20).is_reserved(sb));
+    for first_inode in [0, 1, 2, 10] {
+        sb.first_inode = first_inode;
+        assert_eq!(InodeNo::first_usable(sb), InodeNo::GOOD_OLD_FIRST);
+    }
+    // revision 0 doesn't record it
+    sb.rev_major = 0;
+    sb.first_inode = 20;
+    assert_eq!(InodeNo::first_usable(sb), InodeNo(11));
+    assert!(InodeNo::ROOT.is_reserved(sb));
+}
+
+#[test]
+fn allocation_skips_reserved_inodes() {
+    let mut image = fixture().build();
+    // a damaged superblock saying nothing is reserved
+    image.ext2.superblock.first_inode = 0;
+    let inode = image.ext2.create_file(ROOT, "f", 0o644).unwrap();
+    assert!(!InodeNo(inode).is_reserved(&image.ext2.superblock));
+    assert!(inode >= 11);
+}
+
+#[test]
+fn first_usable_in_a_later_group() {
+    let mut image = fixture().size(SIZE).build();
+    let inodes_per_group = image.ext2.superblock.inodes_per_group as usize;
+    // everything up to a few inodes into group 1 reserved: the allocator
+    // skips group 0 and starts part way into group 1
+    image.ext2.superblock.first_inode = (inodes_per_group + 5) as u32;
+    let first = image.ext2.create_file(ROOT, "a", 0o644).unwrap();
+    assert_eq!(first, inodes_per_group + 5);
+    assert_eq!(
+        InodeNo(first)
+            .to_group_and_index(&image.ext2.superblock)
+            .unwrap(),
+        (1, 4)
+    );
+    let second = image.ext2.create_file(ROOT, "b", 0o644).unwrap();
+    assert_eq!(second, first + 1);
+}
+
+#[test]
+fn inodes_lists_the_root_and_nothing_reserved() {
+    let image = fixture().size(SIZE).file("f", b"f").build();
+    let sb = &image.ext2.superblock;
+    let listed: Vec<usize> = image.ext2.inodes().map(|(inode, _)| inode).collect();
+    assert_eq!(listed[0], ROOT);
+    assert!(listed[1..]
+        .iter()
+        .all(|&inode| !InodeNo(inode).is_reserved(sb)));
+    assert!(listed.contains(&image.inode("/f")));
+}
+
+// an image made by mke2fs with `args`, holding /sub/a.txt and /big
+fn mke2fs(args: &[&str]) -> Ext2 {
+    let dir = TempDir::new("inodeno");
+    let tree = dir.path().join("tree");
+    fs::create_dir_all(tree.join("sub")).unwrap();
+    fs::write(tree.join("sub/a.txt"), b"hello").unwrap();
+    fs::write(tree.join("big"), pattern(30 << 10)).unwrap();
+    let file = dir.path().join("image");
+    let output = Command::new("mke2fs")
+        .args(["-q", "-F", "-b", "1024"])
+        .args(args)
+        .arg("-d")
+        .arg(&tree)
+        .arg(&file)
+        .arg("4096k")
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    Ext2::new(fs::read(&file).unwrap()).unwrap()
+}
+
+fn reads_the_tree(ext2: &Ext2) {
+    assert!(ext2.get_inode(ROOT).unwrap().is_dir());
+    let a = ext2.resolve_path(ROOT, "/sub/a.txt").unwrap();
+    assert_eq!(ext2.read_file_inode(a).unwrap(), b"hello");
+    let big = ext2.resolve_path(ROOT, "/big").unwrap();
+    assert_eq!(ext2.read_file_inode(big).unwrap(), pattern(30 << 10));
+    assert_eq!(ext2.check(), []);
+}
+
+#[test]
+fn small_inodes() {
+    if !e2fsprogs::available() {
+        return;
+    }
+    let ext2 = mke2fs(&["-t", "ext2", "-I", "128"]);
+    assert_eq!(ext2.superblock.inode_size, 128);
+    assert_eq!(InodeNo::slot_size(&ext2.superblock), 128);
+    reads_the_tree(&ext2);
+}
+
+#[test]
+fn revision_0() {
+    if !e2fsprogs::available() {
+        return;
+    }
+    let mut ext2 = mke2fs(&["-r", "0"]);
+    let sb = &ext2.superblock;
+    assert_eq!(sb.rev_major, 0);
+    assert_eq!(InodeNo::slot_size(sb), InodeNo::GOOD_OLD_SIZE);
+    assert_eq!(InodeNo::first_usable(sb), InodeNo::GOOD_OLD_FIRST);
+    reads_the_tree(&ext2);
+    // and a new file gets a slot of its own, past the reserved ones
+    let before = ext2.resolve_path(ROOT, "/big").unwrap();
+    let new = ext2.create_file(ROOT, "new", 0o644).unwrap();
+    assert!(new > before);
+    ext2.write_file(new, 0, b"new").unwrap();
+    assert_eq!(ext2.read_file_inode(new).unwrap(), b"new");
+    reads_the_tree(&ext2);
+}
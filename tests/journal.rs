@@ -0,0 +1,104 @@
+//! Noticing ext3 journals, and whether they still hold transactions, on
+//! images made by mke2fs. Skipped, passing, when e2fsprogs isn't installed.
+
+mod common;
+
+use common::e2fsprogs::{self, mke2fs};
+use common::{fixture, Image, ROOT};
+use ext2::Ext2Error;
+
+const SIZE: usize = 8 << 20;
+// where the required features are in the superblock, and the bit saying the
+// journal has to be replayed
+const FEATURES_REQ: usize = 1024 + 0x60;
+const RECOVER: u32 = 0x4;
+
+// the byte offset of the journal's superblock in `image`
+fn journal_superblock(image: &[u8]) -> usize {
+    let opened = Image::from_bytes(image);
+    let journal = opened.ext2.superblock.journal_inode as usize;
+    let first = opened.ext2.file_blocks(journal).unwrap().next().unwrap();
+    first.unwrap() * opened.ext2.block_size
+}
+
+fn put_be32(image: &mut [u8], at: usize, value: u32) {
+    image[at..at + 4].copy_from_slice(&value.to_be_bytes());
+}
+
+#[test]
+fn ext2_has_no_journal() {
+    let image = fixture().build();
+    assert_eq!(image.ext2.journal(), None);
+    assert_eq!(image.ext2.fs_info().journal, None);
+    assert!(!image.ext2.describe().contains("Journal"));
+}
+
+#[test]
+fn clean_journal() {
+    if !e2fsprogs::available() {
+        return;
+    }
+    let mut image = Image::from_bytes(&mke2fs(&["-t", "ext3"], SIZE));
+    let journal = image.ext2.journal().unwrap();
+    assert_eq!(journal.inode, 8);
+    assert_eq!(journal.device, None);
+    assert!(journal.size >= 1024 * 1024);
+    assert!(journal.is_clean(), "{:?}", journal);
+    assert!(image
+        .ext2
+        .describe()
+        .contains("State:                clean"));
+
+    // never written, even when asked to open read-write
+    assert_eq!(
+        image.ext2.forced_read_only(),
+        Some("the filesystem has an ext3 journal")
+    );
+    assert!(matches!(
+        image.ext2.create_file(ROOT, "f", 0o644),
+        Err(Ext2Error::ReadOnly)
+    ));
+}
+
+#[test]
+fn dirty_journal() {
+    if !e2fsprogs::available() {
+        return;
+    }
+    let mut bytes = mke2fs(&["-t", "ext3"], SIZE);
+    let at = journal_superblock(&bytes);
+    // a transaction, number 7, waiting at the journal's block 1
+    put_be32(&mut bytes, at + 0x18, 7);
+    put_be32(&mut bytes, at + 0x1c, 1);
+    let features = u32::from_le_bytes(bytes[FEATURES_REQ..FEATURES_REQ + 4].try_into().unwrap());
+    bytes[FEATURES_REQ..FEATURES_REQ + 4].copy_from_slice(&(features | RECOVER).to_le_bytes());
+
+    let image = Image::from_bytes(&bytes);
+    let journal = image.ext2.journal().unwrap();
+    assert!(journal.needs_recovery);
+    assert_eq!((journal.sequence, journal.start), (7, 1));
+    assert!(!journal.is_clean());
+    assert_eq!(
+        journal.state(),
+        "needs recovery, from transaction 7 at journal block 1"
+    );
+    // still readable
+    assert!(image.ext2.read_dir_inode(ROOT).unwrap().len() >= 3);
+}
+
+#[test]
+fn unreadable_journal() {
+    if !e2fsprogs::available() {
+        return;
+    }
+    let mut bytes = mke2fs(&["-t", "ext3"], SIZE);
+    let at = journal_superblock(&bytes);
+    put_be32(&mut bytes, at, 0x1234_5678);
+
+    let journal = Image::from_bytes(&bytes).ext2.journal().unwrap();
+    assert_eq!(
+        journal.problem.as_deref(),
+        Some("bad magic number 0x12345678")
+    );
+    assert!(!journal.is_clean());
+}
@@ -0,0 +1,102 @@
+//! `--json` on the commands that report on paths: one JSON document on
+//! stdout, with the same entries the plain output lists.
+
+mod common;
+
+use common::{fixture, TempDir};
+use serde_json::Value;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+fn run(image: &Path, command: &[&str]) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_ext2"))
+        .arg(image)
+        .args(command)
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{:?}: {}",
+        command,
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout).unwrap()
+}
+
+fn json(image: &Path, command: &[&str]) -> Value {
+    serde_json::from_str(&run(image, command)).unwrap()
+}
+
+fn image(dir: &TempDir) -> std::path::PathBuf {
+    let mut image = fixture()
+        .dir("docs", |d| {
+            d.file("a.txt", b"hello")
+                .file("b.md", b"# b")
+                .dir("more", |d| d.file("c.txt", b"c"))
+        })
+        .symlink("link.txt", "docs/a.txt")
+        .build();
+    let path = dir.path().join("image.ext2");
+    fs::write(&path, image.synced_bytes()).unwrap();
+    path
+}
+
+#[test]
+fn stat_reports_the_path_and_its_inode() {
+    let dir = TempDir::new("json");
+    let path = image(&dir);
+    let stat = json(&path, &["stat", "--json", "docs/a.txt"]);
+    assert_eq!(stat["path"], "docs/a.txt");
+    assert_eq!(stat["type"], "regular file");
+    assert_eq!(stat["size"], 5);
+    assert_eq!(stat["links"], 1);
+    // the same fields istat gives for the inode
+    let inode = stat["inode"].as_u64().unwrap().to_string();
+    let mut istat = json(&path, &["istat", "--json", &inode]);
+    istat["path"] = stat["path"].clone();
+    assert_eq!(stat, istat);
+
+    let plain = run(&path, &["stat", "docs/a.txt"]);
+    assert!(plain.starts_with(&format!("File: docs/a.txt\nInode: {}\n", inode)));
+}
+
+#[test]
+fn find_lists_matching_entries() {
+    let dir = TempDir::new("json");
+    let path = image(&dir);
+    let paths = |found: &Value| -> Vec<String> {
+        found
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|entry| entry["path"].as_str().unwrap().to_string())
+            .collect()
+    };
+
+    let found = json(&path, &["find", "--json", "/", "-name", "*.txt"]);
+    assert_eq!(
+        paths(&found),
+        ["/docs/a.txt", "/docs/more/c.txt", "/link.txt"]
+    );
+    assert_eq!(found[0]["name"], "a.txt");
+    assert_eq!(found[0]["size"], 5);
+    assert_eq!(found[2]["type"], "symbolic link");
+
+    let files = json(
+        &path,
+        &["find", "--json", "docs", "-type", "f", "-name", "?.*"],
+    );
+    assert_eq!(
+        paths(&files),
+        ["docs/a.txt", "docs/b.md", "docs/more/c.txt"]
+    );
+    let dirs = json(&path, &["find", "--json", "-type", "d"]);
+    assert_eq!(paths(&dirs), ["lost+found", "docs", "docs/more"]);
+
+    // and without --json, the same paths a line each
+    assert_eq!(
+        run(&path, &["find", "docs", "-name", "*.txt"]),
+        "docs/a.txt\ndocs/more/c.txt\n"
+    );
+}
@@ -0,0 +1,63 @@
+//! Changing the volume label and UUID, and having the change reach every
+//! copy of the superblock once the image is written out.
+
+mod common;
+
+use common::{e2fsprogs, fixture, Image};
+use ext2::Ext2Error;
+use uuid::Uuid;
+
+// big enough at 1KiB blocks for several groups, so there are backups
+const SIZE: usize = 32 << 20;
+
+#[test]
+fn set_label_and_uuid() {
+    let mut image = fixture().size(SIZE).build();
+    assert_eq!(image.ext2.label(), "");
+    image.ext2.set_label("backups").unwrap();
+    let old = image.ext2.uuid;
+    let uuid = image.ext2.regenerate_uuid().unwrap();
+    assert_ne!(uuid, old);
+    assert_eq!(uuid.get_version_num(), 4);
+    assert_eq!(image.ext2.uuid, uuid);
+
+    let mut reopened = Image::from_bytes(&image.synced_bytes());
+    assert_eq!(reopened.ext2.label(), "backups");
+    assert_eq!(reopened.ext2.uuid, uuid);
+    let backups = reopened.ext2.backup_superblocks();
+    assert!(backups.len() > 1);
+    for (group, _, backup) in backups {
+        let backup = backup.unwrap();
+        assert_eq!(&backup.volume_name[..8], b"backups\0", "group {}", group);
+        assert_eq!(Uuid::from_bytes(backup.fs_id), uuid, "group {}", group);
+    }
+    assert_eq!(reopened.ext2.check(), []);
+
+    if e2fsprogs::available() {
+        let (_dir, file) = reopened.dump();
+        e2fsprogs::fsck(&file).unwrap();
+    }
+}
+
+#[test]
+fn shorter_label_clears_the_rest() {
+    let mut image = fixture().build();
+    image.ext2.set_label("sixteen-byte-lbl").unwrap();
+    image.ext2.set_label("short").unwrap();
+    assert_eq!(image.ext2.label(), "short");
+    assert_eq!(&image.ext2.superblock.volume_name[5..], [0; 11]);
+    image.ext2.set_label("").unwrap();
+    assert_eq!(image.ext2.label(), "");
+}
+
+#[test]
+fn label_must_fit() {
+    let mut image = fixture().build();
+    for bad in ["seventeen-bytes!!", "nul\0"] {
+        assert!(matches!(
+            image.ext2.set_label(bad),
+            Err(Ext2Error::InvalidName { .. })
+        ));
+    }
+    assert_eq!(image.ext2.label(), "");
+}
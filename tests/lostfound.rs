@@ -0,0 +1,157 @@
+//! Bringing orphans back: `ensure_lost_and_found` makes /lost+found the way
+//! mke2fs does when it's missing, and `relink_orphan` links an inode nothing
+//! refers to into it as `#<inode>`, moving a directory's `..` along with it.
+
+mod common;
+
+use common::{fixture, pattern, Image, ROOT};
+use ext2::{Ext2, Ext2Error, Inconsistency};
+
+fn image() -> Image {
+    fixture()
+        .dir("p", |d| {
+            d.file("f", &pattern(2000))
+                .dir("o", |d| d.file("x", b"in o"))
+        })
+        .build()
+}
+
+// lose the entry `name` in `dir`'s first block by zeroing its inode
+// number, as a crash part way through might, leaving every count alone
+fn drop_entry(ext2: &mut Ext2, dir: usize, name: &str) {
+    let block_num = ext2.file_blocks(dir).unwrap().next().unwrap().unwrap();
+    let block = ext2.block_mut(block_num).unwrap();
+    let mut offset = 0;
+    loop {
+        let rec_len = u16::from_le_bytes([block[offset + 4], block[offset + 5]]) as usize;
+        let name_len = block[offset + 6] as usize;
+        if &block[offset + 8..offset + 8 + name_len] == name.as_bytes() {
+            block[offset..offset + 4].fill(0);
+            return;
+        }
+        offset += rec_len;
+    }
+}
+
+fn links(ext2: &Ext2, inode: usize) -> u16 {
+    ext2.get_inode(inode).unwrap().hard_links
+}
+
+#[test]
+fn lost_and_found_is_made_like_mke2fs_makes_it() {
+    for (block_size, size) in [(1024, 12 << 10), (4096, 16 << 10)] {
+        let mut image = fixture().block_size(block_size).build();
+        let ext2 = &mut image.ext2;
+        let existing = ext2.lookup(ROOT, "lost+found").unwrap().unwrap();
+        assert_eq!(ext2.ensure_lost_and_found().unwrap(), existing);
+
+        ext2.remove_dir(ROOT, "lost+found").unwrap();
+        let root_links = links(ext2, ROOT);
+        let dirs = ext2.block_groups[0].dirs_count;
+        let dir = ext2.ensure_lost_and_found().unwrap();
+        assert_eq!(ext2.lookup(ROOT, "lost+found").unwrap(), Some(dir));
+        let record = ext2.get_inode(dir).unwrap();
+        assert!(record.is_dir());
+        assert_eq!(record.type_perm.bits() & 0o7777, 0o700);
+        // room for orphans already there, so none need allocating
+        assert_eq!(record.size_low as usize, size, "{}", block_size);
+        assert_eq!(ext2.owned_blocks(dir).unwrap().len(), size / block_size);
+        assert_eq!(record.hard_links, 2);
+        assert_eq!(links(ext2, ROOT), root_links + 1);
+        assert_eq!(ext2.block_groups[0].dirs_count, dirs + 1);
+        assert_eq!(ext2.check(), []);
+    }
+}
+
+#[test]
+fn lost_and_found_that_is_a_file_is_refused() {
+    let mut image = fixture().build();
+    let ext2 = &mut image.ext2;
+    ext2.remove_dir(ROOT, "lost+found").unwrap();
+    ext2.create_file(ROOT, "lost+found", 0o644).unwrap();
+    assert!(matches!(
+        ext2.ensure_lost_and_found(),
+        Err(Ext2Error::NotADirectory { name }) if name == "/lost+found"
+    ));
+}
+
+#[test]
+fn an_orphaned_file_is_relinked_by_inode_number() {
+    let mut image = image();
+    let (p, f) = (image.inode("/p"), image.inode("/p/f"));
+    let lost_and_found = image.inode("/lost+found");
+    let ext2 = &mut image.ext2;
+    drop_entry(ext2, p, "f");
+    assert_eq!(ext2.check(), [Inconsistency::Unreferenced { inode: f }]);
+    assert_eq!(ext2.orphans().unwrap(), [f]);
+
+    let path = ext2.relink_orphan(f).unwrap();
+    assert_eq!(path, format!("/lost+found/#{}", f));
+    assert_eq!(ext2.resolve_path(ROOT, &path).unwrap(), f);
+    assert_eq!(links(ext2, f), 1);
+    // a file's entry adds nothing to the directory's own count
+    assert_eq!(links(ext2, lost_and_found), 2);
+    assert_eq!(ext2.read_file_inode(f).unwrap(), pattern(2000));
+    assert_eq!(ext2.check(), []);
+    assert_eq!(ext2.orphans().unwrap(), []);
+    // and it's there now, so not again
+    assert!(ext2.relink_orphan(f).is_err());
+}
+
+#[test]
+fn an_orphaned_directory_takes_its_parent_link_along() {
+    let mut image = image();
+    let (p, o, x) = (
+        image.inode("/p"),
+        image.inode("/p/o"),
+        image.inode("/p/o/x"),
+    );
+    let lost_and_found = image.inode("/lost+found");
+    let ext2 = &mut image.ext2;
+    let dirs = ext2.block_groups.iter().map(|g| g.dirs_count).sum::<u16>();
+    assert_eq!(links(ext2, p), 3);
+    drop_entry(ext2, p, "o");
+    // p's count still has o's `..` in it, which the walk no longer reaches,
+    // nor what's in o
+    assert_eq!(
+        ext2.check(),
+        [
+            Inconsistency::LinkCount {
+                inode: p,
+                path: Some(String::from("/p")),
+                recorded: 3,
+                actual: 2,
+            },
+            Inconsistency::Unreferenced { inode: o },
+            Inconsistency::Unreferenced { inode: x },
+        ]
+    );
+
+    let path = ext2.relink_orphan(o).unwrap();
+    assert_eq!(path, format!("/lost+found/#{}", o));
+    assert_eq!(ext2.lookup(o, "..").unwrap(), Some(lost_and_found));
+    assert_eq!(links(ext2, lost_and_found), 3);
+    assert_eq!(links(ext2, p), 2);
+    assert_eq!(links(ext2, o), 2);
+    // it was a directory all along, so no group counts one more or less
+    let now = ext2.block_groups.iter().map(|g| g.dirs_count).sum::<u16>();
+    assert_eq!(now, dirs);
+    let path = format!("/lost+found/#{}/x", o);
+    assert_eq!(ext2.resolve_path(ROOT, &path).unwrap(), x);
+    assert_eq!(ext2.read_file_inode(x).unwrap(), b"in o");
+    assert_eq!(ext2.check(), []);
+}
+
+#[test]
+fn relinking_makes_lost_and_found_when_missing() {
+    let mut image = image();
+    let (p, f) = (image.inode("/p"), image.inode("/p/f"));
+    let ext2 = &mut image.ext2;
+    ext2.remove_dir(ROOT, "lost+found").unwrap();
+    drop_entry(ext2, p, "f");
+    let path = ext2.relink_orphan(f).unwrap();
+    assert_eq!(path, format!("/lost+found/#{}", f));
+    let dir = ext2.lookup(ROOT, "lost+found").unwrap().unwrap();
+    assert_eq!(links(ext2, dir), 2);
+    assert_eq!(ext2.check(), []);
+}
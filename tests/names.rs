@@ -0,0 +1,84 @@
+//! Names with bytes that would upset a terminal: stored and read back as
+//! they are, shown escaped, and only ever rejected on the way in for what
+//! ext2 itself doesn't allow.
+
+mod common;
+
+use common::{fixture, ROOT};
+use ext2::{Escaped, Ext2Error};
+
+const NEWLINE: &str = "two\nlines";
+const ANSI: &str = "\x1b[31mred\x1b[0m";
+
+#[test]
+fn read_back_and_shown_escaped() {
+    let image = fixture()
+        .file(NEWLINE, b"a")
+        .file(ANSI, b"b")
+        .file("back\\slash", b"c")
+        .build();
+    let ext2 = &image.ext2;
+    let entries = ext2.read_dir_inode(ROOT).unwrap();
+    let shown: Vec<(Vec<u8>, String)> = entries
+        .iter()
+        .map(|entry| (entry.name.clone(), entry.escaped().to_string()))
+        .filter(|(bytes, _)| bytes.len() > 2 && bytes.as_slice() != b"lost+found")
+        .collect();
+    assert_eq!(
+        shown,
+        [
+            (NEWLINE.as_bytes().to_vec(), String::from("two\\x0alines")),
+            (
+                ANSI.as_bytes().to_vec(),
+                String::from("\\x1b[31mred\\x1b[0m")
+            ),
+            (b"back\\slash".to_vec(), String::from("back\\\\slash")),
+        ]
+    );
+    assert!(ext2.lookup(ROOT, NEWLINE).unwrap().is_some());
+
+    // and so are names in errors
+    let err = ext2.resolve_path(ROOT, "/\x1b[2Jgone").unwrap_err();
+    assert_eq!(err.to_string(), "/\\x1b[2Jgone: No such file or directory");
+}
+
+#[test]
+fn not_utf8() {
+    let mut image = fixture().file("xx", b"").build();
+    let ext2 = &mut image.ext2;
+    // there's no way to create such a name through a `&str`, so patch it in
+    let block = ext2.get_inode(ROOT).unwrap().direct_pointer[0] as usize;
+    let data = ext2.block_mut(block).unwrap();
+    let at = data.windows(2).position(|bytes| bytes == b"xx").unwrap();
+    data[at..at + 2].copy_from_slice(&[0xff, 0xc3]);
+
+    let entries = ext2.read_dir_inode(ROOT).unwrap();
+    let entry = entries
+        .iter()
+        .find(|entry| entry.name == [0xff, 0xc3])
+        .unwrap();
+    assert_eq!(entry.escaped().to_string(), "\\xff\\xc3");
+    // valid sequences on either side stay as they are
+    assert_eq!(Escaped("café\u{7f}".as_bytes()).to_string(), "café\\x7f");
+    assert_eq!(Escaped(b"a\xe2\x82b").to_string(), "a\\xe2\\x82b");
+}
+
+#[test]
+fn invalid_names_rejected() {
+    let mut image = fixture().build();
+    let ext2 = &mut image.ext2;
+    let longest = "n".repeat(255);
+    for name in ["", ".", "..", "a/b", "nul\0", &format!("{}n", longest)] {
+        assert!(
+            matches!(
+                ext2.create_file(ROOT, name, 0o644),
+                Err(Ext2Error::InvalidName { .. })
+            ),
+            "{:?}",
+            name
+        );
+    }
+    ext2.create_file(ROOT, &longest, 0o644).unwrap();
+    ext2.create_file(ROOT, NEWLINE, 0o644).unwrap();
+    assert_eq!(ext2.check(), []);
+}
@@ -0,0 +1,95 @@
+//! Allocation falls back through every group, and fails with `NoSpace` or
+//! `NoInodes` only once they're all full, leaving a consistent filesystem.
+
+mod common;
+
+use common::{fixture, pattern, ROOT};
+use ext2::{Ext2Error, Inconsistency};
+
+#[test]
+fn filling_every_block() {
+    // 1K blocks, so two groups
+    let mut image = fixture().size(10 << 20).build();
+    let ext2 = &mut image.ext2;
+    assert_eq!(ext2.block_groups.len(), 2);
+    let data = pattern(64 * 1024);
+    let mut i = 0;
+    let last = loop {
+        let inode = ext2.create_file(ROOT, &format!("f{}", i), 0o644).unwrap();
+        i += 1;
+        match ext2.write_file(inode, 0, &data) {
+            Ok(written) if written == data.len() => {}
+            Ok(_) | Err(Ext2Error::NoSpace) => break inode,
+            Err(err) => panic!("{}", err),
+        }
+    };
+    assert!(i > 100);
+    assert!(ext2.block_groups.iter().all(|g| g.free_blocks_count == 0));
+    assert_eq!(ext2.superblock.free_blocks_count, 0);
+    assert_eq!(ext2.space_info().available_kib, 0);
+    assert!(matches!(
+        ext2.write_file(last, 64 * 1024, b"more"),
+        Err(Ext2Error::NoSpace)
+    ));
+    assert!(matches!(
+        ext2.create_dir(ROOT, "dir", 0o755),
+        Err(Ext2Error::NoSpace)
+    ));
+    assert_eq!(ext2.check(), []);
+
+    // and what's freed can be used again
+    ext2.unlink(ROOT, "f0").unwrap();
+    let inode = ext2.create_file(ROOT, "again", 0o644).unwrap();
+    assert_eq!(ext2.write_file(inode, 0, &data).unwrap(), data.len());
+    assert_eq!(ext2.check(), []);
+}
+
+#[test]
+fn filling_every_inode() {
+    let mut image = fixture().size(20 << 20).build();
+    let ext2 = &mut image.ext2;
+    assert!(ext2.block_groups.len() > 2);
+    let err = 'fill: loop {
+        for d in 0.. {
+            let dir = match ext2.create_dir(ROOT, &format!("d{}", d), 0o755) {
+                Ok(dir) => dir,
+                Err(err) => break 'fill err,
+            };
+            for f in 0..200 {
+                if let Err(err) = ext2.create_file(dir, &format!("f{}", f), 0o644) {
+                    break 'fill err;
+                }
+            }
+        }
+    };
+    assert!(matches!(err, Ext2Error::NoInodes), "{}", err);
+    assert_eq!(err.to_string(), "No space left on device");
+    assert_eq!(err.errno(), libc::ENOSPC);
+    assert!(ext2.block_groups.iter().all(|g| g.free_inodes_count == 0));
+    let space = ext2.space_info();
+    assert_eq!(space.free_inodes, 0);
+    assert!(space.available_kib > 0);
+    assert!(matches!(
+        ext2.create_symlink(ROOT, "link", "d0"),
+        Err(Ext2Error::NoInodes)
+    ));
+    assert_eq!(ext2.check(), []);
+}
+
+#[test]
+fn bitmaps_are_trusted_over_free_counts() {
+    let mut image = fixture().size(10 << 20).build();
+    let ext2 = &mut image.ext2;
+    for group in ext2.block_groups.iter_mut() {
+        group.free_inodes_count = 0;
+        group.free_blocks_count = 0;
+    }
+    let inode = ext2.create_file(ROOT, "f", 0o644).unwrap();
+    assert_eq!(ext2.write_file(inode, 0, b"hello").unwrap(), 5);
+    assert_eq!(ext2.read_file_inode(inode).unwrap(), b"hello");
+    // only the counts are off, and fsck puts them right
+    assert!(ext2.check().iter().all(|problem| matches!(
+        problem,
+        Inconsistency::FreeBlocksCount { .. } | Inconsistency::FreeInodesCount { .. }
+    )));
+}
@@ -0,0 +1,156 @@
+//! Opening images through `Ext2::options`: from a file, a buffer or a
+//! device, with options that have to go together, and changing some of
+//! them once it's open.
+
+mod common;
+
+use common::{fixture, pattern, CountingDevice, ROOT};
+use ext2::{Device, Ext2, Ext2Error, Strictness};
+use std::io::Cursor;
+
+#[test]
+fn from_a_path_bytes_or_a_device() {
+    let mut image = fixture().file("hello.txt", b"hello\n").build();
+    let bytes = image.synced_bytes();
+    let (_dir, path) = image.dump();
+
+    // one byte in, so not aligned as `open` needs it
+    let mut unaligned = vec![0];
+    unaligned.extend(&bytes);
+    let opened = [
+        Ext2::options().open_path(&path).unwrap(),
+        Ext2::options().open_bytes(&unaligned[1..]).unwrap(),
+        Ext2::options()
+            .read_only(true)
+            .open_device(Device::new(bytes))
+            .unwrap(),
+    ];
+    for ext2 in &opened {
+        let hello = ext2.resolve_path(ROOT, "/hello.txt").unwrap();
+        assert_eq!(ext2.read_file_inode(hello).unwrap(), b"hello\n");
+    }
+    assert!(opened[2].is_read_only());
+
+    let missing = path.with_file_name("missing.ext2");
+    assert!(matches!(
+        Ext2::options().open_path(missing),
+        Err(Ext2Error::Host { .. })
+    ));
+}
+
+#[test]
+fn blocks_read_as_needed() {
+    let mut image = fixture()
+        .file("hello.txt", b"hello\n")
+        .file_with_size("big.bin", 256 << 10)
+        .build();
+    let block_size = image.ext2.block_size;
+    let (device, reads) = CountingDevice::new(image.synced_bytes());
+    let ext2 = Ext2::options().read_only(true).open_device(device).unwrap();
+    // only the superblock and the descriptor table to open it
+    assert_eq!(reads.count(), 2);
+
+    let hello = ext2.resolve_path(ROOT, "/hello.txt").unwrap();
+    assert_eq!(ext2.read_file_inode(hello).unwrap(), b"hello\n");
+    let big = ext2
+        .get_inode(ext2.resolve_path(ROOT, "/big.bin").unwrap())
+        .unwrap();
+    let untouched = big.direct_pointer[0] as u64;
+    assert!(!reads.per_block(block_size).contains_key(&untouched));
+
+    // and each one only once, however often it's looked at
+    reads.clear();
+    for _ in 0..3 {
+        ext2.read_file_inode(hello).unwrap();
+    }
+    assert_eq!(reads.count(), 0);
+}
+
+#[test]
+fn file_contents_are_not_all_kept() {
+    const SIZE: usize = 2 << 20;
+    let mut image = fixture().file_with_size("big.bin", SIZE).build();
+    let block_size = image.ext2.block_size;
+    let (device, reads) = CountingDevice::new(image.synced_bytes());
+    let ext2 = Ext2::options().read_only(true).open_device(device).unwrap();
+    let big = ext2.resolve_path(ROOT, "/big.bin").unwrap();
+    let first = ext2.get_inode(big).unwrap().direct_pointer[0] as u64;
+
+    // a file of more blocks than are kept: by the end, its first block has
+    // gone, and reading the file again reads it again
+    let mut out = Vec::new();
+    ext2.copy_file_to(big, &mut out).unwrap();
+    assert_eq!(out, pattern(SIZE));
+    assert_eq!(reads.per_block(block_size)[&first], 1);
+    reads.clear();
+    let mut start = [0; 16];
+    ext2.read_at(big, 0, &mut start).unwrap();
+    assert_eq!(start[..], pattern(SIZE)[..16]);
+    assert_eq!(reads.all(), [(first * block_size as u64, block_size)]);
+}
+
+#[test]
+fn lenient_only_read_only() {
+    let bytes = fixture().build().synced_bytes();
+    let lenient = Ext2::options().strictness(Strictness::Lenient);
+    assert!(matches!(
+        lenient.open_bytes(&bytes),
+        Err(Ext2Error::BadOptions { .. })
+    ));
+    assert!(lenient.read_only(true).open_bytes(&bytes).is_ok());
+}
+
+#[test]
+fn owns_its_device() {
+    let bytes = fixture()
+        .file("hello.txt", b"hello\n")
+        .build()
+        .synced_bytes();
+    let mut ext2 = Ext2::new(bytes.clone()).unwrap();
+    assert_eq!(*ext2.device_bytes().unwrap(), bytes);
+    let hello = ext2.resolve_path(ROOT, "/hello.txt").unwrap();
+    ext2.write_file(hello, 0, b"howdy\n").unwrap();
+    // changes stay out of the device until they're synced over a copy of it
+    assert_eq!(*ext2.device_bytes().unwrap(), bytes);
+
+    // nothing borrowed, so it can go to another thread with what it was given
+    let synced = std::thread::spawn(move || {
+        let mut device = Cursor::new(ext2.device_bytes().unwrap().into_owned());
+        ext2.sync(&mut device).unwrap();
+        device.into_inner()
+    })
+    .join()
+    .unwrap();
+    let reopened = Ext2::new(synced.into_boxed_slice()).unwrap();
+    assert_eq!(reopened.read_file_inode(hello).unwrap(), b"howdy\n");
+}
+
+#[test]
+fn change_options_once_open() {
+    let mut image = fixture().file("f", b"f").build();
+    let f = image.inode("/f");
+    let ext2 = &mut image.ext2;
+    assert!(!ext2.noatime());
+    ext2.inode_mut(f).unwrap().atime = 0;
+    ext2.set_noatime(true);
+    ext2.touch_accessed(f).unwrap();
+    assert_eq!(ext2.get_inode(f).unwrap().atime, 0);
+    ext2.set_noatime(false);
+    ext2.touch_accessed(f).unwrap();
+    assert_ne!(ext2.get_inode(f).unwrap().atime, 0);
+
+    // lenient reads still need the filesystem read-only
+    assert!(matches!(
+        ext2.set_strictness(Strictness::Lenient),
+        Err(Ext2Error::BadOptions { .. })
+    ));
+    assert_eq!(ext2.strictness(), Strictness::Strict);
+    let mut read_only = Ext2::options()
+        .read_only(true)
+        .open_bytes(&image.synced_bytes())
+        .unwrap();
+    read_only.set_strictness(Strictness::Lenient).unwrap();
+    assert_eq!(read_only.strictness(), Strictness::Lenient);
+    read_only.set_strictness(Strictness::Strict).unwrap();
+    assert_eq!(read_only.strictness(), Strictness::Strict);
+}
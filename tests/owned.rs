@@ -0,0 +1,163 @@
+//! The owned copies `dump` prints: each survives a JSON and a RON round trip
+//! unchanged, and serializes to exactly the fields of the on-disk structure,
+//! so a field added to or dropped from one shows up here.
+
+mod common;
+
+use common::{fixture, pattern};
+use ext2::{GroupDescriptorOwned, InodeOwned, SuperblockOwned};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fmt::Debug;
+
+const SUPERBLOCK_FIELDS: &[&str] = &[
+    "inodes_count",
+    "blocks_count",
+    "r_blocks_count",
+    "free_blocks_count",
+    "free_inodes_count",
+    "first_data_block",
+    "log_block_size",
+    "log_frag_size",
+    "blocks_per_group",
+    "frags_per_group",
+    "inodes_per_group",
+    "mtime",
+    "wtime",
+    "mnt_count",
+    "max_mnt_count",
+    "magic",
+    "state",
+    "errors",
+    "rev_minor",
+    "lastcheck",
+    "checkinterval",
+    "creator_os",
+    "rev_major",
+    "block_uid",
+    "block_gid",
+    "first_inode",
+    "inode_size",
+    "block_group",
+    "features_opt",
+    "features_req",
+    "features_ronly",
+    "fs_id",
+    "volume_name",
+    "last_mnt_path",
+    "compression",
+    "prealloc_blocks_files",
+    "prealloc_blocks_dirs",
+    "reserved_gdt_blocks",
+    "journal_id",
+    "journal_inode",
+    "journal_dev",
+    "journal_orphan_head",
+    "hash_seed",
+    "def_hash_version",
+    "journal_backup_type",
+    "desc_size",
+    "default_mount_opts",
+    "first_meta_bg",
+    "mkfs_time",
+    "journal_blocks",
+    "blocks_count_high",
+    "reserved_blocks_count_high",
+    "free_blocks_count_high",
+    "min_extra_isize",
+    "want_extra_isize",
+    "misc_flags",
+];
+
+const GROUP_FIELDS: &[&str] = &[
+    "block_usage_addr",
+    "inode_usage_addr",
+    "inode_table_block",
+    "free_blocks_count",
+    "free_inodes_count",
+    "dirs_count",
+];
+
+const INODE_FIELDS: &[&str] = &[
+    "type_perm",
+    "uid",
+    "size_low",
+    "atime",
+    "ctime",
+    "mtime",
+    "dtime",
+    "gid",
+    "hard_links",
+    "sectors_count",
+    "flags",
+    "os_specific_1",
+    "direct_pointer",
+    "indirect_pointer",
+    "doubly_indirect",
+    "triply_indirect",
+    "gen_number",
+    "ext_attribute_block",
+    "size_high",
+    "frag_block_addr",
+    "os_specific_2",
+];
+
+// `value` comes back from JSON and from RON, and its JSON keys are `fields`
+fn round_trip<T>(value: &T, fields: &[&str])
+where
+    T: Serialize + DeserializeOwned + PartialEq + Debug,
+{
+    let json = serde_json::to_string(value).unwrap();
+    assert_eq!(serde_json::from_str::<T>(&json).unwrap(), *value);
+    let ron = ron::to_string(value).unwrap();
+    assert_eq!(ron::from_str::<T>(&ron).unwrap(), *value);
+
+    let serde_json::Value::Object(object) = serde_json::from_str(&json).unwrap() else {
+        panic!("not an object: {}", json);
+    };
+    let mut keys: Vec<&str> = object.keys().map(String::as_str).collect();
+    let mut expected = fields.to_vec();
+    keys.sort_unstable();
+    expected.sort_unstable();
+    assert_eq!(keys, expected);
+}
+
+#[test]
+fn superblock_round_trips() {
+    let image = fixture().block_size(1024).build();
+    let owned = SuperblockOwned::from(&image.ext2.superblock);
+    assert_eq!(owned.magic, 0xef53);
+    round_trip(&owned, SUPERBLOCK_FIELDS);
+}
+
+#[test]
+fn group_descriptors_round_trip() {
+    let image = fixture()
+        .block_size(1024)
+        .size(20 << 20)
+        .dir("d", |d| d)
+        .build();
+    assert!(image.ext2.block_groups.len() > 1);
+    for group in &image.ext2.block_groups {
+        round_trip(&GroupDescriptorOwned::from(group), GROUP_FIELDS);
+    }
+}
+
+#[test]
+fn inodes_round_trip() {
+    // a file reaching its doubly indirect block, a symlink and directories
+    let image = fixture()
+        .block_size(1024)
+        .file("big", &pattern(300 << 10))
+        .symlink("link", "big")
+        .dir("d", |d| d)
+        .build();
+    for path in ["/big", "/link", "/d", "/"] {
+        let record = image.ext2.get_inode(image.inode(path)).unwrap();
+        let owned = InodeOwned::from(record);
+        assert_eq!(owned.type_perm, record.type_perm.bits());
+        round_trip(&owned, INODE_FIELDS);
+    }
+    let big = InodeOwned::from(image.ext2.get_inode(image.inode("/big")).unwrap());
+    assert_ne!(big.doubly_indirect, 0);
+}
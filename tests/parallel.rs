@@ -0,0 +1,79 @@
+//! `walk_parallel` against the sequential `walk`: the same paths and inodes,
+//! in whatever order the threads get to them.
+
+#![cfg(feature = "parallel")]
+
+mod common;
+
+use common::{fixture, pattern, ROOT};
+use ext2::{Ext2Error, WalkControl, WalkOptions};
+use std::collections::BTreeSet;
+use std::sync::Mutex;
+
+#[test]
+fn parallel_and_sequential_walks_visit_the_same_paths() {
+    let image = fixture()
+        .dir("a", |d| {
+            d.file("one", &pattern(3000))
+                .dir("b", |d| {
+                    d.file("two", b"2")
+                        .dir("c", |d| d.dir("d", |d| d.file("deepest", b"!")))
+                })
+                .symlink("up", "..")
+        })
+        .dir("empty", |d| d)
+        .dir("wide", |d| {
+            (0..40).fold(d, |d, i| d.file(&format!("f{:02}", i), b"w"))
+        })
+        .file("top", b"top")
+        .build();
+    let ext2 = &image.ext2;
+
+    let mut sequential = BTreeSet::new();
+    ext2.walk(ROOT, &WalkOptions::new(), &mut |entry| {
+        sequential.insert((entry.path().to_string(), entry.inode));
+        WalkControl::Continue
+    })
+    .unwrap();
+    let parallel = Mutex::new(BTreeSet::new());
+    ext2.walk_parallel(ROOT, &|path, inode, _| {
+        // each path once
+        assert!(parallel.lock().unwrap().insert((path.to_string(), inode)));
+        Ok(())
+    })
+    .unwrap();
+    let parallel = parallel.into_inner().unwrap();
+    assert_eq!(parallel, sequential);
+    assert!(parallel.contains(&(
+        String::from("a/b/c/d/deepest"),
+        image.inode("/a/b/c/d/deepest")
+    )));
+    assert_eq!(parallel.len(), 52);
+
+    // and from a directory below the root, relative to it
+    let b = image.inode("/a/b");
+    let below = Mutex::new(BTreeSet::new());
+    ext2.walk_parallel(b, &|path, _, _| {
+        below.lock().unwrap().insert(path.to_string());
+        Ok(())
+    })
+    .unwrap();
+    let expected = ["c", "c/d", "c/d/deepest", "two"].map(String::from);
+    assert_eq!(below.into_inner().unwrap(), BTreeSet::from(expected));
+}
+
+#[test]
+fn the_first_error_ends_a_parallel_walk() {
+    let image = fixture()
+        .dir("a", |d| d.file("x", b"x"))
+        .dir("b", |d| d.file("y", b"y"))
+        .build();
+    let result = image.ext2.walk_parallel(ROOT, &|path, _, _| {
+        if path == "b/y" {
+            Err(Ext2Error::Cancelled)
+        } else {
+            Ok(())
+        }
+    });
+    assert!(matches!(result, Err(Ext2Error::Cancelled)));
+}
@@ -0,0 +1,174 @@
+//! Finding a filesystem behind an MBR or GPT partition table, opening it at
+//! its offset, and writing it back there.
+
+mod common;
+
+use common::{fixture, TempDir, ROOT};
+use ext2::{
+    device_partitions, partitions, Ext2, Ext2Error, FileDevice, OffsetDevice, PartitionType,
+};
+use std::fs;
+use std::io::Cursor;
+
+const START_SECTOR: usize = 2048;
+const LINUX_GUID: [u8; 16] = [
+    0xaf, 0x3d, 0xc6, 0x0f, 0x83, 0x84, 0x72, 0x47, 0x8e, 0x79, 0x3d, 0x69, 0xd8, 0x47, 0x7d, 0xe4,
+];
+
+// a disk with an empty partition table, room for one partition at
+// `START_SECTOR`, and the filesystem in it
+fn disk_with(filesystem: &[u8]) -> Vec<u8> {
+    let mut disk = vec![0; START_SECTOR * 512 + filesystem.len()];
+    disk[START_SECTOR * 512..].copy_from_slice(filesystem);
+    disk[510..512].copy_from_slice(&[0x55, 0xaa]);
+    disk
+}
+
+fn mbr_entry(disk: &mut [u8], index: usize, kind: u8, start: usize, sectors: usize) {
+    let entry = &mut disk[446 + 16 * index..446 + 16 * (index + 1)];
+    entry[4] = kind;
+    entry[8..12].copy_from_slice(&(start as u32).to_le_bytes());
+    entry[12..16].copy_from_slice(&(sectors as u32).to_le_bytes());
+}
+
+fn filesystem() -> Vec<u8> {
+    fixture()
+        .file("hello.txt", b"hello\n")
+        .build()
+        .synced_bytes()
+}
+
+#[test]
+fn bare_filesystem_has_no_partitions() {
+    assert_eq!(partitions(&filesystem()), []);
+}
+
+#[test]
+fn mbr() {
+    let filesystem = filesystem();
+    let sectors = filesystem.len() / 512;
+    let mut disk = disk_with(&filesystem);
+    mbr_entry(&mut disk, 0, 0x0c, 63, 100);
+    mbr_entry(&mut disk, 2, 0x83, START_SECTOR, sectors);
+    // past the end of the disk, so left out
+    mbr_entry(&mut disk, 3, 0x83, START_SECTOR + sectors, 100);
+
+    let found = partitions(&disk);
+    assert_eq!(found.len(), 2);
+    assert_eq!(
+        (found[0].number, found[0].kind, found[0].offset),
+        (1, PartitionType::Mbr(0x0c), 63 * 512)
+    );
+    assert!(!found[0].is_linux());
+    let linux = &found[1];
+    assert_eq!(linux.number, 3);
+    assert!(linux.is_linux());
+    assert_eq!(linux.offset, START_SECTOR as u64 * 512);
+    assert_eq!(linux.slice(&disk), &filesystem[..]);
+}
+
+#[test]
+fn gpt() {
+    let filesystem = filesystem();
+    let sectors = filesystem.len() / 512;
+    let mut disk = disk_with(&filesystem);
+    let disk_sectors = disk.len() / 512;
+    mbr_entry(&mut disk, 0, 0xee, 1, disk_sectors - 1);
+    let header = &mut disk[512..1024];
+    header[..8].copy_from_slice(b"EFI PART");
+    header[72..80].copy_from_slice(&2u64.to_le_bytes());
+    header[80..84].copy_from_slice(&128u32.to_le_bytes());
+    header[84..88].copy_from_slice(&128u32.to_le_bytes());
+    // the second entry, the first left unused
+    let entry = &mut disk[1024 + 128..1024 + 256];
+    entry[..16].copy_from_slice(&LINUX_GUID);
+    entry[32..40].copy_from_slice(&(START_SECTOR as u64).to_le_bytes());
+    entry[40..48].copy_from_slice(&((START_SECTOR + sectors - 1) as u64).to_le_bytes());
+
+    let found = partitions(&disk);
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].number, 2);
+    assert!(found[0].is_linux());
+    assert_eq!(
+        found[0].kind.to_string(),
+        "GPT type 0fc63daf-8483-4772-8e79-3d69d8477de4"
+    );
+    assert_eq!(found[0].slice(&disk), &filesystem[..]);
+}
+
+#[test]
+fn open_by_number() {
+    let filesystem = filesystem();
+    let mut disk = disk_with(&filesystem);
+    mbr_entry(&mut disk, 1, 0x83, START_SECTOR, filesystem.len() / 512);
+
+    let ext2 = Ext2::options().partition(2).open_bytes(&disk).unwrap();
+    let hello = ext2.resolve_path(ROOT, "/hello.txt").unwrap();
+    assert_eq!(ext2.read_file_inode(hello).unwrap(), b"hello\n");
+    assert!(matches!(
+        Ext2::options().partition(1).open_bytes(&disk),
+        Err(Ext2Error::NotFound { .. })
+    ));
+    // the whole disk isn't a filesystem
+    assert!(Ext2::options().open_bytes(&disk).is_err());
+}
+
+#[test]
+fn open_and_sync_at_offset() {
+    let filesystem = filesystem();
+    let mut disk = disk_with(&filesystem);
+    mbr_entry(&mut disk, 0, 0x83, START_SECTOR, filesystem.len() / 512);
+    let partition = partitions(&disk).remove(0);
+
+    let mut ext2 = Ext2::new(partition.slice(&disk).to_vec()).unwrap();
+    let hello = ext2.resolve_path(ROOT, "/hello.txt").unwrap();
+    assert_eq!(ext2.read_file_inode(hello).unwrap(), b"hello\n");
+    ext2.create_dir(ROOT, "new", 0o755).unwrap();
+
+    let mut device = Cursor::new(disk.clone());
+    ext2.sync(&mut OffsetDevice::new(&mut device, partition.offset))
+        .unwrap();
+    let synced = device.into_inner();
+    // the table in front is untouched, and the partition holds the change
+    assert_eq!(synced[..START_SECTOR * 512], disk[..START_SECTOR * 512]);
+    let reopened = Ext2::new(partition.slice(&synced).to_vec()).unwrap();
+    assert!(reopened.resolve_path(ROOT, "/new").is_ok());
+    assert_eq!(reopened.check(), []);
+}
+
+#[test]
+fn open_partition_of_a_file() {
+    let filesystem = filesystem();
+    let mut disk = disk_with(&filesystem);
+    mbr_entry(&mut disk, 0, 0x83, START_SECTOR, filesystem.len() / 512);
+    let dir = TempDir::new("partition");
+    let path = dir.path().join("disk.img");
+    fs::write(&path, &disk).unwrap();
+
+    let device = FileDevice::open(&path).unwrap();
+    assert_eq!(device_partitions(&device).unwrap(), partitions(&disk));
+    let ext2 = Ext2::options().partition(1).open_path(&path).unwrap();
+    let hello = ext2.resolve_path(ROOT, "/hello.txt").unwrap();
+    assert_eq!(ext2.read_file_inode(hello).unwrap(), b"hello\n");
+    assert_eq!(*ext2.device_bytes().unwrap(), filesystem[..]);
+}
+
+#[test]
+fn open_backup_in_partition() {
+    let filesystem = fixture()
+        .block_size(1024)
+        .size(16 << 20)
+        .build()
+        .synced_bytes();
+    let mut disk = disk_with(&filesystem);
+    mbr_entry(&mut disk, 0, 0x83, START_SECTOR, filesystem.len() / 512);
+    let start = START_SECTOR * 512;
+    let (_, offset) = ext2::find_backup_superblock(&disk[start..]).unwrap();
+    disk[start + 1024..start + 2048].fill(0);
+
+    let ext2 = Ext2::options()
+        .partition(1)
+        .open_backup(disk, offset)
+        .unwrap();
+    assert_eq!(ext2.check(), []);
+}
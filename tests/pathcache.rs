@@ -0,0 +1,73 @@
+//! The path cache: a deep path resolved again searches no directories, and
+//! once anything on the filesystem changes, one along the way included, the
+//! path is looked up again a directory at a time.
+
+mod common;
+
+use common::{fixture, Image, ROOT};
+use ext2::{Ext2, Ext2Error, PathCache};
+
+const PATH: &str = "/a/b/c/d/e/f";
+
+// resolve `path` through `cache`, and how many directories that searched
+fn resolve(cache: &mut PathCache, ext2: &Ext2, path: &str) -> (usize, u64) {
+    let before = ext2.lookups();
+    let inode = cache.resolve(ext2, ROOT, path).unwrap();
+    (inode, ext2.lookups() - before)
+}
+
+fn image() -> Image {
+    fixture()
+        .dir("a", |a| {
+            a.dir("b", |b| {
+                b.dir("c", |c| {
+                    c.dir("d", |d| d.dir("e", |e| e.file("f", b"deep")))
+                })
+            })
+        })
+        .build()
+}
+
+#[test]
+fn a_resolved_path_is_not_searched_again() {
+    let image = image();
+    let ext2 = &image.ext2;
+    let f = image.inode(PATH);
+    let mut cache = PathCache::new();
+
+    assert_eq!(resolve(&mut cache, ext2, PATH), (f, 6));
+    assert_eq!(cache.len(), 6);
+    assert_eq!(resolve(&mut cache, ext2, PATH), (f, 0));
+    // and a prefix of it, or a path sharing one, only searches what's new
+    assert_eq!(resolve(&mut cache, ext2, "/a/b/c").1, 0);
+    assert_eq!(
+        resolve(&mut cache, ext2, "/a/b/c/d/e/.."),
+        (image.inode("/a/b/c/d"), 1)
+    );
+}
+
+#[test]
+fn a_modified_ancestor_is_searched_again() {
+    let mut image = image();
+    let f = image.inode(PATH);
+    let mut cache = PathCache::new();
+    assert_eq!(resolve(&mut cache, &image.ext2, PATH), (f, 6));
+
+    // a new entry in /a/b
+    let b = image.inode("/a/b");
+    image.ext2.create_file(b, "new", 0o644).unwrap();
+    assert_eq!(resolve(&mut cache, &image.ext2, PATH), (f, 6));
+    assert_eq!(resolve(&mut cache, &image.ext2, PATH), (f, 0));
+
+    // /a/b/c renamed away: the old path is gone, not served from the cache
+    image.ext2.rename(b, "c", b, "moved").unwrap();
+    let before = image.ext2.lookups();
+    let err = cache.resolve(&image.ext2, ROOT, PATH).unwrap_err();
+    assert!(
+        matches!(err.root_cause(), Ext2Error::NotFound { .. }),
+        "{}",
+        err
+    );
+    assert_eq!(image.ext2.lookups() - before, 3);
+    assert_eq!(resolve(&mut cache, &image.ext2, "/a/b/moved/d/e/f"), (f, 4));
+}
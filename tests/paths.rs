@@ -0,0 +1,98 @@
+//! Finding a directory's path from its inode, going up through `..`, and
+//! paths that differ from the names in them only by case.
+
+mod common;
+
+use common::{fixture, ROOT};
+use ext2::{Ext2Error, Ext2Options};
+
+#[test]
+fn dir_path() {
+    let mut image = fixture()
+        .dir("a", |d| d.dir("b", |d| d.dir("c", |d| d.file("f", b"f"))))
+        .dir("other", |d| d)
+        .build();
+    assert_eq!(image.ext2.dir_path(ROOT).unwrap(), "/");
+    let c = image.inode("/a/b/c");
+    assert_eq!(image.ext2.dir_path(c).unwrap(), "/a/b/c");
+
+    // the path as it is now, not as it was
+    let a = image.inode("/a");
+    let other = image.inode("/other");
+    image.ext2.rename(ROOT, "a", other, "moved").unwrap();
+    assert_eq!(image.ext2.dir_path(a).unwrap(), "/other/moved");
+    assert_eq!(image.ext2.dir_path(c).unwrap(), "/other/moved/b/c");
+}
+
+#[test]
+fn case_insensitive_lookup() {
+    let mut image = fixture()
+        .dir("Docs", |d| {
+            d.file("README.txt", b"readme")
+                .file("a.TXT", b"1")
+                .file("A.txt", b"2")
+        })
+        .build();
+    let readme = image.inode("/Docs/README.txt");
+    let upper_a = image.inode("/Docs/A.txt");
+    let ext2 = &mut image.ext2;
+    assert!(matches!(
+        ext2.resolve_path(ROOT, "/docs/readme.txt"),
+        Err(Ext2Error::NotFound { .. })
+    ));
+
+    ext2.set_case_insensitive_lookup(true);
+    assert_eq!(ext2.resolve_path(ROOT, "/DOCS/readme.TXT").unwrap(), readme);
+    // an exact match wins
+    assert_eq!(ext2.resolve_path(ROOT, "/docs/A.txt").unwrap(), upper_a);
+    // otherwise two that match but for case are one too many
+    assert!(matches!(
+        ext2.resolve_path(ROOT, "/docs/a.txt"),
+        Err(Ext2Error::AmbiguousName { name }) if name == "a.txt"
+    ));
+    assert_eq!(ext2.lookup(ROOT, "nothing").unwrap(), None);
+
+    // and the same from the options
+    let reopened = Ext2Options::new()
+        .read_only(true)
+        .case_insensitive_lookup(true)
+        .open(image.synced_bytes())
+        .unwrap();
+    assert!(reopened.case_insensitive_lookup());
+    assert_eq!(
+        reopened.resolve_path(ROOT, "/docs/readme.txt").unwrap(),
+        readme
+    );
+}
+
+#[test]
+fn case_insensitive_changes() {
+    let mut image = fixture()
+        .file("readme.txt", b"old")
+        .file("other", b"new")
+        .file("gone.txt", b"x")
+        .dir("Empty", |d| d)
+        .build();
+    let other = image.inode("/other");
+    let ext2 = &mut image.ext2;
+    ext2.set_case_insensitive_lookup(true);
+
+    // replaces readme.txt, keeping the name as it is on disk
+    ext2.rename(ROOT, "other", ROOT, "README.TXT").unwrap();
+    assert_eq!(ext2.lookup(ROOT, "readme.txt").unwrap(), Some(other));
+    assert_eq!(ext2.lookup(ROOT, "other").unwrap(), None);
+    assert_eq!(ext2.read_file_inode(other).unwrap(), b"new");
+
+    ext2.unlink(ROOT, "GONE.TXT").unwrap();
+    assert_eq!(ext2.lookup(ROOT, "gone.txt").unwrap(), None);
+    ext2.remove_dir(ROOT, "empty").unwrap();
+    assert_eq!(ext2.lookup(ROOT, "Empty").unwrap(), None);
+    assert_eq!(ext2.check(), []);
+
+    // without it, only the exact names
+    ext2.set_case_insensitive_lookup(false);
+    assert!(matches!(
+        ext2.unlink(ROOT, "README.TXT"),
+        Err(Ext2Error::NotFound { .. })
+    ));
+}
@@ -0,0 +1,84 @@
+//! Where new inodes and blocks go: a file stays in its directory's group,
+//! inode and data alike, while new top-level directories are spread over
+//! the groups with the most room.
+
+mod common;
+
+use common::{fixture, pattern, Image, ROOT};
+use ext2::Ext2;
+use std::collections::BTreeSet;
+
+// 32 MiB at 1 KiB blocks: four groups of 8192 blocks, near enough
+fn image() -> Image {
+    fixture().block_size(1024).size(32 << 20).build()
+}
+
+fn inode_group(ext2: &Ext2, inode: usize) -> usize {
+    (inode - 1) / ext2.superblock.inodes_per_group as usize
+}
+
+fn block_group(ext2: &Ext2, block: usize) -> usize {
+    (block - ext2.superblock.first_data_block as usize) / ext2.superblock.blocks_per_group as usize
+}
+
+#[test]
+fn a_file_goes_in_its_directorys_group() {
+    let mut image = image();
+    let ext2 = &mut image.ext2;
+    assert_eq!(ext2.block_groups.len(), 4);
+    let dir = ext2.create_dir(ROOT, "d", 0o755).unwrap();
+    let group = inode_group(ext2, dir);
+    // the root's group isn't where a new top-level directory goes
+    assert_ne!(group, 0);
+    for &block in &ext2.owned_blocks(dir).unwrap() {
+        assert_eq!(block_group(ext2, block), group, "block {}", block);
+    }
+
+    // far enough into the doubly indirect blocks for three blocks of
+    // pointers, which come from the group too
+    let file = ext2.create_file(dir, "f", 0o644).unwrap();
+    ext2.write_file(file, 0, &pattern(300 << 10)).unwrap();
+    assert_eq!(inode_group(ext2, file), group);
+    let blocks = ext2.owned_blocks(file).unwrap();
+    assert_eq!(blocks.len(), 300 + 3);
+    for &block in &blocks {
+        assert_eq!(block_group(ext2, block), group, "block {}", block);
+    }
+    // and in one run, after the directory's block
+    let data: Vec<usize> = ext2
+        .file_blocks(file)
+        .unwrap()
+        .map(|b| b.unwrap())
+        .collect();
+    assert!(data.windows(2).all(|w| w[1] > w[0]), "{:?}", data);
+    assert_eq!(ext2.check(), []);
+}
+
+#[test]
+fn new_top_level_directories_spread_across_groups() {
+    let mut image = image();
+    let ext2 = &mut image.ext2;
+    let mut groups = Vec::new();
+    for name in ["a", "b", "c"] {
+        let dir = ext2.create_dir(ROOT, name, 0o755).unwrap();
+        let file = ext2.create_file(dir, "f", 0o644).unwrap();
+        ext2.write_file(file, 0, &pattern(1 << 20)).unwrap();
+        groups.push(inode_group(ext2, dir));
+    }
+    // each one goes where the most blocks are free, and the megabyte in it
+    // leaves another group the roomiest for the next; never group 0, whose
+    // reserved inodes leave it below the average free
+    assert_eq!(
+        groups.iter().collect::<BTreeSet<_>>().len(),
+        3,
+        "{:?}",
+        groups
+    );
+    assert!(!groups.contains(&0), "{:?}", groups);
+    // while another file in one of them still goes with it
+    let a = image.inode("/a");
+    let ext2 = &mut image.ext2;
+    let file = ext2.create_file(a, "g", 0o644).unwrap();
+    assert_eq!(inode_group(ext2, file), groups[0]);
+    assert_eq!(ext2.check(), []);
+}
@@ -0,0 +1,73 @@
+//! Preallocated blocks: a file written into them afterwards is one extent,
+//! however scattered the free space was.
+
+mod common;
+
+use common::{fixture, pattern, Image, ROOT};
+
+const BLOCK: usize = 1024;
+// past the indirect block and into the doubly indirect one
+const BLOCKS: usize = 300;
+
+// an image whose free space near the start is in gaps of two blocks, left
+// by removing every other small file
+fn scattered() -> Image {
+    let mut fixture = fixture().block_size(BLOCK);
+    for i in 0..40 {
+        fixture = fixture.file_with_size(&format!("s{:02}", i), 2 * BLOCK);
+    }
+    let mut image = fixture.build();
+    for i in (0..40).step_by(2) {
+        image.ext2.unlink(ROOT, &format!("s{:02}", i)).unwrap();
+    }
+    image
+}
+
+#[test]
+fn written_afterwards_in_one_extent() {
+    let data = pattern(BLOCKS * BLOCK);
+
+    // written as it comes, the file fills the gaps
+    let mut image = scattered();
+    let ext2 = &mut image.ext2;
+    let plain = ext2.create_file(ROOT, "plain", 0o644).unwrap();
+    ext2.write_file(plain, 0, &data).unwrap();
+    assert!(ext2.fragmentation(plain).unwrap().extents.len() > 1);
+
+    let mut image = scattered();
+    let ext2 = &mut image.ext2;
+    let log = ext2.create_file(ROOT, "log", 0o644).unwrap();
+    let free = ext2.superblock.free_blocks_count;
+    let allocated = ext2.preallocate(log, data.len() as u64, true).unwrap();
+    // with the indirect block, the doubly indirect one and one under it
+    assert_eq!(allocated, BLOCKS + 3);
+    assert_eq!(ext2.superblock.free_blocks_count, free - allocated as u32);
+    assert_eq!(ext2.get_inode(log).unwrap().size(), 0);
+    assert_eq!(ext2.check(), []);
+
+    ext2.write_file(log, 0, &data).unwrap();
+    let report = ext2.fragmentation(log).unwrap();
+    assert_eq!(report.blocks, BLOCKS);
+    assert_eq!(report.extents.len(), 1);
+    // nothing more was allocated for the write
+    assert_eq!(ext2.superblock.free_blocks_count, free - allocated as u32);
+    assert_eq!(ext2.read_file_inode(log).unwrap(), data);
+    assert_eq!(ext2.check(), []);
+}
+
+#[test]
+fn growing_the_size() {
+    let mut image = fixture().block_size(BLOCK).file("f", b"start").build();
+    let f = image.inode("/f");
+    let ext2 = &mut image.ext2;
+    // the first block is there already
+    assert_eq!(ext2.preallocate(f, 3 * BLOCK as u64, false).unwrap(), 2);
+    let mut expected = vec![0; 3 * BLOCK];
+    expected[..5].copy_from_slice(b"start");
+    assert_eq!(ext2.read_file_inode(f).unwrap(), expected);
+    // nothing left to allocate, and never shrinks
+    assert_eq!(ext2.preallocate(f, 10, false).unwrap(), 0);
+    assert_eq!(ext2.get_inode(f).unwrap().size(), 3 * BLOCK as u64);
+    assert_eq!(ext2.fragmentation(f).unwrap().extents.len(), 1);
+    assert_eq!(ext2.check(), []);
+}
@@ -0,0 +1,242 @@
+//! Reporting on and cancelling the long operations through `Progress`.
+
+mod common;
+
+use common::{fixture, pattern, TempDir, ROOT};
+use ext2::{Ext2Error, Progress, WalkControl, WalkOptions};
+use std::cell::{Cell, RefCell};
+use std::fs;
+use std::sync::atomic::AtomicBool;
+
+/// Records what it's told, and cancels once it's been told about
+/// `cancel_after` items.
+#[derive(Default)]
+struct Recorder {
+    items: RefCell<Vec<String>>,
+    bytes: Cell<(u64, Option<u64>)>,
+    cancel_after: Option<usize>,
+}
+
+impl Recorder {
+    fn cancelling_after(items: usize) -> Recorder {
+        Recorder {
+            cancel_after: Some(items),
+            ..Recorder::default()
+        }
+    }
+}
+
+impl Progress for Recorder {
+    fn on_item(&self, path: &str) {
+        self.items.borrow_mut().push(path.to_string());
+    }
+
+    fn on_bytes(&self, done: u64, total: Option<u64>) {
+        assert!(done >= self.bytes.get().0, "bytes went backwards");
+        self.bytes.set((done, total));
+    }
+
+    fn should_cancel(&self) -> bool {
+        self.cancel_after
+            .map_or(false, |after| self.items.borrow().len() >= after)
+    }
+}
+
+#[test]
+fn tar_reports_every_path_and_byte() {
+    let image = fixture()
+        .file_with_size("a", 3000)
+        .dir("dir", |d| {
+            d.file_with_size("b", 5000).symlink("link", "../a")
+        })
+        .build();
+    let progress = Recorder::default();
+    let mut archive = Vec::new();
+    let written = image
+        .ext2
+        .export_tar_with_progress(ROOT, &mut archive, &progress)
+        .unwrap();
+    assert_eq!(progress.items.borrow().len(), written);
+    assert!(progress.items.borrow().contains(&"dir/b".to_string()));
+    assert_eq!(progress.bytes.get(), (8000, None));
+
+    let mut copy = fixture().build();
+    let progress = Recorder::default();
+    let summary = copy
+        .ext2
+        .import_tar_with_progress(ROOT, &archive[..], &progress)
+        .unwrap();
+    assert_eq!(progress.items.borrow().len(), written);
+    assert_eq!(progress.bytes.get(), (summary.bytes, None));
+    assert_eq!(summary.bytes, 8000);
+    assert!(copy.ext2.check().is_empty());
+}
+
+#[test]
+fn cancelled_export_stops_between_entries() {
+    let image = fixture()
+        .file_with_size("a", 3000)
+        .file_with_size("b", 3000)
+        .file_with_size("c", 3000)
+        .build();
+    let mut whole = Vec::new();
+    image.ext2.export_tar(ROOT, &mut whole).unwrap();
+    let progress = Recorder::cancelling_after(2);
+    let mut archive = Vec::new();
+    let result = image
+        .ext2
+        .export_tar_with_progress(ROOT, &mut archive, &progress);
+    assert!(matches!(result, Err(Ext2Error::Cancelled)));
+    assert_eq!(progress.items.borrow().len(), 2);
+    // the first two entries whole, and nothing after them
+    assert!(archive.len() < whole.len());
+    assert!(whole.starts_with(&archive));
+    assert_ne!(archive[archive.len() - 512..], [0; 512]);
+}
+
+#[test]
+fn cancelled_import_rolls_back() {
+    let source = fixture()
+        .dir("dir", |d| {
+            d.file_with_size("a", 3000)
+                .file_with_size("b", 3000)
+                .file_with_size("c", 3000)
+        })
+        .build();
+    let mut archive = Vec::new();
+    source.ext2.export_tar(ROOT, &mut archive).unwrap();
+
+    let mut image = fixture().build();
+    let before = image.synced_bytes();
+    let progress = Recorder::cancelling_after(3);
+    let result = image
+        .ext2
+        .import_tar_with_progress(ROOT, &archive[..], &progress);
+    assert!(matches!(result, Err(Ext2Error::Cancelled)));
+    assert!(image.ext2.resolve_path(ROOT, "/dir").is_err());
+    assert!(image.ext2.check().is_empty());
+    assert_eq!(image.synced_bytes(), before);
+}
+
+#[test]
+fn cancelled_populate_rolls_back() {
+    let host = TempDir::new("progress");
+    fs::create_dir(host.path().join("dir")).unwrap();
+    for name in ["a", "b", "c"] {
+        fs::write(host.path().join("dir").join(name), pattern(3000)).unwrap();
+    }
+
+    let mut image = fixture().build();
+    let progress = Recorder::default();
+    let summary = image
+        .ext2
+        .populate_from_host_with_progress(host.path(), "/", &progress)
+        .unwrap();
+    assert_eq!(progress.items.borrow().len(), 4);
+    assert_eq!(progress.bytes.get(), (summary.bytes, None));
+
+    let mut image = fixture().build();
+    let before = image.synced_bytes();
+    let progress = Recorder::cancelling_after(3);
+    let result = image
+        .ext2
+        .populate_from_host_with_progress(host.path(), "/", &progress);
+    assert!(matches!(result, Err(Ext2Error::Cancelled)));
+    assert!(image.ext2.resolve_path(ROOT, "/dir").is_err());
+    assert_eq!(image.synced_bytes(), before);
+}
+
+#[test]
+fn dedup_hashes_towards_a_known_total() {
+    let image = fixture()
+        .file_with_size("a", 3000)
+        .file_with_size("b", 3000)
+        .file_with_size("lone", 5000)
+        .build();
+    let progress = Recorder::default();
+    let groups = image
+        .ext2
+        .find_duplicates_with_progress(ROOT, &progress)
+        .unwrap();
+    assert_eq!(groups.len(), 1);
+    // the file of a size no other has is never hashed
+    assert_eq!(*progress.items.borrow(), ["a", "b"]);
+    assert_eq!(progress.bytes.get(), (6000, Some(6000)));
+
+    let progress = Recorder::cancelling_after(1);
+    let result = image.ext2.find_duplicates_with_progress(ROOT, &progress);
+    assert!(matches!(result, Err(Ext2Error::Cancelled)));
+}
+
+#[test]
+fn check_visits_every_directory() {
+    let image = fixture()
+        .dir("x", |d| d.dir("y", |d| d.file("f", b"f")))
+        .build();
+    let progress = Recorder::default();
+    assert_eq!(
+        image.ext2.check_with_progress(&progress).unwrap(),
+        image.ext2.check()
+    );
+    let items = progress.items.borrow();
+    for dir in ["/", "/x", "/x/y"] {
+        assert!(items.contains(&dir.to_string()), "{} not visited", dir);
+    }
+
+    let progress = Recorder::cancelling_after(1);
+    let result = image.ext2.check_with_progress(&progress);
+    assert!(matches!(result, Err(Ext2Error::Cancelled)));
+}
+
+#[test]
+fn set_flag_stops_a_walk_before_it_starts() {
+    static SET: AtomicBool = AtomicBool::new(true);
+    static CLEAR: AtomicBool = AtomicBool::new(false);
+    let image = fixture()
+        .dir("x", |d| d.file("f", b"f").file("g", b"g"))
+        .build();
+    let mut visited = 0;
+    let result = image
+        .ext2
+        .walk(ROOT, &WalkOptions::new().cancel_on(&SET), &mut |_| {
+            visited += 1;
+            WalkControl::Continue
+        });
+    assert!(matches!(result, Err(Ext2Error::Cancelled)));
+    assert_eq!(visited, 0);
+    let result = image
+        .ext2
+        .biggest_files(ROOT, 10, &WalkOptions::new().cancel_on(&SET));
+    assert!(matches!(result, Err(Ext2Error::Cancelled)));
+
+    image
+        .ext2
+        .walk(ROOT, &WalkOptions::new().cancel_on(&CLEAR), &mut |_| {
+            visited += 1;
+            WalkControl::Continue
+        })
+        .unwrap();
+    // lost+found, x, and x's two files
+    assert_eq!(visited, 4);
+}
+
+#[test]
+fn set_flag_stops_streaming_a_file() {
+    let image = fixture().file_with_size("f", 10000).build();
+    let f = image.inode("/f");
+    let mut out = Vec::new();
+    let result = image
+        .ext2
+        .copy_file_to_with_progress(f, &mut out, &AtomicBool::new(true));
+    assert!(matches!(result, Err(Ext2Error::Cancelled)));
+    assert!(out.is_empty());
+
+    let progress = Recorder::default();
+    let copied = image
+        .ext2
+        .copy_file_to_with_progress(f, &mut out, &progress)
+        .unwrap();
+    assert_eq!(copied, 10000);
+    assert_eq!(out, pattern(10000));
+    assert_eq!(progress.bytes.get(), (10000, Some(10000)));
+}
@@ -0,0 +1,91 @@
+//! Punching holes: the range reads as zeros, its blocks are free again, and
+//! the filesystem stays consistent.
+
+mod common;
+
+use common::{fixture, pattern, ROOT};
+
+const BLOCK: usize = 1024;
+// 12 direct blocks, 256 under the indirect block, 300 under the doubly
+// indirect one
+const BLOCKS: usize = 12 + 256 + 300;
+
+fn sectors(ext2: &ext2::Ext2, inode: usize) -> u32 {
+    ext2.get_inode(inode).unwrap().sectors_count
+}
+
+#[test]
+fn across_the_indirect_blocks() {
+    let mut image = fixture()
+        .block_size(BLOCK)
+        .file_with_size("big", BLOCKS * BLOCK)
+        .build();
+    let big = image.inode("/big");
+    let ext2 = &mut image.ext2;
+    let free = ext2.superblock.free_blocks_count;
+    let before = sectors(ext2, big);
+
+    // from part way through block 5 to part way through block 300: blocks
+    // 6 to 299 whole, the indirect block's 256 among them
+    let (offset, end) = (5 * BLOCK + 100, 300 * BLOCK + 7);
+    let freed = ext2
+        .punch_hole(big, offset as u64, (end - offset) as u64)
+        .unwrap();
+    assert_eq!(freed, 294 + 1);
+    assert_eq!(ext2.superblock.free_blocks_count, free + freed as u32);
+    assert_eq!(sectors(ext2, big), before - freed as u32 * 2);
+    assert!(ext2.block_map(big).unwrap().indirect.is_none());
+
+    let mut expected = pattern(BLOCKS * BLOCK);
+    expected[offset..end].fill(0);
+    assert_eq!(ext2.get_inode(big).unwrap().size(), (BLOCKS * BLOCK) as u64);
+    assert!(ext2.read_file_inode(big).unwrap() == expected);
+    assert_eq!(ext2.check(), []);
+
+    // the rest of the doubly indirect part, from the start of the block the
+    // last hole ended part way through, so it goes too
+    let start = 300 * BLOCK as u64;
+    ext2.punch_hole(big, start, (BLOCKS * BLOCK) as u64 - start)
+        .unwrap();
+    assert!(ext2.block_map(big).unwrap().doubly_indirect.is_none());
+    assert_eq!(ext2.check(), []);
+}
+
+#[test]
+fn edges_and_the_end() {
+    let size = 3 * BLOCK + 10;
+    let mut image = fixture()
+        .block_size(BLOCK)
+        .file_with_size("f", size)
+        .build();
+    let f = image.inode("/f");
+    let ext2 = &mut image.ext2;
+    let mut expected = pattern(size);
+
+    // inside one block, so nothing to free
+    assert_eq!(ext2.punch_hole(f, 10, 20).unwrap(), 0);
+    expected[10..30].fill(0);
+    assert_eq!(ext2.read_file_inode(f).unwrap(), expected);
+
+    // to past the end: the last block, which the file only partly fills,
+    // goes whole
+    assert_eq!(ext2.punch_hole(f, 3 * BLOCK as u64, 5000).unwrap(), 1);
+    expected[3 * BLOCK..].fill(0);
+    assert_eq!(ext2.read_file_inode(f).unwrap(), expected);
+    assert_eq!(ext2.get_inode(f).unwrap().size(), size as u64);
+    assert_eq!(ext2.check(), []);
+
+    // past the end, nothing
+    assert_eq!(ext2.punch_hole(f, size as u64, 100).unwrap(), 0);
+}
+
+#[test]
+fn only_regular_files() {
+    let mut image = fixture().file("f", b"data").build();
+    let ext2 = &mut image.ext2;
+    assert!(ext2.punch_hole(ROOT, 0, 1).is_err());
+    let f = ext2.resolve_path(ROOT, "/f").unwrap();
+    ext2.set_inode_flags(f, ext2::structs::InodeFlags::APPEND)
+        .unwrap();
+    assert!(ext2.punch_hole(f, 0, 1).is_err());
+}
@@ -0,0 +1,134 @@
+//! Reading ahead: sequential reads of a device that isn't in memory fetch
+//! the blocks that follow on the device in one read, and other reads don't.
+
+mod common;
+
+use common::{fixture, pattern, CountingDevice, Reads, ROOT};
+use ext2::{Ext2, Ext2Options};
+use std::io::SeekFrom;
+
+const SIZE: usize = 256 << 10;
+
+// a filesystem with one big file, "/big", on a device that counts reads
+fn open(readahead: usize) -> (Ext2, Reads) {
+    let bytes = fixture()
+        .block_size(1024)
+        .file_with_size("big", SIZE)
+        .build()
+        .synced_bytes();
+    let (device, reads) = CountingDevice::new(bytes);
+    let ext2 = Ext2Options::new()
+        .read_only(true)
+        .readahead_blocks(readahead)
+        .open_device(device)
+        .unwrap();
+    (ext2, reads)
+}
+
+// how many reads `cat` takes once the inode has been looked up
+fn cat_reads(readahead: usize) -> usize {
+    let (ext2, reads) = open(readahead);
+    let big = ext2.resolve_path(ROOT, "/big").unwrap();
+    ext2.get_inode(big).unwrap();
+    reads.clear();
+    let mut out = Vec::new();
+    ext2.copy_file_to(big, &mut out).unwrap();
+    assert_eq!(out, pattern(SIZE));
+    reads.count()
+}
+
+#[test]
+fn sequential_cat_reads_less_often() {
+    let blocks = SIZE / 1024;
+    // one read a block, and one for each indirect block
+    let one_by_one = cat_reads(0);
+    assert!(one_by_one > blocks);
+    let ahead = cat_reads(16);
+    assert!(
+        ahead * 8 < one_by_one,
+        "{} reads against {}",
+        ahead,
+        one_by_one
+    );
+}
+
+#[test]
+fn runs_stop_at_gaps() {
+    let (ext2, reads) = open(16);
+    let big = ext2.resolve_path(ROOT, "/big").unwrap();
+    let record = ext2.get_inode(big).unwrap();
+    reads.clear();
+    let mut out = Vec::new();
+    ext2.copy_file_to(big, &mut out).unwrap();
+
+    // no read takes in a block that isn't the file's, e.g. the indirect
+    // block between its 12th and 13th, and none goes past 16 blocks
+    let data: Vec<u64> = ext2
+        .file_blocks(big)
+        .unwrap()
+        .map(|block| block.unwrap() as u64)
+        .collect();
+    let indirect = record.indirect_pointer as u64;
+    for (start, len) in reads.all() {
+        assert!(len <= 16 * 1024);
+        let first = start / 1024;
+        let last = (start + len as u64) / 1024;
+        if (first..last).any(|block| block == indirect) {
+            assert_eq!(len, 1024);
+            continue;
+        }
+        assert!((first..last).all(|block| data.contains(&block)));
+    }
+    // and every block is read once
+    assert!(reads.per_block(1024).values().all(|&count| count == 1));
+}
+
+#[test]
+fn reader_reads_ahead_only_sequentially() {
+    let (mut ext2, reads) = open(16);
+    let big = ext2.resolve_path(ROOT, "/big").unwrap();
+    let mut reader = ext2.open_file(big).unwrap();
+    // the indirect block the jump below needs
+    ext2.file_blocks(big).unwrap().nth(100).unwrap().unwrap();
+    let mut buf = [0; 100];
+
+    // a jump somewhere else reads just the block it lands in
+    reader
+        .seek(&ext2, SeekFrom::Start(100 * 1024 + 10))
+        .unwrap();
+    reads.clear();
+    reader.read(&ext2, &mut buf).unwrap();
+    assert_eq!(buf[..], pattern(SIZE)[100 * 1024 + 10..100 * 1024 + 110]);
+    assert_eq!(reads.all().iter().map(|&(_, len)| len).sum::<usize>(), 1024);
+
+    // and reading on from there brings in the blocks after it
+    reads.clear();
+    let mut block = [0; 1024];
+    reader.read(&ext2, &mut block).unwrap();
+    assert_eq!(reads.count(), 1);
+    assert!(reads.all()[0].1 > 1024);
+    reads.clear();
+    for _ in 0..8 {
+        reader.read(&ext2, &mut block).unwrap();
+    }
+    assert_eq!(reads.count(), 0);
+    ext2.close_file(reader).unwrap();
+}
+
+#[test]
+fn in_memory_devices_are_not_read_ahead() {
+    let bytes = fixture().file_with_size("big", SIZE).build().synced_bytes();
+    let (device, reads) = CountingDevice::in_memory(bytes);
+    let ext2 = Ext2Options::new()
+        .read_only(true)
+        .readahead_blocks(16)
+        .open_device(device)
+        .unwrap();
+    let big = ext2.resolve_path(ROOT, "/big").unwrap();
+    reads.clear();
+    let mut out = Vec::new();
+    ext2.copy_file_to(big, &mut out).unwrap();
+    assert_eq!(out, pattern(SIZE));
+    // every block is taken straight from the device's bytes
+    assert_eq!(reads.count(), 0);
+}
@@ -0,0 +1,94 @@
+//! `--read-only`: every command that would modify the filesystem is refused
+//! with the same message, and the image file is left exactly as it was.
+
+mod common;
+
+use common::{fixture, TempDir, ROOT};
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+// each command that modifies the filesystem, with arguments it would
+// succeed with on a read-write open
+const MUTATING: &[&[&str]] = &[
+    &["mkdir", "new"],
+    &["mknod", "fifo", "p"],
+    &["touch", "docs/a.txt"],
+    &["touch", "new"],
+    &["punch", "big", "0", "4096"],
+    &["fallocate", "docs/a.txt", "8192"],
+    &["defrag", "big"],
+    &["compactdir", "docs"],
+    &["fsck", "--repair"],
+    &["sync"],
+    &["chattr", "+i", "docs/a.txt"],
+    &["undelete", "12", "back"],
+    &["orphans", "relink", "12"],
+    &["rm", "docs/a.txt"],
+    &["rm", "-r", "docs"],
+    &["rmdir", "empty"],
+    &["mv", "docs/a.txt", "moved"],
+    &["resize", "2048"],
+    &["label", "new"],
+    &["uuid", "--regenerate"],
+    &["populate", "{host}", "/"],
+    &["untar", "{tar}", "/"],
+    &["dedup", "--link", "/"],
+];
+
+fn run(image: &Path, command: &[&str]) -> (Option<i32>, String) {
+    let output = Command::new(env!("CARGO_BIN_EXE_ext2"))
+        .arg("--read-only")
+        .arg(image)
+        .args(command)
+        .output()
+        .unwrap();
+    (
+        output.status.code(),
+        String::from_utf8_lossy(&output.stderr).into_owned(),
+    )
+}
+
+#[test]
+fn mutating_commands_leave_the_image_identical() {
+    let mut image = fixture()
+        .block_size(1024)
+        .dir("docs", |d| {
+            d.file("a.txt", b"hello").file("b.txt", b"hello")
+        })
+        .dir("empty", |d| d)
+        .file_with_size("big", 64 << 10)
+        .build();
+    let dir = TempDir::new("readonly");
+    let host = dir.path().join("host");
+    fs::create_dir(&host).unwrap();
+    fs::write(host.join("f"), b"from the host").unwrap();
+    let tar = dir.path().join("a.tar");
+    image
+        .ext2
+        .export_tar(ROOT, fs::File::create(&tar).unwrap())
+        .unwrap();
+    let path = dir.path().join("image.ext2");
+    let bytes = image.synced_bytes();
+    fs::write(&path, &bytes).unwrap();
+
+    for command in MUTATING {
+        let command: Vec<&str> = command
+            .iter()
+            .map(|&arg| match arg {
+                "{host}" => host.to_str().unwrap(),
+                "{tar}" => tar.to_str().unwrap(),
+                arg => arg,
+            })
+            .collect();
+        let (status, stderr) = run(&path, &command);
+        assert_eq!(status, Some(1), "{:?}: {}", command, stderr);
+        assert_eq!(
+            stderr,
+            format!("{}: the filesystem is open read-only\n", command[0]),
+            "{:?}",
+            command
+        );
+        assert!(fs::read(&path).unwrap() == bytes, "{:?}", command);
+    }
+}
@@ -0,0 +1,98 @@
+//! `remove_tree`: a whole tree goes, except what's also linked from outside
+//! it, which keeps its data and loses only the link.
+
+mod common;
+
+use common::{e2fsprogs, fixture, pattern, ROOT};
+use ext2::{WalkControl, WalkOptions};
+
+const SHARED: &[u8] = b"linked from outside the tree too";
+
+#[test]
+fn a_hard_link_outside_the_tree_survives() {
+    let mut image = fixture()
+        .block_size(1024)
+        .dir("tree", |d| {
+            d.file("top", b"top")
+                .symlink("link", "../outside")
+                .dir("a", |d| {
+                    d.file("f", &pattern(3000)).dir("b", |d| {
+                        d.file("shared", SHARED)
+                            .file_with_size("big", 20 << 10)
+                            .dir("empty", |d| d)
+                    })
+                })
+        })
+        .file("outside", SHARED)
+        .build();
+    let outside = image.inode("/outside");
+    let ext2 = &mut image.ext2;
+    // make /tree/a/b/shared a second link to /outside
+    let groups = ext2.find_duplicates(ROOT).unwrap();
+    assert_eq!(groups.len(), 1);
+    assert_eq!(ext2.link_duplicates(ROOT, &groups[0]).unwrap(), 1);
+    assert_eq!(
+        ext2.resolve_path(ROOT, "/tree/a/b/shared").unwrap(),
+        outside
+    );
+    assert_eq!(ext2.get_inode(outside).unwrap().hard_links, 2);
+
+    // everything in the tree but the shared file, and the blocks they own,
+    // indirect ones included
+    let tree = ext2.resolve_path(ROOT, "/tree").unwrap();
+    let mut owned = vec![tree];
+    ext2.walk(tree, &WalkOptions::new(), &mut |entry| {
+        if entry.inode != outside {
+            owned.push(entry.inode);
+        }
+        WalkControl::Continue
+    })
+    .unwrap();
+    assert_eq!(owned.len(), 8);
+    let sectors_per_block = 1024 / 512;
+    let blocks: u32 = owned
+        .iter()
+        .map(|&inode| ext2.get_inode(inode).unwrap().sectors_count / sectors_per_block)
+        .sum();
+    let free_blocks = ext2.superblock.free_blocks_count;
+    let free_inodes = ext2.superblock.free_inodes_count;
+    let root_links = ext2.get_inode(ROOT).unwrap().hard_links;
+
+    let mut asked = Vec::new();
+    let removed = ext2
+        .remove_tree(ROOT, "tree", |path, _| {
+            asked.push(path.to_string());
+            true
+        })
+        .unwrap();
+    assert_eq!(removed, 9);
+    // deepest first, and the top last
+    assert_eq!(asked.last().unwrap(), "tree");
+    let position = |path: &str| asked.iter().position(|p| p == path).unwrap();
+    assert!(position("tree/a/b/shared") < position("tree/a/b"));
+    assert!(position("tree/a/b") < position("tree/a"));
+
+    assert!(ext2.resolve_path(ROOT, "/tree").is_err());
+    for &inode in &owned {
+        assert!(!ext2.inode_is_allocated(inode).unwrap(), "inode {}", inode);
+    }
+    assert_eq!(ext2.superblock.free_inodes_count, free_inodes + 8);
+    assert_eq!(ext2.superblock.free_blocks_count, free_blocks + blocks);
+    // the tree's `..` linked to the root
+    assert_eq!(ext2.get_inode(ROOT).unwrap().hard_links, root_links - 1);
+
+    // the symlink was removed, not followed, and the shared file kept its data
+    let record = ext2.get_inode(outside).unwrap();
+    assert_eq!(record.hard_links, 1);
+    assert_eq!(record.dtime, 0);
+    assert_eq!(ext2.resolve_path(ROOT, "/outside").unwrap(), outside);
+    assert_eq!(ext2.read_file_inode(outside).unwrap(), SHARED);
+    assert_eq!(ext2.check(), []);
+    if e2fsprogs::available() {
+        let (_dir, file) = image.dump();
+        if let Err(complaint) = e2fsprogs::fsck(&file) {
+            panic!("{}", complaint);
+        }
+        assert_eq!(e2fsprogs::cat(&file, "/outside"), SHARED);
+    }
+}
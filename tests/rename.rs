@@ -0,0 +1,125 @@
+//! `rename` as rename(2) does it: over an existing file or empty directory,
+//! never over a non-empty one nor below itself, and with a directory's `..`
+//! and both parents' link counts following it to a new parent.
+
+mod common;
+
+use common::{fixture, pattern, Image, ROOT};
+use ext2::{Ext2, Ext2Error};
+
+fn image() -> Image {
+    fixture()
+        .file("a", &pattern(3000))
+        .file("b", b"b")
+        .dir("p", |d| {
+            d.dir("src", |d| d.file("x", b"x").dir("deeper", |d| d))
+                .dir("empty", |d| d)
+                .dir("full", |d| d.file("y", b"y"))
+        })
+        .dir("q", |d| d)
+        .build()
+}
+
+fn links(ext2: &Ext2, inode: usize) -> u16 {
+    ext2.get_inode(inode).unwrap().hard_links
+}
+
+#[test]
+fn a_file_replaces_a_file() {
+    let mut image = image();
+    let (a, b) = (image.inode("/a"), image.inode("/b"));
+    let ext2 = &mut image.ext2;
+    let free = ext2.superblock.free_inodes_count;
+    ext2.rename(ROOT, "a", ROOT, "b").unwrap();
+    assert_eq!(ext2.lookup(ROOT, "a").unwrap(), None);
+    assert_eq!(ext2.lookup(ROOT, "b").unwrap(), Some(a));
+    assert_eq!(ext2.read_file_inode(a).unwrap(), pattern(3000));
+    assert_eq!(links(ext2, a), 1);
+    // b had no other name, so it's gone
+    assert!(!ext2.inode_is_allocated(b).unwrap());
+    assert_eq!(ext2.superblock.free_inodes_count, free + 1);
+    assert_eq!(ext2.check(), []);
+}
+
+#[test]
+fn a_directory_replaces_only_an_empty_directory() {
+    let mut image = image();
+    let (p, src, empty) = (
+        image.inode("/p"),
+        image.inode("/p/src"),
+        image.inode("/p/empty"),
+    );
+    let ext2 = &mut image.ext2;
+    assert!(matches!(
+        ext2.rename(p, "src", p, "full"),
+        Err(Ext2Error::NotEmpty { name }) if name == "full"
+    ));
+    assert!(matches!(
+        ext2.rename(p, "src", ROOT, "a"),
+        Err(Ext2Error::NotADirectory { .. })
+    ));
+    assert!(matches!(
+        ext2.rename(ROOT, "a", p, "empty"),
+        Err(Ext2Error::IsADirectory { .. })
+    ));
+    assert_eq!(ext2.check(), []);
+
+    // p: `.`, its entry in the root, and src's, empty's and full's `..`
+    assert_eq!(links(ext2, p), 5);
+    let dirs = ext2.block_groups.iter().map(|g| g.dirs_count).sum::<u16>();
+    ext2.rename(p, "src", p, "empty").unwrap();
+    assert_eq!(ext2.lookup(p, "empty").unwrap(), Some(src));
+    assert_eq!(ext2.lookup(p, "src").unwrap(), None);
+    assert!(!ext2.inode_is_allocated(empty).unwrap());
+    // one `..` fewer pointing at p
+    assert_eq!(links(ext2, p), 4);
+    let now = ext2.block_groups.iter().map(|g| g.dirs_count).sum::<u16>();
+    assert_eq!(now, dirs - 1);
+    assert_eq!(ext2.check(), []);
+}
+
+#[test]
+fn a_directory_is_not_moved_below_itself() {
+    let mut image = image();
+    let (p, src, deeper) = (
+        image.inode("/p"),
+        image.inode("/p/src"),
+        image.inode("/p/src/deeper"),
+    );
+    let ext2 = &mut image.ext2;
+    for (dir, name) in [(src, "self"), (deeper, "loop")] {
+        assert!(matches!(
+            ext2.rename(p, "src", dir, name),
+            Err(Ext2Error::IntoItself { .. })
+        ));
+    }
+    assert!(matches!(
+        ext2.rename(ROOT, "p", deeper, "p"),
+        Err(Ext2Error::IntoItself { .. })
+    ));
+    assert_eq!(ext2.lookup(p, "src").unwrap(), Some(src));
+    assert_eq!(ext2.check(), []);
+}
+
+#[test]
+fn a_directory_moved_across_takes_its_parent_link_along() {
+    let mut image = image();
+    let (p, q, src) = (image.inode("/p"), image.inode("/q"), image.inode("/p/src"));
+    let x = image.inode("/p/src/x");
+    let ext2 = &mut image.ext2;
+    let (p_links, q_links) = (links(ext2, p), links(ext2, q));
+    ext2.rename(p, "src", q, "moved").unwrap();
+    assert_eq!(ext2.lookup(q, "moved").unwrap(), Some(src));
+    assert_eq!(ext2.lookup(src, "..").unwrap(), Some(q));
+    assert_eq!(links(ext2, p), p_links - 1);
+    assert_eq!(links(ext2, q), q_links + 1);
+    // its own count is its entry, `.` and deeper's `..`, wherever it is
+    assert_eq!(links(ext2, src), 3);
+    assert_eq!(ext2.resolve_path(ROOT, "/q/moved/x").unwrap(), x);
+
+    // and within the same parent, nothing changes but the name
+    ext2.rename(q, "moved", q, "renamed").unwrap();
+    assert_eq!(ext2.lookup(src, "..").unwrap(), Some(q));
+    assert_eq!(links(ext2, q), q_links + 1);
+    assert_eq!(ext2.check(), []);
+}
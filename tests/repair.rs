@@ -0,0 +1,136 @@
+//! `repair` on images with wrong link counts, stray bitmap bits and free
+//! counts that have drifted: afterwards `check` finds nothing, and the
+//! changes that throw something away are only made if `confirm` agrees.
+
+mod common;
+
+use common::{e2fsprogs, fixture, pattern, Image};
+use ext2::{Ext2, Inconsistency};
+
+fn image() -> Image {
+    fixture()
+        .file("a", &pattern(1024))
+        .file("b", &pattern(3000))
+        .dir("d", |d| d.file("c", b"c"))
+        .build()
+}
+
+fn flip_block_bit(ext2: &mut Ext2, group: usize, index: usize) {
+    let bitmap = ext2.block_groups[group].block_usage_addr as usize;
+    ext2.block_mut(bitmap).unwrap()[index / 8] ^= 1 << (index % 8);
+}
+
+fn flip_inode_bit(ext2: &mut Ext2, group: usize, index: usize) {
+    let bitmap = ext2.block_groups[group].inode_usage_addr as usize;
+    ext2.block_mut(bitmap).unwrap()[index / 8] ^= 1 << (index % 8);
+}
+
+// an unused block and an unused inode of group 0 marked in use, and a
+// block `a` uses marked free, none of it with the counts
+fn stray_bits(image: &mut Image) -> (usize, usize, usize) {
+    let a = image.inode("/a");
+    let ext2 = &mut image.ext2;
+    let unused = ext2.block_bitmap(0).unwrap().find_first_clear().unwrap();
+    flip_block_bit(ext2, 0, unused);
+    let used = ext2.get_inode(a).unwrap().direct_pointer[0] as usize;
+    flip_block_bit(ext2, 0, used - ext2.group_first_block(0));
+    let inode = ext2.inode_bitmap(0).unwrap().find_first_clear().unwrap();
+    flip_inode_bit(ext2, 0, inode);
+    (ext2.group_first_block(0) + unused, used, inode + 1)
+}
+
+fn assert_clean(image: &mut Image) {
+    assert_eq!(image.ext2.check(), []);
+    if e2fsprogs::available() {
+        let (_dir, path) = image.dump();
+        e2fsprogs::fsck(&path).unwrap();
+    }
+}
+
+#[test]
+fn repair_fixes_link_counts_bitmaps_and_free_counts() {
+    let mut image = image();
+    let (a, d, c) = (image.inode("/a"), image.inode("/d"), image.inode("/d/c"));
+    stray_bits(&mut image);
+    let ext2 = &mut image.ext2;
+    ext2.inode_mut(a).unwrap().hard_links = 5;
+    ext2.inode_mut(c).unwrap().hard_links = 0;
+    ext2.inode_mut(d).unwrap().hard_links = 7;
+    ext2.block_groups[0].free_blocks_count -= 4;
+    ext2.block_groups[0].free_inodes_count += 2;
+    ext2.block_groups[0].dirs_count += 1;
+    ext2.superblock.free_blocks_count += 9;
+    ext2.superblock.free_inodes_count -= 3;
+    assert!(ext2.check().len() >= 10, "{:?}", ext2.check());
+
+    let mut asked = Vec::new();
+    let changes = ext2
+        .repair(|question| {
+            asked.push(question.to_string());
+            true
+        })
+        .unwrap();
+    assert_eq!(
+        asked,
+        [
+            "free 1 unreferenced inode(s) holding no data?",
+            "mark 1 block(s) no inode uses as free?",
+        ]
+    );
+    for change in [
+        format!("inode {}: link count 5 -> 1", a),
+        format!("inode {}: link count 7 -> 2", d),
+        format!("inode {}: link count 0 -> 1", c),
+    ] {
+        assert!(changes.contains(&change), "{}: {:?}", change, changes);
+    }
+    assert_eq!(ext2.get_inode(a).unwrap().hard_links, 1);
+    assert_eq!(ext2.get_inode(d).unwrap().hard_links, 2);
+    assert_eq!(ext2.read_file_inode(a).unwrap(), pattern(1024));
+    assert_eq!(ext2.read_file_inode(c).unwrap(), b"c");
+    assert_clean(&mut image);
+}
+
+#[test]
+fn repair_leaves_what_confirm_declines() {
+    let mut image = image();
+    let (unused, used, inode) = stray_bits(&mut image);
+    let ext2 = &mut image.ext2;
+
+    let mut asked = 0;
+    let changes = ext2
+        .repair(|_| {
+            asked += 1;
+            false
+        })
+        .unwrap();
+    assert_eq!(asked, 2);
+    // the block in use is marked so again, whatever confirm says, and the
+    // counts follow the bitmaps as they're left
+    assert!(changes.contains(&format!("block {}: marked in use", used)));
+    assert!(!changes.iter().any(|change| change.ends_with("freed")));
+    assert!(!changes.iter().any(|change| change.ends_with("marked free")));
+    assert_eq!(
+        ext2.check(),
+        [
+            Inconsistency::Unreferenced { inode },
+            Inconsistency::BlockMarkedUsed {
+                group: 0,
+                block: unused,
+            },
+        ]
+    );
+    assert!(ext2
+        .block_bitmap(0)
+        .unwrap()
+        .is_set(unused - ext2.group_first_block(0)));
+
+    // and agreeing the next time finishes the job
+    ext2.repair(|_| true).unwrap();
+    assert!(!ext2
+        .block_bitmap(0)
+        .unwrap()
+        .is_set(unused - ext2.group_first_block(0)));
+    assert!(!ext2.inode_bitmap(0).unwrap().is_set(inode - 1));
+    assert_clean(&mut image);
+}
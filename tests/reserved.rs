@@ -0,0 +1,71 @@
+//! The reserved inodes below the first usable one, and leaving lost+found
+//! out of walks.
+
+mod common;
+
+use common::e2fsprogs::{self, mke2fs};
+use common::{fixture, Image, ROOT};
+use ext2::{ReservedInode, WalkControl, WalkOptions};
+
+#[test]
+fn roles() {
+    assert_eq!(ReservedInode::of(0), None);
+    assert_eq!(ReservedInode::of(1).unwrap().role, "bad blocks");
+    assert_eq!(ReservedInode::of(2).unwrap().role, "root directory");
+    assert_eq!(ReservedInode::of(8).unwrap().role, "journal");
+    assert_eq!(ReservedInode::of(11), None);
+}
+
+#[test]
+fn in_use_on_ext2() {
+    let image = fixture().build();
+    let reserved = image.ext2.reserved_inodes().unwrap();
+    assert_eq!(reserved.len(), 10);
+    let in_use: Vec<usize> = reserved
+        .iter()
+        .filter(|reserved| reserved.in_use)
+        .map(|reserved| reserved.inode)
+        .collect();
+    assert!(in_use.contains(&ROOT));
+    assert!(!in_use.contains(&1));
+    assert!(!in_use.contains(&8));
+    assert!(reserved.iter().all(|reserved| reserved.allocated));
+}
+
+#[test]
+fn journal_in_use_on_ext3() {
+    if !e2fsprogs::available() {
+        return;
+    }
+    let image = Image::from_bytes(&mke2fs(&["-t", "ext3"], 8 << 20));
+    let journal = &image.ext2.reserved_inodes().unwrap()[7];
+    assert_eq!(journal.role.unwrap().role, "journal");
+    assert!(journal.in_use);
+    assert!(journal.blocks > 0);
+}
+
+#[test]
+fn walk_skips_lost_and_found() {
+    let image = fixture()
+        .file("a", b"a")
+        .dir("d", |d| d.dir("lost+found", |d| d.file("kept", b"")))
+        .build();
+    let walked = |options: &WalkOptions| {
+        let mut paths = Vec::new();
+        image
+            .ext2
+            .walk(ROOT, options, &mut |entry| {
+                paths.push(entry.path());
+                WalkControl::Continue
+            })
+            .unwrap();
+        paths.sort();
+        paths
+    };
+    assert!(walked(&WalkOptions::new()).contains(&String::from("lost+found")));
+    // only the root's
+    assert_eq!(
+        walked(&WalkOptions::new().skip_lost_and_found(true)),
+        ["a", "d", "d/lost+found", "d/lost+found/kept"]
+    );
+}
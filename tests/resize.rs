@@ -0,0 +1,175 @@
+//! `resize_grow` into space added to the end of the device: the old last
+//! group is filled out, new groups get their bitmaps, inode table and, where
+//! sparse_super gives them one, a backup superblock, and everything that was
+//! there reads back the same.
+
+mod common;
+
+use common::{e2fsprogs, fixture, pattern, Image, ROOT};
+use ext2::structs::BlockGroupDescriptor;
+use ext2::{Ext2, InodeNo};
+use std::fs;
+use std::mem;
+
+const BLOCKS_PER_GROUP: usize = 8192;
+
+// 12 MiB at 1 KiB blocks: a whole group 0 and a group 1 of 4095 blocks,
+// with room on the device for `device_blocks` blocks
+fn image(device_blocks: usize) -> Image {
+    let mut image = fixture()
+        .block_size(1024)
+        .size(12 << 20)
+        .dir("docs", |d| d.file("a.txt", b"hello"))
+        .file("big", &pattern(40 << 10))
+        .build();
+    let mut bytes = image.synced_bytes();
+    assert_eq!(bytes.len(), 12288 * 1024);
+    bytes.resize(device_blocks * 1024, 0);
+    Image::from_bytes(&bytes)
+}
+
+// the bitmaps and inode table right after the backup, if the group has one
+fn expected_descriptor(ext2: &Ext2, group: usize, size: usize) -> (u32, u32, u32, u16, u16) {
+    let sb = &ext2.superblock;
+    let descriptor_blocks =
+        (ext2.block_groups.len() * mem::size_of::<BlockGroupDescriptor>()).div_ceil(1024);
+    let copies = if ext2.group_has_superblock(group) {
+        1 + descriptor_blocks + sb.reserved_gdt_blocks as usize
+    } else {
+        0
+    };
+    let inode_table_blocks = (sb.inodes_per_group as usize * InodeNo::slot_size(sb)).div_ceil(1024);
+    let first = ext2.group_first_block(group);
+    (
+        (first + copies) as u32,
+        (first + copies + 1) as u32,
+        (first + copies + 2) as u32,
+        (size - copies - 2 - inode_table_blocks) as u16,
+        sb.inodes_per_group as u16,
+    )
+}
+
+fn descriptor(ext2: &Ext2, group: usize) -> (u32, u32, u32, u16, u16) {
+    let descriptor = &ext2.block_groups[group];
+    (
+        descriptor.block_usage_addr,
+        descriptor.inode_usage_addr,
+        descriptor.inode_table_block,
+        descriptor.free_blocks_count,
+        descriptor.free_inodes_count,
+    )
+}
+
+// where the group's bitmaps and inode table are
+fn layout(ext2: &Ext2, group: usize) -> (u32, u32, u32) {
+    let (block_bitmap, inode_bitmap, inode_table, ..) = descriptor(ext2, group);
+    (block_bitmap, inode_bitmap, inode_table)
+}
+
+// what a backup in `group` holds, opened the way a damaged primary would be
+fn backup(bytes: &[u8], group: usize) -> Ext2 {
+    let offset = (1 + group * BLOCKS_PER_GROUP) * 1024;
+    Ext2::from_backup(bytes.to_vec(), offset).unwrap()
+}
+
+// the grown filesystem's files are intact, it checks clean with us and with
+// e2fsck, and each backup has the new superblock and descriptor table
+fn assert_grown(mut image: Image) {
+    let big = image.inode("/big");
+    assert_eq!(image.ext2.read_file_inode(big).unwrap(), pattern(40 << 10));
+    let a = image.inode("/docs/a.txt");
+    assert_eq!(image.ext2.read_file_inode(a).unwrap(), b"hello");
+    assert_eq!(image.ext2.check(), []);
+
+    let bytes = image.synced_bytes();
+    let ext2 = &image.ext2;
+    for group in 1..ext2.block_groups.len() {
+        if !ext2.group_has_superblock(group) {
+            continue;
+        }
+        let copy = backup(&bytes, group);
+        assert_eq!(copy.superblock.blocks_count, ext2.superblock.blocks_count);
+        assert_eq!(copy.superblock.inodes_count, ext2.superblock.inodes_count);
+        assert_eq!(copy.block_groups.len(), ext2.block_groups.len());
+        // the free counts in a backup are as of when it was written, as
+        // with e2fsprogs, so only the layout has to match
+        for g in 0..ext2.block_groups.len() {
+            assert_eq!(layout(&copy, g), layout(ext2, g), "group {}", g);
+        }
+    }
+    if e2fsprogs::available() {
+        let (_dir, path) = image.dump();
+        e2fsprogs::fsck(&path).unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().len() as usize, bytes.len());
+    }
+}
+
+#[test]
+fn grow_into_a_partial_last_group() {
+    let mut image = image(20000);
+    let ext2 = &mut image.ext2;
+    let (old_free, old_inodes) = (
+        ext2.superblock.free_blocks_count,
+        ext2.superblock.inodes_count,
+    );
+    let group_1_free = ext2.block_groups[1].free_blocks_count;
+
+    assert_eq!(ext2.resize_grow(20000).unwrap(), 20000);
+    assert_eq!(ext2.superblock.blocks_count, 20000);
+    assert_eq!(ext2.block_groups.len(), 3);
+    // group 1 filled out from 4095 blocks to a whole group
+    assert_eq!(
+        ext2.block_groups[1].free_blocks_count,
+        group_1_free + (BLOCKS_PER_GROUP - 4095) as u16
+    );
+    // group 2 has no backup and runs from block 16385 to the end
+    assert!(!ext2.group_has_superblock(2));
+    assert_eq!(ext2.group_blocks_count(2), 20000 - 16385);
+    assert_eq!(
+        descriptor(ext2, 2),
+        expected_descriptor(ext2, 2, 20000 - 16385)
+    );
+    assert_eq!(
+        ext2.superblock.free_blocks_count,
+        old_free + (BLOCKS_PER_GROUP - 4095) as u32 + ext2.block_groups[2].free_blocks_count as u32
+    );
+    let inodes_per_group = ext2.superblock.inodes_per_group;
+    assert_eq!(ext2.superblock.inodes_count, old_inodes + inodes_per_group);
+    // the new space is usable
+    let file = ext2.create_file(ROOT, "new", 0o644).unwrap();
+    ext2.write_file(file, 0, &pattern(6 << 20)).unwrap();
+    assert_eq!(ext2.read_file_inode(file).unwrap(), pattern(6 << 20));
+    assert_grown(image);
+}
+
+#[test]
+fn grow_to_a_group_boundary() {
+    // four whole groups, the last one with a backup (3 is a power of 3)
+    let end = 1 + 4 * BLOCKS_PER_GROUP;
+    let mut image = image(end);
+    let ext2 = &mut image.ext2;
+    let old_free = ext2.superblock.free_blocks_count;
+    let group_1_free = ext2.block_groups[1].free_blocks_count;
+
+    assert_eq!(ext2.resize_grow(end).unwrap(), end);
+    assert_eq!(ext2.block_groups.len(), 4);
+    assert_eq!(
+        ext2.block_groups[1].free_blocks_count,
+        group_1_free + (BLOCKS_PER_GROUP - 4095) as u16
+    );
+    let mut added = (BLOCKS_PER_GROUP - 4095) as u32;
+    for group in 2..4 {
+        assert_eq!(ext2.group_blocks_count(group), BLOCKS_PER_GROUP);
+        assert_eq!(
+            descriptor(ext2, group),
+            expected_descriptor(ext2, group, BLOCKS_PER_GROUP),
+            "group {}",
+            group
+        );
+        added += ext2.block_groups[group].free_blocks_count as u32;
+    }
+    assert!(!ext2.group_has_superblock(2));
+    assert!(ext2.group_has_superblock(3));
+    assert_eq!(ext2.superblock.free_blocks_count, old_free + added);
+    assert_grown(image);
+}
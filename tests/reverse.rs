@@ -0,0 +1,122 @@
+//! The reverse lookups: `block_owners` from a block to the inodes holding
+//! it, however far down the indirect blocks it is, and `inode_paths` from an
+//! inode to every name it has.
+
+mod common;
+
+use common::{fixture, pattern, ROOT};
+use ext2::{BlockRun, Ext2Error, IndirectPointers};
+use std::collections::BTreeMap;
+
+// the first data block of a run, skipping holes
+fn first_data(runs: &[BlockRun]) -> usize {
+    runs.iter().find(|run| !run.is_hole()).unwrap().physical
+}
+
+#[test]
+fn blocks_are_traced_through_indirect_blocks() {
+    // 300 blocks at 1 KiB, past the 12 + 256 the singly indirect block
+    // reaches, so into the doubly indirect one
+    let mut image = fixture()
+        .block_size(1024)
+        .file("big", &pattern(300 << 10))
+        .file("small", b"small")
+        .build();
+    let (big, small) = (image.inode("/big"), image.inode("/small"));
+    let ext2 = &mut image.ext2;
+    let map = ext2.block_map(big).unwrap();
+    let indirect = map.indirect.unwrap();
+    let IndirectPointers::Data(runs) = &indirect.pointers else {
+        panic!("{:?}", indirect);
+    };
+    let through_indirect = first_data(runs);
+    let doubly = map.doubly_indirect.unwrap();
+    let IndirectPointers::Indirect(second) = &doubly.pointers else {
+        panic!("{:?}", doubly);
+    };
+    let IndirectPointers::Data(runs) = &second[0].pointers else {
+        panic!("{:?}", second[0]);
+    };
+    let through_doubly = first_data(runs);
+    let small_data = ext2.file_blocks(small).unwrap().next().unwrap().unwrap();
+
+    let blocks = [
+        map.direct[0].physical,
+        indirect.block,
+        through_indirect,
+        doubly.block,
+        second[0].block,
+        through_doubly,
+        small_data,
+    ];
+    let owners = ext2.block_owners(&blocks).unwrap();
+    let expected: BTreeMap<usize, Vec<usize>> = blocks
+        .iter()
+        .map(|&block| (block, vec![if block == small_data { small } else { big }]))
+        .collect();
+    assert_eq!(owners, expected);
+}
+
+#[test]
+fn metadata_and_free_blocks_have_no_owner() {
+    let mut image = fixture().file("a", &pattern(5000)).build();
+    let ext2 = &mut image.ext2;
+    let group = &ext2.block_groups[0];
+    let metadata = [
+        group.block_usage_addr as usize,
+        group.inode_usage_addr as usize,
+        group.inode_table_block as usize,
+    ];
+    let free =
+        ext2.group_first_block(0) + ext2.block_bitmap(0).unwrap().find_first_clear().unwrap();
+    let mut blocks = metadata.to_vec();
+    blocks.push(free);
+    let owners = ext2.block_owners(&blocks).unwrap();
+    assert_eq!(owners.len(), 4);
+    assert!(
+        owners.values().all(|found| found.is_empty()),
+        "{:?}",
+        owners
+    );
+
+    let blocks_count = ext2.superblock.blocks_count as usize;
+    assert!(matches!(
+        ext2.block_owners(&[blocks_count]),
+        Err(Ext2Error::BlockOutOfRange { block, .. }) if block == blocks_count
+    ));
+}
+
+#[test]
+fn every_hard_link_is_a_path() {
+    let mut image = fixture()
+        .file("a", &pattern(2000))
+        .dir("d", |d| {
+            d.file("b", &pattern(2000))
+                .dir("e", |d| d.file("c", &pattern(2000)))
+        })
+        .file("other", b"other")
+        .build();
+    let (a, other) = (image.inode("/a"), image.inode("/other"));
+    let e = image.inode("/d/e");
+    let ext2 = &mut image.ext2;
+    // three copies of the same thing become three names for one inode
+    let group = ext2.find_duplicates(ROOT).unwrap().remove(0);
+    assert_eq!(ext2.link_duplicates(ROOT, &group).unwrap(), 2);
+    assert_eq!(ext2.get_inode(a).unwrap().hard_links, 3);
+
+    let unlinked = ext2.create_file(ROOT, "gone", 0o644).unwrap();
+    ext2.unlink(ROOT, "gone").unwrap();
+    let paths = ext2.inode_paths(&[a, other, e, ROOT, unlinked]).unwrap();
+    let expected: BTreeMap<usize, Vec<String>> = [
+        (a, vec!["/a", "/d/b", "/d/e/c"]),
+        (other, vec!["/other"]),
+        // not its `.`, nor the `..` of anything in it
+        (e, vec!["/d/e"]),
+        (ROOT, vec!["/"]),
+        (unlinked, vec![]),
+    ]
+    .into_iter()
+    .map(|(inode, paths)| (inode, paths.into_iter().map(String::from).collect()))
+    .collect();
+    assert_eq!(paths, expected);
+}
@@ -0,0 +1,357 @@
+//! Random sequences of filesystem operations, applied both to a freshly
+//! made image through the crate's API and to a model that's too simple to be
+//! wrong: a map from paths to file contents, and a set of directories. After
+//! every sequence the image must list the same tree with the same contents,
+//! and `Ext2::check` must find nothing wrong. After every operation the free
+//! block and inode counts and the directory counts, per group and in the
+//! superblock, must match a recount from the bitmaps. When a sequence fails,
+//! proptest shrinks it to the shortest one that still does.
+//!
+//! Operations name their targets by index into what exists at the time
+//! (modulo its length), not by path, so that sequences stay meaningful as
+//! shrinking removes steps from them. Names come from a tiny alphabet so they
+//! collide often, and the model predicts which operations must fail; the
+//! image has to fail exactly those.
+//!
+//! There's no truncate in the crate, so there's no truncate here either.
+
+mod common;
+
+use common::{fixture, ROOT};
+use ext2::Ext2;
+use proptest::prelude::*;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+
+const IMAGE_SIZE: usize = 4 << 20;
+
+#[derive(Debug, Clone)]
+enum Op {
+    CreateFile {
+        dir: usize,
+        name: String,
+    },
+    Mkdir {
+        dir: usize,
+        name: String,
+    },
+    /// Write `len` bytes made from `seed` at `offset`, which may leave a hole
+    Write {
+        file: usize,
+        offset: u64,
+        len: usize,
+        seed: u8,
+    },
+    /// Write at the current end of the file
+    Append {
+        file: usize,
+        len: usize,
+        seed: u8,
+    },
+    Rename {
+        entry: usize,
+        dir: usize,
+        name: String,
+    },
+    /// `unlink` a file, `remove_dir` a directory
+    Remove {
+        entry: usize,
+    },
+}
+
+fn name() -> impl Strategy<Value = String> {
+    "[ab]{1,2}"
+}
+
+fn op() -> impl Strategy<Value = Op> {
+    prop_oneof![
+        (any::<usize>(), name()).prop_map(|(dir, name)| Op::CreateFile { dir, name }),
+        (any::<usize>(), name()).prop_map(|(dir, name)| Op::Mkdir { dir, name }),
+        (any::<usize>(), 0..40_000u64, 0..20_000usize, any::<u8>()).prop_map(
+            |(file, offset, len, seed)| Op::Write {
+                file,
+                offset,
+                len,
+                seed
+            }
+        ),
+        (any::<usize>(), 0..5_000usize, any::<u8>()).prop_map(|(file, len, seed)| Op::Append {
+            file,
+            len,
+            seed
+        }),
+        (any::<usize>(), any::<usize>(), name()).prop_map(|(entry, dir, name)| Op::Rename {
+            entry,
+            dir,
+            name
+        }),
+        any::<usize>().prop_map(|entry| Op::Remove { entry }),
+    ]
+}
+
+/// What the image should hold.
+#[derive(Debug, Default, PartialEq)]
+struct Model {
+    files: BTreeMap<PathBuf, Vec<u8>>,
+    /// Every directory but the root
+    dirs: BTreeSet<PathBuf>,
+}
+
+impl Model {
+    fn dirs(&self) -> Vec<PathBuf> {
+        std::iter::once(PathBuf::from("/"))
+            .chain(self.dirs.iter().cloned())
+            .collect()
+    }
+
+    fn files(&self) -> Vec<PathBuf> {
+        self.files.keys().cloned().collect()
+    }
+
+    /// Files and directories, but not the root
+    fn entries(&self) -> Vec<PathBuf> {
+        let mut entries = self.files();
+        entries.extend(self.dirs.iter().cloned());
+        entries.sort();
+        entries
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        self.files.contains_key(path) || self.dirs.contains(path)
+    }
+
+    fn is_empty_dir(&self, dir: &Path) -> bool {
+        self.entries()
+            .iter()
+            .all(|entry| entry.parent() != Some(dir))
+    }
+}
+
+fn pick<T: Clone>(items: &[T], index: usize) -> Option<T> {
+    (!items.is_empty()).then(|| items[index % items.len()].clone())
+}
+
+fn data(len: usize, seed: u8) -> Vec<u8> {
+    (0..len).map(|i| seed.wrapping_add(i as u8 / 3)).collect()
+}
+
+fn split(path: &Path) -> (&Path, &str) {
+    (
+        path.parent().unwrap(),
+        path.file_name().unwrap().to_str().unwrap(),
+    )
+}
+
+fn inode(ext2: &Ext2, path: &Path) -> usize {
+    ext2.resolve_path(ROOT, path.to_str().unwrap()).unwrap()
+}
+
+/// Apply `op` to both, checking the image fails where the model says it must.
+fn apply(model: &mut Model, ext2: &mut Ext2, op: &Op) -> Result<(), TestCaseError> {
+    match op {
+        Op::CreateFile { dir, name } | Op::Mkdir { dir, name } => {
+            let dir = pick(&model.dirs(), *dir).unwrap();
+            let path = dir.join(name);
+            let parent = inode(ext2, &dir);
+            let result = match op {
+                Op::CreateFile { .. } => ext2.create_file(parent, name, 0o644),
+                _ => ext2.create_dir(parent, name, 0o755),
+            };
+            if model.exists(&path) {
+                prop_assert!(result.is_err(), "{:?} created over an existing entry", path);
+            } else {
+                result.map_err(|err| TestCaseError::fail(format!("{:?}: {}", op, err)))?;
+                match op {
+                    Op::CreateFile { .. } => model.files.insert(path, Vec::new()).is_none(),
+                    _ => model.dirs.insert(path),
+                };
+            }
+        }
+        Op::Write {
+            file,
+            offset,
+            len,
+            seed,
+        } => {
+            let Some(path) = pick(&model.files(), *file) else {
+                return Ok(());
+            };
+            write(model, ext2, &path, *offset, &data(*len, *seed))?;
+        }
+        Op::Append { file, len, seed } => {
+            let Some(path) = pick(&model.files(), *file) else {
+                return Ok(());
+            };
+            let offset = model.files[&path].len() as u64;
+            write(model, ext2, &path, offset, &data(*len, *seed))?;
+        }
+        Op::Rename { entry, dir, name } => {
+            let Some(from) = pick(&model.entries(), *entry) else {
+                return Ok(());
+            };
+            let to_dir = pick(&model.dirs(), *dir).unwrap();
+            let to = to_dir.join(name);
+            // replacing entries and the errors for moving a directory below
+            // itself are rename's own business; keep to plain moves
+            if model.exists(&to) || to.starts_with(&from) {
+                return Ok(());
+            }
+            let (from_dir, from_name) = split(&from);
+            let (old_parent, new_parent) = (inode(ext2, from_dir), inode(ext2, &to_dir));
+            ext2.rename(old_parent, from_name, new_parent, name)
+                .map_err(|err| TestCaseError::fail(format!("{:?}: {}", op, err)))?;
+            if let Some(contents) = model.files.remove(&from) {
+                model.files.insert(to, contents);
+            } else {
+                // the directory and everything below it move
+                let moved: Vec<PathBuf> = model
+                    .entries()
+                    .into_iter()
+                    .filter(|path| path.starts_with(&from))
+                    .collect();
+                for path in moved {
+                    let new_path = to.join(path.strip_prefix(&from).unwrap());
+                    if let Some(contents) = model.files.remove(&path) {
+                        model.files.insert(new_path, contents);
+                    } else {
+                        model.dirs.remove(&path);
+                        model.dirs.insert(new_path);
+                    }
+                }
+            }
+        }
+        Op::Remove { entry } => {
+            let Some(path) = pick(&model.entries(), *entry) else {
+                return Ok(());
+            };
+            let (dir, name) = split(&path);
+            let parent = inode(ext2, dir);
+            if model.files.contains_key(&path) {
+                ext2.unlink(parent, name)
+                    .map_err(|err| TestCaseError::fail(format!("{:?}: {}", op, err)))?;
+                model.files.remove(&path);
+            } else if model.is_empty_dir(&path) {
+                ext2.remove_dir(parent, name)
+                    .map_err(|err| TestCaseError::fail(format!("{:?}: {}", op, err)))?;
+                model.dirs.remove(&path);
+            } else {
+                prop_assert!(
+                    ext2.remove_dir(parent, name).is_err(),
+                    "removed non-empty {:?}",
+                    path
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+fn write(
+    model: &mut Model,
+    ext2: &mut Ext2,
+    path: &Path,
+    offset: u64,
+    data: &[u8],
+) -> Result<(), TestCaseError> {
+    let written = ext2
+        .write_file(inode(ext2, path), offset, data)
+        .map_err(|err| TestCaseError::fail(format!("writing {:?}: {}", path, err)))?;
+    prop_assert_eq!(written, data.len(), "short write to {:?}", path);
+    let contents = model.files.get_mut(path).unwrap();
+    let end = offset as usize + data.len();
+    if contents.len() < end {
+        contents.resize(end, 0);
+    }
+    contents[offset as usize..end].copy_from_slice(data);
+    Ok(())
+}
+
+/// The free and directory counts of every group, and the superblock's
+/// totals, against what the bitmaps and inodes say.
+fn check_counts(ext2: &Ext2, after: &Op) -> Result<(), TestCaseError> {
+    let inodes_per_group = ext2.superblock.inodes_per_group as usize;
+    let (mut free_blocks, mut free_inodes) = (0, 0);
+    for (group, descriptor) in ext2.block_groups.iter().enumerate() {
+        let blocks = ext2.block_bitmap(group).unwrap().count_clear();
+        let inodes = ext2.inode_bitmap(group).unwrap();
+        let dirs = (0..inodes_per_group)
+            .filter(|&index| inodes.is_set(index))
+            .filter(|&index| {
+                let inode = group * inodes_per_group + index + 1;
+                ext2.get_inode(inode).unwrap().is_dir()
+            })
+            .count();
+        prop_assert_eq!(
+            (
+                descriptor.free_blocks_count as usize,
+                descriptor.free_inodes_count as usize,
+                descriptor.dirs_count as usize
+            ),
+            (blocks, inodes.count_clear(), dirs),
+            "group {} after {:?}",
+            group,
+            after
+        );
+        free_blocks += blocks;
+        free_inodes += inodes.count_clear();
+    }
+    prop_assert_eq!(
+        (
+            ext2.superblock.free_blocks_count as usize,
+            ext2.superblock.free_inodes_count as usize
+        ),
+        (free_blocks, free_inodes),
+        "superblock after {:?}",
+        after
+    );
+    Ok(())
+}
+
+/// Read the whole tree back out of the image, in the model's terms.
+fn read_back(ext2: &Ext2) -> Model {
+    let mut found = Model::default();
+    let mut pending = vec![(PathBuf::from("/"), ROOT)];
+    while let Some((dir, dir_inode)) = pending.pop() {
+        for entry in ext2.read_dir_inode(dir_inode).unwrap() {
+            let (inode, name) = (entry.inode, entry.name_lossy().into_owned());
+            if name == "." || name == ".." || (dir_inode == ROOT && name == "lost+found") {
+                continue;
+            }
+            let path = dir.join(&name);
+            if ext2.get_inode(inode).unwrap().is_dir() {
+                found.dirs.insert(path.clone());
+                pending.push((path, inode));
+            } else {
+                found
+                    .files
+                    .insert(path, ext2.read_file_inode(inode).unwrap());
+            }
+        }
+    }
+    found
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(64))]
+
+    #[test]
+    fn operations_match_the_model(ops in prop::collection::vec(op(), 1..40)) {
+        let mut image = fixture().size(IMAGE_SIZE).build();
+        let mut model = Model::default();
+        for op in &ops {
+            apply(&mut model, &mut image.ext2, op)?;
+            check_counts(&image.ext2, op)?;
+        }
+        let found = read_back(&image.ext2);
+        prop_assert_eq!(&found.dirs, &model.dirs);
+        prop_assert_eq!(
+            found.files.keys().collect::<Vec<_>>(),
+            model.files.keys().collect::<Vec<_>>()
+        );
+        for (path, contents) in &model.files {
+            prop_assert!(&found.files[path] == contents, "contents of {:?} differ", path);
+        }
+        let problems = image.ext2.check();
+        prop_assert!(problems.is_empty(), "check found: {:?}", problems);
+    }
+}
@@ -0,0 +1,43 @@
+//! The one image in the repository, made by mke2fs rather than by the crate,
+//! with a handful of files: it opens, reads back as it was made, and checks
+//! clean. Every other test builds its image with `fixture()`.
+
+use ext2::Ext2;
+
+const ROOT: usize = 2;
+
+#[test]
+fn sample_image() {
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/myfs.ext2");
+    let ext2 = Ext2::options().read_only(true).open_path(path).unwrap();
+    let hello = ext2.resolve_path(ROOT, "/hello.txt").unwrap();
+    assert_eq!(
+        ext2.read_file_inode(hello).unwrap(),
+        b"Hello, ext2 world!\n"
+    );
+    let inside = ext2
+        .resolve_path(ROOT, "/test_directory/file_in_folder.txt")
+        .unwrap();
+    assert_eq!(
+        ext2.read_file_inode(inside).unwrap(),
+        b"Hello! I'm a file inside a folder.\n"
+    );
+    let mut names: Vec<Vec<u8>> = ext2
+        .read_dir_inode(ROOT)
+        .unwrap()
+        .into_iter()
+        .map(|entry| entry.name)
+        .collect();
+    names.sort();
+    assert_eq!(
+        names,
+        [
+            &b"."[..],
+            b"..",
+            b"hello.txt",
+            b"lost+found",
+            b"test_directory"
+        ]
+    );
+    assert_eq!(ext2.check(), []);
+}
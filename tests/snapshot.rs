@@ -0,0 +1,67 @@
+//! Snapshots and rollback: everything done after a snapshot is undone, down
+//! to the last byte of the image.
+
+mod common;
+
+use common::{fixture, pattern, Image, ROOT};
+
+// `mkdir`, then a write that allocates, into the new directory and into an
+// existing file, past its indirect block
+fn modify(image: &mut Image) {
+    let ext2 = &mut image.ext2;
+    let dir = ext2.create_dir(ROOT, "new", 0o755).unwrap();
+    let file = ext2.create_file(dir, "file", 0o644).unwrap();
+    ext2.write_file(file, 0, &pattern(40 << 10)).unwrap();
+    let a = ext2.resolve_path(ROOT, "/docs/a.txt").unwrap();
+    ext2.write_file(a, 20 << 10, b"later").unwrap();
+}
+
+#[test]
+fn rollback_of_a_synced_image_is_byte_identical() {
+    let bytes = fixture()
+        .block_size(1024)
+        .dir("docs", |d| d.file("a.txt", b"hello"))
+        .build()
+        .synced_bytes();
+    let mut image = Image::from_bytes(&bytes);
+    let snapshot = image.ext2.snapshot();
+    assert_eq!(snapshot.dirty_blocks(), 0);
+    assert!(!image.ext2.modified_since(&snapshot));
+    // what syncing would write now, the mount opening it counted included
+    let before = image.synced_bytes();
+
+    modify(&mut image);
+    assert!(image.ext2.modified_since(&snapshot));
+    assert!(image.synced_bytes() != before);
+
+    image.ext2.rollback(&snapshot);
+    assert_eq!(image.ext2.snapshot().dirty_blocks(), 0);
+    assert!(image.ext2.resolve_path(ROOT, "/new").is_err());
+    assert!(image.synced_bytes() == before);
+    // and only the superblock differs from the image it was opened from
+    let changed: Vec<usize> = (0..bytes.len())
+        .filter(|&i| before[i] != bytes[i])
+        .map(|i| i / 1024)
+        .collect();
+    assert!(changed.iter().all(|&block| block == 1), "{:?}", changed);
+}
+
+#[test]
+fn rollback_keeps_changes_made_before_the_snapshot() {
+    // the fixture is built in memory, so the snapshot holds modified blocks,
+    // which the changes after it modify again
+    let mut image = fixture().dir("docs", |d| d.file("a.txt", b"hello")).build();
+    let before = image.synced_bytes();
+    let snapshot = image.ext2.snapshot();
+    assert!(snapshot.dirty_blocks() > 0);
+
+    modify(&mut image);
+    image.ext2.rollback(&snapshot);
+    assert!(image.synced_bytes() == before);
+    assert_eq!(image.ext2.check(), []);
+
+    // and the snapshot can be rolled back to more than once
+    modify(&mut image);
+    image.ext2.rollback(&snapshot);
+    assert!(image.synced_bytes() == before);
+}
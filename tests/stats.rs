@@ -0,0 +1,118 @@
+//! `inodes`, the iterator over every allocated inode, and the `stats` built
+//! on it: files counted by type and size, and nothing counted for an inode
+//! the bitmap says is free, whatever is left in its slot.
+
+mod common;
+
+use common::{fixture, pattern, Image, ROOT};
+use ext2::{FsStats, TypeStats};
+use std::collections::BTreeMap;
+
+// at 1 KiB blocks, so the directories' sizes are the same whoever makes it
+fn image() -> Image {
+    let mut image = fixture()
+        .block_size(1024)
+        .dir("d", |d| {
+            d.file("a", b"")
+                .file("b", &pattern(3000))
+                .file("c", b"12345")
+                .file("gone", &pattern(1000))
+        })
+        .symlink("link", "d/b")
+        .build();
+    let d = image.inode("/d");
+    let ext2 = &mut image.ext2;
+    ext2.create_node(ROOT, "pipe", 0x1000 | 0o644, None)
+        .unwrap();
+    ext2.unlink(d, "gone").unwrap();
+    image
+}
+
+#[test]
+fn inodes_lists_what_is_allocated_in_order() {
+    let image = image();
+    let expected: Vec<usize> = {
+        let mut all: Vec<usize> = [
+            "/",
+            "/lost+found",
+            "/d",
+            "/d/a",
+            "/d/b",
+            "/d/c",
+            "/link",
+            "/pipe",
+        ]
+        .iter()
+        .map(|path| image.inode(path))
+        .collect();
+        all.sort();
+        all
+    };
+    let listed: Vec<usize> = image.ext2.inodes().map(|(inode, _)| inode).collect();
+    // gone's inode still has its size and pointers, but its bit is clear
+    assert_eq!(listed, expected);
+    // every allocated inode but the reserved ones, the root aside
+    let ext2 = &image.ext2;
+    let allocated = (ext2.superblock.inodes_count - ext2.superblock.free_inodes_count) as usize;
+    assert_eq!(
+        listed.len(),
+        allocated - (ext2.first_usable_inode() - 1) + 1
+    );
+    assert!(ext2
+        .inodes()
+        .all(|(_, record)| record.hard_links > 0 && record.dtime == 0));
+}
+
+#[test]
+fn a_freed_inode_is_skipped_whatever_its_slot_holds() {
+    let mut image = image();
+    let b = image.inode("/d/b");
+    let ext2 = &mut image.ext2;
+    // clear b's bit behind its back; the inode itself is left as it was
+    let group = (b - 1) / ext2.superblock.inodes_per_group as usize;
+    let index = (b - 1) % ext2.superblock.inodes_per_group as usize;
+    let bitmap = ext2.block_groups[group].inode_usage_addr as usize;
+    ext2.block_mut(bitmap).unwrap()[index / 8] &= !(1 << (index % 8));
+    assert!(ext2.get_inode(b).unwrap().hard_links > 0);
+    assert!(ext2.inodes().all(|(inode, _)| inode != b));
+    let stats = ext2.stats(10).unwrap();
+    assert_eq!(stats.by_type["regular file"].count, 2);
+    assert!(stats.largest.iter().all(|&(inode, _)| inode != b));
+}
+
+#[test]
+fn stats_counts_by_type_and_size() {
+    let image = image();
+    let (b, c, a) = (
+        image.inode("/d/b"),
+        image.inode("/d/c"),
+        image.inode("/d/a"),
+    );
+    let stats = image.ext2.stats(2).unwrap();
+    let by_type = |count, bytes| TypeStats { count, bytes };
+    let mut size_histogram = vec![0; 13];
+    // empty, then 5 bytes in 4..8, then 3000 in 2048..4096
+    size_histogram[0] = 1;
+    size_histogram[3] = 1;
+    size_histogram[12] = 1;
+    assert_eq!(
+        stats,
+        FsStats {
+            by_type: BTreeMap::from([
+                // the root's block, lost+found's 12 and d's
+                ("directory", by_type(3, 14 << 10)),
+                ("regular file", by_type(3, 3005)),
+                ("symbolic link", by_type(1, 3)),
+                ("FIFO", by_type(1, 0)),
+            ]),
+            largest: vec![(b, 3000), (c, 5)],
+            dirs: 3,
+            // lost+found is empty; d, link and pipe in the root, a, b and c
+            // in d
+            dir_entries: 7,
+            size_histogram,
+        }
+    );
+    assert_eq!(stats.average_dir_entries(), 7.0 / 3.0);
+    assert!(image.ext2.stats(10).unwrap().largest.contains(&(a, 0)));
+}
@@ -0,0 +1,101 @@
+//! Reading damaged images: strict reads stop at the first inconsistency,
+//! lenient ones work around it and keep a warning.
+
+mod common;
+
+use common::{fixture, pattern, ROOT};
+use ext2::{Ext2, Ext2Error, Ext2Options, Strictness};
+
+const BLOCK_SIZE: usize = 1024;
+// twelve direct blocks, then four through the indirect block
+const BIG_SIZE: usize = 16 * BLOCK_SIZE;
+
+fn open(bytes: &[u8], strictness: Strictness) -> Ext2 {
+    Ext2Options::new()
+        .read_only(true)
+        .strictness(strictness)
+        .open_bytes(bytes)
+        .unwrap()
+}
+
+// an image whose big.bin has its indirect pointer past the end of the
+// filesystem, with the inode of big.bin
+fn broken_indirect() -> (Vec<u8>, usize) {
+    let mut image = fixture()
+        .block_size(BLOCK_SIZE)
+        .file_with_size("big.bin", BIG_SIZE)
+        .build();
+    let big = image.inode("/big.bin");
+    image.ext2.inode_mut(big).unwrap().indirect_pointer = u32::MAX;
+    (image.synced_bytes(), big)
+}
+
+#[test]
+fn strict_by_default() {
+    let (bytes, big) = broken_indirect();
+    let ext2 = open(&bytes, Strictness::default());
+    assert!(ext2.read_file_inode(big).is_err());
+    assert!(ext2.warnings().is_empty());
+}
+
+#[test]
+fn lenient_zero_fills_unreadable_blocks() {
+    let (bytes, big) = broken_indirect();
+    let ext2 = open(&bytes, Strictness::Lenient);
+    let contents = ext2.read_file_inode(big).unwrap();
+    assert_eq!(contents.len(), BIG_SIZE);
+    assert_eq!(
+        contents[..12 * BLOCK_SIZE],
+        pattern(BIG_SIZE)[..12 * BLOCK_SIZE]
+    );
+    assert!(contents[12 * BLOCK_SIZE..].iter().all(|&b| b == 0));
+
+    // one warning for the four blocks behind the one broken pointer
+    let warnings = ext2.warnings();
+    assert_eq!(warnings.len(), 1, "{:?}", warnings);
+    assert!(
+        warnings[0].contains(&format!("inode {}", big)),
+        "{}",
+        warnings[0]
+    );
+
+    let mut buf = [1; 100];
+    assert_eq!(
+        ext2.read_at(big, 13 * BLOCK_SIZE as u64, &mut buf).unwrap(),
+        100
+    );
+    assert_eq!(buf, [0; 100]);
+    assert!(!ext2.take_warnings().is_empty());
+    assert!(ext2.warnings().is_empty());
+}
+
+#[test]
+fn lenient_keeps_entries_before_a_bad_one() {
+    let mut image = fixture()
+        .block_size(BLOCK_SIZE)
+        .dir("docs", |d| d.file("a.txt", b"a").file("b.txt", b"b"))
+        .build();
+    let docs = image.inode("/docs");
+    let block = image.ext2.get_inode(docs).unwrap().direct_pointer[0] as usize;
+    let mut bytes = image.synced_bytes();
+    // the entry after "." and "..", 12 bytes each, gets an impossible size
+    let entry_size = block * BLOCK_SIZE + 24 + 4;
+    bytes[entry_size..entry_size + 2].copy_from_slice(&3u16.to_le_bytes());
+    let strict = open(&bytes, Strictness::Strict);
+    assert!(matches!(
+        strict.read_dir_inode(docs),
+        Err(Ext2Error::CorruptDirectory { offset: 24, .. })
+    ));
+
+    let lenient = open(&bytes, Strictness::Lenient);
+    let names: Vec<String> = lenient
+        .read_dir_inode(docs)
+        .unwrap()
+        .into_iter()
+        .map(|entry| entry.name_lossy().into_owned())
+        .collect();
+    assert_eq!(names, [".", ".."]);
+    assert_eq!(lenient.warnings().len(), 1);
+    // the rest of the filesystem reads as usual
+    assert!(lenient.resolve_path(ROOT, "/docs").is_ok());
+}
@@ -0,0 +1,48 @@
+//! `strings_to`, especially with runs of text that cross block boundaries.
+
+mod common;
+
+use common::fixture;
+
+const BLOCK_SIZE: usize = 1024;
+
+fn strings(contents: &[u8], min_len: usize, offsets: bool) -> (u64, String) {
+    let image = fixture().block_size(BLOCK_SIZE).file("f", contents).build();
+    let mut out = Vec::new();
+    let found = image
+        .ext2
+        .strings_to(image.inode("/f"), min_len, offsets, &mut out)
+        .unwrap();
+    (found, String::from_utf8(out).unwrap())
+}
+
+#[test]
+fn runs_within_a_block() {
+    let (found, out) = strings(b"\x7fELF\x02\x01hi\0main\0\tlibc.so.6", 4, false);
+    // "ELF" and "hi" are too short
+    assert_eq!(found, 2);
+    assert_eq!(out, "main\n\tlibc.so.6\n");
+    assert_eq!(strings(b"\x01ab\x01abc", 2, false).1, "ab\nabc\n");
+}
+
+#[test]
+fn runs_across_blocks() {
+    let mut contents = vec![0; 3 * BLOCK_SIZE + 10];
+    // long enough only with the bytes from the next block
+    contents[BLOCK_SIZE - 2..BLOCK_SIZE + 2].copy_from_slice(b"abcd");
+    // too short, even with them
+    contents[2 * BLOCK_SIZE - 2..2 * BLOCK_SIZE + 1].copy_from_slice(b"xyz");
+    // from the middle of a block on into the next, to the end of the file
+    for byte in &mut contents[2 * BLOCK_SIZE + 500..] {
+        *byte = b'q';
+    }
+    let (found, out) = strings(&contents, 4, true);
+    assert_eq!(found, 2);
+    let lines: Vec<&str> = out.lines().collect();
+    assert_eq!(lines[0], "    3fe abcd");
+    assert_eq!(
+        lines[1],
+        format!("    9f4 {}", "q".repeat(BLOCK_SIZE - 500 + 10))
+    );
+    assert_eq!(lines.len(), 2);
+}
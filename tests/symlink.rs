@@ -0,0 +1,64 @@
+//! `read_link`: targets kept in the inode and in a data block, and a symlink
+//! whose inode claims more than a block, which is an error rather than an
+//! allocation of whatever size it claims.
+
+mod common;
+
+use common::{fixture, Image, ROOT};
+use ext2::{Ext2Error, WalkControl, WalkOptions};
+
+fn image(long_target: &str) -> Image {
+    fixture()
+        .block_size(1024)
+        .file("target", b"pointed at")
+        .symlink("short", "target")
+        .symlink("long", long_target)
+        .build()
+}
+
+#[test]
+fn short_and_long_targets() {
+    let long_target = "./".repeat(100) + "target";
+    let image = image(&long_target);
+    let ext2 = &image.ext2;
+    let short = image.inode("/short");
+    assert_eq!(ext2.read_link(short).unwrap(), b"target");
+    assert_eq!(ext2.get_inode(short).unwrap().sectors_count, 0);
+    let long = image.inode("/long");
+    assert_eq!(ext2.read_link(long).unwrap(), long_target.as_bytes());
+    assert_ne!(ext2.get_inode(long).unwrap().direct_pointer[0], 0);
+}
+
+#[test]
+fn a_symlink_claiming_4gib_is_corrupt() {
+    let mut image = image(&("./".repeat(100) + "target"));
+    let long = image.inode("/long");
+    image.ext2.inode_mut(long).unwrap().set_size(4 << 30);
+    let ext2 = &image.ext2;
+
+    let err = ext2.read_link(long).unwrap_err();
+    assert!(
+        matches!(err.root_cause(), Ext2Error::CorruptInode { inode, .. } if *inode == long),
+        "{}",
+        err
+    );
+    // and a walk following symlinks stops there too, rather than aborting
+    let options = WalkOptions::new().follow_symlinks(true);
+    let walked = ext2.walk(ROOT, &options, &mut |_| WalkControl::Continue);
+    assert!(matches!(
+        walked.unwrap_err().root_cause(),
+        Ext2Error::CorruptInode { .. }
+    ));
+    // the fast symlink is unaffected
+    assert_eq!(ext2.read_link(image.inode("/short")).unwrap(), b"target");
+}
+
+#[test]
+fn a_symlink_just_over_a_block_is_corrupt() {
+    let mut image = image(&("./".repeat(100) + "target"));
+    let long = image.inode("/long");
+    image.ext2.inode_mut(long).unwrap().set_size(1025);
+    assert!(image.ext2.read_link(long).is_err());
+    image.ext2.inode_mut(long).unwrap().set_size(1024);
+    assert_eq!(image.ext2.read_link(long).unwrap().len(), 1024);
+}
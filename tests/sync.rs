@@ -0,0 +1,52 @@
+//! `sync` writes only what's changed since the last one: nothing at all when
+//! nothing has, and just the blocks a later change touched otherwise, while
+//! the image it keeps up to date ends up the same as a full write would.
+
+mod common;
+
+use common::{fixture, CountingWriter, Image};
+use ext2::Ext2;
+
+#[test]
+fn a_second_sync_writes_only_what_changed() {
+    let mut image = fixture()
+        .block_size(1024)
+        .dir("docs", |d| {
+            d.file("a.txt", b"hello").file("b.txt", b"world")
+        })
+        .build();
+    let a = image.inode("/docs/a.txt");
+    let original = image.ext2.device_bytes().unwrap().into_owned();
+    let mut device = CountingWriter::new(original);
+
+    let written = image.ext2.sync(&mut device).unwrap();
+    assert!(written > 0);
+    assert_eq!(device.writes.len(), written);
+    assert!(device.writes.iter().all(|&(_, len)| len == 1024));
+
+    // nothing since, so not even the superblock's write time
+    device.writes.clear();
+    assert_eq!(image.ext2.sync(&mut device).unwrap(), 0);
+    assert_eq!(device.writes, []);
+
+    // the fixtures' clock stands still, so the times in the inode and the
+    // superblock come out the same, and overwriting a file in place only
+    // changes its data block
+    image.ext2.write_file(a, 0, b"HELLO").unwrap();
+    let data = image.ext2.file_blocks(a).unwrap().next().unwrap().unwrap();
+    assert_eq!(image.ext2.sync(&mut device).unwrap(), 1);
+    assert_eq!(device.writes, [(data as u64 * 1024, 1024)]);
+    device.writes.clear();
+    assert_eq!(image.ext2.sync(&mut device).unwrap(), 0);
+    assert_eq!(device.writes, []);
+
+    // and what the device holds is every change since opening, as writing
+    // them all over a copy of the original gives
+    let synced = device.image.into_inner();
+    assert!(synced == image.synced_bytes());
+    let reopened = Image {
+        ext2: Ext2::new(synced).unwrap(),
+    };
+    assert_eq!(reopened.ext2.read_file_inode(a).unwrap(), b"HELLO");
+    assert_eq!(reopened.ext2.check(), []);
+}
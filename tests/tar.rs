@@ -0,0 +1,116 @@
+//! `tar` then `untar`: a tree exported from one image and imported into a
+//! fresh one comes back with the same names, types, modes, owners, times and
+//! contents, and the same hard links.
+
+mod common;
+
+use common::{fixture, pattern, ROOT};
+use ext2::structs::TypePerm;
+use ext2::{Ext2, WalkControl, WalkOptions};
+use std::collections::BTreeMap;
+
+// what a path should come back with: its mode, owner, group, mtime, and its
+// contents, symlink target or device numbers
+type Meta = (u16, u16, u16, u32, Vec<u8>);
+
+fn tree(ext2: &Ext2) -> (BTreeMap<String, Meta>, Vec<Vec<String>>) {
+    let mut paths = BTreeMap::new();
+    let mut by_inode: BTreeMap<usize, Vec<String>> = BTreeMap::new();
+    ext2.walk(ROOT, &WalkOptions::new(), &mut |entry| {
+        let record = entry.record;
+        let data = if record.is_regular() {
+            ext2.read_file_inode(entry.inode).unwrap()
+        } else if record.is_symlink() {
+            ext2.read_link(entry.inode).unwrap()
+        } else if let Some((major, minor)) = record.device() {
+            format!("{},{}", major, minor).into_bytes()
+        } else {
+            Vec::new()
+        };
+        let meta = (
+            record.type_perm.bits(),
+            record.uid,
+            record.gid,
+            record.mtime,
+            data,
+        );
+        paths.insert(entry.path(), meta);
+        if !record.is_dir() {
+            by_inode.entry(entry.inode).or_default().push(entry.path());
+        }
+        WalkControl::Continue
+    })
+    .unwrap();
+    // the paths sharing an inode, by what they are rather than which inode
+    let mut links: Vec<Vec<String>> = by_inode
+        .into_values()
+        .filter(|paths| paths.len() > 1)
+        .collect();
+    links.iter_mut().for_each(|paths| paths.sort());
+    links.sort();
+    (paths, links)
+}
+
+#[test]
+fn round_trip_into_a_fresh_image() {
+    let long_name = "n".repeat(150);
+    let long_target = "x/".repeat(70) + "target";
+    let mut image = fixture()
+        .block_size(1024)
+        .dir("docs", |d| {
+            d.file("a.txt", b"hello")
+                .file("empty", b"")
+                .file("copy2", b"the same twice")
+                .symlink("short", "a.txt")
+                .symlink("long", &long_target)
+                .dir("deep", |d| {
+                    d.dir("er", |d| d.file(&long_name, &pattern(5000)))
+                })
+        })
+        .file("copy1", b"the same twice")
+        .file_with_size("big.bin", 100 << 10)
+        .build();
+    let ext2 = &mut image.ext2;
+    // a hard link, a FIFO and a device, and owners, modes and times that
+    // aren't the defaults
+    let groups = ext2.find_duplicates(ROOT).unwrap();
+    assert_eq!(ext2.link_duplicates(ROOT, &groups[0]).unwrap(), 1);
+    ext2.create_node(ROOT, "pipe", 0o010640, None).unwrap();
+    let docs = ext2.resolve_path(ROOT, "/docs").unwrap();
+    ext2.create_node(docs, "tty", 0o020620, Some((4, 1)))
+        .unwrap();
+    let a = ext2.resolve_path(ROOT, "/docs/a.txt").unwrap();
+    let record = ext2.inode_mut(a).unwrap();
+    record.uid = 1000;
+    record.gid = 100;
+    record.type_perm = TypePerm::from_bits_truncate(0o100600);
+    record.mtime = 1_000_000_000;
+    let deep = ext2.resolve_path(ROOT, "/docs/deep").unwrap();
+    let record = ext2.inode_mut(deep).unwrap();
+    record.type_perm = TypePerm::from_bits_truncate(0o040700);
+    record.mtime = 1_500_000_000;
+
+    let mut archive = Vec::new();
+    let written = ext2.export_tar(ROOT, &mut archive).unwrap();
+    let (paths, links) = tree(ext2);
+    // an entry a path, the hard link's second name as a link entry
+    assert_eq!(written, paths.len());
+    assert_eq!(
+        links,
+        [vec![String::from("copy1"), String::from("docs/copy2")]]
+    );
+
+    let mut fresh = fixture().block_size(1024).build();
+    let summary = fresh.ext2.import_tar(ROOT, &archive[..]).unwrap();
+    assert!(summary.skipped.is_empty(), "{:?}", summary.skipped);
+    let (imported, imported_links) = tree(&fresh.ext2);
+    assert_eq!(
+        imported.keys().collect::<Vec<_>>(),
+        paths.keys().collect::<Vec<_>>()
+    );
+    for (path, meta) in &paths {
+        assert!(imported[path] == *meta, "{}", path);
+    }
+    assert_eq!(imported_links, links);
+    assert_eq!(fresh.ext2.check(), []);
+}
@@ -0,0 +1,67 @@
+//! The CSV timeline of a tree, with deleted files and awkward names.
+
+mod common;
+
+use common::{fixture, ROOT};
+
+fn timeline(ext2: &ext2::Ext2, root: usize, deleted: bool) -> (usize, String) {
+    let mut out = Vec::new();
+    let rows = ext2.export_timeline(root, deleted, &mut out).unwrap();
+    (rows, String::from_utf8(out).unwrap())
+}
+
+#[test]
+fn sorted_by_mtime_and_quoted() {
+    let mut image = fixture()
+        .dir("d", |d| d.file("a,b", b"1").file("say \"hi\"\n", b"22"))
+        .file("later", b"333")
+        .build();
+    let ext2 = &mut image.ext2;
+    let d = ext2.resolve_path(ROOT, "/d").unwrap();
+    let later = ext2.resolve_path(ROOT, "/later").unwrap();
+    ext2.inode_mut(later).unwrap().mtime = u32::MAX;
+    ext2.inode_mut(d).unwrap().mtime = 0;
+
+    let (rows, csv) = timeline(ext2, d, false);
+    assert_eq!(rows, 2);
+    let lines: Vec<&str> = csv.split_inclusive('\n').collect();
+    assert_eq!(
+        lines[0],
+        "path,inode,size,uid,gid,mode,atime,mtime,ctime,dtime\n"
+    );
+    // the same mtime, so in path order
+    assert!(lines[1].starts_with("\"/d/a,b\","), "{}", lines[1]);
+    assert!(lines[1].contains(",1,0,0,100644,"), "{}", lines[1]);
+    assert!(csv.contains("\"/d/say \"\"hi\"\"\n\","), "{}", csv);
+
+    let (rows, csv) = timeline(ext2, ROOT, false);
+    // lost+found, d and its two files, and later
+    assert_eq!(rows, 5);
+    assert!(csv.lines().nth(1).unwrap().starts_with("/d,"));
+    let last = csv.lines().last().unwrap();
+    assert!(
+        last.starts_with(&format!("/later,{},3,", later)),
+        "{}",
+        last
+    );
+}
+
+#[test]
+fn deleted_files() {
+    let mut image = fixture().file("gone", b"bye").file("kept", b"hi").build();
+    let gone = image.inode("/gone");
+    let ext2 = &mut image.ext2;
+    ext2.unlink(ROOT, "gone").unwrap();
+
+    let (_, csv) = timeline(ext2, ROOT, false);
+    assert!(!csv.contains("(deleted)"));
+    let (_, csv) = timeline(ext2, ROOT, true);
+    let row = csv.lines().find(|line| line.contains("(deleted)")).unwrap();
+    assert!(
+        row.starts_with(&format!("gone (deleted),{},3,", gone)),
+        "{}",
+        row
+    );
+    // the deletion time is the last column
+    assert!(!row.ends_with(",0"), "{}", row);
+}
@@ -0,0 +1,72 @@
+//! The largest and most recently modified files in a tree.
+
+mod common;
+
+use common::{fixture, ROOT};
+use ext2::WalkOptions;
+
+fn paths(files: &[ext2::RankedFile]) -> Vec<&str> {
+    files.iter().map(|file| file.path.as_str()).collect()
+}
+
+#[test]
+fn biggest() {
+    let mut image = fixture()
+        .file_with_size("small", 10)
+        .file_with_size("big", 5000)
+        .dir("d", |d| {
+            d.file_with_size("b", 3000).file_with_size("a", 3000)
+        })
+        .file_with_size("middle", 4000)
+        .build();
+    let ext2 = &mut image.ext2;
+    let options = WalkOptions::new();
+    let found = ext2.biggest_files(ROOT, 3, &options).unwrap();
+    // the two the same size in path order
+    assert_eq!(paths(&found), ["big", "middle", "d/a"]);
+    assert_eq!(found[0].size, 5000);
+    assert_eq!(ext2.biggest_files(ROOT, 10, &options).unwrap().len(), 5);
+    assert!(ext2.biggest_files(ROOT, 0, &options).unwrap().is_empty());
+
+    // in lost+found only when it isn't skipped
+    let lost = ext2.resolve_path(ROOT, "/lost+found").unwrap();
+    let huge = ext2.create_file(lost, "#99", 0o644).unwrap();
+    ext2.write_file(huge, 0, &[1; 6000]).unwrap();
+    let skip = WalkOptions::new().skip_lost_and_found(true);
+    assert_eq!(ext2.biggest_files(ROOT, 1, &skip).unwrap()[0].path, "big");
+    assert_eq!(
+        ext2.biggest_files(ROOT, 1, &options).unwrap()[0].path,
+        "lost+found/#99"
+    );
+}
+
+#[test]
+fn recent() {
+    let mut image = fixture()
+        .file("old", b"old")
+        .file("older", b"older")
+        .file("new", b"new")
+        .build();
+    let ext2 = &mut image.ext2;
+    for (path, mtime) in [("/old", 200), ("/older", 100), ("/new", 300)] {
+        let inode = ext2.resolve_path(ROOT, path).unwrap();
+        ext2.inode_mut(inode).unwrap().mtime = mtime;
+    }
+    let found = ext2.recent_files(ROOT, 2, &WalkOptions::new()).unwrap();
+    assert_eq!(paths(&found), ["new", "old"]);
+    assert_eq!(found[0].mtime, 300);
+}
+
+#[test]
+fn hard_links_count_once() {
+    let mut image = fixture()
+        .file_with_size("a", 2000)
+        .file_with_size("b", 2000)
+        .file_with_size("c", 1000)
+        .build();
+    let ext2 = &mut image.ext2;
+    let group = ext2.find_duplicates(ROOT).unwrap().remove(0);
+    ext2.link_duplicates(ROOT, &group).unwrap();
+    let found = ext2.biggest_files(ROOT, 2, &WalkOptions::new()).unwrap();
+    assert_eq!(paths(&found), ["a", "c"]);
+}
@@ -0,0 +1,93 @@
+//! `undelete`: a file removed with `unlink` comes back with the same
+//! contents while none of its blocks have been allocated again, and is
+//! refused, with nothing changed, once any of them has.
+
+mod common;
+
+use common::{e2fsprogs, fixture, pattern, Image, ROOT};
+use ext2::Ext2Error;
+
+// big enough for an indirect block at 1 KiB blocks
+fn image() -> Image {
+    fixture()
+        .block_size(1024)
+        .dir("docs", |d| {
+            d.file("big", &pattern(40 << 10)).file("a", b"a")
+        })
+        .build()
+}
+
+#[test]
+fn delete_and_undelete_round_trip() {
+    let mut image = image();
+    let docs = image.inode("/docs");
+    let big = image.inode("/docs/big");
+    let ext2 = &mut image.ext2;
+    let blocks = ext2.owned_blocks(big).unwrap();
+    let free = ext2.superblock.free_blocks_count;
+    ext2.unlink(docs, "big").unwrap();
+    assert_eq!(
+        ext2.superblock.free_blocks_count,
+        free + blocks.len() as u32
+    );
+
+    let deleted = ext2.deleted_inodes().unwrap();
+    let found = deleted.iter().find(|d| d.inode == big).unwrap();
+    assert_eq!(found.size, 40 << 10);
+    assert_eq!(found.names, ["big"]);
+    assert_eq!(found.reused_blocks, Vec::<usize>::new());
+
+    ext2.undelete(big, docs, "back").unwrap();
+    assert_eq!(ext2.lookup(docs, "back").unwrap(), Some(big));
+    assert_eq!(ext2.owned_blocks(big).unwrap(), blocks);
+    assert_eq!(ext2.read_file_inode(big).unwrap(), pattern(40 << 10));
+    let record = ext2.get_inode(big).unwrap();
+    assert_eq!((record.hard_links, record.dtime), (1, 0));
+    assert_eq!(ext2.superblock.free_blocks_count, free);
+    assert!(ext2
+        .deleted_inodes()
+        .unwrap()
+        .iter()
+        .all(|d| d.inode != big));
+    assert_eq!(ext2.check(), []);
+    if e2fsprogs::available() {
+        let (_dir, path) = image.dump();
+        e2fsprogs::fsck(&path).unwrap();
+    }
+}
+
+#[test]
+fn undelete_is_refused_once_a_block_is_reallocated() {
+    let mut image = image();
+    let docs = image.inode("/docs");
+    let big = image.inode("/docs/big");
+    let ext2 = &mut image.ext2;
+    let blocks = ext2.owned_blocks(big).unwrap();
+    // made first, so it doesn't get big's inode too
+    let new = ext2.create_file(ROOT, "new", 0o644).unwrap();
+    ext2.unlink(docs, "big").unwrap();
+    // blocks come from the front of the group, so some of those just freed
+    ext2.write_file(new, 0, &pattern(4 << 10)).unwrap();
+    let taken: Vec<usize> = ext2
+        .owned_blocks(new)
+        .unwrap()
+        .into_iter()
+        .filter(|block| blocks.contains(block))
+        .collect();
+    assert!(!taken.is_empty());
+
+    let found = ext2.deleted_inodes().unwrap();
+    let found = found.iter().find(|d| d.inode == big).unwrap();
+    assert_eq!(found.reused_blocks, taken);
+    let free = ext2.superblock.free_blocks_count;
+    assert!(matches!(
+        ext2.undelete(big, docs, "back"),
+        Err(Ext2Error::NotRecoverable { inode, .. }) if inode == big
+    ));
+    // nothing taken back, not even the blocks nobody else has
+    assert_eq!(ext2.lookup(docs, "back").unwrap(), None);
+    assert!(!ext2.inode_is_allocated(big).unwrap());
+    assert_eq!(ext2.superblock.free_blocks_count, free);
+    assert_eq!(ext2.read_file_inode(new).unwrap(), pattern(4 << 10));
+    assert_eq!(ext2.check(), []);
+}
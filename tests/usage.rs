@@ -0,0 +1,153 @@
+//! `group_usage` and `fragmentation`, and the `fsmap` and `frag` commands
+//! that print them, on an image made by `mkfs` directly, whose layout is
+//! known to the block whichever way the fixtures are being built.
+
+mod common;
+
+use common::{pattern, Image, FIXTURE_TIME, ROOT};
+use ext2::{mkfs, Extent, FixedClock, GroupUsage, MkfsOptions};
+use std::process::Command;
+
+// 10 MiB at 1 KiB blocks: group 0 is blocks 1 to 8192 and group 1 the 2047
+// left. Both start with a superblock, a descriptor block, the two bitmaps
+// and 1280 inodes of 256 bytes, 320 blocks of them, so 324 of metadata.
+// Group 0 has the root's block and lost+found's 12, f's 20 and its indirect
+// block; the directory d goes to group 1, with its block and g's 5.
+fn image() -> Image {
+    let mut device = vec![0; 10 << 20];
+    mkfs(
+        &mut device,
+        &MkfsOptions::new().clock(FixedClock(FIXTURE_TIME)),
+    )
+    .unwrap();
+    let mut image = Image::from_bytes(&device);
+    let ext2 = &mut image.ext2;
+    let f = ext2.create_file(ROOT, "f", 0o644).unwrap();
+    ext2.write_file(f, 0, &pattern(20 << 10)).unwrap();
+    let d = ext2.create_dir(ROOT, "d", 0o755).unwrap();
+    let g = ext2.create_file(d, "g", 0o644).unwrap();
+    ext2.write_file(g, 0, &pattern(5000)).unwrap();
+    image
+}
+
+#[test]
+fn group_usage_counts_each_kind_of_block() {
+    let image = image();
+    let ext2 = &image.ext2;
+    assert_eq!(ext2.block_groups.len(), 2);
+    assert_eq!(
+        ext2.group_usage(0, 16).unwrap(),
+        GroupUsage {
+            group: 0,
+            first_block: 1,
+            blocks: 8192,
+            metadata: 324,
+            data: 34,
+            free: 7834,
+            // 512 blocks a character, the metadata all in the first
+            map: String::from("M..............."),
+        }
+    );
+    assert_eq!(
+        ext2.group_usage(1, 16).unwrap(),
+        GroupUsage {
+            group: 1,
+            first_block: 8193,
+            blocks: 2047,
+            metadata: 324,
+            data: 6,
+            free: 1717,
+            // 127 or 128 blocks a character, so the metadata spans three
+            map: String::from("MMM............."),
+        }
+    );
+    for group in 0..2 {
+        let usage = ext2.group_usage(group, 0).unwrap();
+        assert_eq!(usage.map, "");
+        assert_eq!(usage.metadata + usage.data + usage.free, usage.blocks);
+        assert_eq!(
+            usage.free,
+            ext2.block_groups[group].free_blocks_count as usize
+        );
+    }
+}
+
+#[test]
+fn fragmentation_steps_over_the_indirect_block() {
+    let image = image();
+    let (f, g) = (image.inode("/f"), image.inode("/d/g"));
+    let ext2 = &image.ext2;
+    // the indirect block is allocated when the 13th data block is, between
+    // the 12th and it
+    let first = ext2.get_inode(f).unwrap().direct_pointer[0] as usize;
+    let report = ext2.fragmentation(f).unwrap();
+    assert_eq!(report.blocks, 20);
+    assert_eq!(
+        report.extents,
+        [
+            Extent {
+                logical: 0,
+                physical: first,
+                len: 12,
+            },
+            Extent {
+                logical: 12,
+                physical: first + 13,
+                len: 8,
+            },
+        ]
+    );
+    assert_eq!(ext2.fragmentation(g).unwrap().extents.len(), 1);
+    assert_eq!(ext2.fragmentation(g).unwrap().blocks, 5);
+}
+
+#[test]
+fn fsmap_and_frag_print_them() {
+    let mut image = image();
+    let first = image
+        .ext2
+        .get_inode(image.inode("/f"))
+        .unwrap()
+        .direct_pointer[0];
+    let (_dir, path) = image.dump();
+    let run = |command: &[&str]| {
+        let output = Command::new(env!("CARGO_BIN_EXE_ext2"))
+            .arg(&path)
+            .args(command)
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        String::from_utf8(output.stdout).unwrap()
+    };
+    let fsmap = run(&["fsmap"]);
+    let rows: Vec<Vec<&str>> = fsmap
+        .lines()
+        .skip(1)
+        .map(|line| line.split_whitespace().collect())
+        .collect();
+    assert_eq!(
+        rows,
+        [
+            ["0", "1", "8192", "324", "34", "7834"],
+            ["1", "8193", "2047", "324", "6", "1717"],
+        ]
+    );
+    assert_eq!(
+        run(&["frag", "/f", "-v"]),
+        format!(
+            "/f: 20 blocks in 2 extents\n\
+             {:>10} {:>10} {:>8}\n\
+             {:>10} {:>10} {:>8}\n\
+             {:>10} {:>10} {:>8}\n",
+            "Logical",
+            "Physical",
+            "Length",
+            0,
+            first,
+            12,
+            12,
+            first + 13,
+            8
+        )
+    );
+}
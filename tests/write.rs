@@ -0,0 +1,168 @@
+//! `write_file`: growing a file into its indirect block, writes that straddle
+//! blocks or overwrite what's there, and writes past the end that leave
+//! holes, each read back through the read path and checked by `e2fsck`.
+
+mod common;
+
+use common::{e2fsprogs, fixture, pattern, Image, ROOT};
+use ext2::Ext2;
+
+const BLOCK: usize = 1024;
+
+// a fixture with 1 KiB blocks, so 12 KiB reaches the indirect block
+fn image() -> Image {
+    fixture()
+        .block_size(BLOCK)
+        .file("three", &pattern(3 * BLOCK))
+        .build()
+}
+
+// the file's data blocks in logical order, 0 for a hole
+fn blocks(ext2: &Ext2, inode: usize) -> Vec<usize> {
+    ext2.file_blocks(inode)
+        .unwrap()
+        .map(|block| block.unwrap())
+        .collect()
+}
+
+fn free_blocks(ext2: &Ext2) -> u32 {
+    ext2.superblock.free_blocks_count
+}
+
+// consistent by our own check, and by e2fsck's when it's installed
+fn assert_clean(image: &mut Image) {
+    assert_eq!(image.ext2.check(), []);
+    if e2fsprogs::available() {
+        let (_dir, file) = image.dump();
+        if let Err(complaint) = e2fsprogs::fsck(&file) {
+            panic!("{}", complaint);
+        }
+    }
+}
+
+#[test]
+fn growing_past_twelve_blocks_allocates_the_indirect_block() {
+    let mut image = image();
+    let ext2 = &mut image.ext2;
+    let file = ext2.create_file(ROOT, "file", 0o644).unwrap();
+    let free = free_blocks(ext2);
+
+    // the twelve direct blocks exactly
+    ext2.write_file(file, 0, &pattern(12 * BLOCK)).unwrap();
+    let record = ext2.get_inode(file).unwrap();
+    assert!(record.direct_pointer.iter().all(|&block| block != 0));
+    assert_eq!(record.indirect_pointer, 0);
+    assert_eq!(record.sectors_count as usize, 12 * BLOCK / 512);
+    assert_eq!(free_blocks(ext2), free - 12);
+
+    // one byte more takes a data block and the indirect block to point at it
+    let contents = pattern(12 * BLOCK + 1);
+    ext2.write_file(file, 12 * BLOCK as u64, &contents[12 * BLOCK..])
+        .unwrap();
+    let record = ext2.get_inode(file).unwrap();
+    assert_ne!(record.indirect_pointer, 0);
+    assert_eq!(record.size(), contents.len() as u64);
+    assert_eq!(record.sectors_count as usize, 14 * BLOCK / 512);
+    assert_eq!(free_blocks(ext2), free - 14);
+    let data = blocks(ext2, file);
+    assert_eq!(data.len(), 13);
+    assert!(!data.contains(&(record.indirect_pointer as usize)));
+    assert_eq!(ext2.read_file_inode(file).unwrap(), contents);
+    assert_clean(&mut image);
+}
+
+#[test]
+fn one_write_across_the_boundary() {
+    let mut image = image();
+    let ext2 = &mut image.ext2;
+    let file = ext2.create_file(ROOT, "file", 0o644).unwrap();
+    let contents = pattern(20 * BLOCK + 100);
+    assert_eq!(ext2.write_file(file, 0, &contents).unwrap(), contents.len());
+
+    let record = ext2.get_inode(file).unwrap();
+    assert_ne!(record.indirect_pointer, 0);
+    assert_eq!(record.sectors_count as usize, 22 * BLOCK / 512);
+    assert_eq!(blocks(ext2, file).len(), 21);
+    assert_eq!(ext2.read_file_inode(file).unwrap(), contents);
+    let mut tail = [0; 200];
+    assert_eq!(
+        ext2.read_at(file, 20 * BLOCK as u64, &mut tail).unwrap(),
+        100
+    );
+    assert_eq!(tail[..100], contents[20 * BLOCK..]);
+    assert_clean(&mut image);
+}
+
+#[test]
+fn overwrites_in_place_and_across_blocks() {
+    let mut image = image();
+    let ext2 = &mut image.ext2;
+    let three = ext2.resolve_path(ROOT, "/three").unwrap();
+    let before = blocks(ext2, three);
+    let free = free_blocks(ext2);
+    let mut expected = pattern(3 * BLOCK);
+
+    // inside block 0, across blocks 0 and 1, and across 1 and 2
+    for (offset, len) in [(100, 50), (BLOCK - 10, 20), (2 * BLOCK - 1, 2)] {
+        let data = vec![b'#'; len];
+        assert_eq!(ext2.write_file(three, offset as u64, &data).unwrap(), len);
+        expected[offset..offset + len].copy_from_slice(&data);
+    }
+    // and one that straddles the end, growing the file into a new block
+    ext2.write_file(three, 3 * BLOCK as u64 - 5, b"0123456789")
+        .unwrap();
+    expected.truncate(3 * BLOCK - 5);
+    expected.extend_from_slice(b"0123456789");
+
+    // only the last write took a block, and the first three stayed put
+    let after = blocks(ext2, three);
+    assert_eq!(after[..3], before[..]);
+    assert_eq!(after.len(), 4);
+    assert_eq!(free_blocks(ext2), free - 1);
+    assert_eq!(ext2.read_file_inode(three).unwrap(), expected);
+    assert_clean(&mut image);
+}
+
+#[test]
+fn writes_past_the_end_leave_holes() {
+    let mut image = image();
+    let ext2 = &mut image.ext2;
+    let file = ext2.create_file(ROOT, "file", 0o644).unwrap();
+    ext2.write_file(file, 0, b"abc").unwrap();
+    let free = free_blocks(ext2);
+
+    // past the end, but in the same block: no block, the gap reads as zeros
+    ext2.write_file(file, 10, b"d").unwrap();
+    assert_eq!(free_blocks(ext2), free);
+    assert_eq!(ext2.read_file_inode(file).unwrap(), b"abc\0\0\0\0\0\0\0d");
+
+    // blocks later: just the block written to, and holes before it
+    ext2.write_file(file, 4 * BLOCK as u64 + 1, b"xyz").unwrap();
+    let data = blocks(ext2, file);
+    assert_eq!(data.len(), 5);
+    assert!(data[1..4].iter().all(|&block| block == 0));
+    assert_ne!(data[4], 0);
+    assert_eq!(free_blocks(ext2), free - 1);
+    let record = ext2.get_inode(file).unwrap();
+    assert_eq!(record.size(), 4 * BLOCK as u64 + 4);
+    assert_eq!(record.sectors_count as usize, 2 * BLOCK / 512);
+    let mut expected = vec![0; 4 * BLOCK + 4];
+    expected[..3].copy_from_slice(b"abc");
+    expected[10] = b'd';
+    expected[4 * BLOCK + 1..].copy_from_slice(b"xyz");
+    assert_eq!(ext2.read_file_inode(file).unwrap(), expected);
+
+    // and far enough on that the only block is past the direct ones
+    let far = ext2.create_file(ROOT, "far", 0o644).unwrap();
+    let free = free_blocks(ext2);
+    ext2.write_file(far, 100 * BLOCK as u64, b"end").unwrap();
+    let record = ext2.get_inode(far).unwrap();
+    assert!(record.direct_pointer.iter().all(|&block| block == 0));
+    assert_ne!(record.indirect_pointer, 0);
+    assert_eq!(free_blocks(ext2), free - 2);
+    let contents = ext2.read_file_inode(far).unwrap();
+    assert_eq!(contents.len(), 100 * BLOCK + 3);
+    assert!(contents[..100 * BLOCK].iter().all(|&byte| byte == 0));
+    assert_eq!(contents[100 * BLOCK..], *b"end");
+    assert_clean(&mut image);
+}